@@ -0,0 +1,5 @@
+fn main() {
+    // Generates the gRPC service of `server::grpc`, requires `protoc`
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/locustdb.proto").unwrap();
+}
@@ -216,7 +216,7 @@ fn ingest(opts: &Opts, db: &LocustDB, small_tables: &[String]) -> u64 {
     }
     let total_events = log.total_events;
     drop(log);
-    db.force_flush();
+    db.force_flush().unwrap();
     total_events
 }
 
@@ -246,7 +246,15 @@ fn create_locustdb(db_path: PathBuf) -> Arc<locustdb::LocustDB> {
     };
     let db = Arc::new(locustdb::LocustDB::new(&options));
     let _locustdb = db.clone();
-    locustdb::server::run(_locustdb, false, vec![], "localhost:8888".to_string()).unwrap();
+    locustdb::server::run(
+        _locustdb,
+        false,
+        vec![],
+        "localhost:8888".to_string(),
+        None,
+        None,
+    )
+    .unwrap();
     db
 }
 
@@ -34,7 +34,8 @@ struct Opt {
 async fn main() {
     env_logger::init();
     let opts = Opt::from_args();
-    let (storage, wal) = Storage::new(&opts.db_path, Arc::new(PerfCounter::default()), true);
+    let (storage, wal) = Storage::new(&opts.db_path, Arc::new(PerfCounter::default()), true)
+        .expect("Failed to open database");
 
     {
         let meta = storage.meta_store().read().unwrap();
@@ -71,6 +72,9 @@ async fn main() {
                             );
                             if opts.meta > 2 {
                                 println!("    {:?}", partition.column_name_to_subpartition_index.iter().filter(|(_, &idx)| idx == i).map(|(name, _)| name).collect::<Vec<_>>());
+                                for (name, codec) in &subpartition.column_codecs {
+                                    println!("    {}: {}", name, codec);
+                                }
                             }
                         }
                     }
@@ -0,0 +1,60 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// Reads the TOML configuration file at `path` and returns the command line arguments equivalent to its settings.
+/// Keys are the names of long command line options, with either dashes or underscores, e.g. `db-path = "/data"`,
+/// `threads = 8`, `server = true` or `cors-allow-origin = ["https://a.com", "https://b.com"]`.
+/// Settings whose option is also given in `cli_args` are skipped, so that command line arguments take precedence.
+pub fn args_from_file(path: &Path, cli_args: &[OsString]) -> Result<Vec<OsString>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read config file {}: {}", path.display(), err))?;
+    let settings = contents
+        .parse::<toml::Table>()
+        .map_err(|err| format!("Failed to parse config file {}: {}", path.display(), err))?;
+    let mut args = Vec::new();
+    for (key, value) in settings {
+        let flag = format!("--{}", key.replace('_', "-"));
+        if flag == "--config" {
+            return Err(format!(
+                "Config file {} cannot set `config`",
+                path.display()
+            ));
+        }
+        let overridden = cli_args.iter().any(|arg| {
+            arg.to_str().map_or(false, |arg| {
+                arg == flag || arg.starts_with(&format!("{}=", flag))
+            })
+        });
+        if overridden {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Boolean(true) => {
+                args.push(OsString::from(flag));
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(string) => string,
+                toml::Value::Integer(int) => int.to_string(),
+                toml::Value::Float(float) => float.to_string(),
+                value => {
+                    return Err(format!(
+                        "Unsupported value {} of setting `{}` in config file {}",
+                        value,
+                        key,
+                        path.display()
+                    ))
+                }
+            };
+            // Passed as a single argument so that values starting with `-` are not mistaken for options
+            args.push(OsString::from(format!("{}={}", flag, value)));
+        }
+    }
+    Ok(args)
+}
@@ -6,9 +6,11 @@ use futures::executor::block_on;
 use structopt::StructOpt;
 use time::OffsetDateTime;
 
+use locustdb::server::TlsConfig;
 use locustdb::unit_fmt::*;
 use locustdb::LocustDB;
 
+mod config;
 mod fmt_table;
 mod print_results;
 mod unicode;
@@ -20,10 +22,25 @@ mod unicode;
     author = "Clemens Winter <clemenswinter1@gmail.com>"
 )]
 struct Opt {
+    /// TOML file with settings named like the long options below, e.g. `threads = 8`. Options given on the command line
+    /// take precedence over the file.
+    #[structopt(long, name = "CONFIG", parse(from_os_str))]
+    config: Option<PathBuf>,
+
     /// Database path
     #[structopt(long, name = "PATH", parse(from_os_str))]
     db_path: Option<PathBuf>,
 
+    /// Additional directories, e.g. on separate disks, that partition files are striped across
+    #[structopt(long, name = "DIR", parse(from_os_str))]
+    data_path: Vec<PathBuf>,
+
+    /// Store the database in an object store instead of PATH, e.g. `s3://bucket/prefix`, `gs://bucket/prefix` or
+    /// `az://account/container/prefix`. Credentials are read from the `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`,
+    /// `GOOGLE_OAUTH_ACCESS_TOKEN`, `AZURE_STORAGE_KEY` or `AZURE_STORAGE_SAS_TOKEN` environment variables.
+    #[structopt(long, name = "URL", conflicts_with = "PATH")]
+    object_store: Option<String>,
+
     /// Load .csv or .csv.gz files into the database
     #[structopt(long, name = "FILES", parse(from_os_str))]
     load: Vec<PathBuf>,
@@ -36,10 +53,22 @@ struct Opt {
     #[structopt(long, name = "GB", default_value = "8")]
     mem_limit_tables: usize,
 
+    /// Limit for resident set size of the process in GiB, columns are evicted and ingestion is rejected when exceeded
+    #[structopt(long, name = "RSS_GB")]
+    max_rss: Option<usize>,
+
+    /// Policy for evicting columns from memory: `lru` or the scan-resistant `slru`
+    #[structopt(long, name = "EVICTION", default_value = "lru")]
+    eviction_policy: locustdb::EvictionPolicy,
+
     /// Maximum size of WAL in bytes
     #[structopt(long, name = "WAL_SIZE", default_value = "16777216")]
     max_wal_size_bytes: u64,
 
+    /// Flush the WAL to partitions at least every INTERVAL seconds, even if it is smaller than WAL_SIZE
+    #[structopt(long, name = "INTERVAL")]
+    wal_flush_interval: Option<u64>,
+
     /// Maximum size of partition files in bytes
     #[structopt(long, name = "PART_SIZE", default_value = "8388608")]
     max_partition_size_bytes: u64,
@@ -55,6 +84,10 @@ struct Opt {
     #[structopt(long)]
     mem_lz4: bool,
 
+    /// Compress partitions written to disk with zstd at the given level (1-22). Requires the `enable_zstd` feature
+    #[structopt(long, name = "LEVEL")]
+    zstd_level: Option<i32>,
+
     /// Number of rows per partition when loading new data
     #[structopt(long, name = "ROWS", default_value = "65536")]
     partition_size: usize,
@@ -67,10 +100,42 @@ struct Opt {
     #[structopt(long)]
     seq_disk_read: bool,
 
+    /// Number of partitions ahead of the current partition that queries read from disk in the background
+    #[structopt(long, name = "PARTITIONS", default_value = "4")]
+    prefetch_partitions: usize,
+
+    /// Maximum number of concurrent disk reads of a single query
+    #[structopt(long, name = "READS")]
+    max_query_disk_reads: Option<usize>,
+
+    /// Maximum amount of memory in MiB a single query allocates for grouping
+    #[structopt(long, name = "QUERY_MB")]
+    query_memory_limit: Option<usize>,
+
+    /// Number of per-partition results of aggregation queries that are cached for later queries
+    #[structopt(long, name = "ENTRIES", default_value = "0")]
+    aggregate_cache_entries: usize,
+
     /// Number of worker threads. [default: number of cores]
     #[structopt(long, name = "INTEGER")]
     threads: Option<usize>,
 
+    /// Maximum number of worker threads, workers are added while queries are waiting and the CPU is not saturated
+    #[structopt(long, name = "MAX_THREADS")]
+    max_threads: Option<usize>,
+
+    /// Pin worker and WAL flush threads to CPUs: `none`, `core` or `numa`
+    #[structopt(long, name = "AFFINITY", default_value = "none")]
+    thread_affinity: locustdb::ThreadAffinity,
+
+    /// Maximum number of queries that execute concurrently, further queries are queued
+    #[structopt(long, name = "QUERIES")]
+    max_concurrent_queries: Option<usize>,
+
+    /// Reject queries that have been queued for longer than TIMEOUT seconds
+    #[structopt(long, name = "TIMEOUT", default_value = "30")]
+    query_queue_timeout: u64,
+
     /// Set ingestion schema for select set of columns from nyc taxi ride dataset.
     #[structopt(long, conflicts_with_all(&["trips", "schema"]))]
     reduced_trips: bool,
@@ -95,57 +160,220 @@ struct Opt {
     #[structopt(long, default_value = "127.0.0.1:8080")]
     addrs: String,
 
+    /// PEM file with the certificate chain used to serve HTTPS, requires the `tls` feature
+    #[structopt(long, name = "CERT", parse(from_os_str), requires = "KEY")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM file with the private key of the HTTPS certificate
+    #[structopt(long, name = "KEY", parse(from_os_str), requires = "CERT")]
+    tls_key: Option<PathBuf>,
+
+    /// Bearer token that enables the maintenance endpoints under `/admin` and is required to call them
+    #[structopt(long, name = "TOKEN")]
+    admin_token: Option<String>,
+
+    /// Address to additionally serve the gRPC API on, requires the `grpc` feature
+    #[structopt(long, name = "GRPC_ADDRS")]
+    grpc_addrs: Option<String>,
+
+    /// OTLP/HTTP endpoint to export tracing spans of query execution to, e.g. `http://localhost:4318`, requires the
+    /// `otel` feature
+    #[structopt(long, name = "OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
     /// Maximum length of temporary buffer used in streaming stages during query execution
     #[structopt(long, default_value = "1024")]
     batch_size: usize,
+
+    /// Split partitions with more rows into morsels of this many rows that are scanned concurrently, 0 to disable
+    #[structopt(long, name = "MORSEL_ROWS", default_value = "65536")]
+    morsel_size: usize,
+
+    /// Verify all partition files against their checksums every SECONDS and quarantine corrupted partitions
+    #[structopt(long, name = "SECONDS")]
+    scrub_interval: Option<u64>,
+
+    /// When to fsync WAL segments: `always`, `never` or once every N segments
+    #[structopt(long, name = "POLICY", default_value = "always")]
+    wal_sync: locustdb::WalSync,
+
+    /// What ingestion does while the WAL is full: `block` until it has been flushed, or reject requests and ask
+    /// clients to retry after N seconds
+    #[structopt(long, name = "BACKPRESSURE", default_value = "block")]
+    wal_backpressure: locustdb::WalBackpressure,
+
+    /// Keep flushed WAL segments in the `wal_archive` directory for point-in-time recovery
+    #[structopt(long)]
+    archive_wal: bool,
+
+    /// Memory map partition files instead of reading them into memory
+    #[structopt(long)]
+    mmap_columns: bool,
+
+    /// Execute queries without combining chains of streaming operators
+    #[structopt(long)]
+    no_operator_fusion: bool,
+
+    /// Record executed queries in the `_query_log` table
+    #[structopt(long)]
+    query_log: bool,
+
+    /// Fraction of queries that are recorded in the query log
+    #[structopt(long, name = "RATE", default_value = "1.0")]
+    query_log_sample_rate: f64,
+
+    /// Upgrade the database to the current on-disk format and exit
+    #[structopt(long)]
+    migrate: bool,
 }
 
 fn main() {
     env_logger::init();
 
     let Opt {
+        config: _,
         db_path,
+        data_path,
+        object_store,
         load,
         table,
         mem_limit_tables,
+        max_rss,
+        eviction_policy,
         schema,
         mem_lz4,
+        zstd_level,
         partition_size,
         readahead,
         seq_disk_read,
+        prefetch_partitions,
+        max_query_disk_reads,
+        query_memory_limit,
+        aggregate_cache_entries,
         threads,
+        max_threads,
+        thread_affinity,
+        max_concurrent_queries,
+        query_queue_timeout,
         reduced_trips,
         trips,
         server,
         max_wal_size_bytes,
+        wal_flush_interval,
         max_partition_size_bytes,
         cors_allow_all,
         cors_allow_origin,
         addrs,
+        tls_cert,
+        tls_key,
+        admin_token,
+        grpc_addrs,
+        otlp_endpoint,
         batch_size,
-    } = Opt::from_args();
+        morsel_size,
+        scrub_interval,
+        wal_sync,
+        wal_backpressure,
+        archive_wal,
+        mmap_columns,
+        no_operator_fusion,
+        query_log,
+        query_log_sample_rate,
+        migrate,
+    } = parse_args();
+
+    if let Some(otlp_endpoint) = &otlp_endpoint {
+        #[cfg(feature = "otel")]
+        locustdb::otel::init(otlp_endpoint, "locustdb").unwrap();
+        #[cfg(not(feature = "otel"))]
+        panic!(
+            "Can't export traces to {}, compile with `--features otel`",
+            otlp_endpoint
+        );
+    }
 
+    let object_store = object_store.map(|url| {
+        locustdb::ObjectStoreOptions::new(&url)
+            .unwrap_or_else(|e| panic!("Invalid object store URL {}: {}", url, e))
+            .with_env_credentials()
+    });
     let options = locustdb::Options {
         threads: threads.unwrap_or_else(num_cpus::get),
+        max_threads,
+        worker_idle_timeout: std::time::Duration::from_secs(10),
+        thread_affinity,
         read_threads: if seq_disk_read { 1 } else { num_cpus::get() },
+        background_threads: num_cpus::get(),
+        max_concurrent_queries,
+        query_queue_timeout: std::time::Duration::from_secs(query_queue_timeout),
         db_path: db_path.clone(),
+        data_paths: data_path,
+        object_store,
         mem_size_limit_tables: mem_limit_tables * 1024 * 1024 * 1024,
+        max_rss_bytes: max_rss.map(|gb| gb * 1024 * 1024 * 1024),
+        eviction_policy,
         mem_lz4,
+        partition_compression: zstd_level
+            .map(locustdb::PartitionCompression::Zstd)
+            .unwrap_or_default(),
+        mmap_columns,
         readahead: readahead * 1024 * 1024,
         seq_disk_read,
+        prefetch_partitions,
+        max_query_disk_reads,
+        query_memory_limit: query_memory_limit.map(|mb| mb * 1024 * 1024),
+        aggregate_cache_entries,
+        sort_run_rows: 1 << 20,
         max_wal_size_bytes,
+        wal_flush_interval: wal_flush_interval.map(std::time::Duration::from_secs),
         max_partition_size_bytes,
         partition_combine_factor: 4,
         batch_size,
+        morsel_size,
+        operator_fusion: !no_operator_fusion,
         max_partition_length: 1024 * 1024,
+        scrub_interval: scrub_interval.map(std::time::Duration::from_secs),
+        wal_sync,
+        wal_backpressure,
+        archive_wal,
+        recovery_target: None,
+        query_log,
+        query_log_sample_rate,
     };
 
     if options.readahead > options.mem_size_limit_tables {
         println!("WARNING: `mem-limit-tables` should be at least as large as `readahead`");
     }
 
+    if let Err(err) = options.validate() {
+        eprintln!("Invalid options: {}", err);
+        std::process::exit(1);
+    }
     let locustdb = locustdb::LocustDB::new(&options);
 
+    if migrate {
+        match locustdb.migrate() {
+            Ok(report) if report.applied.is_empty() => println!(
+                "Database is already at format version {}.",
+                report.to_version
+            ),
+            Ok(report) => {
+                for description in &report.applied {
+                    println!("Applied migration: {}", description);
+                }
+                println!(
+                    "Migrated database from format version {} to {}.",
+                    report.from_version, report.to_version
+                );
+            }
+            Err(err) => {
+                eprintln!("Migration failed: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let start_time = OffsetDateTime::unix_epoch().unix_timestamp_nanos();
     let mut loads = Vec::new();
     let file_count = load.len();
@@ -180,13 +408,32 @@ fn main() {
     }
 
     if server {
-        let (_, rx) =
-            locustdb::server::run(Arc::new(locustdb), cors_allow_all, cors_allow_origin, addrs)
-                .unwrap();
+        let locustdb = Arc::new(locustdb);
+        let tls = tls_cert
+            .zip(tls_key)
+            .map(|(cert, key)| TlsConfig::new(cert, key));
+        let (_, rx) = locustdb::server::run(
+            locustdb.clone(),
+            cors_allow_all,
+            cors_allow_origin,
+            addrs,
+            tls,
+            admin_token,
+        )
+        .unwrap();
+        if let Some(grpc_addrs) = grpc_addrs {
+            #[cfg(feature = "grpc")]
+            locustdb::server::grpc::run(locustdb.clone(), &grpc_addrs).unwrap();
+            #[cfg(not(feature = "grpc"))]
+            panic!("Can't serve gRPC API on {}, compile with `--features grpc`", grpc_addrs);
+        }
         block_on(rx).unwrap();
+        locustdb.shutdown();
     } else {
         repl(&locustdb);
     }
+    #[cfg(feature = "otel")]
+    locustdb::otel::shutdown();
 }
 
 fn table_stats(locustdb: &LocustDB) {
@@ -206,6 +453,24 @@ fn table_stats(locustdb: &LocustDB) {
 }
 
 #[allow(clippy::cognitive_complexity)]
+/// Parses the command line arguments, merged with the settings of the config file if one is given.
+fn parse_args() -> Opt {
+    let cli_args = std::env::args_os().collect::<Vec<_>>();
+    let opt = Opt::from_iter(&cli_args);
+    let path = match opt.config.clone() {
+        Some(path) => path,
+        None => return opt,
+    };
+    let file_args = config::args_from_file(&path, &cli_args).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    Opt::from_iter_safe(cli_args.into_iter().chain(file_args)).unwrap_or_else(|err| {
+        eprintln!("Invalid config file {}: {}", path.display(), err.message);
+        std::process::exit(1);
+    })
+}
+
 fn repl(locustdb: &LocustDB) {
     let mut rl = rustyline::Editor::<()>::new();
     rl.load_history(".locustdb_history").ok();
@@ -0,0 +1,222 @@
+use std::error::Error;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+
+use super::s3::{hmac_sha256, unescape_xml, uri_encode, xml_elements};
+
+type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+/// Version of the Blob service REST API used for all requests.
+const API_VERSION: &str = "2021-08-06";
+
+#[derive(Clone)]
+pub(crate) enum AzureCredentials {
+    Anonymous,
+    /// Base64 encoded storage account key.
+    SharedKey(String),
+    /// Shared access signature, appended to the query string of every request.
+    Sas(String),
+    /// OAuth 2.0 access token issued by Microsoft Entra ID.
+    Bearer(String),
+}
+
+/// Creates an authenticated request for `blob` in `container`, or for the container if `blob` is empty.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn request(
+    client: &reqwest::Client,
+    method: Method,
+    endpoint: &str,
+    account: &str,
+    credentials: &AzureCredentials,
+    container: &str,
+    blob: &str,
+    query: &[(&str, String)],
+    body: Option<Vec<u8>>,
+) -> Result<reqwest::RequestBuilder, BoxError> {
+    let mut url = format!("{}/{}", endpoint, uri_encode(container, true));
+    if !blob.is_empty() {
+        url = format!("{}/{}", url, uri_encode(blob, false));
+    }
+    let mut url = reqwest::Url::parse(&url)?;
+    let mut query_string = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true)))
+        .collect::<Vec<_>>();
+    if let AzureCredentials::Sas(token) = credentials {
+        query_string.push(token.trim_start_matches('?').to_string());
+    }
+    if !query_string.is_empty() {
+        url.set_query(Some(&query_string.join("&")));
+    }
+
+    let mut headers = vec![
+        ("x-ms-date", format_date(Utc::now())),
+        ("x-ms-version", API_VERSION.to_string()),
+    ];
+    if method == Method::PUT && !blob.is_empty() {
+        headers.push(("x-ms-blob-type", "BlockBlob".to_string()));
+    }
+    let content_length = body.as_ref().map_or(0, Vec::len);
+    let authorization = match credentials {
+        AzureCredentials::SharedKey(key) => {
+            let key = BASE64.decode(key.trim())?;
+            let signature = sign_shared_key(
+                &key,
+                account,
+                method.as_str(),
+                url.path(),
+                query,
+                content_length,
+                &headers,
+            );
+            Some(format!("SharedKey {}:{}", account, signature))
+        }
+        AzureCredentials::Bearer(token) => Some(format!("Bearer {}", token)),
+        AzureCredentials::Anonymous | AzureCredentials::Sas(_) => None,
+    };
+
+    let mut request = client.request(method, url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    if let Some(authorization) = authorization {
+        request = request.header("authorization", authorization);
+    }
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+    Ok(request)
+}
+
+/// Returns the Shared Key signature of a request.
+/// `path` is the percent-encoded path of the request URL and `headers` contains all `x-ms-` headers.
+fn sign_shared_key(
+    key: &[u8],
+    account: &str,
+    method: &str,
+    path: &str,
+    query: &[(&str, String)],
+    content_length: usize,
+    headers: &[(&str, String)],
+) -> String {
+    let content_length = if content_length == 0 {
+        String::new()
+    } else {
+        content_length.to_string()
+    };
+    let mut canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name.to_lowercase(), value.trim()))
+        .collect::<Vec<_>>();
+    canonical_headers.sort();
+    let mut canonical_query = query
+        .iter()
+        .map(|(name, value)| format!("\n{}:{}", name.to_lowercase(), value))
+        .collect::<Vec<_>>();
+    canonical_query.sort();
+    let string_to_sign = format!(
+        "{}\n\n\n{}\n\n\n\n\n\n\n\n\n{}/{}{}{}",
+        method,
+        content_length,
+        canonical_headers.concat(),
+        account,
+        path,
+        canonical_query.concat(),
+    );
+    BASE64.encode(hmac_sha256(key, string_to_sign.as_bytes()))
+}
+
+/// Query parameters of a List Blobs request for all blobs starting with `prefix`.
+pub(crate) fn list_query(
+    prefix: &str,
+    recursive: bool,
+    max_results: Option<usize>,
+    marker: Option<String>,
+) -> Vec<(&'static str, String)> {
+    let mut query = vec![
+        ("restype", "container".to_string()),
+        ("comp", "list".to_string()),
+        ("prefix", prefix.to_string()),
+    ];
+    if !recursive {
+        query.push(("delimiter", "/".to_string()));
+    }
+    if let Some(max_results) = max_results {
+        query.push(("maxresults", max_results.to_string()));
+    }
+    if let Some(marker) = marker {
+        query.push(("marker", marker));
+    }
+    query
+}
+
+/// Returns the names of the blobs in a List Blobs response and the marker of the next page, if any.
+pub(crate) fn parse_list_response(body: &str) -> (Vec<String>, Option<String>) {
+    let names = xml_elements(body, "Blob")
+        .into_iter()
+        .filter_map(|blob| {
+            xml_elements(blob, "Name")
+                .first()
+                .map(|name| unescape_xml(name))
+        })
+        .collect();
+    let marker = xml_elements(body, "NextMarker")
+        .first()
+        .filter(|marker| !marker.is_empty())
+        .map(|marker| unescape_xml(marker));
+    (names, marker)
+}
+
+/// Formats a timestamp as required by the `x-ms-date` header.
+fn format_date(now: DateTime<Utc>) -> String {
+    now.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_response() {
+        let body = "<EnumerationResults><Blobs>\
+            <Blob><Name>db/wal/1.wal</Name><Properties></Properties></Blob>\
+            <Blob><Name>db/a&amp;b</Name></Blob>\
+            <BlobPrefix><Name>db/tables/</Name></BlobPrefix>\
+            </Blobs><NextMarker>2!abc</NextMarker></EnumerationResults>";
+        let (names, marker) = parse_list_response(body);
+        assert_eq!(names, vec!["db/wal/1.wal", "db/a&b"]);
+        assert_eq!(marker.as_deref(), Some("2!abc"));
+        let (_, marker) = parse_list_response("<Blobs></Blobs><NextMarker></NextMarker>");
+        assert_eq!(marker, None);
+    }
+
+    #[test]
+    fn test_shared_key() {
+        let now = DateTime::parse_from_rfc3339("2013-05-24T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(format_date(now), "Fri, 24 May 2013 00:00:00 GMT");
+        let headers = vec![
+            ("x-ms-version", API_VERSION.to_string()),
+            ("x-ms-date", format_date(now)),
+        ];
+        let sign = |path: &str, content_length: usize| {
+            sign_shared_key(
+                b"key",
+                "account",
+                "PUT",
+                path,
+                &[],
+                content_length,
+                &headers,
+            )
+        };
+        // Signatures are base64 encoded HMAC-SHA256 digests and depend on the path and body
+        assert_eq!(sign("/c/a", 3).len(), 44);
+        assert_ne!(sign("/c/a", 3), sign("/c/b", 3));
+        assert_ne!(sign("/c/a", 3), sign("/c/a", 0));
+    }
+}
@@ -0,0 +1,84 @@
+/// Magic bytes at the start of every zstd frame. Uncompressed subpartitions start with the number of columns as a
+/// little endian `u64`, which can never match.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression applied to subpartition files when they are written to disk.
+/// This is in addition to the LZ4 encoding of individual columns enabled by `mem_lz4`.
+/// Files are decompressed based on their contents, so the compression can be changed without rewriting existing files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PartitionCompression {
+    #[default]
+    None,
+    /// Zstandard with the given level from 1 (fastest) to 22 (smallest). Requires the `enable_zstd` feature.
+    Zstd(i32),
+}
+
+impl PartitionCompression {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            PartitionCompression::None => Ok(()),
+            PartitionCompression::Zstd(_) if !cfg!(feature = "enable_zstd") => {
+                Err("zstd partition compression requires the `enable_zstd` feature".to_string())
+            }
+            PartitionCompression::Zstd(level) if !(1..=22).contains(&level) => Err(format!(
+                "zstd compression level must be between 1 and 22, got {}",
+                level
+            )),
+            PartitionCompression::Zstd(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn compress(self, data: Vec<u8>) -> Vec<u8> {
+        match self {
+            PartitionCompression::None => data,
+            #[cfg(feature = "enable_zstd")]
+            PartitionCompression::Zstd(level) => zstd::bulk::compress(&data, level).unwrap(),
+            #[cfg(not(feature = "enable_zstd"))]
+            PartitionCompression::Zstd(_) => {
+                panic!("zstd is not enabled in this build of LocustDB. Recompile with `features enable_zstd`")
+            }
+        }
+    }
+}
+
+/// Returns the serialized columns of a subpartition file written with any `PartitionCompression`.
+pub(crate) fn decompress(data: Vec<u8>) -> Vec<u8> {
+    try_decompress(data).unwrap()
+}
+
+/// Whether a subpartition file has to be decompressed before its columns can be deserialized.
+pub(crate) fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&ZSTD_MAGIC)
+}
+
+/// Like `decompress`, but returns an error for corrupted zstd frames.
+pub(crate) fn try_decompress(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !is_compressed(&data) {
+        return Ok(data);
+    }
+    decompress_zstd(&data)
+}
+
+#[cfg(feature = "enable_zstd")]
+fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>, String> {
+    zstd::stream::decode_all(data).map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "enable_zstd"))]
+fn decompress_zstd(_: &[u8]) -> Result<Vec<u8>, String> {
+    panic!("Partition is zstd compressed, but zstd is not enabled in this build of LocustDB. Recompile with `features enable_zstd`")
+}
+
+#[cfg(all(test, feature = "enable_zstd"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let data = bincode::serialize(&vec![1u64; 1000]).unwrap();
+        let compressed = PartitionCompression::Zstd(3).compress(data.clone());
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(compressed), data);
+        assert_eq!(decompress(data.clone()), data);
+    }
+}
@@ -7,28 +7,48 @@ use std::path::{Path, PathBuf};
 pub trait BlobWriter {
     fn store(&self, path: &Path, data: &[u8])
         -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+    /// Like `store`, but returns without waiting for the data to reach stable storage
+    fn store_unsynced(
+        &self,
+        path: &Path,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.store(path, data)
+    }
+    /// Waits for data written to `path` with `store_unsynced` to reach stable storage
+    fn sync(&self, _path: &Path) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        Ok(())
+    }
     fn load(&self, path: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync + 'static>>;
     fn delete(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
     fn rename(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
     /// Returns absolute paths of files in the directory
     fn list(&self, path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync + 'static>>;
+    /// Returns absolute paths of files in the directory and all of its subdirectories
+    fn list_recursive(
+        &self,
+        path: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync + 'static>>;
     fn exists(&self, path: &Path) -> Result<bool, Box<dyn Error + Send + Sync + 'static>>;
+    /// Returns the size of the file in bytes
+    fn size(&self, path: &Path) -> Result<u64, Box<dyn Error + Send + Sync + 'static>> {
+        Ok(self.load(path)?.len() as u64)
+    }
 }
 
+#[derive(Default)]
 pub struct FileBlobWriter;
 
 impl FileBlobWriter {
     pub fn new() -> FileBlobWriter {
         FileBlobWriter
     }
-}
 
-impl BlobWriter for FileBlobWriter {
-    /// Atomically writes the data to the file at the given path
-    fn store(
+    fn write(
         &self,
         path: &Path,
         data: &[u8],
+        sync: bool,
     ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
         // Create the directory if it doesn't exist
         if let Some(parent) = path.parent() {
@@ -39,12 +59,43 @@ impl BlobWriter for FileBlobWriter {
         let tmp_path = path.with_extension(".INCOMPLETE");
         let mut file = File::create(&tmp_path)?;
         file.write_all(data)?;
-        file.sync_all()?;
+        if sync {
+            file.sync_all()?;
+        }
         std::fs::rename(tmp_path, path)
             .map_err(|e| format!("Failed to rename file: {}", e))?;
 
         Ok(())
     }
+}
+
+impl BlobWriter for FileBlobWriter {
+    /// Atomically writes the data to the file at the given path
+    fn store(
+        &self,
+        path: &Path,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.write(path, data, true)
+    }
+
+    fn store_unsynced(
+        &self,
+        path: &Path,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        self.write(path, data, false)
+    }
+
+    fn sync(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        match File::open(path) {
+            Ok(file) => file.sync_all()?,
+            // Files that were deleted in the meantime don't need to be synced
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Box::new(err)),
+        }
+        Ok(())
+    }
 
     fn load(&self, path: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync + 'static>> {
         let mut file = File::open(path)?;
@@ -59,6 +110,9 @@ impl BlobWriter for FileBlobWriter {
     }
 
     fn rename(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        if let Some(parent) = dst.parent() {
+            create_dir_all(parent)?;
+        }
         std::fs::rename(src, dst)?;
         Ok(())
     }
@@ -86,7 +140,35 @@ impl BlobWriter for FileBlobWriter {
         }
     }
 
+    fn list_recursive(
+        &self,
+        path: &Path,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync + 'static>> {
+        let mut files = Vec::new();
+        let mut dirs = vec![path.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match dir.read_dir() {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Box::new(err)),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        Ok(files)
+    }
+
     fn exists(&self, path: &Path) -> Result<bool, Box<dyn Error + Send + Sync + 'static>> {
         Ok(path.exists())
     }
+
+    fn size(&self, path: &Path) -> Result<u64, Box<dyn Error + Send + Sync + 'static>> {
+        Ok(std::fs::metadata(path)?.len())
+    }
 }
\ No newline at end of file
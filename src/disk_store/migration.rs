@@ -0,0 +1,107 @@
+use super::storage::Storage;
+use crate::QueryError;
+
+/// Version of the on-disk format written by this version of LocustDB. Databases written before format versions were
+/// introduced have version 0. Databases with an older version can still be opened, `migrate` upgrades them in place.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Upgrades a database from format version `from` to `from + 1`.
+struct Migration {
+    from: u32,
+    description: &'static str,
+    run: fn(&Storage) -> Result<(), QueryError>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "Record checksums of subpartition files written without checksums",
+        run: Storage::add_missing_checksums,
+    },
+    Migration {
+        from: 1,
+        description: "Write the meta store with a header that records its layout",
+        run: Storage::rewrite_metastore,
+    },
+    Migration {
+        from: 2,
+        description: "Record the data directory of subpartition files",
+        run: Storage::record_data_dirs,
+    },
+];
+
+/// Result of `migrate`.
+#[derive(Debug, Clone, Default)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Descriptions of the migrations that were applied, in order.
+    pub applied: Vec<String>,
+}
+
+/// Applies all migrations from the format version of the database up to `FORMAT_VERSION`. The format version is
+/// persisted after every migration, so a migration that fails can be retried without repeating earlier migrations.
+pub fn migrate(storage: &Storage) -> Result<MigrationReport, QueryError> {
+    let from_version = storage.format_version();
+    if from_version > FORMAT_VERSION {
+        return Err(fatal!(
+            "Database has format version {}, but this version of LocustDB only supports format versions up to {}",
+            from_version,
+            FORMAT_VERSION
+        ));
+    }
+    let mut report = MigrationReport {
+        from_version,
+        to_version: from_version,
+        applied: Vec::new(),
+    };
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from_version) {
+        log::info!(
+            "Migrating database from format version {}: {}",
+            migration.from,
+            migration.description
+        );
+        (migration.run)(storage)?;
+        storage.persist_format_version(migration.from + 1)?;
+        report.to_version = migration.from + 1;
+        report.applied.push(migration.description.to_string());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::perf_counter::PerfCounter;
+
+    #[test]
+    fn test_migrate() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let perf_counter = Arc::new(PerfCounter::default());
+        let (storage, _) = Storage::new(tmp_dir.path(), perf_counter.clone(), false).unwrap();
+        assert_eq!(storage.format_version(), FORMAT_VERSION);
+        assert!(migrate(&storage).unwrap().applied.is_empty());
+
+        // Simulates a database written before format versions were introduced
+        storage.persist_format_version(0).unwrap();
+        let (storage, _) = Storage::new(tmp_dir.path(), perf_counter.clone(), false).unwrap();
+        let report = migrate(&storage).unwrap();
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, FORMAT_VERSION);
+        assert_eq!(report.applied.len(), MIGRATIONS.len());
+
+        let (storage, _) = Storage::new(tmp_dir.path(), perf_counter, false).unwrap();
+        assert_eq!(storage.format_version(), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn test_unsupported_format_version() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let perf_counter = Arc::new(PerfCounter::default());
+        let (storage, _) = Storage::new(tmp_dir.path(), perf_counter.clone(), false).unwrap();
+        storage.persist_format_version(FORMAT_VERSION + 1).unwrap();
+        assert!(Storage::new(tmp_dir.path(), perf_counter, false).is_err());
+    }
+}
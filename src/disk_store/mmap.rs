@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use ordered_float::OrderedFloat;
+
+use crate::engine::data_types::EncodingType;
+use crate::mem_store::column::{DataSection, MappedSection};
+use crate::mem_store::{Codec, Column};
+
+/// Maps the subpartition file at `path` into memory.
+pub fn map_file(path: &Path) -> io::Result<Arc<Mmap>> {
+    let file = File::open(path)?;
+    // Subpartition files are never modified in place, new versions are always renamed over the old file
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(Arc::new(mmap))
+}
+
+/// Deserializes the columns of an uncompressed subpartition file.
+/// Data sections refer directly to the mapped file, except for sections that are not aligned for their type which
+/// are copied to the heap.
+pub fn deserialize_columns(mmap: &Arc<Mmap>) -> bincode::Result<Vec<Column>> {
+    // Follows the bincode encoding of `Vec<Column>`, reading everything but the data sections with bincode
+    let mut reader: &[u8] = mmap;
+    let column_count: u64 = bincode::deserialize_from(&mut reader)?;
+    let mut columns = Vec::with_capacity(column_count as usize);
+    for _ in 0..column_count {
+        let name: String = bincode::deserialize_from(&mut reader)?;
+        let len: usize = bincode::deserialize_from(&mut reader)?;
+        let range: Option<(i64, i64)> = bincode::deserialize_from(&mut reader)?;
+        let codec: Codec = bincode::deserialize_from(&mut reader)?;
+        let section_count: u64 = bincode::deserialize_from(&mut reader)?;
+        let mut data = Vec::with_capacity(section_count as usize);
+        for _ in 0..section_count {
+            let section_start = mmap.len() - reader.len();
+            let variant: u32 = bincode::deserialize_from(&mut reader)?;
+            let (encoding_type, element_size) = match variant {
+                0 => (EncodingType::U8, mem::size_of::<u8>()),
+                1 => (EncodingType::U16, mem::size_of::<u16>()),
+                2 => (EncodingType::U32, mem::size_of::<u32>()),
+                3 => (EncodingType::U64, mem::size_of::<u64>()),
+                4 => (EncodingType::I64, mem::size_of::<i64>()),
+                5 => (EncodingType::F64, mem::size_of::<OrderedFloat<f64>>()),
+                6 => {
+                    data.push(DataSection::Null(bincode::deserialize_from(&mut reader)?));
+                    continue;
+                }
+                7 => (EncodingType::Bitvec, mem::size_of::<u8>()),
+                _ => {
+                    return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                        "invalid data section variant {}",
+                        variant
+                    ))))
+                }
+            };
+            let section_len: u64 = bincode::deserialize_from(&mut reader)?;
+            let offset = mmap.len() - reader.len();
+            let size_bytes = section_len as usize * element_size;
+            if size_bytes > reader.len() {
+                return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                    "data section of {} bytes exceeds end of file",
+                    size_bytes
+                ))));
+            }
+            let section =
+                match MappedSection::new(mmap, encoding_type, offset, section_len as usize) {
+                    Some(section) => DataSection::Mapped(section),
+                    None => bincode::deserialize(&mmap[section_start..])?,
+                };
+            data.push(section);
+            reader = &reader[size_bytes..];
+        }
+        columns.push(Column::from_parts(name, len, range, codec, data));
+    }
+    Ok(columns)
+}
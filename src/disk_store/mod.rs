@@ -1,12 +1,22 @@
-mod file_writer;
+mod azure_blob;
+pub mod compression;
+pub mod file_writer;
+pub mod migration;
+pub mod mmap;
 pub mod noop_storage;
+pub mod object_store;
+#[cfg(feature = "parquet_export")]
+pub mod parquet_export;
+pub(crate) mod s3;
 pub mod storage;
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
-use crate::mem_store::column::Column;
+use crate::mem_store::bloom::BloomFilter;
+use crate::mem_store::column::{Column, ColumnStats};
 use crate::perf_counter::QueryPerfCounter;
 use crate::scheduler::inner_locustdb::InnerLocustDB;
 
@@ -37,6 +47,12 @@ pub struct PartitionMetadata {
     pub len: usize,
     pub subpartitions: Vec<SubpartitionMetadata>,
     pub column_name_to_subpartition_index: HashMap<String, usize>,
+    /// Minimum and maximum value of integer columns. Empty for partitions written by older versions.
+    pub column_ranges: HashMap<String, (i64, i64)>,
+    /// Bloom filters of string columns configured with `LocustDB::set_bloom_filter`.
+    pub bloom_filters: HashMap<String, BloomFilter>,
+    /// Number of distinct and null values of each column. Empty for partitions written by older versions.
+    pub column_stats: HashMap<String, ColumnStats>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -44,13 +60,18 @@ pub struct SubpartitionMetadata {
     // pub column_names: HashSet<String>,
     pub size_bytes: u64,
     pub subpartition_key: String,
+    /// Codec signature of each column in the subpartition. Empty for partitions written by older versions.
+    pub column_codecs: HashMap<String, String>,
+    /// Seahash of the subpartition file as stored on disk. `None` for partitions written by older versions.
+    pub checksum: Option<u64>,
+    /// `tables` directory of the additional data directory the file was written to. `None` for files in the database
+    /// directory.
+    pub data_dir: Option<PathBuf>,
 }
 
 impl PartitionMetadata {
-    pub fn subpartition_key(&self, column_name: &str) -> String {
+    pub fn subpartition(&self, column_name: &str) -> &SubpartitionMetadata {
         let subpartition_index = self.column_name_to_subpartition_index[column_name];
-        self.subpartitions[subpartition_index]
-            .subpartition_key
-            .clone()
+        &self.subpartitions[subpartition_index]
     }
 }
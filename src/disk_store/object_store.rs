@@ -0,0 +1,589 @@
+use std::error::Error;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+
+use super::azure_blob::{self, AzureCredentials};
+use super::file_writer::BlobWriter;
+use super::s3::{authorize, canonical_query, unescape_xml, uri_encode, xml_elements, Credentials};
+use crate::QueryError;
+
+type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+/// Delay before the first retry of a failed request, doubled for every further retry
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Location and credentials of a bucket or container that stores the meta store, WAL segments and partitions of a
+/// database instead of the local file system.
+#[derive(Clone)]
+pub struct ObjectStoreOptions {
+    service: Service,
+    // Name of the bucket, or of the container for Azure
+    bucket: String,
+    // Empty or ends with `/`
+    prefix: String,
+    endpoint: Option<String>,
+    max_retries: u32,
+}
+
+#[derive(Clone)]
+enum Service {
+    /// S3 and APIs compatible with S3, such as the XML API of Google Cloud Storage.
+    S3 {
+        region: String,
+        credentials: Credentials,
+    },
+    Azure {
+        account: String,
+        credentials: AzureCredentials,
+    },
+}
+
+impl ObjectStoreOptions {
+    /// Stores all files under `url`, which has the form `s3://bucket/prefix` for S3, `gs://bucket/prefix` for Google
+    /// Cloud Storage or `az://account/container/prefix` for Azure Blob Storage.
+    pub fn new(url: &str) -> Result<ObjectStoreOptions, QueryError> {
+        let (scheme, path) = match url.split_once("://") {
+            Some(parts) => parts,
+            None => bail!(
+                QueryError::ParseError,
+                "Expected s3://, gs:// or az:// URL, got {}",
+                url
+            ),
+        };
+        let s3 = |region: &str| Service::S3 {
+            region: region.to_string(),
+            credentials: Credentials::Anonymous,
+        };
+        let (service, endpoint, path) = match scheme {
+            "s3" => (s3("us-east-1"), None, path),
+            "gs" => (
+                s3("auto"),
+                Some("https://storage.googleapis.com".to_string()),
+                path,
+            ),
+            "az" => {
+                let (account, path) = path.split_once('/').unwrap_or((path, ""));
+                if account.is_empty() {
+                    bail!(QueryError::ParseError, "Missing storage account in {}", url);
+                }
+                let service = Service::Azure {
+                    account: account.to_string(),
+                    credentials: AzureCredentials::Anonymous,
+                };
+                (service, None, path)
+            }
+            _ => bail!(
+                QueryError::NotImplemented,
+                "Storing data at {}:// URLs",
+                scheme
+            ),
+        };
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if bucket.is_empty() {
+            bail!(QueryError::ParseError, "Missing bucket in {}", url);
+        }
+        let prefix = prefix.trim_matches('/');
+        Ok(ObjectStoreOptions {
+            service,
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+            endpoint,
+            max_retries: 3,
+        })
+    }
+
+    /// Base URL of the object store, e.g. `http://localhost:9000` for a local MinIO instance or
+    /// `http://127.0.0.1:10000/devstoreaccount1` for a local Azurite instance.
+    /// Objects are addressed with path-style URLs.
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: &str) -> ObjectStoreOptions {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Number of times a request is retried after a network error or a response with a status that indicates a
+    /// transient failure, such as 503 Slow Down. Defaults to 3.
+    #[must_use]
+    pub fn with_max_retries(mut self, max_retries: u32) -> ObjectStoreOptions {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Region of an S3 bucket. Ignored for Azure.
+    #[must_use]
+    pub fn with_region(mut self, region: &str) -> ObjectStoreOptions {
+        if let Service::S3 { region: r, .. } = &mut self.service {
+            *r = region.to_string();
+        }
+        self
+    }
+
+    /// Signs requests with AWS Signature Version 4. Also works with GCS HMAC keys. Ignored for Azure.
+    #[must_use]
+    pub fn with_aws_credentials(
+        mut self,
+        access_key_id: &str,
+        secret_access_key: &str,
+    ) -> ObjectStoreOptions {
+        if let Service::S3 { credentials, .. } = &mut self.service {
+            *credentials = Credentials::Aws {
+                access_key_id: access_key_id.to_string(),
+                secret_access_key: secret_access_key.to_string(),
+            };
+        }
+        self
+    }
+
+    /// Signs requests with the base64 encoded access key of the Azure storage account. Ignored for S3 and GCS.
+    #[must_use]
+    pub fn with_azure_shared_key(mut self, account_key: &str) -> ObjectStoreOptions {
+        if let Service::Azure { credentials, .. } = &mut self.service {
+            *credentials = AzureCredentials::SharedKey(account_key.to_string());
+        }
+        self
+    }
+
+    /// Authenticates requests with an Azure shared access signature. Ignored for S3 and GCS.
+    #[must_use]
+    pub fn with_sas_token(mut self, token: &str) -> ObjectStoreOptions {
+        if let Service::Azure { credentials, .. } = &mut self.service {
+            *credentials = AzureCredentials::Sas(token.to_string());
+        }
+        self
+    }
+
+    /// Authenticates requests with an OAuth 2.0 access token.
+    #[must_use]
+    pub fn with_bearer_token(mut self, token: &str) -> ObjectStoreOptions {
+        match &mut self.service {
+            Service::S3 { credentials, .. } => {
+                *credentials = Credentials::Bearer(token.to_string())
+            }
+            Service::Azure { credentials, .. } => {
+                *credentials = AzureCredentials::Bearer(token.to_string())
+            }
+        }
+        self
+    }
+
+    /// Reads the endpoint, region and credentials from the `AWS_ENDPOINT_URL`, `AWS_REGION`, `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY` environment variables, or an access token from `GOOGLE_OAUTH_ACCESS_TOKEN`.
+    /// For Azure, the endpoint and credentials are read from `AZURE_STORAGE_ENDPOINT` and `AZURE_STORAGE_KEY` or
+    /// `AZURE_STORAGE_SAS_TOKEN`.
+    #[must_use]
+    pub fn with_env_credentials(mut self) -> ObjectStoreOptions {
+        let var = |name: &str| std::env::var(name).ok();
+        match self.service {
+            Service::S3 { .. } => {
+                if let Some(endpoint) = var("AWS_ENDPOINT_URL") {
+                    self = self.with_endpoint(&endpoint);
+                }
+                if let Some(region) = var("AWS_REGION") {
+                    self = self.with_region(&region);
+                }
+                if let (Some(access_key_id), Some(secret_access_key)) =
+                    (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY"))
+                {
+                    self = self.with_aws_credentials(&access_key_id, &secret_access_key);
+                } else if let Some(token) = var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+                    self = self.with_bearer_token(&token);
+                }
+            }
+            Service::Azure { .. } => {
+                if let Some(endpoint) = var("AZURE_STORAGE_ENDPOINT") {
+                    self = self.with_endpoint(&endpoint);
+                }
+                if let Some(key) = var("AZURE_STORAGE_KEY") {
+                    self = self.with_azure_shared_key(&key);
+                } else if let Some(token) = var("AZURE_STORAGE_SAS_TOKEN") {
+                    self = self.with_sas_token(&token);
+                }
+            }
+        }
+        self
+    }
+
+    fn endpoint(&self) -> String {
+        match (&self.endpoint, &self.service) {
+            (Some(endpoint), _) => endpoint.clone(),
+            (None, Service::S3 { region, .. }) => format!("https://s3.{}.amazonaws.com", region),
+            (None, Service::Azure { account, .. }) => {
+                format!("https://{}.blob.core.windows.net", account)
+            }
+        }
+    }
+}
+
+/// Stores files as objects in an S3 compatible object store or as blobs in Azure Blob Storage.
+/// Paths are interpreted relative to the prefix of the bucket and directories are emulated with `/` separated keys,
+/// so renaming a directory moves every object in it, see `rename`.
+pub struct ObjectStoreBlobWriter {
+    opts: ObjectStoreOptions,
+    client: reqwest::Client,
+    // Requests run on a separate runtime so that the blocking `BlobWriter` methods can be called from async code
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl ObjectStoreBlobWriter {
+    pub fn new(opts: ObjectStoreOptions) -> ObjectStoreBlobWriter {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("locustdb-object-store")
+            .enable_all()
+            .build()
+            .unwrap();
+        ObjectStoreBlobWriter {
+            opts,
+            client: reqwest::Client::new(),
+            runtime: Some(runtime),
+        }
+    }
+
+    fn key(&self, path: &Path) -> String {
+        let path = path.to_string_lossy().replace('\\', "/");
+        format!("{}{}", self.opts.prefix, path.trim_start_matches('/'))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        PathBuf::from(key.strip_prefix(&self.opts.prefix).unwrap_or(key))
+    }
+
+    fn run<T: Send + 'static>(
+        &self,
+        future: impl Future<Output = Result<T, BoxError>> + Send + 'static,
+    ) -> Result<T, BoxError> {
+        let handle = self.runtime.as_ref().unwrap().spawn(future);
+        futures::executor::block_on(handle)?
+    }
+
+    /// Sends a request for the object `key`, or for the bucket if `key` is empty, and returns the status and body.
+    /// Requests that fail with a network error or a transient status are retried with exponential backoff.
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> Result<(StatusCode, Vec<u8>), BoxError> {
+        let endpoint = self.opts.endpoint();
+        let request = match &self.opts.service {
+            Service::S3 {
+                region,
+                credentials,
+            } => {
+                let path = if key.is_empty() {
+                    format!("/{}", uri_encode(&self.opts.bucket, true))
+                } else {
+                    format!(
+                        "/{}/{}",
+                        uri_encode(&self.opts.bucket, true),
+                        uri_encode(key, false)
+                    )
+                };
+                let query = canonical_query(query);
+                let url = if query.is_empty() {
+                    format!("{}{}", endpoint, path)
+                } else {
+                    format!("{}{}?{}", endpoint, path, query)
+                };
+                let mut request = authorize(
+                    self.client.request(method.clone(), &url),
+                    credentials,
+                    method.as_str(),
+                    &endpoint,
+                    region,
+                    &path,
+                    &query,
+                );
+                if let Some(body) = body {
+                    request = request.body(body);
+                }
+                request
+            }
+            Service::Azure {
+                account,
+                credentials,
+            } => azure_blob::request(
+                &self.client,
+                method.clone(),
+                &endpoint,
+                account,
+                credentials,
+                &self.opts.bucket,
+                key,
+                query,
+                body,
+            )?,
+        };
+        let max_retries = self.opts.max_retries;
+        let description = format!("{} {}", method, key);
+        self.run(async move {
+            let mut request = request;
+            let mut backoff = INITIAL_BACKOFF;
+            let mut retries = 0;
+            loop {
+                let next_attempt = request.try_clone();
+                let result = match request.send().await {
+                    Ok(response) => {
+                        let status = response.status();
+                        response.bytes().await.map(|body| (status, body.to_vec()))
+                    }
+                    Err(err) => Err(err),
+                };
+                let transient = match &result {
+                    Ok((status, _)) => is_transient(*status),
+                    Err(err) => !err.is_builder(),
+                };
+                match next_attempt {
+                    Some(next_attempt) if transient && retries < max_retries => {
+                        match &result {
+                            Ok((status, _)) => log::warn!(
+                                "{} failed with status {}, retrying in {:?}",
+                                description,
+                                status,
+                                backoff
+                            ),
+                            Err(err) => log::warn!(
+                                "{} failed: {}, retrying in {:?}",
+                                description,
+                                err,
+                                backoff
+                            ),
+                        }
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                        retries += 1;
+                        request = next_attempt;
+                    }
+                    _ => return result.map_err(BoxError::from),
+                }
+            }
+        })
+        .map_err(|e| format!("{} {} failed: {}", method, key, e).into())
+    }
+
+    fn expect_success(
+        &self,
+        method: Method,
+        key: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, BoxError> {
+        let (status, body) = self.request(method.clone(), key, &[], body)?;
+        if !status.is_success() {
+            return Err(format!(
+                "{} {} failed with status {}: {}",
+                method,
+                key,
+                status,
+                String::from_utf8_lossy(&body)
+            )
+            .into());
+        }
+        Ok(body)
+    }
+
+    /// Returns the keys of all objects starting with `prefix`, excluding objects nested in further directories
+    /// unless `recursive` is set.
+    fn list_keys(
+        &self,
+        prefix: &str,
+        recursive: bool,
+        max_keys: Option<usize>,
+    ) -> Result<Vec<String>, BoxError> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let query = match self.opts.service {
+                Service::S3 { .. } => {
+                    let mut query = vec![
+                        ("list-type", "2".to_string()),
+                        ("prefix", prefix.to_string()),
+                    ];
+                    if !recursive {
+                        query.push(("delimiter", "/".to_string()));
+                    }
+                    if let Some(max_keys) = max_keys {
+                        query.push(("max-keys", max_keys.to_string()));
+                    }
+                    if let Some(token) = continuation_token.take() {
+                        query.push(("continuation-token", token));
+                    }
+                    query
+                }
+                Service::Azure { .. } => {
+                    azure_blob::list_query(prefix, recursive, max_keys, continuation_token.take())
+                }
+            };
+            let (status, body) = self.request(Method::GET, "", &query, None)?;
+            let body = String::from_utf8_lossy(&body);
+            if !status.is_success() {
+                return Err(
+                    format!("Listing {} failed with status {}: {}", prefix, status, body).into(),
+                );
+            }
+            let next_page = match self.opts.service {
+                Service::S3 { .. } => {
+                    for contents in xml_elements(&body, "Contents") {
+                        if let Some(key) = xml_elements(contents, "Key").first() {
+                            keys.push(unescape_xml(key));
+                        }
+                    }
+                    xml_elements(&body, "NextContinuationToken")
+                        .first()
+                        .map(|token| unescape_xml(token))
+                }
+                Service::Azure { .. } => {
+                    let (names, marker) = azure_blob::parse_list_response(&body);
+                    keys.extend(names);
+                    marker
+                }
+            };
+            match next_page {
+                Some(token) if max_keys.is_none() => continuation_token = Some(token),
+                _ => return Ok(keys),
+            }
+        }
+    }
+}
+
+/// Whether a request that failed with `status` may succeed when it is sent again.
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+impl BlobWriter for ObjectStoreBlobWriter {
+    fn store(&self, path: &Path, data: &[u8]) -> Result<(), BoxError> {
+        // Object uploads are atomic, so unlike files they don't need to be written to a temporary location first
+        self.expect_success(Method::PUT, &self.key(path), Some(data.to_vec()))?;
+        Ok(())
+    }
+
+    fn load(&self, path: &Path) -> Result<Vec<u8>, BoxError> {
+        self.expect_success(Method::GET, &self.key(path), None)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), BoxError> {
+        let key = self.key(path);
+        let (status, body) = self.request(Method::DELETE, &key, &[], None)?;
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            return Err(format!(
+                "DELETE {} failed with status {}: {}",
+                key,
+                status,
+                String::from_utf8_lossy(&body)
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Object stores can't rename objects, so each object is copied and then deleted.
+    /// If `src` is a directory, all objects under it are moved. Unlike renaming a directory on a file system this is
+    /// not atomic: all objects are copied before any of them is deleted, so `src` is left intact if copying fails,
+    /// but a failure while deleting leaves some objects at both `src` and `dst`.
+    fn rename(&self, src: &Path, dst: &Path) -> Result<(), BoxError> {
+        let (src, dst) = (self.key(src), self.key(dst));
+        let (status, _) = self.request(Method::HEAD, &src, &[], None)?;
+        let moves = if status.is_success() {
+            vec![(src, dst)]
+        } else {
+            let src_dir = format!("{}/", src);
+            self.list_keys(&src_dir, true, None)?
+                .into_iter()
+                .map(|key| {
+                    let dst_key = format!("{}/{}", dst, &key[src_dir.len()..]);
+                    (key, dst_key)
+                })
+                .collect()
+        };
+        for (src_key, dst_key) in &moves {
+            let data = self.expect_success(Method::GET, src_key, None)?;
+            self.expect_success(Method::PUT, dst_key, Some(data))?;
+        }
+        for (src_key, _) in &moves {
+            self.expect_success(Method::DELETE, src_key, None)?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, path: &Path) -> Result<Vec<PathBuf>, BoxError> {
+        let dir = format!("{}/", self.key(path).trim_end_matches('/'));
+        let keys = self.list_keys(&dir, false, None)?;
+        Ok(keys.iter().map(|key| self.path(key)).collect())
+    }
+
+    fn list_recursive(&self, path: &Path) -> Result<Vec<PathBuf>, BoxError> {
+        let dir = format!("{}/", self.key(path).trim_end_matches('/'));
+        let keys = self.list_keys(&dir, true, None)?;
+        Ok(keys.iter().map(|key| self.path(key)).collect())
+    }
+
+    /// Returns whether there is an object at `path` or any object in the directory `path`.
+    fn exists(&self, path: &Path) -> Result<bool, BoxError> {
+        let key = self.key(path);
+        let (status, _) = self.request(Method::HEAD, &key, &[], None)?;
+        if status.is_success() {
+            return Ok(true);
+        }
+        if status != StatusCode::NOT_FOUND {
+            return Err(format!("HEAD {} failed with status {}", key, status).into());
+        }
+        let dir = format!("{}/", key.trim_end_matches('/'));
+        Ok(!self.list_keys(&dir, true, Some(1))?.is_empty())
+    }
+}
+
+impl Drop for ObjectStoreBlobWriter {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which is not allowed if the writer is dropped from within another runtime
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        let opts = ObjectStoreOptions::new("s3://bucket/db/").unwrap();
+        assert_eq!(opts.prefix, "db/");
+        let writer = ObjectStoreBlobWriter::new(opts);
+        let path = Path::new("").join("tables").join("t").join("00001_x.part");
+        assert_eq!(writer.key(&path), "db/tables/t/00001_x.part");
+        assert_eq!(writer.path("db/wal/3.wal"), PathBuf::from("wal/3.wal"));
+
+        let opts = ObjectStoreOptions::new("gs://bucket").unwrap();
+        assert_eq!(opts.prefix, "");
+        assert_eq!(opts.endpoint(), "https://storage.googleapis.com");
+
+        let opts = ObjectStoreOptions::new("az://account/container/db").unwrap();
+        assert_eq!(opts.bucket, "container");
+        assert_eq!(opts.prefix, "db/");
+        assert_eq!(opts.endpoint(), "https://account.blob.core.windows.net");
+        assert!(ObjectStoreOptions::new("az://account").is_err());
+        assert!(ObjectStoreOptions::new("file:///tmp/db").is_err());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_transient(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_transient(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_transient(StatusCode::REQUEST_TIMEOUT));
+        assert!(!is_transient(StatusCode::NOT_FOUND));
+        assert!(!is_transient(StatusCode::FORBIDDEN));
+        assert!(!is_transient(StatusCode::OK));
+    }
+}
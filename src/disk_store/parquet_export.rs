@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::basic::{Encoding, LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::{WriterProperties, WriterPropertiesBuilder};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::{ColumnPath, Type};
+
+use crate::engine::query_task::{BasicTypeColumn, QueryOutput};
+use crate::ingest::raw_val::RawVal;
+use crate::QueryError;
+
+/// Column values converted to a Parquet physical type.
+/// Definition levels are only present for columns that contain nulls, and only non-null values are stored.
+enum ParquetColumn {
+    Int(Vec<i64>, Option<Vec<i16>>),
+    Float(Vec<f64>, Option<Vec<i16>>),
+    String(Vec<ByteArray>, Option<Vec<i16>>),
+}
+
+impl ParquetColumn {
+    fn from_column(column: &BasicTypeColumn) -> ParquetColumn {
+        match column {
+            BasicTypeColumn::Int(ints) => ParquetColumn::Int(ints.clone(), None),
+            BasicTypeColumn::Float(floats) => ParquetColumn::Float(floats.clone(), None),
+            BasicTypeColumn::String(strings) => ParquetColumn::String(
+                strings
+                    .iter()
+                    .map(|s| ByteArray::from(s.as_str()))
+                    .collect(),
+                None,
+            ),
+            BasicTypeColumn::Null(count) => ParquetColumn::Int(vec![], Some(vec![0; *count])),
+            BasicTypeColumn::Mixed(vals) => ParquetColumn::from_mixed(vals),
+        }
+    }
+
+    /// Columns that contain values of a single type plus nulls keep their type, columns with values of several
+    /// types are written as strings.
+    fn from_mixed(vals: &[RawVal]) -> ParquetColumn {
+        let levels = vals
+            .iter()
+            .map(|v| i16::from(*v != RawVal::Null))
+            .collect::<Vec<_>>();
+        let non_null = || vals.iter().filter(|v| **v != RawVal::Null);
+        if non_null().all(|v| matches!(v, RawVal::Int(_))) {
+            let ints = non_null()
+                .map(|v| match v {
+                    RawVal::Int(i) => *i,
+                    _ => unreachable!(),
+                })
+                .collect();
+            ParquetColumn::Int(ints, Some(levels))
+        } else if non_null().all(|v| matches!(v, RawVal::Float(_))) {
+            let floats = non_null()
+                .map(|v| match v {
+                    RawVal::Float(f) => f.0,
+                    _ => unreachable!(),
+                })
+                .collect();
+            ParquetColumn::Float(floats, Some(levels))
+        } else {
+            let strings = non_null()
+                .map(|v| match v {
+                    RawVal::Str(s) => ByteArray::from(s.as_str()),
+                    v => ByteArray::from(v.to_string().as_str()),
+                })
+                .collect();
+            ParquetColumn::String(strings, Some(levels))
+        }
+    }
+
+    fn schema_type(&self, name: &str) -> Result<Type, QueryError> {
+        let (physical_type, logical_type, levels) = match self {
+            ParquetColumn::Int(_, levels) => (PhysicalType::INT64, None, levels),
+            ParquetColumn::Float(_, levels) => (PhysicalType::DOUBLE, None, levels),
+            ParquetColumn::String(_, levels) => {
+                (PhysicalType::BYTE_ARRAY, Some(LogicalType::String), levels)
+            }
+        };
+        let repetition = if levels.is_some() {
+            Repetition::OPTIONAL
+        } else {
+            Repetition::REQUIRED
+        };
+        Type::primitive_type_builder(name, physical_type)
+            .with_repetition(repetition)
+            .with_logical_type(logical_type)
+            .build()
+            .map_err(|e| fatal!("Failed to create Parquet schema for column {}: {}", name, e))
+    }
+
+    /// Integers are delta encoded and floats stored plain, strings use dictionary encoding which is effective for the
+    /// low cardinality string columns that LocustDB itself stores dictionary encoded.
+    fn set_encoding(
+        &self,
+        properties: WriterPropertiesBuilder,
+        name: &str,
+    ) -> WriterPropertiesBuilder {
+        let path = ColumnPath::from(name);
+        match self {
+            ParquetColumn::Int(_, _) => properties
+                .set_column_dictionary_enabled(path.clone(), false)
+                .set_column_encoding(path, Encoding::DELTA_BINARY_PACKED),
+            ParquetColumn::Float(_, _) => properties
+                .set_column_dictionary_enabled(path.clone(), false)
+                .set_column_encoding(path, Encoding::PLAIN),
+            ParquetColumn::String(_, _) => properties.set_column_dictionary_enabled(path, true),
+        }
+    }
+}
+
+/// Writes all columns of a query result computed with `rowformat = false` to a Parquet file with a single row group.
+pub fn write_parquet(output: &QueryOutput, path: &Path) -> Result<(), QueryError> {
+    let columns = output
+        .columns
+        .iter()
+        .map(|(name, column)| (name.as_str(), ParquetColumn::from_column(column)))
+        .collect::<Vec<_>>();
+
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut properties = WriterProperties::builder();
+    for (name, column) in &columns {
+        fields.push(Arc::new(column.schema_type(name)?));
+        properties = column.set_encoding(properties, name);
+    }
+    let schema = Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()
+        .map_err(|e| fatal!("Failed to create Parquet schema: {}", e))?;
+
+    let file =
+        File::create(path).map_err(|e| fatal!("Failed to create {}: {}", path.display(), e))?;
+    let mut writer =
+        SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties.build()))
+            .map_err(|e| fatal!("{}", e))?;
+    let mut row_group = writer.next_row_group().map_err(|e| fatal!("{}", e))?;
+    for (name, column) in &columns {
+        let mut column_writer = match row_group.next_column().map_err(|e| fatal!("{}", e))? {
+            Some(column_writer) => column_writer,
+            None => return Err(fatal!("Missing column writer for {}", name)),
+        };
+        match column {
+            ParquetColumn::Int(values, levels) => {
+                column_writer
+                    .typed::<Int64Type>()
+                    .write_batch(values, levels.as_deref(), None)
+            }
+            ParquetColumn::Float(values, levels) => column_writer
+                .typed::<DoubleType>()
+                .write_batch(values, levels.as_deref(), None),
+            ParquetColumn::String(values, levels) => column_writer
+                .typed::<ByteArrayType>()
+                .write_batch(values, levels.as_deref(), None),
+        }
+        .map_err(|e| fatal!("Failed to write column {}: {}", name, e))?;
+        column_writer.close().map_err(|e| fatal!("{}", e))?;
+    }
+    row_group.close().map_err(|e| fatal!("{}", e))?;
+    writer.close().map_err(|e| fatal!("{}", e))?;
+    Ok(())
+}
@@ -1,28 +1,295 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::iter;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 
+use super::compression::{self, PartitionCompression};
 use super::file_writer::{BlobWriter, FileBlobWriter};
+use super::migration::FORMAT_VERSION;
+use super::mmap;
 use super::{ColumnLoader, PartitionMetadata, SubpartitionMetadata};
 use crate::logging_client::EventBuffer;
-use crate::mem_store::{Column, DataSource};
+use crate::mem_store::bloom::BloomFilter;
+use crate::mem_store::view::rename_view_tables;
+use crate::mem_store::{
+    Column, ColumnStats, CompactionPolicy, DataSource, Deduplication, MaterializedView,
+    SchemaEnforcement, TableSchema,
+};
 use crate::perf_counter::{PerfCounter, QueryPerfCounter};
+use crate::scheduler::ScheduledQuery;
+use crate::QueryError;
 
 #[derive(Serialize, Deserialize)]
 pub struct WALSegment<'a> {
     pub id: u64,
     pub data: Cow<'a, EventBuffer>,
+    /// Unix timestamp in milliseconds at which the segment was written. 0 for segments written by older versions.
+    pub timestamp_ms: u64,
+}
+
+/// WAL segment written by versions without timestamps.
+#[derive(Deserialize)]
+struct WALSegmentWithoutTimestamp<'a> {
+    id: u64,
+    data: Cow<'a, EventBuffer>,
+}
+
+fn deserialize_wal_segment(path: &Path, data: &[u8]) -> Result<WALSegment<'static>, QueryError> {
+    if let Ok(segment) = bincode::deserialize(data) {
+        return Ok(segment);
+    }
+    let old: WALSegmentWithoutTimestamp = bincode::deserialize(data)
+        .map_err(|err| fatal!("Corrupted WAL segment {}: {}", path.display(), err))?;
+    Ok(WALSegment {
+        id: old.id,
+        data: Cow::Owned(old.data.into_owned()),
+        timestamp_ms: 0,
+    })
+}
+
+/// Point-in-time recovery target, see `Storage::recover_to`.
+#[derive(Clone, Debug)]
+pub struct RecoveryTarget {
+    /// WAL archive of the database the backup was taken from.
+    pub wal_archive: PathBuf,
+    /// Unix timestamp in milliseconds. WAL segments written after this time are not replayed.
+    pub timestamp_ms: u64,
+}
+
+/// When WAL segments are forced to stable storage, which trades ingestion throughput against the number of rows that
+/// can be lost if the machine crashes. Rows are never lost if only the process crashes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WalSync {
+    /// Fsync every segment before ingestion returns.
+    #[default]
+    Always,
+    /// Fsync once every `n` segments, up to `n - 1` segments can be lost.
+    Batched(usize),
+    /// Leave writing segments to stable storage to the operating system.
+    Never,
+}
+
+impl WalSync {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            WalSync::Batched(0) => Err("WAL sync batch size must be greater than 0".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for WalSync {
+    type Err = String;
+
+    /// Parses `always`, `never` or the number of segments per batch.
+    fn from_str(s: &str) -> Result<WalSync, String> {
+        match s {
+            "always" => Ok(WalSync::Always),
+            "never" => Ok(WalSync::Never),
+            _ => s.parse().map(WalSync::Batched).map_err(|_| {
+                format!(
+                    "Invalid WAL sync policy {}, expected always, never or a number",
+                    s
+                )
+            }),
+        }
+    }
 }
 
 type TableName = String;
 
+/// Prefix of meta store files, followed by the format version of their layout as little-endian `u32`. Meta stores
+/// written before format versions were introduced have no header.
+const META_STORE_MAGIC: &[u8; 8] = b"LOCUSTMS";
+
+/// Every change to the layout of the meta store bumps `migration::FORMAT_VERSION`, and `deserialize_metastore` keeps
+/// decoding the layouts of earlier format versions.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct MetaStore {
     pub next_wal_id: u64,
     pub partitions: HashMap<TableName, HashMap<PartitionID, PartitionMetadata>>,
+    /// Schemas of tables created with `CREATE TABLE`.
+    pub schemas: HashMap<TableName, TableSchema>,
+    /// Definitions of materialized views by view name.
+    pub views: HashMap<TableName, MaterializedView>,
+    /// Scheduled queries and their watermarks by name.
+    pub scheduled_queries: HashMap<String, ScheduledQuery>,
+    /// Schema enforcement of tables that do not use the default permissive mode.
+    pub schema_enforcement: HashMap<TableName, SchemaEnforcement>,
+    /// Partitions written by backfill ingestion, which are excluded from compaction.
+    pub backfill_partitions: HashMap<TableName, HashSet<PartitionID>>,
+    /// Deduplication settings of tables that drop rows with duplicate keys.
+    pub deduplication: HashMap<TableName, Deduplication>,
+    /// Shared dictionaries by table and column name.
+    pub shared_dictionaries: HashMap<TableName, HashMap<String, Vec<String>>>,
+    /// Columns with per-partition bloom filters by table.
+    pub bloom_filter_columns: HashMap<TableName, HashSet<String>>,
+    /// Columns by which compaction sorts the rows of each table.
+    pub sort_keys: HashMap<TableName, String>,
+    /// Compaction policies of tables that do not use the default size-tiered policy.
+    pub compaction_policies: HashMap<TableName, CompactionPolicy>,
+    /// Maximum bytes of resident columns of tables with a memory limit.
+    pub memory_limits: HashMap<TableName, usize>,
+    /// Version of the on-disk format, see `migration::FORMAT_VERSION`.
+    pub format_version: u32,
+}
+
+/// Meta store layout of format version 2.
+#[derive(Deserialize)]
+struct MetaStoreV2 {
+    next_wal_id: u64,
+    partitions: PartitionsV2,
+    schemas: HashMap<TableName, TableSchema>,
+    views: HashMap<TableName, MaterializedView>,
+    scheduled_queries: HashMap<String, ScheduledQuery>,
+    schema_enforcement: HashMap<TableName, SchemaEnforcement>,
+    backfill_partitions: HashMap<TableName, HashSet<PartitionID>>,
+    deduplication: HashMap<TableName, Deduplication>,
+    shared_dictionaries: HashMap<TableName, HashMap<String, Vec<String>>>,
+    bloom_filter_columns: HashMap<TableName, HashSet<String>>,
+    sort_keys: HashMap<TableName, String>,
+    compaction_policies: HashMap<TableName, CompactionPolicy>,
+    memory_limits: HashMap<TableName, usize>,
+    format_version: u32,
+}
+
+/// Meta store written by versions before format versions were introduced.
+#[derive(Deserialize)]
+struct LegacyMetaStore {
+    next_wal_id: u64,
+    partitions: LegacyPartitions,
+}
+
+type LegacyPartitions = HashMap<TableName, HashMap<PartitionID, LegacyPartitionMetadata>>;
+
+/// Partition metadata written by versions before format versions were introduced.
+#[derive(Deserialize)]
+struct LegacyPartitionMetadata {
+    id: PartitionID,
+    tablename: String,
+    offset: usize,
+    len: usize,
+    subpartitions: Vec<LegacySubpartitionMetadata>,
+    column_name_to_subpartition_index: HashMap<String, usize>,
+}
+
+#[derive(Deserialize)]
+struct LegacySubpartitionMetadata {
+    size_bytes: u64,
+    subpartition_key: String,
+}
+
+type PartitionsV2 = HashMap<TableName, HashMap<PartitionID, PartitionMetadataV2>>;
+
+/// Partition metadata layout of format version 2.
+#[derive(Deserialize)]
+struct PartitionMetadataV2 {
+    id: PartitionID,
+    tablename: String,
+    offset: usize,
+    len: usize,
+    subpartitions: Vec<SubpartitionMetadataV2>,
+    column_name_to_subpartition_index: HashMap<String, usize>,
+    column_ranges: HashMap<String, (i64, i64)>,
+    bloom_filters: HashMap<String, BloomFilter>,
+    column_stats: HashMap<String, ColumnStats>,
+}
+
+/// Subpartition metadata layout of format version 2.
+#[derive(Deserialize)]
+struct SubpartitionMetadataV2 {
+    size_bytes: u64,
+    subpartition_key: String,
+    column_codecs: HashMap<String, String>,
+    checksum: Option<u64>,
+}
+
+fn upgrade_subpartitions(subpartitions: Vec<SubpartitionMetadataV2>) -> Vec<SubpartitionMetadata> {
+    subpartitions
+        .into_iter()
+        .map(|subpartition| SubpartitionMetadata {
+            size_bytes: subpartition.size_bytes,
+            subpartition_key: subpartition.subpartition_key,
+            column_codecs: subpartition.column_codecs,
+            checksum: subpartition.checksum,
+            data_dir: None,
+        })
+        .collect()
+}
+
+fn upgrade_partitions_v2(
+    partitions: PartitionsV2,
+) -> HashMap<TableName, HashMap<PartitionID, PartitionMetadata>> {
+    partitions
+        .into_iter()
+        .map(|(table, partitions)| {
+            let partitions = partitions
+                .into_iter()
+                .map(|(id, partition)| {
+                    let partition = PartitionMetadata {
+                        id: partition.id,
+                        tablename: partition.tablename,
+                        offset: partition.offset,
+                        len: partition.len,
+                        subpartitions: upgrade_subpartitions(partition.subpartitions),
+                        column_name_to_subpartition_index: partition
+                            .column_name_to_subpartition_index,
+                        column_ranges: partition.column_ranges,
+                        bloom_filters: partition.bloom_filters,
+                        column_stats: partition.column_stats,
+                    };
+                    (id, partition)
+                })
+                .collect();
+            (table, partitions)
+        })
+        .collect()
+}
+
+fn upgrade_partitions(
+    partitions: LegacyPartitions,
+) -> HashMap<TableName, HashMap<PartitionID, PartitionMetadata>> {
+    partitions
+        .into_iter()
+        .map(|(table, partitions)| {
+            let partitions = partitions
+                .into_iter()
+                .map(|(id, partition)| {
+                    let subpartitions = partition
+                        .subpartitions
+                        .into_iter()
+                        .map(|subpartition| SubpartitionMetadata {
+                            size_bytes: subpartition.size_bytes,
+                            subpartition_key: subpartition.subpartition_key,
+                            column_codecs: HashMap::new(),
+                            checksum: None,
+                            data_dir: None,
+                        })
+                        .collect();
+                    let partition = PartitionMetadata {
+                        id: partition.id,
+                        tablename: partition.tablename,
+                        offset: partition.offset,
+                        len: partition.len,
+                        subpartitions,
+                        column_name_to_subpartition_index: partition
+                            .column_name_to_subpartition_index,
+                        column_ranges: HashMap::new(),
+                        bloom_filters: HashMap::new(),
+                        column_stats: HashMap::new(),
+                    };
+                    (id, partition)
+                })
+                .collect();
+            (table, partitions)
+        })
+        .collect()
 }
 
 type PartitionID = u64;
@@ -53,9 +320,24 @@ pub struct Storage {
     wal_dir: PathBuf,
     meta_db_path: PathBuf,
     tables_path: PathBuf,
+    /// `tables` directories in additional data directories that subpartition files are striped across.
+    data_tables_paths: Vec<PathBuf>,
+    next_data_dir: AtomicUsize,
+    /// Whether the database has subpartition files written by format versions that did not record their data
+    /// directory, which are located by checking each data directory for the file.
+    unrecorded_data_dirs: AtomicBool,
+    wal_archive_dir: PathBuf,
     meta_store: Arc<RwLock<MetaStore>>,
     writer: Box<dyn BlobWriter + Send + Sync + 'static>,
     perf_counter: Arc<PerfCounter>,
+    partition_compression: PartitionCompression,
+    archive_wal: bool,
+    mmap_columns: bool,
+    wal_sync: WalSync,
+    /// WAL segments written since the last fsync with `WalSync::Batched`.
+    unsynced_wal_segments: Mutex<Vec<PathBuf>>,
+    /// Files that were deleted while a backup is in progress, which are only removed once the backup is complete.
+    pending_deletions: Mutex<Option<Vec<PathBuf>>>,
 }
 
 impl Storage {
@@ -63,57 +345,212 @@ impl Storage {
         path: &Path,
         perf_counter: Arc<PerfCounter>,
         readonly: bool,
-    ) -> (Storage, Vec<WALSegment>) {
+    ) -> Result<(Storage, Vec<WALSegment>), QueryError> {
+        Storage::with_writer(
+            path,
+            Box::new(FileBlobWriter::new()),
+            perf_counter,
+            readonly,
+        )
+    }
+
+    /// Opens the database at `path` of `writer`, which determines where files are stored.
+    pub fn with_writer(
+        path: &Path,
+        writer: Box<dyn BlobWriter + Send + Sync + 'static>,
+        perf_counter: Arc<PerfCounter>,
+        readonly: bool,
+    ) -> Result<(Storage, Vec<WALSegment>), QueryError> {
         let meta_db_path = path.join("meta");
         let wal_dir = path.join("wal");
         let tables_path = path.join("tables");
-        let writer = Box::new(FileBlobWriter::new());
+        let wal_archive_dir = path.join("wal_archive");
         let (meta_store, wal_segments) = Storage::recover(
-            &writer,
+            writer.as_ref(),
             &meta_db_path,
             &wal_dir,
             readonly,
             perf_counter.as_ref(),
-        );
+        )?;
+        let unrecorded_data_dirs = meta_store.format_version < 3;
         let meta_store = Arc::new(RwLock::new(meta_store));
-        (
+        Ok((
             Storage {
                 wal_dir,
                 meta_db_path,
                 tables_path,
+                data_tables_paths: Vec::new(),
+                next_data_dir: AtomicUsize::new(0),
+                unrecorded_data_dirs: AtomicBool::new(unrecorded_data_dirs),
+                wal_archive_dir,
                 meta_store,
                 writer,
                 perf_counter,
+                partition_compression: PartitionCompression::None,
+                archive_wal: false,
+                mmap_columns: false,
+                wal_sync: WalSync::Always,
+                unsynced_wal_segments: Mutex::default(),
+                pending_deletions: Mutex::new(None),
             },
             wal_segments,
-        )
+        ))
+    }
+
+    /// Sets the compression of subpartition files written from now on.
+    pub fn with_partition_compression(mut self, compression: PartitionCompression) -> Storage {
+        self.partition_compression = compression;
+        self
+    }
+
+    /// Stripes subpartition files written from now on across `data_paths` in addition to the database directory.
+    /// Files are placed in the directory with the most available space, so that directories on separate disks fill up
+    /// evenly. The meta store and WAL remain in the database directory.
+    pub fn with_data_paths(mut self, data_paths: &[PathBuf]) -> Storage {
+        self.data_tables_paths = data_paths.iter().map(|path| path.join("tables")).collect();
+        self
+    }
+
+    /// Memory maps subpartition files when loading columns instead of reading them into the heap. The data sections of
+    /// uncompressed files are served directly from the OS page cache, compressed files are still decompressed to the
+    /// heap. Requires subpartition files to be stored on the local file system.
+    pub fn with_mmap_columns(mut self, mmap_columns: bool) -> Storage {
+        self.mmap_columns = mmap_columns;
+        self
+    }
+
+    /// Sets when WAL segments are forced to stable storage.
+    pub fn with_wal_sync(mut self, wal_sync: WalSync) -> Storage {
+        self.wal_sync = wal_sync;
+        self
+    }
+
+    /// Moves WAL segments to the `wal_archive` directory once they are flushed to partitions instead of deleting them.
+    /// Archived segments are never deleted.
+    pub fn with_wal_archiving(mut self, archive_wal: bool) -> Storage {
+        self.archive_wal = archive_wal;
+        self
+    }
+
+    /// Point-in-time recovery for a database restored from a backup. Adds the segments in the WAL archive that were
+    /// not yet flushed to partitions to `wal_segments`, and discards all segments written after the target time.
+    /// Replayed segments are copied to the WAL so that they are not lost on restart.
+    pub fn recover_to(
+        &self,
+        target: &RecoveryTarget,
+        wal_segments: Vec<WALSegment<'static>>,
+    ) -> Result<Vec<WALSegment<'static>>, QueryError> {
+        // Segments before the checkpoint of the meta store file are already contained in partitions
+        let checkpoint = if self.file_exists(&self.meta_db_path)? {
+            Storage::deserialize_metastore(&self.load_file(&self.meta_db_path)?)?.next_wal_id
+        } else {
+            0
+        };
+        let mut segments = wal_segments
+            .into_iter()
+            .map(|segment| (segment.id, (segment, None)))
+            .collect::<BTreeMap<_, _>>();
+        for file in self.list_files(&target.wal_archive)? {
+            if file
+                .extension()
+                .map_or(true, |extension| extension != "wal")
+            {
+                continue;
+            }
+            let data = self.load_file(&file)?;
+            self.perf_counter.disk_read_wal(data.len() as u64);
+            let segment = deserialize_wal_segment(&file, &data)?;
+            if segment.id >= checkpoint && !segments.contains_key(&segment.id) {
+                segments.insert(segment.id, (segment, Some(data)));
+            }
+        }
+
+        let mut meta_store = self.meta_store.write().unwrap();
+        let mut recovered = Vec::new();
+        for (id, (segment, archived_data)) in segments {
+            let path = self.wal_dir.join(format!("{}.wal", id));
+            if segment.timestamp_ms > target.timestamp_ms {
+                log::info!(
+                    "Discarding wal segment {} written after recovery target",
+                    id
+                );
+                if archived_data.is_none() {
+                    self.remove_file(&path)?;
+                }
+                continue;
+            }
+            if let Some(data) = archived_data {
+                log::info!("Restoring archived wal segment {}", id);
+                self.store_file(&path, &data)?;
+            }
+            meta_store.next_wal_id = meta_store.next_wal_id.max(id + 1);
+            recovered.push(segment);
+        }
+        Ok(recovered)
     }
 
     fn recover(
-        writer: &FileBlobWriter,
+        writer: &dyn BlobWriter,
         meta_db_path: &Path,
         wal_dir: &Path,
         readonly: bool,
         perf_counter: &PerfCounter,
-    ) -> (MetaStore, Vec<WALSegment<'static>>) {
-        let mut meta_store: MetaStore = if writer.exists(meta_db_path).unwrap() {
-            let data = writer.load(meta_db_path).unwrap();
+    ) -> Result<(MetaStore, Vec<WALSegment<'static>>), QueryError> {
+        let meta_db_exists = writer
+            .exists(meta_db_path)
+            .map_err(|err| fatal!("Failed to read {}: {}", meta_db_path.display(), err))?;
+        let mut meta_store: MetaStore = if meta_db_exists {
+            let data = writer
+                .load(meta_db_path)
+                .map_err(|err| fatal!("Failed to read {}: {}", meta_db_path.display(), err))?;
             perf_counter.disk_read_meta_store(data.len() as u64);
-            bincode::deserialize(&data).unwrap()
+            Storage::deserialize_metastore(&data)?
         } else {
             MetaStore {
                 next_wal_id: 0,
                 partitions: HashMap::new(),
+                schemas: HashMap::new(),
+                views: HashMap::new(),
+                scheduled_queries: HashMap::new(),
+                schema_enforcement: HashMap::new(),
+                backfill_partitions: HashMap::new(),
+                deduplication: HashMap::new(),
+                shared_dictionaries: HashMap::new(),
+                bloom_filter_columns: HashMap::new(),
+                sort_keys: HashMap::new(),
+                compaction_policies: HashMap::new(),
+                memory_limits: HashMap::new(),
+                format_version: FORMAT_VERSION,
             }
         };
 
+        if meta_store.format_version > FORMAT_VERSION {
+            return Err(fatal!(
+                "Database has format version {}, but this version of LocustDB only supports format versions up to {}",
+                meta_store.format_version,
+                FORMAT_VERSION
+            ));
+        }
+        if meta_store.format_version < FORMAT_VERSION {
+            log::warn!(
+                "Database has format version {}, run `locustdb --migrate` to upgrade it to version {}",
+                meta_store.format_version,
+                FORMAT_VERSION,
+            );
+        }
+
         let mut wal_segments = Vec::new();
         let next_wal_id = meta_store.next_wal_id;
         log::info!("Recovering from wal checkpoint {}", next_wal_id);
-        for wal_file in writer.list(wal_dir).unwrap() {
-            let wal_data = writer.load(&wal_file).unwrap();
+        let wal_files = writer
+            .list(wal_dir)
+            .map_err(|err| fatal!("Failed to list {}: {}", wal_dir.display(), err))?;
+        for wal_file in wal_files {
+            let wal_data = writer
+                .load(&wal_file)
+                .map_err(|err| fatal!("Failed to read {}: {}", wal_file.display(), err))?;
             perf_counter.disk_read_wal(wal_data.len() as u64);
-            let wal_segment: WALSegment = bincode::deserialize(&wal_data).unwrap();
+            let wal_segment = deserialize_wal_segment(&wal_file, &wal_data)?;
             log::info!(
                 "Found wal segment {} with id {} and {} rows in {} tables",
                 wal_file.display(),
@@ -123,7 +560,9 @@ impl Storage {
             );
             if wal_segment.id < next_wal_id {
                 if !readonly {
-                    writer.delete(&wal_file).unwrap();
+                    writer.delete(&wal_file).map_err(|err| {
+                        fatal!("Failed to delete {}: {}", wal_file.display(), err)
+                    })?;
                     log::info!("Deleting wal segment {}", wal_file.display());
                 } else {
                     log::info!("Skipping wal segment {}", wal_file.display());
@@ -138,32 +577,232 @@ impl Storage {
 
         wal_segments.sort_by_key(|s| s.id);
 
-        (meta_store, wal_segments)
+        Ok((meta_store, wal_segments))
+    }
+
+    fn serialize_metastore(meta_store: &MetaStore) -> Vec<u8> {
+        let mut data = META_STORE_MAGIC.to_vec();
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, meta_store).unwrap();
+        data
+    }
+
+    fn deserialize_metastore(data: &[u8]) -> Result<MetaStore, QueryError> {
+        let data = match data.strip_prefix(META_STORE_MAGIC) {
+            Some(data) => data,
+            None => return Storage::deserialize_legacy_metastore(data),
+        };
+        if data.len() < 4 {
+            return Err(fatal!("Meta store header is truncated"));
+        }
+        let (version, data) = data.split_at(4);
+        let version = u32::from_le_bytes(version.try_into().unwrap());
+        match version {
+            2 => {
+                let old: MetaStoreV2 = bincode::deserialize(data)
+                    .map_err(|err| fatal!("Failed to deserialize meta store: {}", err))?;
+                Ok(MetaStore {
+                    next_wal_id: old.next_wal_id,
+                    partitions: upgrade_partitions_v2(old.partitions),
+                    schemas: old.schemas,
+                    views: old.views,
+                    scheduled_queries: old.scheduled_queries,
+                    schema_enforcement: old.schema_enforcement,
+                    backfill_partitions: old.backfill_partitions,
+                    deduplication: old.deduplication,
+                    shared_dictionaries: old.shared_dictionaries,
+                    bloom_filter_columns: old.bloom_filter_columns,
+                    sort_keys: old.sort_keys,
+                    compaction_policies: old.compaction_policies,
+                    memory_limits: old.memory_limits,
+                    format_version: old.format_version,
+                })
+            }
+            FORMAT_VERSION => bincode::deserialize(data)
+                .map_err(|err| fatal!("Failed to deserialize meta store: {}", err)),
+            _ => Err(fatal!(
+                "Meta store has the layout of format version {}, but this version of LocustDB only supports format versions up to {}",
+                version,
+                FORMAT_VERSION
+            )),
+        }
+    }
+
+    /// Decodes meta stores written without a header by versions before format versions were introduced.
+    fn deserialize_legacy_metastore(data: &[u8]) -> Result<MetaStore, QueryError> {
+        let legacy: LegacyMetaStore = bincode::deserialize(data)
+            .map_err(|err| fatal!("Failed to deserialize meta store: {}", err))?;
+        Ok(MetaStore {
+            next_wal_id: legacy.next_wal_id,
+            partitions: upgrade_partitions(legacy.partitions),
+            schemas: HashMap::new(),
+            views: HashMap::new(),
+            scheduled_queries: HashMap::new(),
+            schema_enforcement: HashMap::new(),
+            backfill_partitions: HashMap::new(),
+            deduplication: HashMap::new(),
+            shared_dictionaries: HashMap::new(),
+            bloom_filter_columns: HashMap::new(),
+            sort_keys: HashMap::new(),
+            compaction_policies: HashMap::new(),
+            memory_limits: HashMap::new(),
+            format_version: 0,
+        })
     }
 
-    fn write_metastore(&self, meta_store: &MetaStore) {
-        let data = bincode::serialize(meta_store).unwrap();
+    fn write_metastore(&self, meta_store: &MetaStore) -> Result<(), QueryError> {
+        let data = Storage::serialize_metastore(meta_store);
         self.perf_counter.disk_write_meta_store(data.len() as u64);
-        self.writer.store(&self.meta_db_path, &data).unwrap();
+        self.store_file(&self.meta_db_path, &data)
+    }
+
+    /// Applies `update` to a copy of the meta store and writes it. The meta store is left unchanged if `update` or
+    /// the write fails, so that it never refers to state that was not persisted.
+    fn update_metastore<T>(
+        &self,
+        update: impl FnOnce(&mut MetaStore) -> Result<T, QueryError>,
+    ) -> Result<T, QueryError> {
+        let mut meta_store = self.meta_store.write().unwrap();
+        let mut updated = meta_store.clone();
+        let result = update(&mut updated)?;
+        self.write_metastore(&updated)?;
+        *meta_store = updated;
+        Ok(result)
     }
 
+    /// Writes the subpartition files of `partition` and records their checksums and the ranges and statistics of its
+    /// columns in its metadata.
     fn write_subpartitions(
         &self,
-        partition: &PartitionMetadata,
-        subpartition_cols: Vec<Vec<Arc<Column>>>,
-    ) {
-        for (metadata, cols) in partition.subpartitions.iter().zip(subpartition_cols) {
-            let table_dir = self.tables_path.join(&partition.tablename);
+        partition: &mut PartitionMetadata,
+        subpartition_cols: &[Vec<Arc<Column>>],
+    ) -> Result<(), QueryError> {
+        let tables_paths = self.tables_paths().collect::<Vec<_>>();
+        let mut available_bytes = if tables_paths.len() > 1 {
+            tables_paths
+                .iter()
+                .map(|path| available_space(path).unwrap_or(u64::MAX))
+                .collect::<Vec<_>>()
+        } else {
+            vec![0]
+        };
+        for (metadata, cols) in partition.subpartitions.iter_mut().zip(subpartition_cols) {
+            for col in cols {
+                if let Some(range) = col.value_range() {
+                    partition
+                        .column_ranges
+                        .insert(col.name().to_string(), range);
+                }
+                if let Some(stats) = col.stats() {
+                    partition.column_stats.insert(col.name().to_string(), stats);
+                }
+            }
             let cols = cols.iter().map(|col| &**col).collect::<Vec<_>>();
-            let data = bincode::serialize(&cols).unwrap();
+            let data = self
+                .partition_compression
+                .compress(bincode::serialize(&cols).unwrap());
             self.perf_counter
                 .new_partition_file_write(data.len() as u64);
-            self.writer
-                .store(
-                    &table_dir.join(partition_filename(partition.id, &metadata.subpartition_key)),
-                    &data,
-                )
-                .unwrap();
+            metadata.checksum = Some(seahash::hash(&data));
+            // Directories with the same amount of available space take turns
+            let start = self.next_data_dir.fetch_add(1, Ordering::Relaxed);
+            let mut dir = start % tables_paths.len();
+            for i in 1..tables_paths.len() {
+                let candidate = (start + i) % tables_paths.len();
+                if available_bytes[candidate] > available_bytes[dir] {
+                    dir = candidate;
+                }
+            }
+            available_bytes[dir] = available_bytes[dir].saturating_sub(data.len() as u64);
+            metadata.data_dir = (dir > 0).then(|| tables_paths[dir].clone());
+            let table_dir = tables_paths[dir].join(&partition.tablename);
+            self.store_file(
+                &table_dir.join(partition_filename(partition.id, &metadata.subpartition_key)),
+                &data,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn tables_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        iter::once(&self.tables_path).chain(&self.data_tables_paths)
+    }
+
+    /// Path of a subpartition file in the data directory it was written to.
+    fn subpartition_path(
+        &self,
+        table: &str,
+        id: PartitionID,
+        subpartition: &SubpartitionMetadata,
+    ) -> PathBuf {
+        let filename = partition_filename(id, &subpartition.subpartition_key);
+        if let Some(data_dir) = &subpartition.data_dir {
+            return data_dir.join(table).join(filename);
+        }
+        let primary = self.tables_path.join(table).join(&filename);
+        if self.data_tables_paths.is_empty() || !self.unrecorded_data_dirs.load(Ordering::Relaxed) {
+            return primary;
+        }
+        self.tables_paths()
+            .map(|tables_path| tables_path.join(table).join(&filename))
+            .find(|path| matches!(self.writer.exists(path), Ok(true)))
+            .unwrap_or(primary)
+    }
+
+    fn store_file(&self, path: &Path, data: &[u8]) -> Result<(), QueryError> {
+        self.writer
+            .store(path, data)
+            .map_err(|err| fatal!("Failed to write {}: {}", path.display(), err))
+    }
+
+    fn load_file(&self, path: &Path) -> Result<Vec<u8>, QueryError> {
+        self.writer
+            .load(path)
+            .map_err(|err| fatal!("Failed to read {}: {}", path.display(), err))
+    }
+
+    fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>, QueryError> {
+        self.writer
+            .list(path)
+            .map_err(|err| fatal!("Failed to list {}: {}", path.display(), err))
+    }
+
+    fn file_exists(&self, path: &Path) -> Result<bool, QueryError> {
+        self.writer
+            .exists(path)
+            .map_err(|err| fatal!("Failed to read {}: {}", path.display(), err))
+    }
+
+    /// Deletes `path`, or defers the deletion until the backup in progress is complete.
+    fn delete_file(&self, path: &Path) -> Result<(), QueryError> {
+        if let Some(pending_deletions) = self.pending_deletions.lock().unwrap().as_mut() {
+            pending_deletions.push(path.to_path_buf());
+            return Ok(());
+        }
+        self.remove_file(path)
+    }
+
+    /// Deletes `path`, or moves it to the WAL archive if it is a WAL segment and archiving is enabled.
+    /// Files that no longer exist are treated as deleted.
+    fn remove_file(&self, path: &Path) -> Result<(), QueryError> {
+        let result = if self.archive_wal && path.starts_with(&self.wal_dir) {
+            let archive_path = self.wal_archive_dir.join(path.file_name().unwrap());
+            self.writer.rename(path, &archive_path)
+        } else {
+            self.writer.delete(path)
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) if is_not_found(err.as_ref()) => Ok(()),
+            Err(err) => Err(fatal!("Failed to delete {}: {}", path.display(), err)),
+        }
+    }
+
+    /// Like `delete_file`, but only logs failures. Used for files that are no longer referenced by the meta store,
+    /// which are removed by garbage collection if the deletion fails.
+    fn delete_unreferenced_file(&self, path: &Path) {
+        if let Err(err) = self.delete_file(path) {
+            log::warn!("{}", err);
         }
     }
 
@@ -171,43 +810,399 @@ impl Storage {
         &self.meta_store
     }
 
-    pub fn persist_wal_segment(&self, mut segment: WALSegment) -> u64 {
+    /// Persists the schema and schema enforcement of a new table.
+    pub fn persist_schema(
+        &self,
+        table: &str,
+        schema: &TableSchema,
+        enforcement: &SchemaEnforcement,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            meta_store.schemas.insert(table.to_string(), schema.clone());
+            set_schema_enforcement(meta_store, table, enforcement);
+            Ok(())
+        })
+    }
+
+    pub fn persist_schema_enforcement(
+        &self,
+        table: &str,
+        enforcement: &SchemaEnforcement,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            set_schema_enforcement(meta_store, table, enforcement);
+            Ok(())
+        })
+    }
+
+    pub fn persist_deduplication(
+        &self,
+        table: &str,
+        deduplication: Option<&Deduplication>,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            match deduplication {
+                Some(deduplication) => {
+                    meta_store
+                        .deduplication
+                        .insert(table.to_string(), deduplication.clone());
+                }
+                None => {
+                    meta_store.deduplication.remove(table);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes the shared dictionaries of `table` unless they are unchanged.
+    pub fn persist_shared_dictionaries(
+        &self,
+        table: &str,
+        dictionaries: HashMap<String, Vec<String>>,
+    ) -> Result<(), QueryError> {
+        let unchanged = {
+            let meta_store = self.meta_store.read().unwrap();
+            match meta_store.shared_dictionaries.get(table) {
+                Some(current) => *current == dictionaries,
+                None => dictionaries.is_empty(),
+            }
+        };
+        if unchanged {
+            return Ok(());
+        }
+        self.update_metastore(|meta_store| {
+            if dictionaries.is_empty() {
+                meta_store.shared_dictionaries.remove(table);
+            } else {
+                meta_store
+                    .shared_dictionaries
+                    .insert(table.to_string(), dictionaries);
+            }
+            Ok(())
+        })
+    }
+
+    pub fn persist_bloom_filter_columns(
+        &self,
+        table: &str,
+        columns: &HashSet<String>,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            if columns.is_empty() {
+                meta_store.bloom_filter_columns.remove(table);
+            } else {
+                meta_store
+                    .bloom_filter_columns
+                    .insert(table.to_string(), columns.clone());
+            }
+            Ok(())
+        })
+    }
+
+    pub fn persist_sort_key(&self, table: &str, sort_key: Option<&str>) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            match sort_key {
+                Some(sort_key) => {
+                    meta_store
+                        .sort_keys
+                        .insert(table.to_string(), sort_key.to_string());
+                }
+                None => {
+                    meta_store.sort_keys.remove(table);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub fn persist_compaction_policy(
+        &self,
+        table: &str,
+        policy: Option<&CompactionPolicy>,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            match policy {
+                Some(policy) => {
+                    meta_store
+                        .compaction_policies
+                        .insert(table.to_string(), policy.clone());
+                }
+                None => {
+                    meta_store.compaction_policies.remove(table);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub fn persist_memory_limit(
+        &self,
+        table: &str,
+        limit: Option<usize>,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            match limit {
+                Some(limit) => {
+                    meta_store.memory_limits.insert(table.to_string(), limit);
+                }
+                None => {
+                    meta_store.memory_limits.remove(table);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub fn persist_view(&self, view: &MaterializedView) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            meta_store.views.insert(view.name.clone(), view.clone());
+            Ok(())
+        })
+    }
+
+    pub fn persist_scheduled_query(
+        &self,
+        scheduled_query: &ScheduledQuery,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            meta_store
+                .scheduled_queries
+                .insert(scheduled_query.name.clone(), scheduled_query.clone());
+            Ok(())
+        })
+    }
+
+    pub fn delete_scheduled_query(&self, name: &str) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            meta_store.scheduled_queries.remove(name);
+            Ok(())
+        })
+    }
+
+    /// Removes all partitions, the schema and any view definition of `table` from the meta store and deletes the
+    /// partition files. Returns the first error encountered while deleting files, after attempting to delete all
+    /// of them.
+    pub fn delete_table(&self, table: &str) -> Result<(), QueryError> {
+        let partitions = self.update_metastore(|meta_store| {
+            let partitions = meta_store.partitions.remove(table).unwrap_or_default();
+            meta_store.schemas.remove(table);
+            meta_store.schema_enforcement.remove(table);
+            meta_store.backfill_partitions.remove(table);
+            meta_store.deduplication.remove(table);
+            meta_store.shared_dictionaries.remove(table);
+            meta_store.bloom_filter_columns.remove(table);
+            meta_store.sort_keys.remove(table);
+            meta_store.compaction_policies.remove(table);
+            meta_store.memory_limits.remove(table);
+            meta_store.views.remove(table);
+            Ok(partitions)
+        })?;
+
+        let mut result = Ok(());
+        for partition in partitions.values() {
+            for subpartition in &partition.subpartitions {
+                let path = self.subpartition_path(table, partition.id, subpartition);
+                if let Err(err) = self.delete_file(&path) {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Moves all partitions and the schema of table `old` to table `new` without rewriting partition files.
+    /// Directories that were already moved are moved back if renaming fails. On object stores, where moving a directory
+    /// is not atomic, this includes objects of the directory that failed to be moved.
+    pub fn rename_table(&self, old: &str, new: &str) -> Result<(), QueryError> {
+        let mut meta_store = self.meta_store.write().unwrap();
+        let mut renamed = Vec::new();
+        let mut result = Ok(());
+        for tables_path in self.tables_paths() {
+            let (old_dir, new_dir) = (tables_path.join(old), tables_path.join(new));
+            result = self.file_exists(&old_dir).and_then(|exists| {
+                if exists {
+                    renamed.push((old_dir.clone(), new_dir.clone()));
+                    self.writer
+                        .rename(&old_dir, &new_dir)
+                        .map_err(|err| fatal!("Failed to rename {}: {}", old_dir.display(), err))?;
+                }
+                Ok(())
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        let mut updated = meta_store.clone();
+        if let Some(mut partitions) = updated.partitions.remove(old) {
+            for partition in partitions.values_mut() {
+                partition.tablename = new.to_string();
+            }
+            updated.partitions.insert(new.to_string(), partitions);
+        }
+        if let Some(schema) = updated.schemas.remove(old) {
+            updated.schemas.insert(new.to_string(), schema);
+        }
+        if let Some(enforcement) = updated.schema_enforcement.remove(old) {
+            updated
+                .schema_enforcement
+                .insert(new.to_string(), enforcement);
+        }
+        if let Some(partitions) = updated.backfill_partitions.remove(old) {
+            updated
+                .backfill_partitions
+                .insert(new.to_string(), partitions);
+        }
+        if let Some(deduplication) = updated.deduplication.remove(old) {
+            updated.deduplication.insert(new.to_string(), deduplication);
+        }
+        if let Some(dictionaries) = updated.shared_dictionaries.remove(old) {
+            updated
+                .shared_dictionaries
+                .insert(new.to_string(), dictionaries);
+        }
+        if let Some(columns) = updated.bloom_filter_columns.remove(old) {
+            updated
+                .bloom_filter_columns
+                .insert(new.to_string(), columns);
+        }
+        if let Some(sort_key) = updated.sort_keys.remove(old) {
+            updated.sort_keys.insert(new.to_string(), sort_key);
+        }
+        if let Some(policy) = updated.compaction_policies.remove(old) {
+            updated.compaction_policies.insert(new.to_string(), policy);
+        }
+        if let Some(limit) = updated.memory_limits.remove(old) {
+            updated.memory_limits.insert(new.to_string(), limit);
+        }
+        rename_view_tables(&mut updated.views, old, new);
+        let result = result.and_then(|()| self.write_metastore(&updated));
+        if result.is_err() {
+            for (old_dir, new_dir) in renamed.into_iter().rev() {
+                if !matches!(self.writer.exists(&new_dir), Ok(true)) {
+                    continue;
+                }
+                if let Err(err) = self.writer.rename(&new_dir, &old_dir) {
+                    log::error!("Failed to move {} back: {}", new_dir.display(), err);
+                }
+            }
+            return result;
+        }
+        *meta_store = updated;
+        Ok(())
+    }
+
+    /// Writes and deletes a probe file next to the WAL directory to verify that WAL segments can be persisted.
+    pub fn check_wal_writable(&self) -> Result<(), QueryError> {
+        // All files in the WAL directory are replayed on startup, so the probe is written to its parent directory
+        let probe = self.wal_dir.with_file_name("healthcheck.tmp");
+        self.writer
+            .store(&probe, b"ok")
+            .map_err(|err| fatal!("Failed to write {}: {}", probe.display(), err))?;
+        self.writer
+            .delete(&probe)
+            .map_err(|err| fatal!("Failed to delete {}: {}", probe.display(), err))
+    }
+
+    /// Writes `segment` to the WAL and returns its size in bytes.
+    pub fn persist_wal_segment(&self, mut segment: WALSegment) -> Result<u64, QueryError> {
         {
             let mut meta_store = self.meta_store.write().unwrap();
             segment.id = meta_store.next_wal_id;
             meta_store.next_wal_id += 1;
         }
+        segment.timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
         let path = self.wal_dir.join(format!("{}.wal", segment.id));
         let data = bincode::serialize(&segment).unwrap();
         self.perf_counter.disk_write_wal(data.len() as u64);
-        self.writer.store(&path, &data).unwrap();
-        data.len() as u64
+        let write_error = |err| fatal!("Failed to write {}: {}", path.display(), err);
+        match self.wal_sync {
+            WalSync::Always => self.writer.store(&path, &data).map_err(write_error)?,
+            WalSync::Never => self
+                .writer
+                .store_unsynced(&path, &data)
+                .map_err(write_error)?,
+            WalSync::Batched(segments) => {
+                self.writer
+                    .store_unsynced(&path, &data)
+                    .map_err(write_error)?;
+                let mut unsynced = self.unsynced_wal_segments.lock().unwrap();
+                unsynced.push(path.clone());
+                if unsynced.len() >= segments {
+                    for path in unsynced.drain(..) {
+                        self.writer
+                            .sync(&path)
+                            .map_err(|err| fatal!("Failed to sync {}: {}", path.display(), err))?;
+                    }
+                }
+            }
+        }
+        Ok(data.len() as u64)
     }
 
+    /// Writes `partitions` and deletes the WAL. The WAL is kept if any partition fails to be written, so that the
+    /// same partitions can be persisted again.
     pub fn persist_partitions_delete_wal(
         &self,
-        partitions: Vec<(PartitionMetadata, Vec<Vec<Arc<Column>>>)>,
-    ) {
-        // Lock meta store
-        let mut meta_store = self.meta_store.write().unwrap();
+        partitions: &[(PartitionMetadata, Vec<Vec<Arc<Column>>>)],
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            for (partition, subpartition_cols) in partitions {
+                let mut partition = partition.clone();
+                self.write_subpartitions(&mut partition, subpartition_cols)?;
+                meta_store
+                    .partitions
+                    .entry(partition.tablename.clone())
+                    .or_default()
+                    .insert(partition.id, partition);
+            }
+            Ok(())
+        })?;
+        self.delete_wal_segments();
+        Ok(())
+    }
 
-        // Write out new partition files
-        for (partition, subpartition_cols) in partitions {
-            self.write_subpartitions(&partition, subpartition_cols);
-            meta_store
-                .partitions
-                .entry(partition.tablename.clone())
-                .or_default()
-                .insert(partition.id, partition);
+    /// Deletes all WAL files, or moves them to the WAL archive if archiving is enabled.
+    fn delete_wal_segments(&self) {
+        self.unsynced_wal_segments.lock().unwrap().clear();
+        match self.list_files(&self.wal_dir) {
+            Ok(files) => {
+                for file in files {
+                    self.delete_unreferenced_file(&file);
+                }
+            }
+            // Segments older than the checkpoint in the meta store are deleted on restart
+            Err(err) => log::warn!("{}", err),
         }
+    }
 
-        // Atomically overwrite meta store file
-        self.write_metastore(&meta_store);
-
-        // Delete WAL files
-        for file in self.writer.list(&self.wal_dir).unwrap() {
-            self.writer.delete(&file).unwrap();
-        }
+    /// Persists partitions written by backfill ingestion. Unlike `persist_partitions_delete_wal`, this leaves the WAL
+    /// untouched since backfilled rows are never written to the WAL.
+    pub fn persist_backfill_partitions(
+        &self,
+        partitions: Vec<(PartitionMetadata, Vec<Vec<Arc<Column>>>)>,
+    ) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            for (mut partition, subpartition_cols) in partitions {
+                self.write_subpartitions(&mut partition, &subpartition_cols)?;
+                meta_store
+                    .backfill_partitions
+                    .entry(partition.tablename.clone())
+                    .or_default()
+                    .insert(partition.id);
+                meta_store
+                    .partitions
+                    .entry(partition.tablename.clone())
+                    .or_default()
+                    .insert(partition.id, partition);
+            }
+            Ok(())
+        })
     }
 
     // Combine set of partitions into single new partition.
@@ -217,9 +1212,10 @@ impl Storage {
         id: PartitionID,
         metadata: Vec<SubpartitionMetadata>,
         subpartitions: Vec<Vec<Arc<Column>>>,
+        bloom_filters: HashMap<String, BloomFilter>,
         old_partitions: &[PartitionID],
         offset: usize,
-    ) {
+    ) -> Result<(), QueryError> {
         log::debug!(
             "compacting {} parititions into {} for table {}",
             old_partitions.len(),
@@ -237,48 +1233,46 @@ impl Storage {
             })
             .collect();
         // Persist new partition files
-        let partition = PartitionMetadata {
+        let mut partition = PartitionMetadata {
             id,
             tablename: table.to_string(),
             len: subpartitions[0][0].len(),
             offset,
             subpartitions: metadata,
             column_name_to_subpartition_index,
+            column_ranges: HashMap::new(),
+            bloom_filters,
+            column_stats: HashMap::new(),
         };
-        self.write_subpartitions(&partition, subpartitions);
+        // Files written before a failure are not referenced by the meta store and removed by garbage collection
+        self.write_subpartitions(&mut partition, &subpartitions)?;
 
         // Atomically update metastore
-        let mut meta_store = self.meta_store.write().unwrap();
-        let all_partitions = meta_store.partitions.get_mut(table).unwrap();
-        let to_delete: Vec<(u64, String)> = old_partitions
-            .iter()
-            .map(|id| all_partitions.remove(id).unwrap())
-            .flat_map(|partition| {
-                let id = partition.id;
-                partition
-                    .subpartitions
-                    .iter()
-                    .map(move |sb| (id, (*sb.subpartition_key).to_string()))
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        meta_store
-            .partitions
-            .get_mut(table)
-            .unwrap()
-            .insert(partition.id, partition);
-        drop(meta_store);
-        let meta_store = self.meta_store.read().unwrap();
-        self.write_metastore(&meta_store);
+        let to_delete = self.update_metastore(|meta_store| {
+            let all_partitions = meta_store.partitions.get_mut(table).unwrap();
+            let to_delete: Vec<(u64, SubpartitionMetadata)> = old_partitions
+                .iter()
+                // Partitions may have been quarantined in the meantime
+                .filter_map(|id| all_partitions.remove(id))
+                .flat_map(|partition| {
+                    let id = partition.id;
+                    partition.subpartitions.into_iter().map(move |sb| (id, sb))
+                })
+                .collect::<Vec<_>>();
+            all_partitions.insert(partition.id, partition);
+            Ok(to_delete)
+        })?;
 
         // Delete old partition files
-        let table_dir = self.tables_path.join(table);
-        for (id, key) in to_delete {
-            let path = table_dir.join(partition_filename(id, &key));
-            self.writer.delete(&path).unwrap();
+        for (id, subpartition) in to_delete {
+            let path = self.subpartition_path(table, id, &subpartition);
+            self.delete_unreferenced_file(&path);
         }
+        Ok(())
     }
 
+    /// Loads the subpartition containing `column_name`. Panics if its file cannot be read or does not match its
+    /// checksum.
     pub fn load_column(
         &self,
         partition: PartitionID,
@@ -286,17 +1280,606 @@ impl Storage {
         column_name: &str,
         perf_counter: &QueryPerfCounter,
     ) -> Vec<Column> {
-        let subpartition_key = self.meta_store.read().unwrap().partitions[table_name][&partition]
-            .subpartition_key(column_name);
-        let path = self
-            .tables_path
-            .join(table_name)
-            .join(partition_filename(partition, &subpartition_key));
-        let data = self.writer.load(&path).unwrap();
+        let subpartition = self.meta_store.read().unwrap().partitions[table_name][&partition]
+            .subpartition(column_name)
+            .clone();
+        let path = self.subpartition_path(table_name, partition, &subpartition);
+        if self.mmap_columns {
+            let mmap = mmap::map_file(&path)
+                .unwrap_or_else(|err| panic!("Failed to map {}: {}", path.display(), err));
+            self.check_loaded_file(&path, &subpartition, &mmap, perf_counter);
+            if !compression::is_compressed(&mmap) {
+                return mmap::deserialize_columns(&mmap).unwrap();
+            }
+            return deserialize_compressed_columns(mmap.to_vec(), perf_counter);
+        }
+        let data = self
+            .load_file(&path)
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.check_loaded_file(&path, &subpartition, &data, perf_counter);
+        deserialize_compressed_columns(data, perf_counter)
+    }
+
+    /// Records the read of a subpartition file and panics if it does not match its checksum.
+    fn check_loaded_file(
+        &self,
+        path: &Path,
+        subpartition: &SubpartitionMetadata,
+        data: &[u8],
+        perf_counter: &QueryPerfCounter,
+    ) {
         self.perf_counter.disk_read_partition(data.len() as u64);
         perf_counter.disk_read(data.len() as u64);
-        bincode::deserialize(&data).unwrap()
+        if let Err(err) = verify_checksum(subpartition, data) {
+            panic!("Corrupted subpartition file {}: {}", path.display(), err);
+        }
+    }
+
+    /// Reads every subpartition file and checks it against the checksum in the meta store. Files written by older
+    /// versions without checksums are checked by deserializing them instead.
+    /// If `quarantine` is set, partitions with corrupted files are removed from the meta store and all of their files
+    /// are moved to the `quarantine` directory.
+    pub fn scrub(&self, quarantine: bool) -> ScrubReport {
+        let partitions = self.meta_store.read().unwrap().partitions.clone();
+        let mut report = ScrubReport::default();
+        for (table, partitions) in &partitions {
+            for partition in partitions.values() {
+                for subpartition in &partition.subpartitions {
+                    let path = self.subpartition_path(table, partition.id, subpartition);
+                    report.files_scanned += 1;
+                    if subpartition.checksum.is_none() {
+                        report.files_without_checksum += 1;
+                    }
+                    let error = match self.verify_subpartition(&path, subpartition) {
+                        Ok(()) => continue,
+                        Err(error) => error,
+                    };
+                    // Files of partitions that were compacted concurrently have been deleted
+                    if !self.contains_partition(table, partition.id) {
+                        continue;
+                    }
+                    log::error!("Corrupted subpartition file {}: {}", path.display(), error);
+                    report.corrupted.push(CorruptedSubpartition {
+                        table: table.clone(),
+                        partition: partition.id,
+                        path,
+                        error,
+                    });
+                }
+            }
+        }
+        if quarantine && !report.corrupted.is_empty() {
+            match self.quarantine_partitions(&report.corrupted) {
+                Ok(quarantined) => report.quarantined = quarantined,
+                Err(err) => log::error!("Failed to quarantine corrupted partitions: {}", err),
+            }
+        }
+        report
+    }
+
+    /// Deletes partition files that are not referenced by the meta store and incomplete WAL segments, which are left
+    /// behind by crashes during flushes and compactions. Must not run concurrently with flushes or compactions, and
+    /// fails while a backup is in progress. If `dry_run` is set, orphaned files are only reported.
+    pub fn collect_garbage(&self, dry_run: bool) -> Result<GarbageCollectionReport, QueryError> {
+        // Prevents tables from being dropped or renamed while their directories are scanned
+        let meta_store = self.meta_store.read().unwrap();
+        let pending_deletions = self.pending_deletions.lock().unwrap();
+        if pending_deletions.is_some() {
+            return Err(fatal!(
+                "Cannot collect garbage while a backup is in progress"
+            ));
+        }
+        let referenced = meta_store
+            .partitions
+            .iter()
+            .flat_map(|(table, partitions)| {
+                partitions.values().flat_map(move |partition| {
+                    partition.subpartitions.iter().map(move |subpartition| {
+                        Path::new(table).join(partition_filename(
+                            partition.id,
+                            &subpartition.subpartition_key,
+                        ))
+                    })
+                })
+            })
+            .collect::<HashSet<_>>();
+        let mut orphans = Vec::new();
+        for tables_path in self.tables_paths() {
+            let files = self
+                .writer
+                .list_recursive(tables_path)
+                .map_err(|err| fatal!("Failed to list {}: {}", tables_path.display(), err))?;
+            for file in files {
+                let referenced = file
+                    .strip_prefix(tables_path)
+                    .map_or(false, |relative| referenced.contains(relative));
+                if !referenced {
+                    orphans.push(file);
+                }
+            }
+        }
+        let wal_files = self
+            .writer
+            .list(&self.wal_dir)
+            .map_err(|err| fatal!("Failed to list {}: {}", self.wal_dir.display(), err))?;
+        orphans.extend(wal_files.into_iter().filter(|file| !is_wal_segment(file)));
+
+        let mut report = GarbageCollectionReport::default();
+        for file in orphans {
+            let size_bytes = match self.writer.size(&file) {
+                Ok(size_bytes) => size_bytes,
+                Err(err) => {
+                    log::warn!("Failed to determine size of {}: {}", file.display(), err);
+                    continue;
+                }
+            };
+            if !dry_run {
+                if let Err(err) = self.writer.delete(&file) {
+                    log::warn!("Failed to delete {}: {}", file.display(), err);
+                    continue;
+                }
+                log::info!("Deleted orphaned file {}", file.display());
+            }
+            report.bytes_reclaimed += size_bytes;
+            report.files.push(file);
+        }
+        Ok(report)
+    }
+
+    pub(crate) fn format_version(&self) -> u32 {
+        self.meta_store.read().unwrap().format_version
+    }
+
+    pub(crate) fn persist_format_version(&self, format_version: u32) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            meta_store.format_version = format_version;
+            Ok(())
+        })
+    }
+
+    /// Records the data directory of subpartition files written by versions that did not record it.
+    pub(crate) fn record_data_dirs(&self) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| {
+            for (table, partitions) in &mut meta_store.partitions {
+                for partition in partitions.values_mut() {
+                    for subpartition in &mut partition.subpartitions {
+                        if subpartition.data_dir.is_some() {
+                            continue;
+                        }
+                        let filename =
+                            partition_filename(partition.id, &subpartition.subpartition_key);
+                        if self.file_exists(&self.tables_path.join(table).join(&filename))? {
+                            continue;
+                        }
+                        for tables_path in &self.data_tables_paths {
+                            if self.file_exists(&tables_path.join(table).join(&filename))? {
+                                subpartition.data_dir = Some(tables_path.clone());
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        self.unrecorded_data_dirs.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Writes the meta store in the layout of the current format version.
+    pub(crate) fn rewrite_metastore(&self) -> Result<(), QueryError> {
+        self.update_metastore(|_| Ok(()))
+    }
+
+    /// Records checksums for subpartition files written by versions without checksums. Files are checked by
+    /// deserializing them before their checksum is recorded.
+    pub(crate) fn add_missing_checksums(&self) -> Result<(), QueryError> {
+        self.update_metastore(|meta_store| self.record_missing_checksums(meta_store))
     }
+
+    fn record_missing_checksums(&self, meta_store: &mut MetaStore) -> Result<(), QueryError> {
+        let mut checksums = Vec::new();
+        for (table, partitions) in &meta_store.partitions {
+            for partition in partitions.values() {
+                for (i, subpartition) in partition.subpartitions.iter().enumerate() {
+                    if subpartition.checksum.is_some() {
+                        continue;
+                    }
+                    let path = self.subpartition_path(table, partition.id, subpartition);
+                    let data = self.load_file(&path)?;
+                    self.perf_counter.disk_read_partition(data.len() as u64);
+                    let checksum = seahash::hash(&data);
+                    compression::try_decompress(data)
+                        .and_then(|data| {
+                            bincode::deserialize::<Vec<Column>>(&data)
+                                .map_err(|err| err.to_string())
+                        })
+                        .map_err(|err| {
+                            fatal!("Corrupted subpartition file {}: {}", path.display(), err)
+                        })?;
+                    checksums.push((table.clone(), partition.id, i, checksum));
+                }
+            }
+        }
+        for (table, id, i, checksum) in checksums {
+            let partition = meta_store
+                .partitions
+                .get_mut(&table)
+                .unwrap()
+                .get_mut(&id)
+                .unwrap();
+            partition.subpartitions[i].checksum = Some(checksum);
+        }
+        Ok(())
+    }
+
+    fn verify_subpartition(
+        &self,
+        path: &Path,
+        subpartition: &SubpartitionMetadata,
+    ) -> Result<(), String> {
+        let data = self.writer.load(path).map_err(|err| err.to_string())?;
+        self.perf_counter.disk_read_partition(data.len() as u64);
+        if subpartition.checksum.is_some() {
+            return verify_checksum(subpartition, &data);
+        }
+        let data = compression::try_decompress(data)?;
+        bincode::deserialize::<Vec<Column>>(&data).map_err(|err| err.to_string())?;
+        Ok(())
+    }
+
+    fn contains_partition(&self, table: &str, id: PartitionID) -> bool {
+        let meta_store = self.meta_store.read().unwrap();
+        meta_store
+            .partitions
+            .get(table)
+            .map_or(false, |partitions| partitions.contains_key(&id))
+    }
+
+    /// Removes the partitions of `corrupted` from the meta store and then moves their files to the `quarantine`
+    /// directory. Files that cannot be moved are left in place and removed by garbage collection.
+    fn quarantine_partitions(
+        &self,
+        corrupted: &[CorruptedSubpartition],
+    ) -> Result<Vec<(String, PartitionID)>, QueryError> {
+        let corrupted = corrupted
+            .iter()
+            .map(|c| (c.table.clone(), c.partition))
+            .collect::<BTreeSet<_>>();
+        let removed = self.update_metastore(|meta_store| {
+            let mut removed = Vec::new();
+            for (table, id) in corrupted {
+                let partition = match meta_store
+                    .partitions
+                    .get_mut(&table)
+                    .and_then(|p| p.remove(&id))
+                {
+                    Some(partition) => partition,
+                    None => continue,
+                };
+                if let Some(backfill_partitions) = meta_store.backfill_partitions.get_mut(&table) {
+                    backfill_partitions.remove(&id);
+                }
+                removed.push((table, partition));
+            }
+            Ok(removed)
+        })?;
+        let mut quarantined = Vec::new();
+        for (table, partition) in removed {
+            for subpartition in &partition.subpartitions {
+                let path = self.subpartition_path(&table, partition.id, subpartition);
+                match self.file_exists(&path) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        log::warn!("{}", err);
+                        continue;
+                    }
+                }
+                // Files are quarantined within their data directory since renames cannot cross file systems
+                let data_dir = path.ancestors().nth(3).unwrap();
+                let quarantine_path = data_dir
+                    .join("quarantine")
+                    .join(&table)
+                    .join(path.file_name().unwrap());
+                log::warn!("Moving {} to {}", path.display(), quarantine_path.display());
+                if let Err(err) = self.writer.rename(&path, &quarantine_path) {
+                    log::warn!("Failed to move {}: {}", path.display(), err);
+                }
+            }
+            quarantined.push((table, partition.id));
+        }
+        Ok(quarantined)
+    }
+
+    /// Copies the meta store, all partition files it references and all WAL segments to the directory `path`, which
+    /// can then be opened as a database of its own. Ingestion, flushes and compactions continue while the backup is
+    /// taken, files they delete are kept until the backup is complete.
+    pub fn create_backup(&self, path: &Path) -> Result<(), QueryError> {
+        let backup = FileBlobWriter::new();
+        if path.join("meta").exists() {
+            return Err(fatal!("{} already contains a database", path.display()));
+        }
+        {
+            let mut pending_deletions = self.pending_deletions.lock().unwrap();
+            if pending_deletions.is_some() {
+                return Err(fatal!("Another backup is in progress"));
+            }
+            *pending_deletions = Some(Vec::new());
+        }
+        let result = self.copy_to_backup(&backup, path);
+        let pending_deletions = self.pending_deletions.lock().unwrap().take().unwrap();
+        for file in pending_deletions {
+            if let Err(err) = self.remove_file(&file) {
+                log::warn!("{}", err);
+            }
+        }
+        result
+    }
+
+    fn copy_to_backup(&self, backup: &FileBlobWriter, path: &Path) -> Result<(), QueryError> {
+        let (meta_data, partition_files) = {
+            let meta_store = self.meta_store.read().unwrap();
+            // The in-memory meta store is not used since its `next_wal_id` also covers WAL segments that have not
+            // been flushed to partitions yet, which would then be skipped when restoring the backup
+            let meta_data = if self.file_exists(&self.meta_db_path)? {
+                Some(self.load_for_backup(&self.meta_db_path)?)
+            } else {
+                None
+            };
+            let partition_files = meta_store
+                .partitions
+                .iter()
+                .flat_map(|(table, partitions)| {
+                    partitions.values().flat_map(move |partition| {
+                        partition.subpartitions.iter().map(move |subpartition| {
+                            let key = &subpartition.subpartition_key;
+                            (
+                                Path::new(table).join(partition_filename(partition.id, key)),
+                                self.subpartition_path(table, partition.id, subpartition),
+                            )
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            (meta_data, partition_files)
+        };
+
+        let mut manifest = BackupManifest::default();
+        for (file, source) in partition_files {
+            let data = self.load_for_backup(&source)?;
+            manifest.add(Path::new("tables").join(&file), &data);
+            store_for_backup(backup, &path.join("tables").join(&file), &data)?;
+        }
+        // WAL segments that were flushed after the meta store was read are skipped on restore
+        for file in self.list_files(&self.wal_dir)? {
+            if file
+                .extension()
+                .map_or(false, |extension| extension == "wal")
+            {
+                let data = self.load_for_backup(&file)?;
+                let name = file.file_name().unwrap();
+                manifest.add(Path::new("wal").join(name), &data);
+                store_for_backup(backup, &path.join("wal").join(name), &data)?;
+            }
+        }
+        // The meta store is written last so that incomplete backups cannot be opened
+        if let Some(meta_data) = &meta_data {
+            manifest.add(PathBuf::from("meta"), meta_data);
+        }
+        let manifest_data = bincode::serialize(&manifest).unwrap();
+        store_for_backup(backup, &path.join("manifest"), &manifest_data)?;
+        if let Some(meta_data) = meta_data {
+            store_for_backup(backup, &path.join("meta"), &meta_data)?;
+        }
+        Ok(())
+    }
+
+    /// Copies the backup at `src` written by `create_backup` to the database directory `dest`. Every file is verified
+    /// against the backup manifest, and the meta store is only written once all partitions it references have been
+    /// restored and match their checksums.
+    pub fn restore_backup(src: &Path, dest: &Path) -> Result<(), QueryError> {
+        let writer = FileBlobWriter::new();
+        if dest.join("meta").exists() {
+            return Err(fatal!("{} already contains a database", dest.display()));
+        }
+        let manifest_path = src.join("manifest");
+        let manifest: BackupManifest = bincode::deserialize(&load_backup_file(&manifest_path)?)
+            .map_err(|e| fatal!("Invalid backup manifest {}: {}", manifest_path.display(), e))?;
+
+        let mut meta_data = None;
+        let mut checksums = HashMap::new();
+        for file in manifest.files {
+            let data = load_backup_file(&src.join(&file.path))?;
+            if data.len() as u64 != file.size_bytes || seahash::hash(&data) != file.checksum {
+                return Err(fatal!(
+                    "Backup file {} does not match the manifest",
+                    file.path.display()
+                ));
+            }
+            if file.path == Path::new("meta") {
+                meta_data = Some(data);
+            } else {
+                store_for_backup(&writer, &dest.join(&file.path), &data)?;
+            }
+            checksums.insert(file.path, file.checksum);
+        }
+
+        // Backups of databases that were never flushed consist of WAL segments only
+        let meta_data = match meta_data {
+            Some(meta_data) => meta_data,
+            None => return Ok(()),
+        };
+        let mut meta_store = Storage::deserialize_metastore(&meta_data)?;
+        for (table, partitions) in &meta_store.partitions {
+            for partition in partitions.values() {
+                for subpartition in &partition.subpartitions {
+                    let path = Path::new("tables").join(table).join(partition_filename(
+                        partition.id,
+                        &subpartition.subpartition_key,
+                    ));
+                    let checksum = checksums.get(&path).ok_or_else(|| {
+                        fatal!("Backup is missing partition file {}", path.display())
+                    })?;
+                    if subpartition.checksum.map_or(false, |c| c != *checksum) {
+                        return Err(fatal!(
+                            "Partition file {} does not match the meta store",
+                            path.display()
+                        ));
+                    }
+                }
+            }
+        }
+        // All partition files are restored to the database directory
+        for partitions in meta_store.partitions.values_mut() {
+            for partition in partitions.values_mut() {
+                for subpartition in &mut partition.subpartitions {
+                    subpartition.data_dir = None;
+                }
+            }
+        }
+        // Rewriting the meta store also upgrades backups of older versions to the current format
+        let meta_data = Storage::serialize_metastore(&meta_store);
+        store_for_backup(&writer, &dest.join("meta"), &meta_data)
+    }
+
+    fn load_for_backup(&self, path: &Path) -> Result<Vec<u8>, QueryError> {
+        self.writer
+            .load(path)
+            .map_err(|e| fatal!("Failed to read {}: {}", path.display(), e))
+    }
+}
+
+/// Files of a backup with their sizes and checksums.
+#[derive(Serialize, Deserialize, Default)]
+struct BackupManifest {
+    files: Vec<BackupFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupFile {
+    /// Path relative to the backup directory
+    path: PathBuf,
+    size_bytes: u64,
+    checksum: u64,
+}
+
+impl BackupManifest {
+    fn add(&mut self, path: PathBuf, data: &[u8]) {
+        self.files.push(BackupFile {
+            path,
+            size_bytes: data.len() as u64,
+            checksum: seahash::hash(data),
+        });
+    }
+}
+
+fn load_backup_file(path: &Path) -> Result<Vec<u8>, QueryError> {
+    FileBlobWriter::new()
+        .load(path)
+        .map_err(|e| fatal!("Failed to read {}: {}", path.display(), e))
+}
+
+fn store_for_backup(backup: &FileBlobWriter, path: &Path, data: &[u8]) -> Result<(), QueryError> {
+    backup
+        .store(path, data)
+        .map_err(|e| fatal!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Result of `Storage::scrub`.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    pub files_scanned: usize,
+    /// Number of files written by older versions without checksums.
+    pub files_without_checksum: usize,
+    pub corrupted: Vec<CorruptedSubpartition>,
+    /// Table and id of the partitions that were removed and moved to the quarantine directory.
+    pub quarantined: Vec<(String, PartitionID)>,
+}
+
+/// Files found by `Storage::collect_garbage`.
+#[derive(Debug, Clone, Default)]
+pub struct GarbageCollectionReport {
+    /// Orphaned files that were deleted, or that would be deleted in a dry run.
+    pub files: Vec<PathBuf>,
+    pub bytes_reclaimed: u64,
+}
+
+/// Subpartition file that is missing, unreadable or does not match its checksum.
+#[derive(Debug, Clone)]
+pub struct CorruptedSubpartition {
+    pub table: String,
+    pub partition: PartitionID,
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Stores `enforcement` for `table`, permissive enforcement is the default and not stored.
+fn set_schema_enforcement(
+    meta_store: &mut MetaStore,
+    table: &str,
+    enforcement: &SchemaEnforcement,
+) {
+    if *enforcement == SchemaEnforcement::Permissive {
+        meta_store.schema_enforcement.remove(table);
+    } else {
+        meta_store
+            .schema_enforcement
+            .insert(table.to_string(), enforcement.clone());
+    }
+}
+
+fn verify_checksum(subpartition: &SubpartitionMetadata, data: &[u8]) -> Result<(), String> {
+    if let Some(expected) = subpartition.checksum {
+        let actual = seahash::hash(data);
+        if actual != expected {
+            return Err(format!(
+                "checksum mismatch, expected {:016x} but got {:016x}",
+                expected, actual
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Deserializes the columns of a subpartition file that may be compressed and records the decompressed bytes.
+fn deserialize_compressed_columns(data: Vec<u8>, perf_counter: &QueryPerfCounter) -> Vec<Column> {
+    let compressed = compression::is_compressed(&data);
+    let data = compression::decompress(data);
+    if compressed {
+        perf_counter.decompressed(data.len() as u64);
+    }
+    bincode::deserialize(&data).unwrap()
+}
+
+/// Available bytes on the file system that contains `path`, if it can be determined.
+fn available_space(path: &Path) -> Option<u64> {
+    use systemstat::{Platform, System};
+    // The directory is only created when the first file is written to it
+    let path = path.ancestors().find_map(|path| path.canonicalize().ok())?;
+    System::new()
+        .mounts()
+        .ok()?
+        .into_iter()
+        .filter(|mount| path.starts_with(&mount.fs_mounted_on))
+        .max_by_key(|mount| mount.fs_mounted_on.len())
+        .map(|mount| mount.avail.as_u64())
+}
+
+/// Whether `path` is named like a WAL segment written by `Storage::persist_wal_segment`.
+fn is_wal_segment(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "wal")
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map_or(false, |stem| stem.parse::<u64>().is_ok())
+}
+
+/// Whether a `BlobWriter` operation failed because the file does not exist.
+fn is_not_found(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .map_or(false, |err| err.kind() == std::io::ErrorKind::NotFound)
 }
 
 fn partition_filename(id: PartitionID, subpartition_key: &str) -> String {
@@ -9,7 +9,7 @@ use ordered_float::OrderedFloat;
 use crate::engine::data_types::*;
 use crate::ingest::raw_val::RawVal;
 use crate::mem_store::codec::Codec;
-use crate::mem_store::column::DataSource;
+use crate::mem_store::column::{ColumnStats, DataSource};
 use crate::mem_store::value::Val;
 
 use super::NullableVec;
@@ -168,6 +168,9 @@ impl<'a> DataSource for BoxedData<'a> {
     fn full_type(&self) -> Type {
         Type::new(self.encoding_type().cast_to_basic(), Some(self.codec()))
     }
+    fn stats(&self) -> Option<ColumnStats> {
+        None
+    }
 }
 
 impl<'a> fmt::Debug for BoxedData<'a> {
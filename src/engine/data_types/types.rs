@@ -223,6 +223,17 @@ impl EncodingType {
         }
     }
 
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            EncodingType::U8
+                | EncodingType::U16
+                | EncodingType::U32
+                | EncodingType::U64
+                | EncodingType::I64
+        )
+    }
+
     pub fn least_upper_bound(&self, other: EncodingType) -> EncodingType {
         if *self == other {
             *self
@@ -234,7 +245,9 @@ impl EncodingType {
                 (EncodingType::Str, EncodingType::OptStr) => EncodingType::OptStr,
                 (EncodingType::OptF64, EncodingType::F64) => EncodingType::OptF64,
                 (EncodingType::F64, EncodingType::OptF64) => EncodingType::OptF64,
-                _ => unimplemented!("lub not implemented for {:?} and {:?}", self, other),
+                (a, b) if a.is_integer() && b.is_integer() => EncodingType::I64,
+                // Any other combination (e.g. strings and integers, or nulls and floats) can only be represented as `Val`
+                _ => EncodingType::Val,
             }
         }
     }
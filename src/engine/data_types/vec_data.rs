@@ -13,6 +13,7 @@ use crate::ingest::raw_val::RawVal;
 use itertools::Itertools;
 
 use crate::mem_store::value::Val;
+use crate::QueryError;
 use crate::engine::data_types::*;
 
 
@@ -206,6 +207,10 @@ impl CastUsize for u64 {
     fn cast_usize(&self) -> usize { *self as usize }
 }
 
+impl CastUsize for usize {
+    fn cast_usize(&self) -> usize { *self }
+}
+
 #[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Copy, Clone)]
 pub enum MergeOp {
     TakeLeft,
@@ -226,10 +231,34 @@ impl VecData<MergeOp> for MergeOp {
 }
 
 
+/// Number of consecutive rows of the left and right input that belong to the same group when merging two sorted inputs.
+/// Counts are `usize` so that a single group can span all rows of an input.
 #[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Copy, Clone)]
 pub struct Premerge {
-    pub left: u32,
-    pub right: u32,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Premerge {
+    /// Fails if `partitioning` covers more rows than the left or right input contains.
+    pub fn validate(
+        partitioning: &[Premerge],
+        left_len: usize,
+        right_len: usize,
+    ) -> Result<(), QueryError> {
+        let (left, right) = partitioning
+            .iter()
+            .fold((0, 0), |(left, right), group| (left + group.left, right + group.right));
+        ensure!(
+            left <= left_len && right <= right_len,
+            "Partitioning covers {} left and {} right rows, but inputs only have {} and {} rows",
+            left,
+            right,
+            left_len,
+            right_len
+        );
+        Ok(())
+    }
 }
 
 impl Display for Premerge {
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use lru::LruCache;
+
+use crate::disk_store::PartitionID;
+use crate::engine::*;
+use crate::mem_store::column::DataSource;
+use crate::mem_store::partition::Partition;
+
+/// Caches the results of aggregation queries for each partition. Partitions are never modified after they have been
+/// created, so the result of an aggregation over a partition can be reused by any later query with the same
+/// normalized form and only partitions that have been added since then have to be scanned.
+///
+/// Cached results reference the columns of their partition. Results are dropped when one of these columns is evicted
+/// or the table is dropped or renamed, so that the cache never keeps columns in memory that are not accounted for by
+/// the memory usage of the tables.
+pub struct AggregateCache {
+    entries: Mutex<LruCache<(Arc<AggregateKey>, PartitionID), CacheEntry>>,
+}
+
+/// Identifies an aggregation over a table by the table name and the normalized form of the query.
+#[derive(PartialEq, Eq, Hash)]
+pub struct AggregateKey {
+    table: String,
+    main_phase: NormalFormQuery,
+}
+
+impl AggregateKey {
+    pub fn new(table: &str, main_phase: &NormalFormQuery) -> Arc<AggregateKey> {
+        Arc::new(AggregateKey {
+            table: table.to_string(),
+            main_phase: main_phase.clone(),
+        })
+    }
+}
+
+struct CacheEntry {
+    /// Rewritten partitions get a new id, but ids are reused if a table is dropped and created again
+    partition: Weak<Partition>,
+    result: Arc<CachedResult>,
+}
+
+pub struct CachedResult {
+    result: BatchResult<'static>,
+    // Lifetime of `result` is not actually static, it references the columns of the partition
+    cols: HashMap<String, Arc<dyn DataSource>>,
+}
+
+impl AggregateCache {
+    pub fn new(capacity: usize) -> AggregateCache {
+        AggregateCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns the cached result of the aggregation identified by `key` on `partition`.
+    pub fn get(
+        &self,
+        key: &Arc<AggregateKey>,
+        partition: &Arc<Partition>,
+    ) -> Option<Arc<CachedResult>> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (key.clone(), partition.id);
+        match entries.get(&key) {
+            Some(entry) if entry.partition.as_ptr() == Arc::as_ptr(partition) => {
+                Some(entry.result.clone())
+            }
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Caches `result` of the aggregation identified by `key` on `partition` if possible. `cols` must contain all
+    /// columns referenced by `result`.
+    pub fn insert(
+        &self,
+        key: &Arc<AggregateKey>,
+        partition: &Arc<Partition>,
+        result: BatchResult<'static>,
+        cols: &HashMap<String, Arc<dyn DataSource>>,
+    ) -> Arc<CachedResult> {
+        let result = Arc::new(CachedResult {
+            result,
+            cols: cols.clone(),
+        });
+        // Rows that are still buffered are converted into a new partition by every query
+        if partition.id != PartitionID::MAX && result.is_sliceable() {
+            self.entries.lock().unwrap().put(
+                (key.clone(), partition.id),
+                CacheEntry {
+                    partition: Arc::downgrade(partition),
+                    result: result.clone(),
+                },
+            );
+        }
+        result
+    }
+
+    /// Drops the results that reference `column` of `partition` in `table`, called when the column is evicted.
+    pub fn evict_column(&self, table: &str, partition: PartitionID, column: &str) {
+        self.remove_where(|key, id, entry| {
+            id == partition && key.table == table && entry.result.cols.contains_key(column)
+        });
+    }
+
+    /// Drops all results of `table`, called when the table is dropped or renamed.
+    pub fn evict_table(&self, table: &str) {
+        self.remove_where(|key, _, _| key.table == table);
+    }
+
+    /// Drops the results of partitions that no longer exist because they were compacted, rewritten or dropped.
+    pub fn evict_removed_partitions(&self) {
+        self.remove_where(|_, _, entry| entry.partition.strong_count() == 0);
+    }
+
+    fn remove_where(&self, predicate: impl Fn(&AggregateKey, PartitionID, &CacheEntry) -> bool) {
+        let mut entries = self.entries.lock().unwrap();
+        let keys = entries
+            .iter()
+            .filter(|((key, id), entry)| predicate(key, *id, entry))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in keys {
+            entries.pop(&key);
+        }
+    }
+}
+
+impl CachedResult {
+    /// Returns a copy of the cached result that references the columns of the cached result.
+    pub fn batch_result(&self) -> BatchResult<'_> {
+        let result = &self.result;
+        BatchResult {
+            columns: result
+                .columns
+                .iter()
+                .map(|column| column.slice_box(0, column.len()))
+                .collect(),
+            projection: result.projection.clone(),
+            aggregations: result.aggregations.clone(),
+            order_by: result.order_by.clone(),
+            level: result.level,
+            scanned_range: result.scanned_range.clone(),
+            batch_count: result.batch_count,
+            show: result.show,
+            unsafe_referenced_buffers: vec![],
+            dictionaries: result.dictionaries.clone(),
+        }
+    }
+
+    fn is_sliceable(&self) -> bool {
+        self.result.columns.iter().all(|column| {
+            !matches!(
+                column.get_type(),
+                EncodingType::ScalarI64
+                    | EncodingType::ScalarStr
+                    | EncodingType::ScalarString
+                    | EncodingType::ByteSlices(_)
+                    | EncodingType::ValRows
+            )
+        })
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use crate::engine::query_task::{BasicTypeColumn, QueryOutput, QueryStats};
+use crate::ingest::raw_val::RawVal;
+use crate::QueryError;
+
+/// Specifies an ASOF join which matches each row of the `left` query with the most recent row of the `right` query,
+/// i.e. the row with the largest `right_on` timestamp that is not larger than the `left_on` timestamp.
+#[derive(Debug, Clone)]
+pub struct AsofJoin {
+    pub left: String,
+    pub right: String,
+    /// Name of the timestamp column in the result of the `left` query.
+    pub left_on: String,
+    /// Name of the timestamp column in the result of the `right` query.
+    pub right_on: String,
+    /// Pairs of columns from `left` and `right` that must be equal for rows to match (e.g. a series or host id).
+    pub by: Vec<(String, String)>,
+    /// Maximum difference between the left and right timestamp, rows without match within tolerance are joined with
+    /// nulls.
+    pub tolerance: Option<i64>,
+}
+
+impl AsofJoin {
+    pub fn new(left: &str, right: &str, left_on: &str, right_on: &str) -> AsofJoin {
+        AsofJoin {
+            left: left.to_string(),
+            right: right.to_string(),
+            left_on: left_on.to_string(),
+            right_on: right_on.to_string(),
+            by: vec![],
+            tolerance: None,
+        }
+    }
+
+    pub fn by(mut self, left_column: &str, right_column: &str) -> Self {
+        self.by
+            .push((left_column.to_string(), right_column.to_string()));
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: i64) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    /// Combines the results of the `left` and `right` queries. Both results must contain rows.
+    pub fn join(&self, left: QueryOutput, right: QueryOutput) -> Result<QueryOutput, QueryError> {
+        let left_on = column_index(&left, &self.left_on)?;
+        let right_on = column_index(&right, &self.right_on)?;
+        let left_by = self
+            .by
+            .iter()
+            .map(|(l, _)| column_index(&left, l))
+            .collect::<Result<Vec<_>, _>>()?;
+        let right_by = self
+            .by
+            .iter()
+            .map(|(_, r)| column_index(&right, r))
+            .collect::<Result<Vec<_>, _>>()?;
+        let left_rows = left
+            .rows
+            .ok_or_else(|| fatal!("ASOF join requires query results in row format"))?;
+        let right_rows = right
+            .rows
+            .ok_or_else(|| fatal!("ASOF join requires query results in row format"))?;
+
+        // Index right rows by join key, each series sorted by timestamp
+        let mut series = HashMap::<Vec<&RawVal>, Vec<(i64, usize)>>::new();
+        for (i, row) in right_rows.iter().enumerate() {
+            match row[right_on] {
+                RawVal::Int(ts) => series
+                    .entry(right_by.iter().map(|&c| &row[c]).collect())
+                    .or_default()
+                    .push((ts, i)),
+                RawVal::Null => {}
+                ref val => bail!(
+                    QueryError::TypeError,
+                    "Expected integer timestamp in column {}, got {}",
+                    self.right_on,
+                    val
+                ),
+            }
+        }
+        for timestamps in series.values_mut() {
+            timestamps.sort_by_key(|&(ts, _)| ts);
+        }
+
+        let right_width = right.colnames.len();
+        let mut rows = Vec::with_capacity(left_rows.len());
+        for left_row in &left_rows {
+            let ts = match left_row[left_on] {
+                RawVal::Int(ts) => Some(ts),
+                RawVal::Null => None,
+                ref val => bail!(
+                    QueryError::TypeError,
+                    "Expected integer timestamp in column {}, got {}",
+                    self.left_on,
+                    val
+                ),
+            };
+            let key = left_by.iter().map(|&c| &left_row[c]).collect::<Vec<_>>();
+            let matched = ts.zip(series.get(&key)).and_then(|(ts, timestamps)| {
+                // Index of the first entry with larger timestamp, the preceding entry is the most recent match
+                let end = timestamps.partition_point(|&(right_ts, _)| right_ts <= ts);
+                let (right_ts, index) = *timestamps.get(end.checked_sub(1)?)?;
+                match self.tolerance {
+                    Some(tolerance) if ts - right_ts > tolerance => None,
+                    _ => Some(index),
+                }
+            });
+            let mut row = left_row.clone();
+            match matched {
+                Some(index) => row.extend(right_rows[index].iter().cloned()),
+                None => row.extend((0..right_width).map(|_| RawVal::Null)),
+            }
+            rows.push(row);
+        }
+
+        let mut colnames = left.colnames.clone();
+        for name in &right.colnames {
+            if colnames.contains(name) {
+                colnames.push(format!("right.{}", name));
+            } else {
+                colnames.push(name.clone());
+            }
+        }
+        let columns = colnames
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let values = rows.iter().map(|row| row[i].clone()).collect();
+                (name.clone(), BasicTypeColumn::from_raw_vals(values))
+            })
+            .collect();
+        let mut query_plans = left.query_plans;
+        for (plan, count) in right.query_plans {
+            *query_plans.entry(plan).or_insert(0) += count;
+        }
+        Ok(QueryOutput {
+            colnames,
+            rows: Some(rows),
+            columns,
+            query_plans,
+            stats: QueryStats {
+                runtime_ns: left.stats.runtime_ns + right.stats.runtime_ns,
+                rows_scanned: left.stats.rows_scanned + right.stats.rows_scanned,
+                partitions_scanned: left.stats.partitions_scanned + right.stats.partitions_scanned,
+                files_opened: left.stats.files_opened + right.stats.files_opened,
+                disk_read_bytes: left.stats.disk_read_bytes + right.stats.disk_read_bytes,
+                bytes_decompressed: left.stats.bytes_decompressed + right.stats.bytes_decompressed,
+                bytes_scanned: left.stats.bytes_scanned + right.stats.bytes_scanned,
+                plan_ns: left.stats.plan_ns + right.stats.plan_ns,
+                load_ns: left.stats.load_ns + right.stats.load_ns,
+                execute_ns: left.stats.execute_ns + right.stats.execute_ns,
+                combine_ns: left.stats.combine_ns + right.stats.combine_ns,
+            },
+        })
+    }
+}
+
+fn column_index(output: &QueryOutput, name: &str) -> Result<usize, QueryError> {
+    match output.colnames.iter().position(|c| c == name) {
+        Some(index) => Ok(index),
+        None => bail!(
+            QueryError::NotImplemented,
+            "Column {} not found in ASOF join input",
+            name
+        ),
+    }
+}
@@ -1,8 +1,10 @@
 use crate::engine::*;
 use crate::errors::QueryError;
 use crate::mem_store::column::DataSource;
+use crate::mem_store::dictionary::DictionarySections;
 use std::cmp::min;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 use std::ops::Range;
 use std::result::Result;
 use std::sync::Arc;
@@ -21,6 +23,8 @@ pub struct BatchResult<'a> {
     pub show: bool,
     // Buffers that are referenced by query result - unsafe to drop before results are converted into owned values
     pub unsafe_referenced_buffers: Vec<BoxedData<'a>>,
+    // Shared dictionary of each grouping column (same order as `projection`) that contains dictionary codes
+    pub dictionaries: Vec<Option<Arc<DictionarySections>>>,
 }
 
 impl<'a> BatchResult<'a> {
@@ -70,6 +74,7 @@ pub fn combine<'a>(
     batch2: BatchResult<'a>,
     limit: usize,
     batch_size: usize,
+    operator_fusion: bool,
 ) -> Result<BatchResult<'a>, QueryError> {
     ensure!(
         batch1.scanned_range.end == batch2.scanned_range.start,
@@ -101,6 +106,8 @@ pub fn combine<'a>(
 
     if !batch1.aggregations.is_empty() {
         // Aggregation query
+        let (batch1, batch2, dictionaries) =
+            unify_dictionaries(batch1, batch2, batch_size, operator_fusion)?;
         let left = batch1
             .columns
             .into_iter()
@@ -129,24 +136,53 @@ pub fn combine<'a>(
             data.push(Box::new(vec![MergeOp::TakeLeft, MergeOp::MergeRight]));
             (vec![], ops)
         } else if lprojection.len() == 1 {
-            let (l, r) = unify_types(&mut qp, left[lprojection[0]], right[rprojection[0]]);
+            let (l, r) = unify_grouping_types(&mut qp, left[lprojection[0]], right[rprojection[0]]);
             let (ops, merged) = qp.merge_deduplicate(l, r);
             (vec![merged.any()], ops)
+        } else if let Some(ranges) =
+            packable_grouping_ranges(&data, left.len(), &lprojection, &rprojection)
+        {
+            // All grouping columns are small integers, so they can be merged as a single packed key instead of
+            // partitioning by each column
+            let lcols = lprojection.iter().map(|&i| left[i]).collect::<Vec<_>>();
+            let rcols = rprojection.iter().map(|&i| right[i]).collect::<Vec<_>>();
+            let l = pack_grouping_key(&mut qp, &lcols, &ranges)?;
+            let r = pack_grouping_key(&mut qp, &rcols, &ranges)?;
+            let (ops, merged) = qp.merge_deduplicate(l.into(), r.into());
+            let merged = merged.i64()?;
+
+            let mut shift = ranges.iter().map(|&(_, width)| width).sum::<u8>();
+            let mut group_by_cols = Vec::with_capacity(ranges.len());
+            for ((&l, &r), &(min, width)) in lcols.iter().zip(&rcols).zip(&ranges) {
+                shift -= width;
+                let mut unpacked = qp.bit_unpack(merged, shift, width).into();
+                if min != 0 {
+                    let offset = qp.scalar_i64(min, true);
+                    unpacked = qp.add(unpacked, offset.into());
+                }
+                let tag = l.tag.least_upper_bound(r.tag);
+                if tag != EncodingType::I64 {
+                    unpacked = qp.cast(unpacked, tag);
+                }
+                group_by_cols.push(unpacked.any());
+            }
+
+            (group_by_cols, ops)
         } else {
-            let (l, r) = unify_types(&mut qp, left[lprojection[0]], right[rprojection[0]]);
+            let (l, r) = unify_grouping_types(&mut qp, left[lprojection[0]], right[rprojection[0]]);
             let mut partitioning = qp.partition(l, r, limit, false);
             for i in 1..(lprojection.len() - 1) {
-                let (l, r) = unify_types(&mut qp, left[lprojection[i]], right[rprojection[i]]);
+                let (l, r) = unify_grouping_types(&mut qp, left[lprojection[i]], right[rprojection[i]]);
                 partitioning = qp.subpartition(partitioning, l, r, false);
             }
 
             let last = lprojection.len() - 1;
-            let (l, r) = unify_types(&mut qp, left[lprojection[last]], right[rprojection[last]]);
+            let (l, r) = unify_grouping_types(&mut qp, left[lprojection[last]], right[rprojection[last]]);
             let (ops, merged) = qp.merge_deduplicate_partitioned(partitioning, l, r);
 
             let mut group_by_cols = Vec::with_capacity(lprojection.len());
             for i in 0..last {
-                let (l, r) = unify_types(&mut qp, left[lprojection[i]], right[rprojection[i]]);
+                let (l, r) = unify_grouping_types(&mut qp, left[lprojection[i]], right[rprojection[i]]);
                 let merged = qp.merge_drop(ops, l, r);
                 group_by_cols.push(merged.any());
             }
@@ -165,7 +201,7 @@ pub fn combine<'a>(
             aggregates.push((aggregated.any(), aggregator));
         }
 
-        let mut executor = qp.prepare(data, batch_size)?;
+        let mut executor = qp.prepare(data, batch_size, operator_fusion)?;
         let mut results = executor.prepare_no_columns();
         executor.run(1, &mut results, batch1.show || batch2.show)?;
 
@@ -185,6 +221,7 @@ pub fn combine<'a>(
                 urb.extend(batch2.unsafe_referenced_buffers);
                 urb
             },
+            dictionaries,
         };
         result.validate()?;
         Ok(result)
@@ -229,10 +266,12 @@ pub fn combine<'a>(
                 let (l, r) = unify_types(&mut qp, l, r);
                 let mut partitioning = qp.partition(l, r, limit, desc);
 
-                for i in 1..(left.len() - 1) {
+                for i in 1..(batch1.order_by.len() - 1) {
                     let (index1, desc) = batch1.order_by[i];
-                    let (index2, _) = batch1.order_by[i];
-                    let (l, r) = unify_types(&mut qp, left[index1], right[index2]);
+                    let (index2, _) = batch2.order_by[i];
+                    let l = null_to_val(&mut qp, left[index1]);
+                    let r = null_to_val(&mut qp, right[index2]);
+                    let (l, r) = unify_types(&mut qp, l, r);
                     partitioning = qp.subpartition(partitioning, l, r, desc);
                 }
                 let l = null_to_val(&mut qp, left[final_sort_col_index1]);
@@ -266,7 +305,7 @@ pub fn combine<'a>(
             }
             order_by.push((merged_final_sort_col.any(), final_desc));
 
-            let mut executor = qp.prepare(data, batch_size)?;
+            let mut executor = qp.prepare(data, batch_size, operator_fusion)?;
             let mut results = executor.prepare_no_columns();
             executor.run(1, &mut results, batch1.show || batch2.show)?;
             let (columns, projection, _, order_by) =
@@ -286,6 +325,7 @@ pub fn combine<'a>(
                     urb.extend(batch2.unsafe_referenced_buffers);
                     urb
                 },
+                dictionaries: vec![],
             })
         } else {
             // Select query
@@ -335,11 +375,166 @@ pub fn combine<'a>(
                     urb.extend(batch2.unsafe_referenced_buffers);
                     urb
                 },
+                dictionaries: vec![],
             })
         }
     }
 }
 
+/// Grouping columns can only be merged on shared dictionary codes if both batches contain codes into the same
+/// dictionary. Otherwise, the codes are decoded first. Returns the dictionaries of the merged grouping columns.
+#[allow(clippy::type_complexity)]
+fn unify_dictionaries<'a>(
+    batch1: BatchResult<'a>,
+    batch2: BatchResult<'a>,
+    batch_size: usize,
+    operator_fusion: bool,
+) -> Result<
+    (
+        BatchResult<'a>,
+        BatchResult<'a>,
+        Vec<Option<Arc<DictionarySections>>>,
+    ),
+    QueryError,
+> {
+    let mut decode = Vec::with_capacity(batch1.dictionaries.len());
+    let mut dictionaries = Vec::with_capacity(batch1.dictionaries.len());
+    for (d1, d2) in batch1.dictionaries.iter().zip(&batch2.dictionaries) {
+        let dictionary = match (d1, d2) {
+            // Shared dictionaries are append-only, so the longer dictionary is valid for both batches
+            (Some(d1), Some(d2)) if d1.is_prefix_of(d2) => Some(d2.clone()),
+            (Some(d1), Some(d2)) if d2.is_prefix_of(d1) => Some(d1.clone()),
+            _ => None,
+        };
+        decode.push(dictionary.is_none());
+        dictionaries.push(dictionary);
+    }
+    let batch1 = decode_dictionaries(batch1, &decode, batch_size, operator_fusion)?;
+    let batch2 = decode_dictionaries(batch2, &decode, batch_size, operator_fusion)?;
+    Ok((batch1, batch2, dictionaries))
+}
+
+/// Replaces the shared dictionary codes in each grouping column selected by `decode` with the strings they refer to.
+pub fn decode_dictionaries<'a>(
+    mut batch: BatchResult<'a>,
+    decode: &[bool],
+    batch_size: usize,
+    operator_fusion: bool,
+) -> Result<BatchResult<'a>, QueryError> {
+    let mut decoded_columns = HashSet::new();
+    for (i, &decode_column) in decode.iter().enumerate() {
+        let dictionary = match batch.dictionaries.get_mut(i) {
+            Some(dictionary) if decode_column => match dictionary.take() {
+                Some(dictionary) => dictionary,
+                None => continue,
+            },
+            _ => continue,
+        };
+        // Multiple projections may refer to the same column
+        let index = batch.projection[i];
+        if !decoded_columns.insert(index) {
+            continue;
+        }
+        let codes = mem::replace(&mut batch.columns[index], Box::new(Vec::<u8>::new()));
+        // Decoded strings point into the backing store, which is kept alive with the other referenced buffers
+        let backing_store: BoxedData<'a> = Box::new(dictionary.backing_store.clone());
+        let backing_store_ref =
+            unsafe { mem::transmute::<&[u8], &'a [u8]>(backing_store.cast_ref_u8()) };
+
+        let mut qp = QueryPlanner::default();
+        let codes_plan = qp.constant_vec(0, codes.encoding_type());
+        let offset_len = qp.constant_vec(1, EncodingType::U64).u64()?;
+        let backing_store_plan = qp.constant_vec(2, EncodingType::U8).u8()?;
+        let decoded = qp.dict_lookup(codes_plan, offset_len, backing_store_plan);
+        let data: Vec<BoxedData<'a>> = vec![
+            codes,
+            Box::new(dictionary.offset_len.clone()),
+            Box::new(backing_store_ref),
+        ];
+        let mut executor = qp.prepare(data, batch_size, operator_fusion)?;
+        let mut results = executor.prepare_no_columns();
+        executor.run(1, &mut results, batch.show)?;
+        let (mut columns, _, _, _) = results.collect_aliased(&[decoded.any()], &[], &[]);
+        batch.columns[index] = columns.pop().unwrap();
+        batch.unsafe_referenced_buffers.push(backing_store);
+    }
+    Ok(batch)
+}
+
+/// Maximum total width of packed grouping keys, which keeps keys positive and within the range of `BitUnpack` masks
+const MAX_PACKED_KEY_BITS: u32 = 62;
+
+/// Returns the smallest value and bit width of each grouping column if the grouping columns of both batches are all
+/// non-null integers that fit into a single packed key. Columns `0..left_len` of `data` belong to the left batch.
+fn packable_grouping_ranges(
+    data: &[BoxedData],
+    left_len: usize,
+    lprojection: &[usize],
+    rprojection: &[usize],
+) -> Option<Vec<(i64, u8)>> {
+    let mut ranges = Vec::with_capacity(lprojection.len());
+    let mut total_width = 0;
+    for (&l, &r) in lprojection.iter().zip(rprojection) {
+        let (lmin, lmax) = integer_range(&*data[l])?;
+        let (rmin, rmax) = integer_range(&*data[left_len + r])?;
+        let (min, max) = (lmin.min(rmin), lmax.max(rmax));
+        let (min, max) = if min > max { (0, 0) } else { (min, max) };
+        min.checked_neg()?;
+        let width = 64 - (max.checked_sub(min)? as u64).leading_zeros();
+        total_width += width;
+        if total_width > MAX_PACKED_KEY_BITS {
+            return None;
+        }
+        ranges.push((min, width as u8));
+    }
+    Some(ranges)
+}
+
+/// Smallest and largest value of an integer column, `(i64::MAX, i64::MIN)` if it is empty.
+fn integer_range(data: &dyn Data<'_>) -> Option<(i64, i64)> {
+    fn range(values: impl Iterator<Item = i64>) -> (i64, i64) {
+        values.fold((i64::MAX, i64::MIN), |(min, max), x| {
+            (min.min(x), max.max(x))
+        })
+    }
+    match data.encoding_type() {
+        EncodingType::U8 => Some(range(data.cast_ref_u8().iter().map(|&x| x as i64))),
+        EncodingType::U16 => Some(range(data.cast_ref_u16().iter().map(|&x| x as i64))),
+        EncodingType::U32 => Some(range(data.cast_ref_u32().iter().map(|&x| x as i64))),
+        EncodingType::I64 => Some(range(data.cast_ref_i64().iter().cloned())),
+        _ => None,
+    }
+}
+
+/// Packs the grouping columns into a single key that orders rows in the same way as the columns, with the first
+/// column in the most significant bits.
+fn pack_grouping_key(
+    qp: &mut QueryPlanner,
+    columns: &[TypedBufferRef],
+    ranges: &[(i64, u8)],
+) -> Result<BufferRef<i64>, QueryError> {
+    let mut packed: Option<BufferRef<i64>> = None;
+    let mut shift = 0;
+    for (&column, &(min, width)) in columns.iter().zip(ranges).rev() {
+        let mut column = if column.tag == EncodingType::I64 {
+            column
+        } else {
+            qp.cast(column, EncodingType::I64)
+        };
+        if min != 0 {
+            let offset = qp.scalar_i64(-min, true);
+            column = qp.add(column, offset.into());
+        }
+        let column = column.i64()?;
+        packed = Some(match packed {
+            Some(packed) => qp.bit_pack(packed, column, shift),
+            None => column,
+        });
+        shift += i64::from(width);
+    }
+    packed.ok_or_else(|| fatal!("No grouping columns to pack"))
+}
+
 fn unify_types(
     qp: &mut QueryPlanner,
     mut left: TypedBufferRef,
@@ -355,6 +550,19 @@ fn unify_types(
     (left, right)
 }
 
+/// Grouping columns of different batches may have been assigned different types (e.g. integers in one partition and
+/// strings in another, or a column that is entirely null). Converts both sides to a common type that supports
+/// partitioning and deduplication.
+fn unify_grouping_types(
+    qp: &mut QueryPlanner,
+    left: TypedBufferRef,
+    right: TypedBufferRef,
+) -> (TypedBufferRef, TypedBufferRef) {
+    let left = null_to_val(qp, left);
+    let right = null_to_val(qp, right);
+    unify_types(qp, left, right)
+}
+
 fn null_to_val(qp: &mut QueryPlanner, plan: TypedBufferRef) -> TypedBufferRef {
     if plan.tag == EncodingType::Null {
         qp.cast(plan, EncodingType::Val)
@@ -0,0 +1,104 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::channel::{mpsc, oneshot};
+use futures::{ready, FutureExt, Stream, StreamExt};
+
+use crate::ingest::raw_val::RawVal;
+use crate::scheduler::admission::AdmissionPermit;
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+use crate::QueryResult;
+
+/// Streams the rows of a query as each partition is scanned instead of merging all rows in memory, see
+/// `LocustDB::query_cursor`. Rows are yielded in batches of up to one partition and in no particular order.
+/// Dropping the cursor kills the query if it is still running.
+pub struct QueryCursor<'a> {
+    db: Arc<InnerLocustDB>,
+    id: u64,
+    colnames: Vec<String>,
+    rows: mpsc::UnboundedReceiver<Vec<Vec<RawVal>>>,
+    completion: oneshot::Receiver<QueryResult>,
+    /// Rows that still have to be skipped to satisfy the `OFFSET` of the query
+    offset: usize,
+    /// Rows that may still be returned without exceeding the `LIMIT` of the query
+    remaining: usize,
+    _permit: Option<AdmissionPermit<'a>>,
+}
+
+impl<'a> QueryCursor<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        db: Arc<InnerLocustDB>,
+        id: u64,
+        colnames: Vec<String>,
+        rows: mpsc::UnboundedReceiver<Vec<Vec<RawVal>>>,
+        completion: oneshot::Receiver<QueryResult>,
+        offset: usize,
+        limit: usize,
+        permit: Option<AdmissionPermit<'a>>,
+    ) -> QueryCursor<'a> {
+        QueryCursor {
+            db,
+            id,
+            colnames,
+            rows,
+            completion,
+            offset,
+            remaining: limit,
+            _permit: permit,
+        }
+    }
+
+    /// Id of the query in `PerfCounter::query_snapshots`.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn colnames(&self) -> &[String] {
+        &self.colnames
+    }
+}
+
+impl<'a> Stream for QueryCursor<'a> {
+    type Item = Result<Vec<Vec<RawVal>>, QueryError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let cursor = &mut *self;
+        while cursor.remaining > 0 {
+            match ready!(cursor.rows.poll_next_unpin(cx)) {
+                Some(mut rows) => {
+                    let skipped = cursor.offset.min(rows.len());
+                    rows.drain(..skipped);
+                    cursor.offset -= skipped;
+                    rows.truncate(cursor.remaining);
+                    cursor.remaining -= rows.len();
+                    if cursor.remaining == 0 {
+                        // No need to scan the remaining partitions
+                        cursor.db.kill_query(cursor.id);
+                    }
+                    if !rows.is_empty() {
+                        return Poll::Ready(Some(Ok(rows)));
+                    }
+                }
+                // The query has completed or failed and dropped its sender
+                None => {
+                    let completion = ready!(cursor.completion.poll_unpin(cx));
+                    cursor.remaining = 0;
+                    return match completion {
+                        Ok(Err(error)) => Poll::Ready(Some(Err(error))),
+                        _ => Poll::Ready(None),
+                    };
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+impl<'a> Drop for QueryCursor<'a> {
+    fn drop(&mut self) {
+        self.db.kill_query(self.id);
+    }
+}
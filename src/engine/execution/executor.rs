@@ -12,6 +12,8 @@ pub struct QueryExecutor<'a> {
     ops: Vec<Box<dyn VecOperator<'a> + 'a>>,
     stages: Vec<ExecutorStage>,
     batch_size: usize,
+    /// Whether chains of streaming operators are combined into composite operators, see `Options::operator_fusion`
+    operator_fusion: bool,
     buffer_provider: BufferProvider,
 }
 
@@ -23,11 +25,16 @@ struct ExecutorStage {
 }
 
 impl<'a> QueryExecutor<'a> {
-    pub fn new(batch_size: usize, buffer_provider: BufferProvider) -> QueryExecutor<'a> {
+    pub fn new(
+        batch_size: usize,
+        operator_fusion: bool,
+        buffer_provider: BufferProvider,
+    ) -> QueryExecutor<'a> {
         QueryExecutor {
             ops: vec![],
             stages: vec![],
             batch_size,
+            operator_fusion,
             buffer_provider,
         }
     }
@@ -111,15 +118,30 @@ impl<'a> QueryExecutor<'a> {
     }
 
     pub fn prepare(&mut self, columns: HashMap<String, Vec<&'a dyn Data<'a>>>) -> Scratchpad<'a> {
+        self.fuse_operators();
         self.stages = self.partition();
         Scratchpad::new(self.buffer_provider.buffer_count(), columns)
     }
 
     pub fn prepare_no_columns(&mut self) -> Scratchpad<'a> {
+        self.fuse_operators();
         self.stages = self.partition();
         Scratchpad::new(self.buffer_provider.buffer_count(), HashMap::default())
     }
 
+    /// Compiles chained arithmetic and comparison operators into fused kernels if the `jit` feature is enabled, and
+    /// combines the remaining chains of streaming operators into composite operators if `operator_fusion` is set.
+    fn fuse_operators(&mut self) {
+        let ops = std::mem::take(&mut self.ops);
+        #[cfg(feature = "jit")]
+        let ops = fuse_expressions(ops);
+        self.ops = if self.operator_fusion {
+            fuse_streaming_operators(ops)
+        } else {
+            ops
+        };
+    }
+
     pub fn run(
         &mut self,
         len: usize,
@@ -0,0 +1,250 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tempfile::TempDir;
+
+use crate::ingest::raw_val::RawVal;
+use crate::QueryError;
+
+/// Sorts rows that may not fit into memory. Rows are buffered until `run_rows` rows have been collected, at which point
+/// they are sorted and written to disk as a sorted run. `merge` then streams all runs in sorted order with a k-way
+/// merge.
+///
+/// Each row starts with the values of the `order_by` columns, followed by the values of the output columns.
+pub struct SortedRuns {
+    dir: TempDir,
+    /// Whether each sort column is in descending order
+    desc: Vec<bool>,
+    run_rows: usize,
+    state: Mutex<RunsState>,
+}
+
+#[derive(Default)]
+struct RunsState {
+    buffer: Vec<Vec<RawVal>>,
+    /// Path and number of rows of each run
+    runs: Vec<(PathBuf, usize)>,
+    next_run: usize,
+}
+
+/// Iterator over the output columns of all rows of `SortedRuns` in sorted order.
+pub struct SortedMerge<'a> {
+    desc: &'a [bool],
+    runs: Vec<RunReader>,
+    heap: BinaryHeap<HeapEntry<'a>>,
+}
+
+struct RunReader {
+    reader: Option<BufReader<File>>,
+    remaining: usize,
+    /// Buffered rows that were not written to disk
+    rows: std::vec::IntoIter<Vec<RawVal>>,
+}
+
+struct HeapEntry<'a> {
+    row: Vec<RawVal>,
+    run: usize,
+    desc: &'a [bool],
+}
+
+impl SortedRuns {
+    pub fn new(desc: Vec<bool>, run_rows: usize) -> Result<SortedRuns, QueryError> {
+        let dir = tempfile::Builder::new()
+            .prefix("locustdb-sort")
+            .tempdir()
+            .map_err(|e| fatal!("Failed to create directory for sorted runs: {}", e))?;
+        Ok(SortedRuns {
+            dir,
+            desc,
+            run_rows: run_rows.max(1),
+            state: Mutex::default(),
+        })
+    }
+
+    /// Adds rows in any order, writing a sorted run to disk once the buffer is full.
+    pub fn push(&self, rows: Vec<Vec<RawVal>>) -> Result<(), QueryError> {
+        let (mut run, path) = {
+            let mut state = self.state.lock().unwrap();
+            state.buffer.extend(rows);
+            if state.buffer.len() < self.run_rows {
+                return Ok(());
+            }
+            let path = self.dir.path().join(format!("run_{}", state.next_run));
+            state.next_run += 1;
+            (mem::take(&mut state.buffer), path)
+        };
+        // Sorting and writing happens outside the lock so that other workers can keep adding rows
+        run.sort_by(|a, b| compare_rows(a, b, &self.desc));
+        let mut writer = BufWriter::new(
+            File::create(&path).map_err(|e| fatal!("Failed to create sorted run: {}", e))?,
+        );
+        for row in &run {
+            bincode::serialize_into(&mut writer, row)
+                .map_err(|e| fatal!("Failed to write sorted run: {}", e))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| fatal!("Failed to write sorted run: {}", e))?;
+        self.state.lock().unwrap().runs.push((path, run.len()));
+        Ok(())
+    }
+
+    /// Number of runs that have been written to disk.
+    pub fn spilled_runs(&self) -> usize {
+        self.state.lock().unwrap().runs.len()
+    }
+
+    /// Merges all runs and any rows that remain in the buffer.
+    pub fn merge(&self) -> Result<SortedMerge<'_>, QueryError> {
+        let (mut buffer, runs) = {
+            let mut state = self.state.lock().unwrap();
+            (mem::take(&mut state.buffer), mem::take(&mut state.runs))
+        };
+        buffer.sort_by(|a, b| compare_rows(a, b, &self.desc));
+        let mut readers = Vec::with_capacity(runs.len() + 1);
+        for (path, len) in runs {
+            let file = File::open(&path).map_err(|e| fatal!("Failed to open sorted run: {}", e))?;
+            readers.push(RunReader {
+                reader: Some(BufReader::new(file)),
+                remaining: len,
+                rows: Vec::new().into_iter(),
+            });
+        }
+        readers.push(RunReader {
+            reader: None,
+            remaining: buffer.len(),
+            rows: buffer.into_iter(),
+        });
+        let mut merge = SortedMerge {
+            desc: &self.desc,
+            runs: readers,
+            heap: BinaryHeap::new(),
+        };
+        for run in 0..merge.runs.len() {
+            merge.advance(run)?;
+        }
+        Ok(merge)
+    }
+}
+
+impl<'a> SortedMerge<'a> {
+    /// Pushes the next row of `run` onto the heap.
+    fn advance(&mut self, run: usize) -> Result<(), QueryError> {
+        if let Some(row) = self.runs[run].next_row()? {
+            self.heap.push(HeapEntry {
+                row,
+                run,
+                desc: self.desc,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for SortedMerge<'a> {
+    type Item = Result<Vec<RawVal>, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let HeapEntry { mut row, run, .. } = self.heap.pop()?;
+        if let Err(error) = self.advance(run) {
+            return Some(Err(error));
+        }
+        Some(Ok(row.split_off(self.desc.len())))
+    }
+}
+
+impl RunReader {
+    fn next_row(&mut self) -> Result<Option<Vec<RawVal>>, QueryError> {
+        match &mut self.reader {
+            Some(reader) if self.remaining > 0 => {
+                self.remaining -= 1;
+                bincode::deserialize_from(reader)
+                    .map(Some)
+                    .map_err(|e| fatal!("Failed to read sorted run: {}", e))
+            }
+            Some(_) => Ok(None),
+            None => Ok(self.rows.next()),
+        }
+    }
+}
+
+impl<'a> PartialEq for HeapEntry<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a> Eq for HeapEntry<'a> {}
+
+impl<'a> PartialOrd for HeapEntry<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for HeapEntry<'a> {
+    // `BinaryHeap` is a max-heap, so the order is reversed to pop the smallest row first.
+    // Ties are broken by run to keep the merge deterministic.
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_rows(&self.row, &other.row, self.desc)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+fn compare_rows(left: &[RawVal], right: &[RawVal], desc: &[bool]) -> Ordering {
+    for (i, &desc) in desc.iter().enumerate() {
+        let ordering = compare_vals(&left[i], &right[i]);
+        if ordering != Ordering::Equal {
+            return if desc { ordering.reverse() } else { ordering };
+        }
+    }
+    Ordering::Equal
+}
+
+/// Integers and floats are compared by value, nulls sort after all other values.
+pub(super) fn compare_vals(left: &RawVal, right: &RawVal) -> Ordering {
+    match (left, right) {
+        (RawVal::Int(i), RawVal::Float(f)) => {
+            (*i as f64).partial_cmp(&f.0).unwrap_or(Ordering::Less)
+        }
+        (RawVal::Float(f), RawVal::Int(i)) => {
+            f.0.partial_cmp(&(*i as f64)).unwrap_or(Ordering::Greater)
+        }
+        _ => left.cmp(right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::raw_val::syntax::*;
+
+    #[test]
+    fn test_merge_runs() {
+        let runs = SortedRuns::new(vec![true, false], 3).unwrap();
+        for chunk in (0..10).collect::<Vec<i64>>().chunks(2) {
+            let rows = chunk
+                .iter()
+                .map(|&i| vec![Int(i % 3), Int(i), Str(&i.to_string())])
+                .collect();
+            runs.push(rows).unwrap();
+        }
+        assert_eq!(runs.spilled_runs(), 2);
+        let merged = runs
+            .merge()
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect::<Vec<_>>();
+        let expected = [2, 5, 8, 1, 4, 7, 0, 3, 6, 9]
+            .iter()
+            .map(|i| vec![Int(*i), Str(&i.to_string())])
+            .collect::<Vec<_>>();
+        assert_eq!(merged, expected);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::errors::QueryError;
+
+/// Limits the memory that the operators of a single query allocate for state that grows with the number of groups,
+/// such as the hash maps of group by queries. Operators that exceed the limit fail instead of exhausting memory, see
+/// `SpilledGroups` for how aggregation queries recover from this.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+/// Memory reserved from a `MemoryBudget`, which is returned to the budget when the reservation is dropped.
+#[derive(Debug, Default)]
+pub struct MemoryReservation {
+    budget: Option<Arc<MemoryBudget>>,
+    bytes: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> MemoryBudget {
+        MemoryBudget {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    fn reserve(&self, bytes: usize) -> Result<(), QueryError> {
+        let used = self.used.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        if used > self.limit {
+            self.used.fetch_sub(bytes, Ordering::SeqCst);
+            return Err(QueryError::MemoryLimitExceeded(format!(
+                "query requires more than {} bytes for grouping",
+                self.limit
+            )));
+        }
+        Ok(())
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::SeqCst);
+    }
+}
+
+impl MemoryReservation {
+    pub fn new(budget: Option<Arc<MemoryBudget>>) -> MemoryReservation {
+        MemoryReservation { budget, bytes: 0 }
+    }
+
+    /// Reserves `bytes` additional bytes, or fails if that would exceed the budget.
+    pub fn grow(&mut self, bytes: usize) -> Result<(), QueryError> {
+        if let Some(budget) = &self.budget {
+            budget.reserve(bytes)?;
+            self.bytes += bytes;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(self.bytes);
+        }
+    }
+}
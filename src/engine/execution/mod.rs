@@ -1,10 +1,22 @@
 pub mod query_task;
+mod aggregate_cache;
+mod asof_join;
 mod buffer;
+mod cursor;
 mod executor;
+mod external_sort;
+mod memory_budget;
 mod batch_merging;
 mod scratchpad;
+mod spilled_grouping;
 
+pub use self::aggregate_cache::{AggregateCache, AggregateKey, CachedResult};
+pub use self::asof_join::AsofJoin;
 pub use self::buffer::*;
+pub use self::cursor::QueryCursor;
 pub use self::scratchpad::*;
 pub use self::executor::*;
-pub use self::batch_merging::{BatchResult, combine};
\ No newline at end of file
+pub use self::external_sort::{SortedMerge, SortedRuns};
+pub use self::memory_budget::{MemoryBudget, MemoryReservation};
+pub use self::spilled_grouping::SpilledGroups;
+pub use self::batch_merging::{BatchResult, combine, decode_dictionaries};
\ No newline at end of file
@@ -4,41 +4,71 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::iter::Iterator;
 use std::mem;
+use std::ops::Range;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
+use std_semaphore::Semaphore;
 
+use futures::channel::mpsc::UnboundedSender;
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
 use crate::engine::*;
 use crate::ingest::raw_val::RawVal;
-use crate::mem_store::column::DataSource;
+use crate::mem_store::column::{ColumnSlice, DataSource};
 use crate::mem_store::partition::Partition;
 use crate::perf_counter::QueryPerfCounter;
-use crate::scheduler::disk_read_scheduler::DiskReadScheduler;
+use crate::scheduler::disk_read_scheduler::{DiskReadScheduler, QueryIo};
 use crate::scheduler::*;
 use crate::syntax::expression::*;
 use crate::QueryError;
 use crate::QueryResult;
 
+/// Maximum time a worker spends on one query before returning it to the back of the task queue, so that queries with
+/// many partitions don't prevent concurrent queries from making progress.
+const TIME_SLICE: Duration = Duration::from_millis(10);
+
 pub struct QueryTask {
     main_phase: NormalFormQuery,
     final_pass: Option<NormalFormQuery>,
+    /// Replaces `main_phase` if only the rows of the final result are materialized, see `materialize`
+    late_materialization: Option<NormalFormQuery>,
     explain: bool,
     rowformat: bool,
     show: Vec<usize>,
-    partitions: Vec<Arc<Partition>>,
-    referenced_cols: HashSet<String>,
+    /// Partitions, or parts of large partitions, that may contain matching rows
+    morsels: Vec<Morsel>,
+    referenced_cols: Arc<HashSet<String>>,
     output_colnames: Vec<String>,
     // Tells us how to reconstruct final output in correct ordering from `projection` and `aggregate` columns
     result_column_sources: Vec<ResultColumn>,
-    start_time: Instant,
+    quantiles: Option<QuantileRewrite>,
+    windows: Option<WindowRewrite>,
     db: Arc<DiskReadScheduler>,
     perf_counter: Arc<QueryPerfCounter>,
+    /// Span of the query that was entered when the task was created, parent of the spans of partition scans and merges
+    span: tracing::Span,
     batch_size: usize,
+    /// Whether chains of streaming operators are combined into composite operators, see `with_operator_fusion`
+    operator_fusion: bool,
+    prefetch_partitions: usize,
+    /// Limits the number of concurrent disk reads of this query
+    read_limit: Option<Arc<Semaphore>>,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Merges the groups of aggregation queries with a memory budget, see `with_memory_limit`
+    spilled_groups: Option<SpilledGroups>,
+    /// Receives the rows of each partition instead of merging them in memory, see `with_sorted_runs`
+    sorted_runs: Option<Arc<SortedRuns>>,
+    /// Receives the rows of each partition as soon as it has been scanned, see `with_cursor`
+    cursor: Option<UnboundedSender<Vec<Vec<RawVal>>>>,
+    /// Per-partition results of aggregation queries, see `with_aggregate_cache`
+    aggregate_cache: Option<Arc<AggregateCache>>,
+    /// Identifies the table and normalized form of the main phase in `aggregate_cache`
+    aggregate_key: Arc<AggregateKey>,
 
     // Lifetime is not actually static, but tied to the lifetime of this struct.
     // There is currently no good way to express this constraint in Rust.
@@ -46,16 +76,44 @@ pub struct QueryTask {
     // TODO(#96): better encapsulate unsafety using some abstraction such as the refstruct crate.
     unsafe_state: Mutex<QueryState<'static>>,
     batch_index: AtomicUsize,
+    /// Index of the first partition that has not been prefetched yet
+    prefetch_index: AtomicUsize,
     completed: AtomicBool,
     sender: SharedSender<QueryResult>,
 }
 
+/// Rows of a partition that are scanned by a single worker.
+struct Morsel {
+    partition: Arc<Partition>,
+    /// Range of rows within the partition, all rows if `None`
+    rows: Option<Range<usize>>,
+    /// Range of rows accounted for by the morsel, which includes the rows of skipped partitions
+    scanned_range: Range<usize>,
+}
+
+impl Morsel {
+    /// Range of the scanned rows within the table.
+    fn range(&self) -> Range<usize> {
+        let range = self.partition.range();
+        match &self.rows {
+            Some(rows) => range.start + rows.start..range.start + rows.end,
+            None => range,
+        }
+    }
+}
+
 pub struct QueryState<'a> {
     completed_batches: usize,
     partial_results: BTreeMap<usize, BatchResult<'a>>,
+    /// Number of pairs of partial results that are being combined without holding the lock
+    combining: usize,
     explains: Vec<String>,
     rows_collected: usize,
     colstacks: Vec<Vec<HashMap<String, Arc<dyn DataSource>>>>,
+    /// Cached results that are referenced by partial results, dropped together with `colstacks`
+    cached_results: Vec<Arc<CachedResult>>,
+    /// Sorted values of each partition of queries containing quantiles, see `QuantileRewrite`
+    quantile_runs: Vec<QuantileRun>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,8 +140,16 @@ pub enum BasicTypeColumn {
 pub struct QueryStats {
     pub runtime_ns: u64,
     pub rows_scanned: u64,
+    pub partitions_scanned: u64,
     pub files_opened: u64,
     pub disk_read_bytes: u64,
+    pub bytes_decompressed: u64,
+    pub bytes_scanned: u64,
+    /// Wall time of each stage, summed over all worker threads, see `QueryPerfCounter`
+    pub plan_ns: u64,
+    pub load_ns: u64,
+    pub execute_ns: u64,
+    pub combine_ns: u64,
 }
 
 impl QueryTask {
@@ -98,7 +164,10 @@ impl QueryTask {
         sender: SharedSender<QueryResult>,
         batch_size: usize,
     ) -> Result<QueryTask, QueryError> {
-        let start_time = Instant::now();
+        let plan_start = Instant::now();
+        let span = tracing::Span::current();
+        let _plan_span = tracing::info_span!("plan").entered();
+        let perf_counter = Arc::new(QueryPerfCounter::default());
         if query.is_select_star() {
             query.select = find_all_cols(&source)
                 .into_iter()
@@ -109,34 +178,81 @@ impl QueryTask {
                 .sorted_by(|a, b| a.name.cmp(&b.name))
         }
 
-        let referenced_cols = query.find_referenced_cols();
+        query.simplify();
 
-        let (main_phase, final_pass, result_column_sources) = query.normalize()?;
         let output_colnames = query.select.iter().map(|c| c.name.clone()).collect();
+        let partitions = prune_partitions(source, &query.filter);
+        let statistics = filter_statistics(&query.filter, &partitions);
+        query.filter = order_conjuncts(&query.filter, &statistics);
+        perf_counter.set_partitions_total(partitions.len() as u64);
+        let (query, quantiles) = match QuantileRewrite::rewrite(&query)? {
+            Some((rewritten, quantiles)) => (rewritten, Some(quantiles)),
+            None => (query, None),
+        };
+        let (query, windows) = match WindowRewrite::rewrite(&query)? {
+            Some((rewritten, windows)) => (rewritten, Some(windows)),
+            None => (query, None),
+        };
+
+        let referenced_cols = Arc::new(query.find_referenced_cols());
+
+        let (main_phase, final_pass, result_column_sources) = query.normalize()?;
+        let aggregate_key = AggregateKey::new(&query.table, &main_phase);
+        let late_materialization = if quantiles.is_none() && windows.is_none() {
+            main_phase.late_materialized()
+        } else {
+            None
+        };
+        perf_counter.planned(plan_start.elapsed());
 
         let task = QueryTask {
             main_phase,
             final_pass,
+            late_materialization,
             explain,
             rowformat,
             show,
-            partitions: source,
+            morsels: partitions
+                .into_iter()
+                .map(|(partition, scanned_range)| Morsel {
+                    partition,
+                    rows: None,
+                    scanned_range,
+                })
+                .collect(),
             referenced_cols,
             output_colnames,
             result_column_sources,
-            start_time,
+            quantiles,
+            windows,
+            prefetch_partitions: db.prefetch_partitions().unwrap_or(0),
+            read_limit: db
+                .max_query_reads()
+                .map(|reads| Arc::new(Semaphore::new(reads as isize))),
+            memory_budget: None,
+            spilled_groups: None,
+            sorted_runs: None,
+            cursor: None,
+            aggregate_cache: None,
+            aggregate_key,
             db,
-            perf_counter: Arc::default(),
+            perf_counter,
+            span,
             batch_size,
+            operator_fusion: true,
 
             unsafe_state: Mutex::new(QueryState {
                 partial_results: BTreeMap::new(),
+                combining: 0,
                 completed_batches: 0,
                 explains: Vec::new(),
                 rows_collected: 0,
                 colstacks: Vec::new(),
+                cached_results: Vec::new(),
+                quantile_runs: Vec::new(),
             }),
             batch_index: AtomicUsize::new(0),
+            prefetch_index: AtomicUsize::new(0),
             completed: AtomicBool::new(false),
             sender,
         };
@@ -148,61 +264,335 @@ impl QueryTask {
                 rows: Some(vec![]),
                 columns: Default::default(),
                 query_plans: Default::default(),
-                stats: QueryStats {
-                    runtime_ns: start_time.elapsed().as_nanos() as u64,
-                    rows_scanned: 0,
-                    files_opened: 0,
-                    disk_read_bytes: 0,
-                },
+                stats: task.perf_counter.complete(),
             }));
         }
 
         Ok(task)
     }
 
+    pub fn perf_counter(&self) -> &Arc<QueryPerfCounter> {
+        &self.perf_counter
+    }
+
+    /// Overrides the disk read settings of `Options` for this query.
+    pub fn with_io(mut self, io: QueryIo) -> QueryTask {
+        // Columns are never read from disk without persistent storage
+        if self.db.prefetch_partitions().is_some() {
+            if let Some(prefetch_partitions) = io.prefetch_partitions {
+                self.prefetch_partitions = prefetch_partitions;
+            }
+        }
+        if let Some(reads) = io.max_disk_reads {
+            self.read_limit = Some(Arc::new(Semaphore::new(reads as isize)));
+        }
+        self
+    }
+
+    /// Limits the grouping state of the query to `memory_limit` bytes. Aggregation queries that support it merge their
+    /// groups with `SpilledGroups`, which writes the groups to disk once they exceed the limit, and group the rows of a
+    /// partition in smaller slices if grouping the whole partition exceeds it. All other queries fail when the grouping
+    /// state of a partition exceeds the limit.
+    pub fn with_memory_limit(mut self, memory_limit: Option<usize>) -> QueryTask {
+        let budget = memory_limit.map(|limit| Arc::new(MemoryBudget::new(limit)));
+        self.spilled_groups = match &budget {
+            Some(budget) if self.supports_spilling() => {
+                let aggregators = self
+                    .main_phase
+                    .aggregate
+                    .iter()
+                    .map(|(aggregator, _)| *aggregator)
+                    .collect();
+                Some(SpilledGroups::new(aggregators, budget.clone()))
+            }
+            _ => None,
+        };
+        self.memory_budget = budget;
+        self
+    }
+
+    /// Whether the groups of this query can be merged with `SpilledGroups`, which requires aggregations and no
+    /// expressions or sorting that are evaluated after the aggregation.
+    fn supports_spilling(&self) -> bool {
+        !self.main_phase.aggregate.is_empty()
+            && self.main_phase.order_by.is_empty()
+            && self.final_pass.is_none()
+            && self.quantiles.is_none()
+            && self.windows.is_none()
+    }
+
+    /// Returns whether each sort column is descending if the result of this query can be sorted with `SortedRuns`,
+    /// which requires an `ORDER BY` clause and no aggregations.
+    pub fn external_sort_order(&self) -> Option<Vec<bool>> {
+        if self.main_phase.order_by.is_empty()
+            || !self.main_phase.aggregate.is_empty()
+            || self.final_pass.is_some()
+            || self.quantiles.is_some()
+            || self.windows.is_some()
+        {
+            return None;
+        }
+        Some(
+            self.main_phase
+                .order_by
+                .iter()
+                .map(|(_, desc)| *desc)
+                .collect(),
+        )
+    }
+
+    /// Pushes the sorted rows of each partition into `runs` and completes without rows once all partitions have been
+    /// scanned. Must only be used if `external_sort_order` returns `Some`.
+    pub fn with_sorted_runs(mut self, runs: Arc<SortedRuns>) -> QueryTask {
+        // Sorted runs contain the values of all output columns
+        self.late_materialization = None;
+        self.sorted_runs = Some(runs);
+        self
+    }
+
+    /// Whether the rows of this query can be returned partition by partition, which requires that there is no
+    /// `ORDER BY` clause and no aggregations.
+    pub fn supports_cursor(&self) -> bool {
+        self.main_phase.order_by.is_empty()
+            && self.main_phase.aggregate.is_empty()
+            && self.final_pass.is_none()
+            && self.quantiles.is_none()
+            && self.windows.is_none()
+    }
+
+    /// Sends the rows of each partition to `rows` as soon as it has been scanned and completes without rows once all
+    /// partitions have been scanned. Must only be used if `supports_cursor` returns true.
+    pub fn with_cursor(mut self, rows: UnboundedSender<Vec<Vec<RawVal>>>) -> QueryTask {
+        self.cursor = Some(rows);
+        self
+    }
+
+    /// Reuses the results of previous queries with the same aggregations for partitions that are contained in `cache`
+    /// and adds the results of all other partitions to it.
+    pub fn with_aggregate_cache(mut self, cache: Option<Arc<AggregateCache>>) -> QueryTask {
+        self.aggregate_cache = cache;
+        self
+    }
+
+    /// Combines chains of streaming operators into composite operators when executing the query, see
+    /// `Options::operator_fusion`.
+    pub fn with_operator_fusion(mut self, operator_fusion: bool) -> QueryTask {
+        self.operator_fusion = operator_fusion;
+        self
+    }
+
+    /// Splits partitions with more than `morsel_size` rows into morsels of `morsel_size` rows that are scanned by
+    /// different workers, so that queries over a few large partitions use all threads. Partitions are only split if
+    /// all referenced columns are resident and can be sliced without decoding them. 0 disables splitting.
+    pub fn with_morsel_size(mut self, morsel_size: usize) -> QueryTask {
+        if morsel_size == 0 {
+            return self;
+        }
+        let mut morsels = Vec::with_capacity(self.morsels.len());
+        for morsel in mem::take(&mut self.morsels) {
+            let len = morsel.partition.len();
+            if len <= morsel_size || !morsel.partition.is_sliceable(&self.referenced_cols) {
+                morsels.push(morsel);
+                continue;
+            }
+            let start = morsel.partition.range().start;
+            for offset in (0..len).step_by(morsel_size) {
+                let end = cmp::min(offset + morsel_size, len);
+                // The first and last morsel account for the rows of skipped partitions before and after the partition
+                let scanned_start = if offset == 0 {
+                    morsel.scanned_range.start
+                } else {
+                    start + offset
+                };
+                let scanned_end = if end == len {
+                    morsel.scanned_range.end
+                } else {
+                    start + end
+                };
+                morsels.push(Morsel {
+                    partition: morsel.partition.clone(),
+                    rows: Some(offset..end),
+                    scanned_range: scanned_start..scanned_end,
+                });
+            }
+        }
+        self.perf_counter.set_partitions_total(morsels.len() as u64);
+        self.morsels = morsels;
+        self
+    }
+
+    pub fn output_colnames(&self) -> &[String] {
+        &self.output_colnames
+    }
+
     pub fn run(&self) {
         let mut rows_scanned = 0;
         let mut rows_collected = 0;
         let mut colstack = Vec::new();
+        let mut cached_results = Vec::new();
         let mut batch_results = BTreeMap::<usize, BatchResult>::new();
         let mut explains = Vec::new();
-        while let Some((partition, id)) = self.next_partition() {
+        let slice_start = Instant::now();
+        let main_phase = self
+            .late_materialization
+            .as_ref()
+            .unwrap_or(&self.main_phase);
+        while let Some((morsel, id)) = self.next_morsel() {
+            let partition = &morsel.partition;
+            let _scan_span = tracing::info_span!(
+                parent: &self.span,
+                "scan_partition",
+                partition = partition.id,
+                rows = morsel.range().len()
+            )
+            .entered();
+            self.prefetch(id);
             let show = self.show.iter().any(|&x| x == id);
-            let cols =
-                partition.get_cols(&self.referenced_cols, &self.db, self.perf_counter.as_ref());
-            rows_scanned += cols.iter().next().map_or(0, |c| c.1.len());
-            let unsafe_cols = unsafe {
-                mem::transmute::<
-                    &HashMap<String, Arc<dyn DataSource>>,
-                    &'static HashMap<String, Arc<dyn DataSource>>,
-                >(&cols)
-            };
-            let (batch_result, explain) = match if self.main_phase.aggregate.is_empty() {
-                self.main_phase.run(
-                    unsafe_cols,
-                    self.explain,
-                    show,
-                    id,
-                    partition.range(),
-                    self.batch_size,
-                )
-            } else {
-                self.main_phase.run_aggregate(
-                    unsafe_cols,
-                    self.explain,
-                    show,
-                    id,
-                    partition.range(),
-                    self.batch_size,
-                )
-            } {
-                Ok(result) => result,
-                Err(error) => {
-                    self.fail_with(error);
-                    return;
+            if let Some(groups) = &self.spilled_groups {
+                match self.group_morsel(morsel, id, show, groups) {
+                    Ok((rows_scanned, group_count, explain)) => {
+                        self.push_unmerged(rows_scanned, group_count, explain)
+                    }
+                    Err(error) => {
+                        self.fail_with(error);
+                        return;
+                    }
+                }
+                if self.completed.load(Ordering::SeqCst) || slice_start.elapsed() >= TIME_SLICE {
+                    break;
+                }
+                continue;
+            }
+            // Cached results cover entire partitions
+            let cache = self.aggregate_cache(show).filter(|_| morsel.rows.is_none());
+            let cached = cache.and_then(|cache| cache.get(&self.aggregate_key, &partition));
+            let (mut batch_result, explain) = match cached {
+                Some(cached) => {
+                    // Lifetime is tied to `cached`, which is kept alive until the query has completed
+                    let batch_result = unsafe {
+                        mem::transmute::<BatchResult, BatchResult<'static>>(cached.batch_result())
+                    };
+                    cached_results.push(cached);
+                    (batch_result, None)
+                }
+                None => {
+                    let load_start = Instant::now();
+                    let mut cols = partition.get_cols(
+                        &self.referenced_cols,
+                        &self.db,
+                        self.read_limit.as_deref(),
+                        self.perf_counter.as_ref(),
+                    );
+                    if let Some(rows) = &morsel.rows {
+                        cols = match slice_columns(&cols, rows) {
+                            Some(cols) => cols,
+                            None => {
+                                self.fail_with(fatal!("Failed to split partition into morsels"));
+                                return;
+                            }
+                        };
+                    }
+                    self.perf_counter.loaded_partition(load_start.elapsed());
+                    rows_scanned += cols.iter().next().map_or(0, |c| c.1.len());
+                    let unsafe_cols = unsafe {
+                        mem::transmute::<
+                            &HashMap<String, Arc<dyn DataSource>>,
+                            &'static HashMap<String, Arc<dyn DataSource>>,
+                        >(&cols)
+                    };
+                    let execute_start = Instant::now();
+                    let (mut batch_result, explain) = match if main_phase.aggregate.is_empty() {
+                        main_phase.run(
+                            unsafe_cols,
+                            self.explain,
+                            show,
+                            id,
+                            morsel.range(),
+                            self.batch_size,
+                            self.operator_fusion,
+                        )
+                    } else {
+                        self.main_phase.run_aggregate(
+                            unsafe_cols,
+                            self.explain,
+                            show,
+                            id,
+                            morsel.range(),
+                            self.batch_size,
+                            self.operator_fusion,
+                            self.memory_budget.as_ref(),
+                        )
+                    } {
+                        Ok(result) => result,
+                        Err(error) => {
+                            self.fail_with(error);
+                            return;
+                        }
+                    };
+                    self.perf_counter.executed(execute_start.elapsed());
+                    if self.late_materialization.is_some() {
+                        // Identifies each row by its index within the table instead of the morsel
+                        let offset = morsel.range().start;
+                        let index = batch_result.projection[0];
+                        let row_ids = batch_result.columns[index]
+                            .cast_ref_usize()
+                            .iter()
+                            .map(|&i| (offset + i) as i64)
+                            .collect::<Vec<_>>();
+                        batch_result.columns[index] = Box::new(row_ids);
+                    }
+                    if self.sorted_runs.is_some()
+                        || self.cursor.is_some()
+                        || self.quantiles.is_some()
+                    {
+                        // Rows are converted into owned values, so the columns can be dropped right away
+                        let rows = self.sort_rows(&batch_result);
+                        let pushed = match (&self.cursor, &self.quantiles) {
+                            // The receiver is dropped when the client stops consuming the rows
+                            (Some(cursor), _) => {
+                                cursor.unbounded_send(rows).map_err(|_| QueryError::Killed)
+                            }
+                            (None, Some(quantiles)) => {
+                                let run = quantiles.sorted_run(rows);
+                                self.unsafe_state.lock().unwrap().quantile_runs.push(run);
+                                Ok(())
+                            }
+                            (None, None) => self.sorted_runs.as_ref().unwrap().push(rows),
+                        };
+                        if let Err(error) = pushed {
+                            self.fail_with(error);
+                            return;
+                        }
+                        self.push_unmerged(rows_scanned, batch_result.len(), explain);
+                        rows_scanned = 0;
+                        if self.completed.load(Ordering::SeqCst)
+                            || slice_start.elapsed() >= TIME_SLICE
+                        {
+                            break;
+                        }
+                        continue;
+                    }
+                    let batch_result = match cache {
+                        Some(cache) => {
+                            let cached =
+                                cache.insert(&self.aggregate_key, &partition, batch_result, &cols);
+                            // Lifetime is tied to `cached`, which is kept alive until the query has completed
+                            let batch_result = unsafe {
+                                mem::transmute::<BatchResult, BatchResult<'static>>(
+                                    cached.batch_result(),
+                                )
+                            };
+                            cached_results.push(cached);
+                            batch_result
+                        }
+                        None => batch_result,
+                    };
+                    colstack.push(cols);
+                    (batch_result, explain)
                 }
             };
-            colstack.push(cols);
+            // Includes the rows of skipped partitions so that the result is adjacent to the results of the next partition
+            batch_result.scanned_range = morsel.scanned_range.clone();
             rows_collected += batch_result.len();
             if let Some(explain) = explain {
                 explains.push(explain);
@@ -211,22 +601,29 @@ impl QueryTask {
             batch_results.insert(batch_result.scanned_range.start, batch_result);
             // Merge only with contiguous previous batch results of same level to get O(n log n) complexity and deterministic order.
             // Find any adjacent batch results of same level and merge them
-            if let Err(error) = QueryTask::combine_results(
-                &mut batch_results,
-                self.combined_limit(),
-                self.batch_size,
-                true,
-            ) {
+            let combine_start = Instant::now();
+            let combined = tracing::info_span!("merge").in_scope(|| {
+                QueryTask::combine_results(
+                    &mut batch_results,
+                    self.combined_limit(),
+                    self.batch_size,
+                    self.operator_fusion,
+                )
+            });
+            if let Err(error) = combined {
                 self.fail_with(error);
                 return;
             }
+            self.perf_counter.combined(combine_start.elapsed());
             if self.completed.load(Ordering::SeqCst) {
                 return;
             }
+            if slice_start.elapsed() >= TIME_SLICE {
+                break;
+            }
             // TODO: abort early if we have selected sufficient number of rows from initial partition
         }
 
-        // TODO: parallelize combining results from different threads
         for (_, result) in batch_results {
             self.push_result(result, rows_scanned, rows_collected, explains.clone());
             rows_scanned = 0;
@@ -235,23 +632,20 @@ impl QueryTask {
         }
 
         // need to keep colstack alive, otherwise results may reference freed data
-        self.push_colstack(colstack);
+        self.push_colstack(colstack, cached_results);
     }
 
     fn combine_results(
         batch_results: &mut BTreeMap<usize, BatchResult>,
         combined_limit: usize,
         batch_size: usize,
-        require_same_level: bool,
+        operator_fusion: bool,
     ) -> Result<(), QueryError> {
-        fn eligible_pair(
-            batch_results: &BTreeMap<usize, BatchResult>,
-            require_same_level: bool,
-        ) -> Option<(usize, usize)> {
+        fn eligible_pair(batch_results: &BTreeMap<usize, BatchResult>) -> Option<(usize, usize)> {
             let mut iter = batch_results.iter();
             let mut prev = iter.next()?;
             for (offset, curr) in iter {
-                if (prev.1.level == curr.level || !require_same_level)
+                if prev.1.level == curr.level
                     && prev.1.scanned_range.end == curr.scanned_range.start
                 {
                     return Some((*prev.0, *offset));
@@ -260,21 +654,17 @@ impl QueryTask {
             }
             None
         }
-        while let Some((key1, key2)) = eligible_pair(batch_results, true).or_else(|| {
-            if !require_same_level {
-                eligible_pair(batch_results, false)
-            } else {
-                None
-            }
-        }) {
+        while let Some((key1, key2)) = eligible_pair(batch_results) {
             let br1 = batch_results.remove(&key1).unwrap();
             let br2 = batch_results.remove(&key2).unwrap();
-            let result = combine(br1, br2, combined_limit, batch_size)?;
+            let result = combine(br1, br2, combined_limit, batch_size, operator_fusion)?;
             batch_results.insert(key1, result);
         }
         Ok(())
     }
 
+    /// Combines `result` with adjacent partial results of other workers until no adjacent result remains. Pairs are
+    /// combined outside of the lock, so that the partial results of all workers are reduced in parallel.
     fn push_result(
         &self,
         result: BatchResult,
@@ -282,74 +672,345 @@ impl QueryTask {
         rows_collected: usize,
         explains: Vec<String>,
     ) {
+        let mut result = unsafe { mem::transmute::<_, BatchResult<'static>>(result) };
         let mut state = self.unsafe_state.lock().unwrap();
         if self.completed.load(Ordering::SeqCst) {
             return;
         }
         state.completed_batches += result.batch_count;
+        self.perf_counter.completed_partitions(result.batch_count as u64);
         state.explains.extend(explains);
         self.perf_counter.scanned(rows_scanned as u64);
         state.rows_collected += rows_collected;
 
-        let result = unsafe { mem::transmute::<_, BatchResult<'static>>(result) };
-        state
-            .partial_results
-            .insert(result.scanned_range.start, result);
+        loop {
+            let left = state
+                .partial_results
+                .range(..result.scanned_range.start)
+                .next_back()
+                .filter(|(_, left)| left.scanned_range.end == result.scanned_range.start)
+                .map(|(&start, _)| start);
+            let (left, right) = if let Some(start) = left {
+                (state.partial_results.remove(&start).unwrap(), result)
+            } else if let Some(right) = state.partial_results.remove(&result.scanned_range.end) {
+                (result, right)
+            } else {
+                break;
+            };
+            state.combining += 1;
+            drop(state);
+            let combine_start = Instant::now();
+            let combined = tracing::info_span!(parent: &self.span, "merge").in_scope(|| {
+                combine(
+                    left,
+                    right,
+                    self.combined_limit(),
+                    self.batch_size,
+                    self.operator_fusion,
+                )
+            });
+            self.perf_counter.combined(combine_start.elapsed());
+            state = self.unsafe_state.lock().unwrap();
+            state.combining -= 1;
+            if self.completed.load(Ordering::SeqCst) {
+                // The query was killed or failed while the lock was released
+                drop(combined);
+                if state.combining == 0 {
+                    state.partial_results.clear();
+                    state.colstacks.clear();
+                    state.cached_results.clear();
+                }
+                return;
+            }
+            result = match combined {
+                Ok(result) => result,
+                Err(error) => {
+                    self.fail_with_no_lock(error);
+                    return;
+                }
+            };
+        }
+
+        if result.batch_count < self.morsels.len() {
+            state
+                .partial_results
+                .insert(result.scanned_range.start, result);
+            return;
+        }
+
+        let combine_start = Instant::now();
+        let _finalize_span = tracing::info_span!(parent: &self.span, "finalize").entered();
+        let decode = vec![true; result.dictionaries.len()];
+        let mut full_result =
+            match decode_dictionaries(result, &decode, self.batch_size, self.operator_fusion) {
+                Ok(full_result) => full_result,
+                Err(error) => {
+                    self.fail_with_no_lock(error);
+                    return;
+                }
+            };
+        // Strings in the result may point into these buffers, so they must outlive the final pass
+        let _referenced_buffers = mem::take(&mut full_result.unsafe_referenced_buffers);
+        let mut final_result = if let Some(final_pass) = &self.final_pass {
+            let data_sources = full_result.into_columns();
+            let cols = unsafe {
+                mem::transmute::<
+                    &HashMap<String, Arc<dyn DataSource>>,
+                    &'static HashMap<String, Arc<dyn DataSource>>,
+                >(&data_sources)
+            };
+            let full_result = final_pass
+                .run(
+                    cols,
+                    self.explain,
+                    !self.show.is_empty(),
+                    0xdead_beef,
+                    0..cols.iter().next().map(|(_, c)| c.len()).unwrap_or(0),
+                    self.batch_size,
+                    self.operator_fusion,
+                )
+                .unwrap()
+                .0;
+            self.convert_to_output_format(&full_result, &state.explains)
+        } else if self.late_materialization.is_some() {
+            match self.materialize(&full_result, &state.explains) {
+                Ok(output) => output,
+                Err(error) => {
+                    self.fail_with_no_lock(error);
+                    return;
+                }
+            }
+        } else {
+            self.convert_to_output_format(&full_result, &state.explains)
+        };
+        self.perf_counter.combined(combine_start.elapsed());
+        final_result.stats = self.perf_counter.complete();
+        self.sender.send(Ok(final_result));
+        self.completed.store(true, Ordering::SeqCst);
+    }
+
+    /// Extracts the values of the sort columns followed by the output columns of each row of `result`. Queries without
+    /// `ORDER BY` only have output columns.
+    fn sort_rows(&self, result: &BatchResult) -> Vec<Vec<RawVal>> {
+        (0..result.len())
+            .map(|i| {
+                let sort_values = result
+                    .order_by
+                    .iter()
+                    .map(|&(index, _)| result.columns[index].get_raw(i));
+                let output_values = self.result_column_sources.iter().map(|proj| {
+                    let index = match proj {
+                        ResultColumn::Proj(i) => result.projection[*i],
+                        ResultColumn::Agg(i) => result.aggregations[*i].0,
+                    };
+                    result.columns[index].get_raw(i)
+                });
+                sort_values.chain(output_values).collect()
+            })
+            .collect()
+    }
+
+    /// Groups the rows of `morsel` and merges the groups into `groups`. Returns the number of scanned rows, the number of
+    /// groups and the query plan.
+    fn group_morsel(
+        &self,
+        morsel: &Morsel,
+        id: usize,
+        show: bool,
+        groups: &SpilledGroups,
+    ) -> Result<(usize, usize, Option<String>), QueryError> {
+        let load_start = Instant::now();
+        let mut cols = morsel.partition.get_cols(
+            &self.referenced_cols,
+            &self.db,
+            self.read_limit.as_deref(),
+            self.perf_counter.as_ref(),
+        );
+        if let Some(rows) = &morsel.rows {
+            cols = slice_columns(&cols, rows)
+                .ok_or_else(|| fatal!("Failed to split partition into morsels"))?;
+        }
+        self.perf_counter.loaded_partition(load_start.elapsed());
+        let rows_scanned = cols.iter().next().map_or(0, |c| c.1.len());
+        let execute_start = Instant::now();
+        let (group_count, explain) = self.group_rows(&cols, morsel.range(), id, show, groups)?;
+        self.perf_counter.executed(execute_start.elapsed());
+        Ok((rows_scanned, group_count, explain))
+    }
 
-        if state.completed_batches == self.partitions.len() {
-            let mut owned_results = mem::take(&mut state.partial_results);
-            if let Err(error) = QueryTask::combine_results(
-                &mut owned_results,
-                self.combined_limit(),
+    /// Groups the rows of `cols`, whose range within the table is `rows`, and merges the groups into `groups`. If the
+    /// grouping state exceeds the memory budget, the groups in memory are written to disk and the grouping is retried,
+    /// or the rows are grouped in two halves if there were no groups in memory.
+    fn group_rows(
+        &self,
+        cols: &HashMap<String, Arc<dyn DataSource>>,
+        rows: Range<usize>,
+        id: usize,
+        show: bool,
+        groups: &SpilledGroups,
+    ) -> Result<(usize, Option<String>), QueryError> {
+        loop {
+            let error = match self.main_phase.run_aggregate(
+                cols,
+                self.explain,
+                show,
+                id,
+                rows.clone(),
                 self.batch_size,
-                false,
+                self.operator_fusion,
+                self.memory_budget.as_ref(),
             ) {
-                self.fail_with_no_lock(error);
-                return;
+                Ok((result, explain)) => {
+                    let decode = vec![true; result.dictionaries.len()];
+                    let result = decode_dictionaries(
+                        result,
+                        &decode,
+                        self.batch_size,
+                        self.operator_fusion,
+                    )?;
+                    groups.push(group_values(&result))?;
+                    return Ok((result.len(), explain));
+                }
+                Err(error @ QueryError::MemoryLimitExceeded(_)) => error,
+                Err(error) => return Err(error),
             };
-            if owned_results.len() != 1 {
-                let error = fatal!(
-                    "Expected exactly one remaining partition. Ranges and levels: {:?}",
-                    owned_results
-                        .values()
-                        .map(|v| (v.scanned_range.clone(), v.level))
-                        .collect::<Vec<_>>()
-                );
-                self.fail_with_no_lock(error);
-                return;
+            if groups.spill()? {
+                continue;
             }
-            let full_result = owned_results.into_iter().next().unwrap().1;
-            let final_result = if let Some(final_pass) = &self.final_pass {
-                let data_sources = full_result.into_columns();
-                let cols = unsafe {
-                    mem::transmute::<
-                        &HashMap<String, Arc<dyn DataSource>>,
-                        &'static HashMap<String, Arc<dyn DataSource>>,
-                    >(&data_sources)
-                };
-                let full_result = final_pass
-                    .run(
-                        cols,
-                        self.explain,
-                        !self.show.is_empty(),
-                        0xdead_beef,
-                        0..cols.iter().next().map(|(_, c)| c.len()).unwrap_or(0),
-                        self.batch_size,
-                    )
-                    .unwrap()
-                    .0;
-                self.convert_to_output_format(&full_result, &state.explains)
-            } else {
-                self.convert_to_output_format(&full_result, &state.explains)
+            // Columns can only be sliced at multiples of 8 rows
+            let mid = rows.len() / 2 / 8 * 8;
+            if mid == 0 {
+                // Other workers return the memory of their grouping state once they have grouped their rows
+                let used = self
+                    .memory_budget
+                    .as_ref()
+                    .map_or(0, |budget| budget.used());
+                if used > 0 {
+                    thread::yield_now();
+                    continue;
+                }
+                return Err(error);
+            }
+            let halves =
+                slice_columns(cols, &(0..mid)).zip(slice_columns(cols, &(mid..rows.len())));
+            let (left, right) = match halves {
+                Some(halves) => halves,
+                None => return Err(error),
+            };
+            let (left_groups, explain) =
+                self.group_rows(&left, rows.start..rows.start + mid, id, show, groups)?;
+            let (right_groups, _) =
+                self.group_rows(&right, rows.start + mid..rows.end, id, show, groups)?;
+            return Ok((left_groups + right_groups, explain));
+        }
+    }
+
+    /// Returns the output rows of the first groups of `groups` after the offset of the query.
+    fn merge_groups(&self, groups: &SpilledGroups) -> Result<Vec<Vec<RawVal>>, QueryError> {
+        let limit = &self.main_phase.limit;
+        let offset = limit.offset as usize;
+        let merged = groups.merge(offset.saturating_add(limit.limit as usize))?;
+        let rows = merged
+            .into_iter()
+            .skip(offset)
+            .map(|(key, values)| {
+                self.result_column_sources
+                    .iter()
+                    .map(|column| match column {
+                        ResultColumn::Proj(i) => key[*i].clone(),
+                        ResultColumn::Agg(i) => values[*i].clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(rows)
+    }
+
+    /// Records a partition whose rows were passed to `sorted_runs`, `cursor`, `quantile_runs` or `spilled_groups`
+    /// instead of being merged.
+    fn push_unmerged(&self, rows_scanned: usize, rows_collected: usize, explain: Option<String>) {
+        let mut state = self.unsafe_state.lock().unwrap();
+        if self.completed.load(Ordering::SeqCst) {
+            return;
+        }
+        state.completed_batches += 1;
+        self.perf_counter.completed_partitions(1);
+        state.explains.extend(explain);
+        self.perf_counter.scanned(rows_scanned as u64);
+        state.rows_collected += rows_collected;
+        if state.completed_batches == self.morsels.len() {
+            let mut query_plans = HashMap::new();
+            for plan in &state.explains {
+                *query_plans.entry(plan.to_owned()).or_insert(0) += 1
+            }
+            let (rows, columns) = match (&self.quantiles, &self.spilled_groups) {
+                (Some(quantiles), _) => {
+                    let combine_start = Instant::now();
+                    let rows = quantiles.merge(&mem::take(&mut state.quantile_runs));
+                    self.perf_counter.combined(combine_start.elapsed());
+                    self.rows_to_output(rows)
+                }
+                (None, Some(groups)) => {
+                    let combine_start = Instant::now();
+                    let rows = match self.merge_groups(groups) {
+                        Ok(rows) => rows,
+                        Err(error) => {
+                            self.fail_with_no_lock(error);
+                            return;
+                        }
+                    };
+                    self.perf_counter.combined(combine_start.elapsed());
+                    self.rows_to_output(rows)
+                }
+                (None, None) => (None, vec![]),
             };
-            self.sender.send(Ok(final_result));
+            self.sender.send(Ok(QueryOutput {
+                colnames: self.output_colnames.clone(),
+                rows,
+                columns,
+                query_plans,
+                stats: self.perf_counter.complete(),
+            }));
             self.completed.store(true, Ordering::SeqCst);
         }
     }
 
-    fn push_colstack(&self, colstack: Vec<HashMap<String, Arc<dyn DataSource>>>) {
+    /// Fails the query with `QueryError::Killed` and frees its intermediate results. Returns false if the query has
+    /// already completed.
+    pub fn kill(&self) -> bool {
+        let mut state = self.unsafe_state.lock().unwrap();
+        if self.completed.load(Ordering::SeqCst) {
+            return false;
+        }
+        self.fail_with_no_lock(QueryError::Killed);
+        // Partial results may reference the columns in colstacks, so they are dropped first. Results that are being
+        // combined may reference them as well, in which case the last combining worker frees them.
+        state.partial_results.clear();
+        state.quantile_runs.clear();
+        if state.combining == 0 {
+            state.colstacks.clear();
+            state.cached_results.clear();
+        }
+        true
+    }
+
+    fn push_colstack(
+        &self,
+        colstack: Vec<HashMap<String, Arc<dyn DataSource>>>,
+        cached_results: Vec<Arc<CachedResult>>,
+    ) {
         let mut state = self.unsafe_state.lock().unwrap();
         state.colstacks.push(colstack);
+        state.cached_results.extend(cached_results);
+    }
+
+    /// Returns the cache for the per-partition results of this query, if they can be cached.
+    fn aggregate_cache(&self, show: bool) -> Option<&AggregateCache> {
+        // Explain output and the output of `show` are produced while executing the query plan
+        if self.main_phase.aggregate.is_empty() || self.explain || show {
+            return None;
+        }
+        self.aggregate_cache.as_deref()
     }
 
     fn fail_with(&self, error: QueryError) {
@@ -362,14 +1023,33 @@ impl QueryTask {
 
     fn fail_with_no_lock(&self, error: QueryError) {
         self.completed.store(true, Ordering::SeqCst);
-        self.batch_index
-            .store(self.partitions.len(), Ordering::SeqCst);
+        self.perf_counter.complete();
+        self.batch_index.store(self.morsels.len(), Ordering::SeqCst);
         self.sender.send(Err(error));
     }
 
-    fn next_partition(&self) -> Option<(&Arc<Partition>, usize)> {
+    /// Reads the columns of the partitions following the morsel at `index` in the background.
+    fn prefetch(&self, index: usize) {
+        let end = cmp::min(index + 1 + self.prefetch_partitions, self.morsels.len());
+        let start = cmp::max(
+            self.prefetch_index.fetch_max(end, Ordering::SeqCst),
+            index + 1,
+        );
+        if start < end {
+            self.db.prefetch(
+                self.morsels[start..end]
+                    .iter()
+                    .map(|morsel| morsel.partition.clone()),
+                &self.referenced_cols,
+                self.read_limit.as_ref(),
+                &self.perf_counter,
+            );
+        }
+    }
+
+    fn next_morsel(&self) -> Option<(&Morsel, usize)> {
         let index = self.batch_index.fetch_add(1, Ordering::SeqCst);
-        self.partitions.get(index).map(|b| (b, index))
+        self.morsels.get(index).map(|morsel| (morsel, index))
     }
 
     fn convert_to_output_format(
@@ -388,7 +1068,7 @@ impl QueryTask {
         full_result.validate().unwrap();
 
         let mut rows = None;
-        if self.rowformat {
+        if self.rowformat || self.windows.is_some() {
             let mut result_rows = Vec::new();
             for i in offset..(count + offset) {
                 let mut record = Vec::with_capacity(self.output_colnames.len());
@@ -420,18 +1100,129 @@ impl QueryTask {
             columns.push((colname.clone(), column));
         }
 
+        if let Some(windows) = &self.windows {
+            let result_rows = windows.evaluate(rows.take().unwrap());
+            (rows, columns) = self.rows_to_output(result_rows);
+        }
+
         QueryOutput {
             colnames: self.output_colnames.clone(),
             rows,
             columns,
             query_plans,
-            stats: QueryStats {
-                runtime_ns: self.start_time.elapsed().as_nanos() as u64,
-                rows_scanned: self.perf_counter.rows_scanned(),
-                files_opened: self.perf_counter.files_opened(),
-                disk_read_bytes: self.perf_counter.disk_read_bytes(),
-            },
+            // Set by the caller once combining the result has completed
+            stats: QueryStats::default(),
+        }
+    }
+
+    /// Returns the rows of a result that was computed in row format, if requested, and its columns.
+    fn rows_to_output(
+        &self,
+        result_rows: Vec<Vec<RawVal>>,
+    ) -> (Option<Vec<Vec<RawVal>>>, Vec<(String, BasicTypeColumn)>) {
+        let columns = self
+            .output_colnames
+            .iter()
+            .enumerate()
+            .map(|(i, colname)| {
+                let values = result_rows.iter().map(|row| row[i].clone()).collect();
+                (colname.clone(), BasicTypeColumn::from_raw_vals(values))
+            })
+            .collect();
+        let rows = if self.rowformat {
+            Some(result_rows)
+        } else {
+            None
+        };
+        (rows, columns)
+    }
+
+    /// Evaluates the projection for the rows of `full_result`, which only contains the sort columns and the index of
+    /// each row within the table, see `NormalFormQuery::late_materialized`.
+    fn materialize(
+        &self,
+        full_result: &BatchResult,
+        explains: &[String],
+    ) -> Result<QueryOutput, QueryError> {
+        let limit = &self.main_phase.limit;
+        let offset = cmp::min(limit.offset as usize, full_result.len());
+        let count = cmp::min(limit.limit as usize, full_result.len() - offset);
+        let row_ids = full_result.columns[full_result.projection[0]].cast_ref_i64();
+
+        // Row range and first morsel of each partition. Morsels are in scan order, which is only ordered by row range
+        // if some partitions were pruned.
+        let mut partition_ranges = self
+            .morsels
+            .iter()
+            .enumerate()
+            .map(|(index, morsel)| (morsel.partition.range(), index))
+            .filter(|(range, _)| !range.is_empty())
+            .collect::<Vec<_>>();
+        partition_ranges.sort_by_key(|(range, index)| (range.start, *index));
+        partition_ranges.dedup_by_key(|(range, _)| range.start);
+
+        // Position in the output and index within the partition of each row, grouped by partition
+        let mut rows_by_partition = BTreeMap::<usize, Vec<(usize, usize)>>::new();
+        for (position, &row_id) in row_ids[offset..offset + count].iter().enumerate() {
+            let row_id = row_id as usize;
+            let (range, partition) = &partition_ranges
+                [partition_ranges.partition_point(|(range, _)| range.end <= row_id)];
+            rows_by_partition
+                .entry(*partition)
+                .or_default()
+                .push((position, row_id - range.start));
+        }
+
+        let mut rows = vec![vec![]; count];
+        for (partition, positions) in rows_by_partition {
+            let partition = &self.morsels[partition].partition;
+            let cols = partition.get_cols(
+                &self.referenced_cols,
+                &self.db,
+                self.read_limit.as_deref(),
+                self.perf_counter.as_ref(),
+            );
+            let indices = positions.iter().map(|&(_, index)| index).collect();
+            let result = self.main_phase.materialize(
+                &cols,
+                indices,
+                partition.range(),
+                self.batch_size,
+                self.operator_fusion,
+            )?;
+            for (i, &(position, _)) in positions.iter().enumerate() {
+                rows[position] = self
+                    .result_column_sources
+                    .iter()
+                    .map(|proj| match proj {
+                        ResultColumn::Proj(j) => result.columns[result.projection[*j]].get_raw(i),
+                        ResultColumn::Agg(_) => unreachable!(),
+                    })
+                    .collect();
+            }
+        }
+
+        let mut query_plans = HashMap::new();
+        for plan in explains {
+            *query_plans.entry(plan.to_owned()).or_insert(0) += 1
         }
+        let columns = self
+            .output_colnames
+            .iter()
+            .enumerate()
+            .map(|(i, colname)| {
+                let values = rows.iter().map(|row| row[i].clone()).collect();
+                (colname.clone(), BasicTypeColumn::from_raw_vals(values))
+            })
+            .collect();
+        Ok(QueryOutput {
+            colnames: self.output_colnames.clone(),
+            rows: if self.rowformat { Some(rows) } else { None },
+            columns,
+            query_plans,
+            // Set by the caller once combining the result has completed
+            stats: QueryStats::default(),
+        })
     }
 
     fn combined_limit(&self) -> usize {
@@ -445,7 +1236,7 @@ impl Task for QueryTask {
     }
     fn completed(&self) -> bool {
         let batch_index = self.batch_index.load(Ordering::SeqCst);
-        self.completed.load(Ordering::SeqCst) || batch_index >= self.partitions.len()
+        self.completed.load(Ordering::SeqCst) || batch_index >= self.morsels.len()
     }
     fn multithreaded(&self) -> bool {
         true
@@ -463,6 +1254,196 @@ fn find_all_cols(source: &[Arc<Partition>]) -> Vec<String> {
     cols.into_iter().collect()
 }
 
+/// Slices rows `rows` of each of the columns, returns `None` if any of the columns does not support slicing.
+/// Extracts the group key and the aggregate values of each group of `result`.
+fn group_values(result: &BatchResult) -> Vec<(Vec<RawVal>, Vec<RawVal>)> {
+    (0..result.len())
+        .map(|i| {
+            let key = result
+                .projection
+                .iter()
+                .map(|&index| result.columns[index].get_raw(i))
+                .collect();
+            let values = result
+                .aggregations
+                .iter()
+                .map(|&(index, _)| result.columns[index].get_raw(i))
+                .collect();
+            (key, values)
+        })
+        .collect()
+}
+
+fn slice_columns(
+    cols: &HashMap<String, Arc<dyn DataSource>>,
+    rows: &Range<usize>,
+) -> Option<HashMap<String, Arc<dyn DataSource>>> {
+    cols.iter()
+        .map(|(name, column)| {
+            let slice = ColumnSlice::new(column, rows.clone())?;
+            Some((name.clone(), Arc::new(slice) as Arc<dyn DataSource>))
+        })
+        .collect()
+}
+
+/// Skips partitions whose column ranges or bloom filters show that none of their rows can match `filter`. The rows of skipped
+/// partitions are accounted to the preceding remaining partition, and at least one partition is kept so that the query
+/// returns the same output as when scanning all partitions.
+fn prune_partitions(
+    mut source: Vec<Arc<Partition>>,
+    filter: &Expr,
+) -> Vec<(Arc<Partition>, Range<usize>)> {
+    if source.iter().all(|partition| may_match(filter, partition)) {
+        return source
+            .into_iter()
+            .map(|partition| {
+                let range = partition.range();
+                (partition, range)
+            })
+            .collect();
+    }
+    source.sort_by_key(|partition| partition.range().start);
+    let mut partitions = Vec::<(Arc<Partition>, Range<usize>)>::new();
+    let mut skipped_start = None;
+    for partition in &source {
+        let range = partition.range();
+        if may_match(filter, partition) {
+            let start = skipped_start.take().unwrap_or(range.start);
+            partitions.push((partition.clone(), start..range.end));
+        } else if let Some((_, last_range)) = partitions.last_mut() {
+            last_range.end = range.end;
+        } else {
+            skipped_start.get_or_insert(range.start);
+        }
+    }
+    if partitions.is_empty() {
+        let start = source[0].range().start;
+        let end = source[source.len() - 1].range().end;
+        partitions.push((source[0].clone(), start..end));
+    }
+    partitions
+}
+
+/// Collects the statistics of all columns referenced by `filter` over the scanned partitions.
+fn filter_statistics(
+    filter: &Expr,
+    partitions: &[(Arc<Partition>, Range<usize>)],
+) -> HashMap<String, ColumnStatistics> {
+    let mut colnames = HashSet::new();
+    filter.add_colnames(&mut colnames);
+    colnames
+        .into_iter()
+        .map(|column| {
+            let mut statistics = ColumnStatistics::default();
+            for (partition, _) in partitions {
+                statistics.add(
+                    partition.len(),
+                    partition.column_stats(&column),
+                    partition.value_range(&column),
+                );
+            }
+            (column, statistics)
+        })
+        .collect()
+}
+
+/// Whether any row of `partition` may satisfy `filter`, judging by the value ranges of its integer columns, the
+/// bloom filters of its string columns and the number of null values of its columns.
+fn may_match(filter: &Expr, partition: &Partition) -> bool {
+    match filter {
+        Expr::Func2(Func2Type::And, lhs, rhs) => {
+            may_match(lhs, partition) && may_match(rhs, partition)
+        }
+        Expr::Func2(Func2Type::Or, lhs, rhs) => {
+            may_match(lhs, partition) || may_match(rhs, partition)
+        }
+        Expr::Func2(op, lhs, rhs) => match (&**lhs, &**rhs) {
+            (Expr::ColName(column), Expr::Const(RawVal::Int(value))) => {
+                range_may_match(*op, partition.value_range(column), *value)
+            }
+            (Expr::Const(RawVal::Int(value)), Expr::ColName(column)) => {
+                let op = match op {
+                    Func2Type::LT => Func2Type::GT,
+                    Func2Type::LTE => Func2Type::GTE,
+                    Func2Type::GT => Func2Type::LT,
+                    Func2Type::GTE => Func2Type::LTE,
+                    op => *op,
+                };
+                range_may_match(op, partition.value_range(column), *value)
+            }
+            (Expr::ColName(column), Expr::Const(RawVal::Str(value)))
+            | (Expr::Const(RawVal::Str(value)), Expr::ColName(column))
+                if *op == Func2Type::Equals =>
+            {
+                partition.may_contain(column, value)
+            }
+            _ => true,
+        },
+        Expr::Func1(Func1Type::IsNull, expr) => match &**expr {
+            Expr::ColName(column) => partition
+                .column_stats(column)
+                .map_or(true, |stats| stats.null_count > 0),
+            _ => true,
+        },
+        Expr::Func1(Func1Type::IsNotNull, expr) => match &**expr {
+            Expr::ColName(column) => partition
+                .column_stats(column)
+                .map_or(true, |stats| stats.null_count < partition.len()),
+            _ => true,
+        },
+        Expr::Const(_) => !is_always_false(filter),
+        _ => true,
+    }
+}
+
+/// Whether `column <op> value` may hold for some value of a column with the given range.
+fn range_may_match(op: Func2Type, range: Option<(i64, i64)>, value: i64) -> bool {
+    let (min, max) = match range {
+        Some(range) => range,
+        None => return true,
+    };
+    match op {
+        Func2Type::Equals => min <= value && value <= max,
+        Func2Type::NotEquals => min != value || max != value,
+        Func2Type::LT => min < value,
+        Func2Type::LTE => min <= value,
+        Func2Type::GT => max > value,
+        Func2Type::GTE => max >= value,
+        _ => true,
+    }
+}
+
+impl QueryOutput {
+    /// Result of statements that do not return any rows.
+    pub fn empty(rowformat: bool) -> QueryOutput {
+        QueryOutput {
+            colnames: vec![],
+            rows: if rowformat { Some(vec![]) } else { None },
+            columns: vec![],
+            query_plans: HashMap::default(),
+            stats: QueryStats::default(),
+        }
+    }
+
+    /// Result of statements that modify rows, containing the number of affected rows in column `colname`.
+    pub fn affected_rows(colname: &str, count: usize, rowformat: bool) -> QueryOutput {
+        QueryOutput {
+            colnames: vec![colname.to_string()],
+            rows: if rowformat {
+                Some(vec![vec![RawVal::Int(count as i64)]])
+            } else {
+                None
+            },
+            columns: vec![(
+                colname.to_string(),
+                BasicTypeColumn::Int(vec![count as i64]),
+            )],
+            query_plans: HashMap::default(),
+            stats: QueryStats::default(),
+        }
+    }
+}
+
 impl BasicTypeColumn {
     fn from_boxed_data(data: BoxedData) -> BasicTypeColumn {
         match data.get_type() {
@@ -519,6 +1500,81 @@ impl BasicTypeColumn {
         }
     }
 
+    pub(crate) fn from_raw_vals(vals: Vec<RawVal>) -> BasicTypeColumn {
+        if vals.iter().all(|v| matches!(v, RawVal::Int(_))) {
+            BasicTypeColumn::Int(
+                vals.into_iter()
+                    .map(|v| match v {
+                        RawVal::Int(i) => i,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            )
+        } else if vals.iter().all(|v| matches!(v, RawVal::Float(_))) {
+            BasicTypeColumn::Float(
+                vals.into_iter()
+                    .map(|v| match v {
+                        RawVal::Float(f) => f.0,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            )
+        } else if vals.iter().all(|v| matches!(v, RawVal::Str(_))) {
+            BasicTypeColumn::String(
+                vals.into_iter()
+                    .map(|v| match v {
+                        RawVal::Str(s) => s,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            )
+        } else if vals.iter().all(|v| *v == RawVal::Null) {
+            BasicTypeColumn::Null(vals.len())
+        } else {
+            BasicTypeColumn::Mixed(vals)
+        }
+    }
+
+    pub(crate) fn into_raw_vals(self) -> Vec<RawVal> {
+        match self {
+            BasicTypeColumn::Int(ints) => ints.into_iter().map(RawVal::Int).collect(),
+            BasicTypeColumn::Float(floats) => floats
+                .into_iter()
+                .map(|f| RawVal::Float(OrderedFloat(f)))
+                .collect(),
+            BasicTypeColumn::String(strings) => strings.into_iter().map(RawVal::Str).collect(),
+            BasicTypeColumn::Null(count) => vec![RawVal::Null; count],
+            BasicTypeColumn::Mixed(vals) => vals,
+        }
+    }
+
+    /// Indices of the values in ascending order. Integers and floats are compared by value, strings come
+    /// after numbers and nulls come last. Equal values keep their relative order.
+    pub(crate) fn sort_order(self) -> Vec<usize> {
+        let values = self.into_raw_vals();
+        let mut order = (0..values.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| match (&values[a], &values[b]) {
+            (RawVal::Int(x), RawVal::Float(y)) => OrderedFloat(*x as f64).cmp(y),
+            (RawVal::Float(x), RawVal::Int(y)) => x.cmp(&OrderedFloat(*y as f64)),
+            (x, y) => x.cmp(y),
+        });
+        order
+    }
+
+    /// Reorders the values so that the value at `order[i]` moves to position `i`.
+    pub(crate) fn permute(self, order: &[usize]) -> BasicTypeColumn {
+        fn permute<T: Clone>(values: Vec<T>, order: &[usize]) -> Vec<T> {
+            order.iter().map(|&i| values[i].clone()).collect()
+        }
+        match self {
+            BasicTypeColumn::Int(v) => BasicTypeColumn::Int(permute(v, order)),
+            BasicTypeColumn::Float(v) => BasicTypeColumn::Float(permute(v, order)),
+            BasicTypeColumn::String(v) => BasicTypeColumn::String(permute(v, order)),
+            BasicTypeColumn::Null(count) => BasicTypeColumn::Null(count),
+            BasicTypeColumn::Mixed(v) => BasicTypeColumn::Mixed(permute(v, order)),
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
             BasicTypeColumn::Int(v) => v.len(),
@@ -4,6 +4,7 @@ use std::cell::*;
 use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::mem;
+use std::sync::Arc;
 
 pub struct Scratchpad<'a> {
     buffers: Vec<RefCell<BoxedData<'a>>>,
@@ -11,6 +12,7 @@ pub struct Scratchpad<'a> {
     null_maps: Vec<Option<usize>>,
     columns: HashMap<String, Vec<&'a dyn Data<'a>>>,
     pinned: Vec<bool>,
+    memory: MemoryReservation,
 }
 
 impl<'a> Scratchpad<'a> {
@@ -25,9 +27,20 @@ impl<'a> Scratchpad<'a> {
             null_maps: vec![None; count],
             columns,
             pinned: vec![false; count],
+            memory: MemoryReservation::default(),
         }
     }
 
+    /// Accounts memory reserved by operators against `budget`.
+    pub fn set_memory_budget(&mut self, budget: Option<Arc<MemoryBudget>>) {
+        self.memory = MemoryReservation::new(budget);
+    }
+
+    /// Reserves memory for operator state, fails if this exceeds the memory budget of the query.
+    pub fn reserve_memory(&mut self, bytes: usize) -> Result<(), QueryError> {
+        self.memory.grow(bytes)
+    }
+
     pub fn get_any(&self, index: BufferRef<Any>) -> Ref<dyn Data<'a>> {
         Ref::map(self.buffer(index).borrow(), |x| x.as_ref())
     }
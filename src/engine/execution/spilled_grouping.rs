@@ -0,0 +1,313 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use fnv::{FnvHashMap, FnvHasher};
+use ordered_float::OrderedFloat;
+use tempfile::TempDir;
+
+use super::external_sort::compare_vals;
+use super::memory_budget::{MemoryBudget, MemoryReservation};
+use crate::engine::Aggregator;
+use crate::ingest::raw_val::RawVal;
+use crate::QueryError;
+
+/// Number of files the groups are partitioned into by the hash of their key when they are written to disk
+const SPILL_BUCKETS: usize = 16;
+
+/// Group key and aggregate values of a group
+pub type Group = (Vec<RawVal>, Vec<RawVal>);
+
+/// Merges the groups of an aggregation query across partitions within a `MemoryBudget`. Groups are merged in memory
+/// until they exceed the budget, at which point they are partitioned by the hash of their key and written to disk.
+/// `merge` then merges each partition of the groups separately, so that only the groups of one partition are held in
+/// memory at once.
+pub struct SpilledGroups {
+    aggregators: Vec<Aggregator>,
+    budget: Arc<MemoryBudget>,
+    state: Mutex<GroupsState>,
+}
+
+struct GroupsState {
+    groups: FnvHashMap<Vec<RawVal>, Vec<RawVal>>,
+    /// Memory of `groups`
+    reservation: MemoryReservation,
+    /// Created once groups are first written to disk
+    dir: Option<TempDir>,
+    /// Path and number of groups of each file that was written for each bucket
+    buckets: Vec<Vec<(PathBuf, usize)>>,
+    next_spill: usize,
+}
+
+impl SpilledGroups {
+    pub fn new(aggregators: Vec<Aggregator>, budget: Arc<MemoryBudget>) -> SpilledGroups {
+        SpilledGroups {
+            aggregators,
+            state: Mutex::new(GroupsState {
+                groups: FnvHashMap::default(),
+                reservation: MemoryReservation::new(Some(budget.clone())),
+                dir: None,
+                buckets: vec![Vec::new(); SPILL_BUCKETS],
+                next_spill: 0,
+            }),
+            budget,
+        }
+    }
+
+    /// Merges `groups` with the groups in memory, writing all groups to disk if they exceed the budget.
+    pub fn push(&self, groups: Vec<Group>) -> Result<(), QueryError> {
+        let mut state = self.state.lock().unwrap();
+        let mut bytes = 0;
+        for (key, values) in groups {
+            bytes += merge_group(&self.aggregators, &mut state.groups, key, values)?;
+        }
+        if state.reservation.grow(bytes).is_err() {
+            drop(state);
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the groups in memory to disk and returns whether there were any.
+    pub fn spill(&self) -> Result<bool, QueryError> {
+        let (groups, reservation, dir, spill) = {
+            let mut state = self.state.lock().unwrap();
+            if state.groups.is_empty() {
+                return Ok(false);
+            }
+            if state.dir.is_none() {
+                let dir = tempfile::Builder::new()
+                    .prefix("locustdb-group")
+                    .tempdir()
+                    .map_err(|e| fatal!("Failed to create directory for spilled groups: {}", e))?;
+                state.dir = Some(dir);
+            }
+            let dir = state.dir.as_ref().unwrap().path().to_path_buf();
+            let spill = state.next_spill;
+            state.next_spill += 1;
+            let reservation = mem::replace(
+                &mut state.reservation,
+                MemoryReservation::new(Some(self.budget.clone())),
+            );
+            (mem::take(&mut state.groups), reservation, dir, spill)
+        };
+        // Writing happens outside the lock so that other workers can keep merging groups
+        let mut buckets = vec![Vec::new(); SPILL_BUCKETS];
+        for group in groups {
+            buckets[bucket(&group.0)].push(group);
+        }
+        let mut files = Vec::with_capacity(SPILL_BUCKETS);
+        for (bucket, groups) in buckets.iter().enumerate() {
+            if groups.is_empty() {
+                continue;
+            }
+            let path = dir.join(format!("spill_{}_{}", spill, bucket));
+            write_groups(&path, groups)?;
+            files.push((bucket, path, groups.len()));
+        }
+        // The memory of the groups is only returned to the budget once they have been written
+        drop(buckets);
+        drop(reservation);
+        let mut state = self.state.lock().unwrap();
+        for (bucket, path, len) in files {
+            state.buckets[bucket].push((path, len));
+        }
+        Ok(true)
+    }
+
+    /// Number of times the groups have been written to disk.
+    pub fn spills(&self) -> usize {
+        self.state.lock().unwrap().next_spill
+    }
+
+    /// Merges all groups, including those written to disk, and returns the first `limit` groups ordered by key.
+    pub fn merge(&self, limit: usize) -> Result<Vec<Group>, QueryError> {
+        // Groups in memory may also be contained in the files, so they are merged bucket by bucket as well
+        if self.spills() > 0 {
+            self.spill()?;
+        }
+        let (groups, buckets) = {
+            let mut state = self.state.lock().unwrap();
+            (mem::take(&mut state.groups), mem::take(&mut state.buckets))
+        };
+        let mut merged = groups.into_iter().collect::<Vec<_>>();
+        for files in buckets {
+            let mut groups = FnvHashMap::default();
+            for (path, len) in files {
+                let file = File::open(&path)
+                    .map_err(|e| fatal!("Failed to open spilled groups: {}", e))?;
+                let mut reader = BufReader::new(file);
+                for _ in 0..len {
+                    let (key, values): Group = bincode::deserialize_from(&mut reader)
+                        .map_err(|e| fatal!("Failed to read spilled groups: {}", e))?;
+                    merge_group(&self.aggregators, &mut groups, key, values)?;
+                }
+            }
+            merged.extend(groups);
+            if merged.len() > limit {
+                merged.sort_by(|a, b| compare_keys(&a.0, &b.0));
+                merged.truncate(limit);
+            }
+        }
+        merged.sort_by(|a, b| compare_keys(&a.0, &b.0));
+        merged.truncate(limit);
+        Ok(merged)
+    }
+}
+
+/// Merges a group into `groups` and returns the estimated number of bytes it added.
+fn merge_group(
+    aggregators: &[Aggregator],
+    groups: &mut FnvHashMap<Vec<RawVal>, Vec<RawVal>>,
+    key: Vec<RawVal>,
+    values: Vec<RawVal>,
+) -> Result<usize, QueryError> {
+    match groups.get_mut(&key) {
+        Some(aggregates) => {
+            for ((&aggregator, aggregate), value) in
+                aggregators.iter().zip(aggregates.iter_mut()).zip(values)
+            {
+                merge_aggregate(aggregator, aggregate, value)?;
+            }
+            Ok(0)
+        }
+        None => {
+            let bytes = group_bytes(&key, &values);
+            groups.insert(key, values);
+            Ok(bytes)
+        }
+    }
+}
+
+/// Combines two values of `aggregator`, nulls are ignored.
+fn merge_aggregate(
+    aggregator: Aggregator,
+    aggregate: &mut RawVal,
+    value: RawVal,
+) -> Result<(), QueryError> {
+    let merged = match (aggregator, &*aggregate, value) {
+        (_, _, RawVal::Null) => return Ok(()),
+        (_, RawVal::Null, value) => value,
+        (
+            Aggregator::SumI64 | Aggregator::SumF64 | Aggregator::Count,
+            RawVal::Int(a),
+            RawVal::Int(b),
+        ) => RawVal::Int(a.checked_add(b).ok_or(QueryError::Overflow)?),
+        (Aggregator::SumI64 | Aggregator::SumF64 | Aggregator::Count, a, b) => {
+            match (as_f64(a), as_f64(&b)) {
+                (Some(a), Some(b)) => RawVal::Float(OrderedFloat(a + b)),
+                _ => return Err(fatal!("Cannot add {:?} and {:?}", a, b)),
+            }
+        }
+        (Aggregator::MaxI64 | Aggregator::MaxF64, a, b) => {
+            if compare_vals(&b, a) != Ordering::Greater {
+                return Ok(());
+            }
+            b
+        }
+        (Aggregator::MinI64 | Aggregator::MinF64, a, b) => {
+            if compare_vals(&b, a) != Ordering::Less {
+                return Ok(());
+            }
+            b
+        }
+    };
+    *aggregate = merged;
+    Ok(())
+}
+
+fn as_f64(val: &RawVal) -> Option<f64> {
+    match val {
+        RawVal::Int(i) => Some(*i as f64),
+        RawVal::Float(f) => Some(f.0),
+        _ => None,
+    }
+}
+
+fn compare_keys(left: &[RawVal], right: &[RawVal]) -> Ordering {
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| compare_vals(left, right))
+        .find(|&ordering| ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn bucket(key: &[RawVal]) -> usize {
+    let mut hasher = FnvHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % SPILL_BUCKETS
+}
+
+/// Estimated memory of a group, including the entry in the hash map.
+fn group_bytes(key: &[RawVal], values: &[RawVal]) -> usize {
+    let heap_size = key
+        .iter()
+        .chain(values)
+        .map(|val| val.heap_size_of_children())
+        .sum::<usize>();
+    2 * mem::size_of::<Vec<RawVal>>()
+        + (key.len() + values.len()) * mem::size_of::<RawVal>()
+        + heap_size
+}
+
+fn write_groups(path: &Path, groups: &[Group]) -> Result<(), QueryError> {
+    let mut writer = BufWriter::new(
+        File::create(path).map_err(|e| fatal!("Failed to create spilled groups: {}", e))?,
+    );
+    for group in groups {
+        bincode::serialize_into(&mut writer, group)
+            .map_err(|e| fatal!("Failed to write spilled groups: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| fatal!("Failed to write spilled groups: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::raw_val::syntax::*;
+
+    #[test]
+    fn test_merge_spilled_groups() {
+        let aggregators = vec![Aggregator::Count, Aggregator::MaxI64, Aggregator::SumF64];
+        let groups = SpilledGroups::new(aggregators, Arc::new(MemoryBudget::new(2048)));
+        for partition in 0..10 {
+            let rows = (0..20)
+                .map(|i| {
+                    let key = vec![Str(&(i % 10).to_string()), Int(i % 2)];
+                    (key, vec![Int(1), Int(partition * 100 + i), Float(0.5)])
+                })
+                .collect();
+            groups.push(rows).unwrap();
+        }
+        assert!(groups.spills() > 0);
+        let merged = groups.merge(3).unwrap();
+        assert_eq!(
+            merged,
+            vec![
+                (vec![Str("0"), Int(0)], vec![Int(20), Int(910), Float(10.0)]),
+                (vec![Str("1"), Int(1)], vec![Int(20), Int(911), Float(10.0)]),
+                (vec![Str("2"), Int(0)], vec![Int(20), Int(912), Float(10.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_aggregate_nulls() {
+        let mut aggregate = Null;
+        merge_aggregate(Aggregator::MinI64, &mut aggregate, Int(3)).unwrap();
+        merge_aggregate(Aggregator::MinI64, &mut aggregate, Null).unwrap();
+        merge_aggregate(Aggregator::MinI64, &mut aggregate, Int(-1)).unwrap();
+        assert_eq!(aggregate, Int(-1));
+        let mut sum = Int(i64::MAX);
+        assert!(matches!(
+            merge_aggregate(Aggregator::SumI64, &mut sum, Int(1)),
+            Err(QueryError::Overflow)
+        ));
+    }
+}
@@ -5,6 +5,12 @@ use crate::engine::*;
 use std::i64;
 use std::marker::PhantomData;
 
+/// Number of independent accumulators per group used by `scatter_aggregate`.
+const LANES: usize = 8;
+/// Maximum number of groups for which `scatter_aggregate` is used. Larger accumulator arrays no longer fit into L1 cache
+/// once they are replicated `LANES` times.
+const MAX_SCATTER_GROUPS: usize = 512;
+
 pub trait Aggregator<T, Acc> {
     fn unit() -> Acc;
     fn accumulate(accumulator: Acc, value: T) ->Acc;
@@ -114,9 +120,13 @@ impl<'a, T, U, V, A: Aggregator<T, V>> VecOperator<'a> for Aggregate<T, U, V, A>
             accumulators.resize(len, A::unit());
         }
 
-        for (i, n) in grouping.iter().zip(nums.iter()) {
-            let i = i.cast_usize();
-            accumulators[i] = A::accumulate(accumulators[i], *n);
+        if is_dense(len, nums.len()) {
+            scatter_aggregate::<T, U, V, A>(&grouping, &nums, &mut accumulators[..len]);
+        } else {
+            for (i, n) in grouping.iter().zip(nums.iter()) {
+                let i = i.cast_usize();
+                accumulators[i] = A::accumulate(accumulators[i], *n);
+            }
         }
 
         Ok(())
@@ -206,11 +216,15 @@ impl<'a, T, U, V, A: CheckedAggregator<T, V>> VecOperator<'a> for CheckedAggrega
         }
 
         let mut any_overflow = false;
-        for (i, n) in grouping.iter().zip(nums.iter()) {
-            let i = i.cast_usize();
-            let (result, overflow) = A::accumulate_checked(accumulators[i], *n);
-            any_overflow |= overflow;
-            accumulators[i] = result;
+        if is_dense(len, nums.len()) {
+            any_overflow = scatter_aggregate_checked::<T, U, V, A>(&grouping, &nums, &mut accumulators[..len]);
+        } else {
+            for (i, n) in grouping.iter().zip(nums.iter()) {
+                let i = i.cast_usize();
+                let (result, overflow) = A::accumulate_checked(accumulators[i], *n);
+                any_overflow |= overflow;
+                accumulators[i] = result;
+            }
         }
 
         if any_overflow { Err(QueryError::Overflow) } else { Ok(()) }
@@ -281,3 +295,100 @@ impl<'a, T, U, V, A: CheckedAggregator<T, V>> VecOperator<'a> for CheckedAggrega
     }
     fn display_output(&self) -> bool { false }
 }
+
+/// Whether grouping `rows` rows into `groups` groups should use `scatter_aggregate`.
+#[inline]
+fn is_dense(groups: usize, rows: usize) -> bool {
+    groups <= MAX_SCATTER_GROUPS && rows >= groups * LANES
+}
+
+/// Aggregates consecutive rows into `LANES` separate accumulators of their group.
+/// With few groups, consecutive rows frequently belong to the same group, and a single accumulator per group makes
+/// each row wait on the store of the previous row. Independent lanes let the rows of a chunk be accumulated in
+/// parallel, and the lanes are combined once per batch.
+fn scatter_aggregate<T, U, V, A>(grouping: &[U], nums: &[T], accumulators: &mut [V])
+    where T: Copy, U: GenericIntVec<U>, V: Copy, A: Aggregator<T, V> {
+    let mut lanes = vec![[A::unit(); LANES]; accumulators.len()];
+    let mut grouping_chunks = grouping.chunks_exact(LANES);
+    let mut num_chunks = nums.chunks_exact(LANES);
+    for (groups, nums) in (&mut grouping_chunks).zip(&mut num_chunks) {
+        for lane in 0..LANES {
+            let g = groups[lane].cast_usize();
+            lanes[g][lane] = A::accumulate(lanes[g][lane], nums[lane]);
+        }
+    }
+    for (g, n) in grouping_chunks.remainder().iter().zip(num_chunks.remainder()) {
+        let g = g.cast_usize();
+        lanes[g][0] = A::accumulate(lanes[g][0], *n);
+    }
+    for (accumulator, lanes) in accumulators.iter_mut().zip(lanes) {
+        *accumulator = lanes.iter().fold(*accumulator, |acc, lane| A::combine(acc, *lane));
+    }
+}
+
+/// Like `scatter_aggregate`, returns whether any addition overflowed. As with the sequential loop, an overflow of a
+/// partial sum is reported even if the total would not overflow.
+fn scatter_aggregate_checked<T, U, V, A>(grouping: &[U], nums: &[T], accumulators: &mut [V]) -> bool
+    where T: Copy, U: GenericIntVec<U>, V: Copy, A: CheckedAggregator<T, V> {
+    let mut lanes = vec![[A::unit(); LANES]; accumulators.len()];
+    let mut any_overflow = false;
+    let mut grouping_chunks = grouping.chunks_exact(LANES);
+    let mut num_chunks = nums.chunks_exact(LANES);
+    for (groups, nums) in (&mut grouping_chunks).zip(&mut num_chunks) {
+        for lane in 0..LANES {
+            let g = groups[lane].cast_usize();
+            let (result, overflow) = A::accumulate_checked(lanes[g][lane], nums[lane]);
+            any_overflow |= overflow;
+            lanes[g][lane] = result;
+        }
+    }
+    for (g, n) in grouping_chunks.remainder().iter().zip(num_chunks.remainder()) {
+        let g = g.cast_usize();
+        let (result, overflow) = A::accumulate_checked(lanes[g][0], *n);
+        any_overflow |= overflow;
+        lanes[g][0] = result;
+    }
+    for (accumulator, lanes) in accumulators.iter_mut().zip(lanes) {
+        for lane in lanes {
+            let (result, overflow) = A::combine_checked(*accumulator, lane);
+            any_overflow |= overflow;
+            *accumulator = result;
+        }
+    }
+    any_overflow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scatter_aggregate() {
+        let grouping = (0..1000u32).map(|i| (i * i % 7) as u8).collect::<Vec<_>>();
+        let nums = (0..1000i64).collect::<Vec<_>>();
+        let mut expected = vec![0i64; 7];
+        for (g, n) in grouping.iter().zip(&nums) {
+            expected[*g as usize] += n;
+        }
+
+        let mut sums = vec![0i64; 7];
+        scatter_aggregate::<i64, u8, i64, SumI64>(&grouping, &nums, &mut sums);
+        assert_eq!(sums, expected);
+
+        let mut checked_sums = vec![0i64; 7];
+        assert!(!scatter_aggregate_checked::<i64, u8, i64, SumI64>(&grouping, &nums, &mut checked_sums));
+        assert_eq!(checked_sums, expected);
+
+        let mut counts = vec![0u32; 7];
+        scatter_aggregate::<i64, u8, u32, Count>(&grouping, &nums, &mut counts);
+        assert_eq!(counts.iter().sum::<u32>(), 1000);
+    }
+
+    #[test]
+    fn test_scatter_aggregate_overflow() {
+        let grouping = vec![0u8; 16];
+        let nums = vec![i64::MAX / 8; 16];
+        let mut sums = vec![0i64];
+        assert!(scatter_aggregate_checked::<i64, u8, i64, SumI64>(&grouping, &nums, &mut sums));
+    }
+}
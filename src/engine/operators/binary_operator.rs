@@ -37,6 +37,13 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for BinaryOperator<LHS, RHS, Out, Op
     fn can_block_output(&self) -> bool { true }
     fn allocates(&self) -> bool { true }
 
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        // Unchecked arithmetic is only fused as part of checked operators
+        JitNode::new(Op::jit_op(), JitInput::vec(self.lhs), JitInput::vec(self.rhs), self.output)
+            .filter(|node| node.op.is_comparison())
+    }
+
     fn display_op(&self, _: bool) -> String {
         format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
     }
@@ -77,6 +84,13 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for BinaryVSOperator<LHS, RHS, Out,
     fn can_block_output(&self) -> bool { true }
     fn allocates(&self) -> bool { true }
 
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        // Unchecked arithmetic is only fused as part of checked operators
+        JitNode::new(Op::jit_op(), JitInput::vec(self.lhs), JitInput::scalar(self.rhs), self.output)
+            .filter(|node| node.op.is_comparison())
+    }
+
     fn display_op(&self, _: bool) -> String {
         format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
     }
@@ -117,6 +131,13 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for BinarySVOperator<LHS, RHS, Out,
     fn can_block_output(&self) -> bool { true }
     fn allocates(&self) -> bool { true }
 
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        // Unchecked arithmetic is only fused as part of checked operators
+        JitNode::new(Op::jit_op(), JitInput::scalar(self.lhs), JitInput::vec(self.rhs), self.output)
+            .filter(|node| node.op.is_comparison())
+    }
+
     fn display_op(&self, _: bool) -> String {
         format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
     }
@@ -161,6 +182,11 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for CheckedBinaryOperator<LHS, RHS,
     fn can_block_output(&self) -> bool { true }
     fn allocates(&self) -> bool { true }
 
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        JitNode::new(Op::jit_op(), JitInput::vec(self.lhs), JitInput::vec(self.rhs), self.output)
+    }
+
     fn display_op(&self, _: bool) -> String {
         format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
     }
@@ -204,6 +230,11 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for CheckedBinaryVSOperator<LHS, RHS
     fn can_block_output(&self) -> bool { true }
     fn allocates(&self) -> bool { true }
 
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        JitNode::new(Op::jit_op(), JitInput::vec(self.lhs), JitInput::scalar(self.rhs), self.output)
+    }
+
     fn display_op(&self, _: bool) -> String {
         format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
     }
@@ -247,6 +278,11 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for CheckedBinarySVOperator<LHS, RHS
     fn can_block_output(&self) -> bool { true }
     fn allocates(&self) -> bool { true }
 
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        JitNode::new(Op::jit_op(), JitInput::scalar(self.lhs), JitInput::vec(self.rhs), self.output)
+    }
+
     fn display_op(&self, _: bool) -> String {
         format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
     }
@@ -395,6 +431,8 @@ impl<'a, LHS, RHS, Out, Op> VecOperator<'a> for NullableCheckedBinarySVOperator<
 pub trait BinaryOp<LHS, RHS, Out> {
     fn perform(lhs: LHS, rhs: RHS) -> Out;
     fn symbol() -> &'static str;
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { None }
 }
 
 pub trait CheckedBinaryOp<LHS, RHS, Out>: BinaryOp<LHS, RHS, Out> {
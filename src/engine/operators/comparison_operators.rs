@@ -1,4 +1,6 @@
 use super::binary_operator::*;
+#[cfg(feature = "jit")]
+use super::jit::JitOp;
 
 use num::PrimInt;
 
@@ -21,6 +23,8 @@ impl<T, U, V> BinaryOp<T, U, u8> for LessThan
         (t < u) as u8
     }
     fn symbol() -> &'static str { "<" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::LessThan) }
 }
 
 impl<'a> BinaryOp<&'a str, &'a str, u8> for LessThan {
@@ -37,6 +41,8 @@ impl<T, U, V> BinaryOp<T, U, u8> for LessThanEquals
         (t <= u) as u8
     }
     fn symbol() -> &'static str { "<=" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::LessThanEquals) }
 }
 
 impl<'a> BinaryOp<&'a str, &'a str, u8> for LessThanEquals {
@@ -52,6 +58,8 @@ impl<T, U, V> BinaryOp<T, U, u8> for Equals
         (t == u) as u8
     }
     fn symbol() -> &'static str { "=" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::Equals) }
 }
 
 impl<'a> BinaryOp<&'a str, &'a str, u8> for Equals {
@@ -68,6 +76,8 @@ impl<T, U, V> BinaryOp<T, U, u8> for NotEquals
         (t != u) as u8
     }
     fn symbol() -> &'static str { "<>" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::NotEquals) }
 }
 
 impl<'a> BinaryOp<&'a str, &'a str, u8> for NotEquals {
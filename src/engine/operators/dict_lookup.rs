@@ -1,4 +1,5 @@
 use crate::engine::*;
+use crate::stringpack::lookup_indexed;
 use std::str;
 
 #[derive(Debug)]
@@ -17,11 +18,8 @@ impl<'a, T: GenericIntVec<T>> VecOperator<'a> for DictLookup<'a, T> {
         let mut output = scratchpad.get_mut(self.output);
         if stream { output.clear(); }
         for i in indices.iter() {
-            let offset_len = dict_indices[i.cast_usize()];
-            let offset = (offset_len >> 24) as usize;
-            let len = (offset_len & 0x00ff_ffff) as usize;
             let string = unsafe {
-                str::from_utf8_unchecked(&dict_data[offset..(offset + len)])
+                str::from_utf8_unchecked(lookup_indexed(dict_indices[i.cast_usize()], dict_data))
             };
             output.push(string);
         }
@@ -61,10 +59,8 @@ impl<'a> VecOperator<'a> for InverseDictLookup<'a> {
             let constant = constant.as_bytes();
             let dict_indices = scratchpad.get(self.dict_indices);
             let dict_data = scratchpad.get(self.dict_data);
-            for (i, offset_len) in dict_indices.iter().enumerate() {
-                let offset = (offset_len >> 24) as usize;
-                let len = (offset_len & 0x00ff_ffff) as usize;
-                if &dict_data[offset..(offset + len)] == constant {
+            for (i, &offset_len) in dict_indices.iter().enumerate() {
+                if lookup_indexed(offset_len, &dict_data) == constant {
                     result = i as i64;
                     break;
                 }
@@ -86,3 +82,122 @@ impl<'a> VecOperator<'a> for InverseDictLookup<'a> {
         format!("inverse_dict_lookup({}, {}, {})", self.dict_indices, self.dict_data, self.constant)
     }
 }
+
+/// Determines the position of a string constant in a sorted dictionary, which allows range comparisons with the
+/// constant to be evaluated on dictionary indices.
+#[derive(Debug)]
+pub struct DictBound<'a> {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub constant: BufferRef<Scalar<&'a str>>,
+    pub upper: bool,
+    pub output: BufferRef<Scalar<i64>>,
+}
+
+impl<'a> VecOperator<'a> for DictBound<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let result = {
+            let constant = scratchpad.get_scalar(&self.constant);
+            let constant = constant.as_bytes();
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let dict_data = scratchpad.get(self.dict_data);
+            let upper = self.upper;
+            let count = dict_indices.partition_point(|&offset_len| {
+                let entry = lookup_indexed(offset_len, &dict_data);
+                if upper { entry <= constant } else { entry < constant }
+            });
+            if upper { count as i64 - 1 } else { count as i64 }
+        };
+        scratchpad.set_const(self.output, result);
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.constant.any(), self.dict_indices.any(), self.dict_data.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.constant.i, &mut self.dict_indices.i, &mut self.dict_data.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { false }
+
+    fn display_op(&self, _: bool) -> String {
+        let bound = if self.upper { "upper_bound" } else { "lower_bound" };
+        format!("{}({}, {}, {})", bound, self.dict_indices, self.dict_data, self.constant)
+    }
+}
+
+/// Evaluates `regex` once for each entry of a dictionary, rather than for every row.
+#[derive(Debug)]
+pub struct DictRegex {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub regex: regex::Regex,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for DictRegex {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let matches = {
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let dict_data = scratchpad.get(self.dict_data);
+            dict_indices
+                .iter()
+                .map(|&offset_len| {
+                    let string = unsafe {
+                        str::from_utf8_unchecked(lookup_indexed(offset_len, &dict_data))
+                    };
+                    self.regex.is_match(string) as u8
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, matches);
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.dict_indices.any(), self.dict_data.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.dict_indices.i, &mut self.dict_data.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] =~ /{}/", self.dict_data, self.dict_indices, self.regex)
+    }
+}
+
+/// Looks up the value of each dictionary index in `members`, which holds one value for each dictionary entry.
+#[derive(Debug)]
+pub struct DictMembership<T> {
+    pub indices: BufferRef<T>,
+    pub members: BufferRef<u8>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for DictMembership<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let indices = scratchpad.get(self.indices);
+        let members = scratchpad.get(self.members);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        for i in indices.iter() {
+            output.push(members[i.cast_usize()]);
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.indices.any(), self.members.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.indices.i, &mut self.members.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, buffer: usize) -> bool { buffer == self.indices.i }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn can_block_output(&self) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}]", self.members, self.indices)
+    }
+}
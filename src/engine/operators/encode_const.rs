@@ -15,7 +15,10 @@ impl<'a> VecOperator<'a> for EncodeIntConstant {
 
     fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
         let constant = scratchpad.get_scalar(&self.constant);
-        let result = self.codec.encode_int(constant);
+        let result = self
+            .codec
+            .encode_int(constant)
+            .unwrap_or_else(|| panic!("encode_int not supported for {:?}", self.codec.ops()));
         scratchpad.set_any(self.output.any(), scalar_i64_data(result));
     }
 
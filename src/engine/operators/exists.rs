@@ -8,7 +8,7 @@ pub struct Exists<T> {
     pub output: BufferRef<u8>,
 }
 
-impl<'a, T: GenericIntVec<T> + CastUsize> VecOperator<'a> for Exists<T> {
+impl<'a, T: VecData<T> + CastUsize + 'static> VecOperator<'a> for Exists<T> {
     fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError>{
         let data = scratchpad.get(self.input);
         let mut exists = scratchpad.get_mut(self.output);
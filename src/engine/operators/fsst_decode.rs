@@ -0,0 +1,72 @@
+use crate::engine::*;
+use crate::mem_store::fsst;
+use crate::stringpack::*;
+use std::fmt;
+use std::mem;
+use std::str;
+
+pub struct FsstDecode<'a> {
+    pub codes: BufferRef<u8>,
+    pub symbols: BufferRef<u8>,
+    pub decoded: BufferRef<&'a str>,
+    pub stringstore: BufferRef<u8>,
+    pub iterator: Option<PackedBytesIterator<'a>>,
+    pub symbol_table: Vec<&'a [u8]>,
+    // Initializing this properly is required for safety
+    pub total_bytes: usize,
+    pub has_more: bool,
+}
+
+impl<'a> VecOperator<'a> for FsstDecode<'a> {
+    fn execute(&mut self, streaming: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        unsafe { scratchpad.unpin(self.stringstore.any()) };
+        {
+            let mut decoded = scratchpad.get_mut(self.decoded);
+            let mut stringstore = scratchpad.get_mut(self.stringstore);
+            if streaming { decoded.clear() }
+            for codes in self.iterator.as_mut().unwrap() {
+                let start = stringstore.len();
+                fsst::decode(&self.symbol_table, codes, &mut stringstore);
+                // unsafe if this were false, total_bytes is exact for well formed data
+                assert!(stringstore.len() <= self.total_bytes);
+                decoded.push(unsafe {
+                    mem::transmute::<_, &'a str>(str::from_utf8_unchecked(&stringstore[start..]))
+                });
+                if decoded.capacity() == decoded.len() { break; }
+            }
+        }
+        scratchpad.pin(&self.stringstore.any());
+        self.has_more = self.iterator.as_ref().unwrap().has_more();
+        Ok(())
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.decoded, Vec::with_capacity(batch_size));
+        // Initializing with sufficient capacity is required for safety - this vector must never get reallocated
+        scratchpad.set(self.stringstore, Vec::with_capacity(self.total_bytes));
+        let codes = scratchpad.get_pinned(self.codes);
+        self.iterator = Some(PackedBytesIterator::from_slice(codes));
+        let symbols = scratchpad.get_pinned(self.symbols);
+        self.symbol_table = fsst::symbols(symbols);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.codes.any(), self.symbols.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.codes.i, &mut self.symbols.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.decoded.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn can_block_output(&self) -> bool { true }
+    fn allocates(&self) -> bool { true }
+    fn is_streaming_producer(&self) -> bool { true }
+    fn has_more(&self) -> bool { self.has_more }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("fsst_decode({}, {})", self.codes, self.symbols)
+    }
+}
+
+impl<'a> fmt::Debug for FsstDecode<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FsstDecode {{ codes: {}, symbols: {}, decoded: {} }}", self.codes, self.symbols, self.decoded)
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::engine::*;
+
+/// Executes a tree of streaming operators as a single operator. The intermediate buffers of the tree are internal to
+/// the composite operator, each of them is produced and consumed by exactly one member and always streamed.
+pub struct Fused<'a> {
+    /// Members in execution order, the last member produces the outputs of the tree
+    ops: Vec<BoxedOperator<'a>>,
+    internal: HashSet<usize>,
+}
+
+impl<'a> Fused<'a> {
+    fn members_with_input(&self, i: usize) -> impl Iterator<Item = &BoxedOperator<'a>> {
+        self.ops
+            .iter()
+            .filter(move |op| op.inputs().iter().any(|input| input.i == i))
+    }
+
+    fn member_with_output(&self, i: usize) -> Option<&BoxedOperator<'a>> {
+        self.ops
+            .iter()
+            .find(|op| op.outputs().iter().any(|output| output.i == i))
+    }
+}
+
+impl<'a> VecOperator<'a> for Fused<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let (root, members) = self.ops.split_last_mut().unwrap();
+        for op in members {
+            op.execute(true, scratchpad)?;
+        }
+        root.execute(stream, scratchpad)
+    }
+
+    fn init(&mut self, total_count: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        for op in &mut self.ops {
+            op.init(total_count, batch_size, scratchpad);
+        }
+    }
+
+    fn finalize(&mut self, scratchpad: &mut Scratchpad<'a>) {
+        for op in &mut self.ops {
+            op.finalize(scratchpad);
+        }
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        self.ops
+            .iter()
+            .flat_map(|op| op.inputs())
+            .filter(|input| !self.internal.contains(&input.i))
+            .collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> {
+        let internal = &self.internal;
+        self.ops
+            .iter_mut()
+            .flat_map(|op| op.inputs_mut())
+            .filter(|input| !internal.contains(&**input))
+            .collect()
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        self.ops
+            .iter()
+            .flat_map(|op| op.outputs())
+            .filter(|output| !self.internal.contains(&output.i))
+            .collect()
+    }
+    fn can_stream_input(&self, i: usize) -> bool {
+        // Inputs consumed by several members are only streamed if all of them can stream it
+        self.members_with_input(i).all(|op| op.can_stream_input(i))
+    }
+    fn can_stream_output(&self, i: usize) -> bool {
+        self.member_with_output(i)
+            .map_or(false, |op| op.can_stream_output(i))
+    }
+    fn can_block_output(&self) -> bool {
+        self.ops.last().unwrap().can_block_output()
+    }
+    fn mutates(&self, i: usize) -> bool {
+        self.ops.iter().any(|op| op.mutates(i))
+    }
+    fn allocates(&self) -> bool {
+        self.ops.iter().any(|op| op.allocates())
+    }
+    fn is_streaming_producer(&self) -> bool {
+        self.ops.iter().any(|op| op.is_streaming_producer())
+    }
+    fn has_more(&self) -> bool {
+        self.ops.iter().any(|op| op.has_more())
+    }
+    fn custom_output_len(&self) -> Option<usize> {
+        self.ops.last().unwrap().custom_output_len()
+    }
+
+    fn display_output(&self) -> bool {
+        false
+    }
+    fn display_op(&self, alternate: bool) -> String {
+        let members = self
+            .ops
+            .iter()
+            .map(|op| op.display(alternate))
+            .collect::<Vec<_>>();
+        format!("fused {{\n  {}\n}}", members.join("\n  "))
+    }
+}
+
+/// Combines operators connected by a streamed buffer that is not consumed by any other operator into `Fused`
+/// operators, so that the executor dispatches and stages each tree of such operators as a unit.
+pub fn fuse_streaming_operators<'a>(ops: Vec<BoxedOperator<'a>>) -> Vec<BoxedOperator<'a>> {
+    let mut consumers = HashMap::<usize, Vec<usize>>::default();
+    for (i, op) in ops.iter().enumerate() {
+        for input in op.inputs() {
+            consumers.entry(input.i).or_default().push(i);
+        }
+    }
+
+    // The consumer each operator is fused into
+    let mut successor = HashMap::<usize, usize>::default();
+    for (i, op) in ops.iter().enumerate() {
+        let outputs = op.outputs();
+        if outputs.len() != 1 {
+            continue;
+        }
+        let output = outputs[0].i;
+        if !op.can_stream_output(output)
+            || op
+                .inputs()
+                .iter()
+                .any(|input| input.i == output || op.mutates(input.i))
+        {
+            continue;
+        }
+        let consumer = match consumers.get(&output) {
+            Some(consumers) if consumers.iter().all(|&c| c == consumers[0]) => consumers[0],
+            _ => continue,
+        };
+        if consumer != i && ops[consumer].can_stream_input(output) && !ops[consumer].mutates(output)
+        {
+            successor.insert(i, consumer);
+        }
+    }
+    if successor.is_empty() {
+        return ops;
+    }
+
+    let mut predecessors = HashMap::<usize, Vec<usize>>::default();
+    for (&op, &consumer) in &successor {
+        predecessors.entry(consumer).or_default().push(op);
+    }
+    for predecessors in predecessors.values_mut() {
+        predecessors.sort_unstable();
+    }
+    let mut trees = HashMap::new();
+    for &root in predecessors.keys() {
+        if successor.contains_key(&root) {
+            continue;
+        }
+        let mut members = Vec::new();
+        add_members(root, &predecessors, &mut members);
+        trees.insert(root, members);
+    }
+
+    let mut ops = ops.into_iter().map(Some).collect::<Vec<_>>();
+    let mut fused = HashMap::new();
+    for (root, members) in trees {
+        let internal = members
+            .iter()
+            .filter(|&&member| member != root)
+            .map(|&member| ops[member].as_ref().unwrap().outputs()[0].i)
+            .collect();
+        let members = members
+            .into_iter()
+            .map(|member| ops[member].take().unwrap())
+            .collect();
+        fused.insert(
+            root,
+            Box::new(Fused {
+                ops: members,
+                internal,
+            }) as BoxedOperator<'a>,
+        );
+    }
+    ops.into_iter()
+        .enumerate()
+        .filter_map(|(i, op)| fused.remove(&i).or(op))
+        .collect()
+}
+
+/// Appends the members of the tree rooted at `op` in execution order.
+fn add_members(op: usize, predecessors: &HashMap<usize, Vec<usize>>, members: &mut Vec<usize>) {
+    for &predecessor in predecessors.get(&op).into_iter().flatten() {
+        add_members(predecessor, predecessors, members);
+    }
+    members.push(op);
+}
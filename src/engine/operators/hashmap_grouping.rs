@@ -3,6 +3,7 @@ use fnv::FnvHashMap;
 use crate::engine::*;
 use crate::ingest::raw_val::RawVal;
 use std::hash::Hash;
+use std::mem;
 
 #[derive(Debug)]
 pub struct HashMapGrouping<T: VecData<T> + Hash> {
@@ -33,21 +34,25 @@ impl<'a, T: VecData<T> + Hash + 'a> HashMapGrouping<T> {
 
 impl<'a, T: VecData<T> + Hash + 'a> VecOperator<'a> for HashMapGrouping<T> {
     fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
-        let count = {
+        let (count, new_groups) = {
             let raw_grouping_key = scratchpad.get(self.input);
             let mut grouping = scratchpad.get_mut(self.grouping_key_out);
             let mut unique = scratchpad.get_mut(self.unique_out);
             if stream {
                 grouping.clear()
             }
+            let groups = unique.len();
             for i in raw_grouping_key.iter() {
                 grouping.push(*self.map.entry(*i).or_insert_with(|| {
                     unique.push(*i);
                     unique.len() as u32 - 1
                 }));
             }
-            RawVal::Int(unique.len() as i64)
+            (RawVal::Int(unique.len() as i64), unique.len() - groups)
         };
+        // Each group is stored in the map and in the unique values
+        scratchpad
+            .reserve_memory(new_groups * (2 * mem::size_of::<T>() + mem::size_of::<u32>()))?;
         scratchpad.set_any(self.cardinality_out.any(), constant_data(count));
         Ok(())
     }
@@ -1,3 +1,5 @@
+use std::mem;
+
 use fnv::FnvHashMap;
 
 use crate::engine::*;
@@ -33,7 +35,7 @@ impl<'a> HashMapGroupingByteSlices {
 impl<'a> VecOperator<'a> for HashMapGroupingByteSlices {
     fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
         // TODO(#100): Fnv is suboptimal for larger inputs (http://cglab.ca/~abeinges/blah/hash-rs/). use xx hash?
-        let count = {
+        let (count, new_groups) = {
             let raw_grouping_key_any = scratchpad.get_any(self.input);
             let raw_grouping_key = raw_grouping_key_any.cast_ref_byte_slices();
             let mut map: FnvHashMap<&[&'a [u8]], u32> = FnvHashMap::default();
@@ -43,6 +45,7 @@ impl<'a> VecOperator<'a> for HashMapGroupingByteSlices {
             if stream {
                 grouping.clear()
             }
+            let groups = unique.len();
             for row in raw_grouping_key.data.chunks(raw_grouping_key.row_len) {
                 grouping.push(*map.entry(row).or_insert_with(|| {
                     for slice in row {
@@ -51,8 +54,12 @@ impl<'a> VecOperator<'a> for HashMapGroupingByteSlices {
                     unique.len() as u32 - 1
                 }));
             }
-            RawVal::Int(unique.len() as i64)
+            (RawVal::Int(unique.len() as i64), unique.len() - groups)
         };
+        // Each group is stored in the map and in the unique values
+        scratchpad.reserve_memory(
+            new_groups * (2 * self.columns * mem::size_of::<&[u8]>() + mem::size_of::<u32>()),
+        )?;
         scratchpad.set_any(self.cardinality_out.any(), constant_data(count));
         Ok(())
     }
@@ -1,3 +1,5 @@
+use std::mem;
+
 use fnv::FnvHashMap;
 
 use crate::engine::*;
@@ -34,7 +36,7 @@ impl<'a> HashMapGroupingValRows<'a> {
 impl<'a> VecOperator<'a> for HashMapGroupingValRows<'a> {
     fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
         // TODO(#100): Fnv is suboptimal for larger inputs (http://cglab.ca/~abeinges/blah/hash-rs/). use xx hash?
-        let count = {
+        let (count, new_groups) = {
             let raw_grouping_key = scratchpad.get_mut_val_rows(self.input);
             let mut map: FnvHashMap<&[Val<'a>], u32> = FnvHashMap::default();
             let mut grouping = scratchpad.get_mut(self.grouping_key_out);
@@ -42,6 +44,7 @@ impl<'a> VecOperator<'a> for HashMapGroupingValRows<'a> {
             if stream {
                 grouping.clear()
             }
+            let groups = unique.len();
             for row in raw_grouping_key.data.chunks(raw_grouping_key.row_len) {
                 grouping.push(*map.entry(row).or_insert_with(|| {
                     for slice in row {
@@ -50,8 +53,12 @@ impl<'a> VecOperator<'a> for HashMapGroupingValRows<'a> {
                     unique.len() as u32 - 1
                 }));
             }
-            RawVal::Int(unique.len() as i64)
+            (RawVal::Int(unique.len() as i64), unique.len() - groups)
         };
+        // Each group is stored in the map and in the unique values
+        scratchpad.reserve_memory(
+            new_groups * (2 * self.columns * mem::size_of::<Val>() + mem::size_of::<u32>()),
+        )?;
         scratchpad.set_any(self.cardinality_out.any(), constant_data(count));
         Ok(())
     }
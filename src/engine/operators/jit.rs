@@ -0,0 +1,700 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use crate::engine::*;
+
+/// Binary operators that can be compiled into fused kernels.
+/// Arithmetic operators are checked and make the kernel report an overflow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JitOp {
+    Add,
+    Subtract,
+    Multiply,
+    LessThan,
+    LessThanEquals,
+    Equals,
+    NotEquals,
+}
+
+impl JitOp {
+    pub fn is_comparison(self) -> bool {
+        !matches!(self, JitOp::Add | JitOp::Subtract | JitOp::Multiply)
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            JitOp::Add => "+",
+            JitOp::Subtract => "-",
+            JitOp::Multiply => "*",
+            JitOp::LessThan => "<",
+            JitOp::LessThanEquals => "<=",
+            JitOp::Equals => "=",
+            JitOp::NotEquals => "<>",
+        }
+    }
+}
+
+/// Element type of the vectors read and written by fused kernels. All values are widened to i64 inside the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JitType {
+    U8,
+    U16,
+    U32,
+    I64,
+}
+
+impl JitType {
+    pub fn of(t: EncodingType) -> Option<JitType> {
+        match t {
+            EncodingType::U8 => Some(JitType::U8),
+            EncodingType::U16 => Some(JitType::U16),
+            EncodingType::U32 => Some(JitType::U32),
+            EncodingType::I64 => Some(JitType::I64),
+            _ => None,
+        }
+    }
+
+    fn shift(self) -> i64 {
+        match self {
+            JitType::U8 => 0,
+            JitType::U16 => 1,
+            JitType::U32 => 2,
+            JitType::I64 => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum JitInput {
+    Vec(BufferRef<Any>, JitType),
+    Scalar(BufferRef<Scalar<i64>>),
+}
+
+impl JitInput {
+    pub fn vec<T: VecData<T>>(buffer: BufferRef<T>) -> Option<JitInput> {
+        JitType::of(T::t()).map(|t| JitInput::Vec(buffer.any(), t))
+    }
+
+    pub fn scalar<T: ScalarData<T>>(buffer: BufferRef<Scalar<T>>) -> Option<JitInput> {
+        if T::t() == EncodingType::ScalarI64 {
+            Some(JitInput::Scalar(buffer.any().scalar_i64()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Describes an operator that can be compiled into a fused kernel, see `VecOperator::jit_node`.
+#[derive(Clone, Copy, Debug)]
+pub struct JitNode {
+    pub op: JitOp,
+    pub lhs: JitInput,
+    pub rhs: JitInput,
+    pub output: BufferRef<Any>,
+    pub output_type: JitType,
+}
+
+impl JitNode {
+    pub fn new<Out: VecData<Out>>(
+        op: Option<JitOp>,
+        lhs: Option<JitInput>,
+        rhs: Option<JitInput>,
+        output: BufferRef<Out>,
+    ) -> Option<JitNode> {
+        let output_type = match JitType::of(Out::t())? {
+            output_type @ (JitType::U8 | JitType::I64) => output_type,
+            _ => return None,
+        };
+        Some(JitNode {
+            op: op?,
+            lhs: lhs?,
+            rhs: rhs?,
+            output: output.any(),
+            output_type,
+        })
+    }
+}
+
+/// Expression computed by a fused kernel.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum JitExpr {
+    /// Element of the input vector with the given index.
+    Input(usize, JitType),
+    /// Scalar with the given index.
+    Scalar(usize),
+    Binary(JitOp, Box<JitExpr>, Box<JitExpr>),
+}
+
+type KernelFn = unsafe extern "C" fn(*const *const u8, *const i64, *mut u8, u64) -> u8;
+
+/// Machine code of a fused kernel. Returns whether any operation overflowed.
+pub struct Kernel {
+    function: KernelFn,
+    // Owns the memory of `function`
+    _module: JITModule,
+}
+
+// The compiled code is immutable and does not reference any thread-local state.
+unsafe impl Send for Kernel {}
+unsafe impl Sync for Kernel {}
+
+lazy_static! {
+    /// Compiled kernels by expression, which is identical for all partitions of a query.
+    static ref KERNELS: Mutex<HashMap<JitExpr, Arc<Kernel>>> = Mutex::default();
+}
+
+impl Kernel {
+    pub fn get(expr: &JitExpr, output_type: JitType) -> Result<Arc<Kernel>, String> {
+        if let Some(kernel) = KERNELS.lock().unwrap().get(expr) {
+            return Ok(kernel.clone());
+        }
+        let kernel = Arc::new(Kernel::compile(expr, output_type)?);
+        KERNELS.lock().unwrap().insert(expr.clone(), kernel.clone());
+        Ok(kernel)
+    }
+
+    fn compile(expr: &JitExpr, output_type: JitType) -> Result<Kernel, String> {
+        let mut flag_builder = settings::builder();
+        flag_builder
+            .set("opt_level", "speed")
+            .map_err(|err| err.to_string())?;
+        let isa = cranelift_native::builder()
+            .map_err(|err| err.to_string())?
+            .finish(settings::Flags::new(flag_builder))
+            .map_err(|err| err.to_string())?;
+        let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+        let pointer = module.target_config().pointer_type();
+        let mut ctx = module.make_context();
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+        ctx.func.signature.params.push(AbiParam::new(types::I64));
+        ctx.func.signature.returns.push(AbiParam::new(types::I8));
+
+        let mut function_builder_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut function_builder_ctx);
+        let flags = MemFlags::trusted();
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let params = builder.block_params(entry).to_vec();
+        let (inputs, scalars, output, len) = (params[0], params[1], params[2], params[3]);
+        let input_count = expr.max_input().map_or(0, |i| i + 1);
+        let inputs = (0..input_count)
+            .map(|i| builder.ins().load(pointer, flags, inputs, (i * 8) as i32))
+            .collect::<Vec<_>>();
+        let scalar_count = expr.max_scalar().map_or(0, |i| i + 1);
+        let scalars = (0..scalar_count)
+            .map(|i| {
+                builder
+                    .ins()
+                    .load(types::I64, flags, scalars, (i * 8) as i32)
+            })
+            .collect::<Vec<_>>();
+
+        // for (index = 0; index < len; index++) { output[index] = expr }
+        let header = builder.create_block();
+        builder.append_block_param(header, types::I64);
+        builder.append_block_param(header, types::I8);
+        let body = builder.create_block();
+        let exit = builder.create_block();
+        builder.append_block_param(exit, types::I8);
+        let zero = builder.ins().iconst(types::I64, 0);
+        let no_overflow = builder.ins().iconst(types::I8, 0);
+        builder.ins().jump(header, &[zero, no_overflow]);
+
+        builder.switch_to_block(header);
+        let index = builder.block_params(header)[0];
+        let overflow = builder.block_params(header)[1];
+        let done = builder
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThanOrEqual, index, len);
+        builder.ins().brif(done, exit, &[overflow], body, &[]);
+
+        builder.switch_to_block(body);
+        builder.seal_block(body);
+        let mut codegen = Codegen {
+            builder,
+            inputs,
+            scalars,
+            index,
+            overflow,
+        };
+        let result = codegen.emit(expr);
+        let Codegen {
+            mut builder,
+            overflow,
+            ..
+        } = codegen;
+        let offset = builder.ins().ishl_imm(index, output_type.shift());
+        let address = builder.ins().iadd(output, offset);
+        // Comparisons produce an i8 and are stored as u8, arithmetic results are stored as i64
+        builder.ins().store(flags, result, address, 0);
+        let next = builder.ins().iadd_imm(index, 1);
+        builder.ins().jump(header, &[next, overflow]);
+        builder.seal_block(header);
+
+        builder.switch_to_block(exit);
+        builder.seal_block(exit);
+        let overflow = builder.block_params(exit)[0];
+        builder.ins().return_(&[overflow]);
+        builder.finalize();
+
+        let id = module
+            .declare_function("kernel", Linkage::Export, &ctx.func.signature)
+            .map_err(|err| err.to_string())?;
+        module
+            .define_function(id, &mut ctx)
+            .map_err(|err| err.to_string())?;
+        module.clear_context(&mut ctx);
+        module
+            .finalize_definitions()
+            .map_err(|err| err.to_string())?;
+        let function =
+            unsafe { mem::transmute::<*const u8, KernelFn>(module.get_finished_function(id)) };
+        Ok(Kernel {
+            function,
+            _module: module,
+        })
+    }
+}
+
+struct Codegen<'b> {
+    builder: FunctionBuilder<'b>,
+    /// Base pointers of the input vectors
+    inputs: Vec<Value>,
+    scalars: Vec<Value>,
+    index: Value,
+    overflow: Value,
+}
+
+impl<'b> Codegen<'b> {
+    /// Emits instructions computing `expr` for the current row. Comparisons produce an i8, everything else an i64.
+    fn emit(&mut self, expr: &JitExpr) -> Value {
+        let flags = MemFlags::trusted();
+        match *expr {
+            JitExpr::Input(i, t) => {
+                let offset = self.builder.ins().ishl_imm(self.index, t.shift());
+                let address = self.builder.ins().iadd(self.inputs[i], offset);
+                match t {
+                    JitType::U8 => self.builder.ins().uload8(types::I64, flags, address, 0),
+                    JitType::U16 => self.builder.ins().uload16(types::I64, flags, address, 0),
+                    JitType::U32 => self.builder.ins().uload32(flags, address, 0),
+                    JitType::I64 => self.builder.ins().load(types::I64, flags, address, 0),
+                }
+            }
+            JitExpr::Scalar(i) => self.scalars[i],
+            JitExpr::Binary(op, ref lhs, ref rhs) => {
+                let lhs = self.emit_i64(lhs);
+                let rhs = self.emit_i64(rhs);
+                let (result, overflow) = match op {
+                    JitOp::Add => {
+                        let sum = self.builder.ins().iadd(lhs, rhs);
+                        // Overflow iff both operands have a different sign than the result
+                        let lhs_sign = self.builder.ins().bxor(lhs, sum);
+                        let rhs_sign = self.builder.ins().bxor(rhs, sum);
+                        let signs = self.builder.ins().band(lhs_sign, rhs_sign);
+                        let overflow = self.builder.ins().icmp_imm(IntCC::SignedLessThan, signs, 0);
+                        (sum, Some(overflow))
+                    }
+                    JitOp::Subtract => {
+                        let difference = self.builder.ins().isub(lhs, rhs);
+                        // Overflow iff the operands have different signs and the result has the sign of `rhs`
+                        let operand_signs = self.builder.ins().bxor(lhs, rhs);
+                        let result_sign = self.builder.ins().bxor(lhs, difference);
+                        let signs = self.builder.ins().band(operand_signs, result_sign);
+                        let overflow = self.builder.ins().icmp_imm(IntCC::SignedLessThan, signs, 0);
+                        (difference, Some(overflow))
+                    }
+                    JitOp::Multiply => {
+                        let product = self.builder.ins().imul(lhs, rhs);
+                        // Overflow iff the high half is not the sign extension of the low half
+                        let high = self.builder.ins().smulhi(lhs, rhs);
+                        let sign = self.builder.ins().sshr_imm(product, 63);
+                        let overflow = self.builder.ins().icmp(IntCC::NotEqual, high, sign);
+                        (product, Some(overflow))
+                    }
+                    comparison => {
+                        let cc = match comparison {
+                            JitOp::LessThan => IntCC::SignedLessThan,
+                            JitOp::LessThanEquals => IntCC::SignedLessThanOrEqual,
+                            JitOp::Equals => IntCC::Equal,
+                            _ => IntCC::NotEqual,
+                        };
+                        (self.builder.ins().icmp(cc, lhs, rhs), None)
+                    }
+                };
+                if let Some(overflow) = overflow {
+                    self.overflow = self.builder.ins().bor(self.overflow, overflow);
+                }
+                result
+            }
+        }
+    }
+
+    fn emit_i64(&mut self, expr: &JitExpr) -> Value {
+        let value = self.emit(expr);
+        if expr.output_type() == JitType::U8 {
+            self.builder.ins().uextend(types::I64, value)
+        } else {
+            value
+        }
+    }
+}
+
+impl JitExpr {
+    fn output_type(&self) -> JitType {
+        match *self {
+            JitExpr::Binary(op, _, _) if op.is_comparison() => JitType::U8,
+            _ => JitType::I64,
+        }
+    }
+
+    fn max_input(&self) -> Option<usize> {
+        match *self {
+            JitExpr::Input(i, _) => Some(i),
+            JitExpr::Scalar(_) => None,
+            JitExpr::Binary(_, ref lhs, ref rhs) => lhs.max_input().max(rhs.max_input()),
+        }
+    }
+
+    fn max_scalar(&self) -> Option<usize> {
+        match *self {
+            JitExpr::Input(..) => None,
+            JitExpr::Scalar(i) => Some(i),
+            JitExpr::Binary(_, ref lhs, ref rhs) => lhs.max_scalar().max(rhs.max_scalar()),
+        }
+    }
+}
+
+/// Operator that evaluates a fused kernel.
+pub struct JitKernel<Out> {
+    pub inputs: Vec<(BufferRef<Any>, JitType)>,
+    pub scalars: Vec<BufferRef<Scalar<i64>>>,
+    pub output: BufferRef<Out>,
+    pub kernel: Arc<Kernel>,
+    pub display: String,
+}
+
+impl<'a, Out: VecData<Out> + Default + 'a> VecOperator<'a> for JitKernel<Out> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|&(input, _)| scratchpad.get_any(input))
+            .collect::<Vec<_>>();
+        let len = inputs.iter().map(|input| input.len()).min().unwrap_or(0);
+        let pointers = inputs
+            .iter()
+            .zip(&self.inputs)
+            .map(|(input, &(_, t))| match t {
+                JitType::U8 => input.cast_ref_u8().as_ptr(),
+                JitType::U16 => input.cast_ref_u16().as_ptr() as *const u8,
+                JitType::U32 => input.cast_ref_u32().as_ptr() as *const u8,
+                JitType::I64 => input.cast_ref_i64().as_ptr() as *const u8,
+            })
+            .collect::<Vec<_>>();
+        let scalars = self
+            .scalars
+            .iter()
+            .map(|scalar| scratchpad.get_scalar(scalar))
+            .collect::<Vec<_>>();
+
+        let mut output = scratchpad.get_mut(self.output);
+        if stream {
+            output.clear();
+        }
+        let start = output.len();
+        output.resize(start + len, Out::default());
+        let overflow = unsafe {
+            (self.kernel.function)(
+                pointers.as_ptr(),
+                scalars.as_ptr(),
+                output[start..].as_mut_ptr() as *mut u8,
+                len as u64,
+            )
+        };
+        if overflow != 0 {
+            Err(QueryError::Overflow)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        self.inputs
+            .iter()
+            .map(|&(input, _)| input)
+            .chain(self.scalars.iter().map(|scalar| scalar.any()))
+            .collect()
+    }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> {
+        self.inputs
+            .iter_mut()
+            .map(|(input, _)| &mut input.i)
+            .chain(self.scalars.iter_mut().map(|scalar| &mut scalar.i))
+            .collect()
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        vec![self.output.any()]
+    }
+    fn can_stream_input(&self, _: usize) -> bool {
+        true
+    }
+    fn can_stream_output(&self, _: usize) -> bool {
+        true
+    }
+    fn can_block_output(&self) -> bool {
+        true
+    }
+    fn allocates(&self) -> bool {
+        true
+    }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("jit({})", self.display)
+    }
+}
+
+/// Replaces trees of arithmetic and comparison operators whose intermediate results are not used anywhere else with
+/// a single `JitKernel`, which avoids dispatching each operator and materializing intermediate buffers.
+pub fn fuse_expressions<'a>(ops: Vec<BoxedOperator<'a>>) -> Vec<BoxedOperator<'a>> {
+    let nodes = ops.iter().map(|op| op.jit_node()).collect::<Vec<_>>();
+    let mut consumers = HashMap::<usize, usize>::default();
+    for op in &ops {
+        for input in op.inputs() {
+            *consumers.entry(input.i).or_default() += 1;
+        }
+    }
+    let producers = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| node.map(|node| (node.output.i, i)))
+        .collect::<HashMap<_, _>>();
+    // Nodes that are fused into the node consuming their output
+    let mut inlined = HashSet::new();
+    for node in nodes.iter().flatten() {
+        for input in [node.lhs, node.rhs] {
+            if let JitInput::Vec(buffer, _) = input {
+                if let Some(&producer) = producers.get(&buffer.i) {
+                    if consumers[&buffer.i] == 1 {
+                        inlined.insert(producer);
+                    }
+                }
+            }
+        }
+    }
+    if inlined.is_empty() {
+        return ops;
+    }
+
+    let mut kernels = HashMap::new();
+    let mut fused = HashSet::new();
+    for (i, node) in nodes.iter().enumerate() {
+        let node = match node {
+            Some(node) if !inlined.contains(&i) => node,
+            _ => continue,
+        };
+        let mut builder = ExprBuilder::default();
+        let expr = builder.build(node, &nodes, &producers, &inlined);
+        if builder.fused.is_empty() || builder.inputs.is_empty() {
+            continue;
+        }
+        let kernel = match Kernel::get(&expr, node.output_type) {
+            Ok(kernel) => kernel,
+            Err(err) => {
+                warn!("Failed to compile {}: {}", builder.display, err);
+                continue;
+            }
+        };
+        let op: BoxedOperator<'a> = match node.output_type {
+            JitType::U8 => Box::new(JitKernel {
+                inputs: builder.inputs,
+                scalars: builder.scalars,
+                output: node.output.u8(),
+                kernel,
+                display: builder.display,
+            }),
+            _ => Box::new(JitKernel {
+                inputs: builder.inputs,
+                scalars: builder.scalars,
+                output: node.output.i64(),
+                kernel,
+                display: builder.display,
+            }),
+        };
+        fused.extend(builder.fused);
+        kernels.insert(i, op);
+    }
+
+    ops.into_iter()
+        .enumerate()
+        .filter(|(i, _)| !fused.contains(i))
+        .map(|(i, op)| kernels.remove(&i).unwrap_or(op))
+        .collect()
+}
+
+#[derive(Default)]
+struct ExprBuilder {
+    inputs: Vec<(BufferRef<Any>, JitType)>,
+    scalars: Vec<BufferRef<Scalar<i64>>>,
+    /// Indices of the operators fused into the expression
+    fused: Vec<usize>,
+    display: String,
+}
+
+impl ExprBuilder {
+    fn build(
+        &mut self,
+        node: &JitNode,
+        nodes: &[Option<JitNode>],
+        producers: &HashMap<usize, usize>,
+        inlined: &HashSet<usize>,
+    ) -> JitExpr {
+        let lhs = self.build_input(node.lhs, nodes, producers, inlined);
+        let lhs_display = mem::take(&mut self.display);
+        let rhs = self.build_input(node.rhs, nodes, producers, inlined);
+        self.display = format!("({} {} {})", lhs_display, node.op.symbol(), self.display);
+        JitExpr::Binary(node.op, Box::new(lhs), Box::new(rhs))
+    }
+
+    fn build_input(
+        &mut self,
+        input: JitInput,
+        nodes: &[Option<JitNode>],
+        producers: &HashMap<usize, usize>,
+        inlined: &HashSet<usize>,
+    ) -> JitExpr {
+        match input {
+            JitInput::Vec(buffer, t) => {
+                if let Some(&producer) = producers.get(&buffer.i) {
+                    if inlined.contains(&producer) {
+                        self.fused.push(producer);
+                        return self.build(
+                            nodes[producer].as_ref().unwrap(),
+                            nodes,
+                            producers,
+                            inlined,
+                        );
+                    }
+                }
+                self.display = format!("{}", buffer);
+                let index = match self
+                    .inputs
+                    .iter()
+                    .position(|&(input, _)| input.i == buffer.i)
+                {
+                    Some(index) => index,
+                    None => {
+                        self.inputs.push((buffer, t));
+                        self.inputs.len() - 1
+                    }
+                };
+                JitExpr::Input(index, t)
+            }
+            JitInput::Scalar(buffer) => {
+                self.display = format!("{}", buffer);
+                self.scalars.push(buffer);
+                JitExpr::Scalar(self.scalars.len() - 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        expr: &JitExpr,
+        output_type: JitType,
+        inputs: &[&[i64]],
+        scalars: &[i64],
+        output: &mut [i64],
+    ) -> bool {
+        let kernel = Kernel::get(expr, output_type).unwrap();
+        let pointers = inputs
+            .iter()
+            .map(|input| input.as_ptr() as *const u8)
+            .collect::<Vec<_>>();
+        let overflow = unsafe {
+            (kernel.function)(
+                pointers.as_ptr(),
+                scalars.as_ptr(),
+                output.as_mut_ptr() as *mut u8,
+                output.len() as u64,
+            )
+        };
+        overflow != 0
+    }
+
+    #[test]
+    fn test_arithmetic_kernel() {
+        // (a + b) * 3
+        let expr = JitExpr::Binary(
+            JitOp::Multiply,
+            Box::new(JitExpr::Binary(
+                JitOp::Add,
+                Box::new(JitExpr::Input(0, JitType::I64)),
+                Box::new(JitExpr::Input(1, JitType::I64)),
+            )),
+            Box::new(JitExpr::Scalar(0)),
+        );
+        let mut output = [0; 4];
+        assert!(!run(
+            &expr,
+            JitType::I64,
+            &[&[1, 2, 3, -4], &[10, 20, 30, 40]],
+            &[3],
+            &mut output
+        ));
+        assert_eq!(output, [33, 66, 99, 108]);
+
+        let mut output = [0; 1];
+        assert!(run(
+            &expr,
+            JitType::I64,
+            &[&[i64::MAX], &[0]],
+            &[3],
+            &mut output
+        ));
+    }
+
+    #[test]
+    fn test_comparison_kernel() {
+        // a - 1 < b
+        let expr = JitExpr::Binary(
+            JitOp::LessThan,
+            Box::new(JitExpr::Binary(
+                JitOp::Subtract,
+                Box::new(JitExpr::Input(0, JitType::I64)),
+                Box::new(JitExpr::Scalar(0)),
+            )),
+            Box::new(JitExpr::Input(1, JitType::I64)),
+        );
+        let kernel = Kernel::get(&expr, JitType::U8).unwrap();
+        let lhs = [1i64, 5, 10];
+        let rhs = [1i64, 4, 10];
+        let pointers = [lhs.as_ptr() as *const u8, rhs.as_ptr() as *const u8];
+        let mut output = [0u8; 3];
+        let overflow =
+            unsafe { (kernel.function)(pointers.as_ptr(), [1].as_ptr(), output.as_mut_ptr(), 3) };
+        assert_eq!(overflow, 0);
+        assert_eq!(output, [1, 0, 1]);
+    }
+}
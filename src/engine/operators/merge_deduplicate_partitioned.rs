@@ -16,6 +16,7 @@ impl<'a, T: VecData<T> + 'a> VecOperator<'a> for MergeDeduplicatePartitioned<T>
             let partitioning = scratchpad.get(self.partitioning);
             let left = scratchpad.get(self.left);
             let right = scratchpad.get(self.right);
+            Premerge::validate(&partitioning, left.len(), right.len())?;
             merge_deduplicate_partitioned(&partitioning, &left, &right)
         };
         scratchpad.set(self.deduplicated, deduplicated);
@@ -46,8 +47,8 @@ pub fn merge_deduplicate_partitioned<'a, T: PartialOrd + Copy + 'a>(partitioning
     let mut j = 0;
     for group in partitioning {
         let mut last = None;
-        let i_max = i + group.left as usize;
-        let j_max = j + group.right as usize;
+        let i_max = i + group.left;
+        let j_max = j + group.right;
         // println!("i_max = {}, j_max = {}", i_max, j_max);
         for _ in 0..(group.left + group.right) {
             // println!("i = {}, j = {}, last = {:?}", i, j, last);
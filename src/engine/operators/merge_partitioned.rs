@@ -20,6 +20,7 @@ impl<'a, T: VecData<T> + 'a, C: Comparator<T> + Debug> VecOperator<'a> for Merge
             let partitioning = scratchpad.get(self.partitioning);
             let left = scratchpad.get(self.left);
             let right = scratchpad.get(self.right);
+            Premerge::validate(&partitioning, left.len(), right.len())?;
             merge_partitioned::<_, C>(&partitioning, &left, &right, self.limit)
         };
         scratchpad.set(self.merged, merged);
@@ -48,8 +49,8 @@ pub fn merge_partitioned<'a, T, C>(partitioning: &[Premerge], left: &[T], right:
     let mut i = 0;
     let mut j = 0;
     'outer: for group in partitioning {
-        let i_max = i + group.left as usize;
-        let j_max = j + group.right as usize;
+        let i_max = i + group.left;
+        let j_max = j + group.right;
         for _ in 0..(group.left + group.right) {
             if j == j_max || (i < i_max && C::cmp_eq(left[i], right[j])) {
                 take_left.push(1);
@@ -25,6 +25,8 @@ mod encode_const;
 mod exists;
 mod filter;
 mod filter_nullable;
+mod fsst_decode;
+mod fused;
 mod functions;
 mod fuse_nulls;
 mod get_null_map;
@@ -51,22 +53,29 @@ mod null_vec_like;
 mod numeric_operators;
 mod parameterized_vec_vec_int_op;
 mod propagate_nullability;
+mod run_length_decode;
 mod scalar_i64;
 mod scalar_str;
 mod select;
 mod sort_by;
 mod sort_by_slices;
 mod sort_by_val_rows;
+mod sort_grouping;
+mod sorted_grouping;
 mod stream_buffer;
 mod to_val;
 mod top_n;
+mod truncate;
 mod type_conversion;
 mod unhexpack_strings;
 mod unpack_strings;
 mod val_rows_pack;
 mod val_rows_unpack;
+mod xor_decode;
 #[cfg(feature = "enable_lz4")]
 mod lz4_decode;
+#[cfg(feature = "jit")]
+mod jit;
 mod merge_deduplicate_partitioned;
 mod partition;
 mod subpartition;
@@ -75,4 +84,7 @@ mod slice_unpack;
 
 mod aggregator;
 
-pub use null_vec_like::LengthSource;
\ No newline at end of file
+pub use null_vec_like::LengthSource;
+pub use self::fused::fuse_streaming_operators;
+#[cfg(feature = "jit")]
+pub use self::jit::{fuse_expressions, JitInput, JitNode, JitOp};
\ No newline at end of file
@@ -4,6 +4,8 @@ use ordered_float::OrderedFloat;
 use std::marker::PhantomData;
 
 use super::binary_operator::*;
+#[cfg(feature = "jit")]
+use super::jit::JitOp;
 
 pub struct Addition<LHS, RHS> {
     lhs: PhantomData<LHS>,
@@ -39,6 +41,8 @@ impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Addition<LHS, RHS>
     }
 
     fn symbol() -> &'static str { "+" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::Add) }
 }
 
 impl<LHS: PrimInt, RHS: PrimInt> CheckedBinaryOp<LHS, RHS, i64> for Addition<LHS, RHS> {
@@ -55,6 +59,8 @@ impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Subtraction<LHS, RH
     }
 
     fn symbol() -> &'static str { "-" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::Subtract) }
 }
 
 impl<LHS: PrimInt, RHS: PrimInt> CheckedBinaryOp<LHS, RHS, i64> for Subtraction<LHS, RHS> {
@@ -71,6 +77,8 @@ impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Multiplication<LHS,
     }
 
     fn symbol() -> &'static str { "*" }
+    #[cfg(feature = "jit")]
+    fn jit_op() -> Option<JitOp> { Some(JitOp::Multiply) }
 }
 
 impl<LHS: PrimInt, RHS: PrimInt> CheckedBinaryOp<LHS, RHS, i64> for Multiplication<LHS, RHS, i64> {
@@ -2,7 +2,6 @@ use crate::engine::*;
 use std::cmp;
 use std::fmt::Debug;
 use std::marker::PhantomData;
-use std::u32;
 
 #[derive(Debug)]
 pub struct Partition<T, C> {
@@ -42,8 +41,7 @@ pub fn partition<'a, T, C>(left: &[T], right: &[T], limit: usize) -> Vec<Premerg
     let mut result = Vec::new();
     let mut i = 0;
     let mut j = 0;
-    let limit = if limit > u32::MAX as usize { u32::MAX } else { limit as u32 };
-    let mut min_elems = 0u32;
+    let mut min_elems = 0;
     while i < left.len() && j < right.len() && min_elems < limit {
         let mut partition = Premerge { left: 0, right: 0 };
         let elem = if C::cmp_eq(left[i], right[j]) { left[i] } else { right[j] };
@@ -66,7 +64,7 @@ pub fn partition<'a, T, C>(left: &[T], right: &[T], limit: usize) -> Vec<Premerg
         while i < left.len() && elem == left[i] {
             i += 1;
         }
-        let partition = Premerge { left: (i - i_start) as u32, right: 0 };
+        let partition = Premerge { left: i - i_start, right: 0 };
         min_elems += cmp::max(partition.left, partition.right);
         result.push(partition);
     }
@@ -78,7 +76,7 @@ pub fn partition<'a, T, C>(left: &[T], right: &[T], limit: usize) -> Vec<Premerg
         while j < right.len() && elem == right[j] {
             j += 1;
         }
-        let partition = Premerge { right: (j - j_start) as u32, left: 0 };
+        let partition = Premerge { right: j - j_start, left: 0 };
         min_elems += cmp::max(partition.left, partition.right);
         result.push(partition);
     }
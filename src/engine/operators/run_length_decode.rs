@@ -0,0 +1,41 @@
+use crate::engine::*;
+use std::iter;
+
+/// Expands runs of values, which requires the entire input since runs are not aligned with batches.
+#[derive(Debug)]
+pub struct RunLengthDecode<T> {
+    pub values: BufferRef<T>,
+    pub run_lengths: BufferRef<u32>,
+    pub decoded: BufferRef<T>,
+    pub decoded_len: usize,
+}
+
+impl<'a, T: VecData<T> + 'a> VecOperator<'a> for RunLengthDecode<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let len = {
+            let values = scratchpad.get(self.values);
+            let run_lengths = scratchpad.get(self.run_lengths);
+            let mut decoded = scratchpad.get_mut(self.decoded);
+            for (&value, &run_length) in values.iter().zip(run_lengths.iter()) {
+                decoded.extend(iter::repeat(value).take(run_length as usize));
+            }
+            decoded.len()
+        };
+        scratchpad.reserve_memory(len * std::mem::size_of::<T>())
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.decoded, Vec::with_capacity(self.decoded_len));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.values.any(), self.run_lengths.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.values.i, &mut self.run_lengths.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.decoded.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("run_length_decode({}, {})", self.values, self.run_lengths)
+    }
+}
@@ -0,0 +1,62 @@
+use crate::engine::*;
+use crate::ingest::raw_val::RawVal;
+use std::mem;
+
+/// Assigns each distinct value of `input` a group index by sorting all values. Unlike `HashMapGrouping`, the unique
+/// values are output in sorted order and the cost does not grow with the number of groups, which makes it the better
+/// choice when most values are distinct.
+#[derive(Debug)]
+pub struct SortGrouping<T> {
+    pub input: BufferRef<T>,
+    pub unique_out: BufferRef<T>,
+    pub grouping_key_out: BufferRef<u32>,
+    pub cardinality_out: BufferRef<Scalar<i64>>,
+}
+
+impl<'a, T: VecData<T> + 'a> VecOperator<'a> for SortGrouping<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let count = {
+            let input = scratchpad.get(self.input);
+            let mut permutation = (0..input.len()).collect::<Vec<_>>();
+            permutation.sort_unstable_by_key(|&i| input[i]);
+
+            let mut grouping = scratchpad.get_mut(self.grouping_key_out);
+            let mut unique = scratchpad.get_mut(self.unique_out);
+            grouping.clear();
+            grouping.resize(input.len(), 0);
+            for i in permutation {
+                if unique.last() != Some(&input[i]) {
+                    unique.push(input[i]);
+                }
+                grouping[i] = unique.len() as u32 - 1;
+            }
+            unique.len()
+        };
+        scratchpad.reserve_memory(count * mem::size_of::<T>())?;
+        let cardinality = constant_data(RawVal::Int(count as i64));
+        scratchpad.set_any(self.cardinality_out.any(), cardinality);
+        Ok(())
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.unique_out, Vec::new());
+        scratchpad.set(self.grouping_key_out, Vec::new());
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.input.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        vec![
+            self.unique_out.any(),
+            self.grouping_key_out.any(),
+            self.cardinality_out.any(),
+        ]
+    }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("sort_grouping({})", self.input)
+    }
+}
@@ -0,0 +1,62 @@
+use crate::engine::*;
+use crate::ingest::raw_val::RawVal;
+use std::mem;
+
+/// Assigns group indices to a grouping key that is sorted, so that all rows of a group are adjacent. A new group
+/// starts whenever the key differs from the previous one. The only state is the last unique value, and groups are
+/// complete as soon as the next group starts.
+#[derive(Debug)]
+pub struct SortedGrouping<T> {
+    pub input: BufferRef<T>,
+    pub unique_out: BufferRef<T>,
+    pub grouping_key_out: BufferRef<u32>,
+    pub cardinality_out: BufferRef<Scalar<i64>>,
+}
+
+impl<'a, T: VecData<T> + 'a> VecOperator<'a> for SortedGrouping<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let (count, new_groups) = {
+            let input = scratchpad.get(self.input);
+            let mut grouping = scratchpad.get_mut(self.grouping_key_out);
+            let mut unique = scratchpad.get_mut(self.unique_out);
+            if stream {
+                grouping.clear();
+            }
+            let groups = unique.len();
+            for &key in input.iter() {
+                if unique.last() != Some(&key) {
+                    unique.push(key);
+                }
+                grouping.push(unique.len() as u32 - 1);
+            }
+            (unique.len(), unique.len() - groups)
+        };
+        scratchpad.reserve_memory(new_groups * mem::size_of::<T>())?;
+        let cardinality = constant_data(RawVal::Int(count as i64));
+        scratchpad.set_any(self.cardinality_out.any(), cardinality);
+        Ok(())
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.unique_out, Vec::new());
+        scratchpad.set(self.grouping_key_out, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.input.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        vec![
+            self.unique_out.any(),
+            self.grouping_key_out.any(),
+            self.cardinality_out.any(),
+        ]
+    }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, output: usize) -> bool { output != self.unique_out.i }
+    fn can_block_output(&self) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("sorted_grouping({})", self.input)
+    }
+}
@@ -17,6 +17,7 @@ impl<'a, T: VecData<T> + 'a, C: Comparator<T> + Debug> VecOperator<'a> for SubPa
             let partitioning = scratchpad.get(self.partitioning);
             let left = scratchpad.get(self.left);
             let right = scratchpad.get(self.right);
+            Premerge::validate(&partitioning, left.len(), right.len())?;
             subpartition::<_, C>(&partitioning, &left, &right)
         };
         scratchpad.set(self.sub_partitioning, sub_partitioning);
@@ -45,8 +46,8 @@ fn subpartition<'a, T: VecData<T> + 'a, C: Comparator<T>>(
     let mut j = 0;
     #[allow(clippy::explicit_counter_loop)] // false positive
         for group in partitioning {
-        let i_max = i + group.left as usize;
-        let j_max = j + group.right as usize;
+        let i_max = i + group.left;
+        let j_max = j + group.right;
         while i < i_max || j < j_max {
             let mut subpartition = Premerge { left: 0, right: 0 };
             let elem = if i < i_max && (j == j_max || C::cmp_eq(left[i], right[j])) {
@@ -76,6 +77,10 @@ mod tests {
     use crate::engine::operators::partition::partition;
     use crate::engine::operators::subpartition::subpartition;
 
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
     use self::MergeOp::*;
 
     #[test]
@@ -122,4 +127,60 @@ mod tests {
             Premerge { left: 0, right: 1 },
         ]);
     }
+
+    /// Sorted distinct `(a, b)` grouping keys, as produced by grouping a partition by two columns.
+    fn random_groups(rng: &mut XorShiftRng, len: usize, a_values: u32) -> BTreeSet<(u32, u32)> {
+        (0..len).map(|_| (rng.gen_range(0, a_values), rng.gen_range(0, 1 << 20))).collect()
+    }
+
+    #[test]
+    fn test_partitioned_merge_randomized() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        // The last cases have a single group of more than `u16::MAX` rows in the first column
+        for &(left_len, right_len, a_values) in &[
+            (0, 10, 3),
+            (100, 100, 1),
+            (1000, 50, 10),
+            (5000, 5000, 100),
+            (70_000, 1000, 1),
+            (100_000, 100_000, 1),
+        ] {
+            let left = random_groups(&mut rng, left_len, a_values);
+            let right = random_groups(&mut rng, right_len, a_values);
+            let (left_a, left_b): (Vec<u32>, Vec<u32>) = left.iter().cloned().unzip();
+            let (right_a, right_b): (Vec<u32>, Vec<u32>) = right.iter().cloned().unzip();
+
+            let partitioning = partition::<u32, CmpLessThan>(&left_a, &right_a, usize::MAX);
+            Premerge::validate(&partitioning, left.len(), right.len()).unwrap();
+            assert_eq!(partitioning.iter().map(|p| p.left).sum::<usize>(), left.len());
+            assert_eq!(partitioning.iter().map(|p| p.right).sum::<usize>(), right.len());
+
+            let (merged_b, ops) = merge_deduplicate_partitioned::<u32>(&partitioning, &left_b, &right_b);
+            let (mut i, mut j) = (0, 0);
+            let mut merged = Vec::new();
+            for op in ops {
+                match op {
+                    TakeLeft => {
+                        merged.push((left_a[i], left_b[i]));
+                        i += 1;
+                    }
+                    TakeRight => {
+                        merged.push((right_a[j], right_b[j]));
+                        j += 1;
+                    }
+                    MergeRight => j += 1,
+                }
+            }
+            let expected = left.union(&right).cloned().collect::<Vec<_>>();
+            assert_eq!(merged, expected);
+            assert_eq!(merged_b, expected.iter().map(|&(_, b)| b).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_validate_partitioning() {
+        let partitioning = vec![Premerge { left: 2, right: 1 }, Premerge { left: 1, right: 3 }];
+        assert!(Premerge::validate(&partitioning, 3, 4).is_ok());
+        assert!(Premerge::validate(&partitioning, 2, 4).is_err());
+    }
 }
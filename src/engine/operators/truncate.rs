@@ -0,0 +1,29 @@
+use crate::engine::*;
+
+#[derive(Debug)]
+pub struct Truncate {
+    pub input: BufferRef<usize>,
+    pub n: usize,
+    pub output: BufferRef<usize>,
+}
+
+impl<'a> VecOperator<'a> for Truncate {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let input = scratchpad.get(self.input);
+        let output = input[..self.n.min(input.len())].to_vec();
+        drop(input);
+        scratchpad.set(self.output, output);
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.input.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[..{}]", self.input, self.n)
+    }
+}
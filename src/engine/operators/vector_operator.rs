@@ -33,6 +33,7 @@ use super::encode_const::*;
 use super::exists::Exists;
 use super::filter::{Filter, NullableFilter};
 use super::filter_nullable::{FilterNullable, NullableFilterNullable};
+use super::fsst_decode::FsstDecode;
 use super::functions::*;
 use super::fuse_nulls::*;
 use super::get_null_map::GetNullMap;
@@ -61,6 +62,7 @@ use super::numeric_operators::*;
 use super::parameterized_vec_vec_int_op::*;
 use super::partition::Partition;
 use super::propagate_nullability::PropagateNullability;
+use super::run_length_decode::RunLengthDecode;
 use super::scalar_i64::ScalarI64;
 use super::scalar_str::ScalarStr;
 use super::select::*;
@@ -69,15 +71,19 @@ use super::slice_unpack::*;
 use super::sort_by::*;
 use super::sort_by_slices::SortBySlices;
 use super::sort_by_val_rows::SortByValRows;
+use super::sort_grouping::SortGrouping;
+use super::sorted_grouping::SortedGrouping;
 use super::stream_buffer::{StreamBuffer, StreamBufferNullable, StreamNullVec};
 use super::subpartition::SubPartition;
 use super::to_val::*;
 use super::top_n::TopN;
+use super::truncate::Truncate;
 use super::type_conversion::TypeConversionOperator;
 use super::unhexpack_strings::UnhexpackStrings;
 use super::unpack_strings::UnpackStrings;
 use super::val_rows_pack::*;
 use super::val_rows_unpack::*;
+use super::xor_decode::XorDecode;
 
 pub type BoxedOperator<'a> = Box<dyn VecOperator<'a> + 'a>;
 
@@ -142,6 +148,12 @@ pub trait VecOperator<'a> {
     fn custom_output_len(&self) -> Option<usize> {
         None
     }
+    /// Describes the operator if it is an arithmetic or comparison operator that can be fused with adjacent operators
+    /// into a compiled kernel.
+    #[cfg(feature = "jit")]
+    fn jit_node(&self) -> Option<JitNode> {
+        None
+    }
 
     fn display(&self, full: bool) -> String {
         let mut s = String::new();
@@ -528,6 +540,36 @@ pub mod operator {
         })
     }
 
+    pub fn fsst_decode<'a>(
+        codes: BufferRef<u8>,
+        symbols: BufferRef<u8>,
+        total_bytes: usize,
+        stringstore: BufferRef<u8>,
+        decoded: BufferRef<&'a str>,
+    ) -> BoxedOperator<'a> {
+        Box::new(FsstDecode::<'a> {
+            codes,
+            symbols,
+            decoded,
+            stringstore,
+            iterator: None,
+            symbol_table: Vec::new(),
+            total_bytes,
+            has_more: true,
+        })
+    }
+
+    pub fn xor_decode<'a>(
+        encoded: BufferRef<u64>,
+        decoded: BufferRef<OrderedFloat<f64>>,
+    ) -> BoxedOperator<'a> {
+        Box::new(XorDecode {
+            encoded,
+            decoded,
+            previous: 0,
+        })
+    }
+
     pub fn delta_decode<'a>(
         encoded: TypedBufferRef,
         decoded: BufferRef<i64>,
@@ -539,6 +581,45 @@ pub mod operator {
         }
     }
 
+    pub fn run_length_decode<'a>(
+        values: TypedBufferRef,
+        run_lengths: BufferRef<u32>,
+        decoded: TypedBufferRef,
+        decoded_len: usize,
+    ) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "run_length_decode";
+            values, decoded: Primitive;
+            Ok(Box::new(RunLengthDecode { values, run_lengths, decoded, decoded_len }))
+        }
+    }
+
+    pub fn dict_regex<'a>(
+        dict_indices: BufferRef<u64>,
+        dict_data: BufferRef<u8>,
+        r: &str,
+        output: BufferRef<u8>,
+    ) -> BoxedOperator<'a> {
+        Box::new(DictRegex {
+            dict_indices,
+            dict_data,
+            regex: regex::Regex::new(r).unwrap(),
+            output,
+        })
+    }
+
+    pub fn dict_membership<'a>(
+        indices: TypedBufferRef,
+        members: BufferRef<u8>,
+        output: BufferRef<u8>,
+    ) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types![
+            "dict_membership";
+            indices: Integer;
+            Ok(Box::new(DictMembership { indices, members, output }))
+        ]
+    }
+
     pub fn inverse_dict_lookup(
         dict_indices: BufferRef<u64>,
         dict_data: BufferRef<u8>,
@@ -553,6 +634,22 @@ pub mod operator {
         })
     }
 
+    pub fn dict_bound(
+        dict_indices: BufferRef<u64>,
+        dict_data: BufferRef<u8>,
+        constant: BufferRef<Scalar<&str>>,
+        upper: bool,
+        output: BufferRef<Scalar<i64>>,
+    ) -> BoxedOperator {
+        Box::new(DictBound {
+            dict_indices,
+            dict_data,
+            constant,
+            upper,
+            output,
+        })
+    }
+
     pub fn _encode_int_const<'a>(
         constant: BufferRef<Scalar<i64>>,
         codec: Codec,
@@ -1362,6 +1459,14 @@ pub mod operator {
         max_index: BufferRef<Scalar<i64>>,
         output: BufferRef<u8>,
     ) -> Result<BoxedOperator<'a>, QueryError> {
+        if input.tag == EncodingType::USize {
+            let input = input.usize()?;
+            return Ok(Box::new(Exists {
+                input,
+                output,
+                max_index,
+            }));
+        }
         reify_types! {
             "exists";
             input: Integer;
@@ -1426,6 +1531,32 @@ pub mod operator {
         }
     }
 
+    pub fn sort_grouping<'a>(
+        raw_grouping_key: TypedBufferRef,
+        unique_out: TypedBufferRef,
+        grouping_key_out: BufferRef<u32>,
+        cardinality_out: BufferRef<Scalar<i64>>,
+    ) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "sort_grouping";
+            raw_grouping_key, unique_out: Primitive;
+            Ok(Box::new(SortGrouping { input: raw_grouping_key, unique_out, grouping_key_out, cardinality_out }))
+        }
+    }
+
+    pub fn sorted_grouping<'a>(
+        raw_grouping_key: TypedBufferRef,
+        unique_out: TypedBufferRef,
+        grouping_key_out: BufferRef<u32>,
+        cardinality_out: BufferRef<Scalar<i64>>,
+    ) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "sorted_grouping";
+            raw_grouping_key, unique_out: Primitive;
+            Ok(Box::new(SortedGrouping { input: raw_grouping_key, unique_out, grouping_key_out, cardinality_out }))
+        }
+    }
+
     pub fn hash_map_grouping_val_rows<'a>(
         raw_grouping_key: BufferRef<ValRows<'a>>,
         columns: usize,
@@ -1512,6 +1643,10 @@ pub mod operator {
         }
     }
 
+    pub fn truncate<'a>(input: BufferRef<usize>, n: usize, output: BufferRef<usize>) -> BoxedOperator<'a> {
+        Box::new(Truncate { input, n, output })
+    }
+
     pub fn merge_deduplicate<'a>(
         left: TypedBufferRef,
         right: TypedBufferRef,
@@ -1520,7 +1655,7 @@ pub mod operator {
     ) -> Result<BoxedOperator<'a>, QueryError> {
         reify_types! {
             "merge_deduplicate";
-            left, right, merged_out: Primitive;
+            left, right, merged_out: PrimitiveOrVal;
             Ok(Box::new(MergeDeduplicate { left, right, deduplicated: merged_out, merge_ops: ops_out }))
         }
     }
@@ -1535,13 +1670,13 @@ pub mod operator {
         if desc {
             reify_types! {
                 "partition";
-                left, right: Primitive;
+                left, right: PrimitiveOrVal;
                 Ok(Box::new(Partition { left, right, partitioning: partition_out, limit, c: PhantomData::<CmpGreaterThan> }))
             }
         } else {
             reify_types! {
                 "partition";
-                left, right: Primitive;
+                left, right: PrimitiveOrVal;
                 Ok(Box::new(Partition { left, right, partitioning: partition_out, limit, c: PhantomData::<CmpLessThan> }))
             }
         }
@@ -1557,13 +1692,13 @@ pub mod operator {
         if desc {
             reify_types! {
                 "subpartition";
-                left, right: Primitive;
+                left, right: PrimitiveOrVal;
                 Ok(Box::new(SubPartition { partitioning, left, right, sub_partitioning: subpartition_out, c: PhantomData::<CmpGreaterThan> }))
             }
         } else {
             reify_types! {
                 "subpartition";
-                left, right: Primitive;
+                left, right: PrimitiveOrVal;
                 Ok(Box::new(SubPartition { partitioning, left, right, sub_partitioning: subpartition_out, c: PhantomData::<CmpLessThan> }))
             }
         }
@@ -1578,7 +1713,7 @@ pub mod operator {
     ) -> Result<BoxedOperator<'a>, QueryError> {
         reify_types! {
             "merge_deduplicate_partitioned";
-            left, right, merged_out: Primitive;
+            left, right, merged_out: PrimitiveOrVal;
             Ok(Box::new(MergeDeduplicatePartitioned { partitioning, left, right, deduplicated: merged_out, merge_ops: ops_out }))
         }
     }
@@ -1591,7 +1726,7 @@ pub mod operator {
     ) -> Result<BoxedOperator<'a>, QueryError> {
         reify_types! {
             "merge_drop";
-            left, right, merged_out: Primitive;
+            left, right, merged_out: PrimitiveOrVal;
             Ok(Box::new(MergeDrop { merge_ops, left, right, deduplicated: merged_out }))
         }
     }
@@ -0,0 +1,41 @@
+use ordered_float::OrderedFloat;
+
+use crate::engine::*;
+
+#[derive(Debug)]
+pub struct XorDecode {
+    pub encoded: BufferRef<u64>,
+    pub decoded: BufferRef<OrderedFloat<f64>>,
+    pub previous: u64,
+}
+
+impl<'a> VecOperator<'a> for XorDecode {
+    fn execute(&mut self, streaming: bool, scratchpad: &mut Scratchpad<'a>) -> Result<(), QueryError> {
+        let encoded = scratchpad.get(self.encoded);
+        let mut decoded = scratchpad.get_mut(self.decoded);
+        if streaming { decoded.clear(); }
+        let mut previous = self.previous;
+        for &e in encoded.iter() {
+            previous ^= e;
+            decoded.push(OrderedFloat(f64::from_bits(previous)));
+        }
+        self.previous = previous;
+        Ok(())
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.decoded, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.encoded.any()] }
+    fn inputs_mut(&mut self) -> Vec<&mut usize> { vec![&mut self.encoded.i] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.decoded.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn can_block_output(&self) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("xor_decode({})", self.encoded)
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::engine::data_types::EncodingType;
+use crate::mem_store::column::DataSource;
+use crate::syntax::expression::Expr;
+
+/// Grouping keys up to this size are always used as group index directly.
+const MIN_DIRECT_CARDINALITY: i64 = 1 << 16;
+/// Grouping keys larger than this are never used as group index directly, regardless of the number of rows.
+const MAX_DIRECT_CARDINALITY: i64 = 1 << 20;
+
+// Estimated cost of the different strategies relative to scanning one row of the grouping key
+const DIRECT_SLOT_COST: f64 = 0.25;
+const HASH_ROW_COST: f64 = 2.0;
+const HASH_GROUP_COST: f64 = 10.0;
+const SORT_ROW_COST: f64 = 0.5;
+
+/// Determines how the grouping key of each row is mapped to the index of its group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupingStrategy {
+    /// The grouping key is used as group index, requires aggregation buffers with one entry per possible key.
+    Direct,
+    /// Group indices are assigned by `HashMapGrouping` in order of first occurrence.
+    HashMap,
+    /// Group indices are assigned by `SortGrouping` in order of the grouping key.
+    Sort,
+    /// The grouping key is sorted and group indices are assigned by `SortedGrouping` in a single streaming pass.
+    Sorted,
+}
+
+/// Chooses the cheapest grouping strategy for `rows` rows with grouping keys in `0..=max_grouping_key`, of which
+/// `groups` are estimated to be distinct. `sortable` indicates whether the grouping key supports `SortGrouping`, and
+/// `sorted` whether the grouping key is known to be sorted.
+pub fn choose_grouping(
+    rows: usize,
+    max_grouping_key: i64,
+    groups: Option<usize>,
+    sortable: bool,
+    sorted: bool,
+) -> GroupingStrategy {
+    if max_grouping_key < MIN_DIRECT_CARDINALITY {
+        return GroupingStrategy::Direct;
+    }
+    // Only compares each key to the previous one and does not depend on the number of groups
+    if sortable && sorted {
+        return GroupingStrategy::Sorted;
+    }
+    let rows = rows.max(1) as f64;
+    let mut candidates = vec![];
+    if max_grouping_key < MAX_DIRECT_CARDINALITY {
+        candidates.push((
+            GroupingStrategy::Direct,
+            rows + max_grouping_key as f64 * DIRECT_SLOT_COST,
+        ));
+    }
+    // Without an estimate, assume a number of groups that favours the hash map which performs well in most cases
+    let groups = groups.map_or(rows.sqrt(), |groups| (groups as f64).min(rows));
+    candidates.push((
+        GroupingStrategy::HashMap,
+        rows * HASH_ROW_COST + groups * HASH_GROUP_COST,
+    ));
+    if sortable {
+        candidates.push((
+            GroupingStrategy::Sort,
+            rows * (SORT_ROW_COST * rows.log2() + 1.0),
+        ));
+    }
+    candidates
+        .into_iter()
+        .min_by(|(_, lhs), (_, rhs)| lhs.total_cmp(rhs))
+        .unwrap()
+        .0
+}
+
+/// Estimates the number of distinct values of `exprs` from the statistics of the grouped columns. Only available if
+/// all expressions are columns with known statistics.
+pub fn estimate_groups(
+    exprs: &[Expr],
+    columns: &HashMap<String, Arc<dyn DataSource>>,
+) -> Option<usize> {
+    exprs.iter().try_fold(1usize, |groups, expr| match expr {
+        Expr::ColName(name) => {
+            let distinct_values = columns.get(name)?.stats()?.distinct_values?;
+            Some(groups.saturating_mul(distinct_values.max(1)))
+        }
+        _ => None,
+    })
+}
+
+/// Whether the grouping key of `exprs` is sorted, which is the case when grouping by a single column with sorted values.
+pub fn is_sorted(exprs: &[Expr], columns: &HashMap<String, Arc<dyn DataSource>>) -> bool {
+    match exprs {
+        [Expr::ColName(name)] => columns
+            .get(name)
+            .and_then(|column| column.stats())
+            .map_or(false, |stats| stats.sorted),
+        _ => false,
+    }
+}
+
+/// Whether `SortGrouping` supports grouping keys of type `tag`.
+pub fn is_sortable(tag: EncodingType) -> bool {
+    matches!(
+        tag,
+        EncodingType::U8
+            | EncodingType::U16
+            | EncodingType::U32
+            | EncodingType::U64
+            | EncodingType::I64
+            | EncodingType::Str
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_grouping() {
+        use GroupingStrategy::*;
+        assert_eq!(choose_grouping(1 << 20, 1000, None, true, false), Direct);
+        assert_eq!(choose_grouping(1 << 20, 1 << 19, None, true, false), Direct);
+        assert_eq!(choose_grouping(1000, 1 << 19, None, true, false), HashMap);
+        assert_eq!(
+            choose_grouping(1 << 20, i64::MAX, None, true, false),
+            HashMap
+        );
+        assert_eq!(
+            choose_grouping(1 << 20, i64::MAX, Some(100), true, false),
+            HashMap
+        );
+        let unique = Some(1 << 20);
+        assert_eq!(
+            choose_grouping(1 << 20, i64::MAX, unique, true, false),
+            Sort
+        );
+        assert_eq!(
+            choose_grouping(1 << 20, i64::MAX, unique, false, false),
+            HashMap
+        );
+        assert_eq!(choose_grouping(1 << 20, 1000, None, true, true), Direct);
+        assert_eq!(choose_grouping(1 << 20, i64::MAX, None, true, true), Sorted);
+        assert_eq!(
+            choose_grouping(1 << 20, i64::MAX, None, false, true),
+            HashMap
+        );
+    }
+}
@@ -1,12 +1,22 @@
 mod filter;
+mod grouping;
 pub mod planner;
+mod quantiles;
 mod query;
 pub mod query_plan;
+mod selectivity;
+mod simplify;
+mod window;
 
 pub use self::filter::Filter;
+pub use self::grouping::GroupingStrategy;
 pub use self::planner::QueryPlanner;
+pub use self::quantiles::{QuantileRewrite, QuantileRun};
 pub use self::query::ColumnInfo;
 pub use self::query::NormalFormQuery;
 pub use self::query::Query;
 pub use self::query::ResultColumn;
 pub use self::query_plan::QueryPlan;
+pub use self::selectivity::{conjuncts, is_deferrable, order_conjuncts, ColumnStatistics};
+pub use self::simplify::is_always_false;
+pub use self::window::WindowRewrite;
@@ -19,10 +19,10 @@ pub struct QueryPlanner {
 }
 
 impl QueryPlanner {
-    pub fn prepare(mut self, mut constant_vecs: Vec<BoxedData>, batch_size: usize) -> Result<QueryExecutor, QueryError> {
+    pub fn prepare(mut self, mut constant_vecs: Vec<BoxedData>, batch_size: usize, operator_fusion: bool) -> Result<QueryExecutor, QueryError> {
         self.perform_rewrites();
 
-        let mut result = QueryExecutor::new(batch_size, std::mem::take(&mut self.buffer_provider));
+        let mut result = QueryExecutor::new(batch_size, operator_fusion, std::mem::take(&mut self.buffer_provider));
         for operation in &self.operations {
             prepare(operation.clone(), &mut constant_vecs, &mut result)?;
         }
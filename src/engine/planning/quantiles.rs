@@ -0,0 +1,259 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+
+use ordered_float::OrderedFloat;
+
+use crate::engine::*;
+use crate::ingest::raw_val::RawVal;
+use crate::syntax::expression::*;
+use crate::syntax::limit::LimitClause;
+use crate::QueryError;
+
+/// Exact quantiles cannot be computed from fixed size partial aggregates.
+/// Queries containing quantiles are instead rewritten into a query that selects all grouping columns and quantile
+/// arguments. The rows of every partition are turned into a `QuantileRun` that holds the sorted values of each group,
+/// and the quantiles of each group are computed by a k-way merge over the runs of all partitions.
+#[derive(Debug, Clone)]
+pub struct QuantileRewrite {
+    outputs: Vec<QuantileOutput>,
+    group_columns: usize,
+    limit: LimitClause,
+}
+
+#[derive(Debug, Clone)]
+enum QuantileOutput {
+    Group(usize),
+    Quantile(f64, usize),
+}
+
+impl QuantileRewrite {
+    /// Returns `None` if the query does not contain any quantiles.
+    pub fn rewrite(query: &Query) -> Result<Option<(Query, QuantileRewrite)>, QueryError> {
+        if !query
+            .select
+            .iter()
+            .any(|c| matches!(c.expr, Expr::Quantile(_, _)))
+        {
+            return Ok(None);
+        }
+        if !query.order_by.is_empty() {
+            bail!(
+                QueryError::NotImplemented,
+                "ORDER BY in queries containing quantiles"
+            );
+        }
+
+        let mut group_by = Vec::new();
+        let mut values = Vec::new();
+        let mut outputs = Vec::with_capacity(query.select.len());
+        for col_info in &query.select {
+            match &col_info.expr {
+                Expr::Quantile(q, expr) => {
+                    Query::ensure_no_aggregates(expr)?;
+                    outputs.push(QuantileOutput::Quantile(q.0, values.len()));
+                    values.push(*expr.clone());
+                }
+                expr => {
+                    let (_, aggregates) =
+                        Query::extract_aggregators(expr, &mut vec![], &col_info.name)?;
+                    if !aggregates.is_empty() {
+                        bail!(
+                            QueryError::NotImplemented,
+                            "Combining quantiles with other aggregation functions"
+                        );
+                    }
+                    outputs.push(QuantileOutput::Group(group_by.len()));
+                    group_by.push(expr.clone());
+                }
+            }
+        }
+
+        let group_columns = group_by.len();
+        let select = group_by
+            .iter()
+            .enumerate()
+            .map(|(i, expr)| ColumnInfo {
+                expr: expr.clone(),
+                name: format!("_qg{}", i),
+            })
+            .chain(values.into_iter().enumerate().map(|(i, expr)| ColumnInfo {
+                expr,
+                name: format!("_qv{}", i),
+            }))
+            .collect();
+        let rewritten = Query {
+            select,
+            table: query.table.clone(),
+            filter: query.filter.clone(),
+            order_by: vec![],
+            limit: LimitClause {
+                limit: u64::MAX,
+                offset: 0,
+            },
+        };
+        Ok(Some((
+            rewritten,
+            QuantileRewrite {
+                outputs,
+                group_columns,
+                limit: query.limit.clone(),
+            },
+        )))
+    }
+
+    /// Sorts the values of each group within the rows of the rewritten query for one partition.
+    pub fn sorted_run(&self, rows: Vec<Vec<RawVal>>) -> QuantileRun {
+        let value_columns = self.value_columns();
+        let mut groups = BTreeMap::<Vec<RawVal>, Vec<Vec<RawVal>>>::new();
+        for mut row in rows {
+            let values = row.split_off(self.group_columns);
+            let group = groups
+                .entry(row)
+                .or_insert_with(|| vec![Vec::new(); value_columns]);
+            for (column, value) in group.iter_mut().zip(values) {
+                if value != RawVal::Null {
+                    column.push(value);
+                }
+            }
+        }
+        for column in groups.values_mut().flatten() {
+            column.sort_unstable_by(compare);
+        }
+        QuantileRun { groups }
+    }
+
+    /// Computes the final result rows by merging the runs of all partitions.
+    pub fn merge(&self, runs: &[QuantileRun]) -> Vec<Vec<RawVal>> {
+        let mut groups = BTreeMap::<&[RawVal], Vec<&[Vec<RawVal>]>>::new();
+        for run in runs {
+            for (group, columns) in &run.groups {
+                groups.entry(&group[..]).or_default().push(&columns[..]);
+            }
+        }
+        // Aggregation without grouping columns always returns a single row
+        if groups.is_empty() && self.group_columns == 0 {
+            groups.insert(&[], vec![]);
+        }
+
+        groups
+            .into_iter()
+            .skip(self.limit.offset as usize)
+            .take(self.limit.limit.min(usize::MAX as u64) as usize)
+            .map(|(group, columns)| {
+                self.outputs
+                    .iter()
+                    .map(|output| match *output {
+                        QuantileOutput::Group(i) => group[i].clone(),
+                        QuantileOutput::Quantile(q, i) => {
+                            let runs = columns.iter().map(|c| &c[i][..]).collect::<Vec<_>>();
+                            quantile(&runs, q)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn value_columns(&self) -> usize {
+        self.outputs
+            .iter()
+            .filter(|output| matches!(output, QuantileOutput::Quantile(_, _)))
+            .count()
+    }
+}
+
+/// Sorted non-null values of each quantile argument for every group of one partition.
+#[derive(Debug, Default)]
+pub struct QuantileRun {
+    groups: BTreeMap<Vec<RawVal>, Vec<Vec<RawVal>>>,
+}
+
+struct MergeEntry<'a> {
+    value: &'a RawVal,
+    run: usize,
+    index: usize,
+}
+
+impl<'a> PartialEq for MergeEntry<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a> Eq for MergeEntry<'a> {}
+
+impl<'a> PartialOrd for MergeEntry<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for MergeEntry<'a> {
+    // `BinaryHeap` is a max-heap, so the order is reversed to pop the smallest value first
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.value, other.value)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+/// Merges the sorted `runs` until the two closest ranks of quantile `q` are found and linearly interpolates between
+/// them if the values are numeric.
+fn quantile(runs: &[&[RawVal]], q: f64) -> RawVal {
+    let len = runs.iter().map(|run| run.len()).sum::<usize>();
+    if len == 0 {
+        return RawVal::Null;
+    }
+    let rank = q * (len - 1) as f64;
+    let (lo_rank, hi_rank) = (rank.floor() as usize, rank.ceil() as usize);
+    let mut heap = runs
+        .iter()
+        .enumerate()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(run, values)| MergeEntry {
+            value: &values[0],
+            run,
+            index: 0,
+        })
+        .collect::<BinaryHeap<_>>();
+    let mut lo = None;
+    for i in 0..=hi_rank {
+        let MergeEntry { value, run, index } = heap.pop().unwrap();
+        if i == lo_rank {
+            lo = Some(value);
+        }
+        if i == hi_rank {
+            let lo = lo.unwrap();
+            if lo == value {
+                return lo.clone();
+            }
+            return match (as_f64(lo), as_f64(value)) {
+                (Some(lo), Some(hi)) => RawVal::Float(OrderedFloat(lo + (hi - lo) * rank.fract())),
+                _ => lo.clone(),
+            };
+        }
+        if let Some(next) = runs[run].get(index + 1) {
+            heap.push(MergeEntry {
+                value: next,
+                run,
+                index: index + 1,
+            });
+        }
+    }
+    unreachable!("hi_rank is smaller than the number of values")
+}
+
+pub(super) fn compare(a: &RawVal, b: &RawVal) -> Ordering {
+    match (as_f64(a), as_f64(b)) {
+        (Some(a), Some(b)) => OrderedFloat(a).cmp(&OrderedFloat(b)),
+        _ => a.cmp(b),
+    }
+}
+
+pub(super) fn as_f64(val: &RawVal) -> Option<f64> {
+    match *val {
+        RawVal::Int(i) => Some(i as f64),
+        RawVal::Float(f) => Some(f.0),
+        RawVal::Str(_) | RawVal::Null => None,
+    }
+}
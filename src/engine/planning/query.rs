@@ -1,6 +1,9 @@
+use crate::engine::planning::grouping;
+use crate::engine::planning::simplify::simplify;
 use crate::engine::*;
 use crate::ingest::raw_val::RawVal;
 use crate::mem_store::column::DataSource;
+use crate::mem_store::dictionary::DictionarySections;
 use crate::syntax::expression::*;
 use crate::syntax::limit::*;
 use crate::QueryError;
@@ -11,7 +14,7 @@ use std::ops::Range;
 use std::sync::Arc;
 use std::u64;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ColumnInfo {
     pub expr: Expr,
     pub name: String,
@@ -20,7 +23,7 @@ pub struct ColumnInfo {
 /// NormalFormQuery observes the following invariants:
 /// - none of the expressions contain aggregation functions
 /// - if aggregate.len() > 0 then order_by.len() == 0 and vice versa
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct NormalFormQuery {
     // Every projection is an expression that does not contain any aggregation functions
     pub projection: Vec<ColumnInfo>,
@@ -29,6 +32,8 @@ pub struct NormalFormQuery {
     pub filter: Expr,
     pub order_by: Vec<(Expr, bool)>,
     pub limit: LimitClause,
+    // Appends the index of each selected row within the partition to the projection, see `late_materialized`
+    pub row_ids: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +53,7 @@ pub enum ResultColumn {
 
 impl NormalFormQuery {
     #[inline(never)] // produces more useful profiles
+    #[allow(clippy::too_many_arguments)]
     pub fn run<'a>(
         &self,
         columns: &'a HashMap<String, Arc<dyn DataSource>>,
@@ -56,25 +62,16 @@ impl NormalFormQuery {
         partition: usize,
         partition_range: Range<usize>,
         batch_size: usize,
+        operator_fusion: bool,
     ) -> Result<(BatchResult<'a>, Option<String>), QueryError> {
         let limit = (self.limit.limit + self.limit.offset) as usize;
         let mut planner = QueryPlanner::default();
 
-        let (filter_plan, _) = QueryPlan::compile_expr(
-            &self.filter,
-            Filter::None,
-            columns,
-            partition_range.len(),
-            &mut planner,
-        )?;
-        let mut filter = match filter_plan.tag {
-            EncodingType::U8 => Filter::U8(filter_plan.u8()?),
-            EncodingType::NullableU8 => Filter::NullableU8(filter_plan.nullable_u8()?),
-            _ => Filter::None,
-        };
+        let mut filter = self.compile_filter(columns, partition_range.len(), &mut planner)?;
 
         // Sorting
         let mut sort_indices = None;
+        let mut bounded = false;
         for (plan, desc) in self.order_by.iter().rev() {
             let (ranking, _) = query_plan::order_preserving(
                 QueryPlan::compile_expr(
@@ -94,6 +91,7 @@ impl NormalFormQuery {
                 && self.order_by.len() == 1
                 && !ranking.is_constant()
             {
+                bounded = true;
                 planner.top_n(ranking, limit, *desc)
             } else {
                 // PERF: sort directly if only single column selected
@@ -106,6 +104,11 @@ impl NormalFormQuery {
             };
             sort_indices = Some(indices)
         }
+        // Rows past the limit of each partition are never part of the result, so they are dropped before projection
+        // instead of being passed on to `combine`
+        if !bounded && limit < partition_range.len() {
+            sort_indices = sort_indices.map(|indices| planner.truncate(indices, limit));
+        }
         if let Some(sort_indices) = sort_indices {
             filter = match filter {
                 Filter::U8(where_true) => {
@@ -125,28 +128,14 @@ impl NormalFormQuery {
             };
         }
 
-        let mut select = Vec::new();
-        for col_info in &self.projection {
-            let (mut plan, plan_type) = QueryPlan::compile_expr(
-                &col_info.expr,
-                filter,
-                columns,
-                partition_range.len(),
-                &mut planner,
-            )?;
-            if let Some(codec) = plan_type.codec {
-                plan = codec.decode(plan, &mut planner);
-            }
-            if plan.is_nullable() {
-                plan = planner.fuse_nulls(plan);
-            }
-            plan = planner.collect(
-                plan,
-                &col_info
-                    .name
-                    .clone(),
-            );
-            select.push(plan.any());
+        let mut select =
+            self.compile_projection(filter, columns, partition_range.len(), &mut planner)?;
+        if self.row_ids {
+            let indices = match filter {
+                Filter::Indices(indices) => indices,
+                _ => return Err(fatal!("Row ids require ORDER BY clause")),
+            };
+            select.push(planner.collect(indices.into(), "_row").any());
         }
         let mut order_by = Vec::new();
         for (i, (expr, desc)) in self.order_by.iter().enumerate() {
@@ -170,7 +159,7 @@ impl NormalFormQuery {
         for c in columns {
             debug!("{}: {:?}", partition, c);
         }
-        let mut executor = planner.prepare(vec![], batch_size)?;
+        let mut executor = planner.prepare(vec![], batch_size, operator_fusion)?;
         let mut results = executor.prepare(NormalFormQuery::column_data(columns));
         debug!("{:#}", &executor);
         executor.run(partition_range.len(), &mut results, show)?;
@@ -187,6 +176,7 @@ impl NormalFormQuery {
                 batch_count: 1,
                 show,
                 unsafe_referenced_buffers: results.collect_pinned(),
+                dictionaries: vec![],
             },
             if explain {
                 Some(format!("{}", executor))
@@ -196,7 +186,153 @@ impl NormalFormQuery {
         ))
     }
 
+    fn compile_filter(
+        &self,
+        columns: &HashMap<String, Arc<dyn DataSource>>,
+        column_len: usize,
+        planner: &mut QueryPlanner,
+    ) -> Result<Filter, QueryError> {
+        // Filters that never hold have been folded into a constant by `Query::simplify`
+        if is_always_false(&self.filter) {
+            let filter = planner.constant_expand(0, column_len, EncodingType::U8);
+            return Ok(Filter::U8(filter.u8()?));
+        }
+        // Expensive predicates ordered last by `order_conjuncts` are only evaluated on rows that satisfy all others
+        let conjuncts = conjuncts(&self.filter);
+        let split = conjuncts
+            .iter()
+            .rposition(|predicate| !is_deferrable(predicate))
+            .map_or(1, |i| i + 1);
+        let (eager, deferred) = conjuncts.split_at(split.min(conjuncts.len()));
+        let eager = eager
+            .iter()
+            .map(|&predicate| predicate.clone())
+            .reduce(|lhs, rhs| Expr::func(Func2Type::And, lhs, rhs))
+            .unwrap();
+        let (filter_plan, _) =
+            QueryPlan::compile_expr(&eager, Filter::None, columns, column_len, planner)?;
+        let filter = match filter_plan.tag {
+            EncodingType::U8 => Filter::U8(filter_plan.u8()?),
+            EncodingType::NullableU8 => Filter::NullableU8(filter_plan.nullable_u8()?),
+            _ => Filter::None,
+        };
+        if deferred.is_empty() || column_len == 0 {
+            return Ok(filter);
+        }
+
+        let buffer = planner.null_vec(column_len, EncodingType::Null);
+        let indices = planner.indices(buffer);
+        let mut selected = match filter {
+            Filter::U8(where_true) => planner.filter(indices.into(), where_true).usize()?,
+            Filter::NullableU8(where_true) => planner
+                .nullable_filter(indices.into(), where_true)
+                .usize()?,
+            _ => indices,
+        };
+        for &predicate in deferred {
+            let filter = Filter::Indices(selected);
+            let (plan, _) =
+                QueryPlan::compile_expr(predicate, filter, columns, column_len, planner)?;
+            selected = match plan.tag {
+                EncodingType::U8 => planner.filter(selected.into(), plan.u8()?),
+                EncodingType::NullableU8 => {
+                    planner.nullable_filter(selected.into(), plan.nullable_u8()?)
+                }
+                _ => bail!(QueryError::TypeError, "Non-boolean filter {:?}", predicate),
+            }
+            .usize()?;
+        }
+        let max_index = planner.scalar_i64(column_len as i64 - 1, true);
+        Ok(Filter::U8(planner.exists(selected.into(), max_index)))
+    }
+
+    /// Evaluates the projection for the rows at `indices` of the partition, in the same order.
+    pub fn materialize<'a>(
+        &self,
+        columns: &'a HashMap<String, Arc<dyn DataSource>>,
+        indices: Vec<usize>,
+        partition_range: Range<usize>,
+        batch_size: usize,
+        operator_fusion: bool,
+    ) -> Result<BatchResult<'a>, QueryError> {
+        let mut planner = QueryPlanner::default();
+        let indices_len = indices.len();
+        let filter = Filter::Indices(planner.constant_vec(0, EncodingType::USize).usize()?);
+        let select =
+            self.compile_projection(filter, columns, partition_range.len(), &mut planner)?;
+
+        let mut executor = planner.prepare(
+            vec![Box::new(indices) as BoxedData],
+            batch_size,
+            operator_fusion,
+        )?;
+        let mut results = executor.prepare(NormalFormQuery::column_data(columns));
+        executor.run(partition_range.len(), &mut results, false)?;
+        let (columns, projection, _, _) = results.collect_aliased(&select, &[], &[]);
+        let result = BatchResult {
+            columns,
+            projection,
+            aggregations: vec![],
+            order_by: vec![],
+            level: 0,
+            scanned_range: partition_range,
+            batch_count: 1,
+            show: false,
+            unsafe_referenced_buffers: results.collect_pinned(),
+            dictionaries: vec![],
+        };
+        ensure!(
+            result.len() == indices_len || result.columns.is_empty(),
+            "Materialized {} of {} rows",
+            result.len(),
+            indices_len
+        );
+        Ok(result)
+    }
+
+    /// Rewrites a query with `ORDER BY` clause to select only the index of each row instead of its projection. Only the
+    /// sort columns and row indices are merged across partitions, and `materialize` evaluates the projection for just
+    /// the rows of the final result.
+    pub fn late_materialized(&self) -> Option<NormalFormQuery> {
+        // Only pays off if there are columns besides the sort columns
+        if self.order_by.is_empty()
+            || !self.aggregate.is_empty()
+            || self.projection.len() <= self.order_by.len()
+        {
+            return None;
+        }
+        Some(NormalFormQuery {
+            projection: vec![],
+            row_ids: true,
+            ..self.clone()
+        })
+    }
+
+    fn compile_projection(
+        &self,
+        filter: Filter,
+        columns: &HashMap<String, Arc<dyn DataSource>>,
+        len: usize,
+        planner: &mut QueryPlanner,
+    ) -> Result<Vec<BufferRef<Any>>, QueryError> {
+        let mut select = Vec::new();
+        for col_info in &self.projection {
+            let (mut plan, plan_type) =
+                QueryPlan::compile_expr(&col_info.expr, filter, columns, len, planner)?;
+            if let Some(codec) = plan_type.codec {
+                plan = codec.decode(plan, planner);
+            }
+            if plan.is_nullable() {
+                plan = planner.fuse_nulls(plan);
+            }
+            plan = planner.collect(plan, &col_info.name);
+            select.push(plan.any());
+        }
+        Ok(select)
+    }
+
     #[inline(never)] // produces more useful profiles
+    #[allow(clippy::too_many_arguments)]
     pub fn run_aggregate<'a>(
         &self,
         columns: &'a HashMap<String, Arc<dyn DataSource>>,
@@ -205,35 +341,27 @@ impl NormalFormQuery {
         partition: usize,
         partition_range: Range<usize>,
         batch_size: usize,
+        operator_fusion: bool,
+        memory_budget: Option<&Arc<MemoryBudget>>,
     ) -> Result<(BatchResult<'a>, Option<String>), QueryError> {
         let mut qp = QueryPlanner::default();
 
         // Filter
-        let (filter_plan, filter_type) = QueryPlan::compile_expr(
-            &self.filter,
-            Filter::None,
-            columns,
-            partition_range.len(),
-            &mut qp,
-        )?;
-        let filter = match filter_type.encoding_type() {
-            EncodingType::U8 => Filter::U8(filter_plan.u8()?),
-            EncodingType::NullableU8 => Filter::NullableU8(filter_plan.nullable_u8()?),
-            _ => Filter::None,
-        };
+        let filter = self.compile_filter(columns, partition_range.len(), &mut qp)?;
 
         // Combine all group by columns into a single decodable grouping key
+        let grouping_exprs = self
+            .projection
+            .iter()
+            .map(|col_info| col_info.expr.clone())
+            .collect::<Vec<_>>();
         let (
             (raw_grouping_key, is_raw_grouping_key_order_preserving),
             max_grouping_key,
             decode_plans,
             encoded_group_by_placeholder,
         ) = query_plan::compile_grouping_key(
-            &self
-                .projection
-                .iter()
-                .map(|col_info| col_info.expr.clone())
-                .collect::<Vec<_>>(),
+            &grouping_exprs,
             filter,
             columns,
             partition_range.len(),
@@ -242,24 +370,53 @@ impl NormalFormQuery {
 
         // Reduce cardinality of grouping key if necessary and perform grouping
         // PERF: also determine and use is_dense. always true for hashmap, depends on group by columns for raw.
-        let (encoded_group_by_column,
+        let strategy = grouping::choose_grouping(
+            partition_range.len(),
+            max_grouping_key,
+            grouping::estimate_groups(&grouping_exprs, columns),
+            grouping::is_sortable(raw_grouping_key.tag),
+            grouping::is_sorted(&grouping_exprs, columns),
+        );
+        let (
+            encoded_group_by_column,
             grouping_key,
             is_grouping_key_order_preserving,
-            aggregation_cardinality) =
-        // PERF: refine criterion
-            if max_grouping_key < 1 << 16 {
+            aggregation_cardinality,
+        ) = match strategy {
+            GroupingStrategy::Direct => {
                 let max_grouping_key_buf = qp.scalar_i64(max_grouping_key, true);
-                (None,
-                 raw_grouping_key,
-                 is_raw_grouping_key_order_preserving,
-                 max_grouping_key_buf)
-            } else {
-                query_plan::prepare_hashmap_grouping(
+                (
+                    None,
                     raw_grouping_key,
-                    decode_plans.len(),
-                    max_grouping_key as usize,
-                    &mut qp)?
-            };
+                    is_raw_grouping_key_order_preserving,
+                    max_grouping_key_buf,
+                )
+            }
+            GroupingStrategy::HashMap => query_plan::prepare_hashmap_grouping(
+                raw_grouping_key,
+                decode_plans.len(),
+                max_grouping_key as usize,
+                &mut qp,
+            )?,
+            GroupingStrategy::Sort => {
+                let (unique, grouping_key, cardinality) = qp.sort_grouping(raw_grouping_key);
+                (
+                    Some(unique),
+                    grouping_key.into(),
+                    is_raw_grouping_key_order_preserving,
+                    cardinality,
+                )
+            }
+            GroupingStrategy::Sorted => {
+                let (unique, grouping_key, cardinality) = qp.sorted_grouping(raw_grouping_key);
+                (
+                    Some(unique),
+                    grouping_key.into(),
+                    is_raw_grouping_key_order_preserving,
+                    cardinality,
+                )
+            }
+        };
 
         // Aggregators
         let mut aggregation_results = Vec::new();
@@ -362,8 +519,20 @@ impl NormalFormQuery {
 
         //  Reconstruct all group by columns from grouping
         let mut grouping_columns = Vec::with_capacity(decode_plans.len());
-        for (decode_plan, _t) in decode_plans {
+        let mut dictionaries = Vec::with_capacity(decode_plans.len());
+        for (decode_plan, t) in decode_plans {
             grouping_columns.push(decode_plan);
+            // Group by columns that still contain shared dictionary codes are decoded after merging
+            dictionaries.push(match t.codec {
+                Some(codec) if codec.is_shared_dictionary() => {
+                    let sections = columns[codec.column_name()].data_sections();
+                    Some(Arc::new(DictionarySections {
+                        offset_len: sections[1].cast_ref_u64().to_vec(),
+                        backing_store: sections[2].cast_ref_u8().to_vec(),
+                    }))
+                }
+                _ => None,
+            });
         }
 
         // If the grouping is not order preserving, we need to sort all output columns by using the ordering constructed from the decoded group by columns
@@ -422,8 +591,9 @@ impl NormalFormQuery {
         for c in columns {
             debug!("{}: {:?}", partition, c);
         }
-        let mut executor = qp.prepare(vec![], batch_size)?;
+        let mut executor = qp.prepare(vec![], batch_size, operator_fusion)?;
         let mut results = executor.prepare(NormalFormQuery::column_data(columns));
+        results.set_memory_budget(memory_budget.cloned());
         debug!("{:#}", &executor);
         executor.run(partition_range.len(), &mut results, show)?;
         let (columns, projection, aggregations, _) = results.collect_aliased(
@@ -445,6 +615,7 @@ impl NormalFormQuery {
             batch_count: 1,
             show,
             unsafe_referenced_buffers: results.collect_pinned(),
+            dictionaries,
         };
         if let Err(err) = batch.validate() {
             warn!("Query result failed validation (partition {}): {}\n{:#}\nGroup By: {:?}\nSelect: {:?}",
@@ -543,6 +714,7 @@ impl Query {
                         limit: u64::MAX,
                         offset: 0,
                     },
+                    row_ids: false,
                 },
                 Some(NormalFormQuery {
                     projection: final_projection,
@@ -550,6 +722,7 @@ impl Query {
                     filter: Expr::Const(RawVal::Int(1)),
                     order_by: final_order_by,
                     limit: self.limit.clone(),
+                    row_ids: false,
                 }),
                 (0..final_projection_len).map(ResultColumn::Proj).collect(),
             )
@@ -561,6 +734,7 @@ impl Query {
                     filter: self.filter.clone(),
                     order_by: self.order_by.clone(),
                     limit: self.limit.clone(),
+                    row_ids: false,
                 },
                 None,
                 final_select_ordering,
@@ -604,13 +778,21 @@ impl Query {
                     aggregates1,
                 )
             }
+            Expr::Quantile(_, _) => bail!(
+                QueryError::NotImplemented,
+                "Quantiles can only be used as top level expression in the select clause"
+            ),
+            Expr::Window(_) => bail!(
+                QueryError::NotImplemented,
+                "Window functions can only be used as top level expression in the select clause"
+            ),
             Expr::Const(_) | Expr::ColName(_) => (expr.clone(), vec![]),
         })
     }
 
     pub fn ensure_no_aggregates(expr: &Expr) -> Result<(), QueryError> {
         match expr {
-            Expr::Aggregate(_, _) => {
+            Expr::Aggregate(_, _) | Expr::Quantile(_, _) | Expr::Window(_) => {
                 bail!(QueryError::TypeError, "Nested aggregates found.")
             }
             Expr::Func1(_, expr) => {
@@ -625,6 +807,17 @@ impl Query {
         Ok(())
     }
 
+    /// Folds constant subexpressions and removes predicates that are always true or false, see `simplify`.
+    pub fn simplify(&mut self) {
+        for col_info in &mut self.select {
+            col_info.expr = simplify(&col_info.expr);
+        }
+        self.filter = simplify(&self.filter);
+        for (expr, _) in &mut self.order_by {
+            *expr = simplify(expr);
+        }
+    }
+
     pub fn is_select_star(&self) -> bool {
         if self.select.len() == 1 {
             matches!(self.select[0].expr, Expr::ColName(ref colname) if colname == "*")
@@ -645,6 +838,20 @@ impl Query {
         colnames
     }
 
+    /// Evaluates `select` for all rows of `table`, ordered by row offset.
+    pub fn project(table: &str, select: Vec<ColumnInfo>) -> Query {
+        Query {
+            select,
+            table: table.to_string(),
+            filter: Expr::Const(RawVal::Int(1)),
+            order_by: vec![],
+            limit: LimitClause {
+                limit: u64::MAX,
+                offset: 0,
+            },
+        }
+    }
+
     pub fn read_column(table: &str, column: &str) -> Query {
         Query {
             select: vec![ColumnInfo {
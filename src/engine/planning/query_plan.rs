@@ -128,6 +128,31 @@ pub enum QueryPlan {
         #[output]
         decoded: BufferRef<Scalar<i64>>,
     },
+    /// Determines the index of the first entry of a sorted dictionary that is greater than or equal to a string
+    /// constant, or the index of the last entry that is less than or equal to it if `upper` is set.
+    DictBound {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        constant: BufferRef<Scalar<&'static str>>,
+        upper: bool,
+        #[output]
+        bound: BufferRef<Scalar<i64>>,
+    },
+    /// Evaluates `regex` once for each entry of a dictionary.
+    DictRegex {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        regex: String,
+        #[output]
+        matches: BufferRef<u8>,
+    },
+    /// Looks up the value of each dictionary index in `members`, which holds one value for each dictionary entry.
+    DictMembership {
+        indices: TypedBufferRef,
+        members: BufferRef<u8>,
+        #[output]
+        is_member: BufferRef<u8>,
+    },
     /// Casts `input` to the specified type.
     Cast {
         input: TypedBufferRef,
@@ -157,12 +182,36 @@ pub enum QueryPlan {
         #[output]
         unpacked_strings: BufferRef<&'static str>,
     },
+    /// Decodes FSST compressed strings using the serialized symbol table `symbols`.
+    FsstDecode {
+        codes: BufferRef<u8>,
+        symbols: BufferRef<u8>,
+        total_bytes: usize,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        decoded: BufferRef<&'static str>,
+    },
+    /// Decodes XOR encoded floats.
+    XorDecode {
+        encoded: BufferRef<u64>,
+        #[output(t = "base=provided")]
+        decoded: TypedBufferRef,
+    },
     /// Decodes delta encoded integers.
     DeltaDecode {
         plan: TypedBufferRef,
         #[output]
         delta_decoded: BufferRef<i64>,
     },
+    /// Repeats each of the `values` the number of times given by the corresponding entry in `run_lengths`.
+    RunLengthDecode {
+        values: TypedBufferRef,
+        run_lengths: BufferRef<u32>,
+        decoded_len: usize,
+        #[output(t = "base=values")]
+        decoded: TypedBufferRef,
+    },
     HashMapGrouping {
         raw_grouping_key: TypedBufferRef,
         max_cardinality: usize,
@@ -173,6 +222,26 @@ pub enum QueryPlan {
         #[output]
         cardinality: BufferRef<Scalar<i64>>,
     },
+    /// Assigns group indices by sorting the grouping keys, `unique` contains the grouping keys in sorted order.
+    SortGrouping {
+        raw_grouping_key: TypedBufferRef,
+        #[output(t = "base=raw_grouping_key")]
+        unique: TypedBufferRef,
+        #[output]
+        grouping_key: BufferRef<u32>,
+        #[output]
+        cardinality: BufferRef<Scalar<i64>>,
+    },
+    /// Assigns group indices to sorted grouping keys in a single pass, `unique` contains the grouping keys in order.
+    SortedGrouping {
+        raw_grouping_key: TypedBufferRef,
+        #[output(t = "base=raw_grouping_key")]
+        unique: TypedBufferRef,
+        #[output]
+        grouping_key: BufferRef<u32>,
+        #[output]
+        cardinality: BufferRef<Scalar<i64>>,
+    },
     HashMapGroupingValRows {
         raw_grouping_key: BufferRef<ValRows<'static>>,
         columns: usize,
@@ -448,6 +517,13 @@ pub enum QueryPlan {
         #[output]
         top_n: BufferRef<usize>,
     },
+    /// Outputs the first `n` elements of `indices`.
+    Truncate {
+        indices: BufferRef<usize>,
+        n: usize,
+        #[output]
+        truncated: BufferRef<usize>,
+    },
     /// Outputs all elements in `plan` where the index corresponds to an entry in `indices`.
     Select {
         plan: TypedBufferRef,
@@ -926,6 +1002,11 @@ impl QueryPlan {
     ) -> Result<(TypedBufferRef, Type), QueryError> {
         use self::Expr::*;
         use self::Func2Type::*;
+        if let (Filter::None, Func1(..) | Func2(..)) = (filter, expr) {
+            if let Some(plan) = QueryPlan::compile_expr_on_runs(expr, columns, planner)? {
+                return Ok(plan);
+            }
+        }
         Ok(match *expr {
             ColName(ref name) => match columns.get::<str>(name.as_ref()) {
                 Some(c) => {
@@ -1036,17 +1117,14 @@ impl QueryPlan {
                         .replace_all(&pattern, "%")
                         .to_string();
                     pattern = format!("^{}$", pattern);
-                    let (mut plan, t) =
+                    let (plan, t) =
                         QueryPlan::compile_expr(expr, filter, columns, column_len, planner)?;
                     if t.decoded != BasicType::String {
                         bail!(QueryError::TypeError,
                                   "Expected expression of type `String` as first argument to LIKE. Actual: {:?}", t)
                     }
-                    if let Some(codec) = t.codec {
-                        plan = codec.decode(plan, planner);
-                    }
                     let type_out = Type::unencoded(BasicType::Boolean).mutable();
-                    (planner.regex(plan.str()?, &pattern).into(), type_out)
+                    (regex_matches(plan, t, &pattern, planner)?.into(), type_out)
                 }
                 _ => bail!(
                     QueryError::TypeError,
@@ -1069,16 +1147,13 @@ impl QueryPlan {
                     Regex::new(regex.as_str()).map_err(|e| {
                         QueryError::TypeError(format!("`{}` is not a valid regex: {}", regex, e))
                     })?;
-                    let (mut plan, t) =
+                    let (plan, t) =
                         QueryPlan::compile_expr(expr, filter, columns, column_len, planner)?;
                     if t.decoded != BasicType::String {
                         bail!(QueryError::TypeError, "Expected expression of type `String` as first argument to regex. Actual: {:?}", t)
                     }
-                    if let Some(codec) = t.codec {
-                        plan = codec.decode(plan, planner);
-                    }
                     let type_out = Type::unencoded(BasicType::Boolean).mutable();
-                    (planner.regex(plan.str()?, regex.as_str()).into(), type_out)
+                    (regex_matches(plan, t, regex, planner)?.into(), type_out)
                 }
                 _ => bail!(
                     QueryError::TypeError,
@@ -1110,48 +1185,38 @@ impl QueryPlan {
                     ),
                 };
 
+                // `x + 0`, `x - 0`, `x * 1` and `x / 1` are just `x`, which is only known to be a number at this point
+                match (function, &**lhs, &**rhs) {
+                    (Add | Subtract, _, Const(RawVal::Int(0)))
+                    | (Multiply | Divide, _, Const(RawVal::Int(1))) => {
+                        return Ok((plan_lhs, type_lhs))
+                    }
+                    (Add, Const(RawVal::Int(0)), _) | (Multiply, Const(RawVal::Int(1)), _) => {
+                        return Ok((plan_rhs, type_rhs))
+                    }
+                    _ => {}
+                }
+
+                let mut encoded = false;
                 if declaration.encoding_invariance && type_lhs.is_scalar && type_rhs.is_encoded() {
-                    plan_lhs = if type_rhs.decoded == BasicType::Integer {
-                        if let QueryPlan::ScalarI64 { value, .. } = *planner.resolve(&plan_lhs) {
-                            planner
-                                .scalar_i64(type_rhs.codec.unwrap().encode_int(value), true)
-                                .into()
-                        } else {
-                            panic!("whoops");
-                        }
-                    } else if type_rhs.decoded == BasicType::String {
-                        type_rhs
-                            .codec
-                            .clone()
-                            .unwrap()
-                            .encode_str(plan_lhs.scalar_str()?, planner)
-                            .into()
-                    } else {
-                        panic!("whoops");
-                    };
+                    if let Some(constant) =
+                        encode_comparison_constant(function, true, plan_lhs, &type_rhs, planner)?
+                    {
+                        plan_lhs = constant;
+                        encoded = true;
+                    }
                 } else if declaration.encoding_invariance
                     && type_rhs.is_scalar
                     && type_lhs.is_encoded()
                 {
-                    plan_rhs = if type_lhs.decoded == BasicType::Integer {
-                        if let QueryPlan::ScalarI64 { value, .. } = *planner.resolve(&plan_rhs) {
-                            planner
-                                .scalar_i64(type_lhs.codec.unwrap().encode_int(value), true)
-                                .into()
-                        } else {
-                            panic!("whoops");
-                        }
-                    } else if type_lhs.decoded == BasicType::String {
-                        type_lhs
-                            .codec
-                            .clone()
-                            .unwrap()
-                            .encode_str(plan_rhs.scalar_str()?, planner)
-                            .into()
-                    } else {
-                        panic!("whoops");
-                    };
-                } else {
+                    if let Some(constant) =
+                        encode_comparison_constant(function, false, plan_rhs, &type_lhs, planner)?
+                    {
+                        plan_rhs = constant;
+                        encoded = true;
+                    }
+                }
+                if !encoded {
                     if let Some(codec) = type_lhs.codec {
                         plan_lhs = codec.decode(plan_lhs, planner);
                     }
@@ -1269,6 +1334,108 @@ impl QueryPlan {
             ref x => bail!(QueryError::NotImplemented, "{:?}.compile_vec()", x),
         })
     }
+
+    /// Evaluates `expr` once for each run of values if it only references a single run length encoded column, and
+    /// expands the result to one value per row.
+    fn compile_expr_on_runs(
+        expr: &Expr,
+        columns: &HashMap<String, Arc<dyn DataSource>>,
+        planner: &mut QueryPlanner,
+    ) -> Result<Option<(TypedBufferRef, Type)>, QueryError> {
+        let (name, column) = match single_column(expr) {
+            Some(Some(name)) => match columns.get(name) {
+                Some(column) => (name, column),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let (values, section) = match RunLengthValues::new(column) {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        let runs = values.len();
+        let mut run_columns = HashMap::<String, Arc<dyn DataSource>>::default();
+        run_columns.insert(name.to_string(), Arc::new(values));
+        let (plan, t) = QueryPlan::compile_expr(expr, Filter::None, &run_columns, runs, planner)?;
+        let run_lengths = planner
+            .column_section(name, section, None, EncodingType::U32)
+            .u32()?;
+        let decoded = planner.run_length_decode(plan, run_lengths, column.len());
+        Ok(Some((decoded, t)))
+    }
+}
+
+/// Returns the name of the only column referenced by `expr`, or `Some(None)` if `expr` is constant. Returns `None` if
+/// `expr` references multiple columns or is not evaluated value by value.
+fn single_column(expr: &Expr) -> Option<Option<&str>> {
+    match expr {
+        Expr::ColName(name) => Some(Some(name.as_str())),
+        Expr::Const(_) => Some(None),
+        Expr::Func1(_, inner) => single_column(inner),
+        Expr::Func2(_, lhs, rhs) => match (single_column(lhs)?, single_column(rhs)?) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => None,
+            (lhs, rhs) => Some(lhs.or(rhs)),
+        },
+        _ => None,
+    }
+}
+
+/// Matches the strings of `plan` against `regex`. Dictionary encoded strings are matched once for each dictionary entry.
+fn regex_matches(
+    plan: TypedBufferRef,
+    t: Type,
+    regex: &str,
+    planner: &mut QueryPlanner,
+) -> Result<BufferRef<u8>, QueryError> {
+    match t.codec {
+        Some(codec) => match codec.dictionary_regex(plan, regex, planner) {
+            Some(matches) => Ok(matches),
+            None => {
+                let decoded = codec.decode(plan, planner);
+                Ok(planner.regex(decoded.str()?, regex))
+            }
+        },
+        None => Ok(planner.regex(plan.str()?, regex)),
+    }
+}
+
+/// Encodes the scalar `constant` such that comparing it with `function` to values of type `t` gives the same result as
+/// comparing it to the decoded values. Returns `None` if the values have to be decoded instead.
+fn encode_comparison_constant(
+    function: Func2Type,
+    constant_is_lhs: bool,
+    constant: TypedBufferRef,
+    t: &Type,
+    planner: &mut QueryPlanner,
+) -> Result<Option<TypedBufferRef>, QueryError> {
+    let codec = match t.codec {
+        Some(ref codec) => codec,
+        None => return Ok(None),
+    };
+    let encoded = match t.decoded {
+        BasicType::Integer => match *planner.resolve(&constant) {
+            QueryPlan::ScalarI64 { value, .. } => codec.encode_int(value),
+            _ => None,
+        }
+        .map(|value| planner.scalar_i64(value, true)),
+        BasicType::String => {
+            let constant = constant.scalar_str()?;
+            match function {
+                Func2Type::Equals | Func2Type::NotEquals => codec.encode_str(constant, planner),
+                // `x < c` holds for all indices smaller than the first entry `>= c`, `c < x` for all indices greater
+                // than the last entry `<= c`
+                Func2Type::LT | Func2Type::GTE => {
+                    codec.dictionary_bound(constant, constant_is_lhs, planner)
+                }
+                Func2Type::LTE | Func2Type::GT => {
+                    codec.dictionary_bound(constant, !constant_is_lhs, planner)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    Ok(encoded.map(TypedBufferRef::from))
 }
 
 fn encoding_range(plan: &TypedBufferRef, qp: &QueryPlanner) -> Option<(i64, i64)> {
@@ -1327,8 +1494,9 @@ fn encoding_range(plan: &TypedBufferRef, qp: &QueryPlanner) -> Option<(i64, i64)
         Cast { ref input, .. } => encoding_range(input, qp),
         LZ4Decode { bytes, .. } => encoding_range(&bytes.into(), qp),
         DeltaDecode { ref plan, .. } => encoding_range(plan, qp),
+        RunLengthDecode { ref values, .. } => encoding_range(values, qp),
         AssembleNullable { ref data, .. } => encoding_range(data, qp),
-        UnpackStrings { .. } | UnhexpackStrings { .. } | Length { .. } => None,
+        UnpackStrings { .. } | UnhexpackStrings { .. } | FsstDecode { .. } | Length { .. } => None,
         ref plan => {
             error!("encoding_range not implement for {:?}", plan);
             None
@@ -1414,14 +1582,13 @@ pub fn compile_grouping_key(
                     let sum = planner.add(decoded_group_by, offset.into());
                     decoded_group_by = planner.cast(sum, gk_type.encoding_type());
                 }
-                if let Some(codec) = gk_type.codec.clone() {
-                    decoded_group_by = codec.decode(decoded_group_by, planner)
-                }
+                let (decoded_group_by, decoded_type) =
+                    decode_group_by(decoded_group_by, &gk_type, planner);
 
                 (
-                    (gk_plan, gk_type.is_order_preserving()),
+                    (gk_plan, is_group_by_order_preserving(&gk_type)),
                     max_cardinality,
-                    vec![(decoded_group_by, gk_type.decoded())],
+                    vec![(decoded_group_by, decoded_type)],
                     encoded_group_by_placeholder,
                 )
             },
@@ -1439,18 +1606,15 @@ pub fn compile_grouping_key(
         for (i, expr) in exprs.iter().enumerate() {
             let (query_plan, plan_type) =
                 QueryPlan::compile_expr(expr, filter, columns, partition_len, planner)?;
-            order_preserving = order_preserving && plan_type.is_order_preserving();
+            order_preserving = order_preserving && is_group_by_order_preserving(&plan_type);
             let vals = planner.cast(query_plan, EncodingType::Val).val()?;
             pack.push(planner.val_rows_pack(vals, exprs.len(), i));
 
             let vals = planner
                 .val_rows_unpack(encoded_group_by_placeholder.val_rows()?, exprs.len(), i)
                 .into();
-            let mut decode_plan = planner.cast(vals, query_plan.tag);
-            if let Some(codec) = plan_type.codec.clone() {
-                decode_plan = codec.decode(decode_plan, planner);
-            }
-            decode_plans.push((decode_plan, plan_type.decoded()));
+            let decode_plan = planner.cast(vals, query_plan.tag);
+            decode_plans.push(decode_group_by(decode_plan, &plan_type, planner));
         }
         Ok((
             (pack[0].into(), order_preserving),
@@ -1489,6 +1653,25 @@ pub fn compile_grouping_key(
     }
 }
 
+/// Decodes a group by column, except for codes into a shared dictionary which are decoded only after results from all
+/// partitions have been merged. Returns the decoded column and its type, which retains the codec in that case.
+fn decode_group_by(
+    plan: TypedBufferRef,
+    t: &Type,
+    planner: &mut QueryPlanner,
+) -> (TypedBufferRef, Type) {
+    match &t.codec {
+        Some(codec) if codec.is_shared_dictionary() => (plan, t.clone()),
+        Some(codec) => (codec.decode(plan, planner), t.decoded()),
+        None => (plan, t.decoded()),
+    }
+}
+
+/// Whether ordering by the grouping key orders by the group by columns as returned by `decode_group_by`.
+fn is_group_by_order_preserving(t: &Type) -> bool {
+    t.is_order_preserving() || t.codec.as_ref().map_or(false, Codec::is_shared_dictionary)
+}
+
 // TODO: return struct
 #[allow(clippy::type_complexity)]
 fn try_bitpacking(
@@ -1544,7 +1727,7 @@ fn try_bitpacking(
             } else {
                 max
             };
-            order_preserving = order_preserving && plan_type.is_order_preserving();
+            order_preserving = order_preserving && is_group_by_order_preserving(&plan_type);
             let adjusted_query_plan = if query_plan.is_nullable() {
                 let fused = planner.fuse_int_nulls(-min + 1, query_plan);
                 if fused.tag != EncodingType::I64 {
@@ -1579,10 +1762,7 @@ fn try_bitpacking(
                 decode_plan = planner.add(decode_plan, offset.into());
             }
             decode_plan = planner.cast(decode_plan, plan_type.encoding_type());
-            if let Some(codec) = plan_type.codec.clone() {
-                decode_plan = codec.decode(decode_plan, planner);
-            }
-            decode_plans.push((decode_plan, plan_type.decoded()));
+            decode_plans.push(decode_group_by(decode_plan, &plan_type, planner));
 
             largest_key += adjusted_max << total_width;
             total_width += bits(adjusted_max);
@@ -1720,11 +1900,35 @@ pub(super) fn prepare<'a>(
             constant,
             decoded,
         } => operator::inverse_dict_lookup(offset_len, backing_store, constant, decoded),
+        QueryPlan::DictBound {
+            offset_len,
+            backing_store,
+            constant,
+            upper,
+            bound,
+        } => operator::dict_bound(offset_len, backing_store, constant, upper, bound),
+        QueryPlan::DictRegex {
+            offset_len,
+            backing_store,
+            regex,
+            matches,
+        } => operator::dict_regex(offset_len, backing_store, &regex, matches),
+        QueryPlan::DictMembership {
+            indices,
+            members,
+            is_member,
+        } => operator::dict_membership(indices, members, is_member)?,
         QueryPlan::Cast { input, casted } => operator::type_conversion(input, casted)?,
         QueryPlan::DeltaDecode {
             plan,
             delta_decoded,
         } => operator::delta_decode(plan, delta_decoded)?,
+        QueryPlan::RunLengthDecode {
+            values,
+            run_lengths,
+            decoded_len,
+            decoded,
+        } => operator::run_length_decode(values, run_lengths, decoded, decoded_len)?,
         QueryPlan::LZ4Decode {
             bytes,
             decoded_len,
@@ -1747,6 +1951,14 @@ pub(super) fn prepare<'a>(
             string_store,
             unpacked_strings,
         ),
+        QueryPlan::FsstDecode {
+            codes,
+            symbols,
+            total_bytes,
+            string_store,
+            decoded,
+        } => operator::fsst_decode(codes, symbols, total_bytes, string_store, decoded),
+        QueryPlan::XorDecode { encoded, decoded } => operator::xor_decode(encoded, decoded.f64()?),
         QueryPlan::HashMapGrouping {
             raw_grouping_key,
             max_cardinality,
@@ -1760,6 +1972,18 @@ pub(super) fn prepare<'a>(
             grouping_key,
             cardinality,
         )?,
+        QueryPlan::SortGrouping {
+            raw_grouping_key,
+            unique,
+            grouping_key,
+            cardinality,
+        } => operator::sort_grouping(raw_grouping_key, unique, grouping_key, cardinality)?,
+        QueryPlan::SortedGrouping {
+            raw_grouping_key,
+            unique,
+            grouping_key,
+            cardinality,
+        } => operator::sorted_grouping(raw_grouping_key, unique, grouping_key, cardinality)?,
         QueryPlan::HashMapGroupingValRows {
             raw_grouping_key,
             max_cardinality,
@@ -1945,6 +2169,11 @@ pub(super) fn prepare<'a>(
             tmp_keys,
             top_n,
         } => operator::top_n(ranking, tmp_keys, n, desc, top_n)?,
+        QueryPlan::Truncate {
+            indices,
+            n,
+            truncated,
+        } => operator::truncate(indices, n, truncated),
         QueryPlan::Connect { input, output } => operator::identity(input, output),
         QueryPlan::Merge {
             lhs,
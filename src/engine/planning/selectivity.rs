@@ -0,0 +1,311 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::ingest::raw_val::RawVal;
+use crate::mem_store::ColumnStats;
+use crate::syntax::expression::*;
+
+/// Predicates whose estimated cost per row is at least this high are only evaluated on rows that satisfy all cheaper
+/// predicates of the filter, see `NormalFormQuery::compile_filter`.
+pub const DEFERRED_COST: f64 = 10.0;
+
+const DEFAULT_EQUALITY_SELECTIVITY: f64 = 0.1;
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+const DEFAULT_PATTERN_SELECTIVITY: f64 = 0.25;
+const DEFAULT_SELECTIVITY: f64 = 0.5;
+
+/// Statistics of a column over a set of partitions.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnStatistics {
+    /// Number of rows in partitions with known statistics
+    pub rows: usize,
+    pub null_count: usize,
+    /// Largest number of distinct values in any partition
+    pub distinct_values: Option<usize>,
+    /// Minimum and maximum value of integer columns
+    pub range: Option<(i64, i64)>,
+}
+
+impl ColumnStatistics {
+    /// Adds the statistics of the column in a partition with `rows` rows.
+    pub fn add(&mut self, rows: usize, stats: Option<ColumnStats>, range: Option<(i64, i64)>) {
+        if let Some(stats) = stats {
+            self.rows += rows;
+            self.null_count += stats.null_count;
+            if let Some(distinct_values) = stats.distinct_values {
+                self.distinct_values = Some(self.distinct_values.unwrap_or(0).max(distinct_values));
+            }
+        }
+        if let Some((min, max)) = range {
+            self.range = Some(match self.range {
+                Some((min0, max0)) => (min0.min(min), max0.max(max)),
+                None => (min, max),
+            });
+        }
+    }
+
+    fn null_fraction(&self) -> f64 {
+        if self.rows == 0 {
+            0.0
+        } else {
+            self.null_count as f64 / self.rows as f64
+        }
+    }
+
+    fn distinct_values(&self) -> Option<f64> {
+        let range = self
+            .range
+            .map(|(min, max)| (max as f64 - min as f64 + 1.0).max(1.0));
+        match (self.distinct_values, range) {
+            (Some(distinct_values), _) => Some(distinct_values.max(1) as f64),
+            (None, range) => range,
+        }
+    }
+}
+
+/// Reorders the conjuncts of `filter` such that the predicates which are most selective relative to their cost are
+/// evaluated first. Predicates are assumed to be independent.
+pub fn order_conjuncts(filter: &Expr, statistics: &HashMap<String, ColumnStatistics>) -> Expr {
+    let conjuncts = conjuncts(filter);
+    if conjuncts.len() < 2 {
+        return filter.clone();
+    }
+    // The expected cost per row of evaluating the remaining predicates is minimized by ascending rank
+    let rank = |predicate: &Expr| {
+        let rejected = 1.0 - selectivity(predicate, statistics);
+        cost(predicate) / rejected.max(1e-6)
+    };
+    let mut ranked = conjuncts
+        .into_iter()
+        .map(|predicate| (rank(predicate), predicate))
+        .collect::<Vec<_>>();
+    ranked.sort_by(|(lhs, _), (rhs, _)| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal));
+    ranked
+        .into_iter()
+        .map(|(_, predicate)| predicate.clone())
+        .reduce(|lhs, rhs| Expr::func(Func2Type::And, lhs, rhs))
+        .unwrap()
+}
+
+/// Splits `filter` into predicates that all have to hold.
+pub fn conjuncts(filter: &Expr) -> Vec<&Expr> {
+    match filter {
+        Expr::Func2(Func2Type::And, lhs, rhs) => {
+            let mut conjuncts = conjuncts(lhs);
+            conjuncts.extend(conjuncts(rhs));
+            conjuncts
+        }
+        _ => vec![filter],
+    }
+}
+
+/// Rough estimate of the relative cost of evaluating `expr` for a single row.
+pub fn cost(expr: &Expr) -> f64 {
+    match expr {
+        Expr::ColName(_) | Expr::Const(_) => 0.0,
+        Expr::Func1(_, expr) => 1.0 + cost(expr),
+        Expr::Func2(Func2Type::Like | Func2Type::NotLike | Func2Type::RegexMatch, lhs, rhs) => {
+            DEFERRED_COST + cost(lhs) + cost(rhs)
+        }
+        Expr::Func2(_, lhs, rhs) => 1.0 + cost(lhs) + cost(rhs),
+        Expr::Aggregate(_, expr) | Expr::Quantile(_, expr) => 1.0 + cost(expr),
+        Expr::Window(window) => 1.0 + cost(&window.expr),
+    }
+}
+
+/// Whether `predicate` is expensive enough to be evaluated only on rows that satisfy all other predicates of a filter.
+pub fn is_deferrable(predicate: &Expr) -> bool {
+    // Null checks on columns without nulls ignore the filter and produce one value per row of the partition
+    fn checks_null(expr: &Expr) -> bool {
+        match expr {
+            Expr::Func1(Func1Type::IsNull | Func1Type::IsNotNull, _) => true,
+            Expr::Func1(_, expr) => checks_null(expr),
+            Expr::Func2(_, lhs, rhs) => checks_null(lhs) || checks_null(rhs),
+            _ => false,
+        }
+    }
+    cost(predicate) >= DEFERRED_COST && !checks_null(predicate)
+}
+
+/// Estimated fraction of rows that satisfy `predicate`.
+pub fn selectivity(predicate: &Expr, statistics: &HashMap<String, ColumnStatistics>) -> f64 {
+    match predicate {
+        Expr::Func2(Func2Type::And, lhs, rhs) => {
+            selectivity(lhs, statistics) * selectivity(rhs, statistics)
+        }
+        Expr::Func2(Func2Type::Or, lhs, rhs) => {
+            let lhs = selectivity(lhs, statistics);
+            let rhs = selectivity(rhs, statistics);
+            lhs + rhs - lhs * rhs
+        }
+        Expr::Func1(Func1Type::Not, predicate) => 1.0 - selectivity(predicate, statistics),
+        Expr::Func1(Func1Type::IsNull, expr) => match &**expr {
+            Expr::ColName(column) => statistics.get(column).map_or(
+                DEFAULT_EQUALITY_SELECTIVITY,
+                ColumnStatistics::null_fraction,
+            ),
+            _ => DEFAULT_EQUALITY_SELECTIVITY,
+        },
+        Expr::Func1(Func1Type::IsNotNull, expr) => {
+            1.0 - selectivity(
+                &Expr::func1(Func1Type::IsNull, (**expr).clone()),
+                statistics,
+            )
+        }
+        Expr::Func2(Func2Type::Like | Func2Type::RegexMatch, _, _) => DEFAULT_PATTERN_SELECTIVITY,
+        Expr::Func2(Func2Type::NotLike, _, _) => 1.0 - DEFAULT_PATTERN_SELECTIVITY,
+        Expr::Func2(function, lhs, rhs) => match (&**lhs, &**rhs) {
+            (Expr::ColName(column), Expr::Const(value)) => {
+                comparison_selectivity(*function, statistics.get(column), value)
+            }
+            (Expr::Const(value), Expr::ColName(column)) => {
+                let function = match function {
+                    Func2Type::LT => Func2Type::GT,
+                    Func2Type::LTE => Func2Type::GTE,
+                    Func2Type::GT => Func2Type::LT,
+                    Func2Type::GTE => Func2Type::LTE,
+                    function => *function,
+                };
+                comparison_selectivity(function, statistics.get(column), value)
+            }
+            _ => default_selectivity(*function),
+        },
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+/// Estimated fraction of rows for which `column <function> value` holds.
+fn comparison_selectivity(
+    function: Func2Type,
+    column: Option<&ColumnStatistics>,
+    value: &RawVal,
+) -> f64 {
+    let column = match column {
+        Some(column) => column,
+        None => return default_selectivity(function),
+    };
+    let non_null = 1.0 - column.null_fraction();
+    let range = match value {
+        RawVal::Int(value) => column.range.map(|range| (range, *value)),
+        _ => None,
+    };
+    let equality = match range {
+        Some(((min, max), value)) if value < min || value > max => 0.0,
+        _ => match column.distinct_values() {
+            Some(distinct_values) => non_null / distinct_values,
+            None => DEFAULT_EQUALITY_SELECTIVITY,
+        },
+    };
+    // Fraction of values smaller than `value`
+    let below = range.map(|((min, max), value)| {
+        let fraction = (value as f64 - min as f64) / (max as f64 - min as f64 + 1.0);
+        fraction.clamp(0.0, 1.0)
+    });
+    let selectivity = match (function, below) {
+        (Func2Type::Equals, _) => equality,
+        (Func2Type::NotEquals, _) => non_null - equality,
+        (Func2Type::LT, Some(below)) => below * non_null,
+        (Func2Type::LTE, Some(below)) => below * non_null + equality,
+        (Func2Type::GT, Some(below)) => (1.0 - below) * non_null - equality,
+        (Func2Type::GTE, Some(below)) => (1.0 - below) * non_null,
+        _ => default_selectivity(function),
+    };
+    selectivity.clamp(0.0, 1.0)
+}
+
+fn default_selectivity(function: Func2Type) -> f64 {
+    match function {
+        Func2Type::Equals => DEFAULT_EQUALITY_SELECTIVITY,
+        Func2Type::NotEquals => 1.0 - DEFAULT_EQUALITY_SELECTIVITY,
+        Func2Type::LT | Func2Type::LTE | Func2Type::GT | Func2Type::GTE => {
+            DEFAULT_RANGE_SELECTIVITY
+        }
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(value: i64) -> Expr {
+        Expr::Const(RawVal::Int(value))
+    }
+
+    fn col(name: &str) -> Expr {
+        Expr::ColName(name.to_string())
+    }
+
+    #[test]
+    fn test_order_conjuncts() {
+        let mut statistics = HashMap::new();
+        let mut x = ColumnStatistics::default();
+        x.add(
+            1000,
+            Some(ColumnStats {
+                distinct_values: Some(1000),
+                null_count: 0,
+                sorted: false,
+            }),
+            Some((0, 999)),
+        );
+        statistics.insert("x".to_string(), x);
+        let mut y = ColumnStatistics::default();
+        y.add(
+            1000,
+            Some(ColumnStats {
+                distinct_values: Some(2),
+                null_count: 0,
+                sorted: false,
+            }),
+            Some((0, 1)),
+        );
+        statistics.insert("y".to_string(), y);
+
+        let like = Expr::func(
+            Func2Type::Like,
+            col("s"),
+            Expr::Const(RawVal::Str("%a%".to_string())),
+        );
+        let y_equals = Expr::func(Func2Type::Equals, col("y"), int(1));
+        let x_equals = Expr::func(Func2Type::Equals, int(7), col("x"));
+        let filter = Expr::func(
+            Func2Type::And,
+            Expr::func(Func2Type::And, like.clone(), y_equals.clone()),
+            x_equals.clone(),
+        );
+        let expected = Expr::func(
+            Func2Type::And,
+            Expr::func(Func2Type::And, x_equals, y_equals),
+            like,
+        );
+        assert_eq!(
+            format!("{:?}", order_conjuncts(&filter, &statistics)),
+            format!("{:?}", expected)
+        );
+    }
+
+    #[test]
+    fn test_comparison_selectivity() {
+        let mut statistics = HashMap::new();
+        let mut x = ColumnStatistics::default();
+        x.add(
+            100,
+            Some(ColumnStats {
+                distinct_values: None,
+                null_count: 50,
+                sorted: false,
+            }),
+            Some((0, 99)),
+        );
+        statistics.insert("x".to_string(), x);
+        let estimate =
+            |function, value| selectivity(&Expr::func(function, col("x"), int(value)), &statistics);
+        assert_eq!(estimate(Func2Type::Equals, 100), 0.0);
+        assert_eq!(estimate(Func2Type::Equals, 10), 0.005);
+        assert_eq!(estimate(Func2Type::LT, 50), 0.25);
+        assert_eq!(estimate(Func2Type::GTE, 0), 0.5);
+        let is_null = Expr::func1(Func1Type::IsNull, col("x"));
+        assert_eq!(selectivity(&is_null, &statistics), 0.5);
+    }
+}
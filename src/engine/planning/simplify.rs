@@ -0,0 +1,271 @@
+use crate::ingest::raw_val::RawVal;
+use crate::syntax::expression::*;
+
+/// Folds constant subexpressions, removes `x + 0` and `x * 1` and removes operands of `AND` and `OR` that are always
+/// true or false. Machine generated queries often contain expressions like `1 = 1 AND ...`, each of which would
+/// otherwise be evaluated by separate operators. This is the only place where constants are folded, the parser
+/// leaves arithmetic on constants such as `now() - interval '7 days'` to this pass.
+pub fn simplify(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Func1(function, inner) => {
+            let inner = simplify(inner);
+            fold1(*function, &inner).unwrap_or_else(|| Expr::func1(*function, inner))
+        }
+        Expr::Func2(function, lhs, rhs) => {
+            let lhs = simplify(lhs);
+            let rhs = simplify(rhs);
+            fold2(*function, &lhs, &rhs).unwrap_or_else(|| Expr::func(*function, lhs, rhs))
+        }
+        Expr::Aggregate(aggregator, inner) => {
+            Expr::Aggregate(*aggregator, Box::new(simplify(inner)))
+        }
+        Expr::Quantile(q, inner) => Expr::Quantile(*q, Box::new(simplify(inner))),
+        Expr::Window(window) => Expr::Window(Box::new(WindowExpr {
+            aggregator: window.aggregator,
+            expr: simplify(&window.expr),
+            partition_by: window.partition_by.iter().map(simplify).collect(),
+            order_by: window
+                .order_by
+                .iter()
+                .map(|(expr, desc)| (simplify(expr), *desc))
+                .collect(),
+            frame: window.frame,
+        })),
+        Expr::ColName(_) | Expr::Const(_) => expr.clone(),
+    }
+}
+
+/// Whether `filter` does not hold for any row.
+pub fn is_always_false(filter: &Expr) -> bool {
+    matches!(filter, Expr::Const(RawVal::Int(0) | RawVal::Null))
+}
+
+fn fold1(function: Func1Type, expr: &Expr) -> Option<Expr> {
+    let value = match expr {
+        Expr::Const(value) => value,
+        _ => return None,
+    };
+    let folded = match (function, value) {
+        (Func1Type::Negate, RawVal::Int(i)) => RawVal::Int(i.checked_neg()?),
+        (Func1Type::Not, RawVal::Int(i)) => boolean(*i == 0),
+        (Func1Type::IsNull, value) => boolean(*value == RawVal::Null),
+        (Func1Type::IsNotNull, value) => boolean(*value != RawVal::Null),
+        _ => return None,
+    };
+    Some(Expr::Const(folded))
+}
+
+fn fold2(function: Func2Type, lhs: &Expr, rhs: &Expr) -> Option<Expr> {
+    if let (Expr::Const(lhs), Expr::Const(rhs)) = (lhs, rhs) {
+        return fold_constants(function, lhs, rhs).map(Expr::Const);
+    }
+    if let Some(operand) = identity_operand(function, lhs, rhs) {
+        return Some(operand.clone());
+    }
+    // Only boolean operands can be removed, anything else is a type error that has to be reported
+    let (constant, other) = match (lhs, rhs) {
+        (Expr::Const(RawVal::Int(value)), other) | (other, Expr::Const(RawVal::Int(value)))
+            if is_predicate(other) =>
+        {
+            (*value != 0, other)
+        }
+        _ => return None,
+    };
+    match (function, constant) {
+        (Func2Type::And, true) | (Func2Type::Or, false) => Some(other.clone()),
+        (Func2Type::And, false) => Some(Expr::Const(boolean(false))),
+        (Func2Type::Or, true) => Some(Expr::Const(boolean(true))),
+        _ => None,
+    }
+}
+
+/// Evaluates `function` on two constants, returns `None` if it cannot be evaluated or results in an error which is
+/// left for the query engine to report.
+fn fold_constants(function: Func2Type, lhs: &RawVal, rhs: &RawVal) -> Option<RawVal> {
+    Some(match (function, lhs, rhs) {
+        (Func2Type::Add, RawVal::Int(l), RawVal::Int(r)) => RawVal::Int(l.checked_add(*r)?),
+        (Func2Type::Subtract, RawVal::Int(l), RawVal::Int(r)) => RawVal::Int(l.checked_sub(*r)?),
+        (Func2Type::Multiply, RawVal::Int(l), RawVal::Int(r)) => RawVal::Int(l.checked_mul(*r)?),
+        (Func2Type::Divide, RawVal::Int(l), RawVal::Int(r)) => RawVal::Int(l.checked_div(*r)?),
+        (Func2Type::Modulo, RawVal::Int(l), RawVal::Int(r)) => RawVal::Int(l.checked_rem(*r)?),
+        (Func2Type::And, RawVal::Int(l), RawVal::Int(r)) => boolean(*l != 0 && *r != 0),
+        (Func2Type::Or, RawVal::Int(l), RawVal::Int(r)) => boolean(*l != 0 || *r != 0),
+        (_, RawVal::Int(_), RawVal::Int(_)) | (_, RawVal::Str(_), RawVal::Str(_)) => {
+            compare(function, lhs, rhs)?
+        }
+        _ => return None,
+    })
+}
+
+/// Returns the operand of `x + 0`, `x - 0` and `x * 1`. Only operands that are known to be numbers are returned, on
+/// other operands such as string columns the arithmetic is a type error that has to be reported.
+fn identity_operand<'a>(function: Func2Type, lhs: &'a Expr, rhs: &'a Expr) -> Option<&'a Expr> {
+    let operand = match (function, lhs, rhs) {
+        (Func2Type::Add, operand, Expr::Const(RawVal::Int(0)))
+        | (Func2Type::Add, Expr::Const(RawVal::Int(0)), operand)
+        | (Func2Type::Subtract, operand, Expr::Const(RawVal::Int(0)))
+        | (Func2Type::Multiply, operand, Expr::Const(RawVal::Int(1)))
+        | (Func2Type::Multiply, Expr::Const(RawVal::Int(1)), operand) => operand,
+        _ => return None,
+    };
+    if is_numeric(operand) {
+        Some(operand)
+    } else {
+        None
+    }
+}
+
+fn compare(function: Func2Type, lhs: &RawVal, rhs: &RawVal) -> Option<RawVal> {
+    Some(match function {
+        Func2Type::Equals => boolean(lhs == rhs),
+        Func2Type::NotEquals => boolean(lhs != rhs),
+        Func2Type::LT => boolean(lhs < rhs),
+        Func2Type::LTE => boolean(lhs <= rhs),
+        Func2Type::GT => boolean(lhs > rhs),
+        Func2Type::GTE => boolean(lhs >= rhs),
+        _ => return None,
+    })
+}
+
+/// Whether `expr` evaluates to a boolean.
+fn is_predicate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Func1(function, _) => matches!(
+            function,
+            Func1Type::Not | Func1Type::IsNull | Func1Type::IsNotNull
+        ),
+        Expr::Func2(function, _, _) => !is_arithmetic(*function),
+        _ => false,
+    }
+}
+
+/// Whether `expr` is known to evaluate to a number. Arithmetic and functions like `length` fail on operands of the
+/// wrong type whether or not they are wrapped in `x + 0`, so they count as numbers.
+fn is_numeric(expr: &Expr) -> bool {
+    match expr {
+        Expr::Func1(function, _) => matches!(
+            function,
+            Func1Type::Negate | Func1Type::ToYear | Func1Type::Length
+        ),
+        Expr::Func2(function, _, _) => is_arithmetic(*function),
+        Expr::Const(value) => matches!(value, RawVal::Int(_) | RawVal::Float(_)),
+        _ => false,
+    }
+}
+
+fn is_arithmetic(function: Func2Type) -> bool {
+    matches!(
+        function,
+        Func2Type::Add
+            | Func2Type::Subtract
+            | Func2Type::Multiply
+            | Func2Type::Divide
+            | Func2Type::Modulo
+    )
+}
+
+fn boolean(value: bool) -> RawVal {
+    RawVal::Int(value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parser::parse_query;
+
+    fn int(value: i64) -> Expr {
+        Expr::Const(RawVal::Int(value))
+    }
+
+    fn col(name: &str) -> Expr {
+        Expr::ColName(name.to_string())
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let expr = Expr::func(
+            Func2Type::LT,
+            Expr::func(Func2Type::Multiply, int(3), int(4)),
+            Expr::func1(Func1Type::Negate, int(-20)),
+        );
+        assert!(matches!(simplify(&expr), Expr::Const(RawVal::Int(1))));
+        // Errors are reported when the query is executed
+        let overflow = Expr::func(Func2Type::Add, int(i64::MAX), int(1));
+        assert!(matches!(
+            simplify(&overflow),
+            Expr::Func2(Func2Type::Add, _, _)
+        ));
+        let division_by_zero = Expr::func(Func2Type::Divide, int(1), int(0));
+        assert!(matches!(
+            simplify(&division_by_zero),
+            Expr::Func2(Func2Type::Divide, _, _)
+        ));
+    }
+
+    #[test]
+    fn test_eliminate_predicates() {
+        let predicate = Expr::func(Func2Type::GT, col("x"), int(3));
+        let always_true = Expr::func(Func2Type::Equals, int(1), int(1));
+        let always_false = Expr::func1(Func1Type::Not, always_true.clone());
+
+        let expr = Expr::func(Func2Type::And, always_true.clone(), predicate.clone());
+        assert!(matches!(simplify(&expr), Expr::Func2(Func2Type::GT, _, _)));
+        let expr = Expr::func(Func2Type::Or, predicate.clone(), always_false.clone());
+        assert!(matches!(simplify(&expr), Expr::Func2(Func2Type::GT, _, _)));
+        let expr = Expr::func(Func2Type::And, predicate.clone(), always_false);
+        assert!(is_always_false(&simplify(&expr)));
+        let expr = Expr::func(Func2Type::Or, always_true, predicate);
+        assert!(matches!(simplify(&expr), Expr::Const(RawVal::Int(1))));
+        // `x` is not a boolean
+        let expr = Expr::func(Func2Type::And, col("x"), int(1));
+        assert!(matches!(simplify(&expr), Expr::Func2(Func2Type::And, _, _)));
+    }
+
+    #[test]
+    fn test_eliminate_identities() {
+        let numeric = Expr::func(Func2Type::Multiply, col("x"), int(2));
+        let expr = Expr::func(Func2Type::Add, int(0), numeric.clone());
+        assert!(matches!(
+            simplify(&expr),
+            Expr::Func2(Func2Type::Multiply, _, _)
+        ));
+        let expr = Expr::func(Func2Type::Multiply, numeric.clone(), int(1));
+        assert!(matches!(
+            simplify(&expr),
+            Expr::Func2(Func2Type::Multiply, _, _)
+        ));
+        let expr = Expr::func(
+            Func2Type::Subtract,
+            Expr::func1(Func1Type::Length, col("s")),
+            int(0),
+        );
+        assert!(matches!(simplify(&expr), Expr::Func1(Func1Type::Length, _)));
+        // The type of `x` is not known, it may be a string column
+        let expr = Expr::func(Func2Type::Add, col("x"), int(0));
+        assert!(matches!(simplify(&expr), Expr::Func2(Func2Type::Add, _, _)));
+        let expr = Expr::func(Func2Type::Multiply, int(1), col("x"));
+        assert!(matches!(
+            simplify(&expr),
+            Expr::Func2(Func2Type::Multiply, _, _)
+        ));
+    }
+
+    #[test]
+    fn test_fold_intervals() {
+        let query =
+            parse_query("SELECT ts FROM default WHERE ts > 86400 - interval '1 day 30 minutes';")
+                .unwrap();
+        let expr = Expr::func(Func2Type::GT, col("ts"), int(-1800));
+        assert_eq!(simplify(&query.filter), expr);
+        // Overflows are reported when the query is executed
+        let query = parse_query(&format!(
+            "SELECT ts FROM default WHERE ts > {} + interval '1 second';",
+            i64::MAX
+        ))
+        .unwrap();
+        assert!(matches!(
+            simplify(&query.filter),
+            Expr::Func2(Func2Type::GT, _, _)
+        ));
+    }
+}
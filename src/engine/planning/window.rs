@@ -0,0 +1,255 @@
+use ordered_float::OrderedFloat;
+
+use crate::engine::planning::quantiles::compare;
+use crate::engine::*;
+use crate::ingest::raw_val::RawVal;
+use crate::syntax::expression::*;
+use crate::syntax::limit::LimitClause;
+use crate::QueryError;
+
+/// Window aggregates depend on neighbouring rows which may reside in different partitions.
+/// Queries containing window functions are rewritten into a query that selects all other projections, the window
+/// function arguments and the PARTITION BY expressions, ordered by PARTITION BY and ORDER BY of the window.
+/// Partition results are combined by the k-way merge used for ORDER BY queries and the window aggregates are
+/// computed on the fully merged result.
+#[derive(Debug, Clone)]
+pub struct WindowRewrite {
+    outputs: Vec<WindowOutput>,
+    windows: Vec<(WindowAggregator, WindowFrame)>,
+    projection_columns: usize,
+    partition_columns: usize,
+    limit: LimitClause,
+}
+
+#[derive(Debug, Clone)]
+enum WindowOutput {
+    Projection(usize),
+    Window(usize),
+}
+
+impl WindowRewrite {
+    /// Returns `None` if the query does not contain any window functions.
+    pub fn rewrite(query: &Query) -> Result<Option<(Query, WindowRewrite)>, QueryError> {
+        if !query
+            .select
+            .iter()
+            .any(|c| matches!(c.expr, Expr::Window(_)))
+        {
+            return Ok(None);
+        }
+        if !query.order_by.is_empty() {
+            bail!(
+                QueryError::NotImplemented,
+                "ORDER BY in queries containing window functions"
+            );
+        }
+
+        let mut projections = Vec::new();
+        let mut args = Vec::new();
+        let mut windows = Vec::new();
+        let mut outputs = Vec::with_capacity(query.select.len());
+        let mut window_spec: Option<&WindowExpr> = None;
+        for col_info in &query.select {
+            match &col_info.expr {
+                Expr::Window(window) => {
+                    Query::ensure_no_aggregates(&window.expr)?;
+                    for expr in window
+                        .partition_by
+                        .iter()
+                        .chain(window.order_by.iter().map(|(expr, _)| expr))
+                    {
+                        Query::ensure_no_aggregates(expr)?;
+                    }
+                    match window_spec {
+                        None => window_spec = Some(window),
+                        Some(spec) => {
+                            if format!("{:?}", (&spec.partition_by, &spec.order_by))
+                                != format!("{:?}", (&window.partition_by, &window.order_by))
+                            {
+                                bail!(
+                                    QueryError::NotImplemented,
+                                    "Window functions with different PARTITION BY or ORDER BY clauses"
+                                );
+                            }
+                        }
+                    }
+                    outputs.push(WindowOutput::Window(windows.len()));
+                    windows.push((window.aggregator, window.frame));
+                    args.push(window.expr.clone());
+                }
+                expr => {
+                    let (_, aggregates) =
+                        Query::extract_aggregators(expr, &mut vec![], &col_info.name)?;
+                    if !aggregates.is_empty() {
+                        bail!(
+                            QueryError::NotImplemented,
+                            "Combining window functions with other aggregation functions"
+                        );
+                    }
+                    outputs.push(WindowOutput::Projection(projections.len()));
+                    projections.push(expr.clone());
+                }
+            }
+        }
+
+        let spec = window_spec.unwrap();
+        let projection_columns = projections.len();
+        let partition_columns = spec.partition_by.len();
+        let select = projections
+            .into_iter()
+            .enumerate()
+            .map(|(i, expr)| ColumnInfo {
+                expr,
+                name: format!("_wp{}", i),
+            })
+            .chain(args.into_iter().enumerate().map(|(i, expr)| ColumnInfo {
+                expr,
+                name: format!("_wa{}", i),
+            }))
+            .chain(
+                spec.partition_by
+                    .iter()
+                    .enumerate()
+                    .map(|(i, expr)| ColumnInfo {
+                        expr: expr.clone(),
+                        name: format!("_wk{}", i),
+                    }),
+            )
+            .collect();
+        let order_by = spec
+            .partition_by
+            .iter()
+            .map(|expr| (expr.clone(), false))
+            .chain(spec.order_by.iter().cloned())
+            .collect();
+        let rewritten = Query {
+            select,
+            table: query.table.clone(),
+            filter: query.filter.clone(),
+            order_by,
+            limit: LimitClause {
+                limit: u64::MAX,
+                offset: 0,
+            },
+        };
+        Ok(Some((
+            rewritten,
+            WindowRewrite {
+                outputs,
+                windows,
+                projection_columns,
+                partition_columns,
+                limit: query.limit.clone(),
+            },
+        )))
+    }
+
+    /// Computes final result rows from rows of the rewritten query, which must be ordered by the PARTITION BY and
+    /// ORDER BY expressions of the window.
+    pub fn evaluate(&self, rows: Vec<Vec<RawVal>>) -> Vec<Vec<RawVal>> {
+        let partition_start = self.projection_columns + self.windows.len();
+        let mut window_values = vec![Vec::with_capacity(rows.len()); self.windows.len()];
+        let mut start = 0;
+        for i in 1..=rows.len() {
+            if i == rows.len() || rows[i][partition_start..] != rows[start][partition_start..] {
+                let partition = &rows[start..i];
+                for (w, &(aggregator, frame)) in self.windows.iter().enumerate() {
+                    let values = partition
+                        .iter()
+                        .map(|row| &row[self.projection_columns + w])
+                        .collect::<Vec<_>>();
+                    window_values[w].extend(aggregate_frames(&values, aggregator, frame));
+                }
+                start = i;
+            }
+        }
+
+        let offset = self.limit.offset.min(usize::MAX as u64) as usize;
+        let limit = self.limit.limit.min(usize::MAX as u64) as usize;
+        rows.iter()
+            .enumerate()
+            .skip(offset)
+            .take(limit)
+            .map(|(i, row)| {
+                self.outputs
+                    .iter()
+                    .map(|output| match *output {
+                        WindowOutput::Projection(p) => row[p].clone(),
+                        WindowOutput::Window(w) => window_values[w][i].clone(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Evaluates the aggregator over the frame of every row in a single window partition.
+fn aggregate_frames(
+    values: &[&RawVal],
+    aggregator: WindowAggregator,
+    frame: WindowFrame,
+) -> Vec<RawVal> {
+    let len = values.len() as i64;
+    // Offsets may be as large as `i64::MAX`
+    let bounds = |i: i64| {
+        let start = frame.start.map_or(0, |s| i.saturating_add(s).clamp(0, len));
+        let end = frame
+            .end
+            .map_or(len, |e| i.saturating_add(e).saturating_add(1).clamp(0, len));
+        (start as usize, end.max(start) as usize)
+    };
+    match aggregator {
+        WindowAggregator::Sum | WindowAggregator::Count | WindowAggregator::Avg => {
+            // Prefix sums over non-null values allow evaluating each frame in constant time.
+            // Strings are counted by COUNT but excluded from SUM and AVG.
+            let is_float = values.iter().any(|val| matches!(val, RawVal::Float(_)));
+            let mut int_sums = vec![0i64; values.len() + 1];
+            let mut float_sums = vec![0f64; values.len() + 1];
+            let mut counts = vec![0i64; values.len() + 1];
+            let mut numeric_counts = vec![0i64; values.len() + 1];
+            for (i, val) in values.iter().enumerate() {
+                let (int, float, count, numeric_count) = match **val {
+                    RawVal::Int(int) => (int, int as f64, 1, 1),
+                    RawVal::Float(float) => (0, float.0, 1, 1),
+                    RawVal::Str(_) => (0, 0.0, 1, 0),
+                    RawVal::Null => (0, 0.0, 0, 0),
+                };
+                int_sums[i + 1] = int_sums[i].wrapping_add(int);
+                float_sums[i + 1] = float_sums[i] + float;
+                counts[i + 1] = counts[i] + count;
+                numeric_counts[i + 1] = numeric_counts[i] + numeric_count;
+            }
+            (0..len)
+                .map(|i| {
+                    let (start, end) = bounds(i);
+                    let count = numeric_counts[end] - numeric_counts[start];
+                    match aggregator {
+                        WindowAggregator::Count => RawVal::Int(counts[end] - counts[start]),
+                        _ if count == 0 => RawVal::Null,
+                        WindowAggregator::Avg => RawVal::Float(OrderedFloat(
+                            (float_sums[end] - float_sums[start]) / count as f64,
+                        )),
+                        _ if is_float => {
+                            RawVal::Float(OrderedFloat(float_sums[end] - float_sums[start]))
+                        }
+                        _ => RawVal::Int(int_sums[end].wrapping_sub(int_sums[start])),
+                    }
+                })
+                .collect()
+        }
+        WindowAggregator::Min | WindowAggregator::Max => (0..len)
+            .map(|i| {
+                let (start, end) = bounds(i);
+                let frame_values = values[start..end]
+                    .iter()
+                    .filter(|val| ***val != RawVal::Null);
+                let extremum = if aggregator == WindowAggregator::Min {
+                    frame_values.min_by(|a, b| compare(a, b))
+                } else {
+                    frame_values.max_by(|a, b| compare(a, b))
+                };
+                extremum.map_or(RawVal::Null, |val| (**val).clone())
+            })
+            .collect(),
+    }
+}
@@ -1,4 +1,6 @@
 #![allow(clippy::nonstandard_macro_braces)]
+use std::time::Duration;
+
 use failure::Backtrace;
 
 #[derive(Fail, Debug)]
@@ -17,6 +19,18 @@ pub enum QueryError {
     TypeError(String),
     #[fail(display = "Overflow or division by zero")]
     Overflow,
+    #[fail(display = "Schema error: {}", _0)]
+    SchemaError(String),
+    #[fail(display = "Overloaded: {}", _0)]
+    Overloaded(String),
+    #[fail(display = "Ingestion is throttled, retry after {:?}", _0)]
+    Throttled(Duration),
+    #[fail(display = "Memory limit exceeded: {}", _0)]
+    MemoryLimitExceeded(String),
+    #[fail(display = "Database is shutting down")]
+    ShuttingDown,
+    #[fail(display = "Query was killed")]
+    Killed,
 }
 
 #[macro_export]
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::*;
+use arrow_array::{Array, RecordBatch};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_schema::{ArrowError, DataType, TimeUnit};
+
+use crate::ingest::input_column::InputColumn;
+use crate::QueryError;
+
+/// Reads all record batches from an Arrow IPC file (also known as Feather v2).
+pub fn read_ipc_file(path: &Path) -> Result<Vec<HashMap<String, InputColumn>>, QueryError> {
+    let file = File::open(path)
+        .map_err(|e| QueryError::ParseError(format!("Failed to open {}: {}", path.display(), e)))?;
+    let reader = FileReader::try_new(file, None).map_err(ipc_error)?;
+    reader
+        .map(|batch| record_batch_columns(&batch.map_err(ipc_error)?))
+        .collect()
+}
+
+/// Reads all record batches from an Arrow IPC stream.
+pub fn read_ipc_stream<R: Read>(
+    reader: R,
+) -> Result<Vec<HashMap<String, InputColumn>>, QueryError> {
+    let reader = StreamReader::try_new(reader, None).map_err(ipc_error)?;
+    reader
+        .map(|batch| record_batch_columns(&batch.map_err(ipc_error)?))
+        .collect()
+}
+
+/// Converts each array of `batch` into a column of the same name, preserving integer, float and string types.
+pub fn record_batch_columns(
+    batch: &RecordBatch,
+) -> Result<HashMap<String, InputColumn>, QueryError> {
+    batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, array)| {
+            let column = convert_array(array.as_ref()).map_err(|e| match e {
+                QueryError::NotImplemented(msg) => {
+                    QueryError::NotImplemented(format!("{} (column {})", msg, field.name()))
+                }
+                e => e,
+            })?;
+            Ok((field.name().clone(), column))
+        })
+        .collect()
+}
+
+fn ipc_error(err: ArrowError) -> QueryError {
+    QueryError::ParseError(format!("Failed to read Arrow IPC data: {}", err))
+}
+
+macro_rules! int_column {
+    ($array:expr, $arrow_type:ty) => {{
+        let values = $array.as_primitive::<$arrow_type>();
+        column(
+            $array,
+            |i| i64::from(values.value(i)),
+            InputColumn::Int,
+            InputColumn::NullableInt,
+        )
+    }};
+}
+
+fn convert_array(array: &dyn Array) -> Result<InputColumn, QueryError> {
+    if array.null_count() == array.len() {
+        return Ok(InputColumn::Null(array.len()));
+    }
+    Ok(match array.data_type() {
+        DataType::Boolean => {
+            let values = array.as_boolean();
+            column(
+                array,
+                |i| i64::from(values.value(i)),
+                InputColumn::Int,
+                InputColumn::NullableInt,
+            )
+        }
+        DataType::Int8 => int_column!(array, Int8Type),
+        DataType::Int16 => int_column!(array, Int16Type),
+        DataType::Int32 => int_column!(array, Int32Type),
+        DataType::Int64 => int_column!(array, Int64Type),
+        DataType::UInt8 => int_column!(array, UInt8Type),
+        DataType::UInt16 => int_column!(array, UInt16Type),
+        DataType::UInt32 => int_column!(array, UInt32Type),
+        DataType::UInt64 => {
+            let values = array.as_primitive::<UInt64Type>();
+            if (0..array.len()).any(|i| array.is_valid(i) && values.value(i) > i64::MAX as u64) {
+                return Err(QueryError::Overflow);
+            }
+            column(
+                array,
+                |i| values.value(i) as i64,
+                InputColumn::Int,
+                InputColumn::NullableInt,
+            )
+        }
+        DataType::Timestamp(TimeUnit::Second, _) => int_column!(array, TimestampSecondType),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            int_column!(array, TimestampMillisecondType)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            int_column!(array, TimestampMicrosecondType)
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            int_column!(array, TimestampNanosecondType)
+        }
+        DataType::Float32 => {
+            let values = array.as_primitive::<Float32Type>();
+            column(
+                array,
+                |i| f64::from(values.value(i)),
+                InputColumn::Float,
+                InputColumn::NullableFloat,
+            )
+        }
+        DataType::Float64 => {
+            let values = array.as_primitive::<Float64Type>();
+            column(
+                array,
+                |i| values.value(i),
+                InputColumn::Float,
+                InputColumn::NullableFloat,
+            )
+        }
+        DataType::Utf8 => {
+            let values = array.as_string::<i32>();
+            column(
+                array,
+                |i| values.value(i).to_string(),
+                InputColumn::Str,
+                InputColumn::NullableStr,
+            )
+        }
+        DataType::LargeUtf8 => {
+            let values = array.as_string::<i64>();
+            column(
+                array,
+                |i| values.value(i).to_string(),
+                InputColumn::Str,
+                InputColumn::NullableStr,
+            )
+        }
+        data_type => bail!(QueryError::NotImplemented, "Arrow type {}", data_type),
+    })
+}
+
+/// Creates a dense column if the array has no nulls, and a sparse column containing only valid values otherwise.
+fn column<T>(
+    array: &dyn Array,
+    value: impl Fn(usize) -> T,
+    dense: fn(Vec<T>) -> InputColumn,
+    sparse: fn(u64, Vec<(u64, T)>) -> InputColumn,
+) -> InputColumn {
+    if array.null_count() == 0 {
+        dense((0..array.len()).map(value).collect())
+    } else {
+        sparse(
+            array.len() as u64,
+            (0..array.len())
+                .filter(|&i| array.is_valid(i))
+                .map(|i| (i as u64, value(i)))
+                .collect(),
+        )
+    }
+}
@@ -40,14 +40,15 @@ impl Buffer {
                 InputColumn::Float(vec) => buffered_col.push_floats(vec),
                 InputColumn::Null(c) => buffered_col.push_nulls(c),
                 InputColumn::NullableFloat(c, data) => {
-                    let mut next_i = 0;
-                    for (i, f) in data {
-                        buffered_col.push_nulls((i - next_i) as usize);
-                        buffered_col.push(RawVal::Float(OrderedFloat(f)));
-                        next_i = i + 1;
-                    }
-                    buffered_col.push_nulls((c - next_i) as usize);
+                    push_sparse(buffered_col, c, data, |f| RawVal::Float(OrderedFloat(f)))
                 }
+                InputColumn::NullableInt(c, data) => {
+                    push_sparse(buffered_col, c, data, RawVal::Int)
+                }
+                InputColumn::NullableStr(c, data) => {
+                    push_sparse(buffered_col, c, data, RawVal::Str)
+                }
+                InputColumn::Mixed(c, data) => push_sparse(buffered_col, c, data, |val| val),
             }
             new_length = cmp::max(new_length, buffered_col.len())
         }
@@ -94,3 +95,19 @@ impl Buffer {
             .sum()
     }
 }
+
+/// Pushes `len` values of which only the non-null values are given, as pairs of row index and value.
+fn push_sparse<T>(
+    col: &mut MixedCol,
+    len: u64,
+    data: Vec<(u64, T)>,
+    to_raw_val: impl Fn(T) -> RawVal,
+) {
+    let mut next_i = 0;
+    for (i, value) in data {
+        col.push_nulls((i - next_i) as usize);
+        col.push(to_raw_val(value));
+        next_i = i + 1;
+    }
+    col.push_nulls((len - next_i) as usize);
+}
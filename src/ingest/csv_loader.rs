@@ -1,24 +1,23 @@
 extern crate csv;
-extern crate flate2;
 
 use ordered_float::OrderedFloat;
 
 use crate::bitvec::*;
 use crate::ingest::raw_val::RawVal;
 use crate::ingest::schema::*;
+use crate::mem_store::schema as table_schema;
 use crate::scheduler::*;
 use crate::stringpack::*;
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
+use std::io::{BufRead, Read};
 use std::ops::BitOr;
 use std::path::{Path, PathBuf};
 use std::str;
 use std::sync::Arc;
 
+use super::decompress;
 use super::extractor;
 
-use self::flate2::read::GzDecoder;
-
 type IngestionTransform = HashMap<usize, extractor::Extractor>;
 
 #[derive(Debug)]
@@ -32,7 +31,13 @@ pub struct Options {
     always_string: HashSet<usize>,
     allow_nulls: HashSet<usize>,
     allow_nulls_all_columns: bool,
-    unzip: bool,
+    column_types: HashMap<usize, table_schema::ColumnType>,
+    type_inference_rows: Option<usize>,
+    null_tokens: HashSet<String>,
+    has_headers: Option<bool>,
+    delimiter: u8,
+    quote: u8,
+    quoting: bool,
 }
 
 impl Options {
@@ -47,7 +52,13 @@ impl Options {
             always_string: HashSet::new(),
             allow_nulls: HashSet::new(),
             allow_nulls_all_columns: false,
-            unzip: filename.as_ref().to_string_lossy().ends_with(".gz"),
+            column_types: HashMap::new(),
+            type_inference_rows: None,
+            null_tokens: HashSet::new(),
+            has_headers: None,
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
         }
     }
 
@@ -134,85 +145,213 @@ impl Options {
         self.allow_nulls_all_columns = true;
         self
     }
+
+    /// Overrides the inferred type of columns. Extractors take precedence over type overrides.
+    #[must_use]
+    pub fn with_column_types(mut self, types: &[(usize, table_schema::ColumnType)]) -> Options {
+        self.column_types = types.iter().cloned().collect();
+        self
+    }
+
+    /// Overrides the inferred type of a single column.
+    #[must_use]
+    pub fn with_column_type(
+        mut self,
+        column: usize,
+        column_type: table_schema::ColumnType,
+    ) -> Options {
+        self.column_types.insert(column, column_type);
+        self
+    }
+
+    /// Infers the type of each column in a separate pass over the whole file.
+    /// By default, types are inferred separately for each partition which may assign different types to the same column.
+    #[must_use]
+    pub fn with_type_inference(mut self) -> Options {
+        self.type_inference_rows = Some(usize::MAX);
+        self
+    }
+
+    /// Infers the type of each column from the first `rows` rows of the file.
+    /// Loading fails if later values cannot be converted to the inferred type.
+    #[must_use]
+    pub fn with_sampled_type_inference(mut self, rows: usize) -> Options {
+        self.type_inference_rows = Some(rows);
+        self
+    }
+
+    /// Fields equal to any of `tokens` are treated like empty fields, which are read as null in columns that allow nulls.
+    #[must_use]
+    pub fn with_null_tokens(mut self, tokens: &[&str]) -> Options {
+        self.null_tokens = tokens.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    /// Whether the first row of the file contains column names.
+    /// Defaults to `true` unless column names are set explicitly, in which case the first row is ingested as data.
+    /// Set this to `true` to skip the header row of a file whose columns are renamed by explicit column names.
+    /// Columns of files without header or explicit column names are named `column_0`, `column_1`, ...
+    #[must_use]
+    pub fn with_headers(mut self, has_headers: bool) -> Options {
+        self.has_headers = Some(has_headers);
+        self
+    }
+
+    #[must_use]
+    pub fn with_delimiter(mut self, delimiter: u8) -> Options {
+        self.delimiter = delimiter;
+        self
+    }
+
+    #[must_use]
+    pub fn with_quote(mut self, quote: u8) -> Options {
+        self.quote = quote;
+        self
+    }
+
+    /// Disables quoting, quote characters are then read as part of the field.
+    #[must_use]
+    pub fn without_quoting(mut self) -> Options {
+        self.quoting = false;
+        self
+    }
 }
 
 pub fn ingest_file(ldb: &InnerLocustDB, opts: &Options) -> Result<(), String> {
-    // Can't combine these two branches because csv::Reader takes a type param which differs for creating from Reader/File
-    if opts.unzip {
-        let f = File::open(&opts.filename).map_err(|x| x.to_string())?;
-        let decoded = GzDecoder::new(f);
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(opts.colnames.is_none())
-            .from_reader(decoded);
-        let headers = match opts.colnames {
-            Some(ref colnames) => colnames.clone(),
-            None => reader
-                .headers()
-                .unwrap()
-                .iter()
-                .map(str::to_owned)
-                .collect(),
-        };
-        auto_ingest(ldb, reader.records().map(|r| r.unwrap()), &headers, opts)
-    } else {
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(opts.colnames.is_none())
-            .from_path(&opts.filename)
-            .map_err(|x| x.to_string())?;
-        let headers = match opts.colnames {
-            Some(ref colnames) => colnames.clone(),
-            None => reader
-                .headers()
-                .unwrap()
-                .iter()
-                .map(str::to_owned)
-                .collect(),
-        };
-        auto_ingest(ldb, reader.records().map(|r| r.unwrap()), &headers, opts)
+    let (headers, records) = read_file(opts)?;
+    let inferred_types = match opts.type_inference_rows {
+        Some(rows) => {
+            let (_, sample) = read_file(opts)?;
+            infer_column_types(sample.take(rows), headers.len(), opts)?
+        }
+        None => vec![None; headers.len()],
+    };
+    auto_ingest(ldb, records, &headers, &inferred_types, opts)?;
+    Ok(())
+}
+
+/// Ingests CSV data from `input` instead of the file given in `opts` and returns the number of rows.
+/// Type inference requires a separate pass over the data and is not supported.
+pub fn ingest_reader<R: Read>(
+    ldb: &InnerLocustDB,
+    input: R,
+    opts: &Options,
+) -> Result<usize, String> {
+    if opts.type_inference_rows.is_some() {
+        return Err("Type inference is only supported when loading files".to_string());
+    }
+    let (headers, records) = csv_records(input, opts)?;
+    let inferred_types = vec![None; headers.len()];
+    auto_ingest(ldb, records, &headers, &inferred_types, opts)
+}
+
+fn read_file(
+    opts: &Options,
+) -> Result<(Vec<String>, csv::StringRecordsIntoIter<Box<dyn BufRead>>), String> {
+    let input = decompress::open(&opts.filename).map_err(|x| x.to_string())?;
+    csv_records(input, opts)
+}
+
+/// Returns column names and an iterator over all remaining records.
+fn csv_records<R: Read>(
+    input: R,
+    opts: &Options,
+) -> Result<(Vec<String>, csv::StringRecordsIntoIter<R>), String> {
+    let has_headers = opts.has_headers.unwrap_or(opts.colnames.is_none());
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .quoting(opts.quoting)
+        .from_reader(input);
+    let headers = match opts.colnames {
+        Some(ref colnames) => colnames.clone(),
+        // Returns the first record without consuming it if the file has no header
+        None => {
+            let headers = reader.headers().map_err(|x| x.to_string())?;
+            if has_headers {
+                headers.iter().map(str::to_owned).collect()
+            } else {
+                (0..headers.len())
+                    .map(|i| format!("column_{}", i))
+                    .collect()
+            }
+        }
+    };
+    Ok((headers, reader.into_records()))
+}
+
+fn infer_column_types<T>(
+    records: T,
+    columns: usize,
+    opts: &Options,
+) -> Result<Vec<Option<table_schema::ColumnType>>, String>
+where
+    T: Iterator<Item = csv::Result<csv::StringRecord>>,
+{
+    let mut types = vec![ColType::nothing(); columns];
+    for row in records {
+        let row = row.map_err(|x| x.to_string())?;
+        for (col_type, val) in types.iter_mut().zip(row.iter()) {
+            if !opts.null_tokens.contains(val) {
+                *col_type = *col_type | ColType::determine(val);
+            }
+        }
     }
+    Ok(types.iter().map(ColType::column_type).collect())
 }
 
 fn auto_ingest<T>(
     ldb: &InnerLocustDB,
     records: T,
     colnames: &[String],
+    inferred_types: &[Option<table_schema::ColumnType>],
     opts: &Options,
-) -> Result<(), String>
+) -> Result<usize, String>
 where
-    T: Iterator<Item = csv::StringRecord>,
+    T: Iterator<Item = csv::Result<csv::StringRecord>>,
 {
     let ignore = (0..colnames.len())
         .map(|x| opts.ignore_cols.contains(&x))
         .collect::<Vec<_>>();
-    let string = (0..colnames.len())
-        .map(|x| opts.always_string.contains(&x))
+    let types = (0..colnames.len())
+        .map(|x| match opts.column_types.get(&x) {
+            Some(&column_type) => Some(column_type),
+            None if opts.always_string.contains(&x) => Some(table_schema::ColumnType::String),
+            None => inferred_types[x],
+        })
         .collect::<Vec<_>>();
     let mut raw_cols = (0..colnames.len())
         .map(|x| RawCol::new(opts.allow_nulls_all_columns || opts.allow_nulls.contains(&x)))
         .collect::<Vec<_>>();
     let mut row_num = 0usize;
     for row in records {
+        let row = row.map_err(|x| x.to_string())?;
         for (i, val) in row.iter().enumerate() {
             if !ignore[i] {
-                raw_cols[i].push(val);
+                if opts.null_tokens.contains(val) {
+                    raw_cols[i].push("");
+                } else {
+                    raw_cols[i].push(val);
+                }
             }
         }
 
         if row_num % opts.partition_size == opts.partition_size - 1 {
-            let cols = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &string);
+            let cols = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &types)?;
             ldb.ingest_heterogeneous(&opts.tablename, cols);
-            ldb.wal_flush();
+            ldb.wal_flush().map_err(|err| err.to_string())?;
         }
         row_num += 1;
     }
 
     if row_num % opts.partition_size != 0 {
-        let cols = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &string);
+        let cols = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &types)?;
         ldb.ingest_heterogeneous(&opts.tablename, cols);
     }
     // ingest_heterogeneous does not write to WAL, so need to flush to ensure data is persisted as partitions
-    ldb.wal_flush();
-    Ok(())
+    ldb.wal_flush().map_err(|err| err.to_string())?;
+    Ok(row_num)
 }
 
 fn create_batch(
@@ -220,19 +359,19 @@ fn create_batch(
     colnames: &[String],
     extractors: &IngestionTransform,
     ignore: &[bool],
-    string: &[bool],
-) -> HashMap<String, Vec<RawVal>> {
+    types: &[Option<table_schema::ColumnType>],
+) -> Result<HashMap<String, Vec<RawVal>>, String> {
     let mut mem_store = HashMap::new();
     for (i, col) in cols.iter_mut().enumerate() {
         if !ignore[i] {
             let new_column = match extractors.get(&i) {
                 Some(extractor) => col.extract(*extractor),
-                None => col.finalize(&colnames[i], string[i]),
+                None => col.finalize(&colnames[i], types[i])?,
             };
             mem_store.insert(colnames[i].to_string(), new_column);
         }
     }
-    mem_store
+    Ok(mem_store)
 }
 
 pub struct CSVIngestionTask {
@@ -307,9 +446,15 @@ impl RawCol {
         self.values.push(elem);
     }
 
-    fn finalize(&mut self, name: &str, string: bool) -> Vec<RawVal> {
-        let result = if self.types.contains_string || string {
-            self.values
+    fn finalize(
+        &mut self,
+        name: &str,
+        column_type: Option<table_schema::ColumnType>,
+    ) -> Result<Vec<RawVal>, String> {
+        let column_type = column_type.or(self.types.column_type());
+        let result = match column_type {
+            Some(table_schema::ColumnType::String) => self
+                .values
                 .iter()
                 .map(|s| {
                     if self.allow_null && s.is_empty() {
@@ -318,52 +463,62 @@ impl RawCol {
                         RawVal::Str(s.to_string())
                     }
                 })
-                .collect()
-        } else if self.types.contains_float {
-            let mut result = Vec::with_capacity(self.values.len());
-            for s in self.values.iter() {
-                if s.is_empty() {
-                    if self.allow_null {
-                        result.push(RawVal::Null);
+                .collect(),
+            Some(table_schema::ColumnType::Float) => {
+                let mut result = Vec::with_capacity(self.values.len());
+                for s in self.values.iter() {
+                    if s.is_empty() {
+                        if self.allow_null {
+                            result.push(RawVal::Null);
+                        } else {
+                            result.push(RawVal::Float(OrderedFloat(0.0)));
+                        }
+                    } else if let Ok(float) = s.parse::<f64>() {
+                        result.push(RawVal::Float(OrderedFloat(float)));
                     } else {
-                        result.push(RawVal::Float(OrderedFloat(0.0)));
+                        return Err(format!(
+                            "Value {} in column {} is not parseable as float",
+                            s, name
+                        ));
                     }
-                } else if let Ok(float) = s.parse::<f64>() {
-                    result.push(RawVal::Float(OrderedFloat(float)));
-                } else {
-                    unreachable!(
-                        "{} should be parseable as float. {} {:?}",
-                        s, name, self.types
-                    )
                 }
+                result
             }
-            result
-        } else if self.types.contains_int {
-            let mut result = Vec::with_capacity(self.values.len());
-            for s in self.values.iter() {
-                if s.is_empty() {
-                    if self.allow_null {
-                        result.push(RawVal::Null);
+            Some(table_schema::ColumnType::Integer) => {
+                let mut result = Vec::with_capacity(self.values.len());
+                for s in self.values.iter() {
+                    if s.is_empty() {
+                        if self.allow_null {
+                            result.push(RawVal::Null);
+                        } else {
+                            result.push(RawVal::Int(0));
+                        }
+                    } else if let Ok(int) = s.parse::<i64>() {
+                        result.push(RawVal::Int(int));
+                    } else if let Ok(float) = s.parse::<f64>() {
+                        // Only floats that are exactly representable as integers, e.g. `3.0`, are accepted
+                        if float.fract() != 0.0
+                            || !(i64::MIN as f64..i64::MAX as f64).contains(&float)
+                        {
+                            return Err(format!(
+                                "Value {} in column {} of type integer is not an integer, declare the column type or increase the number of rows used for type inference",
+                                s, name
+                            ));
+                        }
+                        result.push(RawVal::Int(float as i64));
                     } else {
-                        result.push(RawVal::Int(0));
-                    }
-                } else if let Ok(int) = s.parse::<i64>() {
-                    result.push(RawVal::Int(int));
-                } else if let Ok(float) = s.parse::<f64>() {
-                    result.push(RawVal::Int(float as i64));
-                } else {
-                    unreachable!(
-                        "{} should be parseable as int or float. {} {:?}",
-                        s, name, self.types
-                    )
-                };
+                        return Err(format!(
+                            "Value {} in column {} is not parseable as int or float",
+                            s, name
+                        ));
+                    };
+                }
+                result
             }
-            result
-        } else {
-            vec![RawVal::Null; self.values.len()]
+            None => vec![RawVal::Null; self.values.len()],
         };
         self.clear();
-        result
+        Ok(result)
     }
 
     fn extract(&mut self, extractor: extractor::Extractor) -> Vec<RawVal> {
@@ -474,6 +629,18 @@ impl ColType {
         ColType::new(false, false, false, false)
     }
 
+    fn column_type(&self) -> Option<table_schema::ColumnType> {
+        if self.contains_string {
+            Some(table_schema::ColumnType::String)
+        } else if self.contains_float {
+            Some(table_schema::ColumnType::Float)
+        } else if self.contains_int {
+            Some(table_schema::ColumnType::Integer)
+        } else {
+            None
+        }
+    }
+
     fn determine(s: &str) -> ColType {
         if s.is_empty() {
             ColType::null()
@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Identifies the compression format from the magic bytes at the start of a file.
+    pub fn detect(header: &[u8]) -> Compression {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens a file for reading and transparently decompresses gzip, zstd, bzip2 and xz input.
+/// Formats other than gzip require the `compressed_input` feature.
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Transparently decompresses gzip, zstd, bzip2 and xz input read from `reader`.
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    decoder(reader, compression)
+}
+
+fn decoder<R: BufRead + 'static>(
+    reader: R,
+    compression: Compression,
+) -> io::Result<Box<dyn BufRead>> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => buffered(MultiGzDecoder::new(reader)),
+        #[cfg(feature = "compressed_input")]
+        Compression::Zstd => buffered(zstd::stream::read::Decoder::with_buffer(reader)?),
+        #[cfg(feature = "compressed_input")]
+        Compression::Bzip2 => buffered(bzip2::read::MultiBzDecoder::new(reader)),
+        #[cfg(feature = "compressed_input")]
+        Compression::Xz => buffered(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        #[cfg(not(feature = "compressed_input"))]
+        compression => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "{:?} compressed input requires the `compressed_input` feature",
+                    compression
+                ),
+            ))
+        }
+    })
+}
+
+fn buffered<R: Read + 'static>(reader: R) -> Box<dyn BufRead> {
+    Box::new(BufReader::new(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(b"a,b,c\n"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x04]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::detect(b"BZh91AY&SY"), Compression::Bzip2);
+        assert_eq!(
+            Compression::detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00]),
+            Compression::Xz
+        );
+    }
+}
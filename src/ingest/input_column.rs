@@ -1,9 +1,43 @@
+use ordered_float::OrderedFloat;
+
+use crate::ingest::raw_val::RawVal;
+
 #[allow(dead_code)]
 pub enum InputColumn {
     Int(Vec<i64>),
     Float(Vec<f64>),
     NullableFloat(u64, Vec<(u64, f64)>),
+    NullableInt(u64, Vec<(u64, i64)>),
     Str(Vec<String>),
+    NullableStr(u64, Vec<(u64, String)>),
     Null(usize),
+    /// Values of any type, with omitted rows being null
+    Mixed(u64, Vec<(u64, RawVal)>),
 }
 
+impl InputColumn {
+    pub fn into_raw_vals(self) -> Vec<RawVal> {
+        fn sparse<T>(len: u64, values: Vec<(u64, T)>, f: impl Fn(T) -> RawVal) -> Vec<RawVal> {
+            let mut result = vec![RawVal::Null; len as usize];
+            for (i, value) in values {
+                result[i as usize] = f(value);
+            }
+            result
+        }
+        match self {
+            InputColumn::Int(values) => values.into_iter().map(RawVal::Int).collect(),
+            InputColumn::Float(values) => values
+                .into_iter()
+                .map(|f| RawVal::Float(OrderedFloat(f)))
+                .collect(),
+            InputColumn::Str(values) => values.into_iter().map(RawVal::Str).collect(),
+            InputColumn::NullableInt(len, values) => sparse(len, values, RawVal::Int),
+            InputColumn::NullableFloat(len, values) => {
+                sparse(len, values, |f| RawVal::Float(OrderedFloat(f)))
+            }
+            InputColumn::NullableStr(len, values) => sparse(len, values, RawVal::Str),
+            InputColumn::Null(len) => vec![RawVal::Null; len],
+            InputColumn::Mixed(len, values) => sparse(len, values, |val| val),
+        }
+    }
+}
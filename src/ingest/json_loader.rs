@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::iter;
+
+use ordered_float::OrderedFloat;
+use serde_json::Value;
+
+use crate::ingest::raw_val::RawVal;
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+
+/// Number of rows that are written to the WAL at once.
+const BATCH_SIZE: usize = 1 << 16;
+
+/// Ingests newline-delimited JSON objects into `table` and returns the number of rows.
+/// Nested objects are flattened into dotted column names, e.g. `{"a": {"b": 1}}` is stored as column `a.b`.
+/// Booleans are stored as integers and arrays as their JSON string representation.
+/// Rows are ingested in batches while reading, so if a line is invalid the rows of preceding batches have already
+/// been ingested and their number is included in the error message. Use `load_ndjson_atomic` to validate all rows
+/// first.
+pub fn load_ndjson<R: BufRead>(
+    ldb: &InnerLocustDB,
+    table: &str,
+    reader: R,
+) -> Result<usize, QueryError> {
+    let mut ingested = 0;
+    let result = read_batches(reader, |batch| {
+        let rows = batch.len;
+        ldb.ingest_column_batches(table, iter::once(batch.columns))?;
+        ingested += rows;
+        Ok(())
+    });
+    result.map_err(|err| match err {
+        QueryError::ParseError(msg) if ingested > 0 => QueryError::ParseError(format!(
+            "{} ({} preceding rows were ingested)",
+            msg, ingested
+        )),
+        err => err,
+    })
+}
+
+/// Like `load_ndjson`, but parses all rows before ingesting them at once, so that either all or none of the rows are
+/// ingested.
+pub fn load_ndjson_atomic<R: BufRead>(
+    ldb: &InnerLocustDB,
+    table: &str,
+    reader: R,
+) -> Result<usize, QueryError> {
+    let mut batches = Vec::new();
+    let rows = read_batches(reader, |batch| {
+        batches.push(batch.columns);
+        Ok(())
+    })?;
+    ldb.ingest_column_batches(table, batches)?;
+    Ok(rows)
+}
+
+/// Parses newline-delimited JSON objects and passes them to `ingest` in batches of up to `BATCH_SIZE` rows.
+/// Returns the number of rows.
+fn read_batches<R: BufRead>(
+    reader: R,
+    mut ingest: impl FnMut(ColumnsBuilder) -> Result<(), QueryError>,
+) -> Result<usize, QueryError> {
+    let mut batch = ColumnsBuilder::default();
+    let mut rows = 0;
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| fatal!("Failed to read line {}: {}", line_number + 1, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value = serde_json::from_str(&line).map_err(|e| {
+            QueryError::ParseError(format!("Invalid JSON on line {}: {}", line_number + 1, e))
+        })?;
+        match value {
+            Value::Object(object) => {
+                let mut row = Vec::with_capacity(object.len());
+                for (name, value) in object {
+                    flatten(name, value, &mut row);
+                }
+                batch.push_row(row);
+            }
+            _ => bail!(
+                QueryError::ParseError,
+                "Expected JSON object on line {}",
+                line_number + 1
+            ),
+        }
+        rows += 1;
+        if batch.len == BATCH_SIZE {
+            ingest(std::mem::take(&mut batch))?;
+        }
+    }
+    if batch.len > 0 {
+        ingest(batch)?;
+    }
+    Ok(rows)
+}
+
+pub(crate) fn flatten(name: String, value: Value, row: &mut Vec<(String, RawVal)>) {
+    let value = match value {
+        Value::Object(object) => {
+            for (field, value) in object {
+                flatten(format!("{}.{}", name, field), value, row);
+            }
+            return;
+        }
+        Value::Null => RawVal::Null,
+        Value::Bool(b) => RawVal::Int(i64::from(b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => RawVal::Int(i),
+            None => RawVal::Float(OrderedFloat(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        Value::String(s) => RawVal::Str(s),
+        array @ Value::Array(_) => RawVal::Str(array.to_string()),
+    };
+    row.push((name, value));
+}
+
+/// Collects rows with differing sets of fields into columns of equal length.
+#[derive(Default)]
+pub(crate) struct ColumnsBuilder {
+    pub columns: HashMap<String, Vec<RawVal>>,
+    pub len: usize,
+}
+
+impl ColumnsBuilder {
+    pub fn push_row(&mut self, row: Vec<(String, RawVal)>) {
+        let len = self.len;
+        for (name, value) in row {
+            let column = self
+                .columns
+                .entry(name)
+                .or_insert_with(|| vec![RawVal::Null; len]);
+            // Duplicate keys keep the last value
+            if column.len() > len {
+                column.pop();
+            }
+            column.push(value);
+        }
+        self.len += 1;
+        for column in self.columns.values_mut() {
+            column.resize(self.len, RawVal::Null);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::message::Message;
+use serde_json::Value;
+
+use crate::ingest::json_loader::flatten;
+use crate::ingest::raw_val::RawVal;
+use crate::logging_client::EventBuffer;
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+
+#[derive(Clone, Debug)]
+pub enum KafkaFormat {
+    /// Each message contains one or more newline-separated JSON objects that are ingested as rows of `table`.
+    /// Nested objects are flattened into dotted column names. Only numeric and boolean fields are ingested.
+    Json { table: String },
+    /// Each message contains a bincode serialized `EventBuffer`, the format used by `/insert_bin`.
+    EventBuffer,
+}
+
+#[derive(Clone, Debug)]
+pub struct KafkaOptions {
+    brokers: String,
+    topic: String,
+    group_id: String,
+    format: KafkaFormat,
+    max_batch_messages: usize,
+    max_batch_delay: Duration,
+}
+
+impl KafkaOptions {
+    pub fn new(brokers: &str, topic: &str, group_id: &str, format: KafkaFormat) -> KafkaOptions {
+        KafkaOptions {
+            brokers: brokers.to_string(),
+            topic: topic.to_string(),
+            group_id: group_id.to_string(),
+            format,
+            max_batch_messages: 10_000,
+            max_batch_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Maximum number of messages combined into a single call to `ingest_efficient`.
+    #[must_use]
+    pub fn with_max_batch_messages(mut self, max_batch_messages: usize) -> KafkaOptions {
+        self.max_batch_messages = max_batch_messages;
+        self
+    }
+
+    /// Maximum time to wait for further messages before ingesting a partial batch.
+    #[must_use]
+    pub fn with_max_batch_delay(mut self, max_batch_delay: Duration) -> KafkaOptions {
+        self.max_batch_delay = max_batch_delay;
+        self
+    }
+}
+
+/// Consumes a Kafka topic on a background thread until dropped.
+/// Offsets are committed only after each batch has been written to the WAL, so messages are ingested at least once.
+/// Messages that cannot be decoded are logged and skipped.
+pub struct KafkaConsumer {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KafkaConsumer {
+    pub fn start(ldb: Arc<InnerLocustDB>, opts: KafkaOptions) -> Result<KafkaConsumer, QueryError> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &opts.brokers)
+            .set("group.id", &opts.group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(|e| fatal!("Failed to create Kafka consumer: {}", e))?;
+        consumer
+            .subscribe(&[&opts.topic])
+            .map_err(|e| fatal!("Failed to subscribe to topic {}: {}", opts.topic, e))?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || consume(&ldb, &consumer, &opts, &running))
+        };
+        Ok(KafkaConsumer {
+            running,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for KafkaConsumer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Kafka consumer thread panicked");
+            }
+        }
+    }
+}
+
+fn consume(
+    ldb: &InnerLocustDB,
+    consumer: &BaseConsumer,
+    opts: &KafkaOptions,
+    running: &AtomicBool,
+) {
+    while running.load(Ordering::SeqCst) {
+        let mut events = EventBuffer::default();
+        let mut messages = 0;
+        let deadline = Instant::now() + opts.max_batch_delay;
+        while messages < opts.max_batch_messages && running.load(Ordering::SeqCst) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                break;
+            }
+            match consumer.poll(timeout) {
+                None => break,
+                Some(Err(e)) => log::warn!("Failed to receive Kafka message: {}", e),
+                Some(Ok(message)) => {
+                    messages += 1;
+                    let payload = message.payload().unwrap_or_default();
+                    if let Err(e) = decode(payload, &opts.format, &mut events) {
+                        log::warn!(
+                            "Skipping Kafka message at offset {} of partition {}: {}",
+                            message.offset(),
+                            message.partition(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+        if messages > 0 {
+            if let Err(e) = ldb.ingest_efficient(events) {
+                // Offsets are not committed, so the messages are consumed again after a restart
+                log::warn!("Stopping Kafka consumer: {}", e);
+                return;
+            }
+            if let Err(e) = consumer.commit_consumer_state(CommitMode::Sync) {
+                log::error!("Failed to commit Kafka offsets: {}", e);
+            }
+        }
+    }
+}
+
+fn decode(
+    payload: &[u8],
+    format: &KafkaFormat,
+    events: &mut EventBuffer,
+) -> Result<(), QueryError> {
+    match format {
+        KafkaFormat::Json { table } => {
+            let payload = std::str::from_utf8(payload)
+                .map_err(|e| QueryError::ParseError(format!("Invalid UTF-8: {}", e)))?;
+            let mut rows = Vec::new();
+            for line in payload.lines().filter(|line| !line.trim().is_empty()) {
+                match serde_json::from_str(line) {
+                    Ok(Value::Object(object)) => {
+                        let mut row = Vec::with_capacity(object.len());
+                        for (name, value) in object {
+                            flatten(name, value, &mut row);
+                        }
+                        rows.push(row);
+                    }
+                    Ok(_) => bail!(QueryError::ParseError, "Expected JSON object"),
+                    Err(e) => bail!(QueryError::ParseError, "Invalid JSON: {}", e),
+                }
+            }
+            if rows.is_empty() {
+                return Ok(());
+            }
+            let buffer = events.tables.entry(table.clone()).or_default();
+            for row in rows {
+                buffer.push_row(row.into_iter().filter_map(|(name, value)| match value {
+                    RawVal::Int(i) => Some((name, i as f64)),
+                    RawVal::Float(f) => Some((name, f.0)),
+                    RawVal::Str(_) | RawVal::Null => None,
+                }));
+            }
+        }
+        KafkaFormat::EventBuffer => {
+            let decoded: EventBuffer = bincode::deserialize(payload).map_err(|e| {
+                QueryError::ParseError(format!("Failed to deserialize EventBuffer: {}", e))
+            })?;
+            for (table, rows) in decoded.tables {
+                events.tables.entry(table).or_default().append(rows);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging_client::ColumnData;
+
+    #[test]
+    fn test_decode() {
+        let mut events = EventBuffer::default();
+        let format = KafkaFormat::Json {
+            table: "t".to_string(),
+        };
+        decode(br#"{"a": 1, "b": {"c": 2.5}}"#, &format, &mut events).unwrap();
+        decode(
+            b"{\"a\": 3, \"s\": \"x\"}\n{\"b\": {\"c\": 4}}",
+            &format,
+            &mut events,
+        )
+        .unwrap();
+        assert!(decode(b"[1]", &format, &mut events).is_err());
+
+        let mut other = EventBuffer::default();
+        decode(br#"{"a": 5}"#, &format, &mut other).unwrap();
+        let serialized = bincode::serialize(&other).unwrap();
+        decode(&serialized, &KafkaFormat::EventBuffer, &mut events).unwrap();
+
+        let table = &events.tables["t"];
+        assert_eq!(table.len, 4);
+        assert!(!table.columns.contains_key("s"));
+        match &table.columns["a"].data {
+            ColumnData::Sparse(values) => assert_eq!(values, &[(0, 1.0), (1, 3.0), (3, 5.0)]),
+            data => panic!("Expected sparse column, got {:?}", data),
+        }
+        match &table.columns["b.c"].data {
+            ColumnData::Sparse(values) => assert_eq!(values, &[(0, 2.5), (2, 4.0)]),
+            data => panic!("Expected sparse column, got {:?}", data),
+        }
+    }
+}
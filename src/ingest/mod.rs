@@ -1,5 +1,21 @@
+#[cfg(feature = "arrow_ingest")]
+pub mod arrow;
 pub mod csv_loader;
+pub mod decompress;
+pub mod json_loader;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "object_store_import")]
+pub mod object_store;
+#[cfg(feature = "parquet_import")]
+pub mod parquet_loader;
+#[cfg(feature = "postgres_cdc")]
+pub mod postgres_cdc;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
 pub mod raw_val;
+#[cfg(feature = "sqlite_import")]
+pub mod sqlite_loader;
 pub mod input_column;
 pub mod buffer;
 pub mod extractor;
@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::{stream, StreamExt};
+
+use crate::disk_store::s3::{
+    authorize, canonical_query, unescape_xml, uri_encode, xml_elements, Credentials,
+};
+use crate::ingest::csv_loader::{self, Options as CsvOptions};
+use crate::ingest::decompress;
+use crate::scheduler::InnerLocustDB;
+use crate::unit_fmt;
+use crate::QueryError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFormat {
+    Csv,
+    Parquet,
+}
+
+impl ObjectFormat {
+    /// Determines the format from the extension of an object key. CSV objects may additionally be compressed.
+    /// Returns `None` for objects that are not imported.
+    pub fn from_key(key: &str) -> Option<ObjectFormat> {
+        let key = key.to_lowercase();
+        if key.ends_with(".parquet") {
+            return Some(ObjectFormat::Parquet);
+        }
+        let key = [".gz", ".zst", ".bz2", ".xz"]
+            .iter()
+            .find_map(|suffix| key.strip_suffix(suffix))
+            .unwrap_or(key.as_str());
+        if key.ends_with(".csv") {
+            Some(ObjectFormat::Csv)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct BulkImportOptions {
+    table: String,
+    bucket: String,
+    prefix: String,
+    endpoint: Option<String>,
+    region: String,
+    credentials: Credentials,
+    concurrency: usize,
+    checkpoint: Option<PathBuf>,
+    csv_options: CsvOptions,
+}
+
+impl BulkImportOptions {
+    /// Imports all CSV and Parquet objects under `url` into `table`.
+    /// `url` has the form `s3://bucket/prefix` for S3 or `gs://bucket/prefix` for Google Cloud Storage.
+    pub fn new(url: &str, table: &str) -> Result<BulkImportOptions, QueryError> {
+        let (scheme, path) = match url.split_once("://") {
+            Some(parts) => parts,
+            None => bail!(
+                QueryError::ParseError,
+                "Expected s3:// or gs:// URL, got {}",
+                url
+            ),
+        };
+        let (endpoint, region) = match scheme {
+            "s3" => (None, "us-east-1"),
+            "gs" => (Some("https://storage.googleapis.com".to_string()), "auto"),
+            _ => bail!(
+                QueryError::NotImplemented,
+                "Importing from {}:// URLs",
+                scheme
+            ),
+        };
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if bucket.is_empty() {
+            bail!(QueryError::ParseError, "Missing bucket in {}", url);
+        }
+        Ok(BulkImportOptions {
+            table: table.to_string(),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            endpoint,
+            region: region.to_string(),
+            credentials: Credentials::Anonymous,
+            concurrency: 8,
+            checkpoint: None,
+            csv_options: CsvOptions::new("", table),
+        })
+    }
+
+    /// Base URL of an S3 compatible object store, e.g. `http://localhost:9000` for a local MinIO instance.
+    /// Objects are addressed with path-style URLs.
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: &str) -> BulkImportOptions {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn with_region(mut self, region: &str) -> BulkImportOptions {
+        self.region = region.to_string();
+        self
+    }
+
+    /// Signs requests with AWS Signature Version 4. Also works with GCS HMAC keys.
+    #[must_use]
+    pub fn with_aws_credentials(
+        mut self,
+        access_key_id: &str,
+        secret_access_key: &str,
+    ) -> BulkImportOptions {
+        self.credentials = Credentials::Aws {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+        };
+        self
+    }
+
+    /// Authenticates requests with an OAuth 2.0 access token, e.g. the output of `gcloud auth print-access-token`.
+    #[must_use]
+    pub fn with_bearer_token(mut self, token: &str) -> BulkImportOptions {
+        self.credentials = Credentials::Bearer(token.to_string());
+        self
+    }
+
+    /// Maximum number of objects that are downloaded at the same time.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> BulkImportOptions {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Records the keys of fully ingested objects in the file at `path`.
+    /// Objects listed in the file are skipped, so an interrupted import can be resumed by running it again.
+    #[must_use]
+    pub fn with_checkpoint<P: AsRef<Path>>(mut self, path: P) -> BulkImportOptions {
+        self.checkpoint = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Configures how CSV objects are parsed. Type inference is not supported.
+    #[must_use]
+    pub fn with_csv_options(
+        mut self,
+        configure: impl FnOnce(CsvOptions) -> CsvOptions,
+    ) -> BulkImportOptions {
+        self.csv_options = configure(CsvOptions::new("", &self.table));
+        self
+    }
+
+    fn endpoint(&self) -> String {
+        match &self.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://s3.{}.amazonaws.com", self.region),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BulkImportSummary {
+    /// Number of objects that were ingested.
+    pub objects: usize,
+    /// Number of objects that were skipped because they were already ingested or have an unsupported format.
+    pub skipped: usize,
+    pub rows: usize,
+    pub bytes: usize,
+}
+
+/// Lists all objects under the prefix and ingests them, downloading up to `concurrency` objects at the same time.
+/// Each object is downloaded into memory and persisted before the next object is ingested.
+/// The import stops at the first object that fails to download or ingest.
+/// Must be called from within a Tokio runtime.
+pub async fn import(
+    ldb: Arc<InnerLocustDB>,
+    opts: BulkImportOptions,
+) -> Result<BulkImportSummary, QueryError> {
+    let client = reqwest::Client::new();
+    let opts = Arc::new(opts);
+    let mut checkpoint = None;
+    let mut completed = HashSet::new();
+    if let Some(path) = &opts.checkpoint {
+        if path.exists() {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| fatal!("Failed to read checkpoint {}: {}", path.display(), e))?;
+            completed.extend(contents.lines().map(str::to_string));
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| fatal!("Failed to open checkpoint {}: {}", path.display(), e))?;
+        checkpoint = Some(file);
+    }
+
+    let mut summary = BulkImportSummary::default();
+    let mut pending = Vec::new();
+    for (key, size) in list(&client, &opts).await? {
+        match ObjectFormat::from_key(&key) {
+            Some(format) if !completed.contains(&key) => pending.push((key, size, format)),
+            _ => summary.skipped += 1,
+        }
+    }
+    let total_objects = pending.len();
+    let total_bytes = pending.iter().map(|(_, size, _)| size).sum::<usize>();
+    log::info!(
+        "Importing {} objects ({}) from {}/{}, skipping {}",
+        total_objects,
+        unit_fmt::bite(total_bytes),
+        opts.bucket,
+        opts.prefix,
+        summary.skipped,
+    );
+
+    let mut downloads = stream::iter(pending)
+        .map(|(key, _, format)| {
+            let client = client.clone();
+            let opts = opts.clone();
+            tokio::spawn(async move {
+                let data = get(&client, &opts, &key, &[]).await;
+                (key, format, data)
+            })
+        })
+        .buffer_unordered(opts.concurrency);
+    while let Some(download) = downloads.next().await {
+        let (key, format, data) = download.map_err(|e| fatal!("Download failed: {}", e))?;
+        let data = data?;
+        let bytes = data.len();
+        let rows = {
+            let ldb = ldb.clone();
+            let opts = opts.clone();
+            tokio::task::spawn_blocking(move || ingest_object(&ldb, &opts, data, format))
+                .await
+                .map_err(|e| fatal!("Ingestion failed: {}", e))?
+                .map_err(|e| fatal!("Failed to ingest {}: {}", key, e))?
+        };
+        if let Some(file) = &mut checkpoint {
+            writeln!(file, "{}", key)
+                .and_then(|_| file.sync_data())
+                .map_err(|e| fatal!("Failed to write checkpoint: {}", e))?;
+        }
+        summary.objects += 1;
+        summary.rows += rows;
+        summary.bytes += bytes;
+        log::info!(
+            "Imported {} ({} rows), {}/{} objects, {}/{}",
+            key,
+            rows,
+            summary.objects,
+            total_objects,
+            unit_fmt::bite(summary.bytes),
+            unit_fmt::bite(total_bytes),
+        );
+    }
+    Ok(summary)
+}
+
+fn ingest_object(
+    ldb: &InnerLocustDB,
+    opts: &BulkImportOptions,
+    data: Vec<u8>,
+    format: ObjectFormat,
+) -> Result<usize, QueryError> {
+    match format {
+        ObjectFormat::Csv => {
+            let reader = decompress::decompress(Cursor::new(data))
+                .map_err(|e| QueryError::ParseError(e.to_string()))?;
+            csv_loader::ingest_reader(ldb, reader, &opts.csv_options)
+                .map_err(QueryError::ParseError)
+        }
+        ObjectFormat::Parquet => ingest_parquet(ldb, &opts.table, data),
+    }
+}
+
+#[cfg(feature = "parquet_import")]
+fn ingest_parquet(ldb: &InnerLocustDB, table: &str, data: Vec<u8>) -> Result<usize, QueryError> {
+    let mut file = tempfile::tempfile().map_err(|e| fatal!("{}", e))?;
+    file.write_all(&data).map_err(|e| fatal!("{}", e))?;
+    crate::ingest::parquet_loader::ingest_file(ldb, table, file)
+}
+
+#[cfg(not(feature = "parquet_import"))]
+fn ingest_parquet(_: &InnerLocustDB, _: &str, _: Vec<u8>) -> Result<usize, QueryError> {
+    bail!(
+        QueryError::NotImplemented,
+        "Parquet import requires the `parquet_import` feature"
+    )
+}
+
+/// Returns the key and size of all objects under the prefix using the ListObjectsV2 API.
+async fn list(
+    client: &reqwest::Client,
+    opts: &BulkImportOptions,
+) -> Result<Vec<(String, usize)>, QueryError> {
+    let mut objects = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let mut query = vec![
+            ("list-type", "2".to_string()),
+            ("prefix", opts.prefix.clone()),
+        ];
+        if let Some(token) = continuation_token.take() {
+            query.push(("continuation-token", token));
+        }
+        let body = get(client, opts, "", &query).await?;
+        let body = String::from_utf8_lossy(&body);
+        for contents in xml_elements(&body, "Contents") {
+            let key = match xml_elements(contents, "Key").first() {
+                Some(key) => unescape_xml(key),
+                None => bail!(QueryError::ParseError, "Object without key: {}", contents),
+            };
+            let size = xml_elements(contents, "Size")
+                .first()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0);
+            objects.push((key, size));
+        }
+        match xml_elements(&body, "NextContinuationToken").first() {
+            Some(token) => continuation_token = Some(unescape_xml(token)),
+            None => return Ok(objects),
+        }
+    }
+}
+
+async fn get(
+    client: &reqwest::Client,
+    opts: &BulkImportOptions,
+    key: &str,
+    query: &[(&str, String)],
+) -> Result<Vec<u8>, QueryError> {
+    let path = if key.is_empty() {
+        format!("/{}", uri_encode(&opts.bucket, true))
+    } else {
+        format!(
+            "/{}/{}",
+            uri_encode(&opts.bucket, true),
+            uri_encode(key, false)
+        )
+    };
+    let query = canonical_query(query);
+    let endpoint = opts.endpoint();
+    let url = if query.is_empty() {
+        format!("{}{}", endpoint, path)
+    } else {
+        format!("{}{}?{}", endpoint, path, query)
+    };
+
+    let request = authorize(
+        client.get(&url),
+        &opts.credentials,
+        "GET",
+        &endpoint,
+        &opts.region,
+        &path,
+        &query,
+    );
+    let response = request
+        .send()
+        .await
+        .map_err(|e| fatal!("GET {} failed: {}", url, e))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(fatal!(
+            "GET {} failed with status {}: {}",
+            url,
+            status,
+            body
+        ));
+    }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| fatal!("GET {} failed: {}", url, e))?;
+    Ok(body.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_response() {
+        let body = "<ListBucketResult><Name>b</Name>\
+            <Contents><Key>data/a.csv.gz</Key><Size>123</Size></Contents>\
+            <Contents><Key>data/b&amp;c.parquet</Key><Size>45</Size></Contents>\
+            <Contents><Key>data/_SUCCESS</Key><Size>0</Size></Contents>\
+            <NextContinuationToken>token</NextContinuationToken></ListBucketResult>";
+        let contents = xml_elements(body, "Contents");
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            unescape_xml(xml_elements(contents[1], "Key")[0]),
+            "data/b&c.parquet"
+        );
+        assert_eq!(xml_elements(body, "NextContinuationToken"), vec!["token"]);
+
+        assert_eq!(
+            ObjectFormat::from_key("data/a.csv.gz"),
+            Some(ObjectFormat::Csv)
+        );
+        assert_eq!(
+            ObjectFormat::from_key("data/b.PARQUET"),
+            Some(ObjectFormat::Parquet)
+        );
+        assert_eq!(ObjectFormat::from_key("data/_SUCCESS"), None);
+    }
+}
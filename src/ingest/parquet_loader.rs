@@ -0,0 +1,70 @@
+use std::fs::File;
+
+use ordered_float::OrderedFloat;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+
+use crate::ingest::json_loader::ColumnsBuilder;
+use crate::ingest::raw_val::RawVal;
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+
+/// Number of rows that are written to a new partition at once.
+const BATCH_SIZE: usize = 1 << 16;
+
+/// Ingests all rows of a Parquet file into `table` and returns the number of rows.
+/// Booleans are stored as integers, timestamps as microseconds since the Unix epoch and dates as days since the Unix
+/// epoch. Nested, binary and decimal fields are stored as their string representation.
+pub fn ingest_file(ldb: &InnerLocustDB, table: &str, file: File) -> Result<usize, QueryError> {
+    let reader = SerializedFileReader::new(file)
+        .map_err(|e| QueryError::ParseError(format!("Invalid Parquet file: {}", e)))?;
+    let row_iter = reader
+        .get_row_iter(None)
+        .map_err(|e| QueryError::ParseError(format!("Failed to read Parquet file: {}", e)))?;
+    let mut batch = ColumnsBuilder::default();
+    let mut rows = 0;
+    for row in row_iter {
+        let row =
+            row.map_err(|e| QueryError::ParseError(format!("Failed to read Parquet row: {}", e)))?;
+        batch.push_row(
+            row.get_column_iter()
+                .map(|(name, field)| (name.clone(), convert(field)))
+                .collect(),
+        );
+        rows += 1;
+        if batch.len == BATCH_SIZE {
+            ldb.ingest_heterogeneous(table, std::mem::take(&mut batch).columns);
+            ldb.flush_buffers()?;
+        }
+    }
+    if batch.len > 0 {
+        ldb.ingest_heterogeneous(table, batch.columns);
+        ldb.flush_buffers()?;
+    }
+    Ok(rows)
+}
+
+fn convert(field: &Field) -> RawVal {
+    match *field {
+        Field::Null => RawVal::Null,
+        Field::Bool(b) => RawVal::Int(i64::from(b)),
+        Field::Byte(i) => RawVal::Int(i64::from(i)),
+        Field::Short(i) => RawVal::Int(i64::from(i)),
+        Field::Int(i) => RawVal::Int(i64::from(i)),
+        Field::Long(i) => RawVal::Int(i),
+        Field::UByte(i) => RawVal::Int(i64::from(i)),
+        Field::UShort(i) => RawVal::Int(i64::from(i)),
+        Field::UInt(i) => RawVal::Int(i64::from(i)),
+        Field::ULong(i) => match i64::try_from(i) {
+            Ok(i) => RawVal::Int(i),
+            Err(_) => RawVal::Str(i.to_string()),
+        },
+        Field::Float(f) => RawVal::Float(OrderedFloat(f64::from(f))),
+        Field::Double(f) => RawVal::Float(OrderedFloat(f)),
+        Field::Str(ref s) => RawVal::Str(s.clone()),
+        Field::Date(days) => RawVal::Int(i64::from(days)),
+        Field::TimestampMillis(t) => RawVal::Int(t.saturating_mul(1000)),
+        Field::TimestampMicros(t) => RawVal::Int(t),
+        _ => RawVal::Str(field.to_string()),
+    }
+}
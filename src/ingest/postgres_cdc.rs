@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use byteorder::{BigEndian, ReadBytesExt};
+use chrono::{DateTime, NaiveDateTime};
+use ordered_float::OrderedFloat;
+use postgres::{Client, NoTls};
+
+use crate::ingest::json_loader::ColumnsBuilder;
+use crate::ingest::raw_val::RawVal;
+use crate::logging_client::{EventBuffer, TableBuffer};
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+
+#[derive(Clone, Debug)]
+pub struct PostgresCdcOptions {
+    connection: String,
+    slot: String,
+    publication: String,
+    max_changes: i32,
+    poll_interval: Duration,
+}
+
+impl PostgresCdcOptions {
+    /// `connection` is a libpq style connection string, e.g. `host=localhost user=postgres dbname=app`.
+    /// The logical replication slot is created with the `pgoutput` plugin if it does not exist yet.
+    pub fn new(connection: &str, slot: &str, publication: &str) -> PostgresCdcOptions {
+        PostgresCdcOptions {
+            connection: connection.to_string(),
+            slot: slot.to_string(),
+            publication: publication.to_string(),
+            max_changes: 10_000,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Maximum number of changes that are read from the replication slot at once.
+    #[must_use]
+    pub fn with_max_changes(mut self, max_changes: i32) -> PostgresCdcOptions {
+        self.max_changes = max_changes;
+        self
+    }
+
+    /// Time to wait before polling the replication slot again after it returned no changes.
+    #[must_use]
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> PostgresCdcOptions {
+        self.poll_interval = poll_interval;
+        self
+    }
+}
+
+/// Tails a Postgres publication on a background thread until dropped and appends all inserted rows to the table of
+/// the same name, or `schema.table` for tables outside the `public` schema. Updates, deletes and truncates are ignored.
+/// The replication slot is only advanced after changes are written to the WAL, so rows are ingested at least once.
+///
+/// Integer and boolean columns are stored as integers, floating point and numeric columns as floats and timestamps as
+/// microseconds since the Unix epoch. All other types are stored as their text representation.
+pub struct PostgresCdcConsumer {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PostgresCdcConsumer {
+    pub fn start(
+        ldb: Arc<InnerLocustDB>,
+        opts: PostgresCdcOptions,
+    ) -> Result<PostgresCdcConsumer, QueryError> {
+        let mut client = Client::connect(&opts.connection, NoTls)
+            .map_err(|e| fatal!("Failed to connect to Postgres: {}", e))?;
+        let slot_exists = client
+            .query_opt(
+                "SELECT 1 FROM pg_replication_slots WHERE slot_name = $1",
+                &[&opts.slot],
+            )
+            .map_err(|e| fatal!("Failed to query replication slots: {}", e))?
+            .is_some();
+        if !slot_exists {
+            client
+                .execute(
+                    "SELECT pg_create_logical_replication_slot($1, 'pgoutput')",
+                    &[&opts.slot],
+                )
+                .map_err(|e| fatal!("Failed to create replication slot {}: {}", opts.slot, e))?;
+        }
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || {
+                let mut relations = HashMap::new();
+                while running.load(Ordering::SeqCst) {
+                    match poll(&ldb, &mut client, &opts, &mut relations) {
+                        Ok(0) => thread::sleep(opts.poll_interval),
+                        Ok(_) => {}
+                        Err(e) => {
+                            log::error!("Failed to read changes from slot {}: {}", opts.slot, e);
+                            thread::sleep(opts.poll_interval);
+                        }
+                    }
+                }
+            })
+        };
+        Ok(PostgresCdcConsumer {
+            running,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for PostgresCdcConsumer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Postgres CDC thread panicked");
+            }
+        }
+    }
+}
+
+/// Columns of a table as announced by a relation message.
+struct Relation {
+    table: String,
+    columns: Vec<(String, u32)>,
+}
+
+enum Message {
+    Relation(u32, Relation),
+    Insert(u32, Vec<Option<String>>),
+    Other,
+}
+
+/// Ingests all pending changes and returns the number of changes that were read.
+fn poll(
+    ldb: &InnerLocustDB,
+    client: &mut Client,
+    opts: &PostgresCdcOptions,
+    relations: &mut HashMap<u32, Relation>,
+) -> Result<usize, QueryError> {
+    let changes = client
+        .query(
+            "SELECT lsn::text, data FROM pg_logical_slot_peek_binary_changes($1, NULL, $2, \
+             'proto_version', '1', 'publication_names', $3)",
+            &[&opts.slot, &opts.max_changes, &opts.publication],
+        )
+        .map_err(|e| fatal!("{}", e))?;
+    let last_lsn: String = match changes.last() {
+        Some(change) => change.get(0),
+        None => return Ok(0),
+    };
+
+    let mut tables = HashMap::<String, ColumnsBuilder>::new();
+    for change in &changes {
+        let data: Vec<u8> = change.get(1);
+        match decode(&data)? {
+            Message::Relation(id, relation) => {
+                relations.insert(id, relation);
+            }
+            Message::Insert(id, values) => {
+                let relation = match relations.get(&id) {
+                    Some(relation) => relation,
+                    None => bail!(
+                        QueryError::ParseError,
+                        "Insert into unknown relation {}",
+                        id
+                    ),
+                };
+                let row = relation
+                    .columns
+                    .iter()
+                    .zip(values)
+                    .map(|((name, type_oid), value)| (name.clone(), convert(*type_oid, value)))
+                    .collect();
+                tables
+                    .entry(relation.table.clone())
+                    .or_default()
+                    .push_row(row);
+            }
+            Message::Other => {}
+        }
+    }
+
+    // All tables are written to a single WAL segment before the slot is advanced
+    let mut events = EventBuffer::default();
+    for (table, rows) in tables {
+        events
+            .tables
+            .insert(table, TableBuffer::from_columns(rows.columns));
+    }
+    ldb.ingest_efficient(events)?;
+    client
+        .execute(
+            "SELECT pg_replication_slot_advance($1, $2::text::pg_lsn)",
+            &[&opts.slot, &last_lsn],
+        )
+        .map_err(|e| fatal!("Failed to advance replication slot: {}", e))?;
+    Ok(changes.len())
+}
+
+/// Decodes relation and insert messages of the `pgoutput` logical replication protocol.
+fn decode(data: &[u8]) -> Result<Message, QueryError> {
+    let mut cursor = Cursor::new(data);
+    let message = match read(cursor.read_u8())? {
+        b'R' => {
+            let id = read(cursor.read_u32::<BigEndian>())?;
+            let namespace = read_string(&mut cursor)?;
+            let name = read_string(&mut cursor)?;
+            let _replica_identity = read(cursor.read_u8())?;
+            let column_count = read(cursor.read_i16::<BigEndian>())?;
+            let mut columns = Vec::with_capacity(column_count.max(0) as usize);
+            for _ in 0..column_count {
+                let _flags = read(cursor.read_u8())?;
+                let column = read_string(&mut cursor)?;
+                let type_oid = read(cursor.read_u32::<BigEndian>())?;
+                let _type_modifier = read(cursor.read_i32::<BigEndian>())?;
+                columns.push((column, type_oid));
+            }
+            let table = if namespace == "public" {
+                name
+            } else {
+                format!("{}.{}", namespace, name)
+            };
+            Message::Relation(id, Relation { table, columns })
+        }
+        b'I' => {
+            let id = read(cursor.read_u32::<BigEndian>())?;
+            let _new_tuple = read(cursor.read_u8())?;
+            let column_count = read(cursor.read_i16::<BigEndian>())?;
+            let mut values = Vec::with_capacity(column_count.max(0) as usize);
+            for _ in 0..column_count {
+                match read(cursor.read_u8())? {
+                    b't' => {
+                        let len = read(cursor.read_i32::<BigEndian>())?;
+                        let mut bytes = vec![0; len.max(0) as usize];
+                        read(cursor.read_exact(&mut bytes))?;
+                        values.push(Some(String::from_utf8_lossy(&bytes).into_owned()));
+                    }
+                    // Null or unchanged TOAST value
+                    _ => values.push(None),
+                }
+            }
+            Message::Insert(id, values)
+        }
+        _ => Message::Other,
+    };
+    Ok(message)
+}
+
+fn read<T>(result: std::io::Result<T>) -> Result<T, QueryError> {
+    result.map_err(|e| QueryError::ParseError(format!("Truncated replication message: {}", e)))
+}
+
+fn read_string(cursor: &mut Cursor<&[u8]>) -> Result<String, QueryError> {
+    let mut bytes = Vec::new();
+    loop {
+        match read(cursor.read_u8())? {
+            0 => return Ok(String::from_utf8_lossy(&bytes).into_owned()),
+            byte => bytes.push(byte),
+        }
+    }
+}
+
+fn convert(type_oid: u32, value: Option<String>) -> RawVal {
+    const BOOL: u32 = 16;
+    const INT8: u32 = 20;
+    const INT2: u32 = 21;
+    const INT4: u32 = 23;
+    const OID: u32 = 26;
+    const FLOAT4: u32 = 700;
+    const FLOAT8: u32 = 701;
+    const TIMESTAMP: u32 = 1114;
+    const TIMESTAMPTZ: u32 = 1184;
+    const NUMERIC: u32 = 1700;
+
+    let value = match value {
+        Some(value) => value,
+        None => return RawVal::Null,
+    };
+    let converted = match type_oid {
+        BOOL => Some(RawVal::Int(i64::from(value == "t"))),
+        INT2 | INT4 | INT8 | OID => value.parse().ok().map(RawVal::Int),
+        FLOAT4 | FLOAT8 | NUMERIC => value.parse().ok().map(|f| RawVal::Float(OrderedFloat(f))),
+        TIMESTAMP => NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S%.f")
+            .ok()
+            .map(|t| RawVal::Int(t.timestamp_micros())),
+        TIMESTAMPTZ => DateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S%.f%#z")
+            .ok()
+            .map(|t| RawVal::Int(t.timestamp_micros())),
+        _ => None,
+    };
+    // Values that cannot be converted, e.g. `infinity` timestamps, are stored as strings
+    converted.unwrap_or(RawVal::Str(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut relation = vec![b'R'];
+        relation.extend(16384u32.to_be_bytes());
+        relation.extend(b"public\0events\0");
+        relation.push(b'd');
+        relation.extend(3i16.to_be_bytes());
+        for (name, type_oid) in [("id", 20u32), ("ts", 1184), ("name", 25)] {
+            relation.push(1);
+            relation.extend(name.as_bytes());
+            relation.push(0);
+            relation.extend(type_oid.to_be_bytes());
+            relation.extend((-1i32).to_be_bytes());
+        }
+        let relation = match decode(&relation).unwrap() {
+            Message::Relation(16384, relation) => relation,
+            _ => panic!("Expected relation message"),
+        };
+        assert_eq!(relation.table, "events");
+        assert_eq!(relation.columns[1], ("ts".to_string(), 1184));
+
+        let mut insert = vec![b'I'];
+        insert.extend(16384u32.to_be_bytes());
+        insert.push(b'N');
+        insert.extend(3i16.to_be_bytes());
+        for value in ["7", "2023-01-01 00:00:01.5+00"] {
+            insert.push(b't');
+            insert.extend((value.len() as i32).to_be_bytes());
+            insert.extend(value.as_bytes());
+        }
+        insert.push(b'n');
+        let values = match decode(&insert).unwrap() {
+            Message::Insert(16384, values) => values,
+            _ => panic!("Expected insert message"),
+        };
+        let row = relation
+            .columns
+            .iter()
+            .zip(values)
+            .map(|((_, type_oid), value)| convert(*type_oid, value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            row,
+            vec![
+                RawVal::Int(7),
+                RawVal::Int(1_672_531_201_500_000),
+                RawVal::Null
+            ]
+        );
+        assert!(decode(&insert[..8]).is_err());
+    }
+}
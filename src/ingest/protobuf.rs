@@ -0,0 +1,162 @@
+use std::iter;
+use std::sync::RwLock;
+
+use ordered_float::OrderedFloat;
+use prost_reflect::{DescriptorPool, DynamicMessage, FieldDescriptor, MapKey, Value};
+
+use crate::ingest::json_loader::ColumnsBuilder;
+use crate::ingest::raw_val::RawVal;
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+
+/// Message types that can be used to decode protobuf events.
+/// Descriptors are kept in memory only and have to be registered again after a restart.
+#[derive(Default)]
+pub struct ProtobufRegistry {
+    pool: RwLock<DescriptorPool>,
+}
+
+impl ProtobufRegistry {
+    /// Registers all message types of a serialized `google.protobuf.FileDescriptorSet`, e.g. the output of
+    /// `protoc --include_imports --descriptor_set_out`. Returns the full names of all registered message types.
+    pub fn register(&self, file_descriptor_set: &[u8]) -> Result<Vec<String>, QueryError> {
+        let mut pool = self.pool.read().unwrap().clone();
+        pool.decode_file_descriptor_set(file_descriptor_set)
+            .map_err(|e| QueryError::ParseError(format!("Invalid descriptor set: {}", e)))?;
+        let messages = pool
+            .all_messages()
+            .map(|message| message.full_name().to_string())
+            .collect();
+        *self.pool.write().unwrap() = pool;
+        Ok(messages)
+    }
+
+    /// Decodes a sequence of length-delimited messages of type `message` and ingests them as rows of `table`.
+    /// Returns the number of rows. No rows are ingested if any of the messages is invalid.
+    ///
+    /// Nested messages are flattened into dotted column names. Booleans are stored as integers, enums as the name of
+    /// their value, bytes as hex strings and repeated and map fields as their JSON representation.
+    /// Fields that support presence and are not set are stored as null, all other fields default to their zero value.
+    pub fn ingest(
+        &self,
+        ldb: &InnerLocustDB,
+        table: &str,
+        message: &str,
+        mut data: &[u8],
+    ) -> Result<usize, QueryError> {
+        let descriptor = match self.pool.read().unwrap().get_message_by_name(message) {
+            Some(descriptor) => descriptor,
+            None => bail!(
+                QueryError::NotImplemented,
+                "Unregistered message type {}",
+                message
+            ),
+        };
+        let mut batch = ColumnsBuilder::default();
+        let mut rows = 0;
+        while !data.is_empty() {
+            let len = prost::encoding::decode_varint(&mut data)
+                .map_err(|e| QueryError::ParseError(format!("Invalid length prefix: {}", e)))?;
+            if len > data.len() as u64 {
+                bail!(
+                    QueryError::ParseError,
+                    "Message {} is truncated: expected {} bytes, got {}",
+                    rows,
+                    len,
+                    data.len()
+                );
+            }
+            let (encoded, rest) = data.split_at(len as usize);
+            data = rest;
+            let decoded = DynamicMessage::decode(descriptor.clone(), encoded).map_err(|e| {
+                QueryError::ParseError(format!("Failed to decode message {}: {}", rows, e))
+            })?;
+            let mut row = Vec::new();
+            flatten("", &decoded, &mut row);
+            batch.push_row(row);
+            rows += 1;
+        }
+        ldb.ingest_column_batches(table, iter::once(batch.columns))?;
+        Ok(rows)
+    }
+}
+
+fn flatten(prefix: &str, message: &DynamicMessage, row: &mut Vec<(String, RawVal)>) {
+    for field in message.descriptor().fields() {
+        if field.supports_presence() && !message.has_field(&field) {
+            continue;
+        }
+        let name = if prefix.is_empty() {
+            field.name().to_string()
+        } else {
+            format!("{}.{}", prefix, field.name())
+        };
+        match &*message.get_field(&field) {
+            Value::Message(nested) => flatten(&name, nested, row),
+            value => row.push((name, convert(&field, value))),
+        }
+    }
+}
+
+fn convert(field: &FieldDescriptor, value: &Value) -> RawVal {
+    match *value {
+        Value::Bool(b) => RawVal::Int(i64::from(b)),
+        Value::I32(i) => RawVal::Int(i64::from(i)),
+        Value::I64(i) => RawVal::Int(i),
+        Value::U32(u) => RawVal::Int(i64::from(u)),
+        Value::U64(u) => match i64::try_from(u) {
+            Ok(i) => RawVal::Int(i),
+            Err(_) => RawVal::Str(u.to_string()),
+        },
+        Value::F32(f) => RawVal::Float(OrderedFloat(f64::from(f))),
+        Value::F64(f) => RawVal::Float(OrderedFloat(f)),
+        Value::String(ref s) => RawVal::Str(s.clone()),
+        Value::Bytes(ref bytes) => RawVal::Str(hex::encode(bytes)),
+        Value::EnumNumber(number) => match field
+            .kind()
+            .as_enum()
+            .and_then(|enum_descriptor| enum_descriptor.get_value(number))
+        {
+            Some(enum_value) => RawVal::Str(enum_value.name().to_string()),
+            None => RawVal::Int(i64::from(number)),
+        },
+        Value::Message(_) | Value::List(_) | Value::Map(_) => {
+            RawVal::Str(to_json(value).to_string())
+        }
+    }
+}
+
+fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Bool(b) => (*b).into(),
+        Value::I32(i) | Value::EnumNumber(i) => (*i).into(),
+        Value::I64(i) => (*i).into(),
+        Value::U32(u) => (*u).into(),
+        Value::U64(u) => (*u).into(),
+        Value::F32(f) => f64::from(*f).into(),
+        Value::F64(f) => (*f).into(),
+        Value::String(s) => s.clone().into(),
+        Value::Bytes(bytes) => hex::encode(bytes).into(),
+        Value::Message(message) => message
+            .fields()
+            .map(|(field, value)| (field.name().to_string(), to_json(value)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::List(values) => values.iter().map(to_json).collect(),
+        Value::Map(entries) => entries
+            .iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    MapKey::Bool(b) => b.to_string(),
+                    MapKey::I32(i) => i.to_string(),
+                    MapKey::I64(i) => i.to_string(),
+                    MapKey::U32(u) => u.to_string(),
+                    MapKey::U64(u) => u.to_string(),
+                    MapKey::String(s) => s.clone(),
+                };
+                (key, to_json(value))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
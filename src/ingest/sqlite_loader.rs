@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::iter;
+use std::path::Path;
+
+use ordered_float::OrderedFloat;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::ingest::raw_val::RawVal;
+use crate::scheduler::InnerLocustDB;
+use crate::QueryError;
+
+/// Number of rows that are written to a WAL segment at once.
+const BATCH_SIZE: usize = 1 << 16;
+
+/// Loads each of `tables` from the SQLite database at `path` into a LocustDB table of the same name, or all tables
+/// if `tables` is empty. Returns the name and number of rows of each imported table.
+/// Integer, real and text values keep their type, blobs are stored as hex strings.
+pub fn import(
+    ldb: &InnerLocustDB,
+    path: &Path,
+    tables: &[&str],
+) -> Result<Vec<(String, usize)>, QueryError> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| fatal!("Failed to open {}: {}", path.display(), e))?;
+    let tables = if tables.is_empty() {
+        list_tables(&conn)?
+    } else {
+        tables.iter().map(|table| table.to_string()).collect()
+    };
+    let mut imported = Vec::with_capacity(tables.len());
+    for table in tables {
+        let rows = import_table(ldb, &conn, &table)?;
+        log::info!("Imported {} rows from SQLite table {}", rows, table);
+        imported.push((table, rows));
+    }
+    Ok(imported)
+}
+
+fn list_tables(conn: &Connection) -> Result<Vec<String>, QueryError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master \
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .map_err(|e| fatal!("Failed to list tables: {}", e))?;
+    let tables = stmt
+        .query_map([], |row| row.get(0))
+        .and_then(|rows| rows.collect::<Result<Vec<String>, _>>())
+        .map_err(|e| fatal!("Failed to list tables: {}", e))?;
+    Ok(tables)
+}
+
+fn import_table(ldb: &InnerLocustDB, conn: &Connection, table: &str) -> Result<usize, QueryError> {
+    let error = |e: rusqlite::Error| fatal!("Failed to read SQLite table {}: {}", table, e);
+    let mut stmt = conn
+        .prepare(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))
+        .map_err(error)?;
+    let names = stmt
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let mut batch = vec![Vec::new(); names.len()];
+    let mut rows = stmt.query([]).map_err(error)?;
+    let mut count = 0;
+    while let Some(row) = rows.next().map_err(error)? {
+        for (i, column) in batch.iter_mut().enumerate() {
+            let value = match row.get_ref(i).map_err(error)? {
+                ValueRef::Null => RawVal::Null,
+                ValueRef::Integer(int) => RawVal::Int(int),
+                ValueRef::Real(f) => RawVal::Float(OrderedFloat(f)),
+                ValueRef::Text(text) => RawVal::Str(String::from_utf8_lossy(text).into_owned()),
+                ValueRef::Blob(blob) => RawVal::Str(hex::encode(blob)),
+            };
+            column.push(value);
+        }
+        count += 1;
+        if count % BATCH_SIZE == 0 {
+            ingest_batch(ldb, table, &names, &mut batch)?;
+        }
+    }
+    if count % BATCH_SIZE != 0 {
+        ingest_batch(ldb, table, &names, &mut batch)?;
+    }
+    Ok(count)
+}
+
+fn ingest_batch(
+    ldb: &InnerLocustDB,
+    table: &str,
+    names: &[String],
+    batch: &mut [Vec<RawVal>],
+) -> Result<(), QueryError> {
+    let columns = names
+        .iter()
+        .cloned()
+        .zip(batch.iter_mut().map(std::mem::take))
+        .collect::<HashMap<_, _>>();
+    ldb.ingest_column_batches(table, iter::once(columns))
+}
@@ -16,19 +16,43 @@ extern crate failure_derive;
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
+pub use crate::disk_store::compression::PartitionCompression;
+pub use crate::disk_store::migration::{MigrationReport, FORMAT_VERSION};
 pub use crate::disk_store::noop_storage::NoopStorage;
+pub use crate::disk_store::object_store::ObjectStoreOptions;
+pub use crate::disk_store::storage::{
+    CorruptedSubpartition, GarbageCollectionReport, RecoveryTarget, ScrubReport, WalSync,
+};
 
-pub use crate::engine::query_task::{QueryOutput, BasicTypeColumn};
+pub use crate::engine::query_task::{QueryOutput, BasicTypeColumn, QueryStats};
+pub use crate::engine::AsofJoin;
+pub use crate::engine::ColumnStatistics;
+pub use crate::engine::QueryCursor;
 pub use crate::errors::QueryError;
 pub use crate::ingest::colgen;
 pub use crate::ingest::csv_loader::Options as LoadOptions;
 pub use crate::ingest::extractor;
+#[cfg(feature = "kafka")]
+pub use crate::ingest::kafka::{KafkaConsumer, KafkaFormat, KafkaOptions};
 pub use crate::ingest::nyc_taxi_data;
+#[cfg(feature = "object_store_import")]
+pub use crate::ingest::object_store::{BulkImportOptions, BulkImportSummary, ObjectFormat};
+#[cfg(feature = "postgres_cdc")]
+pub use crate::ingest::postgres_cdc::{PostgresCdcConsumer, PostgresCdcOptions};
 pub use crate::ingest::raw_val::syntax as value_syntax;
 pub use crate::ingest::raw_val::RawVal as Value;
 pub use crate::locustdb::LocustDB;
 pub use crate::locustdb::Options;
+pub use crate::locustdb::WalBackpressure;
+pub use crate::mem_store::compaction::CompactionPolicy;
+pub use crate::mem_store::dedup::Deduplication;
+pub use crate::mem_store::lru::EvictionPolicy;
+pub use crate::mem_store::schema::{ColumnSchema, ColumnType, SchemaEnforcement, TableSchema};
 pub use crate::mem_store::table::TableStats;
+pub use crate::scheduler::affinity::ThreadAffinity;
+pub use crate::scheduler::disk_read_scheduler::QueryIo;
+pub use crate::scheduler::{CompactionReport, FlushReport, Health};
+pub use crate::scheduler::ScheduledQuery;
 
 #[macro_use]
 mod errors;
@@ -46,7 +70,12 @@ mod stringpack;
 mod syntax;
 pub mod unit_fmt;
 
+#[cfg(feature = "otel")]
+pub mod otel;
 #[cfg(feature = "python")]
 pub mod python;
 
+#[cfg(feature = "arrow_ingest")]
+pub use arrow_array;
+
 pub type QueryResult = Result<QueryOutput, QueryError>;
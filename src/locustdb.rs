@@ -1,17 +1,32 @@
+use std::collections::HashMap;
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use futures::channel::oneshot;
+use futures::channel::{mpsc, oneshot};
 
-use crate::engine::query_task::QueryTask;
+use crate::disk_store::compression::PartitionCompression;
+use crate::disk_store::migration::MigrationReport;
+use crate::disk_store::object_store::ObjectStoreOptions;
+use crate::disk_store::storage::{
+    GarbageCollectionReport, RecoveryTarget, ScrubReport, Storage, WalSync,
+};
+use crate::engine::query_task::{QueryOutput, QueryTask};
+use crate::engine::{AsofJoin, ColumnStatistics, Query, QueryCursor, SortedRuns};
 use crate::ingest::colgen::GenTable;
-use crate::ingest::csv_loader::{CSVIngestionTask, Options as LoadOptions};
+use crate::ingest::csv_loader::{self, CSVIngestionTask, Options as LoadOptions};
+use crate::ingest::decompress;
+use crate::ingest::json_loader;
+use crate::ingest::raw_val::RawVal;
 use crate::logging_client::EventBuffer;
 use crate::mem_store::*;
-use crate::perf_counter::PerfCounter;
+use crate::perf_counter::{PerfCounter, QueryProgress};
+use crate::scheduler::affinity::ThreadAffinity;
+use crate::scheduler::disk_read_scheduler::QueryIo;
 use crate::scheduler::*;
+use crate::syntax::command::Command;
 use crate::syntax::parser;
 use crate::QueryError;
 use crate::QueryResult;
@@ -19,6 +34,8 @@ use crate::QueryResult;
 // Cannot implement Clone on LocustDB without changing Drop implementation.
 pub struct LocustDB {
     inner_locustdb: Arc<InnerLocustDB>,
+    #[cfg(feature = "protobuf")]
+    protobuf_registry: crate::ingest::protobuf::ProtobufRegistry,
 }
 
 impl LocustDB {
@@ -32,6 +49,8 @@ impl LocustDB {
         InnerLocustDB::start_worker_threads(&locustdb);
         LocustDB {
             inner_locustdb: locustdb,
+            #[cfg(feature = "protobuf")]
+            protobuf_registry: Default::default(),
         }
     }
 
@@ -42,22 +61,283 @@ impl LocustDB {
         rowformat: bool,
         show: Vec<usize>,
     ) -> Result<QueryResult, oneshot::Canceled> {
+        self.run_query_with_io(query, explain, rowformat, show, QueryIo::default())
+            .await
+    }
+
+    /// Runs a query with disk read settings that differ from the defaults in `Options`, e.g. to run a scan of cold
+    /// data with fewer concurrent disk reads.
+    pub async fn run_query_with_io(
+        &self,
+        query: &str,
+        explain: bool,
+        rowformat: bool,
+        show: Vec<usize>,
+        io: QueryIo,
+    ) -> Result<QueryResult, oneshot::Canceled> {
+        self.run_logged_query(query, explain, rowformat, show, io, None)
+            .await
+    }
+
+    /// Runs a query on behalf of `user`, who is recorded in the `client_user` column of the query log, see
+    /// `Options::query_log`. The user is not authenticated and only identifies the client to readers of the log.
+    pub async fn run_query_as(
+        &self,
+        user: &str,
+        query: &str,
+        rowformat: bool,
+    ) -> Result<QueryResult, oneshot::Canceled> {
+        self.run_logged_query(
+            query,
+            false,
+            rowformat,
+            vec![],
+            QueryIo::default(),
+            Some(user),
+        )
+        .await
+    }
+
+    async fn run_logged_query(
+        &self,
+        query: &str,
+        explain: bool,
+        rowformat: bool,
+        show: Vec<usize>,
+        io: QueryIo,
+        user: Option<&str>,
+    ) -> Result<QueryResult, oneshot::Canceled> {
+        let start_time = Instant::now();
+        let result = self
+            .execute_query(query, explain, rowformat, show, io)
+            .await;
+        self.inner_locustdb
+            .log_query(query, user, start_time.elapsed(), &result);
+        result
+    }
+
+    async fn execute_query(
+        &self,
+        query: &str,
+        explain: bool,
+        rowformat: bool,
+        show: Vec<usize>,
+        io: QueryIo,
+    ) -> Result<QueryResult, oneshot::Canceled> {
+        let query_text = query;
+        if io.max_disk_reads == Some(0) {
+            return Ok(Err(QueryError::ParseError(
+                "max_disk_reads must be greater than 0".to_string(),
+            )));
+        }
         let (sender, receiver) = oneshot::channel();
+        let span = tracing::info_span!("query", query = query_text);
 
         // PERF: perform compilation and table snapshot in asynchronous task?
-        let query = match parser::parse_query(query) {
-            Ok(query) => query,
+        let command = span
+            .in_scope(|| tracing::info_span!("parse").in_scope(|| parser::parse_command(query)));
+        let query = match command {
+            Ok(Command::Query(query)) => query,
+            Ok(Command::CreateTable {
+                name,
+                schema,
+                schema_enforcement,
+                if_not_exists,
+            }) => {
+                return Ok(self
+                    .inner_locustdb
+                    .create_table(&name, schema, schema_enforcement, if_not_exists)
+                    .map(|_| QueryOutput::empty(rowformat)))
+            }
+            Ok(Command::DropTable { name, if_exists }) => {
+                return Ok(self
+                    .inner_locustdb
+                    .drop_table(&name, if_exists)
+                    .map(|_| QueryOutput::empty(rowformat)))
+            }
+            Ok(Command::RenameTable { old, new }) => {
+                return Ok(self
+                    .inner_locustdb
+                    .rename_table(&old, &new)
+                    .map(|_| QueryOutput::empty(rowformat)))
+            }
+            Ok(Command::CreateMaterializedView(view)) => {
+                return Ok(self
+                    .inner_locustdb
+                    .create_materialized_view(view)
+                    .map(|_| QueryOutput::empty(rowformat)))
+            }
+            Ok(Command::DropMaterializedView { name, if_exists }) => {
+                return Ok(self
+                    .inner_locustdb
+                    .drop_materialized_view(&name, if_exists)
+                    .map(|_| QueryOutput::empty(rowformat)))
+            }
+            Ok(Command::Update {
+                table,
+                assignments,
+                filter,
+            }) => {
+                return Ok(self
+                    .inner_locustdb
+                    .update(&table, &assignments, &filter)
+                    .map(|rows| QueryOutput::affected_rows("updated_rows", rows, rowformat)))
+            }
             Err(err) => return Ok(Err(err)),
         };
 
-        let mut data = match self.inner_locustdb.snapshot(&query.table) {
-            Some(data) => data,
+        // Held until the query has completed
+        let _permit = match self.inner_locustdb.admit_query().await {
+            Ok(permit) => permit,
+            Err(err) => return Ok(Err(err)),
+        };
+
+        // The task is created within the span of the query, which becomes the parent of the spans of its execution
+        let task =
+            span.in_scope(|| self.query_task(query_text, query, rowformat, explain, show, sender));
+        match task {
+            Ok((id, task)) => {
+                self.inner_locustdb.schedule_query(
+                    id,
+                    task.with_io(io)
+                        .with_memory_limit(self.inner_locustdb.opts().query_memory_limit),
+                );
+                let result = receiver.await?;
+                Ok(result)
+            }
+            Err(err) => Ok(Err(err)),
+        }
+    }
+
+    /// Runs a query without `ORDER BY` clause and aggregations and streams its rows as each partition is scanned,
+    /// instead of merging all rows in memory before returning them.
+    pub async fn query_cursor(&self, query: &str) -> Result<QueryCursor<'_>, QueryError> {
+        let query_text = query;
+        let span = tracing::info_span!("query", query = query_text);
+        let command = span
+            .in_scope(|| tracing::info_span!("parse").in_scope(|| parser::parse_command(query)));
+        let query = match command? {
+            Command::Query(query) => query,
+            _ => bail!(
+                QueryError::NotImplemented,
+                "Only SELECT queries can be streamed"
+            ),
+        };
+        let limit = query.limit.clone();
+        let permit = self.inner_locustdb.admit_query().await?;
+        let (sender, receiver) = oneshot::channel();
+        let (id, task) =
+            span.in_scope(|| self.query_task(query_text, query, true, false, vec![], sender))?;
+        if !task.supports_cursor() {
+            task.perf_counter().complete();
+            bail!(
+                QueryError::NotImplemented,
+                "Queries with ORDER BY or aggregations cannot be streamed"
+            );
+        }
+        let (rows, cursor) = mpsc::unbounded();
+        let colnames = task.output_colnames().to_vec();
+        self.inner_locustdb
+            .schedule_query(id, task.with_cursor(rows));
+        Ok(QueryCursor::new(
+            self.inner_locustdb.clone(),
+            id,
+            colnames,
+            cursor,
+            receiver,
+            limit.offset as usize,
+            limit.limit as usize,
+            permit,
+        ))
+    }
+
+    /// Runs `query` and writes the result to the CSV file at `path`, returning the number of rows written.
+    /// Results of queries with `ORDER BY` and without aggregations are sorted externally: rows are written to disk in
+    /// sorted runs of `Options::sort_run_rows` rows which are merged while writing the file, so that results larger than
+    /// memory can be exported in sorted order.
+    pub async fn export_csv(&self, query: &str, path: &Path) -> Result<u64, QueryError> {
+        let query_text = query;
+        let span = tracing::info_span!("query", query = query_text);
+        let command = span
+            .in_scope(|| tracing::info_span!("parse").in_scope(|| parser::parse_command(query)));
+        let query = match command? {
+            Command::Query(query) => query,
+            _ => bail!(
+                QueryError::NotImplemented,
+                "Only SELECT queries can be exported"
+            ),
+        };
+        let limit = query.limit.clone();
+        let _permit = self.inner_locustdb.admit_query().await?;
+        let (sender, receiver) = oneshot::channel();
+        let (id, task) =
+            span.in_scope(|| self.query_task(query_text, query, true, false, vec![], sender))?;
+        let task = task.with_memory_limit(self.inner_locustdb.opts().query_memory_limit);
+        let mut writer = csv::Writer::from_path(path)
+            .map_err(|e| fatal!("Failed to create {:?}: {}", path, e))?;
+        let csv_error = |e: csv::Error| fatal!("Failed to write {:?}: {}", path, e);
+        let csv_field = |val: RawVal| match val {
+            RawVal::Str(s) => s,
+            RawVal::Null => String::new(),
+            val => val.to_string(),
+        };
+
+        let mut rows_written = 0;
+        match task.external_sort_order() {
+            Some(desc) => {
+                let sort_run_rows = self.inner_locustdb.opts().sort_run_rows;
+                let runs = Arc::new(SortedRuns::new(desc, sort_run_rows)?);
+                self.inner_locustdb
+                    .schedule_query(id, task.with_sorted_runs(runs.clone()));
+                let output = receiver.await.map_err(|_| fatal!("Query was canceled"))??;
+                writer.write_record(&output.colnames).map_err(csv_error)?;
+                for row in runs
+                    .merge()?
+                    .skip(limit.offset as usize)
+                    .take(limit.limit as usize)
+                {
+                    writer
+                        .write_record(row?.into_iter().map(csv_field))
+                        .map_err(csv_error)?;
+                    rows_written += 1;
+                }
+            }
             None => {
-                return Ok(Err(QueryError::NotImplemented(format!(
-                    "Table {} does not exist!",
-                    &query.table
-                ))))
+                self.inner_locustdb.schedule_query(id, task);
+                let output = receiver.await.map_err(|_| fatal!("Query was canceled"))??;
+                writer.write_record(&output.colnames).map_err(csv_error)?;
+                for row in output.rows.unwrap_or_default() {
+                    writer
+                        .write_record(row.into_iter().map(csv_field))
+                        .map_err(csv_error)?;
+                    rows_written += 1;
+                }
             }
+        }
+        writer
+            .flush()
+            .map_err(|e| fatal!("Failed to write {:?}: {}", path, e))?;
+        Ok(rows_written)
+    }
+
+    /// Plans `query` and tracks its counters in `PerfCounter` under `query_text`. Returns the id of the query and
+    /// the task that executes it.
+    fn query_task(
+        &self,
+        query_text: &str,
+        query: Query,
+        rowformat: bool,
+        explain: bool,
+        show: Vec<usize>,
+        sender: oneshot::Sender<QueryResult>,
+    ) -> Result<(u64, QueryTask), QueryError> {
+        let mut data = match self.inner_locustdb.snapshot(&query.table) {
+            Some(data) => data,
+            None => bail!(
+                QueryError::NotImplemented,
+                "Table {} does not exist!",
+                &query.table
+            ),
         };
 
         if self.inner_locustdb.opts().seq_disk_read {
@@ -74,7 +354,7 @@ impl LocustDB {
             self.inner_locustdb.schedule(read_data);
         }
 
-        let query_task = QueryTask::new(
+        let task = QueryTask::new(
             query,
             rowformat,
             explain,
@@ -83,16 +363,206 @@ impl LocustDB {
             self.inner_locustdb.disk_read_scheduler().clone(),
             SharedSender::new(sender),
             self.inner_locustdb.opts().batch_size,
-        );
+        )?
+        .with_aggregate_cache(self.inner_locustdb.aggregate_cache())
+        .with_morsel_size(self.inner_locustdb.opts().morsel_size)
+        .with_operator_fusion(self.inner_locustdb.opts().operator_fusion);
+        let id = self
+            .perf_counter()
+            .track_query(query_text, task.perf_counter().clone());
+        Ok((id, task))
+    }
 
-        match query_task {
-            Ok(task) => {
-                self.schedule(task);
-                let result = receiver.await?;
-                Ok(result)
-            }
-            Err(err) => Ok(Err(err)),
-        }
+    /// Runs the `left` and `right` queries of the join and matches each left row with the most recent right row.
+    /// There is no SQL syntax for ASOF joins, queries containing a JOIN clause are rejected by the parser.
+    pub async fn asof_join(&self, join: &AsofJoin) -> Result<QueryResult, oneshot::Canceled> {
+        let left = match self.run_query(&join.left, false, true, vec![]).await? {
+            Ok(left) => left,
+            Err(err) => return Ok(Err(err)),
+        };
+        let right = match self.run_query(&join.right, false, true, vec![]).await? {
+            Ok(right) => right,
+            Err(err) => return Ok(Err(err)),
+        };
+        Ok(join.join(left, right))
+    }
+
+    /// Creates an empty table with declared column names and types.
+    pub fn create_table(&self, name: &str, schema: TableSchema) -> Result<(), QueryError> {
+        self.inner_locustdb
+            .create_table(name, schema, SchemaEnforcement::Permissive, false)
+    }
+
+    /// Sets how ingestion into a table created with a declared schema handles rows that do not conform to the schema.
+    pub fn set_schema_enforcement(
+        &self,
+        table: &str,
+        enforcement: SchemaEnforcement,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb
+            .set_schema_enforcement(table, enforcement)
+    }
+
+    /// Drops rows ingested into `table` whose key was already ingested, or disables deduplication if `deduplication`
+    /// is `None`. Keys are compared against rows in the current WAL window and the lookback of `deduplication`.
+    pub fn set_deduplication(
+        &self,
+        table: &str,
+        deduplication: Option<Deduplication>,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb.set_deduplication(table, deduplication)
+    }
+
+    pub fn deduplication(&self, table: &str) -> Option<Deduplication> {
+        self.inner_locustdb.deduplication(table)
+    }
+
+    /// Sets the column by which compaction sorts the rows of `table`, e.g. a timestamp, or disables sorting if
+    /// `sort_key` is `None`. Sorted partitions compress better and have narrow value ranges for the sort key, which
+    /// lets queries that filter on a range of the sort key skip most partitions.
+    pub fn set_sort_key(&self, table: &str, sort_key: Option<&str>) -> Result<(), QueryError> {
+        self.inner_locustdb
+            .set_sort_key(table, sort_key.map(str::to_string))
+    }
+
+    pub fn sort_key(&self, table: &str) -> Option<String> {
+        self.inner_locustdb.sort_key(table)
+    }
+
+    /// Sets the policy that decides which partitions of `table` are combined by compaction, or restores the default
+    /// size-tiered policy with `partition_combine_factor` if `policy` is `None`.
+    pub fn set_compaction_policy(
+        &self,
+        table: &str,
+        policy: Option<CompactionPolicy>,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb.set_compaction_policy(table, policy)
+    }
+
+    pub fn compaction_policy(&self, table: &str) -> Option<CompactionPolicy> {
+        self.inner_locustdb.compaction_policy(table)
+    }
+
+    /// Limits the bytes of columns of `table` that are resident in memory, or removes the limit if `limit` is `None`.
+    /// Columns of tables over their limit are evicted before any other columns, so a single large table cannot evict
+    /// all other tables from memory. Enforced in the background, like `mem_size_limit_tables`.
+    pub fn set_memory_limit(&self, table: &str, limit: Option<usize>) -> Result<(), QueryError> {
+        self.inner_locustdb.set_memory_limit(table, limit)
+    }
+
+    pub fn memory_limit(&self, table: &str) -> Option<usize> {
+        self.inner_locustdb.memory_limit(table)
+    }
+
+    /// Enables or disables encoding of string column `column` of `table` with a dictionary shared by all partitions.
+    /// Group by queries on such columns merge results from different partitions by comparing dictionary codes rather
+    /// than strings. Only suitable for low cardinality columns, and only affects partitions created afterwards.
+    pub fn set_shared_dictionary(
+        &self,
+        table: &str,
+        column: &str,
+        enabled: bool,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb
+            .set_shared_dictionary(table, column, enabled)
+    }
+
+    /// Number of rows, null values and distinct values and the value range of each column of `table`, as used by the
+    /// query planner. Statistics are recorded when partitions are created and persisted with their metadata.
+    pub fn column_statistics(&self, table: &str) -> HashMap<String, ColumnStatistics> {
+        self.inner_locustdb.column_statistics(table)
+    }
+
+    pub fn shared_dictionary_columns(&self, table: &str) -> Vec<String> {
+        self.inner_locustdb.shared_dictionary_columns(table)
+    }
+
+    /// Enables or disables per-partition bloom filters for string column `column` of `table`. Queries with an
+    /// equality predicate on the column skip partitions that cannot contain the value without reading them from
+    /// disk. Intended for high cardinality columns such as ids, and only affects partitions created afterwards.
+    pub fn set_bloom_filter(
+        &self,
+        table: &str,
+        column: &str,
+        enabled: bool,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb.set_bloom_filter(table, column, enabled)
+    }
+
+    pub fn bloom_filter_columns(&self, table: &str) -> Vec<String> {
+        self.inner_locustdb.bloom_filter_columns(table)
+    }
+
+    /// Removes the table and deletes all of its data.
+    pub fn drop_table(&self, name: &str) -> Result<(), QueryError> {
+        self.inner_locustdb.drop_table(name, false)
+    }
+
+    /// Renames a table without rewriting any of its data.
+    pub fn rename_table(&self, old: &str, new: &str) -> Result<(), QueryError> {
+        self.inner_locustdb.rename_table(old, new)
+    }
+
+    /// Creates a table `name` containing the results of the aggregation `query`, which is updated incrementally as new
+    /// rows are ingested into the table that `query` selects from.
+    pub fn create_materialized_view(&self, name: &str, query: &str) -> Result<(), QueryError> {
+        self.inner_locustdb
+            .create_materialized_view(MaterializedView::new(name, query)?)
+    }
+
+    /// Runs `query` every `interval` seconds over the rows with `timestamp_column` in the time window since the
+    /// previous run and appends the results to table `target`.
+    pub fn create_scheduled_query(
+        &self,
+        name: &str,
+        query: &str,
+        target: &str,
+        timestamp_column: &str,
+        interval: u64,
+    ) -> Result<(), QueryError> {
+        let scheduled_query = ScheduledQuery::new(name, query, target, timestamp_column, interval)?;
+        self.inner_locustdb.create_scheduled_query(scheduled_query)
+    }
+
+    pub fn drop_scheduled_query(&self, name: &str) -> Result<(), QueryError> {
+        self.inner_locustdb.drop_scheduled_query(name)
+    }
+
+    pub fn scheduled_queries(&self) -> Vec<ScheduledQuery> {
+        self.inner_locustdb.scheduled_queries()
+    }
+
+    /// Runs `query` and writes the result to the Parquet file at `path`.
+    #[cfg(feature = "parquet_export")]
+    pub async fn export_parquet(
+        &self,
+        query: &str,
+        path: &std::path::Path,
+    ) -> Result<(), QueryError> {
+        let output = self
+            .run_query(query, false, false, vec![])
+            .await
+            .map_err(|_| fatal!("Query was canceled"))??;
+        crate::disk_store::parquet_export::write_parquet(&output, path)
+    }
+
+    /// Writes all columns of `table` to the Parquet file at `path`.
+    #[cfg(feature = "parquet_export")]
+    pub async fn export_table_parquet(
+        &self,
+        table: &str,
+        path: &std::path::Path,
+    ) -> Result<(), QueryError> {
+        self.export_parquet(&format!("SELECT * FROM \"{}\"", table), path)
+            .await
+    }
+
+    pub fn table_schema(&self, name: &str) -> Option<TableSchema> {
+        self.inner_locustdb.table_schema(name)
+    }
+
+    pub fn schema_enforcement(&self, name: &str) -> Option<SchemaEnforcement> {
+        self.inner_locustdb.schema_enforcement(name)
     }
 
     pub async fn load_csv(&self, options: LoadOptions) -> Result<(), Box<dyn Error>> {
@@ -106,8 +576,175 @@ impl LocustDB {
         Ok(receiver.await??)
     }
 
-    pub async fn ingest_efficient(&self, events: EventBuffer) {
-        self.inner_locustdb.ingest_efficient(events);
+    pub async fn ingest_efficient(&self, events: EventBuffer) -> Result<(), QueryError> {
+        self.inner_locustdb.ingest_efficient(events)
+    }
+
+    /// Ingests CSV data read from `reader` into the table given in `options` and returns the number of rows.
+    /// The filename of `options` is ignored. Rows are written to partitions directly instead of the WAL, but are
+    /// subject to the same `wal_backpressure` while the WAL exceeds `max_wal_size_bytes`.
+    pub fn ingest_csv<R: std::io::Read>(
+        &self,
+        options: &LoadOptions,
+        reader: R,
+    ) -> Result<usize, QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        self.inner_locustdb.ensure_wal_capacity()?;
+        csv_loader::ingest_reader(&self.inner_locustdb, reader, options)
+            .map_err(QueryError::ParseError)
+    }
+
+    /// Ingests a file of newline-delimited JSON objects into `table` and returns the number of rows.
+    /// Compressed files are decompressed transparently.
+    pub fn load_ndjson(&self, table: &str, path: &std::path::Path) -> Result<usize, QueryError> {
+        let reader = decompress::open(path)
+            .map_err(|e| fatal!("Failed to open {}: {}", path.display(), e))?;
+        self.ingest_ndjson(table, reader)
+    }
+
+    /// Ingests newline-delimited JSON objects into `table` and returns the number of rows.
+    /// Rows are ingested in batches while reading, so an invalid line does not undo the ingestion of preceding
+    /// batches.
+    pub fn ingest_ndjson<R: std::io::BufRead>(
+        &self,
+        table: &str,
+        reader: R,
+    ) -> Result<usize, QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        json_loader::load_ndjson(&self.inner_locustdb, table, reader)
+    }
+
+    /// Like `ingest_ndjson`, but validates all lines before ingesting any rows, so that either all or none of the
+    /// rows are ingested. All rows are held in memory at once.
+    pub fn ingest_ndjson_atomic<R: std::io::BufRead>(
+        &self,
+        table: &str,
+        reader: R,
+    ) -> Result<usize, QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        json_loader::load_ndjson_atomic(&self.inner_locustdb, table, reader)
+    }
+
+    /// Ingests historical rows into `table` without mixing them with recent data. Rows are grouped into one partition
+    /// per `bucket_width` wide interval of the integer timestamps in `time_column` and sorted by timestamp.
+    /// Returns the number of rows.
+    pub fn ingest_backfill(
+        &self,
+        table: &str,
+        time_column: &str,
+        bucket_width: i64,
+        columns: HashMap<String, Vec<RawVal>>,
+    ) -> Result<usize, QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        self.inner_locustdb
+            .ingest_backfill(table, time_column, bucket_width, columns)
+    }
+
+    /// Ingests all rows of the Parquet file at `path` into `table` and returns the number of rows.
+    #[cfg(feature = "parquet_import")]
+    pub fn load_parquet(&self, table: &str, path: &std::path::Path) -> Result<usize, QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        let file = std::fs::File::open(path)
+            .map_err(|e| fatal!("Failed to open {}: {}", path.display(), e))?;
+        crate::ingest::parquet_loader::ingest_file(&self.inner_locustdb, table, file)
+    }
+
+    /// Loads tables from the SQLite database at `path` into LocustDB tables of the same name, or all tables if
+    /// `tables` is empty. Returns the name and number of rows of each imported table.
+    #[cfg(feature = "sqlite_import")]
+    pub fn import_sqlite(
+        &self,
+        path: &std::path::Path,
+        tables: &[&str],
+    ) -> Result<Vec<(String, usize)>, QueryError> {
+        crate::ingest::sqlite_loader::import(&self.inner_locustdb, path, tables)
+    }
+
+    /// Imports all CSV and Parquet objects under an S3 or GCS prefix. Must be called from within a Tokio runtime.
+    #[cfg(feature = "object_store_import")]
+    pub async fn import_prefix(
+        &self,
+        options: crate::ingest::object_store::BulkImportOptions,
+    ) -> Result<crate::ingest::object_store::BulkImportSummary, QueryError> {
+        crate::ingest::object_store::import(self.inner_locustdb.clone(), options).await
+    }
+
+    /// Registers the message types of a serialized `google.protobuf.FileDescriptorSet` for use with `ingest_protobuf`.
+    /// Returns the full names of all registered message types.
+    #[cfg(feature = "protobuf")]
+    pub fn register_protobuf_descriptors(
+        &self,
+        file_descriptor_set: &[u8],
+    ) -> Result<Vec<String>, QueryError> {
+        self.protobuf_registry.register(file_descriptor_set)
+    }
+
+    /// Ingests a sequence of length-delimited protobuf messages of the registered type `message` into `table` and
+    /// returns the number of rows. No rows are ingested if any of the messages is invalid.
+    #[cfg(feature = "protobuf")]
+    pub fn ingest_protobuf(
+        &self,
+        table: &str,
+        message: &str,
+        data: &[u8],
+    ) -> Result<usize, QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        self.protobuf_registry
+            .ingest(&self.inner_locustdb, table, message, data)
+    }
+
+    /// Starts consuming a Kafka topic in the background. Consumption stops when the returned consumer is dropped.
+    #[cfg(feature = "kafka")]
+    pub fn consume_kafka(
+        &self,
+        options: crate::ingest::kafka::KafkaOptions,
+    ) -> Result<crate::ingest::kafka::KafkaConsumer, QueryError> {
+        crate::ingest::kafka::KafkaConsumer::start(self.inner_locustdb.clone(), options)
+    }
+
+    /// Starts replicating inserts from a Postgres publication in the background. Replication stops when the returned
+    /// consumer is dropped.
+    #[cfg(feature = "postgres_cdc")]
+    pub fn consume_postgres_cdc(
+        &self,
+        options: crate::ingest::postgres_cdc::PostgresCdcOptions,
+    ) -> Result<crate::ingest::postgres_cdc::PostgresCdcConsumer, QueryError> {
+        crate::ingest::postgres_cdc::PostgresCdcConsumer::start(
+            self.inner_locustdb.clone(),
+            options,
+        )
+    }
+
+    /// Ingests an Arrow record batch into `table`, preserving integer and string column types.
+    #[cfg(feature = "arrow_ingest")]
+    pub fn ingest_arrow(
+        &self,
+        table: &str,
+        batch: arrow_array::RecordBatch,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        let columns = crate::ingest::arrow::record_batch_columns(&batch)?;
+        self.inner_locustdb.ingest_batches(table, vec![columns])
+    }
+
+    /// Ingests all record batches of an Arrow IPC file (Feather v2) into `table`.
+    #[cfg(feature = "arrow_ingest")]
+    pub fn load_arrow_ipc(&self, table: &str, path: &std::path::Path) -> Result<(), QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        let batches = crate::ingest::arrow::read_ipc_file(path)?;
+        self.inner_locustdb.ingest_batches(table, batches)
+    }
+
+    /// Ingests all record batches of an Arrow IPC stream into `table`.
+    #[cfg(feature = "arrow_ingest")]
+    pub fn ingest_arrow_ipc_stream<R: std::io::Read>(
+        &self,
+        table: &str,
+        reader: R,
+    ) -> Result<(), QueryError> {
+        self.inner_locustdb.ensure_accepting_ingestion()?;
+        let batches = crate::ingest::arrow::read_ipc_stream(reader)?;
+        self.inner_locustdb.ingest_batches(table, batches)
     }
 
     pub async fn gen_table(&self, opts: GenTable) -> Result<(), oneshot::Canceled> {
@@ -185,10 +822,81 @@ impl LocustDB {
         self.inner_locustdb.perf_counter()
     }
 
-    pub fn force_flush(&self) {
-        self.inner_locustdb.wal_flush();
+    /// Partitions completed and bytes scanned so far by the query with the id listed in
+    /// `PerfCounter::query_snapshots`.
+    pub fn query_progress(&self, id: u64) -> Option<QueryProgress> {
+        self.perf_counter().query_progress(id)
+    }
+
+    /// Checks that worker threads are alive, the WAL is writable, and that ingestion is neither blocked by the WAL
+    /// backlog nor rejected due to memory pressure. Writes a small file to the database directory.
+    pub fn health(&self) -> Health {
+        self.inner_locustdb.health()
+    }
+
+    /// Kills the running query with the id listed in `PerfCounter::query_snapshots`, which then fails with
+    /// `QueryError::Killed`. Returns false if there is no such query or it has already completed.
+    pub fn kill_query(&self, id: u64) -> bool {
+        self.inner_locustdb.kill_query(id)
+    }
+
+    /// Reads all partition files and verifies them against the checksums recorded when they were written.
+    /// If `quarantine` is set, partitions with corrupted files are dropped and their files are moved to the
+    /// `quarantine` directory of the database.
+    pub fn scrub(&self, quarantine: bool) -> ScrubReport {
+        self.inner_locustdb.scrub(quarantine)
+    }
+
+    /// Deletes partition files and WAL segments that are not referenced by the database, such as files left behind by
+    /// a crash during a flush, and reports the number of bytes reclaimed. If `dry_run` is set, the files are only
+    /// reported. Fails while a backup is in progress.
+    pub fn collect_garbage(&self, dry_run: bool) -> Result<GarbageCollectionReport, QueryError> {
+        self.inner_locustdb.collect_garbage(dry_run)
+    }
+
+    /// Upgrades a database written by an older version of LocustDB to the current on-disk format in place.
+    pub fn migrate(&self) -> Result<MigrationReport, QueryError> {
+        self.inner_locustdb.migrate()
     }
 
+    /// Writes a consistent snapshot of the database to the directory `path` while ingestion continues. The backup
+    /// contains all rows ingested before the call and can be copied to another machine and opened with `db_path`.
+    pub fn create_backup(&self, path: &std::path::Path) -> Result<(), QueryError> {
+        self.inner_locustdb.create_backup(path)
+    }
+
+    /// Restores a backup created with `create_backup` to the empty directory `dest_db_path` and opens the restored
+    /// database. Fails without opening the database if any file of the backup is missing or corrupted.
+    pub fn restore_backup(
+        src: &std::path::Path,
+        dest_db_path: &std::path::Path,
+    ) -> Result<LocustDB, QueryError> {
+        Storage::restore_backup(src, dest_db_path)?;
+        Ok(LocustDB::new(&Options {
+            db_path: Some(dest_db_path.to_path_buf()),
+            ..Options::default()
+        }))
+    }
+
+    /// Rejects new ingestion, waits for queued tasks to complete and flushes all buffered rows to partitions, so that
+    /// no WAL has to be replayed when the database is opened again. Queries are no longer executed afterwards.
+    pub fn shutdown(&self) {
+        self.inner_locustdb.shutdown();
+    }
+
+    /// Writes all buffered rows to partitions and deletes the WAL segments they were read from. Blocks until
+    /// concurrent flushes and compactions have completed, so all rows ingested before the call are persisted
+    /// when it returns. If the partitions cannot be written the WAL is kept, and they are written by the next flush.
+    pub fn force_flush(&self) -> Result<FlushReport, QueryError> {
+        self.inner_locustdb.flush_buffers()
+    }
+
+    /// Compacts the partitions of `table` according to its compaction policy, see `set_compaction_policy`.
+    pub fn compact_table(&self, table: &str) -> Result<CompactionReport, QueryError> {
+        self.inner_locustdb.compact_table(table)
+    }
+
+    /// Evicts all columns tracked by the LRU cache from memory and returns the number of bytes evicted.
     pub fn evict_cache(&self) -> usize {
         self.inner_locustdb.evict_cache()
     }
@@ -197,61 +905,227 @@ impl LocustDB {
 #[derive(Clone)]
 pub struct Options {
     pub threads: usize,
+    /// Maximum number of worker threads that execute queries. If larger than `threads`, additional workers are started
+    /// while queries wait for a worker and the CPU is not saturated, and exit after being idle for
+    /// `worker_idle_timeout`.
+    pub max_threads: Option<usize>,
+    pub worker_idle_timeout: Duration,
+    /// Pins worker threads and the WAL flush thread to cores or NUMA nodes, see `ThreadAffinity`
+    pub thread_affinity: ThreadAffinity,
     pub read_threads: usize,
+    /// Number of worker threads that execute the internal queries of compactions and materialized views, separately
+    /// from the `threads` that execute user queries
+    pub background_threads: usize,
+    /// Maximum number of queries that execute concurrently. Further queries wait in a queue.
+    pub max_concurrent_queries: Option<usize>,
+    /// Maximum time a query waits in the queue before it is rejected, see `max_concurrent_queries`
+    pub query_queue_timeout: Duration,
     pub db_path: Option<PathBuf>,
+    /// Additional directories, e.g. on separate disks, that partition files are striped across
+    pub data_paths: Vec<PathBuf>,
+    /// Stores the database in an object store instead of `db_path`
+    pub object_store: Option<ObjectStoreOptions>,
     pub mem_size_limit_tables: usize,
+    /// Maximum resident set size of the process in bytes. When exceeded, columns are evicted and ingestion fails with
+    /// `QueryError::Overloaded` until the process is below the limit again. Only supported on Linux.
+    pub max_rss_bytes: Option<usize>,
+    /// Determines which columns are evicted from memory when tables exceed `mem_size_limit_tables`
+    pub eviction_policy: EvictionPolicy,
     pub mem_lz4: bool,
+    /// Compression of partitions written to disk
+    pub partition_compression: PartitionCompression,
+    /// Memory map partition files when loading columns instead of copying them into the heap
+    pub mmap_columns: bool,
     pub readahead: usize,
     pub seq_disk_read: bool,
+    /// Number of partitions ahead of the partition a query is scanning whose columns are read from disk in the
+    /// background, which hides disk latency on cold scans. 0 disables prefetching.
+    pub prefetch_partitions: usize,
+    /// Maximum number of disk reads a single query issues concurrently, so that one cold scan does not saturate the
+    /// disk for all other queries. Only limited by `read_threads` if `None`.
+    pub max_query_disk_reads: Option<usize>,
+    /// Maximum number of bytes a single query allocates for grouping state. Aggregation queries that group by more
+    /// distinct values write their groups to temporary files and merge them once all partitions have been scanned.
+    /// Queries that evaluate expressions or sort after aggregating fail with `QueryError::MemoryLimitExceeded` instead
+    /// of exhausting memory. Unlimited if `None`.
+    pub query_memory_limit: Option<usize>,
+    /// Maximum number of per-partition results of aggregation queries that are cached and reused by later queries
+    /// with the same aggregations, filter and grouping. Cached results are dropped when a column they reference is
+    /// evicted. 0 disables caching.
+    pub aggregate_cache_entries: usize,
+    /// Maximum number of rows that `LocustDB::export_csv` sorts in memory before writing them to disk as a sorted run
+    pub sort_run_rows: usize,
     /// Maximum size of WAL in bytes before triggering compaction
     pub max_wal_size_bytes: u64,
+    /// How ingestion behaves while the WAL exceeds `max_wal_size_bytes`, see `WalBackpressure`
+    pub wal_backpressure: WalBackpressure,
+    /// Maximum time that ingested rows remain in the WAL before they are flushed to partitions, even if the WAL is
+    /// smaller than `max_wal_size_bytes`
+    pub wal_flush_interval: Option<Duration>,
     /// Maximum size of partition
     pub max_partition_size_bytes: u64,
     /// Combine partitions when the size of every original partition is less than this factor of the combined partition size
     pub partition_combine_factor: u64,
     /// Maximum length of temporary buffer used in streaming stages during query execution
     pub batch_size: usize,
+    /// Partitions with more rows are split into morsels of this many rows that are scanned concurrently, so that
+    /// queries over a few large partitions use all threads. Must be a multiple of 8. 0 disables splitting.
+    pub morsel_size: usize,
+    /// Combines chains of streaming operators into a single operator when executing queries, which reduces dispatch
+    /// overhead. Disabling it is only useful to compare results and performance with unfused execution.
+    pub operator_fusion: bool,
     /// Maximum number of rows in a partitions. Not implemented.
     pub max_partition_length: usize,
+    /// Interval at which all partition files are scrubbed in the background. Corrupted partitions are quarantined.
+    pub scrub_interval: Option<Duration>,
+    /// When WAL segments are forced to stable storage, see `WalSync`
+    pub wal_sync: WalSync,
+    /// Moves WAL segments to the `wal_archive` directory after they are flushed instead of deleting them
+    pub archive_wal: bool,
+    /// Replays archived WAL segments up to the target time on startup, used to restore a backup to a point in time
+    pub recovery_target: Option<RecoveryTarget>,
+    /// Records the text, client-supplied user, duration, number of rows returned and error of queries run with
+    /// `LocustDB::run_query` in the `_query_log` table
+    pub query_log: bool,
+    /// Fraction of queries that are recorded in the query log, between 0 and 1
+    pub query_log_sample_rate: f64,
 }
 
 impl Default for Options {
     fn default() -> Options {
         Options {
             threads: num_cpus::get(),
+            max_threads: None,
+            worker_idle_timeout: Duration::from_secs(10),
+            thread_affinity: ThreadAffinity::None,
             read_threads: num_cpus::get(),
+            background_threads: num_cpus::get(),
+            max_concurrent_queries: None,
+            query_queue_timeout: Duration::from_secs(30),
             db_path: None,
+            data_paths: Vec::new(),
+            object_store: None,
             mem_size_limit_tables: 8 * 1024 * 1024 * 1024, // 8 GiB
+            max_rss_bytes: None,
+            eviction_policy: EvictionPolicy::Lru,
             mem_lz4: true,
+            partition_compression: PartitionCompression::None,
+            mmap_columns: false,
             readahead: 256 * 1024 * 1024, // 256 MiB
             seq_disk_read: false,
+            prefetch_partitions: 4,
+            max_query_disk_reads: None,
+            query_memory_limit: None,
+            aggregate_cache_entries: 0,
+            sort_run_rows: 1 << 20,
             max_wal_size_bytes: 64 * 1024 * 1024, // 64 MiB
+            wal_backpressure: WalBackpressure::Block,
+            wal_flush_interval: None,
             max_partition_size_bytes: 8 * 1024 * 1024, // 8 MiB
             partition_combine_factor: 4,
             batch_size: 1024,
+            morsel_size: 1 << 16,
+            operator_fusion: true,
             max_partition_length: 1024 * 1024,
+            scrub_interval: None,
+            wal_sync: WalSync::Always,
+            archive_wal: false,
+            recovery_target: None,
+            query_log: false,
+            query_log_sample_rate: 1.0,
         }
     }
 }
 
 impl Options {
-    fn validate(&self) -> Result<(), String> {
+    /// Checks that the options are consistent, `LocustDB::new` panics if they are not.
+    pub fn validate(&self) -> Result<(), String> {
         if self.threads == 0 {
             return Err("threads must be greater than 0".to_string());
         }
+        if self
+            .max_threads
+            .map_or(false, |max_threads| max_threads < self.threads)
+        {
+            return Err("max_threads must not be less than threads".to_string());
+        }
         if self.read_threads == 0 {
             return Err("read_threads must be greater than 0".to_string());
         }
+        if self.background_threads == 0 {
+            return Err("background_threads must be greater than 0".to_string());
+        }
+        if self.max_query_disk_reads == Some(0) {
+            return Err("max_query_disk_reads must be greater than 0".to_string());
+        }
+        if self.query_memory_limit == Some(0) {
+            return Err("query_memory_limit must be greater than 0".to_string());
+        }
+        if self.max_rss_bytes == Some(0) {
+            return Err("max_rss_bytes must be greater than 0".to_string());
+        }
+        if self.sort_run_rows == 0 {
+            return Err("sort_run_rows must be greater than 0".to_string());
+        }
+        if self.max_concurrent_queries == Some(0) {
+            return Err("max_concurrent_queries must be greater than 0".to_string());
+        }
         if self.partition_combine_factor == 0 {
             return Err("partition_combine_factor must be greater than 0".to_string());
         }
         if self.batch_size % 8 != 0 {
             return Err("batch_size must be a multiple of 8".to_string());
         }
+        if self.morsel_size % 8 != 0 {
+            return Err("morsel_size must be a multiple of 8".to_string());
+        }
+        if !self.data_paths.is_empty() && (self.db_path.is_none() || self.object_store.is_some()) {
+            return Err("data_paths requires db_path without object_store".to_string());
+        }
+        if self.mmap_columns && (self.db_path.is_none() || self.object_store.is_some()) {
+            return Err("mmap_columns requires db_path without object_store".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.query_log_sample_rate) {
+            return Err("query_log_sample_rate must be between 0 and 1".to_string());
+        }
+        self.partition_compression.validate()?;
+        self.wal_sync.validate()?;
+        self.eviction_policy.validate()?;
         Ok(())
     }
 }
 
+/// How ingestion behaves while the WAL exceeds `Options::max_wal_size_bytes` until it has been flushed to partitions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WalBackpressure {
+    /// Block ingestion until the WAL has been flushed.
+    #[default]
+    Block,
+    /// Fail ingestion with `QueryError::Throttled`, which asks clients to retry after the given duration. The server
+    /// responds with `429 Too Many Requests` and a `Retry-After` header.
+    Reject(Duration),
+}
+
+impl str::FromStr for WalBackpressure {
+    type Err = String;
+
+    /// Parses `block` or the number of seconds after which rejected clients should retry.
+    fn from_str(s: &str) -> Result<WalBackpressure, String> {
+        match s {
+            "block" => Ok(WalBackpressure::Block),
+            _ => s
+                .parse()
+                .map(|secs| WalBackpressure::Reject(Duration::from_secs(secs)))
+                .map_err(|_| {
+                    format!(
+                        "Invalid WAL backpressure policy {}, expected block or a number of seconds",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
 impl Drop for LocustDB {
     fn drop(&mut self) {
         self.inner_locustdb.stop();
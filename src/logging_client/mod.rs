@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::mem;
 use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, Condvar, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -8,6 +9,8 @@ use tokio::select;
 use tokio::time::{self, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
 
+use crate::ingest::raw_val::RawVal;
+
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct EventBuffer {
     pub tables: HashMap<String, TableBuffer>,
@@ -28,6 +31,8 @@ pub struct ColumnBuffer {
 pub enum ColumnData {
     Dense(Vec<f64>),
     Sparse(Vec<(u64, f64)>),
+    /// Row indices and values of columns that contain values other than floats, null values are omitted
+    Mixed(Vec<(u64, RawVal)>),
 }
 
 pub struct LoggingClient {
@@ -81,7 +86,13 @@ impl LoggingClient {
         }
     }
 
-    pub fn log<Row: IntoIterator<Item = (String, f64)>>(&mut self, table: &str, row: Row) {
+    /// Buffers a row of `table` that is sent to the server by the next flush. Values may be floats, ints, strings or
+    /// `RawVal::Null`, which is omitted.
+    pub fn log<V: Into<RawVal>, Row: IntoIterator<Item = (String, V)>>(
+        &mut self,
+        table: &str,
+        row: Row,
+    ) {
         let time_millis = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -102,12 +113,18 @@ impl LoggingClient {
         let mut events = self.events.lock().unwrap();
         let table = events.tables.entry(table.to_string()).or_default();
         for (column_name, value) in row {
-            self.buffer_size.fetch_add(8, std::sync::atomic::Ordering::SeqCst);
+            let value = value.into();
+            let size = match &value {
+                RawVal::Str(str) => 8 + str.len(),
+                _ => 8,
+            };
+            self.buffer_size
+                .fetch_add(size as u64, std::sync::atomic::Ordering::SeqCst);
             table
                 .columns
                 .entry(column_name.to_string())
                 .or_default()
-                .push(value, table.len);
+                .push_val(value, table.len);
         }
         table
             .columns
@@ -186,7 +203,21 @@ impl BackgroundWorker {
                     tokio::time::sleep(backoff).await;
                 }
                 Ok(response) => {
-                    if let Err(err) = response.error_for_status_ref() {
+                    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                        // The server is flushing its WAL, the batch is resent once the server asks to retry
+                        let backoff = response
+                            .headers()
+                            .get(reqwest::header::RETRY_AFTER)
+                            .and_then(|retry_after| retry_after.to_str().ok())
+                            .and_then(|retry_after| retry_after.parse().ok())
+                            .map_or(time::Duration::from_secs(1), time::Duration::from_secs);
+                        log::warn!(
+                            "Ingestion throttled, retrying data batch ({} B) in {:?}",
+                            bytes,
+                            backoff
+                        );
+                        tokio::time::sleep(backoff).await;
+                    } else if let Err(err) = response.error_for_status_ref() {
                         log::warn!("Failed to send data batch ({} B): {}", bytes, err);
                         let backoff = time::Duration::from_secs(1);
                         tokio::time::sleep(backoff).await;
@@ -201,8 +232,88 @@ impl BackgroundWorker {
     }
 }
 
+impl TableBuffer {
+    pub(crate) fn push_row<Row: IntoIterator<Item = (String, f64)>>(&mut self, row: Row) {
+        for (column_name, value) in row {
+            self.columns
+                .entry(column_name)
+                .or_default()
+                .push(value, self.len);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn push_val_row<Row: IntoIterator<Item = (String, RawVal)>>(&mut self, row: Row) {
+        for (column_name, value) in row {
+            self.columns
+                .entry(column_name)
+                .or_default()
+                .push_val(value, self.len);
+        }
+        self.len += 1;
+    }
+
+    /// Creates a buffer from columns of equal length, omitting null values.
+    pub(crate) fn from_columns(columns: HashMap<String, Vec<RawVal>>) -> TableBuffer {
+        let mut buffer = TableBuffer {
+            len: columns
+                .values()
+                .map(|values| values.len() as u64)
+                .max()
+                .unwrap_or(0),
+            columns: HashMap::default(),
+        };
+        for (column_name, values) in columns {
+            let mut column = ColumnBuffer::default();
+            for (i, value) in values.into_iter().enumerate() {
+                column.push_val(value, i as u64);
+            }
+            if !column.data.is_empty() {
+                buffer.columns.insert(column_name, column);
+            }
+        }
+        buffer
+    }
+
+    /// Appends all rows of `other`.
+    pub(crate) fn append(&mut self, other: TableBuffer) {
+        for (column_name, column) in other.columns {
+            let buffer = self.columns.entry(column_name).or_default();
+            match column.data {
+                ColumnData::Dense(data) => {
+                    for (i, value) in data.into_iter().enumerate() {
+                        buffer.push(value, self.len + i as u64);
+                    }
+                }
+                ColumnData::Sparse(data) => {
+                    for (i, value) in data {
+                        buffer.push(value, self.len + i);
+                    }
+                }
+                ColumnData::Mixed(data) => {
+                    for (i, value) in data {
+                        buffer.push_val(value, self.len + i);
+                    }
+                }
+            }
+        }
+        self.len += other.len;
+    }
+
+    /// Returns the values of each row, omitting columns that are null.
+    pub(crate) fn into_rows(self) -> Vec<Vec<(String, RawVal)>> {
+        let mut rows = vec![Vec::new(); self.len as usize];
+        for (column_name, column) in self.columns {
+            for (i, value) in column.data.into_sparse() {
+                rows[i as usize].push((column_name.clone(), value));
+            }
+        }
+        rows
+    }
+}
+
 impl ColumnBuffer {
-    fn push(&mut self, value: f64, len: u64) {
+    pub(crate) fn push(&mut self, value: f64, len: u64) {
         match &mut self.data {
             ColumnData::Dense(data) => {
                 if data.len() as u64 == len {
@@ -218,6 +329,23 @@ impl ColumnBuffer {
                 }
             }
             ColumnData::Sparse(data) => data.push((len, value)),
+            ColumnData::Mixed(data) => data.push((len, RawVal::from(value))),
+        }
+    }
+
+    /// Pushes `value` into row `len`, switching to the `Mixed` representation for values that are not floats.
+    pub(crate) fn push_val(&mut self, value: RawVal, len: u64) {
+        match value {
+            RawVal::Float(float) => self.push(float.0, len),
+            RawVal::Null => {}
+            value => {
+                if !matches!(self.data, ColumnData::Mixed(_)) {
+                    self.data = ColumnData::Mixed(mem::take(&mut self.data).into_sparse());
+                }
+                if let ColumnData::Mixed(data) = &mut self.data {
+                    data.push((len, value));
+                }
+            }
         }
     }
 
@@ -248,6 +376,7 @@ impl ColumnData {
         match self {
             ColumnData::Dense(data) => data.len(),
             ColumnData::Sparse(data) => data.len(),
+            ColumnData::Mixed(data) => data.len(),
         }
     }
 
@@ -256,6 +385,23 @@ impl ColumnData {
         match self {
             ColumnData::Dense(data) => data.is_empty(),
             ColumnData::Sparse(data) => data.is_empty(),
+            ColumnData::Mixed(data) => data.is_empty(),
+        }
+    }
+
+    /// Returns the row index and value of each non-null value.
+    fn into_sparse(self) -> Vec<(u64, RawVal)> {
+        match self {
+            ColumnData::Dense(data) => data
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (i as u64, RawVal::from(value)))
+                .collect(),
+            ColumnData::Sparse(data) => data
+                .into_iter()
+                .map(|(i, value)| (i, RawVal::from(value)))
+                .collect(),
+            ColumnData::Mixed(data) => data,
         }
     }
 }
@@ -265,3 +411,30 @@ impl Default for ColumnData {
         ColumnData::Dense(Vec::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_syntax::*;
+
+    #[test]
+    fn test_push_mixed_values() {
+        let mut table = TableBuffer::default();
+        table.push_row([("a".to_string(), 1.0)]);
+        table.push_val_row([("a".to_string(), Str("x")), ("b".to_string(), Null)]);
+        table.push_val_row([("a".to_string(), Float(2.0)), ("b".to_string(), Int(3))]);
+        match &table.columns["a"].data {
+            ColumnData::Mixed(values) => {
+                assert_eq!(values, &[(0, Float(1.0)), (1, Str("x")), (2, Float(2.0))])
+            }
+            data => panic!("Expected mixed column, got {:?}", data),
+        }
+        assert_eq!(table.columns["b"].data.len(), 1);
+        let mut row = table.into_rows().remove(2);
+        row.sort();
+        assert_eq!(
+            row,
+            vec![("a".to_string(), Float(2.0)), ("b".to_string(), Int(3))]
+        );
+    }
+}
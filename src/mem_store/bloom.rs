@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Bits per distinct value, which gives a false positive rate of about 1% with `HASHES` hash functions.
+const BITS_PER_VALUE: usize = 10;
+const HASHES: u32 = 7;
+
+/// Bloom filter over the values of a string column in one partition. Queries with equality predicates on the column
+/// skip partitions whose filter does not contain the value without loading the column.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    pub fn from_strings<S: AsRef<str>>(values: impl Iterator<Item = S>) -> BloomFilter {
+        let hashes = values
+            .map(|value| hash(value.as_ref()))
+            .collect::<HashSet<_>>();
+        let words = (hashes.len() * BITS_PER_VALUE + 63) / 64;
+        let mut filter = BloomFilter {
+            bits: vec![0; words.max(1)],
+            hashes: HASHES,
+        };
+        for hash in hashes {
+            for bit in filter.bit_indices(hash) {
+                filter.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        filter
+    }
+
+    /// Whether the filter may contain `value`. Never returns false for values the filter was built from.
+    pub fn may_contain(&self, value: &str) -> bool {
+        self.bit_indices(hash(value))
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn bit_indices(&self, hash: u64) -> impl Iterator<Item = usize> {
+        // Derives all hash functions from two halves of a single hash (Kirsch and Mitzenmacher)
+        let len = self.bits.len() as u64 * 64;
+        let h1 = hash & 0xffff_ffff;
+        let h2 = hash >> 32;
+        (0..u64::from(self.hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+fn hash(value: &str) -> u64 {
+    seahash::hash(value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_may_contain() {
+        let values = (0..1000).map(|i| format!("user-{}", i)).collect::<Vec<_>>();
+        let filter = BloomFilter::from_strings(values.iter());
+        assert!(values.iter().all(|value| filter.may_contain(value)));
+        let false_positives = (1000..11000)
+            .filter(|i| filter.may_contain(&format!("user-{}", i)))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+
+        let empty = BloomFilter::from_strings(Vec::<String>::new().into_iter());
+        assert!(!empty.may_contain("user-0"));
+    }
+}
@@ -85,6 +85,37 @@ impl Codec {
         codec
     }
 
+    /// Prepends the expansion of runs of values in section 0, using the run lengths stored in section `section`.
+    pub fn with_run_length(&self, section: usize, decoded_length: usize) -> Codec {
+        let mut ops = vec![
+            CodecOp::PushDataSection(section),
+            CodecOp::RunLength(decoded_length),
+        ];
+        ops.extend_from_slice(&self.ops);
+        let mut section_types = self.section_types.clone();
+        section_types.push(EncodingType::U32);
+        let mut codec = Codec::new(ops, section_types);
+        codec.set_column_name(&self.column_name);
+        codec
+    }
+
+    /// If section 0 consists of runs of values, returns the index of the section holding the run lengths and the
+    /// codec that decodes the values of the runs.
+    pub fn run_length_values(&self) -> Option<(usize, Codec)> {
+        match self.ops[..] {
+            [CodecOp::PushDataSection(section), CodecOp::RunLength(_), ref rest @ ..] => {
+                let mut codec = if rest.is_empty() {
+                    Codec::identity(self.decoded_type)
+                } else {
+                    Codec::new(rest.to_vec(), self.section_types.clone())
+                };
+                codec.set_column_name(&self.column_name);
+                Some((section, codec))
+            }
+            _ => None,
+        }
+    }
+
     pub fn without_lz4(&self) -> Codec {
         let mut ops = Vec::with_capacity(self.ops.len() - 1);
         let mut decoded_type = None;
@@ -139,7 +170,7 @@ impl Codec {
                     None,
                     self.section_types[section_index],
                 ),
-                CodecOp::DictLookup(_t) => {
+                CodecOp::DictLookup(_) | CodecOp::SharedDictLookup(_) => {
                     let dict_data = stack.pop().unwrap();
                     let dict_indices = stack.pop().unwrap();
                     let indices = stack.pop().unwrap();
@@ -149,6 +180,11 @@ impl Codec {
                         dict_data.u8().unwrap(),
                     )
                 }
+                CodecOp::RunLength(decoded_length) => {
+                    let run_lengths = stack.pop().unwrap().u32().unwrap();
+                    let values = stack.pop().unwrap();
+                    planner.run_length_decode(values, run_lengths, decoded_length)
+                }
                 CodecOp::LZ4(t, decoded_length) => {
                     planner.lz4_decode(stack.pop().unwrap().u8().unwrap(), decoded_length, t)
                 }
@@ -158,6 +194,14 @@ impl Codec {
                 CodecOp::UnhexpackStrings(upper, total_bytes) => planner
                     .unhexpack_strings(stack.pop().unwrap().u8().unwrap(), upper, total_bytes)
                     .into(),
+                CodecOp::Fsst(total_bytes) => {
+                    let symbols = stack.pop().unwrap().u8().unwrap();
+                    let codes = stack.pop().unwrap().u8().unwrap();
+                    planner.fsst_decode(codes, symbols, total_bytes).into()
+                }
+                CodecOp::Xor => {
+                    planner.xor_decode(stack.pop().unwrap().u64().unwrap(), EncodingType::F64)
+                }
                 CodecOp::Unknown => panic!("unknown decode plan!"),
             };
             stack.push(plan);
@@ -205,38 +249,111 @@ impl Codec {
     pub fn is_identity(&self) -> bool {
         self.ops.is_empty()
     }
+    /// Whether the column consists of non-null codes into a shared dictionary. Results grouped by such columns
+    /// contain codes that are only decoded after results from all partitions have been merged.
+    pub fn is_shared_dictionary(&self) -> bool {
+        matches!(
+            self.ops[..],
+            [
+                ..,
+                CodecOp::PushDataSection(1),
+                CodecOp::PushDataSection(2),
+                CodecOp::SharedDictLookup(_)
+            ]
+        ) && !self.ops.contains(&CodecOp::Nullable)
+    }
+    /// Sections that hold one entry (or bit) per row, if any range of rows can be decoded from the corresponding range
+    /// of these sections and the entire other sections. Returns `None` if decoding depends on the preceding rows.
+    pub fn row_sections(&self) -> Option<Vec<usize>> {
+        match self.ops[..] {
+            _ if self.is_fixed_width => Some(vec![0]),
+            [CodecOp::PushDataSection(present), CodecOp::Nullable, ref rest @ ..]
+                if Codec::has_property(rest, CodecOp::is_elementwise_decodable) =>
+            {
+                Some(vec![0, present])
+            }
+            _ => None,
+        }
+    }
+        pub fn column_name(&self) -> &str {
+        &self.column_name
+    }
 
+    /// Determines the dictionary index of `string_const`, or -1 if it is not contained in the dictionary. Returns
+    /// `None` if the column does not consist of non-null dictionary indices.
     pub fn encode_str(
         &self,
         string_const: BufferRef<Scalar<&'static str>>,
         planner: &mut QueryPlanner,
-    ) -> BufferRef<Scalar<i64>> {
+    ) -> Option<BufferRef<Scalar<i64>>> {
         match self.ops[..] {
-            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)] =>
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)]
+            | [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::SharedDictLookup(_)] =>
             {
-                let offset_len = planner
-                    .column_section(&self.column_name, 1, None, EncodingType::U64)
-                    .u64()
-                    .unwrap();
-                let backing_store = planner
-                    .column_section(&self.column_name, 2, None, EncodingType::U8)
-                    .u8()
-                    .unwrap();
-                planner.inverse_dict_lookup(offset_len, backing_store, string_const)
+                let (offset_len, backing_store) = self.dictionary_sections(planner);
+                Some(planner.inverse_dict_lookup(offset_len, backing_store, string_const))
             }
-            _ => panic!("encode_str not supported for {:?}", &self.ops),
+            _ => None,
         }
     }
 
-    pub fn encode_int(&self, x: i64) -> i64 {
-        if let CodecOp::Add(_, y) = self.ops[0] {
-            assert_eq!(self.ops.len(), 1);
-            x - y
-        } else if let CodecOp::ToI64(_) = self.ops[0] {
-            assert_eq!(self.ops.len(), 1);
-            x
-        } else {
-            panic!("encode_int not supported for {:?}", &self.ops)
+    /// Determines the index of the first dictionary entry that is greater than or equal to `string_const`, or the
+    /// index of the last entry that is less than or equal to it if `upper` is set. Returns `None` if the column does
+    /// not consist of non-null indices into a sorted dictionary.
+    pub fn dictionary_bound(
+        &self,
+        string_const: BufferRef<Scalar<&'static str>>,
+        upper: bool,
+        planner: &mut QueryPlanner,
+    ) -> Option<BufferRef<Scalar<i64>>> {
+        match self.ops[..] {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)] => {
+                let (offset_len, backing_store) = self.dictionary_sections(planner);
+                Some(planner.dict_bound(offset_len, backing_store, string_const, upper))
+            }
+            _ => None,
+        }
+    }
+
+    /// Evaluates `regex` on each entry of the dictionary and looks up the result for each row, if `plan` consists of
+    /// non-null dictionary indices.
+    pub fn dictionary_regex(
+        &self,
+        plan: TypedBufferRef,
+        regex: &str,
+        planner: &mut QueryPlanner,
+    ) -> Option<BufferRef<u8>> {
+        match self.ops[..] {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)]
+            | [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::SharedDictLookup(_)] =>
+            {
+                let (offset_len, backing_store) = self.dictionary_sections(planner);
+                let matches = planner.dict_regex(offset_len, backing_store, regex);
+                Some(planner.dict_membership(plan, matches))
+            }
+            _ => None,
+        }
+    }
+
+    fn dictionary_sections(&self, planner: &mut QueryPlanner) -> (BufferRef<u64>, BufferRef<u8>) {
+        let offset_len = planner
+            .column_section(&self.column_name, 1, None, EncodingType::U64)
+            .u64()
+            .unwrap();
+        let backing_store = planner
+            .column_section(&self.column_name, 2, None, EncodingType::U8)
+            .u8()
+            .unwrap();
+        (offset_len, backing_store)
+    }
+
+    /// Encodes the integer `x`, returns `None` if the codec is not an order preserving mapping of single values or
+    /// `x` cannot be encoded.
+    pub fn encode_int(&self, x: i64) -> Option<i64> {
+        match self.ops[..] {
+            [CodecOp::Add(_, y)] => x.checked_sub(y),
+            [CodecOp::ToI64(_)] => Some(x),
+            _ => None,
         }
     }
 
@@ -315,6 +432,14 @@ pub enum CodecOp {
     LZ4(EncodingType, usize),
     UnpackStrings,
     UnhexpackStrings(bool, usize),
+    /// FSST compressed strings with the given total length in bytes, followed by the symbol table
+    Fsst(usize),
+    /// Floats stored as the XOR of their bit pattern with that of the previous value
+    Xor,
+    /// Lookup into a copy of a table wide dictionary, which is not sorted
+    SharedDictLookup(EncodingType),
+    /// Runs of values followed by the length of each run, with the given total length
+    RunLength(usize),
     Unknown,
 }
 
@@ -334,7 +459,7 @@ impl CodecOp {
                         EncodingType::I64
                     }
                 }
-                CodecOp::DictLookup(_) => {
+                CodecOp::DictLookup(_) | CodecOp::SharedDictLookup(_) => {
                     type_stack.pop();
                     type_stack.pop();
                     if type_stack.pop().unwrap().is_nullable() {
@@ -346,7 +471,20 @@ impl CodecOp {
                 CodecOp::LZ4(t, _) => *t,
                 CodecOp::UnpackStrings => EncodingType::Str,
                 CodecOp::UnhexpackStrings(_, _) => EncodingType::Str,
+                CodecOp::Fsst(_) => {
+                    type_stack.pop();
+                    type_stack.pop();
+                    EncodingType::Str
+                }
+                CodecOp::Xor => {
+                    type_stack.pop();
+                    EncodingType::F64
+                }
                 CodecOp::PushDataSection(i) => section_types[*i],
+                CodecOp::RunLength(_) => {
+                    type_stack.pop();
+                    type_stack.pop().unwrap()
+                }
                 CodecOp::Unknown => panic!("Unknown.output_type()"),
             };
             type_stack.push(t);
@@ -365,6 +503,10 @@ impl CodecOp {
             CodecOp::LZ4(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::Fsst(_) => false,
+            CodecOp::Xor => false,
+            CodecOp::SharedDictLookup(_) => false,
+            CodecOp::RunLength(_) => false,
             CodecOp::Unknown => panic!("Unknown.is_summation_preserving()"),
         }
     }
@@ -380,6 +522,10 @@ impl CodecOp {
             CodecOp::LZ4(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::Fsst(_) => false,
+            CodecOp::Xor => false,
+            CodecOp::SharedDictLookup(_) => false,
+            CodecOp::RunLength(_) => false,
             CodecOp::Unknown => panic!("Unknown.is_order_preserving()"),
         }
     }
@@ -396,6 +542,10 @@ impl CodecOp {
             CodecOp::LZ4(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::Fsst(_) => false,
+            CodecOp::Xor => false,
+            CodecOp::SharedDictLookup(_) => true,
+            CodecOp::RunLength(_) => false,
             CodecOp::Unknown => panic!("Unknown.is_fixed_width()"),
         }
     }
@@ -411,6 +561,10 @@ impl CodecOp {
             CodecOp::LZ4(_, _) => 1,
             CodecOp::UnpackStrings => 1,
             CodecOp::UnhexpackStrings(_, _) => 1,
+            CodecOp::Fsst(_) => 2,
+            CodecOp::Xor => 1,
+            CodecOp::SharedDictLookup(_) => 3,
+            CodecOp::RunLength(_) => 2,
             CodecOp::Unknown => panic!("Unknown.is_fixed_width()"),
         }
     }
@@ -438,6 +592,16 @@ impl CodecOp {
             }
             CodecOp::UnpackStrings => "StrUnpack".to_string(),
             CodecOp::UnhexpackStrings(_, _) => "StrHexUnpack".to_string(),
+            CodecOp::Fsst(_) => "Fsst".to_string(),
+            CodecOp::Xor => "Xor".to_string(),
+            CodecOp::SharedDictLookup(t) => format!("SharedDict({:?})", t),
+            CodecOp::RunLength(decoded_len) => {
+                if alternate {
+                    format!("RunLength({})", decoded_len)
+                } else {
+                    "RunLength".to_string()
+                }
+            }
             CodecOp::Unknown => "Unknown".to_string(),
         }
     }
@@ -1,14 +1,21 @@
 use std::fmt;
 use std::mem;
+use std::ops::Range;
 use std::sync::Arc;
 
+use memmap2::Mmap;
 use ordered_float::OrderedFloat;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
 use crate::engine::data_types::*;
 use crate::mem_store::lz4;
 use crate::mem_store::*;
 
+/// Columns with fewer values are never run length encoded.
+const MIN_RUN_LENGTH_ENCODED_LEN: usize = 1024;
+/// Columns are only run length encoded if runs are at least this long on average.
+const MIN_AVERAGE_RUN_LENGTH: usize = 8;
+
 #[derive(Serialize, Deserialize)]
 pub struct Column {
     name: String,
@@ -16,6 +23,45 @@ pub struct Column {
     range: Option<(i64, i64)>,
     codec: Codec,
     data: Vec<DataSection>,
+    /// Minimum and maximum of the values of integer columns, used to skip partitions that can't match a filter.
+    /// Not stored with the column, partitions loaded from disk get their ranges from the partition metadata instead.
+    #[serde(skip)]
+    value_range: Option<(i64, i64)>,
+    /// Like `value_range`, only known for columns that have been created in memory.
+    #[serde(skip)]
+    stats: Option<ColumnStats>,
+}
+
+/// Number of distinct and null values of a column, used to estimate the selectivity of filters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ColumnStats {
+    /// Number of distinct values, or a lower bound for columns with too many values to be dictionary encoded
+    pub distinct_values: Option<usize>,
+    pub null_count: usize,
+    /// Whether the column has no nulls and its values are in ascending order, as is the case for the sort key of
+    /// compacted partitions. Not persisted, columns loaded from disk are treated as unsorted.
+    #[serde(skip)]
+    pub sorted: bool,
+}
+
+impl ColumnStats {
+    /// Statistics of a column of `len` values where `present` is the bitmap of non-null values, if any, and `sorted`
+    /// indicates whether the values are in ascending order.
+    pub fn new(
+        len: usize,
+        distinct_values: Option<usize>,
+        present: Option<&[u8]>,
+        sorted: bool,
+    ) -> ColumnStats {
+        let present_count = present.map_or(len, |present| {
+            present.iter().map(|byte| byte.count_ones() as usize).sum()
+        });
+        ColumnStats {
+            distinct_values,
+            null_count: len - present_count,
+            sorted: sorted && present_count == len,
+        }
+    }
 }
 
 pub trait DataSource: fmt::Debug + Sync + Send {
@@ -25,6 +71,7 @@ pub trait DataSource: fmt::Debug + Sync + Send {
     fn len(&self) -> usize;
     fn data_sections(&self) -> Vec<&dyn Data>;
     fn full_type(&self) -> Type;
+    fn stats(&self) -> Option<ColumnStats>;
 }
 
 impl<T: DataSource> DataSource for Arc<T> {
@@ -46,6 +93,9 @@ impl<T: DataSource> DataSource for Arc<T> {
     fn full_type(&self) -> Type {
         (**self).full_type()
     }
+    fn stats(&self) -> Option<ColumnStats> {
+        (**self).stats()
+    }
 }
 
 impl DataSource for Column {
@@ -72,6 +122,139 @@ impl DataSource for Column {
     fn full_type(&self) -> Type {
         Type::new(self.basic_type(), Some(self.codec()))
     }
+    fn stats(&self) -> Option<ColumnStats> {
+        self.stats
+    }
+}
+
+/// The runs of values of a run length encoded column, which allows expressions to be evaluated once per run.
+#[derive(Debug)]
+pub struct RunLengthValues {
+    column: Arc<dyn DataSource>,
+    codec: Codec,
+    runs: usize,
+}
+
+impl RunLengthValues {
+    /// Returns the runs of `column` and the index of the section holding the run lengths, if `column` is run length
+    /// encoded.
+    pub fn new(column: &Arc<dyn DataSource>) -> Option<(RunLengthValues, usize)> {
+        let (section, codec) = column.codec().run_length_values()?;
+        let runs = column.data_sections()[section].len();
+        let values = RunLengthValues {
+            column: column.clone(),
+            codec,
+            runs,
+        };
+        Some((values, section))
+    }
+}
+
+impl DataSource for RunLengthValues {
+    fn encoding_type(&self) -> EncodingType {
+        self.codec.encoding_type()
+    }
+    fn range(&self) -> Option<(i64, i64)> {
+        self.column.range()
+    }
+    fn codec(&self) -> Codec {
+        self.codec.clone()
+    }
+    fn len(&self) -> usize {
+        self.runs
+    }
+    fn data_sections(&self) -> Vec<&dyn Data> {
+        self.column.data_sections()
+    }
+    fn full_type(&self) -> Type {
+        Type::new(self.codec.decoded_type(), Some(self.codec()))
+    }
+    fn stats(&self) -> Option<ColumnStats> {
+        self.column.stats()
+    }
+}
+
+/// A range of rows of a column, used to split large partitions into morsels that are scanned concurrently. Only
+/// supported for columns whose rows can be decoded independently of the preceding rows, see `Codec::row_sections`.
+#[derive(Debug)]
+pub struct ColumnSlice {
+    // Keeps the column alive for as long as `sections` refers to its data
+    _column: Arc<dyn DataSource>,
+    codec: Codec,
+    range: Option<(i64, i64)>,
+    len: usize,
+    sections: Vec<SectionSlice>,
+}
+
+#[derive(Debug)]
+enum SectionSlice {
+    Data(MappedData),
+    Null(usize),
+}
+
+impl ColumnSlice {
+    /// Rows `rows` of `column`, the start of `rows` must be a multiple of 8 so that null maps can be sliced.
+    pub fn new(column: &Arc<dyn DataSource>, rows: Range<usize>) -> Option<ColumnSlice> {
+        assert_eq!(rows.start % 8, 0);
+        let codec = column.codec();
+        let row_sections = codec.row_sections()?;
+        let mut sections = Vec::new();
+        for (i, data) in column.data_sections().into_iter().enumerate() {
+            let encoding_type = codec.section_types()[i];
+            let (offset, len) = if !row_sections.contains(&i) {
+                (0, data.len())
+            } else if encoding_type == EncodingType::Bitvec {
+                (rows.start / 8, (rows.len() + 7) / 8)
+            } else {
+                (rows.start, rows.len())
+            };
+            let section = if encoding_type == EncodingType::Null {
+                SectionSlice::Null(len)
+            } else {
+                // The slices refer to the data of `column`, which is kept alive by the `ColumnSlice`
+                SectionSlice::Data(unsafe { MappedData::slice(data, encoding_type, offset, len)? })
+            };
+            sections.push(section);
+        }
+        Some(ColumnSlice {
+            _column: column.clone(),
+            codec,
+            range: column.range(),
+            len: rows.len(),
+            sections,
+        })
+    }
+}
+
+impl DataSource for ColumnSlice {
+    fn encoding_type(&self) -> EncodingType {
+        self.codec.encoding_type()
+    }
+    fn range(&self) -> Option<(i64, i64)> {
+        self.range
+    }
+    fn codec(&self) -> Codec {
+        self.codec.clone()
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn data_sections(&self) -> Vec<&dyn Data> {
+        self.sections
+            .iter()
+            .map(|section| match section {
+                SectionSlice::Data(data) => data.to_any_vec(),
+                SectionSlice::Null(len) => len as &dyn Data,
+            })
+            .collect()
+    }
+    fn full_type(&self) -> Type {
+        Type::new(self.codec.decoded_type(), Some(self.codec()))
+    }
+    fn stats(&self) -> Option<ColumnStats> {
+        // Statistics are only known for the entire column
+        None
+    }
 }
 
 impl Column {
@@ -94,6 +277,27 @@ impl Column {
             range,
             codec,
             data,
+            value_range: None,
+            stats: None,
+        }
+    }
+
+    /// Reassembles a column from its serialized fields, e.g. when the data sections are mapped from disk.
+    pub(crate) fn from_parts(
+        name: String,
+        len: usize,
+        range: Option<(i64, i64)>,
+        codec: Codec,
+        data: Vec<DataSection>,
+    ) -> Column {
+        Column {
+            name,
+            len,
+            range,
+            codec,
+            data,
+            value_range: None,
+            stats: None,
         }
     }
 
@@ -104,6 +308,45 @@ impl Column {
             range: None,
             codec: Codec::identity(BasicType::Null),
             data: vec![DataSection::Null(len)],
+            value_range: None,
+            stats: Some(ColumnStats {
+                distinct_values: Some(0),
+                null_count: len,
+                sorted: false,
+            }),
+        }
+    }
+
+    pub fn set_value_range(&mut self, min: i64, max: i64) {
+        self.value_range = Some((min, max));
+    }
+
+    pub fn value_range(&self) -> Option<(i64, i64)> {
+        self.value_range
+    }
+
+    pub fn set_stats(&mut self, stats: ColumnStats) {
+        self.stats = Some(stats);
+    }
+
+    /// Stores runs of equal values in section 0 as a single value and the length of the run. Only applies to non-null
+    /// columns that are decoded value by value and have long runs, returns whether the column was encoded.
+    pub fn run_length_encode(&mut self) -> bool {
+        if !self.codec.is_elementwise_decodable()
+            || self.codec.is_shared_dictionary()
+            || self.len < MIN_RUN_LENGTH_ENCODED_LEN
+            || self.data[0].len() != self.len
+        {
+            return false;
+        }
+        match self.data[0].run_length_encode(self.len / MIN_AVERAGE_RUN_LENGTH) {
+            Some((values, run_lengths)) => {
+                self.codec = self.codec.with_run_length(self.data.len(), self.len);
+                self.data[0] = values;
+                self.data.push(DataSection::U32(run_lengths));
+                true
+            }
+            None => false,
         }
     }
 
@@ -119,10 +362,10 @@ impl Column {
 
     #[cfg(feature = "enable_lz4")]
     pub fn lz4_decode(&mut self) {
-        if let Some(CodecOp::LZ4(decoded_type, _)) = self.codec.ops().get(0).copied() {
+        if let Some(CodecOp::LZ4(decoded_type, decoded_len)) = self.codec.ops().get(0).copied() {
             trace!("lz4_decode before: {:?}", self);
             self.codec = self.codec.without_lz4();
-            self.data[0] = self.data[0].lz4_decode(decoded_type, self.len);
+            self.data[0] = self.data[0].lz4_decode(decoded_type, decoded_len);
             trace!("lz4_decode after: {:?}", self);
         }
     }
@@ -197,7 +440,7 @@ impl fmt::Debug for Column {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
 pub enum DataSection {
     U8(Vec<u8>),
     U16(Vec<u16>),
@@ -207,6 +450,198 @@ pub enum DataSection {
     F64(Vec<OrderedFloat<f64>>),
     Null(usize),
     Bitvec(Vec<u8>),
+    /// Data that is read directly from a memory mapped subpartition file instead of being copied to the heap.
+    #[serde(skip_deserializing)]
+    Mapped(MappedSection),
+}
+
+/// Data section that borrows its values from a memory mapped file. The pages are served by the OS page cache and
+/// dropping the section unmaps them.
+pub struct MappedSection {
+    // Keeps the mapping alive for as long as `data` refers to it
+    _mmap: Arc<Mmap>,
+    data: MappedData,
+}
+
+#[derive(Debug)]
+pub enum MappedData {
+    U8(&'static [u8]),
+    U16(&'static [u16]),
+    U32(&'static [u32]),
+    U64(&'static [u64]),
+    I64(&'static [i64]),
+    F64(&'static [OrderedFloat<f64>]),
+    Bitvec(&'static [u8]),
+}
+
+impl MappedSection {
+    /// Interprets `len` values of type `encoding_type` at `offset` of `mmap` as a data section.
+    /// Returns `None` if the values are not aligned for their type, or if this platform is big endian and the values
+    /// would have to be byte swapped.
+    pub fn new(
+        mmap: &Arc<Mmap>,
+        encoding_type: EncodingType,
+        offset: usize,
+        len: usize,
+    ) -> Option<MappedSection> {
+        fn slice<T>(mmap: &Mmap, offset: usize, len: usize) -> Option<&'static [T]> {
+            let bytes = &mmap[offset..offset + len * mem::size_of::<T>()];
+            if cfg!(target_endian = "big") || bytes.as_ptr() as usize % mem::align_of::<T>() != 0 {
+                return None;
+            }
+            // The mapping outlives the slice since `MappedSection` holds a reference to it
+            Some(unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const T, len) })
+        }
+        let data = match encoding_type {
+            EncodingType::U8 => MappedData::U8(slice(mmap, offset, len)?),
+            EncodingType::U16 => MappedData::U16(slice(mmap, offset, len)?),
+            EncodingType::U32 => MappedData::U32(slice(mmap, offset, len)?),
+            EncodingType::U64 => MappedData::U64(slice(mmap, offset, len)?),
+            EncodingType::I64 => MappedData::I64(slice(mmap, offset, len)?),
+            EncodingType::F64 => MappedData::F64(slice(mmap, offset, len)?),
+            EncodingType::Bitvec => MappedData::Bitvec(slice(mmap, offset, len)?),
+            _ => return None,
+        };
+        Some(MappedSection {
+            _mmap: mmap.clone(),
+            data,
+        })
+    }
+
+    pub fn to_any_vec(&self) -> &dyn Data {
+        self.data.to_any_vec()
+    }
+
+    pub fn len(&self) -> usize {
+        match self.data {
+            MappedData::U8(x) | MappedData::Bitvec(x) => x.len(),
+            MappedData::U16(x) => x.len(),
+            MappedData::U32(x) => x.len(),
+            MappedData::U64(x) => x.len(),
+            MappedData::I64(x) => x.len(),
+            MappedData::F64(x) => x.len(),
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        match self.data {
+            MappedData::U8(x) | MappedData::Bitvec(x) => mem::size_of_val(x),
+            MappedData::U16(x) => mem::size_of_val(x),
+            MappedData::U32(x) => mem::size_of_val(x),
+            MappedData::U64(x) => mem::size_of_val(x),
+            MappedData::I64(x) => mem::size_of_val(x),
+            MappedData::F64(x) => mem::size_of_val(x),
+        }
+    }
+
+    /// Copies the data into an owned data section.
+    pub fn to_owned_section(&self) -> DataSection {
+        match self.data {
+            MappedData::U8(x) => DataSection::U8(x.to_vec()),
+            MappedData::U16(x) => DataSection::U16(x.to_vec()),
+            MappedData::U32(x) => DataSection::U32(x.to_vec()),
+            MappedData::U64(x) => DataSection::U64(x.to_vec()),
+            MappedData::I64(x) => DataSection::I64(x.to_vec()),
+            MappedData::F64(x) => DataSection::F64(x.to_vec()),
+            MappedData::Bitvec(x) => DataSection::Bitvec(x.to_vec()),
+        }
+    }
+}
+
+impl MappedData {
+    /// Borrows `len` values of type `encoding_type` starting at `offset` from `data`.
+    ///
+    /// # Safety
+    /// The returned slices must not outlive `data`.
+    unsafe fn slice(
+        data: &dyn Data,
+        encoding_type: EncodingType,
+        offset: usize,
+        len: usize,
+    ) -> Option<MappedData> {
+        unsafe fn slice<T>(values: &[T], offset: usize, len: usize) -> &'static [T] {
+            let values = &values[offset..offset + len];
+            std::slice::from_raw_parts(values.as_ptr(), values.len())
+        }
+        Some(match encoding_type {
+            EncodingType::U8 => MappedData::U8(slice(data.cast_ref_u8(), offset, len)),
+            EncodingType::U16 => MappedData::U16(slice(data.cast_ref_u16(), offset, len)),
+            EncodingType::U32 => MappedData::U32(slice(data.cast_ref_u32(), offset, len)),
+            EncodingType::U64 => MappedData::U64(slice(data.cast_ref_u64(), offset, len)),
+            EncodingType::I64 => MappedData::I64(slice(data.cast_ref_i64(), offset, len)),
+            EncodingType::F64 => MappedData::F64(slice(data.cast_ref_f64(), offset, len)),
+            EncodingType::Bitvec => MappedData::Bitvec(slice(data.cast_ref_u8(), offset, len)),
+            _ => return None,
+        })
+    }
+
+    fn to_any_vec(&self) -> &dyn Data {
+        // Shortens the lifetime of the slice to that of the section, which keeps the data alive
+        fn data<'a, T>(x: &'a &'static [T]) -> &'a dyn Data<'a>
+        where
+            &'a [T]: Data<'a>,
+        {
+            let x: &'a &'a [T] = x;
+            x
+        }
+        match self {
+            MappedData::U8(ref x) | MappedData::Bitvec(ref x) => data(x),
+            MappedData::U16(ref x) => data(x),
+            MappedData::U32(ref x) => data(x),
+            MappedData::U64(ref x) => data(x),
+            MappedData::I64(ref x) => data(x),
+            MappedData::F64(ref x) => data(x),
+        }
+    }
+}
+
+impl fmt::Debug for MappedSection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mapped({:?})", self.data)
+    }
+}
+
+/// Mapped sections are serialized like the owned section they were loaded from.
+impl Serialize for DataSection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            DataSection::U8(x) => serializer.serialize_newtype_variant("DataSection", 0, "U8", x),
+            DataSection::U16(x) => serializer.serialize_newtype_variant("DataSection", 1, "U16", x),
+            DataSection::U32(x) => serializer.serialize_newtype_variant("DataSection", 2, "U32", x),
+            DataSection::U64(x) => serializer.serialize_newtype_variant("DataSection", 3, "U64", x),
+            DataSection::I64(x) => serializer.serialize_newtype_variant("DataSection", 4, "I64", x),
+            DataSection::F64(x) => serializer.serialize_newtype_variant("DataSection", 5, "F64", x),
+            DataSection::Null(x) => {
+                serializer.serialize_newtype_variant("DataSection", 6, "Null", x)
+            }
+            DataSection::Bitvec(x) => {
+                serializer.serialize_newtype_variant("DataSection", 7, "Bitvec", x)
+            }
+            DataSection::Mapped(x) => match x.data {
+                MappedData::U8(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 0, "U8", x)
+                }
+                MappedData::U16(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 1, "U16", x)
+                }
+                MappedData::U32(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 2, "U32", x)
+                }
+                MappedData::U64(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 3, "U64", x)
+                }
+                MappedData::I64(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 4, "I64", x)
+                }
+                MappedData::F64(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 5, "F64", x)
+                }
+                MappedData::Bitvec(x) => {
+                    serializer.serialize_newtype_variant("DataSection", 7, "Bitvec", x)
+                }
+            },
+        }
+    }
 }
 
 impl DataSection {
@@ -220,6 +655,7 @@ impl DataSection {
             DataSection::F64(ref x) => x,
             DataSection::Null(ref x) => x,
             DataSection::Bitvec(ref x) => x,
+            DataSection::Mapped(ref x) => x.to_any_vec(),
         }
     }
 
@@ -233,6 +669,7 @@ impl DataSection {
             DataSection::F64(ref x) => x.len(),
             DataSection::Null(ref x) => *x,
             DataSection::Bitvec(ref x) => x.len(),
+            DataSection::Mapped(ref x) => x.len(),
         }
     }
 
@@ -246,6 +683,7 @@ impl DataSection {
             DataSection::F64(ref x) => x.capacity(),
             DataSection::Null(ref x) => *x,
             DataSection::Bitvec(ref x) => x.capacity(),
+            DataSection::Mapped(ref x) => x.len(),
         }
     }
 
@@ -259,6 +697,15 @@ impl DataSection {
             DataSection::F64(_) => EncodingType::F64,
             DataSection::Null(_) => EncodingType::Null,
             DataSection::Bitvec(_) => EncodingType::Bitvec,
+            DataSection::Mapped(ref x) => match x.data {
+                MappedData::U8(_) => EncodingType::U8,
+                MappedData::U16(_) => EncodingType::U16,
+                MappedData::U32(_) => EncodingType::U32,
+                MappedData::U64(_) => EncodingType::U64,
+                MappedData::I64(_) => EncodingType::I64,
+                MappedData::F64(_) => EncodingType::F64,
+                MappedData::Bitvec(_) => EncodingType::Bitvec,
+            },
         }
     }
 
@@ -320,41 +767,81 @@ impl DataSection {
                 )
             }
             DataSection::Null(ref x) => (DataSection::Null(*x), false),
+            DataSection::Mapped(ref x) => x.to_owned_section().lz4_encode(),
+        }
+    }
+
+    /// Collapses runs of equal values into the value and the length of the run. Returns `None` if the section is not
+    /// integer valued or has more than `max_runs` runs.
+    pub fn run_length_encode(&self, max_runs: usize) -> Option<(DataSection, Vec<u32>)> {
+        fn runs<T: PartialEq + Copy>(data: &[T], max_runs: usize) -> Option<(Vec<T>, Vec<u32>)> {
+            let mut values = Vec::new();
+            let mut run_lengths = Vec::<u32>::new();
+            for &x in data {
+                match run_lengths.last_mut() {
+                    Some(run_length) if values.last() == Some(&x) && *run_length < u32::MAX => {
+                        *run_length += 1
+                    }
+                    _ => {
+                        if values.len() == max_runs {
+                            return None;
+                        }
+                        values.push(x);
+                        run_lengths.push(1);
+                    }
+                }
+            }
+            values.shrink_to_fit();
+            run_lengths.shrink_to_fit();
+            Some((values, run_lengths))
+        }
+        match self {
+            DataSection::U8(ref x) => runs(x, max_runs).map(|(v, r)| (DataSection::U8(v), r)),
+            DataSection::U16(ref x) => runs(x, max_runs).map(|(v, r)| (DataSection::U16(v), r)),
+            DataSection::U32(ref x) => runs(x, max_runs).map(|(v, r)| (DataSection::U32(v), r)),
+            DataSection::U64(ref x) => runs(x, max_runs).map(|(v, r)| (DataSection::U64(v), r)),
+            DataSection::I64(ref x) => runs(x, max_runs).map(|(v, r)| (DataSection::I64(v), r)),
+            _ => None,
         }
     }
 
     #[cfg(feature = "enable_lz4")]
     pub fn lz4_decode(&self, decoded_type: EncodingType, len: usize) -> DataSection {
-        match self {
-            DataSection::U8(encoded) => match decoded_type {
-                EncodingType::U8 => {
-                    let mut decoded = vec![0; len];
-                    lz4::decode::<u8>(&mut lz4::decoder(encoded), &mut decoded);
-                    DataSection::U8(decoded)
-                }
-                EncodingType::U16 => {
-                    let mut decoded = vec![0; len];
-                    lz4::decode::<u16>(&mut lz4::decoder(encoded), &mut decoded);
-                    DataSection::U16(decoded)
-                }
-                EncodingType::U32 => {
-                    let mut decoded = vec![0; len];
-                    lz4::decode::<u32>(&mut lz4::decoder(encoded), &mut decoded);
-                    DataSection::U32(decoded)
-                }
-                EncodingType::U64 => {
-                    let mut decoded = vec![0; len];
-                    lz4::decode::<u64>(&mut lz4::decoder(encoded), &mut decoded);
-                    DataSection::U64(decoded)
-                }
-                EncodingType::I64 => {
-                    let mut decoded = vec![0; len];
-                    lz4::decode::<i64>(&mut lz4::decoder(encoded), &mut decoded);
-                    DataSection::I64(decoded)
-                }
-                t => panic!("Unexpected type {:?} for lz4 decode", t),
-            },
+        let encoded: &[u8] = match self {
+            DataSection::U8(encoded) => encoded,
+            DataSection::Mapped(MappedSection {
+                data: MappedData::U8(encoded),
+                ..
+            }) => encoded,
             _ => panic!("Trying to lz4 encode non u8 data section"),
+        };
+        match decoded_type {
+            EncodingType::U8 => {
+                let mut decoded = vec![0; len];
+                lz4::decode::<u8>(&mut lz4::decoder(encoded), &mut decoded);
+                DataSection::U8(decoded)
+            }
+            EncodingType::U16 => {
+                let mut decoded = vec![0; len];
+                lz4::decode::<u16>(&mut lz4::decoder(encoded), &mut decoded);
+                DataSection::U16(decoded)
+            }
+            EncodingType::U32 => {
+                let mut decoded = vec![0; len];
+                lz4::decode::<u32>(&mut lz4::decoder(encoded), &mut decoded);
+                DataSection::U32(decoded)
+            }
+            EncodingType::U64 => {
+                let mut decoded = vec![0; len];
+                lz4::decode::<u64>(&mut lz4::decoder(encoded), &mut decoded);
+                DataSection::U64(decoded)
+            }
+            EncodingType::I64 => {
+                let mut decoded = vec![0; len];
+                lz4::decode::<i64>(&mut lz4::decoder(encoded), &mut decoded);
+                DataSection::I64(decoded)
+            }
+            t => panic!("Unexpected type {:?} for lz4 decode", t),
         }
     }
 
@@ -367,7 +854,7 @@ impl DataSection {
                 DataSection::U64(ref mut x) => x.shrink_to_fit(),
                 DataSection::I64(ref mut x) => x.shrink_to_fit(),
                 DataSection::F64(ref mut x) => x.shrink_to_fit(),
-                DataSection::Null(_) => {}
+                DataSection::Null(_) | DataSection::Mapped(_) => {}
             }
         }
     }
@@ -381,6 +868,8 @@ impl DataSection {
             DataSection::I64(ref x) => x.capacity() * mem::size_of::<i64>(),
             DataSection::F64(ref x) => x.capacity() * mem::size_of::<OrderedFloat<f64>>(),
             DataSection::Null(_) => 0,
+            // Counted towards the memory limit so that evicting columns also bounds the amount of mapped data
+            DataSection::Mapped(ref x) => x.size_bytes(),
         }
     }
 }
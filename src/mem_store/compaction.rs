@@ -0,0 +1,86 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::mem_store::partition::Partition;
+
+/// Decides which partitions of a table are combined by compaction.
+/// Only contiguous partitions are combined, and each compaction combines at least two partitions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CompactionPolicy {
+    /// Combines the newest partitions once the size of the oldest of them is less than `1 / combine_factor` of their
+    /// total size, which keeps the number of partitions logarithmic in the size of the table.
+    SizeTiered { combine_factor: u64 },
+    /// Combines adjacent partitions whose values of the integer `column` fall into the same window of `window` units,
+    /// e.g. one day of timestamps. Queries that filter on a time range then only scan partitions of matching windows.
+    TimeWindow { column: String, window: i64 },
+    /// Combines adjacent partitions that are smaller than `target_bytes`, as long as the combined partition does not
+    /// exceed `target_bytes`.
+    TargetPartitionSize { target_bytes: u64 },
+}
+
+impl CompactionPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            CompactionPolicy::SizeTiered { combine_factor: 0 } => {
+                Err("combine_factor must be greater than 0".to_string())
+            }
+            CompactionPolicy::TimeWindow { window, .. } if window <= 0 => {
+                Err("window must be greater than 0".to_string())
+            }
+            CompactionPolicy::TargetPartitionSize { target_bytes: 0 } => {
+                Err("target_bytes must be greater than 0".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the range of `partitions`, which are ordered by offset, that should be combined.
+    pub fn plan(&self, partitions: &[Arc<Partition>]) -> Option<Range<usize>> {
+        let range = match self {
+            CompactionPolicy::SizeTiered { combine_factor } => {
+                let mut total = 0;
+                let mut start = None;
+                for (i, partition) in partitions.iter().enumerate().rev() {
+                    let size = partition.total_size_bytes() as u64;
+                    total += size;
+                    if size * combine_factor < total {
+                        start = Some(i);
+                    }
+                }
+                start.map(|start| start..partitions.len())
+            }
+            CompactionPolicy::TimeWindow { column, window } => {
+                let window_of = |partition: &Arc<Partition>| {
+                    let (min, max) = partition.value_range(column)?;
+                    let window_index = min.div_euclid(*window);
+                    (window_index == max.div_euclid(*window)).then_some(window_index)
+                };
+                (0..partitions.len()).find_map(|start| {
+                    let window_index = window_of(&partitions[start])?;
+                    let end = start
+                        + partitions[start..]
+                            .iter()
+                            .take_while(|partition| window_of(partition) == Some(window_index))
+                            .count();
+                    Some(start..end).filter(|range| range.len() >= 2)
+                })
+            }
+            CompactionPolicy::TargetPartitionSize { target_bytes } => (0..partitions.len())
+                .find_map(|start| {
+                    let mut total = 0;
+                    let end = start
+                        + partitions[start..]
+                            .iter()
+                            .take_while(|partition| {
+                                total += partition.total_size_bytes() as u64;
+                                total <= *target_bytes
+                            })
+                            .count();
+                    Some(start..end).filter(|range| range.len() >= 2)
+                }),
+        };
+        range.filter(|range| range.len() >= 2)
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::{HashSet, VecDeque};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ingest::raw_val::RawVal;
+
+/// Drops ingested rows whose key was already ingested into the same table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Deduplication {
+    /// Column that identifies duplicate rows. Rows without a value for the key column are never dropped.
+    pub key_column: String,
+    /// How long keys are remembered after the rows they belong to have been flushed from the WAL.
+    /// If `None`, rows are only compared against rows that are still in the WAL.
+    pub lookback: Option<Duration>,
+}
+
+impl Deduplication {
+    pub fn new(key_column: &str) -> Deduplication {
+        Deduplication {
+            key_column: key_column.to_string(),
+            lookback: None,
+        }
+    }
+
+    pub fn with_lookback(mut self, lookback: Duration) -> Deduplication {
+        self.lookback = Some(lookback);
+        self
+    }
+}
+
+/// Keys of rows ingested into a table with deduplication.
+/// Keys are only kept in memory, so duplicates of rows ingested before a restart are not detected.
+#[derive(Default)]
+pub struct SeenKeys {
+    // Keys of rows that are still in the WAL
+    current: HashSet<RawVal>,
+    // Keys of flushed WAL windows within the lookback, oldest first
+    flushed: VecDeque<(Instant, HashSet<RawVal>)>,
+}
+
+impl SeenKeys {
+    /// Records `key` and returns whether it has not been seen before.
+    pub fn insert(&mut self, key: RawVal) -> bool {
+        if key == RawVal::Null {
+            return true;
+        }
+        if self.flushed.iter().any(|(_, keys)| keys.contains(&key)) {
+            return false;
+        }
+        self.current.insert(key)
+    }
+
+    /// Forgets `key` if it was recorded since the last call to `rotate`.
+    pub fn remove(&mut self, key: &RawVal) {
+        self.current.remove(key);
+    }
+
+    /// Starts a new WAL window and forgets keys of windows that were flushed more than `lookback` ago.
+    pub fn rotate(&mut self, lookback: Option<Duration>) {
+        let lookback = match lookback {
+            Some(lookback) => lookback,
+            None => {
+                self.current.clear();
+                self.flushed.clear();
+                return;
+            }
+        };
+        let now = Instant::now();
+        if !self.current.is_empty() {
+            self.flushed.push_back((now, mem::take(&mut self.current)));
+        }
+        while let Some(&(flushed_at, _)) = self.flushed.front() {
+            if now.duration_since(flushed_at) <= lookback {
+                break;
+            }
+            self.flushed.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_keys() {
+        let mut seen = SeenKeys::default();
+        assert!(seen.insert(RawVal::Int(1)));
+        assert!(!seen.insert(RawVal::Int(1)));
+        assert!(seen.insert(RawVal::Null));
+        assert!(seen.insert(RawVal::Null));
+        seen.rotate(Some(Duration::from_secs(3600)));
+        assert!(!seen.insert(RawVal::Int(1)));
+        assert!(seen.insert(RawVal::Int(2)));
+        seen.rotate(None);
+        assert!(seen.insert(RawVal::Int(1)));
+        seen.remove(&RawVal::Int(1));
+        assert!(seen.insert(RawVal::Int(1)));
+    }
+}
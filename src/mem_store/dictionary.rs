@@ -0,0 +1,125 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::stringpack::IndexedPackedStrings;
+
+/// Maximum number of strings in a shared dictionary. Every partition stores a copy of the dictionary, so shared
+/// dictionaries are only suitable for low cardinality columns. Partitions with values that do not fit into the
+/// dictionary fall back to the regular per-partition string encodings.
+pub const MAX_SHARED_DICTIONARY_SIZE: usize = 1 << 12;
+
+/// Append-only dictionary that assigns the same code to a string in every partition of a table column.
+/// Since codes agree across partitions, results grouped by the column can be merged by comparing codes.
+#[derive(Default)]
+pub struct SharedDictionary {
+    strings: IndexedPackedStrings,
+    codes: HashMap<String, u32>,
+}
+
+impl SharedDictionary {
+    pub fn from_strings(strings: &[String]) -> SharedDictionary {
+        let mut dictionary = SharedDictionary::default();
+        for s in strings {
+            dictionary.insert(s);
+        }
+        dictionary
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.len() == 0
+    }
+
+    pub fn strings(&self) -> Vec<String> {
+        self.strings.iter().map(str::to_string).collect()
+    }
+
+    /// Returns the code of every string, adding new strings to the dictionary.
+    /// Returns `None` and leaves the dictionary unchanged if the new strings would exceed `MAX_SHARED_DICTIONARY_SIZE`.
+    pub fn encode<'a>(
+        &mut self,
+        strings: impl Iterator<Item = &'a str> + Clone,
+    ) -> Option<Vec<u32>> {
+        let mut new_strings = HashSet::new();
+        for s in strings.clone() {
+            if !self.codes.contains_key(s)
+                && new_strings.insert(s)
+                && self.len() + new_strings.len() > MAX_SHARED_DICTIONARY_SIZE
+            {
+                return None;
+            }
+        }
+        Some(strings.map(|s| self.insert(s)).collect())
+    }
+
+    /// The dictionary in the format of the dictionary sections of a dictionary encoded string column.
+    pub fn sections(&self) -> DictionarySections {
+        let (offset_len, backing_store) = self.strings.clone().into_parts();
+        DictionarySections {
+            offset_len,
+            backing_store,
+        }
+    }
+
+    fn insert(&mut self, s: &str) -> u32 {
+        if let Some(&code) = self.codes.get(s) {
+            return code;
+        }
+        let code = self.strings.len() as u32;
+        self.strings.push(s);
+        self.codes.insert(s.to_string(), code);
+        code
+    }
+}
+
+/// Copy of a shared dictionary as stored in a column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictionarySections {
+    pub offset_len: Vec<u64>,
+    pub backing_store: Vec<u8>,
+}
+
+impl DictionarySections {
+    /// Whether all codes of `self` refer to the same strings in `other`, i.e. `other` is the same shared dictionary
+    /// with possibly more strings appended.
+    pub fn is_prefix_of(&self, other: &DictionarySections) -> bool {
+        other.offset_len.starts_with(&self.offset_len)
+            && other.backing_store.starts_with(&self.backing_store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut dictionary = SharedDictionary::default();
+        assert_eq!(
+            dictionary.encode(["b", "a", "b"].into_iter()),
+            Some(vec![0, 1, 0])
+        );
+        let repeated = vec!["x"; MAX_SHARED_DICTIONARY_SIZE * 2];
+        assert_eq!(
+            dictionary
+                .encode(repeated.into_iter())
+                .map(|codes| codes[0]),
+            Some(2)
+        );
+        let sections = dictionary.sections();
+        assert_eq!(dictionary.encode(["c", "a"].into_iter()), Some(vec![3, 1]));
+        assert!(sections.is_prefix_of(&dictionary.sections()));
+        assert!(!dictionary.sections().is_prefix_of(&sections));
+
+        let restored = SharedDictionary::from_strings(&dictionary.strings());
+        assert_eq!(restored.sections(), dictionary.sections());
+
+        let many = (0..MAX_SHARED_DICTIONARY_SIZE)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(dictionary.encode(many.iter().map(String::as_str)), None);
+        assert_eq!(dictionary.len(), 4);
+    }
+}
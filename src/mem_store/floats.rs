@@ -3,6 +3,8 @@ use ordered_float::OrderedFloat;
 use crate::mem_store::*;
 use std::sync::Arc;
 
+const XOR_MIN_ZERO_BYTE_GAIN: usize = 20;
+
 pub struct FloatColumn;
 
 impl FloatColumn {
@@ -12,15 +14,35 @@ impl FloatColumn {
             n
         });
         values.shrink_to_fit();
-        let mut column = match null {
-            Some(present) => Column::new(
+        // XOR encoding only reduces size in combination with LZ4
+        let xor_encoded = if cfg!(feature = "enable_lz4") {
+            FloatColumn::xor_encode(&values)
+        } else {
+            None
+        };
+        let mut column = match (null, xor_encoded) {
+            (Some(present), Some(encoded)) => Column::new(
+                name,
+                values.len(),
+                None,
+                vec![CodecOp::Xor, CodecOp::PushDataSection(1), CodecOp::Nullable],
+                vec![encoded.into(), DataSection::Bitvec(present)],
+            ),
+            (Some(present), None) => Column::new(
                 name,
                 values.len(),
                 None,
                 vec![CodecOp::PushDataSection(1), CodecOp::Nullable],
                 vec![values.into(), DataSection::Bitvec(present)],
             ),
-            None => Column::new(
+            (None, Some(encoded)) => Column::new(
+                name,
+                values.len(),
+                None,
+                vec![CodecOp::Xor],
+                vec![encoded.into()],
+            ),
+            (None, None) => Column::new(
                 name,
                 values.len(),
                 None,
@@ -31,4 +53,27 @@ impl FloatColumn {
         column.lz4_encode();
         Arc::new(column)
     }
-}
\ No newline at end of file
+
+    /// XORs the bit pattern of each value with that of the previous value, as in Gorilla (Pelkonen et al., 2015).
+    /// Returns `None` unless the encoded values contain more zero bytes than the original ones, which is the case for
+    /// slowly changing series where consecutive values share their sign, exponent and leading mantissa bits.
+    fn xor_encode(values: &[OrderedFloat<f64>]) -> Option<Vec<u64>> {
+        let zero_bytes = |x: u64| x.to_le_bytes().iter().filter(|&&b| b == 0).count();
+        let mut encoded = Vec::with_capacity(values.len());
+        let mut previous = 0;
+        let mut original_zero_bytes = 0;
+        let mut encoded_zero_bytes = 0;
+        for value in values {
+            let bits = value.0.to_bits();
+            encoded.push(bits ^ previous);
+            original_zero_bytes += zero_bytes(bits);
+            encoded_zero_bytes += zero_bytes(bits ^ previous);
+            previous = bits;
+        }
+        if encoded_zero_bytes * 100 > original_zero_bytes * (100 + XOR_MIN_ZERO_BYTE_GAIN) {
+            Some(encoded)
+        } else {
+            None
+        }
+    }
+}
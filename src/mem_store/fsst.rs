@@ -0,0 +1,185 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Fast Static Symbol Table compression of strings (Boncz, Neumann, Leis 2020).
+/// Strings are encoded as sequences of one byte codes that refer to symbols of up to 8 bytes, with `ESCAPE` followed by
+/// a literal byte for input that is not covered by any symbol. Decoding is a table lookup per code.
+pub struct SymbolTable {
+    symbols: Vec<Vec<u8>>,
+    // Codes of all symbols starting with a given byte, longest symbols first
+    by_first_byte: Vec<Vec<u8>>,
+}
+
+const MAX_SYMBOLS: usize = 255;
+const MAX_SYMBOL_LEN: usize = 8;
+const ESCAPE: u8 = 255;
+const SAMPLE_BYTES: usize = 1 << 14;
+const GENERATIONS: usize = 5;
+
+impl SymbolTable {
+    /// Builds a symbol table from a sample of `strings`.
+    pub fn build<'a>(strings: impl Iterator<Item = &'a str>) -> SymbolTable {
+        let mut sample = Vec::new();
+        let mut sample_bytes = 0;
+        for s in strings {
+            if sample_bytes >= SAMPLE_BYTES {
+                break;
+            }
+            sample_bytes += s.len();
+            sample.push(s.as_bytes());
+        }
+
+        let mut table = SymbolTable::new(vec![]);
+        for _ in 0..GENERATIONS {
+            // Counts how often each symbol and each concatenation of two adjacent symbols occurs when encoding the sample
+            let mut counts = HashMap::<Vec<u8>, usize>::default();
+            for s in &sample {
+                let mut previous: Option<&[u8]> = None;
+                let mut pos = 0;
+                while pos < s.len() {
+                    let symbol = match table.longest_match(&s[pos..]) {
+                        Some(code) => &table.symbols[code as usize][..],
+                        None => &s[pos..pos + 1],
+                    };
+                    *counts.entry(symbol.to_vec()).or_insert(0) += 1;
+                    if let Some(previous) = previous {
+                        let mut concatenated = previous.to_vec();
+                        concatenated.extend_from_slice(symbol);
+                        concatenated.truncate(MAX_SYMBOL_LEN);
+                        *counts.entry(concatenated).or_insert(0) += 1;
+                    }
+                    previous = Some(symbol);
+                    pos += symbol.len();
+                }
+            }
+            // Keep the symbols that save the most bytes
+            let mut candidates = counts
+                .into_iter()
+                .map(|(symbol, count)| (count * symbol.len(), symbol))
+                .collect::<Vec<_>>();
+            candidates.sort_unstable_by(|a, b| b.cmp(a));
+            table = SymbolTable::new(
+                candidates
+                    .into_iter()
+                    .take(MAX_SYMBOLS)
+                    .map(|(_, symbol)| symbol)
+                    .collect(),
+            );
+        }
+        table
+    }
+
+    fn new(symbols: Vec<Vec<u8>>) -> SymbolTable {
+        let mut by_first_byte = vec![Vec::new(); 256];
+        for (code, symbol) in symbols.iter().enumerate() {
+            by_first_byte[symbol[0] as usize].push(code as u8);
+        }
+        for codes in &mut by_first_byte {
+            codes.sort_by_key(|&code| Reverse(symbols[code as usize].len()));
+        }
+        SymbolTable {
+            symbols,
+            by_first_byte,
+        }
+    }
+
+    fn longest_match(&self, bytes: &[u8]) -> Option<u8> {
+        self.by_first_byte[bytes[0] as usize]
+            .iter()
+            .find(|&&code| bytes.starts_with(&self.symbols[code as usize]))
+            .copied()
+    }
+
+    /// Appends the codes for `string` to `codes`.
+    pub fn encode(&self, string: &str, codes: &mut Vec<u8>) {
+        let bytes = string.as_bytes();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            match self.longest_match(&bytes[pos..]) {
+                Some(code) => {
+                    codes.push(code);
+                    pos += self.symbols[code as usize].len();
+                }
+                None => {
+                    codes.push(ESCAPE);
+                    codes.push(bytes[pos]);
+                    pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Serializes the symbol table as a sequence of length prefixed symbols.
+    pub fn into_vec(self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for symbol in self.symbols {
+            data.push(symbol.len() as u8);
+            data.extend_from_slice(&symbol);
+        }
+        data.shrink_to_fit();
+        data
+    }
+}
+
+/// Returns the symbols of a symbol table serialized with `SymbolTable::into_vec`.
+pub fn symbols(mut data: &[u8]) -> Vec<&[u8]> {
+    let mut symbols = Vec::with_capacity(MAX_SYMBOLS);
+    while !data.is_empty() {
+        let len = data[0] as usize;
+        symbols.push(&data[1..len + 1]);
+        data = &data[len + 1..];
+    }
+    symbols
+}
+
+/// Appends the decoded bytes of `codes` to `decoded`.
+pub fn decode(symbols: &[&[u8]], codes: &[u8], decoded: &mut Vec<u8>) {
+    let mut pos = 0;
+    while pos < codes.len() {
+        if codes[pos] == ESCAPE {
+            decoded.push(codes[pos + 1]);
+            pos += 2;
+        } else {
+            decoded.extend_from_slice(symbols[codes[pos] as usize]);
+            pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let strings = (0..1000)
+            .map(|i| format!("https://example.com/users/{}/profile?ref=söme", i * 7919))
+            .collect::<Vec<_>>();
+        let table = SymbolTable::build(strings.iter().map(|s| &s[..]));
+        let mut encoded = Vec::new();
+        let mut codes = Vec::new();
+        for s in &strings {
+            codes.clear();
+            table.encode(s, &mut codes);
+            encoded.push(codes.clone());
+        }
+        let total_encoded = encoded.iter().map(Vec::len).sum::<usize>();
+        let total_bytes = strings.iter().map(String::len).sum::<usize>();
+        assert!(total_encoded * 2 < total_bytes);
+        // Bytes not covered by the symbol table are escaped
+        let mut unknown = Vec::new();
+        table.encode("\u{1F980}", &mut unknown);
+        assert_eq!(unknown.len(), 8);
+
+        let data = table.into_vec();
+        let symbols = symbols(&data);
+        for (s, codes) in strings.iter().zip(encoded) {
+            let mut decoded = Vec::new();
+            decode(&symbols, &codes, &mut decoded);
+            assert_eq!(decoded, s.as_bytes());
+        }
+        let mut decoded = Vec::new();
+        decode(&symbols, &unknown, &mut decoded);
+        assert_eq!(decoded, "\u{1F980}".as_bytes());
+    }
+}
@@ -17,6 +17,8 @@ impl IntegerColumn {
             n.shrink_to_fit();
             n
         });
+        let sorted = values.windows(2).all(|w| w[0] <= w[1]);
+        let stats = ColumnStats::new(values.len(), None, null.as_deref(), sorted);
         let original_range = Some((min, max));
         let min0 = min;
         let max0 = max;
@@ -84,7 +86,11 @@ impl IntegerColumn {
                 }
             }
         };
-        column.lz4_encode();
+        column.set_value_range(min0, max0);
+        column.set_stats(stats);
+        if !column.run_length_encode() {
+            column.lz4_encode();
+        }
         Arc::new(column)
     }
 
@@ -1,38 +1,267 @@
 use crate::mem_store::partition::ColumnLocator;
 use lru::LruCache;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
+/// Determines which column is evicted when tables exceed `mem_size_limit_tables`.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum EvictionPolicy {
+    /// Evicts the least recently used column.
+    #[default]
+    Lru,
+    /// Columns enter a probationary segment when they are loaded and move to a protected segment when they are accessed
+    /// again. Columns are evicted from the probationary segment first, so a scan that reads many columns once does not
+    /// evict columns that are used repeatedly. `protected_fraction` is the maximum fraction of columns in the protected
+    /// segment, least recently used protected columns are moved back to the probationary segment.
+    SegmentedLru { protected_fraction: f64 },
+}
+
+impl EvictionPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            EvictionPolicy::SegmentedLru { protected_fraction }
+                if !(0.0..=1.0).contains(&protected_fraction) =>
+            {
+                Err("protected_fraction must be between 0 and 1".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    /// Parses `lru` or `slru`, which protects up to 80% of columns.
+    fn from_str(s: &str) -> Result<EvictionPolicy, String> {
+        match s {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "slru" => Ok(EvictionPolicy::SegmentedLru {
+                protected_fraction: 0.8,
+            }),
+            _ => Err(format!(
+                "Invalid eviction policy {}, expected lru or slru",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Lru {
-    cache: Arc<Mutex<LruCache<ColumnLocator, ()>>>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+enum Cache {
+    Lru(LruCache<ColumnLocator, ()>),
+    SegmentedLru {
+        probation: LruCache<ColumnLocator, ()>,
+        protected: LruCache<ColumnLocator, ()>,
+        protected_fraction: f64,
+    },
 }
 
 impl Lru {
+    pub fn new(policy: EvictionPolicy) -> Lru {
+        let cache = match policy {
+            EvictionPolicy::Lru => Cache::Lru(LruCache::unbounded()),
+            EvictionPolicy::SegmentedLru { protected_fraction } => Cache::SegmentedLru {
+                probation: LruCache::unbounded(),
+                protected: LruCache::unbounded(),
+                protected_fraction,
+            },
+        };
+        Lru {
+            cache: Arc::new(Mutex::new(cache)),
+        }
+    }
+
     pub fn touch(&self, column: &ColumnLocator) {
         let mut cache = self.cache.lock().unwrap();
-        cache.get(column);
+        match &mut *cache {
+            Cache::Lru(cache) => {
+                cache.get(column);
+            }
+            Cache::SegmentedLru {
+                probation,
+                protected,
+                protected_fraction,
+            } => {
+                if probation.pop(column).is_none() {
+                    protected.get(column);
+                    return;
+                }
+                protected.put(column.clone(), ());
+                let max_protected =
+                    ((probation.len() + protected.len()) as f64 * *protected_fraction).ceil();
+                while protected.len() as f64 > max_protected {
+                    if let Some((demoted, _)) = protected.pop_lru() {
+                        probation.put(demoted, ());
+                    }
+                }
+            }
+        }
     }
 
     pub fn put(&self, column: ColumnLocator) {
         let mut cache = self.cache.lock().unwrap();
-        cache.put(column, ());
+        match &mut *cache {
+            Cache::Lru(cache) => {
+                cache.put(column, ());
+            }
+            Cache::SegmentedLru {
+                probation,
+                protected,
+                ..
+            } => {
+                if protected.get(&column).is_none() {
+                    probation.put(column, ());
+                }
+            }
+        }
     }
 
     pub fn remove(&self, column: &ColumnLocator) {
         let mut cache = self.cache.lock().unwrap();
-        cache.pop(column);
+        match &mut *cache {
+            Cache::Lru(cache) => {
+                cache.pop(column);
+            }
+            Cache::SegmentedLru {
+                probation,
+                protected,
+                ..
+            } => {
+                probation.pop(column);
+                protected.pop(column);
+            }
+        }
     }
 
     pub fn evict(&self) -> Option<ColumnLocator> {
         let mut cache = self.cache.lock().unwrap();
-        cache.pop_lru().map(|x| x.0)
+        match &mut *cache {
+            Cache::Lru(cache) => cache.pop_lru().map(|x| x.0),
+            Cache::SegmentedLru {
+                probation,
+                protected,
+                ..
+            } => probation
+                .pop_lru()
+                .or_else(|| protected.pop_lru())
+                .map(|x| x.0),
+        }
+    }
+
+    /// Re-keys all columns of table `old` to table `new`, preserving their order of eviction.
+    pub fn rename_table(&self, old: &str, new: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        match &mut *cache {
+            Cache::Lru(cache) => rename_in(cache, old, new),
+            Cache::SegmentedLru {
+                probation,
+                protected,
+                ..
+            } => {
+                rename_in(probation, old, new);
+                rename_in(protected, old, new);
+            }
+        }
+    }
+
+    /// Evicts the column of `table` that would be evicted first by `evict`.
+    pub fn evict_from(&self, table: &str) -> Option<ColumnLocator> {
+        let mut cache = self.cache.lock().unwrap();
+        match &mut *cache {
+            Cache::Lru(cache) => pop_lru_of_table(cache, table),
+            Cache::SegmentedLru {
+                probation,
+                protected,
+                ..
+            } => pop_lru_of_table(probation, table).or_else(|| pop_lru_of_table(protected, table)),
+        }
+    }
+}
+
+fn pop_lru_of_table(cache: &mut LruCache<ColumnLocator, ()>, table: &str) -> Option<ColumnLocator> {
+    let column = cache
+        .iter()
+        .rev()
+        .map(|(column, _)| column)
+        .find(|column| column.table == table)?
+        .clone();
+    cache.pop(&column);
+    Some(column)
+}
+
+fn rename_in(cache: &mut LruCache<ColumnLocator, ()>, old: &str, new: &str) {
+    // Entries are collected from least to most recently used, so reinserting them preserves their relative order
+    let columns = cache
+        .iter()
+        .rev()
+        .map(|(column, _)| column)
+        .filter(|column| column.table == old)
+        .cloned()
+        .collect::<Vec<_>>();
+    for column in columns {
+        cache.pop(&column);
+        cache.put(ColumnLocator::new(new, column.id, &column.column), ());
     }
 }
 
 impl Default for Lru {
     fn default() -> Lru {
-        Lru {
-            cache: Arc::new(Mutex::new(LruCache::unbounded())),
+        Lru::new(EvictionPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segmented_lru_resists_scans() {
+        let lru = Lru::new(EvictionPolicy::SegmentedLru {
+            protected_fraction: 0.5,
+        });
+        let column = |id| ColumnLocator::new("table", id, "column");
+        lru.put(column(0));
+        lru.touch(&column(0));
+        // A scan loads each column once
+        for id in 1..4 {
+            lru.put(column(id));
         }
+        assert_eq!(lru.evict(), Some(column(1)));
+        assert_eq!(lru.evict(), Some(column(2)));
+        assert_eq!(lru.evict(), Some(column(3)));
+        assert_eq!(lru.evict(), Some(column(0)));
+        assert_eq!(lru.evict(), None);
+    }
+
+    #[test]
+    fn test_evict_from_table() {
+        let lru = Lru::default();
+        lru.put(ColumnLocator::new("a", 0, "column"));
+        lru.put(ColumnLocator::new("b", 0, "column"));
+        lru.put(ColumnLocator::new("a", 1, "column"));
+        assert_eq!(
+            lru.evict_from("b"),
+            Some(ColumnLocator::new("b", 0, "column"))
+        );
+        assert_eq!(lru.evict_from("b"), None);
+        assert_eq!(lru.evict(), Some(ColumnLocator::new("a", 0, "column")));
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let lru = Lru::default();
+        lru.put(ColumnLocator::new("a", 0, "column"));
+        lru.put(ColumnLocator::new("b", 0, "column"));
+        lru.put(ColumnLocator::new("a", 1, "column"));
+        lru.rename_table("a", "c");
+        assert_eq!(lru.evict_from("a"), None);
+        assert_eq!(lru.evict(), Some(ColumnLocator::new("b", 0, "column")));
+        assert_eq!(lru.evict(), Some(ColumnLocator::new("c", 0, "column")));
+        assert_eq!(lru.evict(), Some(ColumnLocator::new("c", 1, "column")));
     }
 }
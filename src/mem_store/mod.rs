@@ -1,7 +1,12 @@
+pub mod bloom;
 pub mod codec;
 pub mod column;
 pub mod column_builder;
+pub mod compaction;
+pub mod dedup;
+pub mod dictionary;
 pub mod floats;
+pub mod fsst;
 pub mod integers;
 pub(crate) mod lru;
 #[cfg(feature = "enable_lz4")]
@@ -9,17 +14,23 @@ pub mod lz4;
 mod mixed_column;
 pub mod partition;
 pub mod raw_col;
+pub mod schema;
 pub mod strings;
 pub mod table;
 pub mod tree;
 pub mod value;
+pub mod view;
 
 pub use self::codec::{Codec, CodecOp};
-pub use self::column::{Column, DataSection, DataSource};
-pub use self::lru::Lru;
+pub use self::column::{Column, ColumnSlice, ColumnStats, DataSection, DataSource, RunLengthValues};
+pub use self::compaction::CompactionPolicy;
+pub use self::dedup::Deduplication;
+pub use self::lru::{EvictionPolicy, Lru};
+pub use self::schema::{ColumnSchema, ColumnType, SchemaEnforcement, TableSchema};
 pub use self::table::TableStats;
 pub use self::tree::*;
 pub use self::value::Val;
+pub use self::view::MaterializedView;
 
 #[cfg(not(feature = "enable_lz4"))]
 pub mod lz4 {
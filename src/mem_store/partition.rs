@@ -3,9 +3,12 @@ use std::collections::HashSet;
 use std::ops::Range;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std_semaphore::Semaphore;
 
 use crate::disk_store::*;
 use crate::ingest::buffer::Buffer;
+use crate::mem_store::bloom::BloomFilter;
+use crate::mem_store::dictionary::SharedDictionary;
 use crate::mem_store::*;
 use crate::perf_counter::QueryPerfCounter;
 use crate::scheduler::disk_read_scheduler::DiskReadScheduler;
@@ -24,6 +27,8 @@ pub struct Partition {
     total_size_bytes: usize,
     // Column name -> PartitionID -> ColumnHandle
     pub(crate) cols: HashMap<String, ColumnHandle>,
+    // Bloom filters of string columns, which remain available when the columns are not resident
+    bloom_filters: HashMap<String, BloomFilter>,
     lru: Lru,
 }
 
@@ -54,6 +59,7 @@ impl Partition {
                 range: offset..(offset + len),
                 total_size_bytes,
                 cols,
+                bloom_filters: HashMap::new(),
                 lru,
             },
             keys,
@@ -74,6 +80,8 @@ impl Partition {
                     table,
                     md.id,
                     name.clone(),
+                    md.column_ranges.get(name).copied(),
+                    md.column_stats.get(name).copied(),
                 ),
             );
         }
@@ -81,17 +89,21 @@ impl Partition {
             id: md.id,
             range,
             cols,
+            bloom_filters: md.bloom_filters.clone(),
             lru,
             total_size_bytes,
         }
     }
 
+    /// Builds a partition from buffered rows. String columns with an entry in `dictionaries` are encoded with the
+    /// table's shared dictionary for that column.
     pub fn from_buffer(
         table: &str,
         id: PartitionID,
         buffer: Buffer,
         lru: Lru,
         offset: usize,
+        dictionaries: &mut HashMap<String, SharedDictionary>,
     ) -> (Partition, Vec<(u64, String)>) {
         Partition::new(
             table,
@@ -99,29 +111,106 @@ impl Partition {
             buffer
                 .buffer
                 .into_iter()
-                .map(|(name, raw_col)| raw_col.finalize(&name))
+                .map(|(name, raw_col)| {
+                    let dictionary = dictionaries.get_mut(&name);
+                    raw_col.finalize(&name, dictionary)
+                })
                 .collect(),
             lru,
             offset,
         )
     }
 
+    pub fn with_bloom_filters(mut self, bloom_filters: HashMap<String, BloomFilter>) -> Partition {
+        self.bloom_filters = bloom_filters;
+        self
+    }
+
+    /// Creates a copy of this partition for a renamed table that shares all resident columns.
+    pub fn renamed(&self, table: &str) -> Partition {
+        let cols = self
+            .cols
+            .iter()
+            .map(|(name, handle)| {
+                let handle = match &*handle.try_get() {
+                    Some(col) if handle.is_resident() => {
+                        ColumnHandle::resident(table, self.id, col.clone())
+                    }
+                    _ => ColumnHandle::non_resident(
+                        table,
+                        self.id,
+                        name.clone(),
+                        handle.value_range,
+                        handle.stats,
+                    ),
+                };
+                (name.clone(), handle)
+            })
+            .collect();
+        Partition {
+            id: self.id,
+            range: self.range.clone(),
+            total_size_bytes: self.total_size_bytes,
+            cols,
+            bloom_filters: self.bloom_filters.clone(),
+            lru: self.lru.clone(),
+        }
+    }
+
     pub fn get_cols(
         &self,
         referenced_cols: &HashSet<String>,
         drs: &DiskReadScheduler,
+        read_limit: Option<&Semaphore>,
         perf_counter: &QueryPerfCounter,
     ) -> HashMap<String, Arc<dyn DataSource>> {
         let mut columns = HashMap::<String, Arc<dyn DataSource>>::new();
         for colname in referenced_cols {
             if let Some(handle) = self.cols.get(colname) {
-                let column = drs.get_or_load(handle, &self.cols, perf_counter);
+                let column = drs.get_or_load(handle, &self.cols, read_limit, perf_counter);
+                perf_counter.scanned_bytes(column.heap_size_of_children() as u64);
                 columns.insert(handle.name().to_string(), Arc::new(column));
             }
         }
         columns
     }
 
+    /// Reads referenced columns that are not resident from disk, skipping columns that are already being loaded.
+    pub fn prefetch_cols(
+        &self,
+        referenced_cols: &HashSet<String>,
+        drs: &DiskReadScheduler,
+        read_limit: Option<&Semaphore>,
+        perf_counter: &QueryPerfCounter,
+    ) {
+        for colname in referenced_cols {
+            if let Some(handle) = self.cols.get(colname) {
+                drs.prefetch_column(handle, &self.cols, read_limit, perf_counter);
+            }
+        }
+    }
+
+    /// Minimum and maximum value of an integer column, `None` if the column is absent or not an integer column.
+    pub fn value_range(&self, column: &str) -> Option<(i64, i64)> {
+        self.cols.get(column).and_then(|handle| handle.value_range)
+    }
+
+    /// Number of distinct and null values of `column`, `None` if the column is absent or its statistics are unknown.
+    pub fn column_stats(&self, column: &str) -> Option<ColumnStats> {
+        self.cols.get(column).and_then(|handle| handle.stats)
+    }
+
+    /// Whether `column` may contain the string `value`. Always true for columns without a bloom filter.
+    pub fn may_contain(&self, column: &str, value: &str) -> bool {
+        self.bloom_filters
+            .get(column)
+            .map_or(true, |filter| filter.may_contain(value))
+    }
+
+    pub fn bloom_filters(&self) -> &HashMap<String, BloomFilter> {
+        &self.bloom_filters
+    }
+
     pub fn col_names(&self) -> impl Iterator<Item = &String> {
         self.cols.keys()
     }
@@ -136,7 +225,19 @@ impl Partition {
         non_residents
     }
 
-    pub fn nonresidents_match(
+    /// Whether any range of rows of the columns `cols` can be scanned without decoding the preceding rows, which allows
+    /// the partition to be split into morsels. Columns that are not resident are assumed not to support this.
+    pub fn is_sliceable(&self, cols: &HashSet<String>) -> bool {
+        cols.iter().all(|colname| match self.cols.get(colname) {
+            Some(handle) => handle
+                .try_get()
+                .as_ref()
+                .map_or(false, |column| column.codec().row_sections().is_some()),
+            None => true,
+        })
+    }
+
+        pub fn nonresidents_match(
         &self,
         nonresidents: &HashSet<String>,
         eligible: &HashSet<String>,
@@ -264,6 +365,8 @@ pub struct ColumnHandle {
     resident: AtomicBool,
     load_scheduled: AtomicBool,
     col: Mutex<Option<Arc<Column>>>,
+    value_range: Option<(i64, i64)>,
+    stats: Option<ColumnStats>,
 }
 
 impl ColumnHandle {
@@ -278,6 +381,8 @@ impl ColumnHandle {
             size_bytes: AtomicUsize::new(col.heap_size_of_children()),
             resident: AtomicBool::new(true),
             load_scheduled: AtomicBool::new(false),
+            value_range: col.value_range(),
+            stats: col.stats(),
             col: Mutex::new(Some(col)),
         }
     }
@@ -286,6 +391,8 @@ impl ColumnHandle {
         table: &str,
         id: PartitionID,
         name: String,
+        value_range: Option<(i64, i64)>,
+        stats: Option<ColumnStats>,
     ) -> ColumnHandle {
         ColumnHandle {
             key: ColumnLocator::new(table, id, &name),
@@ -294,6 +401,8 @@ impl ColumnHandle {
             resident: AtomicBool::new(false),
             load_scheduled: AtomicBool::new(false),
             col: Mutex::new(None),
+            value_range,
+            stats,
         }
     }
 
@@ -310,6 +419,15 @@ impl ColumnHandle {
         self.load_scheduled.load(Ordering::SeqCst)
     }
 
+    /// Marks the column as scheduled for loading. Returns false if a load was already scheduled.
+    pub fn schedule_load(&self) -> bool {
+        !self.load_scheduled.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn unschedule_load(&self) {
+        self.load_scheduled.store(false, Ordering::SeqCst)
+    }
+
     pub fn key(&self) -> &ColumnLocator {
         &self.key
     }
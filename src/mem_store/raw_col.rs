@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::iter::repeat;
 use std::mem;
 use std::ops::BitOr;
@@ -7,7 +8,10 @@ use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
 use crate::ingest::raw_val::RawVal;
+use crate::mem_store::bloom::BloomFilter;
 use crate::mem_store::column_builder::*;
+use crate::mem_store::dictionary::SharedDictionary;
+use crate::mem_store::strings::build_shared_dictionary_column;
 use crate::mem_store::*;
 
 // Can eliminate this? Used by in-memory buffer.
@@ -55,7 +59,8 @@ impl MixedCol {
         self.data.len()
     }
 
-    pub fn finalize(self, name: &str) -> Arc<Column> {
+    /// Builds a column from the values. String columns are encoded with `dictionary` if given and if the values fit.
+    pub fn finalize(self, name: &str, dictionary: Option<&mut SharedDictionary>) -> Arc<Column> {
         let present =  if self.types.contains_null {
             let mut present = vec![0u8; (self.data.len() + 7) / 8];
             for (i, v) in self.data.iter().enumerate() {
@@ -68,6 +73,27 @@ impl MixedCol {
             None
         };
         if self.types.contains_string {
+            if let Some(dictionary) = dictionary {
+                let strings = self
+                    .data
+                    .iter()
+                    .map(|v| match v {
+                        RawVal::Str(s) => s.clone(),
+                        RawVal::Int(i) => i.to_string(),
+                        RawVal::Null => String::new(),
+                        RawVal::Float(f) => f.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(column) = build_shared_dictionary_column(
+                    name,
+                    strings.iter().map(String::as_str),
+                    strings.len(),
+                    present.clone(),
+                    dictionary,
+                ) {
+                    return column;
+                }
+            }
             let mut builder = StringColBuilder::default();
             for v in self.data {
                 match v {
@@ -105,6 +131,20 @@ impl MixedCol {
         }
     }
 
+    /// Bloom filter over the non-null values of a string column, or `None` if the column contains no strings.
+    pub fn bloom_filter(&self) -> Option<BloomFilter> {
+        if !self.types.contains_string {
+            return None;
+        }
+        let values = self.data.iter().filter_map(|v| match v {
+            RawVal::Str(s) => Some(Cow::Borrowed(s.as_str())),
+            RawVal::Int(i) => Some(Cow::Owned(i.to_string())),
+            RawVal::Null => None,
+            RawVal::Float(f) => Some(Cow::Owned(f.to_string())),
+        });
+        Some(BloomFilter::from_strings(values))
+    }
+
     pub fn heap_size_of_children(&self) -> usize {
         let data_size = self
             .data
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ingest::raw_val::RawVal;
+use crate::QueryError;
+
+/// Column names and types declared with `CREATE TABLE`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TableSchema {
+    pub columns: Vec<ColumnSchema>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub nullable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    String,
+}
+
+/// How ingestion handles rows that do not conform to the declared schema of a table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub enum SchemaEnforcement {
+    /// Rows are ingested unchanged and columns with conflicting types are stored as mixed columns.
+    #[default]
+    Permissive,
+    /// Rows that do not conform to the schema are dropped.
+    Reject,
+    /// Rows that do not conform to the schema are ingested into the given table instead.
+    DeadLetter(String),
+}
+
+impl TableSchema {
+    pub fn new(columns: Vec<ColumnSchema>) -> Result<TableSchema, QueryError> {
+        for (i, column) in columns.iter().enumerate() {
+            if columns[..i].iter().any(|c| c.name == column.name) {
+                bail!(
+                    QueryError::SchemaError,
+                    "Column {} is declared more than once",
+                    column.name
+                );
+            }
+        }
+        Ok(TableSchema { columns })
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Checks that `value` can be stored in column `name`.
+    pub fn validate(&self, name: &str, value: &RawVal) -> Result<(), QueryError> {
+        let column = match self.column(name) {
+            Some(column) => column,
+            None => bail!(QueryError::SchemaError, "Column {} is not declared", name),
+        };
+        let valid = match (value, column.column_type) {
+            (RawVal::Null, _) => column.nullable,
+            (RawVal::Int(_), ColumnType::Integer | ColumnType::Float) => true,
+            (RawVal::Float(_), ColumnType::Float) => true,
+            (RawVal::Float(float), ColumnType::Integer) => integral(float.0).is_some(),
+            (RawVal::Str(_), ColumnType::String) => true,
+            _ => false,
+        };
+        if !valid {
+            bail!(
+                QueryError::SchemaError,
+                "Value {} is not valid for column {} of type {}",
+                value,
+                name,
+                column.type_name()
+            );
+        }
+        Ok(())
+    }
+
+    /// Converts integral floats in `INTEGER` columns to integers.
+    /// Clients that only send floats, like the logging client, can then ingest into `INTEGER` columns.
+    pub fn coerce(&self, name: &str, value: RawVal) -> RawVal {
+        match (&value, self.column(name).map(|c| c.column_type)) {
+            (RawVal::Float(float), Some(ColumnType::Integer)) => match integral(float.0) {
+                Some(int) => RawVal::Int(int),
+                None => value,
+            },
+            _ => value,
+        }
+    }
+
+    /// Checks that all values of a row can be stored in their columns and that no `NOT NULL` column is missing.
+    /// Null values are treated like missing values, even for columns that are not declared.
+    pub fn validate_row<'a>(
+        &self,
+        row: impl IntoIterator<Item = (&'a str, &'a RawVal)>,
+    ) -> Result<(), QueryError> {
+        let mut present = Vec::new();
+        for (name, value) in row {
+            if *value != RawVal::Null {
+                self.validate(name, value)?;
+                present.push(name);
+            }
+        }
+        for column in &self.columns {
+            if !column.nullable && !present.contains(&column.name.as_str()) {
+                bail!(
+                    QueryError::SchemaError,
+                    "Missing value for column {} of type {}",
+                    column.name,
+                    column.type_name()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn integral(float: f64) -> Option<i64> {
+    if float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 {
+        Some(float as i64)
+    } else {
+        None
+    }
+}
+
+impl ColumnSchema {
+    pub fn new(name: &str, column_type: ColumnType) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            column_type,
+            nullable: true,
+        }
+    }
+
+    pub fn not_null(mut self) -> ColumnSchema {
+        self.nullable = false;
+        self
+    }
+
+    fn type_name(&self) -> String {
+        let name = match self.column_type {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Float => "DOUBLE",
+            ColumnType::String => "VARCHAR",
+        };
+        if self.nullable {
+            name.to_string()
+        } else {
+            format!("{} NOT NULL", name)
+        }
+    }
+}
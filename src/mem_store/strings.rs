@@ -2,6 +2,8 @@ use hex;
 use seahash::SeaHasher;
 
 use crate::engine::data_types::*;
+use crate::mem_store::dictionary::SharedDictionary;
+use crate::mem_store::fsst;
 use crate::mem_store::*;
 use crate::stringpack::*;
 use std::collections::hash_set::HashSet;
@@ -15,6 +17,7 @@ type HashMapSea<K, V> = HashMap<K, V, BuildHasherDefault<SeaHasher>>;
 type HashSetSea<K> = HashSet<K, BuildHasherDefault<SeaHasher>>;
 
 const DICTIONARY_RATIO: usize = 2;
+const FSST_MIN_REDUCTION: usize = 30;
 
 pub fn fast_build_string_column<'a, T>(
     name: &str,
@@ -28,35 +31,50 @@ pub fn fast_build_string_column<'a, T>(
 where
     T: Iterator<Item = &'a str> + Clone,
 {
+    let sorted = strings
+        .clone()
+        .zip(strings.clone().skip(1))
+        .all(|(a, b)| a <= b);
     let mut unique_values = HashSetSea::default();
     for s in strings.clone() {
         unique_values.insert(s);
         // PERF: is 2 the right constant? and should probably also depend on the length of the strings
         // TODO(#103): len > 1000 || name == "string_packed" is a hack to make tests use dictionary encoding. Remove once we are able to group by string packed columns.
         if unique_values.len() == len / DICTIONARY_RATIO {
-            let (mut codec, data) = if (lhex || uhex) && total_bytes / len > 5 {
+            let stats =
+                ColumnStats::new(len, Some(unique_values.len()), present.as_deref(), sorted);
+            let (mut codec, mut data_sections) = if (lhex || uhex) && total_bytes / len > 5 {
                 let packed = PackedBytes::from_iterator(strings.map(|s| hex::decode(s).unwrap()));
                 (
                     vec![CodecOp::UnhexpackStrings(uhex, total_bytes)],
-                    DataSection::U8(packed.into_vec()),
+                    vec![DataSection::U8(packed.into_vec())],
+                )
+            } else if let Some((codes, symbols)) = fsst_encode(strings.clone(), total_bytes) {
+                (
+                    vec![CodecOp::PushDataSection(1), CodecOp::Fsst(total_bytes)],
+                    vec![DataSection::U8(codes), DataSection::U8(symbols)],
                 )
             } else {
                 let packed = PackedStrings::from_iterator(strings);
-                (string_pack_codec(), DataSection::U8(packed.into_vec()))
+                (
+                    string_pack_codec(),
+                    vec![DataSection::U8(packed.into_vec())],
+                )
             };
-            let mut column = if let Some(present) = present {
-                codec.push(CodecOp::PushDataSection(1));
+            if let Some(present) = present {
+                codec.push(CodecOp::PushDataSection(data_sections.len()));
                 codec.push(CodecOp::Nullable);
-                Column::new(name, len, None, codec, vec![data, DataSection::Bitvec(present)])
-            } else {
-                Column::new(name, len, None, codec, vec![data])
-            };
+                data_sections.push(DataSection::Bitvec(present));
+            }
+            let mut column = Column::new(name, len, None, codec, data_sections);
+            column.set_stats(stats);
             column.lz4_encode();
             return Arc::new(column);
         }
     }
 
     let dict_size = unique_values.len();
+    let stats = ColumnStats::new(len, Some(dict_size), present.as_deref(), sorted);
     let mut mapping = unique_values.into_iter().collect::<Vec<_>>();
     mapping.sort_unstable();
     let mut packed_mapping = IndexedPackedStrings::default();
@@ -125,10 +143,79 @@ where
         data_sections.push(DataSection::Bitvec(present));
     }
     let mut column = Column::new(name, len, range, codec, data_sections);
-    column.lz4_encode();
+    column.set_stats(stats);
+    if !column.run_length_encode() {
+        column.lz4_encode();
+    }
     Arc::new(column)
 }
 
+/// Encodes `strings` as codes into the shared `dictionary`, which is copied into the column.
+/// Returns `None` if the strings do not fit into the dictionary.
+pub fn build_shared_dictionary_column<'a>(
+    name: &str,
+    strings: impl Iterator<Item = &'a str> + Clone,
+    len: usize,
+    present: Option<Vec<u8>>,
+    dictionary: &mut SharedDictionary,
+) -> Option<Arc<Column>> {
+    let codes = dictionary.encode(strings)?;
+    let dict_size = dictionary.len();
+    let sections = dictionary.sections();
+    let (mut codec, mut data_sections) = if dict_size <= Into::<usize>::into(u8::MAX) {
+        (
+            shared_dict_codec(EncodingType::U8),
+            vec![DataSection::U8(
+                codes.into_iter().map(|c| c as u8).collect(),
+            )],
+        )
+    } else {
+        (
+            shared_dict_codec(EncodingType::U16),
+            vec![DataSection::U16(
+                codes.into_iter().map(|c| c as u16).collect(),
+            )],
+        )
+    };
+    data_sections.push(DataSection::U64(sections.offset_len));
+    data_sections.push(DataSection::U8(sections.backing_store));
+    if let Some(present) = present {
+        codec.insert(0, CodecOp::PushDataSection(3));
+        codec.insert(1, CodecOp::Nullable);
+        data_sections.push(DataSection::Bitvec(present));
+    }
+    let mut column = Column::new(name, len, Some((0, dict_size as i64)), codec, data_sections);
+    column.lz4_encode();
+    Some(Arc::new(column))
+}
+
+/// FSST compresses `strings` if that reduces their size by at least `FSST_MIN_REDUCTION` percent.
+/// Returns the encoded strings and the serialized symbol table.
+fn fsst_encode<'a>(
+    strings: impl Iterator<Item = &'a str> + Clone,
+    total_bytes: usize,
+) -> Option<(Vec<u8>, Vec<u8>)> {
+    let table = fsst::SymbolTable::build(strings.clone());
+    let mut codes = Vec::new();
+    let mut encoded = Vec::with_capacity(total_bytes);
+    for s in strings {
+        codes.clear();
+        table.encode(s, &mut codes);
+        let mut len = codes.len();
+        while len > 254 {
+            encoded.push(255);
+            len -= 255;
+        }
+        encoded.push(len as u8);
+        encoded.extend_from_slice(&codes);
+        if encoded.len() * 100 > total_bytes * (100 - FSST_MIN_REDUCTION) {
+            return None;
+        }
+    }
+    encoded.shrink_to_fit();
+    Some((encoded, table.into_vec()))
+}
+
 pub fn dict_codec(index_type: EncodingType) -> Vec<CodecOp> {
     vec![
         CodecOp::PushDataSection(1),
@@ -137,6 +224,14 @@ pub fn dict_codec(index_type: EncodingType) -> Vec<CodecOp> {
     ]
 }
 
+pub fn shared_dict_codec(index_type: EncodingType) -> Vec<CodecOp> {
+    vec![
+        CodecOp::PushDataSection(1),
+        CodecOp::PushDataSection(2),
+        CodecOp::SharedDictLookup(index_type),
+    ]
+}
+
 pub fn string_pack_codec() -> Vec<CodecOp> {
     vec![CodecOp::UnpackStrings]
 }
@@ -3,17 +3,22 @@ use std::ops::{DerefMut, Range};
 use std::str;
 use std::sync::atomic::{AtomicU64, AtomicUsize};
 use std::sync::Arc;
-use std::sync::{Mutex, RwLock};
+use std::sync::{Mutex, MutexGuard, RwLock};
 
 use itertools::Itertools;
 
 use crate::disk_store::storage::{Storage, WALSegment};
 use crate::disk_store::*;
+use crate::engine::ColumnStatistics;
 use crate::ingest::buffer::Buffer;
 use crate::ingest::input_column::InputColumn;
 use crate::ingest::raw_val::RawVal;
 use crate::logging_client::ColumnData;
+use crate::mem_store::bloom::BloomFilter;
+use crate::mem_store::dedup::SeenKeys;
+use crate::mem_store::dictionary::SharedDictionary;
 use crate::mem_store::partition::{ColumnLocator, Partition};
+use crate::mem_store::raw_col::MixedCol;
 use crate::mem_store::*;
 
 pub struct Table {
@@ -27,6 +32,24 @@ pub struct Table {
 
     // Set of every column name that is present in any partition
     column_names: RwLock<HashSet<String>>,
+    // Declared schema if the table was created with `CREATE TABLE`
+    schema: RwLock<Option<TableSchema>>,
+    // How ingestion handles rows that do not conform to the declared schema
+    schema_enforcement: RwLock<SchemaEnforcement>,
+    // Partitions written by backfill ingestion, which are never compacted
+    backfill_partitions: RwLock<HashSet<PartitionID>>,
+    // Deduplication settings and the keys of recently ingested rows
+    deduplication: Mutex<Option<(Deduplication, SeenKeys)>>,
+    // Dictionaries shared by all partitions of string columns that use shared dictionary encoding
+    shared_dictionaries: Mutex<HashMap<String, SharedDictionary>>,
+    // String columns for which each new partition records a bloom filter
+    bloom_filter_columns: RwLock<HashSet<String>>,
+    // Column by which compaction sorts rows
+    sort_key: RwLock<Option<String>>,
+    // Compaction policy that overrides the default policy of the database
+    compaction_policy: RwLock<Option<CompactionPolicy>>,
+    // Maximum bytes of resident columns before columns of this table are evicted
+    memory_limit: RwLock<Option<usize>>,
 }
 
 impl Table {
@@ -39,13 +62,179 @@ impl Table {
             buffer: Mutex::new(Buffer::default()),
             lru,
             column_names: RwLock::default(),
+            schema: RwLock::default(),
+            schema_enforcement: RwLock::default(),
+            backfill_partitions: RwLock::default(),
+            deduplication: Mutex::default(),
+            shared_dictionaries: Mutex::default(),
+            bloom_filter_columns: RwLock::default(),
+            sort_key: RwLock::default(),
+            compaction_policy: RwLock::default(),
+            memory_limit: RwLock::default(),
         }
     }
 
+    pub fn with_schema(name: &str, lru: Lru, schema: TableSchema) -> Table {
+        let table = Table::new(name, lru);
+        table
+            .column_names
+            .write()
+            .unwrap()
+            .extend(schema.columns.iter().map(|column| column.name.clone()));
+        *table.schema.write().unwrap() = Some(schema);
+        table
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// Moves all partitions and buffered rows into a new table with a different name.
+    pub fn renamed(self, name: &str) -> Table {
+        let partitions = self
+            .partitions
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|(id, partition)| (id, Arc::new(partition.renamed(name))))
+            .collect();
+        self.lru.rename_table(&self.name, name);
+        let table = Table {
+            name: name.to_string(),
+            partitions: RwLock::new(partitions),
+            next_partition_id: self.next_partition_id,
+            next_partition_offset: self.next_partition_offset,
+            buffer: self.buffer,
+            lru: self.lru,
+            column_names: self.column_names,
+            schema: self.schema,
+            schema_enforcement: self.schema_enforcement,
+            backfill_partitions: self.backfill_partitions,
+            deduplication: self.deduplication,
+            shared_dictionaries: self.shared_dictionaries,
+            bloom_filter_columns: self.bloom_filter_columns,
+            sort_key: self.sort_key,
+            compaction_policy: self.compaction_policy,
+            memory_limit: self.memory_limit,
+        };
+        table
+    }
+
+    pub fn schema(&self) -> Option<TableSchema> {
+        self.schema.read().unwrap().clone()
+    }
+
+    pub fn schema_enforcement(&self) -> SchemaEnforcement {
+        self.schema_enforcement.read().unwrap().clone()
+    }
+
+    pub fn set_schema_enforcement(&self, enforcement: SchemaEnforcement) {
+        *self.schema_enforcement.write().unwrap() = enforcement;
+    }
+
+    pub fn deduplication(&self) -> Option<Deduplication> {
+        let deduplication = self.deduplication.lock().unwrap();
+        deduplication.as_ref().map(|(settings, _)| settings.clone())
+    }
+
+    /// Replaces the deduplication settings and forgets all previously seen keys.
+    pub fn set_deduplication(&self, deduplication: Option<Deduplication>) {
+        *self.deduplication.lock().unwrap() =
+            deduplication.map(|settings| (settings, SeenKeys::default()));
+    }
+
+    /// Columns that are encoded with a shared dictionary.
+    pub fn shared_dictionary_columns(&self) -> Vec<String> {
+        let dictionaries = self.shared_dictionaries.lock().unwrap();
+        dictionaries.keys().cloned().sorted().collect()
+    }
+
+    /// Enables or disables shared dictionary encoding for `column`. Only affects partitions created afterwards.
+    pub fn set_shared_dictionary(&self, column: &str, enabled: bool) {
+        let mut dictionaries = self.shared_dictionaries.lock().unwrap();
+        if enabled {
+            dictionaries.entry(column.to_string()).or_default();
+        } else {
+            dictionaries.remove(column);
+        }
+    }
+
+    pub(crate) fn shared_dictionaries(&self) -> MutexGuard<HashMap<String, SharedDictionary>> {
+        self.shared_dictionaries.lock().unwrap()
+    }
+
+    pub fn sort_key(&self) -> Option<String> {
+        self.sort_key.read().unwrap().clone()
+    }
+
+    /// Sets the column by which compaction sorts rows. Partitions that have already been compacted keep their order.
+    pub fn set_sort_key(&self, sort_key: Option<String>) {
+        *self.sort_key.write().unwrap() = sort_key;
+    }
+
+    pub fn compaction_policy(&self) -> Option<CompactionPolicy> {
+        self.compaction_policy.read().unwrap().clone()
+    }
+
+    /// Overrides the compaction policy of the database for this table, or restores the default if `policy` is `None`.
+    pub fn set_compaction_policy(&self, policy: Option<CompactionPolicy>) {
+        *self.compaction_policy.write().unwrap() = policy;
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        *self.memory_limit.read().unwrap()
+    }
+
+    /// Sets the maximum bytes of resident columns of this table, or removes the limit if `limit` is `None`.
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        *self.memory_limit.write().unwrap() = limit;
+    }
+
+    /// Columns for which partitions record a bloom filter.
+    pub fn bloom_filter_columns(&self) -> Vec<String> {
+        let columns = self.bloom_filter_columns.read().unwrap();
+        columns.iter().cloned().sorted().collect()
+    }
+
+    /// Enables or disables bloom filters for `column`. Only affects partitions created afterwards.
+    /// Returns the set of columns with bloom filters.
+    pub fn set_bloom_filter(&self, column: &str, enabled: bool) -> HashSet<String> {
+        let mut columns = self.bloom_filter_columns.write().unwrap();
+        if enabled {
+            columns.insert(column.to_string());
+        } else {
+            columns.remove(column);
+        }
+        columns.clone()
+    }
+
+    /// Builds the bloom filter of `column` from its values if the table records one for the column.
+    pub(crate) fn bloom_filter(&self, column: &str, values: &MixedCol) -> Option<BloomFilter> {
+        if self.bloom_filter_columns.read().unwrap().contains(column) {
+            values.bloom_filter()
+        } else {
+            None
+        }
+    }
+
+    /// Records the key of each row and returns whether the row should be ingested, i.e. whether its key has not been
+    /// seen before. All rows are ingested if the table does not deduplicate rows.
+    pub(crate) fn first_occurrences(&self, keys: Vec<RawVal>) -> Vec<bool> {
+        match self.deduplication.lock().unwrap().as_mut() {
+            Some((_, seen)) => keys.into_iter().map(|key| seen.insert(key)).collect(),
+            None => vec![true; keys.len()],
+        }
+    }
+
+    /// Forgets keys recorded by `first_occurrences` for rows that were not ingested after all.
+    pub(crate) fn forget_keys(&self, keys: &[RawVal]) {
+        if let Some((_, seen)) = self.deduplication.lock().unwrap().as_mut() {
+            for key in keys {
+                seen.remove(key);
+            }
+        }
+    }
+
     pub fn snapshot(&self) -> Vec<Arc<Partition>> {
         let partitions = self.partitions.read().unwrap();
         let mut partitions: Vec<_> = partitions.values().cloned().collect();
@@ -59,6 +248,7 @@ impl Table {
                     buffer.clone(),
                     self.lru.clone(),
                     offset,
+                    &mut self.shared_dictionaries.lock().unwrap(),
                 )
                 .0,
             ));
@@ -66,6 +256,33 @@ impl Table {
         partitions
     }
 
+    /// Returns rows that have not been written to a partition yet.
+    pub fn buffered(&self) -> Option<Arc<Partition>> {
+        let buffer = self.buffer.lock().unwrap();
+        if buffer.len() == 0 {
+            return None;
+        }
+        let offset = self
+            .next_partition_offset
+            .load(std::sync::atomic::Ordering::SeqCst);
+        Some(Arc::new(
+            Partition::from_buffer(
+                self.name(),
+                u64::MAX,
+                buffer.clone(),
+                self.lru.clone(),
+                offset,
+                &mut self.shared_dictionaries.lock().unwrap(),
+            )
+            .0,
+        ))
+    }
+
+    /// Returns all partitions, excluding rows that are still buffered.
+    pub fn partitions(&self) -> Vec<Arc<Partition>> {
+        self.partitions.read().unwrap().values().cloned().collect()
+    }
+
     pub fn snapshot_parts(&self, parts: &[PartitionID]) -> Vec<Arc<Partition>> {
         let partitions = self.partitions.read().unwrap();
         let mut partitions: Vec<_> = parts.iter().map(|id| partitions[id].clone()).collect();
@@ -79,6 +296,7 @@ impl Table {
                     buffer.clone(),
                     self.lru.clone(),
                     offset,
+                    &mut self.shared_dictionaries.lock().unwrap(),
                 )
                 .0,
             ));
@@ -92,6 +310,54 @@ impl Table {
         lru: &Lru,
     ) -> HashMap<String, Table> {
         let mut tables = HashMap::new();
+        let meta_store = storage.meta_store().read().unwrap();
+        for (name, schema) in &meta_store.schemas {
+            let table = Table::with_schema(name, lru.clone(), schema.clone());
+            if let Some(enforcement) = meta_store.schema_enforcement.get(name) {
+                table.set_schema_enforcement(enforcement.clone());
+            }
+            tables.insert(name.clone(), table);
+        }
+        for (name, deduplication) in &meta_store.deduplication {
+            tables
+                .entry(name.clone())
+                .or_insert_with(|| Table::new(name, lru.clone()))
+                .set_deduplication(Some(deduplication.clone()));
+        }
+        for (name, dictionaries) in &meta_store.shared_dictionaries {
+            let table = tables
+                .entry(name.clone())
+                .or_insert_with(|| Table::new(name, lru.clone()));
+            *table.shared_dictionaries.lock().unwrap() = dictionaries
+                .iter()
+                .map(|(column, strings)| (column.clone(), SharedDictionary::from_strings(strings)))
+                .collect();
+        }
+        for (name, columns) in &meta_store.bloom_filter_columns {
+            let table = tables
+                .entry(name.clone())
+                .or_insert_with(|| Table::new(name, lru.clone()));
+            *table.bloom_filter_columns.write().unwrap() = columns.clone();
+        }
+        for (name, sort_key) in &meta_store.sort_keys {
+            tables
+                .entry(name.clone())
+                .or_insert_with(|| Table::new(name, lru.clone()))
+                .set_sort_key(Some(sort_key.clone()));
+        }
+        for (name, policy) in &meta_store.compaction_policies {
+            tables
+                .entry(name.clone())
+                .or_insert_with(|| Table::new(name, lru.clone()))
+                .set_compaction_policy(Some(policy.clone()));
+        }
+        for (name, limit) in &meta_store.memory_limits {
+            tables
+                .entry(name.clone())
+                .or_insert_with(|| Table::new(name, lru.clone()))
+                .set_memory_limit(Some(*limit));
+        }
+        drop(meta_store);
         for partitions in storage.meta_store().read().unwrap().partitions.values() {
             for md in partitions.values() {
                 let table = tables
@@ -100,6 +366,11 @@ impl Table {
                 table.insert_nonresident_partition(md);
             }
         }
+        for (name, ids) in &storage.meta_store().read().unwrap().backfill_partitions {
+            if let Some(table) = tables.get(name) {
+                table.backfill_partitions.write().unwrap().extend(ids);
+            }
+        }
         let mut next_id = None;
         for wal_segment in wal_segments {
             if let Some(id) = next_id {
@@ -130,6 +401,7 @@ impl Table {
                                 }
                             }
                             ColumnData::Sparse(data) => InputColumn::NullableFloat(rows, data),
+                            ColumnData::Mixed(data) => InputColumn::Mixed(rows, data),
                         };
                         (k, col)
                     })
@@ -153,6 +425,12 @@ impl Table {
             .unwrap_or(0)
     }
 
+    /// Removes a partition whose files were quarantined.
+    pub(crate) fn remove_partition(&self, id: PartitionID) {
+        self.partitions.write().unwrap().remove(&id);
+        self.backfill_partitions.write().unwrap().remove(&id);
+    }
+
     pub fn insert_nonresident_partition(&self, md: &PartitionMetadata) {
         let partition = Arc::new(Partition::nonresident(self.name(), md, self.lru.clone()));
         let mut partitions = self.partitions.write().unwrap();
@@ -204,66 +482,91 @@ impl Table {
     }
 
     pub(crate) fn batch(&self) -> Option<Arc<Partition>> {
+        if let Some((settings, seen)) = self.deduplication.lock().unwrap().as_mut() {
+            seen.rotate(settings.lookback);
+        }
         let mut buffer = self.buffer.lock().unwrap();
         if buffer.len() == 0 {
             return None;
         }
         let buffer = std::mem::take(buffer.deref_mut());
+        Some(self.insert_partition(buffer, false))
+    }
+
+    /// Writes rows to a new partition without going through the buffer.
+    /// The partition is excluded from compaction, so it keeps covering only the time range of the backfilled rows.
+    pub(crate) fn ingest_backfill(&self, columns: HashMap<String, Vec<RawVal>>) -> Arc<Partition> {
+        let mut column_names = self.column_names.write().unwrap();
+        for col in columns.keys() {
+            if !column_names.contains(col) {
+                column_names.insert(col.clone());
+            }
+        }
+        drop(column_names);
+        let mut buffer = Buffer::default();
+        buffer.push_untyped_cols(columns);
+        self.insert_partition(buffer, true)
+    }
+
+    fn insert_partition(&self, buffer: Buffer, backfill: bool) -> Arc<Partition> {
         let part_id = self.next_partition_id();
         let partition_offset = self
             .next_partition_offset
             .fetch_add(buffer.len(), std::sync::atomic::Ordering::SeqCst);
+        let bloom_filters = buffer
+            .buffer
+            .iter()
+            .filter_map(|(name, values)| Some((name.clone(), self.bloom_filter(name, values)?)))
+            .collect();
         let (new_partition, keys) = Partition::from_buffer(
             self.name(),
             part_id,
             buffer,
             self.lru.clone(),
             partition_offset,
+            &mut self.shared_dictionaries.lock().unwrap(),
         );
         let arc_partition;
         {
             let mut partitions = self.partitions.write().unwrap();
-            arc_partition = Arc::new(new_partition);
+            if backfill {
+                self.backfill_partitions.write().unwrap().insert(part_id);
+            }
+            arc_partition = Arc::new(new_partition.with_bloom_filters(bloom_filters));
             partitions.insert(part_id, arc_partition.clone());
         }
         for (id, column) in keys {
             self.lru.put(ColumnLocator::new(self.name(), id, &column));
         }
-        Some(arc_partition)
+        arc_partition
     }
 
-    /// Determines if partitions should be compacted. If so, returns the maximal list of partitions to compact.
-    /// A subset of partitions is eligible for compaction if the size of each
-    /// partition in the subset is at least `combine_factor` times the total size of all partitions in the subset.
-    /// Additionally, partitions can only be compacted if they are contiguous.
-    pub fn plan_compaction(&self, combine_factor: u64) -> Option<(Range<usize>, Vec<PartitionID>)> {
-        // TODO: max partition size
+    /// Determines if partitions should be compacted. If so, returns the offset range and ids of the partitions to
+    /// compact, as chosen by the compaction policy of the table or `default_policy` if the table has none.
+    /// Partitions can only be compacted if they are contiguous.
+    pub fn plan_compaction(
+        &self,
+        default_policy: &CompactionPolicy,
+    ) -> Option<(Range<usize>, Vec<PartitionID>)> {
         let partitions = self.partitions.read().unwrap();
-        // let by_size_desc: Vec<Arc<Partition>> = partitions
-        //     .values()
-        //     .cloned()
-        //     .sorted_by(|p1, p2| p2.total_size_bytes().cmp(&p1.total_size_bytes()));
         let by_offset: Vec<Arc<Partition>> = partitions
             .values()
             .cloned()
             .sorted_by(|p1, p2| p1.range().start.cmp(&p2.range().start));
-        let cumulative = by_offset
+        // Backfilled partitions are barriers, only partitions written after the last one are compacted
+        let backfill_partitions = self.backfill_partitions.read().unwrap();
+        let by_offset = match by_offset
             .iter()
-            .rev()
-            .scan(0, |acc, p| {
-                *acc += p.total_size_bytes() as u64;
-                Some(*acc)
-            })
-            .collect::<Vec<_>>();
-
-        for (i, cum) in cumulative.iter().rev().enumerate() {
-            if by_offset[i].total_size_bytes() as u64 * combine_factor < *cum {
-                let range = by_offset[i].range().start..by_offset.last().unwrap().range().end;
-                return Some((range, by_offset[i..].iter().map(|p| p.id).collect()));
-            }
-        }
-
-        None
+            .rposition(|p| backfill_partitions.contains(&p.id))
+        {
+            Some(last) => by_offset[last + 1..].to_vec(),
+            None => by_offset,
+        };
+        let policy = self.compaction_policy.read().unwrap();
+        let selected = policy.as_ref().unwrap_or(default_policy).plan(&by_offset)?;
+        let selected = &by_offset[selected];
+        let range = selected[0].range().start..selected[selected.len() - 1].range().end;
+        Some((range, selected.iter().map(|p| p.id).collect()))
     }
 
     pub fn compact(
@@ -271,9 +574,11 @@ impl Table {
         id: PartitionID,
         offset: usize,
         columns: Vec<Arc<Column>>,
+        bloom_filters: HashMap<String, BloomFilter>,
         old_partitions: &[PartitionID],
     ) {
         let (partition, keys) = Partition::new(self.name(), id, columns, self.lru.clone(), offset);
+        let partition = partition.with_bloom_filters(bloom_filters);
         {
             let mut partitions = self.partitions.write().unwrap();
             for old_id in old_partitions {
@@ -325,14 +630,33 @@ impl Table {
         }
     }
 
+    /// Statistics of each column over all partitions, including buffered rows.
+    pub fn column_statistics(&self) -> HashMap<String, ColumnStatistics> {
+        let mut statistics = HashMap::<String, ColumnStatistics>::new();
+        for partition in self.snapshot() {
+            for handle in partition.col_handles() {
+                let name = handle.name();
+                statistics.entry(name.to_string()).or_default().add(
+                    partition.len(),
+                    partition.column_stats(name),
+                    partition.value_range(name),
+                );
+            }
+        }
+        statistics
+    }
+
+    /// Size of the columns of all partitions that are resident in memory, excluding buffered rows.
+    pub fn resident_bytes(&self) -> usize {
+        let batches = self.partitions.read().unwrap();
+        batches
+            .iter()
+            .map(|(_, partition)| partition.heap_size_of_children())
+            .sum()
+    }
+
     pub fn heap_size_of_children(&self) -> usize {
-        let batches_size: usize = {
-            let batches = self.partitions.read().unwrap();
-            batches
-                .iter()
-                .map(|(_, partition)| partition.heap_size_of_children())
-                .sum()
-        };
+        let batches_size = self.resident_bytes();
         let buffer_size = {
             let buffer = self.buffer.lock().unwrap();
             buffer.heap_size_of_children()
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Query;
+use crate::syntax::expression::Expr;
+use crate::syntax::parser;
+use crate::QueryError;
+
+/// Aggregation query over a source table whose results are stored in a table with the name of the view.
+/// Whenever the WAL is flushed, the query is evaluated over the new rows of the source table and the resulting
+/// partial aggregates are appended to the view table. Queries against the view combine partial aggregates with
+/// another aggregation, e.g. `SELECT host, SUM(requests) FROM view` for a view containing `COUNT(0) AS requests`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterializedView {
+    pub name: String,
+    pub source: String,
+    /// SQL text of the aggregation query, the table it selects from is replaced by `source`.
+    pub query: String,
+}
+
+impl MaterializedView {
+    /// Returns an error if `query` cannot be maintained incrementally, which requires every column to be either a
+    /// grouping expression or a single `COUNT`, `SUM`, `MIN` or `MAX` aggregate.
+    pub fn new(name: &str, query: &str) -> Result<MaterializedView, QueryError> {
+        let parsed = parser::parse_query(query)?;
+        if parsed.table == name {
+            bail!(
+                QueryError::SchemaError,
+                "Materialized view {} cannot select from itself",
+                name
+            );
+        }
+        if parsed.is_select_star() {
+            bail!(QueryError::NotImplemented, "SELECT * in materialized views");
+        }
+        if !parsed.order_by.is_empty() || parsed.limit.limit != u64::MAX || parsed.limit.offset != 0
+        {
+            bail!(
+                QueryError::NotImplemented,
+                "ORDER BY, LIMIT and OFFSET in materialized views"
+            );
+        }
+        for column in &parsed.select {
+            match &column.expr {
+                Expr::Aggregate(_, expr) => Query::ensure_no_aggregates(expr)?,
+                expr => Query::ensure_no_aggregates(expr).map_err(|_| {
+                    QueryError::NotImplemented(format!(
+                        "Column {} of materialized view {} must be a grouping expression or a single aggregate",
+                        column.name, name
+                    ))
+                })?,
+            }
+        }
+        Ok(MaterializedView {
+            name: name.to_string(),
+            source: parsed.table,
+            query: query.to_string(),
+        })
+    }
+
+    /// Returns the aggregation query, selecting from the current name of the source table.
+    pub fn query(&self) -> Result<Query, QueryError> {
+        let mut query = parser::parse_query(&self.query)?;
+        query.table = self.source.clone();
+        Ok(query)
+    }
+}
+
+/// Updates view names and sources after table `old` has been renamed to `new`.
+pub fn rename_view_tables(views: &mut HashMap<String, MaterializedView>, old: &str, new: &str) {
+    if let Some(mut view) = views.remove(old) {
+        view.name = new.to_string();
+        views.insert(new.to_string(), view);
+    }
+    for view in views.values_mut() {
+        if view.source == old {
+            view.source = new.to_string();
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use opentelemetry::sdk::trace;
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Exports the tracing spans of query parsing, partition scans, disk reads and merges to the OTLP/HTTP collector at
+/// `endpoint`, e.g. `http://localhost:4318`. Spans are batched and sent by a background thread.
+/// Fails if a global tracing subscriber has already been installed.
+pub fn init(endpoint: &str, service_name: &str) -> Result<(), TraceError> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)?;
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber).map_err(|err| err.to_string().into())
+}
+
+/// Exports all spans that have not been sent yet, called before the process exits.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -1,7 +1,21 @@
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::query_task::QueryStats;
 
 const ORDERING: Ordering = Ordering::SeqCst;
 
+/// Number of completed queries whose counters are retained, see `PerfCounter::query_snapshots`
+const RECENT_QUERIES: usize = 100;
+
+/// Upper bounds in seconds of the buckets of `QueryLatencies`
+pub const QUERY_LATENCY_BUCKETS: [f64; 10] =
+    [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0];
+
 #[derive(Debug, Default)]
 pub struct PerfCounter {
     disk_write_wal_bytes: AtomicU64,
@@ -21,13 +35,92 @@ pub struct PerfCounter {
 
     ingestion_requests: AtomicU64,
     network_read_ingestion_bytes: AtomicU64,
+
+    /// Size of the WAL segments that have not been flushed to partitions yet
+    wal_size_bytes: AtomicU64,
+
+    cache_evictions: AtomicU64,
+    cache_evicted_bytes: AtomicU64,
+
+    next_query_id: AtomicU64,
+    /// Running queries and the most recently completed queries
+    queries: Mutex<VecDeque<TrackedQuery>>,
+    /// Latencies of completed queries that are no longer retained in `queries`
+    retired_query_latencies: Mutex<QueryLatencies>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct QueryPerfCounter {
     pub rows_scanned: AtomicU64,
+    pub partitions_scanned: AtomicU64,
     pub files_opened: AtomicU64,
     pub disk_read_bytes: AtomicU64,
+    pub bytes_decompressed: AtomicU64,
+    /// Heap size of the columns read by the query
+    pub bytes_scanned: AtomicU64,
+    /// Number of partitions the query scans after pruning
+    pub partitions_total: AtomicU64,
+    /// Number of partitions whose results have been collected
+    pub partitions_completed: AtomicU64,
+    /// Wall time spent parsing and planning the query
+    pub plan_ns: AtomicU64,
+    /// Wall time worker threads spent reading columns, including waiting for disk reads
+    pub load_ns: AtomicU64,
+    /// Wall time worker threads spent executing the query plans of partitions
+    pub execute_ns: AtomicU64,
+    /// Wall time worker threads spent combining the results of partitions and converting the final result
+    pub combine_ns: AtomicU64,
+    start_time: Instant,
+    /// Runtime of the query once it has completed
+    runtime_ns: AtomicU64,
+    completed: AtomicBool,
+}
+
+#[derive(Debug)]
+struct TrackedQuery {
+    id: u64,
+    query: String,
+    counter: Arc<QueryPerfCounter>,
+}
+
+/// Progress of a single query, see `PerfCounter::query_progress`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryProgress {
+    pub id: u64,
+    pub partitions_completed: u64,
+    pub partitions_total: u64,
+    pub bytes_scanned: u64,
+    pub completed: bool,
+}
+
+/// Histogram of the runtimes of all completed queries, see `PerfCounter::query_latencies`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryLatencies {
+    pub count: u64,
+    pub sum_ns: u64,
+    /// Number of queries whose runtime falls into each bucket of `QUERY_LATENCY_BUCKETS`, queries that exceed the
+    /// last bucket are only included in `count`
+    pub buckets: [u64; QUERY_LATENCY_BUCKETS.len()],
+}
+
+impl QueryLatencies {
+    fn record(&mut self, runtime_ns: u64) {
+        self.count += 1;
+        self.sum_ns += runtime_ns;
+        let runtime_s = runtime_ns as f64 / 1e9;
+        if let Some(bucket) = QUERY_LATENCY_BUCKETS.iter().position(|&le| runtime_s <= le) {
+            self.buckets[bucket] += 1;
+        }
+    }
+}
+
+/// Counters of a single query, see `PerfCounter::query_snapshots`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuerySnapshot {
+    pub id: u64,
+    pub query: String,
+    pub completed: bool,
+    pub stats: QueryStats,
 }
 
 impl PerfCounter {
@@ -91,6 +184,15 @@ impl PerfCounter {
         self.network_read_ingestion_bytes.fetch_add(bytes, ORDERING);
     }
 
+    pub fn set_wal_size(&self, bytes: u64) {
+        self.wal_size_bytes.store(bytes, ORDERING);
+    }
+
+    pub fn cache_evicted(&self, bytes: u64) {
+        self.cache_evictions.fetch_add(1, ORDERING);
+        self.cache_evicted_bytes.fetch_add(bytes, ORDERING);
+    }
+
     pub fn disk_write_bytes(&self) -> u64 {
         self.disk_write_wal_bytes.load(ORDERING)
             + self.disk_write_new_partition_bytes.load(ORDERING)
@@ -147,6 +249,92 @@ impl PerfCounter {
     pub fn files_opened_partition(&self) -> u64 {
         self.file_accessed_partition.load(ORDERING)
     }
+
+    pub fn disk_read_meta_store_bytes(&self) -> u64 {
+        self.disk_read_meta_store_bytes.load(ORDERING)
+    }
+
+    pub fn disk_read_wal_bytes(&self) -> u64 {
+        self.disk_read_wal_bytes.load(ORDERING)
+    }
+
+    pub fn wal_size_bytes(&self) -> u64 {
+        self.wal_size_bytes.load(ORDERING)
+    }
+
+    pub fn cache_evictions(&self) -> u64 {
+        self.cache_evictions.load(ORDERING)
+    }
+
+    pub fn cache_evicted_bytes(&self) -> u64 {
+        self.cache_evicted_bytes.load(ORDERING)
+    }
+
+    /// Number of queries that have been started, including running queries.
+    pub fn queries_started(&self) -> u64 {
+        self.next_query_id.load(ORDERING)
+    }
+
+    /// Tracks the counters of `query` so that they are included in `query_snapshots`. Returns the id of the query.
+    pub fn track_query(&self, query: &str, counter: Arc<QueryPerfCounter>) -> u64 {
+        let id = self.next_query_id.fetch_add(1, ORDERING);
+        let mut queries = self.queries.lock().unwrap();
+        queries.push_back(TrackedQuery {
+            id,
+            query: query.to_string(),
+            counter,
+        });
+        while queries.len() > RECENT_QUERIES {
+            match queries.iter().position(|query| query.counter.completed()) {
+                Some(index) => {
+                    let query = queries.remove(index).unwrap();
+                    self.retired_query_latencies
+                        .lock()
+                        .unwrap()
+                        .record(query.counter.stats().runtime_ns);
+                }
+                None => break,
+            };
+        }
+        id
+    }
+
+    /// Runtimes of all completed queries.
+    pub fn query_latencies(&self) -> QueryLatencies {
+        let queries = self.queries.lock().unwrap();
+        let mut latencies = self.retired_query_latencies.lock().unwrap().clone();
+        for query in queries.iter().filter(|query| query.counter.completed()) {
+            latencies.record(query.counter.stats().runtime_ns);
+        }
+        latencies
+    }
+
+    /// Counters of all running queries and the most recently completed queries, in the order they were started.
+    pub fn query_snapshots(&self) -> Vec<QuerySnapshot> {
+        let queries = self.queries.lock().unwrap();
+        queries
+            .iter()
+            .map(|query| QuerySnapshot {
+                id: query.id,
+                query: query.query.clone(),
+                completed: query.counter.completed(),
+                stats: query.counter.stats(),
+            })
+            .collect()
+    }
+
+    /// Progress of the query with `id`, `None` if the query is unknown or no longer retained.
+    pub fn query_progress(&self, id: u64) -> Option<QueryProgress> {
+        let queries = self.queries.lock().unwrap();
+        let query = queries.iter().find(|query| query.id == id)?;
+        Some(QueryProgress {
+            id,
+            partitions_completed: query.counter.partitions_completed.load(ORDERING),
+            partitions_total: query.counter.partitions_total.load(ORDERING),
+            bytes_scanned: query.counter.bytes_scanned.load(ORDERING),
+            completed: query.counter.completed(),
+        })
+    }
 }
 
 impl QueryPerfCounter {
@@ -158,6 +346,10 @@ impl QueryPerfCounter {
         self.rows_scanned.load(ORDERING)
     }
 
+    pub fn partitions_scanned(&self) -> u64 {
+        self.partitions_scanned.load(ORDERING)
+    }
+
     pub fn files_opened(&self) -> u64 {
         self.files_opened.load(ORDERING)
     }
@@ -174,4 +366,96 @@ impl QueryPerfCounter {
         self.files_opened.fetch_add(1, ORDERING);
         self.disk_read_bytes.fetch_add(bytes, ORDERING);
     }
-}
\ No newline at end of file
+
+    pub fn decompressed(&self, bytes: u64) {
+        self.bytes_decompressed.fetch_add(bytes, ORDERING);
+    }
+
+    pub fn scanned_bytes(&self, bytes: u64) {
+        self.bytes_scanned.fetch_add(bytes, ORDERING);
+    }
+
+    pub fn set_partitions_total(&self, partitions: u64) {
+        self.partitions_total.store(partitions, ORDERING);
+    }
+
+    pub fn completed_partitions(&self, partitions: u64) {
+        self.partitions_completed.fetch_add(partitions, ORDERING);
+    }
+
+    pub fn planned(&self, elapsed: Duration) {
+        self.plan_ns.fetch_add(elapsed.as_nanos() as u64, ORDERING);
+    }
+
+    pub fn loaded_partition(&self, elapsed: Duration) {
+        self.partitions_scanned.fetch_add(1, ORDERING);
+        self.load_ns.fetch_add(elapsed.as_nanos() as u64, ORDERING);
+    }
+
+    pub fn executed(&self, elapsed: Duration) {
+        self.execute_ns
+            .fetch_add(elapsed.as_nanos() as u64, ORDERING);
+    }
+
+    pub fn combined(&self, elapsed: Duration) {
+        self.combine_ns
+            .fetch_add(elapsed.as_nanos() as u64, ORDERING);
+    }
+
+    pub fn completed(&self) -> bool {
+        self.completed.load(ORDERING)
+    }
+
+    /// Marks the query as completed and returns its final stats.
+    pub fn complete(&self) -> QueryStats {
+        if !self.completed.swap(true, ORDERING) {
+            self.runtime_ns
+                .store(self.start_time.elapsed().as_nanos() as u64, ORDERING);
+        }
+        self.stats()
+    }
+
+    /// Current stats of the query. The runtime of running queries is the time since they started.
+    pub fn stats(&self) -> QueryStats {
+        let runtime_ns = if self.completed() {
+            self.runtime_ns.load(ORDERING)
+        } else {
+            self.start_time.elapsed().as_nanos() as u64
+        };
+        QueryStats {
+            runtime_ns,
+            rows_scanned: self.rows_scanned(),
+            partitions_scanned: self.partitions_scanned(),
+            files_opened: self.files_opened(),
+            disk_read_bytes: self.disk_read_bytes(),
+            bytes_decompressed: self.bytes_decompressed.load(ORDERING),
+            bytes_scanned: self.bytes_scanned.load(ORDERING),
+            plan_ns: self.plan_ns.load(ORDERING),
+            load_ns: self.load_ns.load(ORDERING),
+            execute_ns: self.execute_ns.load(ORDERING),
+            combine_ns: self.combine_ns.load(ORDERING),
+        }
+    }
+}
+
+impl Default for QueryPerfCounter {
+    fn default() -> QueryPerfCounter {
+        QueryPerfCounter {
+            rows_scanned: AtomicU64::default(),
+            partitions_scanned: AtomicU64::default(),
+            files_opened: AtomicU64::default(),
+            disk_read_bytes: AtomicU64::default(),
+            bytes_decompressed: AtomicU64::default(),
+            bytes_scanned: AtomicU64::default(),
+            partitions_total: AtomicU64::default(),
+            partitions_completed: AtomicU64::default(),
+            plan_ns: AtomicU64::default(),
+            load_ns: AtomicU64::default(),
+            execute_ns: AtomicU64::default(),
+            combine_ns: AtomicU64::default(),
+            start_time: Instant::now(),
+            runtime_ns: AtomicU64::default(),
+            completed: AtomicBool::default(),
+        }
+    }
+}
@@ -3,9 +3,13 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+use numpy::IntoPyArray;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::types::{PyBytes, PyDict};
 use pyo3::{prelude::*, wrap_pyfunction};
 
 use crate::logging_client::LoggingClient;
+use crate::{BasicTypeColumn, QueryOutput, Value};
 
 lazy_static! {
     static ref RT: tokio::runtime::Runtime = tokio::runtime::Runtime::new().unwrap();
@@ -23,12 +27,155 @@ lazy_static! {
 fn locustdb(_py: Python, m: &PyModule) -> PyResult<()> {
     env_logger::init();
     m.add_function(wrap_pyfunction!(self::log, m)?).unwrap();
+    m.add_class::<Client>()?;
     Ok(())
 }
 
+/// Logs a row of `table` whose values may be `str`, `int`, `float` or `None`.
 #[pyfunction]
-fn log(table: &str, metrics: HashMap<String, f64>) -> PyResult<()> {
+fn log(table: &str, metrics: HashMap<String, &PyAny>) -> PyResult<()> {
+    let row = metrics
+        .into_iter()
+        .map(|(column, value)| Ok((column, value_from_py(value)?)))
+        .collect::<PyResult<Vec<_>>>()?;
     let mut client = DEFAULT_CLIENT.lock().unwrap();
-    client.log(table, metrics);
+    client.log(table, row);
     Ok(())
 }
+
+/// Runs queries against the LocustDB server at `url`.
+#[pyclass]
+struct Client {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[pymethods]
+impl Client {
+    #[new]
+    #[pyo3(signature = (url = "http://localhost:8080"))]
+    fn new(url: &str) -> Client {
+        let _guard = RT.enter();
+        Client {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Returns a dict that maps each column of the result to a list of its values.
+    fn query(&self, py: Python, query: &str) -> PyResult<PyObject> {
+        let output = py.allow_threads(|| RT.block_on(self.fetch(query)))?;
+        let columns = PyDict::new(py);
+        for (colname, column) in output.columns {
+            let values = match column {
+                BasicTypeColumn::Int(ints) => ints.into_py(py),
+                BasicTypeColumn::Float(floats) => floats.into_py(py),
+                BasicTypeColumn::String(strings) => strings.into_py(py),
+                BasicTypeColumn::Null(len) => vec![py.None(); len].into_py(py),
+                BasicTypeColumn::Mixed(vals) => vals
+                    .into_iter()
+                    .map(|val| value_to_py(py, val))
+                    .collect::<Vec<_>>()
+                    .into_py(py),
+            };
+            columns.set_item(colname, values)?;
+        }
+        Ok(columns.into())
+    }
+
+    /// Returns the result as a pandas DataFrame. Integer and float columns are moved into `int64` and `float64` numpy
+    /// arrays without converting individual values, all other columns become `object` arrays.
+    fn query_df(&self, py: Python, query: &str) -> PyResult<PyObject> {
+        let output = py.allow_threads(|| RT.block_on(self.fetch(query)))?;
+        let columns = PyDict::new(py);
+        for (colname, column) in output.columns {
+            let array: PyObject = match column {
+                BasicTypeColumn::Int(ints) => ints.into_pyarray(py).into(),
+                BasicTypeColumn::Float(floats) => floats.into_pyarray(py).into(),
+                BasicTypeColumn::String(strings) => strings
+                    .into_iter()
+                    .map(|string| string.into_py(py))
+                    .collect::<Vec<_>>()
+                    .into_pyarray(py)
+                    .into(),
+                BasicTypeColumn::Null(len) => vec![py.None(); len].into_pyarray(py).into(),
+                BasicTypeColumn::Mixed(vals) => vals
+                    .into_iter()
+                    .map(|val| value_to_py(py, val))
+                    .collect::<Vec<_>>()
+                    .into_pyarray(py)
+                    .into(),
+            };
+            columns.set_item(colname, array)?;
+        }
+        let data_frame = py.import("pandas")?.getattr("DataFrame")?;
+        Ok(data_frame.call1((columns,))?.into())
+    }
+
+    /// Returns the result as a pyarrow Table. The server sends the result as an Arrow IPC stream which pyarrow reads
+    /// without creating Python objects for individual values. Requires a server built with the `arrow_export` feature.
+    fn query_arrow(&self, py: Python, query: &str) -> PyResult<PyObject> {
+        let body =
+            py.allow_threads(|| RT.block_on(self.post_query("query?format=arrow", query)))?;
+        let reader = py
+            .import("pyarrow.ipc")?
+            .call_method1("open_stream", (PyBytes::new(py, &body),))?;
+        Ok(reader.call_method0("read_all")?.into())
+    }
+}
+
+impl Client {
+    async fn fetch(&self, query: &str) -> PyResult<QueryOutput> {
+        let body = self.post_query("query_bin", query).await?;
+        bincode::deserialize(&body).map_err(|err| {
+            PyRuntimeError::new_err(format!("Failed to deserialize query result: {}", err))
+        })
+    }
+
+    async fn post_query(&self, endpoint: &str, query: &str) -> PyResult<Vec<u8>> {
+        let request_error = |err: reqwest::Error| PyRuntimeError::new_err(err.to_string());
+        let response = self
+            .client
+            .post(format!("{}/{}", self.url, endpoint))
+            .json(&serde_json::json!({ "query": query }))
+            .send()
+            .await
+            .map_err(request_error)?;
+        let status = response.status();
+        let body: Vec<u8> = response.bytes().await.map_err(request_error)?.into();
+        if !status.is_success() {
+            return Err(PyRuntimeError::new_err(format!(
+                "Query failed with status {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            )));
+        }
+        Ok(body)
+    }
+}
+
+fn value_to_py(py: Python, val: Value) -> PyObject {
+    match val {
+        Value::Int(int) => int.into_py(py),
+        Value::Float(float) => float.0.into_py(py),
+        Value::Str(str) => str.into_py(py),
+        Value::Null => py.None(),
+    }
+}
+
+fn value_from_py(value: &PyAny) -> PyResult<Value> {
+    if value.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(int) = value.extract::<i64>() {
+        Ok(Value::Int(int))
+    } else if let Ok(float) = value.extract::<f64>() {
+        Ok(Value::from(float))
+    } else if let Ok(str) = value.extract::<String>() {
+        Ok(Value::Str(str))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "Unsupported value {}, expected str, int, float or None",
+            value
+        )))
+    }
+}
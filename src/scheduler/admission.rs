@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+
+use crate::QueryError;
+
+/// Limits the number of concurrently executing queries. Queries over the limit wait in a FIFO queue and are rejected
+/// if they are not admitted within the queue timeout.
+pub struct QueryAdmission {
+    max_concurrent: usize,
+    timeout: Duration,
+    state: Mutex<AdmissionState>,
+    queue_changed: Condvar,
+}
+
+#[derive(Default)]
+struct AdmissionState {
+    running: usize,
+    /// Queued queries and the time at which they are rejected, oldest first
+    queue: VecDeque<(Instant, oneshot::Sender<()>)>,
+}
+
+/// Held by an admitted query while it executes. Admits the next queued query when dropped.
+pub struct AdmissionPermit<'a> {
+    admission: &'a QueryAdmission,
+}
+
+/// Releases the permit of a queued query that was admitted after it stopped waiting, e.g. because its future was
+/// dropped.
+struct Queued<'a> {
+    admission: &'a QueryAdmission,
+    receiver: oneshot::Receiver<()>,
+    admitted: bool,
+}
+
+impl QueryAdmission {
+    pub fn new(max_concurrent: usize, timeout: Duration) -> QueryAdmission {
+        QueryAdmission {
+            max_concurrent,
+            timeout,
+            state: Mutex::default(),
+            queue_changed: Condvar::new(),
+        }
+    }
+
+    /// Waits until fewer than `max_concurrent` queries are executing and all previously queued queries have been
+    /// admitted.
+    pub async fn admit(&self) -> Result<AdmissionPermit<'_>, QueryError> {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.max_concurrent && state.queue.is_empty() {
+                state.running += 1;
+                return Ok(AdmissionPermit { admission: self });
+            }
+            let (sender, receiver) = oneshot::channel();
+            state
+                .queue
+                .push_back((Instant::now() + self.timeout, sender));
+            self.queue_changed.notify_one();
+            receiver
+        };
+        let mut queued = Queued {
+            admission: self,
+            receiver,
+            admitted: false,
+        };
+        match (&mut queued.receiver).await {
+            Ok(()) => {
+                queued.admitted = true;
+                Ok(AdmissionPermit { admission: self })
+            }
+            Err(oneshot::Canceled) => Err(QueryError::Overloaded(format!(
+                "query was not admitted within {:?} because {} queries are already running",
+                self.timeout, self.max_concurrent
+            ))),
+        }
+    }
+
+    /// Rejects queued queries whose timeout has passed, until `running` is false.
+    pub fn expire_queued(&self, running: &AtomicBool) {
+        let mut state = self.state.lock().unwrap();
+        while running.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            while state
+                .queue
+                .front()
+                .map_or(false, |(deadline, _)| *deadline <= now)
+            {
+                // Dropping the sender rejects the query
+                state.queue.pop_front();
+            }
+            let wait = state
+                .queue
+                .front()
+                .map_or(Duration::from_secs(1), |(deadline, _)| {
+                    (*deadline - now).min(Duration::from_secs(1))
+                });
+            state = self.queue_changed.wait_timeout(state, wait).unwrap().0;
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some((_, sender)) = state.queue.pop_front() {
+            // Fails if the query stopped waiting, in which case the permit is passed on to the next query
+            if sender.send(()).is_ok() {
+                return;
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+impl<'a> Drop for AdmissionPermit<'a> {
+    fn drop(&mut self) {
+        self.admission.release();
+    }
+}
+
+impl<'a> Drop for Queued<'a> {
+    fn drop(&mut self) {
+        if !self.admitted {
+            self.receiver.close();
+            if let Ok(Some(())) = self.receiver.try_recv() {
+                self.admission.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn test_admit() {
+        let running = AtomicBool::new(true);
+        let admission = QueryAdmission::new(1, Duration::from_millis(50));
+        thread::scope(|s| {
+            s.spawn(|| admission.expire_queued(&running));
+            let permit = block_on(admission.admit()).unwrap();
+            assert!(block_on(admission.admit()).is_err());
+            drop(permit);
+            let _permit = block_on(admission.admit()).unwrap();
+            running.store(false, Ordering::SeqCst);
+        });
+
+        let admission = QueryAdmission::new(1, Duration::from_secs(60));
+        let permit = block_on(admission.admit()).unwrap();
+        thread::scope(|s| {
+            let queued = s.spawn(|| block_on(admission.admit()).is_ok());
+            while admission.state.lock().unwrap().queue.is_empty() {
+                thread::yield_now();
+            }
+            drop(permit);
+            assert!(queued.join().unwrap());
+        });
+        assert_eq!(admission.state.lock().unwrap().running, 0);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Pins worker threads and the WAL flush thread to CPUs. On machines with multiple sockets this keeps threads close to
+/// the memory they access. Only supported on Linux, elsewhere threads are never pinned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ThreadAffinity {
+    /// Threads are placed by the operating system.
+    #[default]
+    None,
+    /// Each thread is pinned to a single core, assigned round-robin.
+    Core,
+    /// Each thread is pinned to all cores of a NUMA node, assigned round-robin, so that the operating system can still
+    /// balance threads within a node.
+    NumaNode,
+}
+
+impl FromStr for ThreadAffinity {
+    type Err = String;
+
+    /// Parses `none`, `core` or `numa`.
+    fn from_str(s: &str) -> Result<ThreadAffinity, String> {
+        match s {
+            "none" => Ok(ThreadAffinity::None),
+            "core" => Ok(ThreadAffinity::Core),
+            "numa" => Ok(ThreadAffinity::NumaNode),
+            _ => Err(format!(
+                "Invalid thread affinity {}, expected none, core or numa",
+                s
+            )),
+        }
+    }
+}
+
+/// Assigns threads to the CPU sets of a `ThreadAffinity`.
+pub struct ThreadPinning {
+    cpu_sets: Vec<Vec<usize>>,
+    next: AtomicUsize,
+}
+
+impl ThreadPinning {
+    pub fn new(affinity: ThreadAffinity) -> ThreadPinning {
+        let cpu_sets = match affinity {
+            ThreadAffinity::None => vec![],
+            _ if !cfg!(target_os = "linux") => {
+                warn!("Thread affinity is only supported on Linux, threads are not pinned");
+                vec![]
+            }
+            ThreadAffinity::Core => online_cpus().into_iter().map(|cpu| vec![cpu]).collect(),
+            ThreadAffinity::NumaNode => {
+                let nodes = numa_nodes();
+                if nodes.is_empty() {
+                    warn!("Could not determine NUMA nodes, threads are not pinned");
+                }
+                nodes
+            }
+        };
+        ThreadPinning {
+            cpu_sets,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pins the calling thread to the next CPU set.
+    pub fn pin_current_thread(&self) {
+        if self.cpu_sets.is_empty() {
+            return;
+        }
+        let index = self.next.fetch_add(1, Ordering::SeqCst) % self.cpu_sets.len();
+        let cpus = &self.cpu_sets[index];
+        if !set_affinity(cpus) {
+            warn!("Failed to pin thread to CPUs {:?}", cpus);
+        }
+    }
+}
+
+fn online_cpus() -> Vec<usize> {
+    fs::read_to_string("/sys/devices/system/cpu/online")
+        .ok()
+        .and_then(|list| parse_cpu_list(&list))
+        .unwrap_or_else(|| (0..num_cpus::get()).collect())
+}
+
+/// CPUs of each NUMA node that has any.
+fn numa_nodes() -> Vec<Vec<usize>> {
+    let entries = match fs::read_dir("/sys/devices/system/node") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut nodes = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let node = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse::<usize>()
+                .ok()?;
+            let cpus = parse_cpu_list(&fs::read_to_string(entry.path().join("cpulist")).ok()?)?;
+            Some((node, cpus))
+        })
+        .filter(|(_, cpus)| !cpus.is_empty())
+        .collect::<Vec<_>>();
+    nodes.sort();
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+/// Parses CPU lists in the format of the Linux sysfs, e.g. `0-3,8-11`.
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => cpus.extend(start.parse::<usize>().ok()?..=end.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> bool {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus.iter().filter(|&&cpu| cpu < libc::CPU_SETSIZE as usize) {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+}
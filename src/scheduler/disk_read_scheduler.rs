@@ -1,9 +1,11 @@
-// background_load_in_progress used with condition variable
-#![allow(clippy::mutex_atomic)]
+// background_loads_in_progress used with condition variable
+#![allow(clippy::mutex_integer)]
 
 use std::collections::VecDeque;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
 use std_semaphore::Semaphore;
 
 use crate::disk_store::*;
@@ -22,7 +24,32 @@ pub struct DiskReadScheduler {
     lz4_decode: bool,
 
     background_load_wait_queue: Condvar,
-    background_load_in_progress: Mutex<bool>,
+    /// Number of sequential reads and prefetches that are currently loading columns
+    background_loads_in_progress: Mutex<usize>,
+
+    /// Default number of partitions ahead of the partition a query is scanning whose columns are read in the
+    /// background. `None` without persistent storage, in which case all columns are resident.
+    prefetch_partitions: Option<usize>,
+    prefetch_queue: Mutex<VecDeque<Prefetch>>,
+    prefetch_wait_queue: Condvar,
+    /// Default maximum number of concurrent disk reads of a single query
+    max_query_reads: Option<usize>,
+}
+
+struct Prefetch {
+    partition: Arc<Partition>,
+    columns: Arc<HashSet<String>>,
+    read_limit: Option<Arc<Semaphore>>,
+    perf_counter: Arc<QueryPerfCounter>,
+}
+
+/// Overrides the disk read settings of `Options` for a single query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryIo {
+    /// Maximum number of disk reads the query issues concurrently, see `Options::max_query_disk_reads`
+    pub max_disk_reads: Option<usize>,
+    /// Number of partitions that are read ahead of the partition being scanned, see `Options::prefetch_partitions`
+    pub prefetch_partitions: Option<usize>,
 }
 
 #[derive(Default, Debug)]
@@ -39,6 +66,8 @@ impl DiskReadScheduler {
         lru: Lru,
         max_readers: usize,
         lz4_decode: bool,
+        prefetch_partitions: Option<usize>,
+        max_query_reads: Option<usize>,
     ) -> DiskReadScheduler {
         DiskReadScheduler {
             disk_store,
@@ -47,10 +76,94 @@ impl DiskReadScheduler {
             lru,
             lz4_decode,
             background_load_wait_queue: Condvar::default(),
-            background_load_in_progress: Mutex::default(),
+            background_loads_in_progress: Mutex::default(),
+            prefetch_partitions,
+            prefetch_queue: Mutex::default(),
+            prefetch_wait_queue: Condvar::default(),
+            max_query_reads,
+        }
+    }
+
+    pub fn prefetch_partitions(&self) -> Option<usize> {
+        self.prefetch_partitions
+    }
+
+    pub fn max_query_reads(&self) -> Option<usize> {
+        self.max_query_reads
+    }
+
+    /// Reads the non-resident `columns` of `partitions` in the background, so that they are resident by the time the
+    /// query scans them.
+    pub fn prefetch(
+        &self,
+        partitions: impl Iterator<Item = Arc<Partition>>,
+        columns: &Arc<HashSet<String>>,
+        read_limit: Option<&Arc<Semaphore>>,
+        perf_counter: &Arc<QueryPerfCounter>,
+    ) {
+        if self.prefetch_partitions.is_none() {
+            return;
+        }
+        let mut prefetch_queue = self.prefetch_queue.lock().unwrap();
+        for partition in partitions {
+            prefetch_queue.push_back(Prefetch {
+                partition,
+                columns: columns.clone(),
+                read_limit: read_limit.cloned(),
+                perf_counter: perf_counter.clone(),
+            });
+        }
+        self.prefetch_wait_queue.notify_all();
+    }
+
+    /// Services prefetches until `running` is false.
+    pub fn service_prefetches(&self, running: &AtomicBool) {
+        while running.load(Ordering::SeqCst) {
+            let prefetch = {
+                let mut prefetch_queue = self.prefetch_queue.lock().unwrap();
+                match prefetch_queue.pop_front() {
+                    Some(prefetch) => prefetch,
+                    None => {
+                        let _ = self
+                            .prefetch_wait_queue
+                            .wait_timeout(prefetch_queue, Duration::from_secs(1))
+                            .unwrap();
+                        continue;
+                    }
+                }
+            };
+            prefetch.partition.prefetch_cols(
+                &prefetch.columns,
+                self,
+                prefetch.read_limit.as_deref(),
+                &prefetch.perf_counter,
+            );
         }
     }
 
+    /// Loads the column unless it is resident or already being loaded.
+    pub fn prefetch_column(
+        &self,
+        handle: &ColumnHandle,
+        cols: &HashMap<String, ColumnHandle>,
+        read_limit: Option<&Semaphore>,
+        perf_counter: &QueryPerfCounter,
+    ) {
+        {
+            let mut loads_in_progress = self.background_loads_in_progress.lock().unwrap();
+            // Queries that reach the column while it is loaded wait for the load instead of reading it again
+            if handle.is_resident() || !handle.schedule_load() {
+                return;
+            }
+            *loads_in_progress += 1;
+        }
+        debug!("Prefetching {}.{}", handle.name(), handle.id());
+        self.load(handle, cols, read_limit, perf_counter);
+        handle.unschedule_load();
+        *self.background_loads_in_progress.lock().unwrap() -= 1;
+        self.background_load_wait_queue.notify_all();
+    }
+
     pub fn schedule_sequential_read(
         &self,
         snapshot: &mut Vec<Arc<Partition>>,
@@ -115,7 +228,7 @@ impl DiskReadScheduler {
         debug!("Scheduled sequential reads. Queue: {:#?}", &*task_queue);
     }
 
-    pub fn get_or_load(&self, handle: &ColumnHandle, cols: &HashMap<String, ColumnHandle>, perf_counter: &QueryPerfCounter) -> Arc<Column> {
+    pub fn get_or_load(&self, handle: &ColumnHandle, cols: &HashMap<String, ColumnHandle>, read_limit: Option<&Semaphore>, perf_counter: &QueryPerfCounter) -> Arc<Column> {
         loop {
             if handle.is_resident() {
                 let mut maybe_column = handle.try_get();
@@ -135,50 +248,69 @@ impl DiskReadScheduler {
                     debug!("{}.{} was not resident!", handle.name(), handle.id());
                 }
             } else if handle.is_load_scheduled() {
-                let mut is_load_in_progress = self.background_load_in_progress.lock().unwrap();
-                while *is_load_in_progress && !handle.is_resident() && handle.is_load_scheduled() {
+                let mut loads_in_progress = self.background_loads_in_progress.lock().unwrap();
+                while *loads_in_progress > 0 && !handle.is_resident() && handle.is_load_scheduled() {
                     debug!("Queuing for {}.{}", handle.name(), handle.id());
-                    is_load_in_progress = self
+                    loads_in_progress = self
                         .background_load_wait_queue
-                        .wait(is_load_in_progress)
+                        .wait(loads_in_progress)
                         .unwrap();
                 }
             } else {
                 debug!("Point lookup for {}.{}", handle.name(), handle.id());
-                let columns = {
-                    let _token = self.reader_semaphore.access();
-                    self.disk_store.load_column(&handle.key().table, handle.id(), handle.name(), perf_counter)
-                };
-                let mut result = None;
-                #[allow(unused_mut)]
-                for mut column in columns {
-                    let _handle = cols.get(column.name()).unwrap();
-                    // Need to hold lock when we put new value into lru
-                    let mut maybe_column = _handle.try_get();
-                    // TODO: if not main handle, put it at back of lru
-                    self.lru.put(_handle.key().clone());
-                    #[cfg(feature = "enable_lz4")]
-                    {
-                        if self.lz4_decode {
-                            column.lz4_decode();
-                            _handle.update_size_bytes(column.heap_size_of_children());
-                        }
-                    }
-                    let column = Arc::new(column);
-                    *maybe_column = Some(column.clone());
-                    _handle.set_resident(column.heap_size_of_children());
-                    if column.name() == handle.name() {
-                        result = Some(column);
-                    }
+                return self.load(handle, cols, read_limit, perf_counter);
+            }
+        }
+    }
+
+    fn load(
+        &self,
+        handle: &ColumnHandle,
+        cols: &HashMap<String, ColumnHandle>,
+        read_limit: Option<&Semaphore>,
+        perf_counter: &QueryPerfCounter,
+    ) -> Arc<Column> {
+        let _span = tracing::info_span!(
+            "disk_read",
+            table = handle.key().table.as_str(),
+            partition = handle.id(),
+            column = handle.name()
+        )
+        .entered();
+        let columns = {
+            // Acquired first so that queries at their own limit don't occupy readers that other queries could use
+            let _query_token = read_limit.map(|semaphore| semaphore.access());
+            let _token = self.reader_semaphore.access();
+            self.disk_store.load_column(&handle.key().table, handle.id(), handle.name(), perf_counter)
+        };
+        let mut result = None;
+        #[allow(unused_mut)]
+        for mut column in columns {
+            let _handle = cols.get(column.name()).unwrap();
+            // Need to hold lock when we put new value into lru
+            let mut maybe_column = _handle.try_get();
+            // TODO: if not main handle, put it at back of lru
+            self.lru.put(_handle.key().clone());
+            #[cfg(feature = "enable_lz4")]
+            {
+                if self.lz4_decode {
+                    column.lz4_decode();
+                    _handle.update_size_bytes(column.heap_size_of_children());
                 }
-                return result.unwrap()
+            }
+            let column = Arc::new(column);
+            *maybe_column = Some(column.clone());
+            _handle.set_resident(column.heap_size_of_children());
+            if column.name() == handle.name() {
+                result = Some(column);
             }
         }
+        result.unwrap()
     }
 
     pub fn service_reads(&self, ldb: &InnerLocustDB) {
         debug!("Waiting to service reads...");
-        *self.background_load_in_progress.lock().unwrap() = true;
+        *self.background_loads_in_progress.lock().unwrap() += 1;
         debug!("Started servicing reads...");
         loop {
             let next_read = {
@@ -187,7 +319,8 @@ impl DiskReadScheduler {
                     Some(read) => read,
                     None => {
                         debug!("Stopped servicing reads...");
-                        *self.background_load_in_progress.lock().unwrap() = false;
+                        *self.background_loads_in_progress.lock().unwrap() -= 1;
+                        self.background_load_wait_queue.notify_all();
                         return;
                     }
                 }
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of the checks behind the `/healthz` and `/readyz` endpoints, see `LocustDB::health`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Health {
+    /// Number of running query worker threads and the number that was started
+    pub workers: usize,
+    pub min_workers: usize,
+    /// Number of running background worker threads and the number that was started
+    pub background_workers: usize,
+    pub min_background_workers: usize,
+    /// Error encountered while writing a probe file next to the WAL, `None` if it succeeded or the database is not
+    /// persistent
+    pub wal_error: Option<String>,
+    /// Size of the WAL segments that have not been flushed to partitions yet
+    pub wal_size_bytes: u64,
+    /// Ingestion blocks while the WAL exceeds this size, see `Options::max_wal_size_bytes`
+    pub max_wal_size_bytes: u64,
+    /// Whether the process exceeds `Options::max_rss_bytes`
+    pub memory_pressure: bool,
+    pub accepting_ingestion: bool,
+}
+
+impl Health {
+    /// Whether all worker threads are alive and the WAL is writable. Worker threads that panicked are not restarted, so
+    /// an unhealthy database does not recover without a restart.
+    pub fn is_healthy(&self) -> bool {
+        self.workers >= self.min_workers
+            && self.background_workers >= self.min_background_workers
+            && self.wal_error.is_none()
+    }
+
+    /// Whether the database is healthy and can accept ingestion without blocking on the WAL backlog or being rejected
+    /// due to memory pressure.
+    pub fn is_ready(&self) -> bool {
+        self.is_healthy()
+            && self.accepting_ingestion
+            && !self.memory_pressure
+            && self.wal_size_bytes <= self.max_wal_size_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy() -> Health {
+        Health {
+            workers: 4,
+            min_workers: 4,
+            background_workers: 1,
+            min_background_workers: 1,
+            wal_error: None,
+            wal_size_bytes: 0,
+            max_wal_size_bytes: 1024,
+            memory_pressure: false,
+            accepting_ingestion: true,
+        }
+    }
+
+    #[test]
+    fn test_health() {
+        assert!(healthy().is_ready());
+        let dead_worker = Health {
+            workers: 3,
+            ..healthy()
+        };
+        assert!(!dead_worker.is_healthy());
+        let backlogged = Health {
+            wal_size_bytes: 2048,
+            ..healthy()
+        };
+        assert!(backlogged.is_healthy());
+        assert!(!backlogged.is_ready());
+        let readonly_wal = Health {
+            wal_error: Some("Permission denied".to_string()),
+            ..healthy()
+        };
+        assert!(!readonly_wal.is_healthy());
+    }
+}
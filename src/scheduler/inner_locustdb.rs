@@ -1,67 +1,198 @@
 use std::borrow::Cow;
-use std::collections::{HashMap, VecDeque};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, Weak};
 use std::thread;
 use std::time::{Duration, Instant};
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::{mem, str};
+use std::{iter, mem, str};
 
 use futures::channel::oneshot;
 use futures::executor::block_on;
 use itertools::Itertools;
+use systemstat::{Platform, System};
 
-use crate::disk_store::storage::{Storage, WALSegment};
+use crate::disk_store::migration::{self, MigrationReport};
+use crate::disk_store::object_store::ObjectStoreBlobWriter;
+use crate::disk_store::storage::{GarbageCollectionReport, ScrubReport, Storage, WALSegment};
 use crate::disk_store::*;
 use crate::engine::query_task::{BasicTypeColumn, QueryTask};
-use crate::engine::Query;
+use crate::engine::{AggregateCache, ColumnInfo, ColumnStatistics, Query};
 use crate::ingest::colgen::GenTable;
 use crate::ingest::input_column::InputColumn;
 use crate::ingest::raw_val::RawVal;
-use crate::locustdb::Options;
+use crate::locustdb::{Options, WalBackpressure};
 use crate::logging_client::ColumnData;
-use crate::logging_client::EventBuffer;
-use crate::mem_store::partition::Partition;
+use crate::logging_client::{EventBuffer, TableBuffer};
+use crate::mem_store::partition::{ColumnLocator, Partition};
 use crate::mem_store::table::*;
+use crate::mem_store::view::rename_view_tables;
 use crate::perf_counter::PerfCounter;
+use crate::scheduler::admission::{AdmissionPermit, QueryAdmission};
+use crate::scheduler::affinity::ThreadPinning;
 use crate::scheduler::disk_read_scheduler::DiskReadScheduler;
 use crate::scheduler::*;
+use crate::syntax::expression::Expr;
 use crate::{mem_store::*, NoopStorage};
+use crate::{QueryError, QueryResult};
 
 use self::raw_col::MixedCol;
 
 pub struct InnerLocustDB {
     tables: RwLock<HashMap<String, Table>>,
+    views: RwLock<HashMap<String, MaterializedView>>,
+    scheduled_queries: Mutex<HashMap<String, ScheduledQuery>>,
     lru: Lru,
     disk_read_scheduler: Arc<DiskReadScheduler>,
+    aggregate_cache: Option<Arc<AggregateCache>>,
 
     storage: Option<Arc<Storage>>,
 
     wal_size: (Mutex<u64>, Condvar),
+    /// Partitions created by a WAL flush that failed to persist them, persisted again by the next WAL flush
+    unpersisted_partitions: Mutex<Vec<(PartitionMetadata, Vec<Vec<Arc<Column>>>)>>,
 
     opts: Options,
 
     perf_counter: Arc<PerfCounter>,
 
     running: AtomicBool,
-    idle_queue: Condvar,
-    task_queue: Mutex<VecDeque<Arc<dyn Task>>>,
+    /// Cleared by `shutdown` to reject new ingestion
+    accepting_ingestion: AtomicBool,
+    /// Set while the resident set size of the process exceeds `max_rss_bytes` to reject new ingestion
+    memory_pressure: AtomicBool,
+    query_admission: Option<QueryAdmission>,
+    thread_pinning: ThreadPinning,
+    /// Queries that can be killed by their id in `PerfCounter`, see `kill_query`
+    running_queries: Mutex<HashMap<u64, Weak<QueryTask>>>,
+    task_queue: TaskQueue,
+    /// Internal queries run by compactions and materialized views, executed by a separate pool of worker threads
+    background_task_queue: TaskQueue,
+}
+
+/// System tables whose rows are generated from the current state of the database whenever they are queried
+const VIRTUAL_TABLES: [&str; 3] = ["_meta_columns", "_meta_partitions", "_meta_queries"];
+/// Table that queries are recorded in, see `Options::query_log`
+const QUERY_LOG_TABLE: &str = "_query_log";
+/// Integer column whose range is reported as the time range of each partition in `_meta_partitions`
+const TIMESTAMP_COLUMN: &str = "timestamp";
+
+/// Number of times an `UPDATE` is recomputed when partitions it rewrites are compacted concurrently
+const UPDATE_ATTEMPTS: usize = 3;
+
+/// Delay before retrying a WAL flush that failed to persist partitions
+const WAL_FLUSH_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Interval at which the size of the query worker pool is adjusted, see `Options::max_threads`
+const SCALING_INTERVAL: Duration = Duration::from_millis(100);
+/// No query workers are added while CPU utilization is above this fraction
+const MAX_SCALING_CPU_UTILIZATION: f32 = 0.9;
+
+/// Tasks waiting to be executed by one pool of worker threads.
+#[derive(Default)]
+struct TaskQueue {
+    tasks: Mutex<VecDeque<Arc<dyn Task>>>,
+    idle: Condvar,
+    /// Number of tasks currently being executed by worker threads
+    executing: AtomicUsize,
+    /// Number of worker threads of this queue
+    workers: AtomicUsize,
+}
+
+/// Held by a worker thread while it runs. Decrements the number of workers when the thread exits, including by panic,
+/// unless the worker already retired.
+struct Worker<'a> {
+    queue: &'a TaskQueue,
+    retired: bool,
+}
+
+impl TaskQueue {
+    fn is_idle(&self) -> bool {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.iter().all(|task| task.completed()) && self.executing.load(Ordering::SeqCst) == 0
+    }
+
+    /// Whether tasks are waiting while every worker is busy.
+    fn is_backlogged(&self) -> bool {
+        let tasks = self.tasks.lock().unwrap();
+        self.executing.load(Ordering::SeqCst) >= self.workers.load(Ordering::SeqCst)
+            && tasks.iter().any(|task| !task.completed())
+    }
+
+    /// Removes a worker if there are more than `min_workers`.
+    fn retire_worker(&self, min_workers: usize) -> bool {
+        self.workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |workers| {
+                if workers > min_workers {
+                    Some(workers - 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+}
+
+impl<'a> Drop for Worker<'a> {
+    fn drop(&mut self) {
+        if !self.retired {
+            self.queue.workers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
 }
 
 impl InnerLocustDB {
     pub fn new(opts: &Options) -> InnerLocustDB {
-        let lru = Lru::default();
+        let lru = Lru::new(opts.eviction_policy);
         let perf_counter = Arc::new(PerfCounter::default());
-        let storage = opts.db_path.as_ref().map(|path| {
-            let (storage, wal) = Storage::new(path, perf_counter.clone(), false);
+        let storage = match (&opts.object_store, &opts.db_path) {
+            (Some(object_store), _) => Some(Storage::with_writer(
+                Path::new(""),
+                Box::new(ObjectStoreBlobWriter::new(object_store.clone())),
+                perf_counter.clone(),
+                false,
+            )),
+            (None, Some(path)) => Some(Storage::new(path, perf_counter.clone(), false)),
+            (None, None) => None,
+        }
+        .map(|storage| {
+            let (storage, wal) =
+                storage.unwrap_or_else(|err| panic!("Failed to open database: {}", err));
+            let storage = storage
+                .with_partition_compression(opts.partition_compression)
+                .with_data_paths(&opts.data_paths)
+                .with_mmap_columns(opts.mmap_columns)
+                .with_wal_sync(opts.wal_sync)
+                .with_wal_archiving(opts.archive_wal);
+            let wal = match &opts.recovery_target {
+                Some(target) => storage
+                    .recover_to(target, wal)
+                    .unwrap_or_else(|err| panic!("Failed to recover database: {}", err)),
+                None => wal,
+            };
             (Arc::new(storage), wal)
         });
-        let (storage, existing_tables) = match storage {
+        let (storage, existing_tables, views, scheduled_queries) = match storage {
             Some((storage, wal_segments)) => {
-                let tables = Table::restore_tables_from_disk(&storage, wal_segments, &lru);
-                (Some(storage), tables)
+                let mut tables = Table::restore_tables_from_disk(&storage, wal_segments, &lru);
+                let views = storage.meta_store().read().unwrap().views.clone();
+                // Views that have not received any rows yet have no partitions to restore their table from
+                for name in views.keys() {
+                    tables
+                        .entry(name.clone())
+                        .or_insert_with(|| Table::new(name, lru.clone()));
+                }
+                let scheduled_queries = storage
+                    .meta_store()
+                    .read()
+                    .unwrap()
+                    .scheduled_queries
+                    .clone();
+                (Some(storage), tables, views, scheduled_queries)
             }
-            None => (None, HashMap::new()),
+            None => (None, HashMap::new(), HashMap::new(), HashMap::new()),
         };
         let disk_read_scheduler = Arc::new(DiskReadScheduler::new(
             storage
@@ -71,43 +202,220 @@ impl InnerLocustDB {
             lru.clone(),
             opts.read_threads,
             !opts.mem_lz4,
+            // All columns are resident without persistent storage
+            storage.as_ref().map(|_| opts.prefetch_partitions),
+            opts.max_query_disk_reads,
         ));
 
         InnerLocustDB {
             tables: RwLock::new(existing_tables),
+            views: RwLock::new(views),
+            scheduled_queries: Mutex::new(scheduled_queries),
             lru,
             disk_read_scheduler,
+            aggregate_cache: match opts.aggregate_cache_entries {
+                0 => None,
+                entries => Some(Arc::new(AggregateCache::new(entries))),
+            },
             running: AtomicBool::new(true),
+            accepting_ingestion: AtomicBool::new(true),
+            memory_pressure: AtomicBool::new(false),
+            query_admission: opts
+                .max_concurrent_queries
+                .map(|max| QueryAdmission::new(max, opts.query_queue_timeout)),
+            thread_pinning: ThreadPinning::new(opts.thread_affinity),
+            running_queries: Mutex::default(),
 
             storage,
 
             // TODO: doesn't take into account size of existing wal after restart
             wal_size: (Mutex::new(0), Condvar::new()),
+            unpersisted_partitions: Mutex::new(Vec::new()),
 
             opts: opts.clone(),
             perf_counter,
 
-            idle_queue: Condvar::new(),
-            task_queue: Mutex::new(VecDeque::new()),
+            task_queue: TaskQueue::default(),
+            background_task_queue: TaskQueue::default(),
         }
     }
 
     pub fn start_worker_threads(locustdb: &Arc<InnerLocustDB>) {
         for _ in 0..locustdb.opts.threads {
+            InnerLocustDB::spawn_worker(locustdb, false);
+        }
+        for _ in 0..locustdb.opts.background_threads {
+            InnerLocustDB::spawn_worker(locustdb, true);
+        }
+        if locustdb.opts.max_threads > Some(locustdb.opts.threads) {
             let cloned = locustdb.clone();
-            thread::spawn(move || InnerLocustDB::worker_loop(cloned));
+            thread::spawn(move || InnerLocustDB::scale_workers(&cloned));
         }
         let cloned = locustdb.clone();
         thread::spawn(move || InnerLocustDB::enforce_mem_limit(&cloned));
         let cloned = locustdb.clone();
         thread::spawn(move || InnerLocustDB::enforce_wal_limit(&cloned));
+        let cloned = locustdb.clone();
+        thread::spawn(move || InnerLocustDB::run_scheduled_queries(&cloned));
+        if locustdb.disk_read_scheduler.prefetch_partitions().is_some() {
+            for _ in 0..locustdb.opts.read_threads {
+                let cloned = locustdb.clone();
+                thread::spawn(move || InnerLocustDB::service_prefetches(&cloned));
+            }
+        }
+        if locustdb.query_admission.is_some() {
+            let cloned = locustdb.clone();
+            thread::spawn(move || InnerLocustDB::expire_queued_queries(&cloned));
+        }
+        if locustdb.opts.scrub_interval.is_some() {
+            let cloned = locustdb.clone();
+            thread::spawn(move || InnerLocustDB::run_scrub(&cloned));
+        }
+    }
+
+    /// Starts additional query workers, up to `max_threads`, while tasks are waiting for a worker and the CPU is not
+    /// saturated. Workers exit again after they have been idle for `worker_idle_timeout`.
+    fn scale_workers(locustdb: &Arc<InnerLocustDB>) {
+        let max_threads = locustdb.opts.max_threads.unwrap_or(locustdb.opts.threads);
+        let system = System::new();
+        while locustdb.running.load(Ordering::SeqCst) {
+            let cpu_load = system.cpu_load_aggregate().ok();
+            thread::sleep(SCALING_INTERVAL);
+            // Utilization is assumed to be low on platforms where it can't be measured
+            let utilization = cpu_load
+                .and_then(|cpu_load| cpu_load.done().ok())
+                .map_or(0.0, |cpu_load| 1.0 - cpu_load.idle);
+            let queue = &locustdb.task_queue;
+            if utilization < MAX_SCALING_CPU_UTILIZATION
+                && queue.workers.load(Ordering::SeqCst) < max_threads
+                && queue.is_backlogged()
+            {
+                debug!("Adding query worker, CPU utilization {:.2}", utilization);
+                InnerLocustDB::spawn_worker(locustdb, false);
+            }
+        }
+    }
+
+    /// Waits until the query may execute without exceeding `max_concurrent_queries`.
+    pub(crate) async fn admit_query(&self) -> Result<Option<AdmissionPermit<'_>>, QueryError> {
+        match &self.query_admission {
+            Some(admission) => admission.admit().await.map(Some),
+            None => Ok(None),
+        }
     }
 
     pub fn snapshot(&self, table: &str) -> Option<Vec<Arc<Partition>>> {
+        if VIRTUAL_TABLES.contains(&table) {
+            return Some(self.virtual_table(table).snapshot());
+        }
         let tables = self.tables.read().unwrap();
         tables.get(table).map(|t| t.snapshot())
     }
 
+    /// Materializes the system table `name` from the current state of the database. The table is not registered, so its
+    /// partitions are dropped once the query has completed.
+    fn virtual_table(&self, name: &str) -> Table {
+        let mut columns = HashMap::<String, Vec<RawVal>>::new();
+        let mut push = |column: &str, value: RawVal| {
+            columns.entry(column.to_string()).or_default().push(value)
+        };
+        let int = |value: usize| RawVal::Int(value as i64);
+        let optional_int = |value: Option<i64>| value.map_or(RawVal::Null, RawVal::Int);
+        match name {
+            "_meta_columns" => {
+                let tables = self.tables.read().unwrap();
+                for table in tables.values() {
+                    let partitions = table.snapshot();
+                    let statistics = table.column_statistics();
+                    for (column, statistics) in
+                        statistics.into_iter().sorted_by(|a, b| a.0.cmp(&b.0))
+                    {
+                        let handles = partitions
+                            .iter()
+                            .filter_map(|partition| partition.cols.get(&column))
+                            .collect::<Vec<_>>();
+                        push("table_name", RawVal::Str(table.name().to_string()));
+                        push("column_name", RawVal::Str(column));
+                        push("partitions", int(handles.len()));
+                        push(
+                            "resident_partitions",
+                            int(handles.iter().filter(|handle| handle.is_resident()).count()),
+                        );
+                        push(
+                            "resident_bytes",
+                            int(handles
+                                .iter()
+                                .map(|handle| handle.heap_size_of_children())
+                                .sum()),
+                        );
+                        push("null_count", int(statistics.null_count));
+                        push(
+                            "distinct_values",
+                            optional_int(statistics.distinct_values.map(|d| d as i64)),
+                        );
+                        push("min", optional_int(statistics.range.map(|range| range.0)));
+                        push("max", optional_int(statistics.range.map(|range| range.1)));
+                    }
+                }
+            }
+            "_meta_partitions" => {
+                let tables = self.tables.read().unwrap();
+                for table in tables.values() {
+                    for partition in table.partitions().into_iter().sorted_by_key(|p| p.id) {
+                        let time_range = partition.value_range(TIMESTAMP_COLUMN);
+                        push("id", RawVal::Int(partition.id as i64));
+                        push("table_name", RawVal::Str(table.name().to_string()));
+                        push("rows", int(partition.len()));
+                        push("columns", int(partition.cols.len()));
+                        push("size_bytes", int(partition.total_size_bytes()));
+                        push("resident_bytes", int(partition.heap_size_of_children()));
+                        push(
+                            "min_timestamp",
+                            optional_int(time_range.map(|range| range.0)),
+                        );
+                        push(
+                            "max_timestamp",
+                            optional_int(time_range.map(|range| range.1)),
+                        );
+                    }
+                }
+            }
+            "_meta_queries" => {
+                for query in self.perf_counter.query_snapshots() {
+                    let status = if query.completed {
+                        "completed"
+                    } else {
+                        "running"
+                    };
+                    push("id", RawVal::Int(query.id as i64));
+                    push("query", RawVal::Str(query.query));
+                    push("status", RawVal::Str(status.to_string()));
+                    push("runtime_ns", RawVal::Int(query.stats.runtime_ns as i64));
+                    push("rows_scanned", RawVal::Int(query.stats.rows_scanned as i64));
+                    push(
+                        "partitions_scanned",
+                        RawVal::Int(query.stats.partitions_scanned as i64),
+                    );
+                    push(
+                        "disk_read_bytes",
+                        RawVal::Int(query.stats.disk_read_bytes as i64),
+                    );
+                    push(
+                        "bytes_scanned",
+                        RawVal::Int(query.stats.bytes_scanned as i64),
+                    );
+                }
+            }
+            _ => panic!("Unknown virtual table {}", name),
+        }
+        // Not tracked by the LRU of the database, so the virtual table never causes other columns to be evicted
+        let table = Table::new(name, Lru::default());
+        if !columns.is_empty() {
+            table.ingest_heterogeneous(columns);
+        }
+        table
+    }
+
     pub fn full_snapshot(&self) -> Vec<Vec<Arc<Partition>>> {
         let tables = self.tables.read().unwrap();
         tables.values().map(|t| t.snapshot()).collect()
@@ -115,247 +423,1731 @@ impl InnerLocustDB {
 
     pub fn stop(&self) {
         // TODO: ensure all pending ingestion tasks are completed and new requests are rejected
-        // Acquire task_queue_guard to make sure that there are no threads that have checked self.running but not waited on idle_queue yet.
+        // Acquire task queue guards to make sure that there are no threads that have checked self.running but not waited on the idle condvar yet.
         info!("Stopping database...");
         self.running.store(false, Ordering::SeqCst);
-        let _guard = self.task_queue.lock();
-        self.running.store(false, Ordering::SeqCst);
-        self.idle_queue.notify_all();
+        for queue in [&self.task_queue, &self.background_task_queue] {
+            let _guard = queue.tasks.lock();
+            self.running.store(false, Ordering::SeqCst);
+            queue.idle.notify_all();
+        }
+    }
+
+    /// Rejects new ingestion, waits for all queued and running tasks to complete, flushes buffered rows to partitions so
+    /// that the WAL does not have to be replayed on restart, and stops the worker threads.
+    pub fn shutdown(&self) {
+        info!("Shutting down database...");
+        {
+            // Waits for ingestion that is writing to the WAL to complete
+            let _wal_size = self.wal_size.0.lock().unwrap();
+            self.accepting_ingestion.store(false, Ordering::SeqCst);
+        }
+        while !(self.task_queue.is_idle() && self.background_task_queue.is_idle()) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // The WAL is kept if rows cannot be persisted and replayed on restart
+        if let Err(err) = self.flush_buffers() {
+            log::error!("Failed to flush buffered rows on shutdown: {}", err);
+        }
+        self.stop();
+    }
+
+    pub(crate) fn ensure_accepting_ingestion(&self) -> Result<(), QueryError> {
+        if !self.accepting_ingestion.load(Ordering::SeqCst) {
+            Err(QueryError::ShuttingDown)
+        } else if self.memory_pressure.load(Ordering::SeqCst) {
+            Err(QueryError::Overloaded(
+                "process memory usage exceeds max_rss_bytes".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn spawn_worker(locustdb: &Arc<InnerLocustDB>, background: bool) {
+        let queue = if background {
+            &locustdb.background_task_queue
+        } else {
+            &locustdb.task_queue
+        };
+        queue.workers.fetch_add(1, Ordering::SeqCst);
+        let cloned = locustdb.clone();
+        thread::spawn(move || InnerLocustDB::worker_loop(cloned, background));
+    }
+
+    fn worker_loop(locustdb: Arc<InnerLocustDB>, background: bool) {
+        let (queue, min_workers) = if background {
+            (
+                &locustdb.background_task_queue,
+                locustdb.opts.background_threads,
+            )
+        } else {
+            (&locustdb.task_queue, locustdb.opts.threads)
+        };
+        // Only workers of a dynamically sized pool exit when idle
+        let idle_timeout = if !background && locustdb.opts.max_threads > Some(min_workers) {
+            Some(locustdb.opts.worker_idle_timeout)
+        } else {
+            None
+        };
+        let mut worker = Worker {
+            queue,
+            retired: false,
+        };
+        locustdb.thread_pinning.pin_current_thread();
+        while locustdb.running.load(Ordering::SeqCst) {
+            match InnerLocustDB::await_task(&locustdb, queue, idle_timeout) {
+                Some(task) => {
+                    task.execute();
+                    queue.executing.fetch_sub(1, Ordering::SeqCst);
+                }
+                None if idle_timeout.is_some() && queue.retire_worker(min_workers) => {
+                    worker.retired = true;
+                    break;
+                }
+                None => {}
+            }
+        }
+        drop(worker);
+        drop(locustdb) // Make clippy happy
+    }
+
+    /// Returns `None` if the database is stopped or no task arrives within `idle_timeout`.
+    fn await_task(
+        ldb: &InnerLocustDB,
+        queue: &TaskQueue,
+        idle_timeout: Option<Duration>,
+    ) -> Option<Arc<dyn Task>> {
+        let mut task_queue = queue.tasks.lock().unwrap();
+        while task_queue.is_empty() {
+            if !ldb.running.load(Ordering::SeqCst) {
+                return None;
+            }
+            match idle_timeout {
+                Some(timeout) => {
+                    let (guard, result) = queue.idle.wait_timeout(task_queue, timeout).unwrap();
+                    task_queue = guard;
+                    if result.timed_out() && task_queue.is_empty() {
+                        return None;
+                    }
+                }
+                None => task_queue = queue.idle.wait(task_queue).unwrap(),
+            }
+        }
+        while let Some(task) = task_queue.pop_front() {
+            if task.completed() {
+                continue;
+            }
+            // Multithreaded tasks remain queued until all their work has been claimed. Requeueing them at the back
+            // distributes workers round-robin across concurrent tasks.
+            if task.multithreaded() {
+                task_queue.push_back(task.clone());
+            }
+            if !task_queue.is_empty() {
+                queue.idle.notify_one();
+            }
+            // Incremented while holding the lock so that `is_idle` never observes the task in neither state
+            queue.executing.fetch_add(1, Ordering::SeqCst);
+            return Some(task);
+        }
+        None
+    }
+
+    pub fn schedule<T: Task + 'static>(&self, task: T) {
+        // This function may be entered by event loop thread so it's important it always returns quickly.
+        // Since the task queue locks are never held for long, we should be fine.
+        let mut task_queue = self.task_queue.tasks.lock().unwrap();
+        task_queue.push_back(Arc::new(task));
+        self.task_queue.idle.notify_one();
+    }
+
+    /// Schedules a query so that it can be killed with `kill_query` while it runs.
+    pub fn schedule_query(&self, id: u64, task: QueryTask) {
+        let task = Arc::new(task);
+        {
+            let mut running_queries = self.running_queries.lock().unwrap();
+            running_queries.retain(|_, task| task.strong_count() > 0);
+            running_queries.insert(id, Arc::downgrade(&task));
+        }
+        let mut task_queue = self.task_queue.tasks.lock().unwrap();
+        task_queue.push_back(task);
+        self.task_queue.idle.notify_one();
+    }
+
+    /// Checks that worker threads are alive, the WAL is writable and ingestion is not blocked.
+    pub fn health(&self) -> Health {
+        let running = self.running.load(Ordering::SeqCst);
+        let workers = |queue: &TaskQueue| {
+            if running {
+                queue.workers.load(Ordering::SeqCst)
+            } else {
+                0
+            }
+        };
+        Health {
+            workers: workers(&self.task_queue),
+            min_workers: self.opts.threads,
+            background_workers: workers(&self.background_task_queue),
+            min_background_workers: self.opts.background_threads,
+            wal_error: self
+                .storage
+                .as_ref()
+                .and_then(|storage| storage.check_wal_writable().err())
+                .map(|err| err.to_string()),
+            wal_size_bytes: self.perf_counter.wal_size_bytes(),
+            max_wal_size_bytes: self.opts.max_wal_size_bytes,
+            memory_pressure: self.memory_pressure.load(Ordering::SeqCst),
+            accepting_ingestion: self.accepting_ingestion.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Kills the running query with id `id`. Returns false if there is no such query or it has already completed.
+    pub fn kill_query(&self, id: u64) -> bool {
+        let task = self
+            .running_queries
+            .lock()
+            .unwrap()
+            .get(&id)
+            .and_then(Weak::upgrade);
+        task.map_or(false, |task| task.kill())
+    }
+
+    /// Schedules `task` on the background worker threads so that it does not delay queries.
+    fn schedule_background<T: Task + 'static>(&self, task: T) {
+        let mut task_queue = self.background_task_queue.tasks.lock().unwrap();
+        task_queue.push_back(Arc::new(task));
+        self.background_task_queue.idle.notify_one();
+    }
+
+    pub fn ingest_single(&self, table: &str, row: Vec<(String, RawVal)>) {
+        if let Some((schema, enforcement)) = self.enforced_schema(table) {
+            if let Err(err) =
+                schema.validate_row(row.iter().map(|(name, value)| (name.as_str(), value)))
+            {
+                log::debug!(
+                    "Row for table {} does not conform to schema: {}",
+                    table,
+                    err
+                );
+                self.reject_rows(table, enforcement, 1, |dead_letter| dead_letter.ingest(row));
+                return;
+            }
+        }
+        if let Some(key_column) = self.deduplication_key(table) {
+            let key = row
+                .iter()
+                .find(|(name, _)| *name == key_column)
+                .map_or(RawVal::Null, |(_, value)| value.clone());
+            if !self.first_occurrences(table, vec![key])[0] {
+                log::debug!("Dropped duplicate row for table {}", table);
+                return;
+            }
+        }
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        tables.get(table).unwrap().ingest(row)
+    }
+
+    pub fn ingest_efficient(&self, events: EventBuffer) -> Result<(), QueryError> {
+        let mut wal_size = self.wal_capacity()?;
+        self.ensure_accepting_ingestion()?;
+        // Rows are filtered before writing the WAL so that rejected rows are not restored on restart
+        let events = self.enforce_event_schemas(events);
+        // Keys are recorded while holding the WAL lock, which prevents WAL flushes from forgetting them, and are
+        // forgotten again if the rows are not written to the WAL so that a retried request is not dropped as duplicate
+        let (events, recorded_keys) = self.deduplicate_events(events);
+
+        if let Some(storage) = &self.storage {
+            let persisted = storage.persist_wal_segment(WALSegment {
+                id: 0,
+                data: Cow::Borrowed(&events),
+                timestamp_ms: 0,
+            });
+            let bytes_written = match persisted {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    self.forget_keys(recorded_keys);
+                    return Err(err);
+                }
+            };
+            *wal_size += bytes_written;
+            self.perf_counter.set_wal_size(*wal_size);
+        }
+        // TODO: code duplicated in Table::restore_tables_from_disk
+        for (table, data) in events.tables {
+            self.create_if_empty(&table);
+            let tables = self.tables.read().unwrap();
+            let table = tables.get(&table).unwrap();
+            let rows = data.len;
+            // TODO: eliminate conversion
+            let columns = data
+                .columns
+                .into_iter()
+                .map(|(k, v)| {
+                    let col = match v.data {
+                        ColumnData::Dense(data) => {
+                            if (data.len() as u64) < rows {
+                                InputColumn::NullableFloat(
+                                    rows,
+                                    data.into_iter()
+                                        .enumerate()
+                                        .map(|(i, v)| (i as u64, v))
+                                        .collect(),
+                                )
+                            } else {
+                                InputColumn::Float(data)
+                            }
+                        }
+                        ColumnData::Sparse(data) => InputColumn::NullableFloat(rows, data),
+                        ColumnData::Mixed(data) => InputColumn::Mixed(rows, data),
+                    };
+                    (k, col)
+                })
+                .collect();
+            table.ingest_homogeneous(columns);
+        }
+
+        self.wal_size.1.notify_all();
+        Ok(())
+    }
+
+    /// Applies the backpressure of `ingest_efficient` to ingestion that bypasses the WAL, e.g. CSV loading, which
+    /// would otherwise still be accepted while the WAL exceeds `max_wal_size_bytes`.
+    pub(crate) fn ensure_wal_capacity(&self) -> Result<(), QueryError> {
+        self.wal_capacity().map(|_| ())
+    }
+
+    /// Waits until the WAL does not exceed `max_wal_size_bytes` and returns the locked WAL size, or fails with
+    /// `QueryError::Throttled` if `wal_backpressure` rejects ingestion instead.
+    fn wal_capacity(&self) -> Result<MutexGuard<'_, u64>, QueryError> {
+        let (wal_size, wal_condvar) = &self.wal_size;
+        let mut wal_size = wal_size.lock().unwrap();
+        while *wal_size > self.opts.max_wal_size_bytes {
+            if let WalBackpressure::Reject(retry_after) = self.opts.wal_backpressure {
+                return Err(QueryError::Throttled(retry_after));
+            }
+            wal_size = wal_condvar.wait(wal_size).unwrap();
+        }
+        Ok(wal_size)
+    }
+
+    /// Creates new partition from currently open buffer in each table, persists partitions to disk, and deletes WAL.
+    /// If the partitions cannot be persisted the WAL is kept, and the partitions are persisted by the next flush.
+    pub(crate) fn wal_flush(&self) -> Result<(), QueryError> {
+        let start_time = Instant::now();
+        let tables = self.tables.read().unwrap();
+        // Views are updated before batching so that new view rows are persisted together with the source rows
+        for view in self.views.read().unwrap().values() {
+            self.update_view(&tables, view);
+        }
+        let mut new_partitions = mem::take(&mut *self.unpersisted_partitions.lock().unwrap());
+        let mut batched_tables = Vec::new();
+        let mut compactions = Vec::new();
+        let default_policy = CompactionPolicy::SizeTiered {
+            combine_factor: self.opts.partition_combine_factor,
+        };
+        for table in tables.values() {
+            if let Some(partition) = table.batch() {
+                new_partitions.push(self.partition_metadata(table, &partition));
+                batched_tables.push(table);
+            }
+
+            if let Some(compaction) = table.plan_compaction(&default_policy) {
+                compactions.push((table.name(), table.next_partition_id(), compaction));
+            }
+        }
+
+        if let Some(s) = self.storage.as_ref() {
+            let persisted = batched_tables
+                .into_iter()
+                .try_for_each(|table| self.persist_shared_dictionaries(table))
+                .and_then(|_| s.persist_partitions_delete_wal(&new_partitions));
+            if let Err(err) = persisted {
+                // Compactions are skipped since they would combine partitions that have not been persisted
+                *self.unpersisted_partitions.lock().unwrap() = new_partitions;
+                return Err(err);
+            }
+        }
+
+        // The WAL has been deleted at this point, a failed compaction is retried by the next flush
+        for (table, id, (range, parts)) in compactions {
+            if let Err(err) = self.compact(&tables, table, id, range, &parts) {
+                log::error!("Failed to compact partitions of table {}: {}", table, err);
+            }
+        }
+
+        log::info!("Performed wal flush in {:?}", start_time.elapsed());
+        Ok(())
+    }
+
+    /// Combines the partitions `parts` of `table`, which cover the rows in `range`, into a new partition with id `id`.
+    fn compact(
+        &self,
+        tables: &HashMap<String, Table>,
+        table: &str,
+        id: PartitionID,
+        range: Range<usize>,
+        parts: &[PartitionID],
+    ) -> Result<(), QueryError> {
+        // get table, create new merged partition/sub-partitions (not registered with table)
+        // - get names of all columns
+        // - run query for each column, construct Column
+        // - create subpartitions
+        let colnames = tables[table].column_names(parts);
+        let mut columns = Vec::with_capacity(colnames.len());
+        let mut bloom_filters = HashMap::new();
+        let data = tables[table].snapshot_parts(parts);
+        // Rows of all columns are reordered by the sort key, which keeps the value ranges of partitions narrow
+        let order = tables[table]
+            .sort_key()
+            .filter(|sort_key| colnames.contains(sort_key))
+            .map(|sort_key| {
+                self.read_column(table, &sort_key, data.clone())
+                    .sort_order()
+            });
+        for column in &colnames {
+            let mut column_builder = MixedCol::default();
+            let values = self.read_column(table, column, data.clone());
+            let values = match &order {
+                Some(order) => values.permute(order),
+                None => values,
+            };
+            match values {
+                BasicTypeColumn::Int(ints) => column_builder.push_ints(ints),
+                BasicTypeColumn::Float(floats) => column_builder.push_floats(floats),
+                BasicTypeColumn::String(strings) => column_builder.push_strings(strings),
+                BasicTypeColumn::Null(count) => column_builder.push_nulls(count),
+                BasicTypeColumn::Mixed(raws) => {
+                    raws.into_iter().for_each(|r| column_builder.push(r))
+                }
+            }
+            assert_eq!(
+                range.len(),
+                column_builder.len(),
+                "range={range:?}, column_builder.len() = {}, table = {table},  column = {column}",
+                column_builder.len(),
+            );
+            if let Some(filter) = tables[table].bloom_filter(column, &column_builder) {
+                bloom_filters.insert(column.clone(), filter);
+            }
+            let mut dictionaries = tables[table].shared_dictionaries();
+            columns.push(column_builder.finalize(column, dictionaries.get_mut(column)));
+        }
+        let (metadata, subpartitions) = subpartition(&self.opts, columns.clone());
+        self.persist_shared_dictionaries(&tables[table])?;
+        // write subpartitions to disk, update metastore unlinking old partitions, delete old partitions
+        if let Some(storage) = self.storage.as_ref() {
+            storage.compact(
+                table,
+                id,
+                metadata,
+                subpartitions,
+                bloom_filters.clone(),
+                parts,
+                range.start,
+            )?;
+        }
+
+        // replace old partitions with new partition
+        tables[table].compact(id, range.start, columns, bloom_filters, parts);
+        Ok(())
+    }
+
+    /// Splits the columns of a new partition into subpartitions and creates the metadata used to persist them.
+    fn partition_metadata(
+        &self,
+        table: &Table,
+        partition: &Partition,
+    ) -> (PartitionMetadata, Vec<Vec<Arc<Column>>>) {
+        let columns: Vec<_> = partition
+            .col_handles()
+            .map(|c| c.try_get().as_ref().unwrap().clone())
+            .sorted_by(|a, b| a.name().cmp(b.name()));
+        let (metadata, subpartitions) = subpartition(&self.opts, columns);
+        let column_name_to_subpartition_index = subpartitions
+            .iter()
+            .enumerate()
+            .flat_map(|(i, subpartition)| {
+                subpartition
+                    .iter()
+                    .map(move |column| (column.name().to_string(), i))
+            })
+            .collect();
+        let partition_metadata = PartitionMetadata {
+            id: partition.id,
+            tablename: table.name().to_string(),
+            len: partition.len(),
+            offset: partition.range().start,
+            subpartitions: metadata,
+            column_name_to_subpartition_index,
+            column_ranges: HashMap::new(),
+            bloom_filters: partition.bloom_filters().clone(),
+            column_stats: HashMap::new(),
+        };
+        (partition_metadata, subpartitions)
+    }
+
+    /// Appends the view query evaluated over rows of the source table that have not been batched yet to the view table.
+    fn update_view(&self, tables: &HashMap<String, Table>, view: &MaterializedView) {
+        if let Some(partition) = tables
+            .get(&view.source)
+            .and_then(|source| source.buffered())
+        {
+            self.append_to_view(tables, view, partition);
+        }
+    }
+
+    /// Appends the view query evaluated over the rows of `partition` to the view table.
+    fn append_to_view(
+        &self,
+        tables: &HashMap<String, Table>,
+        view: &MaterializedView,
+        partition: Arc<Partition>,
+    ) {
+        let target = match tables.get(&view.name) {
+            Some(target) => target,
+            None => return,
+        };
+        match view
+            .query()
+            .and_then(|query| self.query_columns(query, vec![partition]))
+        {
+            Ok(columns) => {
+                if columns.values().any(|column| !column.is_empty()) {
+                    target.ingest_heterogeneous(columns);
+                }
+            }
+            Err(err) => {
+                log::error!("Failed to update materialized view {}: {}", view.name, err)
+            }
+        }
+    }
+
+    /// Runs `query` over the partitions in `data` and returns the result columns.
+    fn query_columns(
+        &self,
+        query: Query,
+        data: Vec<Arc<Partition>>,
+    ) -> Result<HashMap<String, Vec<RawVal>>, QueryError> {
+        let (sender, receiver) = oneshot::channel();
+        let query_task = QueryTask::new(
+            query,
+            false,
+            false,
+            vec![],
+            data,
+            self.disk_read_scheduler().clone(),
+            SharedSender::new(sender),
+            self.opts.batch_size,
+        )?;
+        self.schedule_background(query_task);
+        let output = block_on(receiver).unwrap()?;
+        Ok(output
+            .columns
+            .into_iter()
+            .map(|(name, column)| (name, column.into_raw_vals()))
+            .collect())
+    }
+
+    /// Reads all values of `column` from the partitions in `data`, ordered by row offset.
+    fn read_column(&self, table: &str, column: &str, data: Vec<Arc<Partition>>) -> BasicTypeColumn {
+        let query = Query::read_column(table, column);
+        let (sender, receiver) = oneshot::channel();
+        let query_task = QueryTask::new(
+            query,
+            false,
+            false,
+            vec![],
+            data,
+            self.disk_read_scheduler().clone(),
+            SharedSender::new(sender),
+            self.opts.batch_size,
+        )
+        .unwrap();
+        self.schedule_background(query_task);
+        let result = block_on(receiver).unwrap().unwrap();
+        result.columns.into_iter().next().unwrap().1
+    }
+
+    pub fn restore(&self, id: PartitionID, column: Column) {
+        let column = Arc::new(column);
+        for table in self.tables.read().unwrap().values() {
+            table.restore(id, &column);
+        }
+    }
+
+    /// Ingests batches of columns into `table` like `ingest_column_batches`.
+    pub fn ingest_batches(
+        &self,
+        table: &str,
+        batches: Vec<HashMap<String, InputColumn>>,
+    ) -> Result<(), QueryError> {
+        self.ingest_column_batches(
+            table,
+            batches.into_iter().map(|columns| {
+                columns
+                    .into_iter()
+                    .map(|(name, column)| (name, column.into_raw_vals()))
+                    .collect()
+            }),
+        )
+    }
+
+    /// Ingests batches of columns into `table` as a single WAL segment, so that either all or none of the rows are
+    /// ingested. Rows are persisted to partitions by the next WAL flush.
+    pub fn ingest_column_batches(
+        &self,
+        table: &str,
+        batches: impl IntoIterator<Item = HashMap<String, Vec<RawVal>>>,
+    ) -> Result<(), QueryError> {
+        let mut buffer = TableBuffer::default();
+        for columns in batches {
+            buffer.append(TableBuffer::from_columns(columns));
+        }
+        if buffer.len == 0 {
+            return Ok(());
+        }
+        let mut events = EventBuffer::default();
+        events.tables.insert(table.to_string(), buffer);
+        self.ingest_efficient(events)
+    }
+
+    /// Ingests historical rows into new partitions that each cover a single `bucket_width` wide interval of the integer
+    /// timestamps in `time_column`, instead of appending them to the buffer that holds the most recent rows.
+    /// Rows are sorted by timestamp within each partition. Backfilled partitions are never compacted, so late-arriving
+    /// data does not widen the time range covered by partitions of recent data. Returns the number of ingested rows.
+    pub fn ingest_backfill(
+        &self,
+        table: &str,
+        time_column: &str,
+        bucket_width: i64,
+        columns: HashMap<String, Vec<RawVal>>,
+    ) -> Result<usize, QueryError> {
+        if bucket_width <= 0 {
+            bail!(
+                QueryError::ParseError,
+                "Backfill bucket width must be positive, got {}",
+                bucket_width
+            );
+        }
+        let columns = self.enforce_schema(table, columns);
+        let len = columns.values().map(Vec::len).max().unwrap_or(0);
+        let timestamps = match columns.get(time_column) {
+            Some(timestamps) => timestamps,
+            None if len == 0 => return Ok(0),
+            None => bail!(
+                QueryError::ParseError,
+                "Backfill rows are missing time column {}",
+                time_column
+            ),
+        };
+        let mut buckets = BTreeMap::<i64, Vec<(i64, usize)>>::new();
+        for row in 0..len {
+            match timestamps.get(row).unwrap_or(&RawVal::Null) {
+                &RawVal::Int(timestamp) => buckets
+                    .entry(timestamp.div_euclid(bucket_width))
+                    .or_default()
+                    .push((timestamp, row)),
+                value => bail!(
+                    QueryError::TypeError,
+                    "Time column {} contains non-integer value {}",
+                    time_column,
+                    value
+                ),
+            }
+        }
+
+        self.create_if_empty(table);
+        let (wal_size, _) = &self.wal_size;
+        // Prevents compactions from running concurrently with the creation of backfilled partitions
+        let wal_size = wal_size.lock().unwrap();
+        let tables = self.tables.read().unwrap();
+        let views = self.views.read().unwrap();
+        let mut new_partitions = Vec::with_capacity(buckets.len());
+        let mut updated_views = false;
+        for mut rows in buckets.into_values() {
+            rows.sort_unstable();
+            let bucket = columns
+                .iter()
+                .map(|(name, values)| {
+                    let values = rows
+                        .iter()
+                        .map(|&(_, row)| values.get(row).cloned().unwrap_or(RawVal::Null))
+                        .collect::<Vec<_>>();
+                    (name.clone(), values)
+                })
+                .collect();
+            let partition = tables[table].ingest_backfill(bucket);
+            for view in views.values().filter(|view| view.source == table) {
+                self.append_to_view(&tables, view, partition.clone());
+                updated_views = true;
+            }
+            new_partitions.push(self.partition_metadata(&tables[table], &partition));
+        }
+        self.persist_shared_dictionaries(&tables[table])?;
+        if let Some(storage) = &self.storage {
+            storage.persist_backfill_partitions(new_partitions)?;
+        }
+        drop(views);
+        drop(tables);
+        drop(wal_size);
+        // View rows are buffered and have to be persisted separately
+        if updated_views {
+            self.flush_buffers()?;
+        }
+        Ok(len)
+    }
+
+    /// Writes all buffered rows to partitions, used after ingesting rows that bypass the WAL.
+    pub(crate) fn flush_buffers(&self) -> Result<FlushReport, QueryError> {
+        let start_time = Instant::now();
+        let (wal_size, wal_condvar) = &self.wal_size;
+        let mut wal_size = wal_size.lock().unwrap();
+        let partition_bytes = self.perf_counter.disk_write_new_partition_bytes();
+        let compaction_bytes = self.perf_counter.disk_write_compaction_bytes();
+        self.wal_flush()?;
+        let report = FlushReport {
+            wal_bytes: *wal_size,
+            partition_bytes_written: self.perf_counter.disk_write_new_partition_bytes()
+                - partition_bytes,
+            compaction_bytes_written: self.perf_counter.disk_write_compaction_bytes()
+                - compaction_bytes,
+            duration_ns: start_time.elapsed().as_nanos() as u64,
+        };
+        *wal_size = 0;
+        self.perf_counter.set_wal_size(0);
+        drop(wal_size);
+        wal_condvar.notify_all();
+        Ok(report)
+    }
+
+    /// Compacts the partitions of `table` according to its compaction policy until no more partitions qualify,
+    /// without waiting for the next WAL flush.
+    pub fn compact_table(&self, table: &str) -> Result<CompactionReport, QueryError> {
+        let start_time = Instant::now();
+        let (wal_size, _) = &self.wal_size;
+        // Prevents compactions from running concurrently with WAL flushes
+        let _wal_size = wal_size.lock().unwrap();
+        let tables = self.tables.read().unwrap();
+        let partitions_before = match tables.get(table) {
+            Some(t) => t.partitions().len(),
+            None => bail!(QueryError::SchemaError, "Table {} does not exist", table),
+        };
+        let compaction_bytes = self.perf_counter.disk_write_compaction_bytes();
+        let default_policy = CompactionPolicy::SizeTiered {
+            combine_factor: self.opts.partition_combine_factor,
+        };
+        let mut compactions = 0;
+        // Terminates because every compaction reduces the number of partitions
+        while let Some((range, parts)) = tables[table].plan_compaction(&default_policy) {
+            let id = tables[table].next_partition_id();
+            self.compact(&tables, table, id, range, &parts)?;
+            compactions += 1;
+        }
+        log::info!(
+            "Performed {} compactions of table {} in {:?}",
+            compactions,
+            table,
+            start_time.elapsed()
+        );
+        Ok(CompactionReport {
+            compactions,
+            partitions_before,
+            partitions_after: tables[table].partitions().len(),
+            bytes_written: self.perf_counter.disk_write_compaction_bytes() - compaction_bytes,
+            duration_ns: start_time.elapsed().as_nanos() as u64,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn ingest_homogeneous(&self, table: &str, columns: HashMap<String, InputColumn>) {
+        if self.enforced_schema(table).is_some() || self.deduplication_key(table).is_some() {
+            let columns = columns
+                .into_iter()
+                .map(|(name, column)| (name, column.into_raw_vals()))
+                .collect();
+            return self.ingest_heterogeneous(table, columns);
+        }
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        tables.get(table).unwrap().ingest_homogeneous(columns)
+    }
+
+    #[allow(dead_code)]
+    pub fn ingest_heterogeneous(&self, table: &str, columns: HashMap<String, Vec<RawVal>>) {
+        let columns = self.enforce_schema(table, columns);
+        let columns = self.deduplicate(table, columns);
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        tables.get(table).unwrap().ingest_heterogeneous(columns)
+    }
+
+    /// Returns the key column of `table` if ingestion into `table` drops rows with duplicate keys.
+    fn deduplication_key(&self, table: &str) -> Option<String> {
+        let tables = self.tables.read().unwrap();
+        Some(tables.get(table)?.deduplication()?.key_column)
+    }
+
+    /// Records the given row keys of `table` and returns which rows have not been seen before.
+    fn first_occurrences(&self, table: &str, keys: Vec<RawVal>) -> Vec<bool> {
+        let tables = self.tables.read().unwrap();
+        match tables.get(table) {
+            Some(table) => table.first_occurrences(keys),
+            None => vec![true; keys.len()],
+        }
+    }
+
+    /// Removes rows whose key was already ingested into `table`.
+    fn deduplicate(
+        &self,
+        table: &str,
+        columns: HashMap<String, Vec<RawVal>>,
+    ) -> HashMap<String, Vec<RawVal>> {
+        let key_column = match self.deduplication_key(table) {
+            Some(key_column) => key_column,
+            None => return columns,
+        };
+        let len = columns.values().map(Vec::len).max().unwrap_or(0);
+        let keys = (0..len)
+            .map(|i| {
+                columns
+                    .get(&key_column)
+                    .and_then(|keys| keys.get(i))
+                    .cloned()
+                    .unwrap_or(RawVal::Null)
+            })
+            .collect();
+        let keep = self.first_occurrences(table, keys);
+        let duplicates = keep.iter().filter(|&&keep| !keep).count();
+        if duplicates == 0 {
+            return columns;
+        }
+        log::debug!("Dropped {} duplicate rows for table {}", duplicates, table);
+        columns
+            .into_iter()
+            .map(|(name, values)| {
+                let values = values
+                    .into_iter()
+                    .zip(&keep)
+                    .filter_map(|(value, &keep)| keep.then_some(value))
+                    .collect();
+                (name, values)
+            })
+            .collect()
+    }
+
+    /// Removes rows whose key was already ingested into their table from `events`. Also returns the keys of the
+    /// remaining rows of each table, which have been recorded as seen.
+    fn deduplicate_events(
+        &self,
+        mut events: EventBuffer,
+    ) -> (EventBuffer, Vec<(String, Vec<RawVal>)>) {
+        let mut recorded_keys = Vec::new();
+        for (table, buffer) in &mut events.tables {
+            let key_column = match self.deduplication_key(table) {
+                Some(key_column) => key_column,
+                None => continue,
+            };
+            let rows = mem::take(buffer).into_rows();
+            let keys = rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .find(|(name, _)| *name == key_column)
+                        .map_or(RawVal::Null, |(_, value)| value.clone())
+                })
+                .collect::<Vec<_>>();
+            let keep = self.first_occurrences(table, keys.clone());
+            let mut recorded = Vec::new();
+            for ((row, key), keep) in rows.into_iter().zip(keys).zip(keep) {
+                if keep {
+                    buffer.push_val_row(row);
+                    recorded.push(key);
+                }
+            }
+            recorded_keys.push((table.clone(), recorded));
+        }
+        (events, recorded_keys)
+    }
+
+    /// Forgets keys recorded by `deduplicate_events` for rows that were not ingested.
+    fn forget_keys(&self, keys: Vec<(String, Vec<RawVal>)>) {
+        let tables = self.tables.read().unwrap();
+        for (table, keys) in keys {
+            if let Some(table) = tables.get(&table) {
+                table.forget_keys(&keys);
+            }
+        }
+    }
+
+    /// Returns the declared schema of `table` and how it is enforced, unless ingestion into `table` is permissive.
+    fn enforced_schema(&self, table: &str) -> Option<(TableSchema, SchemaEnforcement)> {
+        let tables = self.tables.read().unwrap();
+        let table = tables.get(table)?;
+        match table.schema_enforcement() {
+            SchemaEnforcement::Permissive => None,
+            enforcement => Some((table.schema()?, enforcement)),
+        }
+    }
+
+    /// Removes rows that do not conform to the schema of `table` and rejects them or ingests them into the dead letter
+    /// table. Values of accepted rows are coerced to the declared column types.
+    fn enforce_schema(
+        &self,
+        table: &str,
+        columns: HashMap<String, Vec<RawVal>>,
+    ) -> HashMap<String, Vec<RawVal>> {
+        let (schema, enforcement) = match self.enforced_schema(table) {
+            Some(enforced_schema) => enforced_schema,
+            None => return columns,
+        };
+        let len = columns.values().map(Vec::len).max().unwrap_or(0);
+        let null = RawVal::Null;
+        let valid = (0..len)
+            .map(|i| {
+                let row = columns
+                    .iter()
+                    .map(|(name, values)| (name.as_str(), values.get(i).unwrap_or(&null)));
+                schema.validate_row(row).is_ok()
+            })
+            .collect::<Vec<_>>();
+        let rejected_rows = valid.iter().filter(|&&valid| !valid).count();
+        if rejected_rows == 0 {
+            return columns
+                .into_iter()
+                .map(|(name, values)| {
+                    let values = values
+                        .into_iter()
+                        .map(|value| schema.coerce(&name, value))
+                        .collect();
+                    (name, values)
+                })
+                .collect();
+        }
+        let mut accepted = HashMap::with_capacity(columns.len());
+        let mut rejected = HashMap::with_capacity(columns.len());
+        for (name, values) in columns {
+            let mut accepted_values = Vec::with_capacity(len - rejected_rows);
+            let mut rejected_values = Vec::with_capacity(rejected_rows);
+            for (value, &valid) in values.into_iter().zip(&valid) {
+                if valid {
+                    accepted_values.push(schema.coerce(&name, value));
+                } else {
+                    rejected_values.push(value);
+                }
+            }
+            accepted.insert(name.clone(), accepted_values);
+            rejected.insert(name, rejected_values);
+        }
+        self.reject_rows(table, enforcement, rejected_rows, |dead_letter| {
+            dead_letter.ingest_heterogeneous(rejected)
+        });
+        accepted
+    }
+
+    /// Removes rows that do not conform to the schema of their table from `events`.
+    /// Values of accepted rows are coerced to the declared column types.
+    /// Rows for dead letter tables are added to `events` so that they are persisted in the same WAL segment.
+    fn enforce_event_schemas(&self, mut events: EventBuffer) -> EventBuffer {
+        let mut dead_letters = Vec::new();
+        for (table, buffer) in &mut events.tables {
+            let (schema, enforcement) = match self.enforced_schema(table) {
+                Some(enforced_schema) => enforced_schema,
+                None => continue,
+            };
+            let mut accepted = TableBuffer::default();
+            let mut rejected = TableBuffer::default();
+            for row in mem::take(buffer).into_rows() {
+                if schema
+                    .validate_row(row.iter().map(|(name, value)| (name.as_str(), value)))
+                    .is_ok()
+                {
+                    accepted.push_val_row(row.into_iter().map(|(name, value)| {
+                        let value = schema.coerce(&name, value);
+                        (name, value)
+                    }));
+                } else {
+                    rejected.push_val_row(row);
+                }
+            }
+            *buffer = accepted;
+            if rejected.len == 0 {
+                continue;
+            }
+            match enforcement {
+                SchemaEnforcement::DeadLetter(dead_letter) => {
+                    dead_letters.push((dead_letter, rejected))
+                }
+                _ => log::warn!(
+                    "Rejected {} rows that do not conform to the schema of table {}",
+                    rejected.len,
+                    table
+                ),
+            }
+        }
+        for (dead_letter, rows) in dead_letters {
+            events.tables.entry(dead_letter).or_default().append(rows);
+        }
+        events
+    }
+
+    /// Drops rejected rows or passes the dead letter table to `ingest`.
+    /// Rows are ingested into the dead letter table without enforcing its schema.
+    fn reject_rows(
+        &self,
+        table: &str,
+        enforcement: SchemaEnforcement,
+        rows: usize,
+        ingest: impl FnOnce(&Table),
+    ) {
+        match enforcement {
+            SchemaEnforcement::DeadLetter(dead_letter) => {
+                self.create_if_empty(&dead_letter);
+                let tables = self.tables.read().unwrap();
+                ingest(&tables[&dead_letter]);
+            }
+            _ => log::warn!(
+                "Rejected {} rows that do not conform to the schema of table {}",
+                rows,
+                table
+            ),
+        }
+    }
+
+    pub fn drop_pending_tasks(&self) {
+        self.task_queue.tasks.lock().unwrap().clear();
+        self.background_task_queue.tasks.lock().unwrap().clear();
+    }
+
+    pub fn mem_tree(&self, depth: usize) -> Vec<MemTreeTable> {
+        let tables = self.tables.read().unwrap();
+        tables.values().map(|table| table.mem_tree(depth)).collect()
+    }
+
+    pub fn stats(&self) -> Vec<TableStats> {
+        let tables = self.tables.read().unwrap();
+        tables.values().map(|table| table.stats()).collect()
+    }
+
+    pub fn gen_partition(&self, opts: &GenTable, p: u64) {
+        opts.gen(self, p);
+    }
+
+    pub fn create_table(
+        &self,
+        table: &str,
+        schema: TableSchema,
+        schema_enforcement: SchemaEnforcement,
+        if_not_exists: bool,
+    ) -> Result<(), QueryError> {
+        if schema_enforcement == SchemaEnforcement::DeadLetter(table.to_string()) {
+            bail!(
+                QueryError::SchemaError,
+                "Table {} cannot be its own dead letter table",
+                table
+            );
+        }
+        {
+            let mut tables = self.tables.write().unwrap();
+            if tables.contains_key(table) || VIRTUAL_TABLES.contains(&table) {
+                if if_not_exists {
+                    return Ok(());
+                }
+                bail!(QueryError::SchemaError, "Table {} already exists", table);
+            }
+            if let Some(storage) = &self.storage {
+                storage.persist_schema(table, &schema, &schema_enforcement)?;
+            }
+            let new_table = Table::with_schema(table, self.lru.clone(), schema);
+            new_table.set_schema_enforcement(schema_enforcement);
+            tables.insert(table.to_string(), new_table);
+        }
+        self.record_table_event(table, "create", None);
+        Ok(())
+    }
+
+    pub fn set_schema_enforcement(
+        &self,
+        table: &str,
+        enforcement: SchemaEnforcement,
+    ) -> Result<(), QueryError> {
+        let tables = self.tables.read().unwrap();
+        let t = match tables.get(table) {
+            Some(t) => t,
+            None => bail!(QueryError::SchemaError, "Table {} does not exist", table),
+        };
+        if t.schema().is_none() {
+            bail!(
+                QueryError::SchemaError,
+                "Table {} was not created with a declared schema",
+                table
+            );
+        }
+        if enforcement == SchemaEnforcement::DeadLetter(table.to_string()) {
+            bail!(
+                QueryError::SchemaError,
+                "Table {} cannot be its own dead letter table",
+                table
+            );
+        }
+        if let Some(storage) = &self.storage {
+            storage.persist_schema_enforcement(table, &enforcement)?;
+        }
+        t.set_schema_enforcement(enforcement);
+        Ok(())
+    }
+
+    /// Drops ingested rows whose value in the key column of `deduplication` was already ingested into `table`, or
+    /// disables deduplication if `deduplication` is `None`. Creates the table if it does not exist yet.
+    pub fn set_deduplication(
+        &self,
+        table: &str,
+        deduplication: Option<Deduplication>,
+    ) -> Result<(), QueryError> {
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        if let Some(storage) = &self.storage {
+            storage.persist_deduplication(table, deduplication.as_ref())?;
+        }
+        tables[table].set_deduplication(deduplication);
+        Ok(())
+    }
+
+    pub fn deduplication(&self, table: &str) -> Option<Deduplication> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table)?.deduplication()
+    }
+
+    /// Sets the column by which compaction sorts the rows of `table`, or disables sorting if `sort_key` is `None`.
+    /// Creates the table if it does not exist yet.
+    pub fn set_sort_key(&self, table: &str, sort_key: Option<String>) -> Result<(), QueryError> {
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        if let Some(storage) = &self.storage {
+            storage.persist_sort_key(table, sort_key.as_deref())?;
+        }
+        tables[table].set_sort_key(sort_key);
+        Ok(())
+    }
+
+    pub fn sort_key(&self, table: &str) -> Option<String> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table)?.sort_key()
+    }
+
+    /// Sets the compaction policy of `table`, or restores the default size-tiered policy if `policy` is `None`.
+    /// Creates the table if it does not exist yet.
+    pub fn set_compaction_policy(
+        &self,
+        table: &str,
+        policy: Option<CompactionPolicy>,
+    ) -> Result<(), QueryError> {
+        if let Some(policy) = &policy {
+            if let Err(error) = policy.validate() {
+                bail!(
+                    QueryError::SchemaError,
+                    "Invalid compaction policy for table {}: {}",
+                    table,
+                    error
+                );
+            }
+        }
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        if let Some(storage) = &self.storage {
+            storage.persist_compaction_policy(table, policy.as_ref())?;
+        }
+        tables[table].set_compaction_policy(policy);
+        Ok(())
+    }
+
+    pub fn compaction_policy(&self, table: &str) -> Option<CompactionPolicy> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table)?.compaction_policy()
+    }
+
+    /// Limits the bytes of resident columns of `table`, or removes the limit if `limit` is `None`. Creates the table if
+    /// it does not exist yet.
+    pub fn set_memory_limit(&self, table: &str, limit: Option<usize>) -> Result<(), QueryError> {
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        if let Some(storage) = &self.storage {
+            storage.persist_memory_limit(table, limit)?;
+        }
+        tables[table].set_memory_limit(limit);
+        Ok(())
+    }
+
+    pub fn memory_limit(&self, table: &str) -> Option<usize> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table)?.memory_limit()
+    }
+
+    /// Enables or disables encoding of string column `column` of `table` with a dictionary that is shared by all
+    /// partitions. Creates the table if it does not exist yet.
+    pub fn set_shared_dictionary(
+        &self,
+        table: &str,
+        column: &str,
+        enabled: bool,
+    ) -> Result<(), QueryError> {
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        tables[table].set_shared_dictionary(column, enabled);
+        self.persist_shared_dictionaries(&tables[table])
+    }
+
+    pub fn column_statistics(&self, table: &str) -> HashMap<String, ColumnStatistics> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .get(table)
+            .map(|table| table.column_statistics())
+            .unwrap_or_default()
+    }
+
+    pub fn shared_dictionary_columns(&self, table: &str) -> Vec<String> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .get(table)
+            .map(|table| table.shared_dictionary_columns())
+            .unwrap_or_default()
+    }
+
+    /// Enables or disables bloom filters for string column `column` of `table`. Partitions created afterwards record
+    /// which values the column contains so that queries with equality predicates on the column can skip them.
+    /// Creates the table if it does not exist yet.
+    pub fn set_bloom_filter(
+        &self,
+        table: &str,
+        column: &str,
+        enabled: bool,
+    ) -> Result<(), QueryError> {
+        self.create_if_empty(table);
+        let tables = self.tables.read().unwrap();
+        let previously_enabled = tables[table]
+            .bloom_filter_columns()
+            .contains(&column.to_string());
+        let columns = tables[table].set_bloom_filter(column, enabled);
+        if let Some(storage) = &self.storage {
+            if let Err(err) = storage.persist_bloom_filter_columns(table, &columns) {
+                tables[table].set_bloom_filter(column, previously_enabled);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn bloom_filter_columns(&self, table: &str) -> Vec<String> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .get(table)
+            .map(|table| table.bloom_filter_columns())
+            .unwrap_or_default()
+    }
+
+    /// Persists the shared dictionaries of `table`. Dictionaries are append-only, so they can be written before the
+    /// partitions that use them.
+    fn persist_shared_dictionaries(&self, table: &Table) -> Result<(), QueryError> {
+        if let Some(storage) = &self.storage {
+            let dictionaries = table
+                .shared_dictionaries()
+                .iter()
+                .map(|(column, dictionary)| (column.clone(), dictionary.strings()))
+                .collect();
+            storage.persist_shared_dictionaries(table.name(), dictionaries)?;
+        }
+        Ok(())
     }
 
-    fn worker_loop(locustdb: Arc<InnerLocustDB>) {
-        while locustdb.running.load(Ordering::SeqCst) {
-            if let Some(task) = InnerLocustDB::await_task(&locustdb) {
-                task.execute();
+    /// Removes the table and deletes all of its data, including any rows that are still in the WAL.
+    pub fn drop_table(&self, table: &str, if_exists: bool) -> Result<(), QueryError> {
+        if is_system_table(table) {
+            bail!(
+                QueryError::SchemaError,
+                "Cannot drop system table {}",
+                table
+            );
+        }
+        // Block ingestion and WAL flushes while the table is removed
+        let (wal_size, wal_condvar) = &self.wal_size;
+        let mut wal_size = wal_size.lock().unwrap();
+        let removed = self.tables.write().unwrap().remove(table);
+        self.views.write().unwrap().remove(table);
+        if let Some(cache) = &self.aggregate_cache {
+            cache.evict_table(table);
+        }
+        if removed.is_none() {
+            if if_exists {
+                return Ok(());
             }
+            bail!(QueryError::SchemaError, "Table {} does not exist", table);
         }
-        drop(locustdb) // Make clippy happy
+        let mut deleted = Ok(());
+        if let Some(storage) = &self.storage {
+            // The table is removed from the meta store even if some of its files could not be deleted
+            deleted = storage.delete_table(table);
+            // WAL segments may still contain rows of the dropped table, flushing persists the remaining tables and
+            // deletes the WAL.
+            self.wal_flush()?;
+            *wal_size = 0;
+            self.perf_counter.set_wal_size(0);
+        }
+        drop(wal_size);
+        wal_condvar.notify_all();
+        self.record_table_event(table, "drop", None);
+        deleted
     }
 
-    fn await_task(ldb: &Arc<InnerLocustDB>) -> Option<Arc<dyn Task>> {
-        let mut task_queue = ldb.task_queue.lock().unwrap();
-        while task_queue.is_empty() {
-            if !ldb.running.load(Ordering::SeqCst) {
-                return None;
-            }
-            task_queue = ldb.idle_queue.wait(task_queue).unwrap();
+    /// Renames table `old` to `new`, which must not exist yet.
+    pub fn rename_table(&self, old: &str, new: &str) -> Result<(), QueryError> {
+        if let Some(system_table) = [old, new].into_iter().find(|&table| is_system_table(table)) {
+            bail!(
+                QueryError::SchemaError,
+                "Cannot rename system table {}",
+                system_table
+            );
         }
-        while let Some(task) = task_queue.pop_front() {
-            if task.completed() {
-                continue;
+        // Block ingestion and WAL flushes while the table is renamed
+        let (wal_size, wal_condvar) = &self.wal_size;
+        let mut wal_size = wal_size.lock().unwrap();
+        if self.storage.is_some() {
+            // WAL segments refer to the old table name, flushing persists all buffered rows and deletes the WAL.
+            self.wal_flush()?;
+            *wal_size = 0;
+            self.perf_counter.set_wal_size(0);
+        }
+        {
+            // Checking and renaming under the same lock ensures no table named `new` is created in the meantime
+            let mut tables = self.tables.write().unwrap();
+            if !tables.contains_key(old) {
+                bail!(QueryError::SchemaError, "Table {} does not exist", old);
             }
-            if task.multithreaded() {
-                task_queue.push_front(task.clone());
+            if tables.contains_key(new) {
+                bail!(QueryError::SchemaError, "Table {} already exists", new);
             }
-            if !task_queue.is_empty() {
-                ldb.idle_queue.notify_one();
+            if let Some(storage) = &self.storage {
+                storage.rename_table(old, new)?;
             }
-            return Some(task);
+            let table = tables.remove(old).unwrap();
+            tables.insert(new.to_string(), table.renamed(new));
         }
-        None
+        rename_view_tables(&mut self.views.write().unwrap(), old, new);
+        if let Some(cache) = &self.aggregate_cache {
+            cache.evict_table(old);
+        }
+        drop(wal_size);
+        wal_condvar.notify_all();
+        self.record_table_event(old, "rename", Some(new));
+        Ok(())
     }
 
-    pub fn schedule<T: Task + 'static>(&self, task: T) {
-        // This function may be entered by event loop thread so it's important it always returns quickly.
-        // Since the task queue locks are never held for long, we should be fine.
-        let mut task_queue = self.task_queue.lock().unwrap();
-        task_queue.push_back(Arc::new(task));
-        self.idle_queue.notify_one();
-    }
+    /// Applies `assignments` to all rows of `table` that match `filter` and returns the number of updated rows.
+    /// Partitions containing matching rows are replaced by rewritten partitions in the same way as compactions,
+    /// all other partitions are left untouched.
+    pub fn update(
+        &self,
+        table: &str,
+        assignments: &[(String, Expr)],
+        filter: &Expr,
+    ) -> Result<usize, QueryError> {
+        if is_system_table(table) {
+            bail!(
+                QueryError::SchemaError,
+                "Cannot update system table {}",
+                table
+            );
+        }
+        if assignments
+            .iter()
+            .map(|(_, expr)| expr)
+            .chain(iter::once(filter))
+            .any(Expr::contains_aggregate)
+        {
+            bail!(
+                QueryError::TypeError,
+                "Aggregates are not allowed in UPDATE statements"
+            );
+        }
+        let (wal_size, wal_condvar) = &self.wal_size;
+        for _ in 0..UPDATE_ATTEMPTS {
+            let (partitions, schema) = {
+                let mut wal_size = wal_size.lock().unwrap();
+                // Moves buffered rows into partitions, which makes them eligible for rewriting
+                self.wal_flush()?;
+                *wal_size = 0;
+                self.perf_counter.set_wal_size(0);
+                let tables = self.tables.read().unwrap();
+                let table = match tables.get(table) {
+                    Some(table) => table,
+                    None => bail!(QueryError::SchemaError, "Table {} does not exist", table),
+                };
+                let partitions = table
+                    .partitions()
+                    .into_iter()
+                    .map(|partition| {
+                        let column_names = table.column_names(&[partition.id]);
+                        (partition, column_names)
+                    })
+                    .collect::<Vec<_>>();
+                (partitions, table.schema())
+            };
+            wal_condvar.notify_all();
 
-    pub fn ingest_single(&self, table: &str, row: Vec<(String, RawVal)>) {
-        self.create_if_empty(table);
-        let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest(row)
+            // Rewritten partitions are computed without blocking ingestion, and before replacing any partition so
+            // that errors leave the table unchanged
+            let mut rewrites = Vec::new();
+            let mut updated_rows = 0;
+            for (partition, column_names) in partitions {
+                if let Some((columns, rows)) = self.rewrite_partition(
+                    table,
+                    &partition,
+                    column_names,
+                    schema.as_ref(),
+                    assignments,
+                    filter,
+                )? {
+                    rewrites.push((partition, columns));
+                    updated_rows += rows;
+                }
+            }
+
+            // Block ingestion and WAL flushes while partitions are replaced
+            let wal_size = wal_size.lock().unwrap();
+            let replaced = self.replace_partitions(table, rewrites);
+            drop(wal_size);
+            wal_condvar.notify_all();
+            if replaced? {
+                return Ok(updated_rows);
+            }
+        }
+        bail!(
+            QueryError::Overloaded,
+            "Partitions of table {} were modified concurrently {} times, retry the update",
+            table,
+            UPDATE_ATTEMPTS
+        )
     }
 
-    pub fn ingest_efficient(&self, events: EventBuffer) {
-        let (wal_size, wal_condvar) = &self.wal_size;
-        let mut wal_size = wal_size.lock().unwrap();
-        while *wal_size > self.opts.max_wal_size_bytes {
-            wal_size = wal_condvar.wait(wal_size).unwrap();
+    /// Evaluates `filter` and `assignments` for all rows of `partition` with the query engine and returns all
+    /// columns of the rewritten partition together with the number of updated rows, or `None` if no row matches.
+    fn rewrite_partition(
+        &self,
+        table: &str,
+        partition: &Arc<Partition>,
+        column_names: Vec<String>,
+        schema: Option<&TableSchema>,
+        assignments: &[(String, Expr)],
+        filter: &Expr,
+    ) -> Result<Option<(HashMap<String, Vec<RawVal>>, usize)>, QueryError> {
+        let len = partition.len();
+        let matching = match filter {
+            Expr::Const(value) => vec![is_true(value); len],
+            _ => {
+                let query = Query::project(
+                    table,
+                    vec![ColumnInfo {
+                        expr: filter.clone(),
+                        name: "matches".to_string(),
+                    }],
+                );
+                let values = self
+                    .query_columns(query, vec![partition.clone()])?
+                    .remove("matches")
+                    .unwrap_or_default();
+                ensure!(
+                    values.len() == len,
+                    "Filter returned {} values for partition with {} rows",
+                    values.len(),
+                    len
+                );
+                values.iter().map(is_true).collect::<Vec<_>>()
+            }
+        };
+        let updated_rows = matching.iter().filter(|&&matching| matching).count();
+        if updated_rows == 0 {
+            return Ok(None);
         }
 
-        if let Some(storage) = &self.storage {
-            let bytes_written = storage.persist_wal_segment(WALSegment {
-                id: 0,
-                data: Cow::Borrowed(&events),
+        // Reads all columns and evaluates all assigned expressions in a single query. Output columns are named by
+        // index since column names may collide with each other's aliases.
+        let select = column_names
+            .iter()
+            .map(|column| Expr::ColName(column.clone()))
+            .chain(assignments.iter().map(|(_, expr)| expr.clone()))
+            .enumerate()
+            .filter(|(_, expr)| !matches!(expr, Expr::Const(_)))
+            .map(|(i, expr)| ColumnInfo {
+                expr,
+                name: i.to_string(),
+            })
+            .collect();
+        let mut values =
+            self.query_columns(Query::project(table, select), vec![partition.clone()])?;
+        let mut take = |i: usize| {
+            values
+                .remove(&i.to_string())
+                .ok_or_else(|| fatal!("Missing output column {}", i))
+        };
+        let mut columns = HashMap::with_capacity(column_names.len());
+        for (i, column) in column_names.iter().enumerate() {
+            columns.insert(column.clone(), take(i)?);
+        }
+        // All assigned values are computed from the original rows before any column is updated
+        let mut assigned_values = Vec::with_capacity(assignments.len());
+        for (i, (_, expr)) in assignments.iter().enumerate() {
+            assigned_values.push(match expr {
+                Expr::Const(value) => vec![value.clone(); len],
+                _ => take(column_names.len() + i)?,
             });
-            *wal_size += bytes_written;
         }
-        // TODO: code duplicated in Table::restore_tables_from_disk
-        for (table, data) in events.tables {
-            self.create_if_empty(&table);
-            let tables = self.tables.read().unwrap();
-            let table = tables.get(&table).unwrap();
-            let rows = data.len;
-            // TODO: eliminate conversion
-            let columns = data
-                .columns
-                .into_iter()
-                .map(|(k, v)| {
-                    let col = match v.data {
-                        ColumnData::Dense(data) => {
-                            if (data.len() as u64) < rows {
-                                InputColumn::NullableFloat(
-                                    rows,
-                                    data.into_iter()
-                                        .enumerate()
-                                        .map(|(i, v)| (i as u64, v))
-                                        .collect(),
-                                )
-                            } else {
-                                InputColumn::Float(data)
-                            }
-                        }
-                        ColumnData::Sparse(data) => InputColumn::NullableFloat(rows, data),
-                    };
-                    (k, col)
-                })
-                .collect();
-            table.ingest_homogeneous(columns);
+        for ((column, _), assigned) in assignments.iter().zip(assigned_values) {
+            let values = columns
+                .entry(column.clone())
+                .or_insert_with(|| vec![RawVal::Null; len]);
+            for (row, value) in assigned.into_iter().enumerate() {
+                if !matching[row] {
+                    continue;
+                }
+                if let Some(schema) = schema {
+                    schema.validate(column, &value)?;
+                }
+                values[row] = value;
+            }
         }
-
-        wal_condvar.notify_all();
+        Ok(Some((columns, updated_rows)))
     }
 
-    /// Creates new partition from currently open buffer in each table, persists partitions to disk, and deletes WAL.
-    pub(crate) fn wal_flush(&self) {
-        let start_time = Instant::now();
+    /// Replaces each partition in `rewrites` with a new partition containing the rewritten columns. Returns false
+    /// without replacing any partition if one of them has been compacted or rewritten in the meantime.
+    fn replace_partitions(
+        &self,
+        name: &str,
+        rewrites: Vec<(Arc<Partition>, HashMap<String, Vec<RawVal>>)>,
+    ) -> Result<bool, QueryError> {
         let tables = self.tables.read().unwrap();
-        let mut new_partitions = Vec::new();
-        let mut compactions = Vec::new();
-        for table in tables.values() {
-            if let Some(partition) = table.batch() {
-                let columns: Vec<_> = partition
-                    .col_handles()
-                    .map(|c| c.try_get().as_ref().unwrap().clone())
-                    .sorted_by(|a, b| a.name().cmp(b.name()));
-                let (metadata, subpartitions) = subpartition(&self.opts, columns);
-                let column_name_to_subpartition_index = subpartitions
-                    .iter()
-                    .enumerate()
-                    .flat_map(|(i, subpartition)| {
-                        subpartition
-                            .iter()
-                            .map(move |column| (column.name().to_string(), i))
-                    })
-                    .collect();
-                let partition_metadata = PartitionMetadata {
-                    id: partition.id,
-                    tablename: table.name().to_string(),
-                    len: partition.len(),
-                    offset: partition.range().start,
-                    subpartitions: metadata,
-                    column_name_to_subpartition_index,
-                };
-                new_partitions.push((partition_metadata, subpartitions));
-            }
-
-            if let Some(compaction) = table.plan_compaction(self.opts.partition_combine_factor) {
-                compactions.push((table.name(), table.next_partition_id(), compaction));
-            }
+        let table = match tables.get(name) {
+            Some(table) => table,
+            None => bail!(QueryError::SchemaError, "Table {} does not exist", name),
+        };
+        let current = table
+            .partitions()
+            .iter()
+            .map(|partition| partition.id)
+            .collect::<Vec<_>>();
+        if rewrites
+            .iter()
+            .any(|(partition, _)| !current.contains(&partition.id))
+        {
+            return Ok(false);
         }
 
-        if let Some(s) = self.storage.as_ref() {
-            s.persist_partitions_delete_wal(new_partitions)
+        for (partition, columns) in rewrites {
+            let id = table.next_partition_id();
+            let offset = partition.range().start;
+            let mut bloom_filters = HashMap::new();
+            let columns = columns
+                .into_iter()
+                .sorted_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(column, values)| {
+                    let mut column_builder = MixedCol::default();
+                    values.into_iter().for_each(|v| column_builder.push(v));
+                    if let Some(filter) = table.bloom_filter(&column, &column_builder) {
+                        bloom_filters.insert(column.clone(), filter);
+                    }
+                    column_builder.finalize(&column, table.shared_dictionaries().get_mut(&column))
+                })
+                .collect::<Vec<_>>();
+            self.persist_shared_dictionaries(table)?;
+            let (metadata, subpartitions) = subpartition(&self.opts, columns.clone());
+            if let Some(storage) = self.storage.as_ref() {
+                storage.compact(
+                    name,
+                    id,
+                    metadata,
+                    subpartitions,
+                    bloom_filters.clone(),
+                    &[partition.id],
+                    offset,
+                )?;
+            }
+            table.compact(id, offset, columns, bloom_filters, &[partition.id]);
         }
+        Ok(true)
+    }
 
-        for (table, id, (range, parts)) in compactions {
-            // get table, create new merged partition/sub-partitions (not registered with table)
-            // - get names of all columns
-            // - run query for each column, construct Column
-            // - create subpartitions
-            let colnames = tables[table].column_names(&parts);
-            let mut columns = Vec::with_capacity(colnames.len());
-            let data = tables[table].snapshot_parts(&parts);
-            for column in &colnames {
-                let query = Query::read_column(table, column);
-                let (sender, receiver) = oneshot::channel();
-                let query_task = QueryTask::new(
-                    query,
-                    false,
-                    false,
-                    vec![],
-                    data.clone(),
-                    self.disk_read_scheduler().clone(),
-                    SharedSender::new(sender),
-                    self.opts.batch_size,
-                )
-                .unwrap();
-                self.schedule(query_task);
-                let result = block_on(receiver).unwrap().unwrap();
-                let mut column_builder = MixedCol::default();
-                let column_data = result.columns.into_iter().next().unwrap().1;
-                match column_data {
-                    BasicTypeColumn::Int(ints) => column_builder.push_ints(ints),
-                    BasicTypeColumn::Float(floats) => column_builder.push_floats(floats),
-                    BasicTypeColumn::String(strings) => column_builder.push_strings(strings),
-                    BasicTypeColumn::Null(count) => column_builder.push_nulls(count),
-                    BasicTypeColumn::Mixed(raws) => {
-                        raws.into_iter().for_each(|r| column_builder.push(r))
-                    }
-                }
-                assert_eq!(
-                    range.len(),
-                    column_builder.len(),
-                    "range={range:?}, column_builder.len() = {}, table = {table},  column = {column}",
-                    column_builder.len(),
+    /// Creates the view table from all existing rows of the source table and keeps it up to date as new rows are
+    /// ingested into the source table.
+    pub fn create_materialized_view(&self, view: MaterializedView) -> Result<(), QueryError> {
+        let query = view.query()?;
+        // Block ingestion and WAL flushes while the view is populated
+        let (wal_size, wal_condvar) = &self.wal_size;
+        let mut wal_size = wal_size.lock().unwrap();
+        let result = self.populate_view(&view, query).and_then(|()| {
+            // Persists the initial contents of the view before it is registered, rows that are still buffered in
+            // the source table have already been included.
+            self.wal_flush()?;
+            *wal_size = 0;
+            self.perf_counter.set_wal_size(0);
+            if let Some(storage) = &self.storage {
+                storage.persist_view(&view)?;
+            }
+            self.views
+                .write()
+                .unwrap()
+                .insert(view.name.clone(), view.clone());
+            Ok(())
+        });
+        drop(wal_size);
+        wal_condvar.notify_all();
+        result?;
+        self.record_table_event(&view.name, "create", None);
+        Ok(())
+    }
 
+    fn populate_view(&self, view: &MaterializedView, query: Query) -> Result<(), QueryError> {
+        let data = {
+            let tables = self.tables.read().unwrap();
+            if is_system_table(&view.name) || tables.contains_key(&view.name) {
+                bail!(
+                    QueryError::SchemaError,
+                    "Table {} already exists",
+                    view.name
                 );
-                columns.push(column_builder.finalize(column));
             }
-            let (metadata, subpartitions) = subpartition(&self.opts, columns.clone());
-            // write subpartitions to disk, update metastore unlinking old partitions, delete old partitions
-            if let Some(storage) = self.storage.as_ref() {
-                storage.compact(table, id, metadata, subpartitions, &parts, range.start);
+            if self.views.read().unwrap().contains_key(&view.source) {
+                bail!(
+                    QueryError::NotImplemented,
+                    "Materialized views over materialized views"
+                );
             }
-
-            // replace old partitions with new partition
-            tables[table].compact(id, range.start, columns, &parts);
+            match tables.get(&view.source) {
+                Some(source) => source.snapshot(),
+                None => bail!(
+                    QueryError::SchemaError,
+                    "Table {} does not exist",
+                    view.source
+                ),
+            }
+        };
+        let columns = if data.is_empty() {
+            HashMap::new()
+        } else {
+            self.query_columns(query, data)?
+        };
+        let mut tables = self.tables.write().unwrap();
+        if tables.contains_key(&view.name) {
+            bail!(
+                QueryError::SchemaError,
+                "Table {} already exists",
+                view.name
+            );
         }
-
-        log::info!("Performed wal flush in {:?}", start_time.elapsed());
+        let table = Table::new(&view.name, self.lru.clone());
+        if columns.values().any(|column| !column.is_empty()) {
+            table.ingest_heterogeneous(columns);
+        }
+        tables.insert(view.name.clone(), table);
+        Ok(())
     }
 
-    pub fn restore(&self, id: PartitionID, column: Column) {
-        let column = Arc::new(column);
-        for table in self.tables.read().unwrap().values() {
-            table.restore(id, &column);
+    /// Removes the materialized view and its table.
+    pub fn drop_materialized_view(&self, name: &str, if_exists: bool) -> Result<(), QueryError> {
+        if !self.views.read().unwrap().contains_key(name) {
+            if if_exists {
+                return Ok(());
+            }
+            bail!(
+                QueryError::SchemaError,
+                "Materialized view {} does not exist",
+                name
+            );
         }
+        self.drop_table(name, if_exists)
     }
 
-    #[allow(dead_code)]
-    pub fn ingest_homogeneous(&self, table: &str, columns: HashMap<String, InputColumn>) {
-        self.create_if_empty(table);
-        let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest_homogeneous(columns)
+    pub fn create_scheduled_query(
+        &self,
+        scheduled_query: ScheduledQuery,
+    ) -> Result<(), QueryError> {
+        let mut scheduled_queries = self.scheduled_queries.lock().unwrap();
+        if scheduled_queries.contains_key(&scheduled_query.name) {
+            bail!(
+                QueryError::SchemaError,
+                "Scheduled query {} already exists",
+                scheduled_query.name
+            );
+        }
+        if let Some(storage) = &self.storage {
+            storage.persist_scheduled_query(&scheduled_query)?;
+        }
+        scheduled_queries.insert(scheduled_query.name.clone(), scheduled_query);
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    pub fn ingest_heterogeneous(&self, table: &str, columns: HashMap<String, Vec<RawVal>>) {
-        self.create_if_empty(table);
-        let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest_heterogeneous(columns)
+    pub fn drop_scheduled_query(&self, name: &str) -> Result<(), QueryError> {
+        let mut scheduled_queries = self.scheduled_queries.lock().unwrap();
+        if !scheduled_queries.contains_key(name) {
+            bail!(
+                QueryError::SchemaError,
+                "Scheduled query {} does not exist",
+                name
+            );
+        }
+        if let Some(storage) = &self.storage {
+            storage.delete_scheduled_query(name)?;
+        }
+        scheduled_queries.remove(name);
+        Ok(())
     }
 
-    pub fn drop_pending_tasks(&self) {
-        let mut task_queue = self.task_queue.lock().unwrap();
-        task_queue.clear();
+    pub fn scheduled_queries(&self) -> Vec<ScheduledQuery> {
+        let scheduled_queries = self.scheduled_queries.lock().unwrap();
+        scheduled_queries
+            .values()
+            .cloned()
+            .sorted_by(|a, b| a.name.cmp(&b.name))
     }
 
-    pub fn mem_tree(&self, depth: usize) -> Vec<MemTreeTable> {
-        let tables = self.tables.read().unwrap();
-        tables.values().map(|table| table.mem_tree(depth)).collect()
+    /// Runs the query over the window ending at `end`, appends the results to the target table and advances the
+    /// watermark. Results are persisted before the watermark so that no window is skipped after a crash.
+    fn run_scheduled_query(
+        &self,
+        scheduled_query: &ScheduledQuery,
+        end: i64,
+    ) -> Result<(), QueryError> {
+        let query = scheduled_query.window_query(end)?;
+        let data = match self.snapshot(&query.table) {
+            Some(data) => data,
+            None => bail!(
+                QueryError::SchemaError,
+                "Table {} does not exist",
+                query.table
+            ),
+        };
+        let columns = self.query_columns(query, data)?;
+        if columns.values().any(|column| !column.is_empty()) {
+            self.ingest_heterogeneous(&scheduled_query.target, columns);
+            self.flush_buffers()?;
+        }
+        let mut scheduled_queries = self.scheduled_queries.lock().unwrap();
+        // The scheduled query may have been dropped in the meantime
+        if let Some(scheduled_query) = scheduled_queries.get_mut(&scheduled_query.name) {
+            scheduled_query.watermark = end;
+            if let Some(storage) = &self.storage {
+                storage.persist_scheduled_query(scheduled_query)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn stats(&self) -> Vec<TableStats> {
+    pub fn table_schema(&self, table: &str) -> Option<TableSchema> {
         let tables = self.tables.read().unwrap();
-        tables.values().map(|table| table.stats()).collect()
+        tables.get(table).and_then(|t| t.schema())
     }
 
-    pub fn gen_partition(&self, opts: &GenTable, p: u64) {
-        opts.gen(self, p);
+    pub fn schema_enforcement(&self, table: &str) -> Option<SchemaEnforcement> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table).map(|t| t.schema_enforcement())
     }
 
     fn create_if_empty(&self, table: &str) {
@@ -368,26 +2160,115 @@ impl InnerLocustDB {
                 let mut tables = self.tables.write().unwrap();
                 tables.insert(table.to_string(), Table::new(table, self.lru.clone()));
             }
-            self.ingest_single(
-                "_meta_tables",
-                vec![
-                    (
-                        "timestamp".to_string(),
-                        RawVal::Int(
-                            SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs() as i64,
-                        ),
-                    ),
-                    ("name".to_string(), RawVal::Str(table.to_string())),
-                ],
-            );
+            self.record_table_event(table, "create", None);
+        }
+    }
+
+    /// Records a query that took `duration` in the query log if `Options::query_log` is set and the query is sampled.
+    /// `user` is supplied by the client and not authenticated, so it is recorded in the `client_user` column.
+    pub(crate) fn log_query(
+        &self,
+        query: &str,
+        user: Option<&str>,
+        duration: Duration,
+        result: &Result<QueryResult, oneshot::Canceled>,
+    ) {
+        if !self.opts.query_log || rand::random::<f64>() >= self.opts.query_log_sample_rate {
+            return;
+        }
+        let (rows, error) = match result {
+            Ok(Ok(output)) => {
+                let rows = match &output.rows {
+                    Some(rows) => rows.len(),
+                    None => output.columns.first().map_or(0, |(_, column)| column.len()),
+                };
+                (RawVal::Int(rows as i64), RawVal::Null)
+            }
+            Ok(Err(err)) => (RawVal::Null, RawVal::Str(err.to_string())),
+            Err(_) => (RawVal::Null, RawVal::Str("Query was canceled".to_string())),
+        };
+        let row = vec![
+            (
+                "timestamp".to_string(),
+                RawVal::Int(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs() as i64,
+                ),
+            ),
+            ("query".to_string(), RawVal::Str(query.to_string())),
+            (
+                "client_user".to_string(),
+                user.map_or(RawVal::Null, |user| RawVal::Str(user.to_string())),
+            ),
+            (
+                "duration_ns".to_string(),
+                RawVal::Int(duration.as_nanos() as i64),
+            ),
+            ("rows".to_string(), rows),
+            ("error".to_string(), error),
+        ];
+        let columns = row
+            .into_iter()
+            .map(|(name, value)| (name, vec![value]))
+            .collect();
+        // Written to the WAL like other ingested rows so that recorded queries are not lost on a crash
+        if let Err(err) = self.ingest_column_batches(QUERY_LOG_TABLE, iter::once(columns)) {
+            log::warn!("Failed to record query in {}: {}", QUERY_LOG_TABLE, err);
+        }
+    }
+
+    fn record_table_event(&self, table: &str, event: &str, new_name: Option<&str>) {
+        let mut row = vec![
+            (
+                "timestamp".to_string(),
+                RawVal::Int(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs() as i64,
+                ),
+            ),
+            ("name".to_string(), RawVal::Str(table.to_string())),
+            ("event".to_string(), RawVal::Str(event.to_string())),
+        ];
+        if let Some(new_name) = new_name {
+            row.push(("new_name".to_string(), RawVal::Str(new_name.to_string())));
         }
+        self.ingest_single("_meta_tables", row);
     }
 
+    /// Evicts columns while tables exceed `mem_size_limit_tables`. If `max_rss_bytes` is set, also evicts columns worth
+    /// the excess of the resident set size of the process, which includes scratchpads and ingest buffers, and rejects
+    /// ingestion until the process is below the limit again. Tables that exceed their own memory limit are evicted from
+    /// first, so that they cannot evict the columns of all other tables.
     fn enforce_mem_limit(ldb: &Arc<InnerLocustDB>) {
+        if ldb.opts.max_rss_bytes.is_some() && process_rss_bytes().is_none() {
+            warn!("Failed to determine resident set size of process, max_rss_bytes is ignored");
+        }
         while ldb.running.load(Ordering::SeqCst) {
+            if let Some(cache) = &ldb.aggregate_cache {
+                cache.evict_removed_partitions();
+            }
+            for table in ldb.tables.read().unwrap().values() {
+                let limit = match table.memory_limit() {
+                    Some(limit) => limit,
+                    None => continue,
+                };
+                let mut resident_bytes = table.resident_bytes();
+                while resident_bytes > limit {
+                    match ldb.lru.evict_from(table.name()) {
+                        Some(victim) => {
+                            let bytes = table.evict(&victim);
+                            ldb.evict_cached_results(&victim);
+                            ldb.perf_counter.cache_evicted(bytes as u64);
+                            resident_bytes = resident_bytes.saturating_sub(bytes)
+                        }
+                        None => break,
+                    }
+                }
+            }
             let mut mem_usage_bytes: usize = {
                 let tables = ldb.tables.read().unwrap();
                 tables
@@ -395,13 +2276,38 @@ impl InnerLocustDB {
                     .map(|table| table.heap_size_of_children())
                     .sum()
             };
-            if mem_usage_bytes > ldb.opts.mem_size_limit_tables {
+            let rss_excess_bytes = ldb
+                .opts
+                .max_rss_bytes
+                .and_then(|max_rss_bytes| Some(process_rss_bytes()?.saturating_sub(max_rss_bytes)))
+                .unwrap_or(0);
+            let was_under_pressure = ldb
+                .memory_pressure
+                .swap(rss_excess_bytes > 0, Ordering::SeqCst);
+            if rss_excess_bytes > 0 && !was_under_pressure {
+                warn!(
+                    "Process exceeds max_rss_bytes by {} bytes, rejecting ingestion",
+                    rss_excess_bytes
+                );
+            } else if rss_excess_bytes == 0 && was_under_pressure {
+                info!("Process is below max_rss_bytes, accepting ingestion");
+            }
+            let limit = ldb
+                .opts
+                .mem_size_limit_tables
+                .min(mem_usage_bytes.saturating_sub(rss_excess_bytes));
+            if mem_usage_bytes > limit {
                 info!("Evicting. mem_usage_bytes = {}", mem_usage_bytes);
-                while mem_usage_bytes > ldb.opts.mem_size_limit_tables {
+                while mem_usage_bytes > limit {
                     match ldb.lru.evict() {
                         Some(victim) => {
                             let tables = ldb.tables.read().unwrap();
-                            mem_usage_bytes -= tables[&victim.table].evict(&victim);
+                            let bytes = tables
+                                .get(&victim.table)
+                                .map_or(0, |table| table.evict(&victim));
+                            ldb.evict_cached_results(&victim);
+                            ldb.perf_counter.cache_evicted(bytes as u64);
+                            mem_usage_bytes -= bytes;
                         }
                         None => {
                             if ldb.opts.mem_size_limit_tables > 0 {
@@ -420,19 +2326,140 @@ impl InnerLocustDB {
         }
     }
 
+    fn service_prefetches(&self) {
+        self.disk_read_scheduler.service_prefetches(&self.running);
+    }
+
+    fn expire_queued_queries(&self) {
+        if let Some(admission) = &self.query_admission {
+            admission.expire_queued(&self.running);
+        }
+    }
+
+    /// Flushes the WAL when it exceeds `max_wal_size_bytes`, or when it is not empty and `wal_flush_interval` has
+    /// passed since the last flush.
     fn enforce_wal_limit(&self) {
+        self.thread_pinning.pin_current_thread();
         let (wal_size, wal_condvar) = &self.wal_size;
         let mut wal_size = wal_size.lock().unwrap();
+        let mut last_flush = Instant::now();
         while self.running.load(Ordering::SeqCst) {
-            if *wal_size < self.opts.max_wal_size_bytes {
+            let until_interval = self
+                .opts
+                .wal_flush_interval
+                .map(|interval| interval.saturating_sub(last_flush.elapsed()));
+            let interval_elapsed = until_interval.map_or(false, |d| d.is_zero()) && *wal_size > 0;
+            if *wal_size < self.opts.max_wal_size_bytes && !interval_elapsed {
+                let timeout = until_interval
+                    .filter(|d| !d.is_zero())
+                    .map_or(Duration::from_secs(1), |d| d.min(Duration::from_secs(1)));
+                (wal_size, _) = wal_condvar.wait_timeout(wal_size, timeout).unwrap();
+            } else if let Err(err) = self.wal_flush() {
+                log::error!("Failed to flush WAL: {}", err);
+                // The WAL is kept and flushed again after a delay
                 (wal_size, _) = wal_condvar
-                    .wait_timeout(wal_size, Duration::from_secs(1))
+                    .wait_timeout(wal_size, WAL_FLUSH_RETRY_DELAY)
                     .unwrap();
             } else {
-                self.wal_flush();
                 *wal_size = 0;
+                self.perf_counter.set_wal_size(0);
+                last_flush = Instant::now();
+            }
+        }
+    }
+
+    fn run_scheduled_queries(&self) {
+        while self.running.load(Ordering::SeqCst) {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64;
+            let due = self
+                .scheduled_queries
+                .lock()
+                .unwrap()
+                .values()
+                .filter_map(|q| q.due(now).map(|end| (q.clone(), end)))
+                .collect::<Vec<_>>();
+            for (scheduled_query, end) in due {
+                if let Err(err) = self.run_scheduled_query(&scheduled_query, end) {
+                    log::error!(
+                        "Failed to run scheduled query {}: {}",
+                        scheduled_query.name,
+                        err
+                    );
+                }
+            }
+            thread::sleep(Duration::from_millis(1000));
+        }
+    }
+
+    /// Periodically scrubs all partition files and quarantines corrupted partitions.
+    fn run_scrub(&self) {
+        let interval = self.opts.scrub_interval.unwrap();
+        let mut last_scrub = Instant::now();
+        while self.running.load(Ordering::SeqCst) {
+            if last_scrub.elapsed() >= interval {
+                let report = self.scrub(true);
+                info!(
+                    "Scrubbed {} partition files, {} corrupted",
+                    report.files_scanned,
+                    report.corrupted.len()
+                );
+                last_scrub = Instant::now();
+            }
+            thread::sleep(Duration::from_millis(1000));
+        }
+    }
+
+    /// Verifies all partition files, see `Storage::scrub`. Quarantined partitions are also removed from the tables.
+    pub fn scrub(&self, quarantine: bool) -> ScrubReport {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return ScrubReport::default(),
+        };
+        let report = storage.scrub(quarantine);
+        let tables = self.tables.read().unwrap();
+        for (table, id) in &report.quarantined {
+            if let Some(table) = tables.get(table) {
+                table.remove_partition(*id);
             }
         }
+        report
+    }
+
+    /// Deletes files that are not referenced by the database, see `Storage::collect_garbage`.
+    pub fn collect_garbage(&self, dry_run: bool) -> Result<GarbageCollectionReport, QueryError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(GarbageCollectionReport::default()),
+        };
+        // Blocks ingestion and WAL flushes, which write files before they are referenced by the meta store
+        let (wal_size, _) = &self.wal_size;
+        let _wal_size = wal_size.lock().unwrap();
+        storage.collect_garbage(dry_run)
+    }
+
+    /// Upgrades the database to the current on-disk format, see `migration::migrate`.
+    pub fn migrate(&self) -> Result<MigrationReport, QueryError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Err(fatal!("Migrations require persistent storage")),
+        };
+        // Blocks WAL flushes and compactions while partition metadata is rewritten
+        let (wal_size, _) = &self.wal_size;
+        let _wal_size = wal_size.lock().unwrap();
+        migration::migrate(storage)
+    }
+
+    /// Flushes buffered rows and copies the database to `path`, see `Storage::create_backup`.
+    pub fn create_backup(&self, path: &Path) -> Result<(), QueryError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Err(fatal!("Backups require a database with persistent storage")),
+        };
+        self.flush_buffers()?;
+        storage.create_backup(path)
     }
 
     pub fn opts(&self) -> &Options {
@@ -443,6 +2470,10 @@ impl InnerLocustDB {
         &self.disk_read_scheduler
     }
 
+    pub fn aggregate_cache(&self) -> Option<Arc<AggregateCache>> {
+        self.aggregate_cache.clone()
+    }
+
     pub fn perf_counter(&self) -> &PerfCounter {
         self.perf_counter.as_ref()
     }
@@ -451,11 +2482,23 @@ impl InnerLocustDB {
         let tables = self.tables.read().unwrap();
         let mut bytes_evicted = 0;
         while let Some(victim) = self.lru.evict() {
-            bytes_evicted += tables[&victim.table].evict(&victim);
+            let bytes = tables
+                .get(&victim.table)
+                .map_or(0, |table| table.evict(&victim));
+            self.evict_cached_results(&victim);
+            self.perf_counter.cache_evicted(bytes as u64);
+            bytes_evicted += bytes;
         }
         bytes_evicted
     }
 
+    /// Drops the cached aggregation results that keep the evicted column in memory.
+    fn evict_cached_results(&self, victim: &ColumnLocator) {
+        if let Some(cache) = &self.aggregate_cache {
+            cache.evict_column(&victim.table, victim.id, &victim.column);
+        }
+    }
+
     pub fn search_column_names(&self, table: &str, column: &str) -> Vec<String> {
         let tables = self.tables.read().unwrap();
         tables
@@ -472,7 +2515,7 @@ impl Drop for InnerLocustDB {
 
 #[derive(Default)]
 struct PartitionBuilder {
-    subpartition_metadata: Vec<(Vec<String>, u64)>,
+    subpartition_metadata: Vec<(Vec<String>, u64, HashMap<String, String>)>,
     subpartitions: Vec<Vec<Arc<Column>>>,
     subpartition: Vec<Arc<Column>>,
     bytes: u64,
@@ -487,6 +2530,10 @@ fn subpartition(
         acc.subpartition_metadata.push((
             acc.subpartition.iter().map(|c| c.name().to_string()).collect(),
             acc.bytes,
+            acc.subpartition
+                .iter()
+                .map(|c| (c.name().to_string(), c.codec().signature(false)))
+                .collect(),
         ));
         acc.subpartitions.push(mem::take(&mut acc.subpartition));
         acc.bytes = 0;
@@ -503,14 +2550,18 @@ fn subpartition(
     create_subpartition(&mut acc);
 
     let subpartition_metadata = if acc.subpartitions.len() == 1 {
+        let (_, size_bytes, column_codecs) = acc.subpartition_metadata.pop().unwrap();
         vec![SubpartitionMetadata {
             subpartition_key: "all".to_string(),
-            size_bytes: acc.subpartition_metadata[0].1,
+            size_bytes,
+            column_codecs,
+            checksum: None,
+            data_dir: None,
         }]
     } else {
         acc.subpartition_metadata
-            .iter()
-            .map(|(column_names, size)| {
+            .into_iter()
+            .map(|(column_names, size, column_codecs)| {
                 let first_col = column_names.iter().next().unwrap();
                 let is_column_name_filesystem_safe = first_col.len() <= 64
                     && first_col
@@ -529,10 +2580,31 @@ fn subpartition(
                     };
                 SubpartitionMetadata {
                     subpartition_key,
-                    size_bytes: *size,
+                    size_bytes: size,
+                    column_codecs,
+                    checksum: None,
+                    data_dir: None,
                 }
             })
             .collect()
     };
     (subpartition_metadata, acc.subpartitions)
 }
+
+/// Resident set size of the current process, only available on Linux.
+fn process_rss_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    Some(kib * 1024)
+}
+
+/// Whether `table` is maintained by the database and cannot be created, modified or dropped by queries.
+fn is_system_table(table: &str) -> bool {
+    table == "_meta_tables" || VIRTUAL_TABLES.contains(&table)
+}
+
+/// Whether a value produced by a filter expression selects the row, nulls never match.
+fn is_true(value: &RawVal) -> bool {
+    matches!(value, RawVal::Int(i) if *i != 0)
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of `LocustDB::force_flush`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlushReport {
+    /// Size of the WAL segments that were made obsolete by the flush
+    pub wal_bytes: u64,
+    /// Bytes written to new partitions
+    pub partition_bytes_written: u64,
+    /// Bytes written by compactions that were triggered by the flush
+    pub compaction_bytes_written: u64,
+    pub duration_ns: u64,
+}
+
+/// Result of `LocustDB::compact_table`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Number of compactions that were performed, each combining two or more partitions
+    pub compactions: usize,
+    pub partitions_before: usize,
+    pub partitions_after: usize,
+    /// Bytes written to the combined partitions
+    pub bytes_written: u64,
+    pub duration_ns: u64,
+}
@@ -1,8 +1,16 @@
+pub(crate) mod admission;
+pub(crate) mod affinity;
+mod health;
+mod maintenance;
+mod scheduled_query;
 mod shared_sender;
 mod task;
 pub(crate) mod disk_read_scheduler;
 pub(crate) mod inner_locustdb;
 
+pub use self::health::Health;
 pub use self::inner_locustdb::InnerLocustDB;
+pub use self::maintenance::{CompactionReport, FlushReport};
+pub use self::scheduled_query::ScheduledQuery;
 pub use self::task::Task;
 pub use self::shared_sender::SharedSender;
\ No newline at end of file
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Query;
+use crate::ingest::raw_val::RawVal;
+use crate::syntax::expression::*;
+use crate::syntax::parser;
+use crate::QueryError;
+
+/// Query that runs periodically over rows of its table within successive time windows and appends its results to a
+/// target table, e.g. to maintain per-minute averages of raw events.
+/// Each run covers rows with `timestamp_column` in `[watermark, end)`, where `end` is the current unix time in seconds
+/// rounded down to a multiple of `interval`. Grouping by time buckets that divide `interval` therefore always produces
+/// complete buckets. Rows that arrive after their window has been processed are not included.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledQuery {
+    pub name: String,
+    pub query: String,
+    pub target: String,
+    pub timestamp_column: String,
+    /// Number of seconds between runs.
+    pub interval: u64,
+    /// All rows with timestamps before the watermark have been processed.
+    pub watermark: i64,
+}
+
+impl ScheduledQuery {
+    pub fn new(
+        name: &str,
+        query: &str,
+        target: &str,
+        timestamp_column: &str,
+        interval: u64,
+    ) -> Result<ScheduledQuery, QueryError> {
+        if interval == 0 || interval > i64::MAX as u64 {
+            bail!(
+                QueryError::SchemaError,
+                "Invalid interval {} for scheduled query {}",
+                interval,
+                name
+            );
+        }
+        let parsed = parser::parse_query(query)?;
+        if parsed.table == target {
+            bail!(
+                QueryError::SchemaError,
+                "Scheduled query {} cannot write to the table it selects from",
+                name
+            );
+        }
+        if parsed.limit.limit != u64::MAX || parsed.limit.offset != 0 {
+            bail!(
+                QueryError::NotImplemented,
+                "LIMIT and OFFSET in scheduled queries"
+            );
+        }
+        Ok(ScheduledQuery {
+            name: name.to_string(),
+            query: query.to_string(),
+            target: target.to_string(),
+            timestamp_column: timestamp_column.to_string(),
+            interval,
+            watermark: 0,
+        })
+    }
+
+    /// Returns the end of the window for the next run if a run is due at unix time `now`.
+    pub fn due(&self, now: i64) -> Option<i64> {
+        let end = now - now.rem_euclid(self.interval as i64);
+        if end > self.watermark {
+            Some(end)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the query restricted to rows in the window ending at `end`.
+    pub fn window_query(&self, end: i64) -> Result<Query, QueryError> {
+        let mut query = parser::parse_query(&self.query)?;
+        let timestamp = || Box::new(Expr::ColName(self.timestamp_column.clone()));
+        let window = Expr::Func2(
+            Func2Type::And,
+            Box::new(Expr::Func2(
+                Func2Type::GTE,
+                timestamp(),
+                Box::new(Expr::Const(RawVal::Int(self.watermark))),
+            )),
+            Box::new(Expr::Func2(
+                Func2Type::LT,
+                timestamp(),
+                Box::new(Expr::Const(RawVal::Int(end))),
+            )),
+        );
+        query.filter = Expr::Func2(Func2Type::And, Box::new(query.filter), Box::new(window));
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        let mut scheduled_query =
+            ScheduledQuery::new("rollup", "SELECT count(0) FROM events", "counts", "ts", 60)
+                .unwrap();
+        assert_eq!(scheduled_query.due(30), None);
+        assert_eq!(scheduled_query.due(130), Some(120));
+        scheduled_query.watermark = 120;
+        assert_eq!(scheduled_query.due(179), None);
+        assert_eq!(scheduled_query.due(180), Some(180));
+    }
+}
@@ -0,0 +1,53 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Response to admin requests if the server was started without an admin token
+const DISABLED: &str =
+    "Admin endpoints are disabled, start the server with --admin-token to enable them";
+
+/// Checks that `req` carries `Authorization: Bearer <token>` with the admin token configured for the server.
+/// Admin endpoints are disabled if no token is configured.
+pub fn authorize(req: &HttpRequest, admin_token: Option<&str>) -> Result<(), HttpResponse> {
+    let admin_token = match admin_token {
+        Some(admin_token) => admin_token,
+        None => return Err(HttpResponse::Forbidden().json(DISABLED)),
+    };
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "));
+    match token {
+        Some(token) if constant_time_eq(token.as_bytes(), admin_token.as_bytes()) => Ok(()),
+        _ => Err(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .json("Invalid or missing admin token")),
+    }
+}
+
+/// Compares without returning early on the first mismatch, so that the token cannot be guessed from response times.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert!(authorize(&req, Some("secret")).is_ok());
+        let err = authorize(&req, Some("other")).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+        let err = authorize(&req, None).unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        let err = authorize(&TestRequest::default().to_http_request(), Some("secret")).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+    }
+}
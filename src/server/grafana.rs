@@ -0,0 +1,258 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::value_to_json;
+use crate::{QueryOutput, Value};
+
+/// Macro in targets and annotation queries that expands to a filter of the given column on the time range of the
+/// dashboard, e.g. `$__timeFilter(timestamp)`. Times are milliseconds since the Unix epoch.
+const TIME_FILTER_MACRO: &str = "$__timeFilter(";
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SearchRequest {
+    #[serde(default)]
+    pub target: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TimeRange {
+    pub from: String,
+    pub to: String,
+}
+
+impl TimeRange {
+    /// Start and end of the range in milliseconds since the Unix epoch.
+    pub fn millis(&self) -> Result<(i64, i64), String> {
+        let parse = |time: &str| {
+            DateTime::parse_from_rfc3339(time)
+                .map(|time| time.timestamp_millis())
+                .map_err(|err| format!("Invalid time {}: {}", time, err))
+        };
+        Ok((parse(&self.from)?, parse(&self.to)?))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Target {
+    /// LocustDB query of the panel
+    #[serde(default)]
+    pub target: String,
+    /// `timeserie` or `table`
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub hide: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct QueryRequest {
+    pub range: TimeRange,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Annotation {
+    #[serde(default)]
+    pub query: String,
+    /// Remaining properties are echoed back with each annotation
+    #[serde(flatten)]
+    pub properties: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnnotationsRequest {
+    pub range: TimeRange,
+    pub annotation: Annotation,
+}
+
+/// Replaces `$__timeFilter(column)` with a filter that selects rows of `column` within `[from, to)`.
+pub fn expand_macros(query: &str, (from, to): (i64, i64)) -> Result<String, String> {
+    let mut expanded = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(start) = rest.find(TIME_FILTER_MACRO) {
+        let args = &rest[start + TIME_FILTER_MACRO.len()..];
+        let end = args
+            .find(')')
+            .ok_or_else(|| format!("Unterminated $__timeFilter in {}", query))?;
+        let column = args[..end].trim();
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&format!("({} >= {} AND {} < {})", column, from, column, to));
+        rest = &args[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Converts the result of a `timeserie` target into one series per column after the first, which holds the time of
+/// each row in milliseconds.
+pub fn to_timeseries(output: &QueryOutput) -> Result<Vec<serde_json::Value>, String> {
+    if output.colnames.len() < 2 {
+        return Err(
+            "Time series queries must select a time column followed by value columns".to_string(),
+        );
+    }
+    let rows = output.rows.as_deref().unwrap_or_default();
+    let times = rows
+        .iter()
+        .map(|row| time_millis(&row[0]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(output.colnames[1..]
+        .iter()
+        .enumerate()
+        .map(|(i, colname)| {
+            let datapoints = rows
+                .iter()
+                .zip(&times)
+                .map(|(row, time)| json!([value_to_json(&row[i + 1]), time]))
+                .collect::<Vec<_>>();
+            json!({ "target": colname, "datapoints": datapoints })
+        })
+        .collect())
+}
+
+/// Converts the result of a `table` target.
+pub fn to_table(output: &QueryOutput) -> serde_json::Value {
+    let rows = output.rows.as_deref().unwrap_or_default();
+    let columns = output
+        .colnames
+        .iter()
+        .enumerate()
+        .map(|(i, colname)| {
+            let is_string = rows.iter().any(|row| matches!(row[i], Value::Str(_)));
+            let kind = if is_string { "string" } else { "number" };
+            json!({ "text": colname, "type": kind })
+        })
+        .collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(value_to_json).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    json!({ "type": "table", "columns": columns, "rows": rows })
+}
+
+/// Converts each row of the result of an annotation query into an annotation. The time is taken from the `time`
+/// column or the first column, and the `title`, `text` and comma separated `tags` from the columns of that name.
+pub fn to_annotations(
+    output: &QueryOutput,
+    annotation: &Annotation,
+) -> Result<Vec<serde_json::Value>, String> {
+    let column = |name: &str| output.colnames.iter().position(|colname| colname == name);
+    let time_column = match column("time") {
+        Some(time_column) => time_column,
+        None if !output.colnames.is_empty() => 0,
+        None => return Err("Annotation queries must select a time column".to_string()),
+    };
+    let text = |row: &[Value], name: &str| match column(name).map(|i| &row[i]) {
+        Some(Value::Str(str)) => str.clone(),
+        Some(Value::Int(int)) => int.to_string(),
+        Some(Value::Float(float)) => float.0.to_string(),
+        Some(Value::Null) | None => String::new(),
+    };
+    output
+        .rows
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|row| {
+            let tags = text(row, "tags");
+            let tags = tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>();
+            Ok(json!({
+                "annotation": annotation,
+                "time": time_millis(&row[time_column])?,
+                "title": text(row, "title"),
+                "text": text(row, "text"),
+                "tags": tags,
+            }))
+        })
+        .collect()
+}
+
+fn time_millis(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Int(int) => Ok(*int),
+        Value::Float(float) => Ok(float.0 as i64),
+        _ => Err(format!(
+            "Time column contains non-numeric value {:?}",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn output(colnames: &[&str], rows: Vec<Vec<Value>>) -> QueryOutput {
+        QueryOutput {
+            colnames: colnames.iter().map(|colname| colname.to_string()).collect(),
+            rows: Some(rows),
+            columns: vec![],
+            query_plans: HashMap::new(),
+            stats: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        assert_eq!(
+            expand_macros(
+                "SELECT ts, value FROM metrics WHERE $__timeFilter( ts ) AND host = 'a';",
+                (1000, 2000)
+            )
+            .unwrap(),
+            "SELECT ts, value FROM metrics WHERE (ts >= 1000 AND ts < 2000) AND host = 'a';"
+        );
+        assert!(expand_macros("SELECT * FROM t WHERE $__timeFilter(ts;", (0, 1)).is_err());
+    }
+
+    #[test]
+    fn test_time_range() {
+        let range = TimeRange {
+            from: "2016-10-31T06:33:44.866Z".to_string(),
+            to: "2016-10-31T12:33:44.866Z".to_string(),
+        };
+        assert_eq!(range.millis().unwrap(), (1477895624866, 1477917224866));
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let output = output(
+            &["ts", "value"],
+            vec![
+                vec![Value::Int(1000), Value::Float(0.5.into())],
+                vec![Value::Int(2000), Value::Null],
+            ],
+        );
+        assert_eq!(
+            to_timeseries(&output).unwrap(),
+            vec![json!({ "target": "value", "datapoints": [[0.5, 1000], [null, 2000]] })]
+        );
+    }
+
+    #[test]
+    fn test_to_annotations() {
+        let output = output(
+            &["time", "title", "tags"],
+            vec![vec![
+                Value::Int(1000),
+                Value::Str("deploy".to_string()),
+                Value::Str("a, b".to_string()),
+            ]],
+        );
+        let annotation = Annotation {
+            query: String::new(),
+            properties: serde_json::Map::new(),
+        };
+        let annotations = to_annotations(&output, &annotation).unwrap();
+        assert_eq!(annotations[0]["time"], json!(1000));
+        assert_eq!(annotations[0]["title"], json!("deploy"));
+        assert_eq!(annotations[0]["tags"], json!(["a", "b"]));
+    }
+}
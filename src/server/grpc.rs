@@ -0,0 +1,205 @@
+use std::io;
+use std::net::{AddrParseError, SocketAddr};
+use std::sync::Arc;
+use std::thread;
+
+use actix_web::web::Bytes;
+use futures::executor::block_on;
+use futures::{SinkExt, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use super::ChunkReader;
+use crate::{LoadOptions, LocustDB, QueryError, Value};
+
+mod proto {
+    tonic::include_proto!("locustdb");
+}
+
+use self::proto::locust_db_server::{LocustDb, LocustDbServer};
+use self::proto::{Format, InsertRequest, InsertResponse, QueryRequest, QueryResponse, Row};
+
+struct GrpcService {
+    db: Arc<LocustDB>,
+}
+
+#[tonic::async_trait]
+impl LocustDb for GrpcService {
+    async fn query(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<QueryResponse>, Status> {
+        let query = request.into_inner().query;
+        log::debug!("Query: {:?}", query);
+        let db = self.db.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            block_on(db.run_query(&query, false, true, vec![]))
+        })
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?
+        .map_err(|_| Status::cancelled("Query was canceled"))?
+        .map_err(query_error_status)?;
+        Ok(Response::new(QueryResponse {
+            id: 0,
+            colnames: result.colnames,
+            rows: result
+                .rows
+                .unwrap_or_default()
+                .iter()
+                .map(|row| to_row(row))
+                .collect(),
+        }))
+    }
+
+    type QueryStreamStream = futures::channel::mpsc::Receiver<Result<QueryResponse, Status>>;
+
+    async fn query_stream(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<Self::QueryStreamStream>, Status> {
+        let query = request.into_inner().query;
+        log::debug!("Query stream: {:?}", query);
+        let (started, start) = oneshot::channel();
+        let (mut responses, stream) = futures::channel::mpsc::channel(1);
+        let db = self.db.clone();
+        // The cursor borrows the database, so it is driven by a thread that owns a reference to it
+        tokio::task::spawn_blocking(move || {
+            block_on(async move {
+                let mut cursor = match db.query_cursor(&query).await {
+                    Ok(cursor) => cursor,
+                    Err(err) => {
+                        let _ = started.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = started.send(Ok(()));
+                let header = QueryResponse {
+                    id: cursor.id(),
+                    colnames: cursor.colnames().to_vec(),
+                    rows: vec![],
+                };
+                if responses.send(Ok(header)).await.is_err() {
+                    return;
+                }
+                while let Some(rows) = cursor.next().await {
+                    let response = rows
+                        .map(|rows| QueryResponse {
+                            rows: rows.iter().map(|row| to_row(row)).collect(),
+                            ..QueryResponse::default()
+                        })
+                        .map_err(query_error_status);
+                    // Waits until the client has received the previous response, and fails once the client
+                    // disconnects which drops and kills the query
+                    if responses.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        });
+        match start.await {
+            Ok(Ok(())) => Ok(Response::new(stream)),
+            Ok(Err(err)) => Err(query_error_status(err)),
+            Err(_) => Err(Status::cancelled("Query was canceled")),
+        }
+    }
+
+    async fn insert(
+        &self,
+        request: Request<Streaming<InsertRequest>>,
+    ) -> Result<Response<InsertResponse>, Status> {
+        let mut requests = request.into_inner();
+        let first = match requests.message().await? {
+            Some(first) => first,
+            None => return Err(Status::invalid_argument("Empty insert stream")),
+        };
+        let csv = first.format() == Format::Csv;
+
+        // Chunks are decoded on a blocking thread while they are being received
+        let (sender, receiver) = mpsc::channel(16);
+        let db = self.db.clone();
+        let table = first.table.clone();
+        let ingestion = tokio::task::spawn_blocking(move || {
+            let reader = ChunkReader {
+                receiver,
+                chunk: Bytes::new(),
+            };
+            if csv {
+                db.ingest_csv(&LoadOptions::new("", &table), reader)
+            } else {
+                db.ingest_ndjson(&table, io::BufReader::new(reader))
+            }
+        });
+        let mut next = Some(Ok(first));
+        while let Some(request) = next {
+            let chunk = request
+                .map(|request| Bytes::from(request.data))
+                .map_err(|status| io::Error::new(io::ErrorKind::Other, status.to_string()));
+            if let Ok(chunk) = &chunk {
+                self.db
+                    .perf_counter()
+                    .network_read_ingestion(chunk.len() as u64);
+            }
+            let failed = chunk.is_err();
+            // Send fails if ingestion has already stopped with an error
+            if sender.send(chunk).await.is_err() || failed {
+                break;
+            }
+            next = requests.next().await;
+        }
+        drop(sender);
+
+        match ingestion.await {
+            Ok(Ok(rows)) => Ok(Response::new(InsertResponse { rows: rows as u64 })),
+            Ok(Err(err)) => {
+                log::error!("Failed to ingest gRPC insert request: {}", err);
+                Err(query_error_status(err))
+            }
+            Err(err) => {
+                log::error!("Ingestion of gRPC insert request panicked: {}", err);
+                Err(Status::internal(err.to_string()))
+            }
+        }
+    }
+}
+
+fn query_error_status(err: QueryError) -> Status {
+    match err {
+        QueryError::NotImplemented(_) => Status::unimplemented(err.to_string()),
+        _ => Status::invalid_argument(err.to_string()),
+    }
+}
+
+fn to_row(row: &[Value]) -> Row {
+    let values = row
+        .iter()
+        .map(|val| proto::Value {
+            value: Some(match val {
+                Value::Int(int) => proto::value::Value::Int(*int),
+                Value::Float(float) => proto::value::Value::Float(float.0),
+                Value::Str(str) => proto::value::Value::Str(str.clone()),
+                Value::Null => proto::value::Value::Null(true),
+            }),
+        })
+        .collect();
+    Row { values }
+}
+
+/// Serves the gRPC API defined in `proto/locustdb.proto` on `addr`.
+pub async fn serve(db: Arc<LocustDB>, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(LocustDbServer::new(GrpcService { db }))
+        .serve(addr)
+        .await
+}
+
+/// Starts serving the gRPC API on `addrs` on a separate thread, see `serve`.
+pub fn run(db: Arc<LocustDB>, addrs: &str) -> Result<thread::JoinHandle<()>, AddrParseError> {
+    let addr = addrs.parse()?;
+    Ok(thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        if let Err(err) = runtime.block_on(serve(db, addr)) {
+            log::error!("gRPC server failed: {}", err);
+        }
+    }))
+}
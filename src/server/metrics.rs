@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use crate::perf_counter::{PerfCounter, QUERY_LATENCY_BUCKETS};
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Renders the values of `perf_counter` in the Prometheus text exposition format.
+pub fn render(perf_counter: &PerfCounter) -> String {
+    let mut metrics = String::new();
+    let counters = [
+        (
+            "locustdb_ingestion_requests_total",
+            "Number of ingestion requests received over the network",
+            perf_counter.ingestion_requests(),
+        ),
+        (
+            "locustdb_ingestion_bytes_total",
+            "Bytes of ingestion requests received over the network",
+            perf_counter.network_read_ingestion_bytes(),
+        ),
+        (
+            "locustdb_disk_write_wal_bytes_total",
+            "Bytes written to WAL segments",
+            perf_counter.disk_write_wal_bytes(),
+        ),
+        (
+            "locustdb_disk_write_partition_bytes_total",
+            "Bytes written to new partitions",
+            perf_counter.disk_write_new_partition_bytes(),
+        ),
+        (
+            "locustdb_disk_write_compaction_bytes_total",
+            "Bytes written to partitions by compactions",
+            perf_counter.disk_write_compaction_bytes(),
+        ),
+        (
+            "locustdb_disk_write_meta_store_bytes_total",
+            "Bytes written to the meta store",
+            perf_counter.disk_write_meta_store_bytes(),
+        ),
+        (
+            "locustdb_disk_read_partition_bytes_total",
+            "Bytes read from partitions",
+            perf_counter.disk_read_partition_bytes(),
+        ),
+        (
+            "locustdb_disk_read_wal_bytes_total",
+            "Bytes read from WAL segments",
+            perf_counter.disk_read_wal_bytes(),
+        ),
+        (
+            "locustdb_disk_read_meta_store_bytes_total",
+            "Bytes read from the meta store",
+            perf_counter.disk_read_meta_store_bytes(),
+        ),
+        (
+            "locustdb_partition_files_opened_total",
+            "Number of partition files read",
+            perf_counter.files_opened_partition(),
+        ),
+        (
+            "locustdb_files_created_total",
+            "Number of WAL, partition and meta store files created",
+            perf_counter.files_created(),
+        ),
+        (
+            "locustdb_cache_evictions_total",
+            "Number of columns evicted from memory",
+            perf_counter.cache_evictions(),
+        ),
+        (
+            "locustdb_cache_evicted_bytes_total",
+            "Bytes of columns evicted from memory",
+            perf_counter.cache_evicted_bytes(),
+        ),
+        (
+            "locustdb_queries_started_total",
+            "Number of queries that have been started",
+            perf_counter.queries_started(),
+        ),
+    ];
+    for (name, help, value) in counters {
+        writeln!(metrics, "# HELP {} {}", name, help).unwrap();
+        writeln!(metrics, "# TYPE {} counter", name).unwrap();
+        writeln!(metrics, "{} {}", name, value).unwrap();
+    }
+
+    writeln!(
+        metrics,
+        "# HELP locustdb_wal_size_bytes Size of the WAL segments that have not been flushed to partitions"
+    )
+    .unwrap();
+    writeln!(metrics, "# TYPE locustdb_wal_size_bytes gauge").unwrap();
+    writeln!(
+        metrics,
+        "locustdb_wal_size_bytes {}",
+        perf_counter.wal_size_bytes()
+    )
+    .unwrap();
+
+    let latencies = perf_counter.query_latencies();
+    let name = "locustdb_query_duration_seconds";
+    writeln!(metrics, "# HELP {} Runtime of completed queries", name).unwrap();
+    writeln!(metrics, "# TYPE {} histogram", name).unwrap();
+    let mut cumulative = 0;
+    for (le, count) in QUERY_LATENCY_BUCKETS.iter().zip(latencies.buckets) {
+        cumulative += count;
+        writeln!(metrics, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative).unwrap();
+    }
+    writeln!(
+        metrics,
+        "{}_bucket{{le=\"+Inf\"}} {}",
+        name, latencies.count
+    )
+    .unwrap();
+    writeln!(metrics, "{}_sum {}", name, latencies.sum_ns as f64 / 1e9).unwrap();
+    writeln!(metrics, "{}_count {}", name, latencies.count).unwrap();
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let perf_counter = PerfCounter::new();
+        perf_counter.network_read_ingestion(100);
+        perf_counter.set_wal_size(42);
+        perf_counter.cache_evicted(7);
+        let metrics = render(&perf_counter);
+        assert!(metrics.contains("\nlocustdb_ingestion_bytes_total 100\n"));
+        assert!(metrics.contains("\nlocustdb_wal_size_bytes 42\n"));
+        assert!(metrics.contains("\nlocustdb_cache_evictions_total 1\n"));
+        assert!(metrics.contains("\nlocustdb_query_duration_seconds_bucket{le=\"+Inf\"} 0\n"));
+        assert!(metrics.contains("# TYPE locustdb_query_duration_seconds histogram\n"));
+    }
+}
@@ -2,21 +2,47 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use actix_cors::Cors;
 use actix_web::dev::ServerHandle;
+use actix_web::http::header;
 use actix_web::web::{Bytes, Data};
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    get, middleware, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
 use futures::channel::oneshot::Canceled;
+use futures::{SinkExt, StreamExt};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tera::{Context, Tera};
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 
-use crate::{logging_client, BasicTypeColumn, LocustDB};
+use crate::{logging_client, BasicTypeColumn, LoadOptions, LocustDB};
 use crate::{QueryError, QueryOutput, Value};
 
+mod admin;
+mod grafana;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod metrics;
+mod pagination;
+mod result_format;
+mod tls;
+
+use self::pagination::{PagedQueries, PagedQuery};
+use self::result_format::ResultFormat;
+pub use self::tls::TlsConfig;
+
+/// Interval between progress events streamed by `/queries/{query_id}/progress`
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Response header with the cursor of the next page of paginated `/query` results that are not returned as JSON
+const CURSOR_HEADER: &str = "x-locustdb-cursor";
+/// Request header with the user that is recorded in the query log for queries run by `/query`. The header is not
+/// authenticated, so it is recorded as the `client_user`.
+const USER_HEADER: &str = "x-locustdb-user";
+
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
         let mut tera = match Tera::new("templates/**/*") {
@@ -41,11 +67,28 @@ struct DataBatch {
 #[derive(Clone)]
 struct AppState {
     db: Arc<LocustDB>,
+    paged_queries: Arc<PagedQueries>,
+    /// Bearer token required by `/admin` endpoints
+    admin_token: Option<Arc<str>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct InsertParams {
+    format: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct QueryParams {
+    format: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct QueryRequest {
     query: String,
+    /// Returns at most this many rows and a cursor for the next page, only supported by `/query`
+    max_rows: Option<usize>,
+    /// Cursor returned with the previous page of the same query
+    cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -145,6 +188,154 @@ async fn tables(data: web::Data<AppState>) -> impl Responder {
     HttpResponse::Ok().body(body)
 }
 
+/// Counters of running and recently completed queries.
+#[get("/queries")]
+async fn queries(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.db.perf_counter().query_snapshots())
+}
+
+/// Values of the database's `PerfCounter` in the Prometheus text exposition format.
+#[get("/metrics")]
+async fn prometheus_metrics(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(metrics::CONTENT_TYPE)
+        .body(metrics::render(data.db.perf_counter()))
+}
+
+/// Liveness probe that fails with `503 Service Unavailable` if a worker thread died or the WAL is not writable.
+#[get("/healthz")]
+async fn healthz(data: web::Data<AppState>) -> impl Responder {
+    let health = data.db.health();
+    if health.is_healthy() {
+        HttpResponse::Ok().json(health)
+    } else {
+        HttpResponse::ServiceUnavailable().json(health)
+    }
+}
+
+/// Readiness probe that additionally fails while ingestion is blocked by the WAL backlog or rejected due to memory
+/// pressure.
+#[get("/readyz")]
+async fn readyz(data: web::Data<AppState>) -> impl Responder {
+    let health = data.db.health();
+    if health.is_ready() {
+        HttpResponse::Ok().json(health)
+    } else {
+        HttpResponse::ServiceUnavailable().json(health)
+    }
+}
+
+/// Progress of the query with the id listed by `/queries`. Clients that accept `text/event-stream` receive a
+/// server-sent event with the progress every `PROGRESS_INTERVAL` until the query completes.
+#[get("/queries/{query_id}/progress")]
+async fn query_progress(
+    req: HttpRequest,
+    path: web::Path<u64>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let query_id = path.into_inner();
+    let progress = match data.db.query_progress(query_id) {
+        Some(progress) => progress,
+        None => return HttpResponse::NotFound().json(format!("Query {} not found", query_id)),
+    };
+    let accepts_events = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map_or(false, |accept| accept.contains("text/event-stream"));
+    if !accepts_events {
+        return HttpResponse::Ok().json(progress);
+    }
+
+    let db = data.db.clone();
+    let events = futures::stream::unfold(Some(progress), move |progress| {
+        let db = db.clone();
+        async move {
+            let progress = progress?;
+            let event = format!("data: {}\n\n", serde_json::to_string(&progress).unwrap());
+            let next = if progress.completed {
+                None
+            } else {
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+                db.query_progress(query_id)
+            };
+            Some((Ok::<_, actix_web::Error>(Bytes::from(event)), next))
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compression would hold back events until enough of them have been buffered
+        .insert_header(header::ContentEncoding::Identity)
+        .streaming(events)
+}
+
+/// Kills a runaway query by the id listed by `/queries`.
+#[post("/admin/kill/{query_id}")]
+async fn kill_query(
+    req: HttpRequest,
+    path: web::Path<u64>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = admin::authorize(&req, data.admin_token.as_deref()) {
+        return response;
+    }
+    let query_id = path.into_inner();
+    if data.db.kill_query(query_id) {
+        log::warn!("Killed query {}", query_id);
+        HttpResponse::Ok().json(json!({ "status": "ok" }))
+    } else {
+        HttpResponse::NotFound().json(format!("Query {} is not running", query_id))
+    }
+}
+
+/// Writes all buffered rows to partitions and reports the bytes written, see `LocustDB::force_flush`.
+#[post("/admin/flush")]
+async fn admin_flush(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = admin::authorize(&req, data.admin_token.as_deref()) {
+        return response;
+    }
+    let db = data.db.clone();
+    match tokio::task::spawn_blocking(move || db.force_flush()).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(err)) => HttpResponse::InternalServerError().json(err.to_string()),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+/// Compacts the partitions of the table given in the path, see `LocustDB::compact_table`.
+#[post("/admin/compact/{table}")]
+async fn admin_compact(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = admin::authorize(&req, data.admin_token.as_deref()) {
+        return response;
+    }
+    let db = data.db.clone();
+    let table = path.into_inner();
+    match tokio::task::spawn_blocking(move || db.compact_table(&table)).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(err)) => HttpResponse::NotFound().json(err.to_string()),
+        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
+    }
+}
+
+/// Evicts all columns tracked by the LRU cache from memory, see `LocustDB::evict_cache`.
+#[post("/admin/evict_cache")]
+async fn admin_evict_cache(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = admin::authorize(&req, data.admin_token.as_deref()) {
+        return response;
+    }
+    let start_time = Instant::now();
+    let bytes_evicted = data.db.evict_cache();
+    HttpResponse::Ok().json(json!({
+        "bytes_evicted": bytes_evicted,
+        "duration_ns": start_time.elapsed().as_nanos() as u64,
+    }))
+}
+
 #[post("/echo")]
 async fn echo(req_body: String) -> impl Responder {
     HttpResponse::Ok().body(req_body)
@@ -162,17 +353,135 @@ async fn query_data(_data: web::Data<AppState>) -> impl Responder {
     HttpResponse::Ok().json(response)
 }
 
+/// Runs a query and returns the result as JSON, or as CSV, newline-delimited JSON or Arrow IPC stream.
+/// The format is taken from the `format` query parameter (`json`, `csv`, `ndjson` or `arrow`) or the `Accept` header.
+/// Requests with `max_rows` return one page of rows together with a cursor that is passed along with the query to
+/// fetch the next page, see `paginated_query`.
 #[post("/query")]
-async fn query(data: web::Data<AppState>, req_body: web::Json<QueryRequest>) -> impl Responder {
+async fn query(
+    data: web::Data<AppState>,
+    params: web::Query<QueryParams>,
+    req: HttpRequest,
+    req_body: web::Json<QueryRequest>,
+) -> impl Responder {
     log::debug!("Query: {:?}", req_body);
-    let result = data
-        .db
-        .run_query(&req_body.query, false, true, vec![])
-        .await
-        .unwrap()
-        .unwrap();
+    let format = match params.format.as_deref() {
+        Some(format) => match ResultFormat::from_param(format) {
+            Some(format) => format,
+            None => {
+                return HttpResponse::BadRequest().json(format!("Unsupported format {}", format))
+            }
+        },
+        None => req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map_or(ResultFormat::Json, ResultFormat::from_accept),
+    };
+    if req_body.max_rows.is_some() || req_body.cursor.is_some() {
+        return paginated_query(&data, req_body.into_inner(), format).await;
+    }
+    let user = req
+        .headers()
+        .get(USER_HEADER)
+        .and_then(|user| user.to_str().ok());
+    let result = match user {
+        Some(user) => {
+            data.db
+                .run_query_as(user, &req_body.query, !format.is_columnar())
+                .await
+        }
+        None => {
+            data.db
+                .run_query(&req_body.query, false, !format.is_columnar(), vec![])
+                .await
+        }
+    };
+    match flatmap_err_response(result) {
+        Ok(result) => query_output_response(result, format),
+        Err(err) => err,
+    }
+}
 
-    let response = json!({
+/// Returns the next page of a paginated query. The cursor of the next page is included in JSON responses and in the
+/// `x-locustdb-cursor` header of responses in other formats, and is absent on the last page. Queries are kept running
+/// between pages and dropped if the next page is not requested within a few minutes.
+async fn paginated_query(
+    data: &AppState,
+    request: QueryRequest,
+    format: ResultFormat,
+) -> HttpResponse {
+    if format.is_columnar() {
+        return HttpResponse::BadRequest().json("Arrow IPC results can't be paginated");
+    }
+    let mut paged_query = match &request.cursor {
+        Some(cursor) => match data.paged_queries.take(cursor) {
+            Some(paged_query) if paged_query.query() == request.query => paged_query,
+            Some(_) => {
+                return HttpResponse::BadRequest().json("Cursor belongs to a different query")
+            }
+            None => return HttpResponse::BadRequest().json("Unknown or expired cursor"),
+        },
+        None => {
+            let max_rows = request.max_rows.unwrap_or(usize::MAX);
+            match PagedQuery::start(data.db.clone(), request.query, max_rows).await {
+                Ok(paged_query) => paged_query,
+                Err(err) => return query_error_response(err),
+            }
+        }
+    };
+    if let Some(max_rows) = request.max_rows {
+        paged_query.set_max_rows(max_rows);
+    }
+    let (rows, more) = match paged_query.next_page().await {
+        Ok(page) => page,
+        Err(err) => return query_error_response(err),
+    };
+    let output = QueryOutput {
+        colnames: paged_query.colnames().to_vec(),
+        rows: Some(rows),
+        columns: vec![],
+        query_plans: HashMap::new(),
+        stats: Default::default(),
+    };
+    let cursor = if more {
+        Some(data.paged_queries.insert(paged_query))
+    } else {
+        None
+    };
+
+    if format == ResultFormat::Json {
+        let mut response = query_output_to_json_rows(output);
+        response["cursor"] = json!(cursor);
+        return HttpResponse::Ok().json(response);
+    }
+    let mut response = query_output_response(output, format);
+    if let Some(cursor) = cursor {
+        response.headers_mut().insert(
+            header::HeaderName::from_static(CURSOR_HEADER),
+            header::HeaderValue::from_str(&cursor).unwrap(),
+        );
+    }
+    response
+}
+
+fn query_output_response(result: QueryOutput, format: ResultFormat) -> HttpResponse {
+    let body = match format {
+        ResultFormat::Json => return HttpResponse::Ok().json(query_output_to_json_rows(result)),
+        ResultFormat::Csv => result_format::to_csv(&result),
+        ResultFormat::Ndjson => Ok(result_format::to_ndjson(&result)),
+        ResultFormat::ArrowIpc => result_format::to_arrow_ipc(result),
+    };
+    match body {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(format.content_type())
+            .body(body),
+        Err(err) => query_error_response(err),
+    }
+}
+
+fn query_output_to_json_rows(result: QueryOutput) -> serde_json::Value {
+    json!({
         "colnames": result.colnames,
         "rows": result.rows.unwrap().iter().map(|row| row.iter().map(|val| match val {
             Value::Int(int) => json!(int),
@@ -181,8 +490,58 @@ async fn query(data: web::Data<AppState>, req_body: web::Json<QueryRequest>) ->
             Value::Float(float) => json!(float.0),
         }).collect::<Vec<_>>()).collect::<Vec<_>>(),
         "stats": result.stats,
+    })
+}
+
+/// Streams the rows of a query without `ORDER BY` and aggregations as newline-delimited JSON while its partitions are
+/// scanned. The first line holds the query id and column names, each following line one row.
+#[post("/query_stream")]
+async fn query_stream(
+    data: web::Data<AppState>,
+    req_body: web::Json<QueryRequest>,
+) -> impl Responder {
+    log::debug!("Query stream: {:?}", req_body);
+    let (started, start) = oneshot::channel();
+    let (mut lines, body) = futures::channel::mpsc::channel(1);
+    let db = data.db.clone();
+    let query = req_body.into_inner().query;
+    // The cursor borrows the database, so it is driven by a task that owns a reference to it
+    actix_web::rt::spawn(async move {
+        let mut cursor = match db.query_cursor(&query).await {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                let _ = started.send(Err(err));
+                return;
+            }
+        };
+        let header = json!({ "id": cursor.id(), "colnames": cursor.colnames() });
+        let _ = started.send(Ok(header));
+        while let Some(rows) = cursor.next().await {
+            let mut chunk = String::new();
+            match rows {
+                Ok(rows) => {
+                    for row in rows {
+                        let row = row.iter().map(value_to_json).collect::<Vec<_>>();
+                        writeln!(chunk, "{}", json!(row)).unwrap();
+                    }
+                }
+                Err(err) => writeln!(chunk, "{}", json!({ "error": err.to_string() })).unwrap(),
+            }
+            // Fails once the client disconnects, which drops and kills the query
+            if lines.send(Bytes::from(chunk)).await.is_err() {
+                break;
+            }
+        }
     });
-    HttpResponse::Ok().json(response)
+    let header = match start.await {
+        Ok(Ok(header)) => header,
+        Ok(Err(err)) => return HttpResponse::BadRequest().json(err.to_string()),
+        Err(_) => return HttpResponse::InternalServerError().json("Query was canceled"),
+    };
+    let header = futures::stream::once(async move { Bytes::from(format!("{}\n", header)) });
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(header.chain(body).map(Ok::<_, actix_web::Error>))
 }
 
 #[post("/query_cols")]
@@ -204,6 +563,25 @@ async fn query_cols(
     }
 }
 
+/// Runs a query and returns the bincode serialized `QueryOutput` with the result columns, used by the Python client.
+#[post("/query_bin")]
+async fn query_bin(data: web::Data<AppState>, req_body: web::Json<QueryRequest>) -> impl Responder {
+    log::debug!("Query: {:?}", req_body);
+    let result = data
+        .db
+        .run_query(&req_body.query, false, false, vec![])
+        .await;
+    match flatmap_err_response(result) {
+        Ok(result) => match bincode::serialize(&result) {
+            Ok(body) => HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .body(body),
+            Err(err) => query_error_response(fatal!("Failed to serialize result: {}", err)),
+        },
+        Err(err) => err,
+    }
+}
+
 #[post("/multi_query_cols")]
 async fn multi_query_cols(
     data: web::Data<AppState>,
@@ -252,15 +630,112 @@ fn flatmap_err_response(
 ) -> Result<QueryOutput, HttpResponse> {
     match err {
         Ok(Ok(result)) => Ok(result),
-        Ok(Err(QueryError::NotImplemented(msg))) => Err(HttpResponse::NotImplemented().json(msg)),
-        Ok(Err(QueryError::FatalError(msg, bt))) => {
-            Err(HttpResponse::InternalServerError().json((msg, bt.to_string())))
-        }
-        Ok(Err(err)) => Err(HttpResponse::BadRequest().json(err.to_string())),
+        Ok(Err(err)) => Err(query_error_response(err)),
         Err(err) => Err(HttpResponse::InternalServerError().json(err.to_string())),
     }
 }
 
+fn query_error_response(err: QueryError) -> HttpResponse {
+    match err {
+        QueryError::NotImplemented(msg) => HttpResponse::NotImplemented().json(msg),
+        QueryError::FatalError(msg, bt) => {
+            HttpResponse::InternalServerError().json((msg, bt.to_string()))
+        }
+        err => HttpResponse::BadRequest().json(err.to_string()),
+    }
+}
+
+/// Connection test of the Grafana JSON datasource, whose URL is `<server>/grafana`.
+#[get("/grafana/")]
+async fn grafana_test() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Lists the tables whose name contains the search target of a Grafana query editor.
+#[post("/grafana/search")]
+async fn grafana_search(
+    data: web::Data<AppState>,
+    req_body: web::Json<grafana::SearchRequest>,
+) -> impl Responder {
+    let tables = match data.db.table_stats().await {
+        Ok(tables) => tables,
+        Err(err) => return HttpResponse::InternalServerError().json(err.to_string()),
+    };
+    let names = tables
+        .into_iter()
+        .map(|table| table.name)
+        .filter(|name| name.contains(&req_body.target))
+        .sorted()
+        .collect::<Vec<_>>();
+    HttpResponse::Ok().json(names)
+}
+
+/// Runs the queries of the targets of a Grafana panel. `timeserie` targets select the time in milliseconds followed by
+/// one column per series, `table` targets may select any columns.
+#[post("/grafana/query")]
+async fn grafana_query(
+    data: web::Data<AppState>,
+    req_body: web::Json<grafana::QueryRequest>,
+) -> impl Responder {
+    let range = match req_body.range.millis() {
+        Ok(range) => range,
+        Err(err) => return HttpResponse::BadRequest().json(json!({ "message": err })),
+    };
+    let mut results = Vec::new();
+    for target in req_body.targets.iter().filter(|target| !target.hide) {
+        let output = match grafana_run_query(&data, &target.target, range).await {
+            Ok(output) => output,
+            Err(err) => return err,
+        };
+        if target.kind.as_deref() == Some("table") {
+            results.push(grafana::to_table(&output));
+        } else {
+            match grafana::to_timeseries(&output) {
+                Ok(series) => results.extend(series),
+                Err(err) => return HttpResponse::BadRequest().json(json!({ "message": err })),
+            }
+        }
+    }
+    HttpResponse::Ok().json(results)
+}
+
+/// Runs the query of a Grafana annotation and returns one annotation per row.
+#[post("/grafana/annotations")]
+async fn grafana_annotations(
+    data: web::Data<AppState>,
+    req_body: web::Json<grafana::AnnotationsRequest>,
+) -> impl Responder {
+    let range = match req_body.range.millis() {
+        Ok(range) => range,
+        Err(err) => return HttpResponse::BadRequest().json(json!({ "message": err })),
+    };
+    let output = match grafana_run_query(&data, &req_body.annotation.query, range).await {
+        Ok(output) => output,
+        Err(err) => return err,
+    };
+    match grafana::to_annotations(&output, &req_body.annotation) {
+        Ok(annotations) => HttpResponse::Ok().json(annotations),
+        Err(err) => HttpResponse::BadRequest().json(json!({ "message": err })),
+    }
+}
+
+/// Expands the macros of a Grafana query and runs it. Errors are returned in the format displayed by Grafana.
+async fn grafana_run_query(
+    data: &AppState,
+    query: &str,
+    range: (i64, i64),
+) -> Result<QueryOutput, HttpResponse> {
+    let query = grafana::expand_macros(query, range)
+        .map_err(|err| HttpResponse::BadRequest().json(json!({ "message": err })))?;
+    match data.db.run_query(&query, false, true, vec![]).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(err)) => Err(HttpResponse::BadRequest().json(json!({ "message": err.to_string() }))),
+        Err(err) => {
+            Err(HttpResponse::InternalServerError().json(json!({ "message": err.to_string() })))
+        }
+    }
+}
+
 // TODO: even more efficient, push all data-conversions into client
 #[post("/insert_bin")]
 async fn insert_bin(data: web::Data<AppState>, req_body: Bytes) -> impl Responder {
@@ -296,14 +771,265 @@ async fn insert_bin(data: web::Data<AppState>, req_body: Bytes) -> impl Responde
             .map(|t| t.columns.values().next().map(|c| c.data.len()).unwrap_or(0))
             .sum::<usize>()
     );
-    data.db.ingest_efficient(events).await;
+    match data.db.ingest_efficient(events).await {
+        Ok(()) => {}
+        Err(QueryError::Throttled(retry_after)) => {
+            return throttled_response("/insert_bin", retry_after);
+        }
+        Err(err) => {
+            log::error!("Failed to ingest /insert_bin request: {}", err);
+            return HttpResponse::ServiceUnavailable().json(err.to_string());
+        }
+    }
     HttpResponse::Ok().json(r#"{"status": "ok"}"#)
 }
 
+/// Responds with `429 Too Many Requests` to ingestion requests rejected because the WAL exceeds `max_wal_size_bytes`.
+fn throttled_response(endpoint: &str, retry_after: Duration) -> HttpResponse {
+    log::warn!(
+        "Throttling {} request, WAL exceeds max_wal_size_bytes",
+        endpoint
+    );
+    // Retry-After only supports whole seconds
+    let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+        .json(format!(
+            "Ingestion is throttled, retry after {} seconds",
+            retry_after_secs
+        ))
+}
+
+/// Ingests newline-delimited JSON objects into the table given in the path.
+/// No rows are ingested if any line of the body is invalid.
+#[post("/insert_ndjson/{table}")]
+async fn insert_ndjson(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+    req_body: Bytes,
+) -> impl Responder {
+    data.db
+        .perf_counter()
+        .network_read_ingestion(req_body.len() as u64);
+    let db = data.db.clone();
+    let table = path.into_inner();
+    match tokio::task::spawn_blocking(move || db.ingest_ndjson_atomic(&table, &req_body[..])).await
+    {
+        Ok(Ok(rows)) => HttpResponse::Ok().json(json!({ "status": "ok", "rows": rows })),
+        Ok(Err(QueryError::Throttled(retry_after))) => {
+            throttled_response("/insert_ndjson", retry_after)
+        }
+        Ok(Err(err)) => {
+            log::error!("Failed to ingest /insert_ndjson request: {}", err);
+            HttpResponse::BadRequest().json(err.to_string())
+        }
+        Err(err) => {
+            log::error!("Ingestion of /insert_ndjson request panicked: {}", err);
+            HttpResponse::InternalServerError().json(err.to_string())
+        }
+    }
+}
+
+/// Streams a newline-delimited JSON or CSV body into the table given in the path.
+/// The format is taken from the `format` query parameter or the content type and defaults to NDJSON.
+/// CSV bodies must start with a header row.
+#[post("/insert/{table}")]
+async fn insert(
+    path: web::Path<String>,
+    params: web::Query<InsertParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    mut payload: web::Payload,
+) -> impl Responder {
+    let csv = match params.format.as_deref() {
+        Some("csv") => true,
+        Some("ndjson") | Some("json") => false,
+        Some(format) => {
+            return HttpResponse::BadRequest().json(format!("Unsupported format {}", format))
+        }
+        None => req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map_or(false, |content_type| content_type.starts_with("text/csv")),
+    };
+
+    // Body chunks are decoded on a blocking thread while they are being received
+    let (sender, receiver) = mpsc::channel(16);
+    let db = data.db.clone();
+    let table = path.into_inner();
+    let ingestion = tokio::task::spawn_blocking(move || {
+        let reader = ChunkReader {
+            receiver,
+            chunk: Bytes::new(),
+        };
+        if csv {
+            db.ingest_csv(&LoadOptions::new("", &table), reader)
+        } else {
+            db.ingest_ndjson(&table, std::io::BufReader::new(reader))
+        }
+    });
+    while let Some(chunk) = payload.next().await {
+        let chunk =
+            chunk.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()));
+        if let Ok(chunk) = &chunk {
+            data.db
+                .perf_counter()
+                .network_read_ingestion(chunk.len() as u64);
+        }
+        let failed = chunk.is_err();
+        // Send fails if ingestion has already stopped with an error
+        if sender.send(chunk).await.is_err() || failed {
+            break;
+        }
+    }
+    drop(sender);
+
+    match ingestion.await {
+        Ok(Ok(rows)) => HttpResponse::Ok().json(json!({ "status": "ok", "rows": rows })),
+        Ok(Err(QueryError::Throttled(retry_after))) => throttled_response("/insert", retry_after),
+        Ok(Err(err)) => {
+            log::error!("Failed to ingest /insert request: {}", err);
+            HttpResponse::BadRequest().json(err.to_string())
+        }
+        Err(err) => {
+            log::error!("Ingestion of /insert request panicked: {}", err);
+            HttpResponse::InternalServerError().json(err.to_string())
+        }
+    }
+}
+
+/// Blocking reader over request body chunks received by an async handler.
+struct ChunkReader {
+    receiver: mpsc::Receiver<std::io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl std::io::Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        self.chunk = self.chunk.slice(len..);
+        Ok(len)
+    }
+}
+
+/// Ingests an Arrow IPC stream into the table given in the path.
+#[cfg(feature = "arrow_ingest")]
+#[post("/insert_arrow/{table}")]
+async fn insert_arrow(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+    req_body: Bytes,
+) -> impl Responder {
+    data.db
+        .perf_counter()
+        .network_read_ingestion(req_body.len() as u64);
+    let db = data.db.clone();
+    let table = path.into_inner();
+    match tokio::task::spawn_blocking(move || db.ingest_arrow_ipc_stream(&table, &req_body[..]))
+        .await
+    {
+        Ok(Ok(())) => HttpResponse::Ok().json(r#"{"status": "ok"}"#),
+        Ok(Err(QueryError::Throttled(retry_after))) => {
+            throttled_response("/insert_arrow", retry_after)
+        }
+        Ok(Err(err)) => {
+            log::error!("Failed to ingest /insert_arrow request: {}", err);
+            HttpResponse::BadRequest().json(err.to_string())
+        }
+        Err(err) => {
+            log::error!("Ingestion of /insert_arrow request panicked: {}", err);
+            HttpResponse::InternalServerError().json(err.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+#[derive(Serialize, Deserialize, Debug)]
+struct ProtobufParams {
+    message: String,
+}
+
+/// Registers the message types of a serialized `FileDescriptorSet` for use with `/insert_protobuf`.
+#[cfg(feature = "protobuf")]
+#[post("/protobuf_descriptors")]
+async fn protobuf_descriptors(data: web::Data<AppState>, req_body: Bytes) -> impl Responder {
+    match data.db.register_protobuf_descriptors(&req_body) {
+        Ok(messages) => HttpResponse::Ok().json(json!({ "status": "ok", "messages": messages })),
+        Err(err) => {
+            log::error!("Failed to register protobuf descriptors: {}", err);
+            HttpResponse::BadRequest().json(err.to_string())
+        }
+    }
+}
+
+/// Ingests length-delimited protobuf messages into the table given in the path.
+/// The `message` query parameter names the registered message type, e.g. `?message=app.Event`.
+#[cfg(feature = "protobuf")]
+#[post("/insert_protobuf/{table}")]
+async fn insert_protobuf(
+    path: web::Path<String>,
+    params: web::Query<ProtobufParams>,
+    data: web::Data<AppState>,
+    req_body: Bytes,
+) -> impl Responder {
+    data.db
+        .perf_counter()
+        .network_read_ingestion(req_body.len() as u64);
+    let db = data.db.clone();
+    let table = path.into_inner();
+    let message = params.into_inner().message;
+    match tokio::task::spawn_blocking(move || db.ingest_protobuf(&table, &message, &req_body)).await
+    {
+        Ok(Ok(rows)) => HttpResponse::Ok().json(json!({ "status": "ok", "rows": rows })),
+        Ok(Err(QueryError::Throttled(retry_after))) => {
+            throttled_response("/insert_protobuf", retry_after)
+        }
+        Ok(Err(err)) => {
+            log::error!("Failed to ingest /insert_protobuf request: {}", err);
+            HttpResponse::BadRequest().json(err.to_string())
+        }
+        Err(err) => {
+            log::error!("Ingestion of /insert_protobuf request panicked: {}", err);
+            HttpResponse::InternalServerError().json(err.to_string())
+        }
+    }
+}
+
+/// Registers handlers that depend on optional features.
+#[cfg_attr(
+    not(any(feature = "arrow_ingest", feature = "protobuf")),
+    allow(unused_variables)
+)]
+fn optional_routes(config: &mut web::ServiceConfig) {
+    #[cfg(feature = "arrow_ingest")]
+    config.service(insert_arrow);
+    #[cfg(feature = "protobuf")]
+    config
+        .service(protobuf_descriptors)
+        .service(insert_protobuf);
+}
+
 async fn manual_hello() -> impl Responder {
     HttpResponse::Ok().body("Hey there!")
 }
 
+fn value_to_json(val: &Value) -> serde_json::Value {
+    match val {
+        Value::Int(int) => json!(int),
+        Value::Str(str) => json!(str),
+        Value::Null => json!(null),
+        Value::Float(float) => json!(float.0),
+    }
+}
+
 fn query_output_to_json_cols(result: QueryOutput) -> serde_json::Value {
     let mut cols: HashMap<String, serde_json::Value> = HashMap::default();
     for (colname, data) in result.columns {
@@ -331,19 +1057,25 @@ fn query_output_to_json_cols(result: QueryOutput) -> serde_json::Value {
     })
 }
 
+/// Starts the HTTP server on `addrs`, or the HTTPS server if `tls` is given. Maintenance endpoints under `/admin` are
+/// only enabled if `admin_token` is given.
 pub fn run(
     db: Arc<LocustDB>,
     cors_allow_all: bool,
     cors_allow_origin: Vec<String>,
     addrs: String,
+    tls: Option<TlsConfig>,
+    admin_token: Option<String>,
 ) -> std::io::Result<(ServerHandle, oneshot::Receiver<()>)> {
+    let paged_queries = Arc::new(PagedQueries::default());
+    let admin_token: Option<Arc<str>> = admin_token.map(Arc::from);
     let server = HttpServer::new(move || {
         let cors = if cors_allow_all {
             Cors::permissive()
         } else {
             let mut cors = Cors::default()
                 .allowed_methods(vec!["GET", "POST", "OPTIONS"])
-                .allowed_headers(vec!["Authorization", "Accept"])
+                .allowed_headers(vec!["Authorization", "Accept", USER_HEADER])
                 .allowed_header(actix_web::http::header::CONTENT_TYPE)
                 .max_age(3600);
             for origin in &cors_allow_origin {
@@ -351,26 +1083,60 @@ pub fn run(
             }
             cors
         };
-        let app_state = AppState { db: db.clone() };
+        let app_state = AppState {
+            db: db.clone(),
+            paged_queries: paged_queries.clone(),
+            admin_token: admin_token.clone(),
+        };
         App::new()
+            // Compresses responses with gzip, zstd or brotli as negotiated by the `Accept-Encoding` request header
+            .wrap(middleware::Compress::default())
             .wrap(cors)
             .app_data(Data::new(app_state))
             .app_data(Data::new(web::PayloadConfig::new(512 * 1024 * 1024)))
             .service(index)
             .service(echo)
             .service(tables)
+            .service(queries)
+            .service(prometheus_metrics)
+            .service(healthz)
+            .service(readyz)
+            .service(query_progress)
+            .service(kill_query)
+            .service(admin_flush)
+            .service(admin_compact)
+            .service(admin_evict_cache)
             .service(query)
+            .service(query_stream)
             .service(table_handler)
-            // .service(insert)
+            .service(insert)
             .service(insert_bin)
+            .service(insert_ndjson)
             .service(query_data)
             .service(query_cols)
+            .service(query_bin)
             .service(multi_query_cols)
             .service(columns)
+            .service(grafana_test)
+            .service(grafana_search)
+            .service(grafana_query)
+            .service(grafana_annotations)
             .service(plot)
+            .configure(optional_routes)
             .route("/hey", web::get().to(manual_hello))
-    })
-    .bind(&addrs)?
+    });
+    let server = match tls {
+        #[cfg(feature = "tls")]
+        Some(tls) => server.bind_rustls_021(&addrs, tls.server_config()?)?,
+        #[cfg(not(feature = "tls"))]
+        Some(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Serving HTTPS requires the `tls` feature",
+            ))
+        }
+        None => server.bind(&addrs)?,
+    }
     .run();
 
     let (tx, rx) = oneshot::channel();
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::{mpsc, oneshot};
+use futures::{SinkExt, StreamExt};
+
+use crate::{LocustDB, QueryError, Value};
+
+/// Paginated queries whose next page is not requested within this time are dropped, which kills the query.
+const PAGE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Query of a paginated `/query` request that is kept running between requests for its pages. Rows are pulled from the
+/// query as pages are requested, so at most one batch of rows beyond the current page is buffered.
+pub struct PagedQuery {
+    query: String,
+    colnames: Vec<String>,
+    max_rows: usize,
+    /// Rows that have been received but not returned yet
+    rows: Vec<Vec<Value>>,
+    batches: mpsc::Receiver<Result<Vec<Vec<Value>>, QueryError>>,
+    finished: bool,
+    last_access: Instant,
+}
+
+impl PagedQuery {
+    /// Starts running `query` on a task that sends its rows whenever the previous batch has been received.
+    /// Queries without `ORDER BY` and aggregations are streamed, all others are run to completion before the first
+    /// page is returned.
+    pub async fn start(
+        db: Arc<LocustDB>,
+        query: String,
+        max_rows: usize,
+    ) -> Result<PagedQuery, QueryError> {
+        let (started, start) = oneshot::channel();
+        let (mut sender, batches) = mpsc::channel(1);
+        let query_text = query.clone();
+        // The cursor borrows the database, so it is driven by a task that owns a reference to it
+        actix_web::rt::spawn(async move {
+            match db.query_cursor(&query_text).await {
+                Ok(mut cursor) => {
+                    let _ = started.send(Ok(cursor.colnames().to_vec()));
+                    while let Some(rows) = cursor.next().await {
+                        // Fails once the paged query is dropped, which drops and kills the query
+                        if sender.send(rows).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(QueryError::NotImplemented(_)) => {
+                    match db.run_query(&query_text, false, true, vec![]).await {
+                        Ok(Ok(output)) => {
+                            let _ = started.send(Ok(output.colnames));
+                            let _ = sender.send(Ok(output.rows.unwrap_or_default())).await;
+                        }
+                        Ok(Err(err)) => {
+                            let _ = started.send(Err(err));
+                        }
+                        Err(_) => {
+                            let _ = started.send(Err(fatal!("Query was canceled")));
+                        }
+                    }
+                }
+                Err(err) => {
+                    let _ = started.send(Err(err));
+                }
+            }
+        });
+        let colnames = start.await.map_err(|_| fatal!("Query was canceled"))??;
+        Ok(PagedQuery {
+            query,
+            colnames,
+            max_rows,
+            rows: Vec::new(),
+            batches,
+            finished: false,
+            last_access: Instant::now(),
+        })
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn colnames(&self) -> &[String] {
+        &self.colnames
+    }
+
+    pub fn set_max_rows(&mut self, max_rows: usize) {
+        self.max_rows = max_rows;
+    }
+
+    /// Returns the next page of at most `max_rows` rows and whether there are more rows after it.
+    pub async fn next_page(&mut self) -> Result<(Vec<Vec<Value>>, bool), QueryError> {
+        // Receives one row more than fits onto the page to determine whether there is another page
+        while !self.finished && self.rows.len() <= self.max_rows {
+            match self.batches.next().await {
+                Some(rows) => self.rows.extend(rows?),
+                None => self.finished = true,
+            }
+        }
+        let rest = self.rows.split_off(self.rows.len().min(self.max_rows));
+        let page = mem::replace(&mut self.rows, rest);
+        self.last_access = Instant::now();
+        Ok((page, !self.rows.is_empty() || !self.finished))
+    }
+}
+
+/// Paginated queries by the cursor returned with their last page. Expired queries are dropped whenever a page is
+/// requested.
+#[derive(Default)]
+pub struct PagedQueries {
+    queries: Mutex<HashMap<String, PagedQuery>>,
+}
+
+impl PagedQueries {
+    /// Removes and returns the query of `cursor` while its next page is produced. Also drops all expired queries.
+    pub fn take(&self, cursor: &str) -> Option<PagedQuery> {
+        let mut queries = self.queries.lock().unwrap();
+        drop_expired(&mut queries);
+        queries.remove(cursor)
+    }
+
+    /// Keeps `query` running until its next page is requested and returns the cursor for the next page. Also drops
+    /// all expired queries, so that queries whose cursors are never used are dropped even if no cursor is taken.
+    pub fn insert(&self, query: PagedQuery) -> String {
+        let cursor = format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        );
+        let mut queries = self.queries.lock().unwrap();
+        drop_expired(&mut queries);
+        queries.insert(cursor.clone(), query);
+        cursor
+    }
+}
+
+fn drop_expired(queries: &mut HashMap<String, PagedQuery>) {
+    queries.retain(|_, query| query.last_access.elapsed() < PAGE_TIMEOUT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn paged_query(rows: i64, max_rows: usize) -> PagedQuery {
+        let (mut sender, batches) = mpsc::channel(rows as usize);
+        for i in 0..rows {
+            sender.try_send(Ok(vec![vec![Value::Int(i)]])).unwrap();
+        }
+        PagedQuery {
+            query: "SELECT id FROM events;".to_string(),
+            colnames: vec!["id".to_string()],
+            max_rows,
+            rows: Vec::new(),
+            batches,
+            finished: false,
+            last_access: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_continuation() {
+        let paged_queries = PagedQueries::default();
+        let mut query = paged_query(5, 2);
+        let mut ids = vec![];
+        loop {
+            let (rows, more) = block_on(query.next_page()).unwrap();
+            assert!(rows.len() <= 2);
+            ids.extend(rows);
+            if !more {
+                break;
+            }
+            let cursor = paged_queries.insert(query);
+            query = paged_queries.take(&cursor).unwrap();
+            // Cursors can only be used once
+            assert!(paged_queries.take(&cursor).is_none());
+        }
+        assert_eq!(ids, (0..5).map(|i| vec![Value::Int(i)]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let paged_queries = PagedQueries::default();
+        let mut expired = paged_query(5, 2);
+        expired.last_access -= PAGE_TIMEOUT;
+        let expired_cursor = paged_queries.insert(expired);
+        // Inserting another query drops the expired query even though its cursor is never used
+        let cursor = paged_queries.insert(paged_query(5, 2));
+        assert_eq!(paged_queries.queries.lock().unwrap().len(), 1);
+        assert!(paged_queries.take(&expired_cursor).is_none());
+        assert!(paged_queries.take(&cursor).is_some());
+    }
+
+    #[test]
+    fn test_unknown_cursor() {
+        let paged_queries = PagedQueries::default();
+        paged_queries.insert(paged_query(5, 2));
+        assert!(paged_queries.take(&"0".repeat(32)).is_none());
+        assert!(paged_queries.take("").is_none());
+        assert_eq!(paged_queries.queries.lock().unwrap().len(), 1);
+    }
+}
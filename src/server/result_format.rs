@@ -0,0 +1,216 @@
+use super::value_to_json;
+use crate::{QueryError, QueryOutput, Value};
+
+/// Serialization of the results returned by `/query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultFormat {
+    /// Object with column names, rows and query statistics.
+    Json,
+    /// Header row with column names followed by one row per result row.
+    Csv,
+    /// One JSON object per row that maps column names to values.
+    Ndjson,
+    /// Arrow IPC stream with a single record batch.
+    ArrowIpc,
+}
+
+impl ResultFormat {
+    /// Parses the `format` query parameter.
+    pub fn from_param(format: &str) -> Option<ResultFormat> {
+        match format {
+            "json" => Some(ResultFormat::Json),
+            "csv" => Some(ResultFormat::Csv),
+            "ndjson" => Some(ResultFormat::Ndjson),
+            "arrow" => Some(ResultFormat::ArrowIpc),
+            _ => None,
+        }
+    }
+
+    /// Picks the first supported media type listed in an `Accept` header, or JSON if there is none.
+    pub fn from_accept(accept: &str) -> ResultFormat {
+        accept
+            .split(',')
+            .filter_map(|media_range| media_range.split(';').next())
+            .find_map(|media_type| match media_type.trim() {
+                "application/json" => Some(ResultFormat::Json),
+                "text/csv" => Some(ResultFormat::Csv),
+                "application/x-ndjson" => Some(ResultFormat::Ndjson),
+                "application/vnd.apache.arrow.stream" => Some(ResultFormat::ArrowIpc),
+                _ => None,
+            })
+            .unwrap_or(ResultFormat::Json)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ResultFormat::Json => "application/json",
+            ResultFormat::Csv => "text/csv",
+            ResultFormat::Ndjson => "application/x-ndjson",
+            ResultFormat::ArrowIpc => "application/vnd.apache.arrow.stream",
+        }
+    }
+
+    /// Whether results are serialized from `QueryOutput::columns` rather than `QueryOutput::rows`.
+    pub fn is_columnar(self) -> bool {
+        self == ResultFormat::ArrowIpc
+    }
+}
+
+pub fn to_csv(output: &QueryOutput) -> Result<Vec<u8>, QueryError> {
+    let csv_error = |e: csv::Error| fatal!("Failed to write CSV: {}", e);
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&output.colnames).map_err(csv_error)?;
+    for row in output.rows.iter().flatten() {
+        let fields = row.iter().map(|val| match val {
+            Value::Int(int) => int.to_string(),
+            Value::Float(float) => float.0.to_string(),
+            Value::Str(str) => str.clone(),
+            Value::Null => String::new(),
+        });
+        writer.write_record(fields).map_err(csv_error)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| fatal!("Failed to write CSV: {}", e))
+}
+
+pub fn to_ndjson(output: &QueryOutput) -> Vec<u8> {
+    let mut ndjson = Vec::new();
+    for row in output.rows.iter().flatten() {
+        let object = output
+            .colnames
+            .iter()
+            .zip(row)
+            .map(|(colname, val)| (colname.clone(), value_to_json(val)))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::to_writer(&mut ndjson, &object).unwrap();
+        ndjson.push(b'\n');
+    }
+    ndjson
+}
+
+#[cfg(feature = "arrow_export")]
+pub fn to_arrow_ipc(output: QueryOutput) -> Result<Vec<u8>, QueryError> {
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, Float64Array, Int64Array, NullArray, RecordBatch, StringArray};
+    use arrow_ipc::writer::StreamWriter;
+    use arrow_schema::{ArrowError, Field, Schema};
+
+    use crate::BasicTypeColumn;
+
+    let arrow_error = |e: ArrowError| fatal!("Failed to write Arrow IPC stream: {}", e);
+    let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = output
+        .columns
+        .into_iter()
+        .map(|(name, column)| {
+            let array: ArrayRef = match column {
+                BasicTypeColumn::Int(ints) => Arc::new(Int64Array::from(ints)),
+                BasicTypeColumn::Float(floats) => Arc::new(Float64Array::from(floats)),
+                BasicTypeColumn::String(strings) => Arc::new(StringArray::from(strings)),
+                BasicTypeColumn::Null(len) => Arc::new(NullArray::new(len)),
+                // Mixed columns use the narrowest type that can represent all values, nulls are preserved
+                BasicTypeColumn::Mixed(vals) => {
+                    if vals
+                        .iter()
+                        .all(|val| matches!(val, Value::Int(_) | Value::Null))
+                    {
+                        Arc::new(
+                            vals.iter()
+                                .map(|val| match val {
+                                    Value::Int(int) => Some(*int),
+                                    _ => None,
+                                })
+                                .collect::<Int64Array>(),
+                        )
+                    } else if vals.iter().all(|val| !matches!(val, Value::Str(_))) {
+                        Arc::new(
+                            vals.iter()
+                                .map(|val| match val {
+                                    Value::Int(int) => Some(*int as f64),
+                                    Value::Float(float) => Some(float.0),
+                                    _ => None,
+                                })
+                                .collect::<Float64Array>(),
+                        )
+                    } else {
+                        Arc::new(
+                            vals.iter()
+                                .map(|val| match val {
+                                    Value::Int(int) => Some(int.to_string()),
+                                    Value::Float(float) => Some(float.0.to_string()),
+                                    Value::Str(str) => Some(str.clone()),
+                                    Value::Null => None,
+                                })
+                                .collect::<StringArray>(),
+                        )
+                    }
+                }
+            };
+            (Field::new(name, array.data_type().clone(), true), array)
+        })
+        .unzip();
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(arrow_error)?;
+    let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema()).map_err(arrow_error)?;
+    writer.write(&batch).map_err(arrow_error)?;
+    writer.into_inner().map_err(arrow_error)
+}
+
+#[cfg(not(feature = "arrow_export"))]
+pub fn to_arrow_ipc(_: QueryOutput) -> Result<Vec<u8>, QueryError> {
+    bail!(
+        QueryError::NotImplemented,
+        "Arrow IPC results require the `arrow_export` feature"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn output() -> QueryOutput {
+        QueryOutput {
+            colnames: vec!["name".to_string(), "value".to_string()],
+            rows: Some(vec![
+                vec![Value::Str("a,b".to_string()), Value::Int(1)],
+                vec![Value::Null, Value::Float(0.5.into())],
+            ]),
+            columns: vec![],
+            query_plans: HashMap::new(),
+            stats: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_from_accept() {
+        assert_eq!(ResultFormat::from_accept("*/*"), ResultFormat::Json);
+        assert_eq!(
+            ResultFormat::from_accept("text/html, text/csv;q=0.9"),
+            ResultFormat::Csv
+        );
+        assert_eq!(
+            ResultFormat::from_accept("application/x-ndjson"),
+            ResultFormat::Ndjson
+        );
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let csv = to_csv(&output()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "name,value\n\"a,b\",1\n,0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_to_ndjson() {
+        let ndjson = to_ndjson(&output());
+        assert_eq!(
+            String::from_utf8(ndjson).unwrap(),
+            "{\"name\":\"a,b\",\"value\":1}\n{\"name\":null,\"value\":0.5}\n"
+        );
+    }
+}
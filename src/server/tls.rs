@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+/// Certificate chain and private key used to serve HTTPS, both in PEM format.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> TlsConfig {
+        TlsConfig {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+        }
+    }
+
+    /// Reads the certificate chain and the first RSA, PKCS8 or EC private key from their files.
+    #[cfg(feature = "tls")]
+    pub fn server_config(&self) -> std::io::Result<rustls::ServerConfig> {
+        use rustls_pemfile::Item;
+        use std::fs::File;
+        use std::io::{self, BufReader};
+
+        let invalid_data = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&self.cert_path)?))?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect::<Vec<_>>();
+        if certs.is_empty() {
+            return Err(invalid_data(format!(
+                "No certificates found in {}",
+                self.cert_path.display()
+            )));
+        }
+        let mut keys = BufReader::new(File::open(&self.key_path)?);
+        let key = loop {
+            match rustls_pemfile::read_one(&mut keys)? {
+                Some(Item::RSAKey(key)) | Some(Item::PKCS8Key(key)) | Some(Item::ECKey(key)) => {
+                    break rustls::PrivateKey(key)
+                }
+                Some(_) => {}
+                None => {
+                    return Err(invalid_data(format!(
+                        "No private key found in {}",
+                        self.key_path.display()
+                    )))
+                }
+            }
+        };
+        rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid_data(format!("Invalid certificate or private key: {}", e)))
+    }
+}
@@ -1,29 +1,52 @@
 use std::str;
 
-#[derive(Default)]
+/// Number of low bits of an index entry that hold the length of the string.
+const LEN_BITS: u32 = 24;
+/// Length that marks an entry whose offset and length are stored in the overflow table at the end of the backing store.
+const OVERFLOW_LEN: u64 = (1 << LEN_BITS) - 1;
+/// Largest offset that fits into the high bits of an index entry.
+const MAX_INLINE_OFFSET: u64 = (1 << (64 - LEN_BITS)) - 1;
+
+/// Strings in a single backing store that can be looked up by index. Each string is described by a `u64` entry that
+/// holds its offset in the high 40 bits and its length in the low 24 bits. Strings that are too long or start too far
+/// into the backing store for this encoding have the length `OVERFLOW_LEN` instead and the high bits hold an index
+/// into an overflow table of full `u64` offsets and lengths, which `into_parts` appends to the backing store.
+#[derive(Default, Clone)]
 pub struct IndexedPackedStrings {
     data: Vec<u64>,
     backing_store: Vec<u8>,
+    overflow: Vec<(u64, u64)>,
 }
 
 impl IndexedPackedStrings {
     pub fn push(&mut self, elem: &str) {
         let bytes = elem.as_bytes();
-        // TODO(34): overflow
-        self.data
-            .push(((self.backing_store.len() << 24) + bytes.len()) as u64);
+        let offset = self.backing_store.len() as u64;
+        let len = bytes.len() as u64;
+        if len < OVERFLOW_LEN && offset <= MAX_INLINE_OFFSET {
+            self.data.push((offset << LEN_BITS) | len);
+        } else {
+            self.data
+                .push(((self.overflow.len() as u64) << LEN_BITS) | OVERFLOW_LEN);
+            self.overflow.push((offset, len));
+        }
         self.backing_store.extend_from_slice(bytes);
     }
 
     pub fn clear(&mut self) {
         self.data.clear();
         self.backing_store.clear();
+        self.overflow.clear();
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &str> + Clone {
         self.data.iter().map(move |&offset_len| {
-            let offset = (offset_len >> 24) as usize;
-            let len = (offset_len & 0x00ff_ffff) as usize;
+            let (offset, len) = if offset_len & OVERFLOW_LEN == OVERFLOW_LEN {
+                self.overflow[(offset_len >> LEN_BITS) as usize]
+            } else {
+                (offset_len >> LEN_BITS, offset_len & OVERFLOW_LEN)
+            };
+            let (offset, len) = (offset as usize, len as usize);
             unsafe { str::from_utf8_unchecked(&self.backing_store[offset..(offset + len)]) }
         })
     }
@@ -32,11 +55,39 @@ impl IndexedPackedStrings {
         self.data.len()
     }
 
+    /// Returns the index entries and the backing store, followed by the overflow table if any entries overflowed.
+    /// Strings are retrieved from the parts with `lookup_indexed`.
     pub fn into_parts(self) -> (Vec<u64>, Vec<u8>) {
-        (self.data, self.backing_store)
+        let mut backing_store = self.backing_store;
+        if !self.overflow.is_empty() {
+            for (offset, len) in &self.overflow {
+                backing_store.extend_from_slice(&offset.to_le_bytes());
+                backing_store.extend_from_slice(&len.to_le_bytes());
+            }
+            backing_store.extend_from_slice(&(self.overflow.len() as u64).to_le_bytes());
+        }
+        (self.data, backing_store)
     }
 }
 
+/// Returns the bytes of the string with index entry `offset_len` in the parts returned by
+/// `IndexedPackedStrings::into_parts`.
+#[inline]
+pub fn lookup_indexed(offset_len: u64, backing_store: &[u8]) -> &[u8] {
+    let (offset, len) = if offset_len & OVERFLOW_LEN == OVERFLOW_LEN {
+        let read_u64 =
+            |pos: usize| u64::from_le_bytes(backing_store[pos..pos + 8].try_into().unwrap());
+        let overflow_count = read_u64(backing_store.len() - 8) as usize;
+        let entry =
+            backing_store.len() - 8 - 16 * (overflow_count - (offset_len >> LEN_BITS) as usize);
+        (read_u64(entry), read_u64(entry + 8))
+    } else {
+        (offset_len >> LEN_BITS, offset_len & OVERFLOW_LEN)
+    };
+    let (offset, len) = (offset as usize, len as usize);
+    &backing_store[offset..(offset + len)]
+}
+
 pub struct PackedStrings {
     data: Vec<u8>,
 }
@@ -176,3 +227,37 @@ impl<'a> Iterator for PackedBytesIterator<'a> {
         Some(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_packed_strings() {
+        let giant = "x".repeat(OVERFLOW_LEN as usize + 10);
+        let mut strings = IndexedPackedStrings::default();
+        for s in ["a", &giant, "", "bcd", &giant[1..], "e"] {
+            strings.push(s);
+        }
+        let expected = vec!["a", &giant, "", "bcd", &giant[1..], "e"];
+        assert_eq!(strings.iter().collect::<Vec<_>>(), expected);
+
+        let (offset_len, backing_store) = strings.into_parts();
+        let unpacked = offset_len
+            .iter()
+            .map(|&offset_len| str::from_utf8(lookup_indexed(offset_len, &backing_store)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(unpacked, expected);
+    }
+
+    #[test]
+    fn test_indexed_packed_strings_without_overflow() {
+        let mut strings = IndexedPackedStrings::default();
+        strings.push("ab");
+        strings.push("cde");
+        let (offset_len, backing_store) = strings.into_parts();
+        // Same encoding as before overflowing entries were supported
+        assert_eq!(offset_len, vec![2, (2 << 24) + 3]);
+        assert_eq!(backing_store, b"abcde");
+    }
+}
@@ -0,0 +1,34 @@
+use crate::engine::Query;
+use crate::mem_store::{MaterializedView, SchemaEnforcement, TableSchema};
+use crate::syntax::expression::Expr;
+
+/// Parsed SQL statement, either a query or a command that modifies the database.
+#[derive(Debug)]
+pub enum Command {
+    Query(Query),
+    CreateTable {
+        name: String,
+        schema: TableSchema,
+        schema_enforcement: SchemaEnforcement,
+        if_not_exists: bool,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    RenameTable {
+        old: String,
+        new: String,
+    },
+    CreateMaterializedView(MaterializedView),
+    DropMaterializedView {
+        name: String,
+        if_exists: bool,
+    },
+    /// Sets columns to the value of the corresponding expression for all rows matching `filter`.
+    Update {
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        filter: Expr,
+    },
+}
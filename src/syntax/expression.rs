@@ -1,15 +1,45 @@
 use self::Expr::*;
 use crate::engine::*;
 use crate::ingest::raw_val::RawVal;
+use ordered_float::OrderedFloat;
 use std::collections::HashSet;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
     ColName(String),
     Const(RawVal),
     Func1(Func1Type, Box<Expr>),
     Func2(Func2Type, Box<Expr>, Box<Expr>),
     Aggregate(Aggregator, Box<Expr>),
+    /// Exact quantile `q` (between 0 and 1) of the expression, e.g. `median(x)` is `Quantile(0.5, x)`.
+    Quantile(OrderedFloat<f64>, Box<Expr>),
+    /// Aggregate computed over a window frame, e.g. `avg(x) OVER (ORDER BY ts ROWS BETWEEN 59 PRECEDING AND CURRENT ROW)`.
+    Window(Box<WindowExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WindowExpr {
+    pub aggregator: WindowAggregator,
+    pub expr: Expr,
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<(Expr, bool)>,
+    pub frame: WindowFrame,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum WindowAggregator {
+    Sum,
+    Count,
+    Avg,
+    Min,
+    Max,
+}
+
+/// Bounds of a window frame as row offsets relative to the current row, `None` means unbounded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WindowFrame {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -33,7 +63,7 @@ pub enum Func2Type {
     NotLike,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Func1Type {
     Negate,
     ToYear,
@@ -55,10 +85,32 @@ impl Expr {
             }
             Func1(_, ref expr) => expr.add_colnames(result),
             Aggregate(_, ref expr) => expr.add_colnames(result),
+            Quantile(_, ref expr) => expr.add_colnames(result),
+            Window(ref window) => {
+                window.expr.add_colnames(result);
+                for expr in &window.partition_by {
+                    expr.add_colnames(result);
+                }
+                for (expr, _) in &window.order_by {
+                    expr.add_colnames(result);
+                }
+            }
             Const(_) => {}
         }
     }
 
+    /// Whether the expression contains an aggregate, quantile or window function.
+    pub fn contains_aggregate(&self) -> bool {
+        match *self {
+            ColName(_) | Const(_) => false,
+            Func1(_, ref expr) => expr.contains_aggregate(),
+            Func2(_, ref expr1, ref expr2) => {
+                expr1.contains_aggregate() || expr2.contains_aggregate()
+            }
+            Aggregate(_, _) | Quantile(_, _) | Window(_) => true,
+        }
+    }
+
     pub fn func(ftype: Func2Type, expr1: Expr, expr2: Expr) -> Expr {
         Func2(ftype, Box::new(expr1), Box::new(expr2))
     }
@@ -1,4 +1,4 @@
-#[derive(Clone, Debug, Hash, PartialEq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct LimitClause {
     pub limit: u64,
     pub offset: u64,
@@ -1,3 +1,4 @@
+pub mod command;
 pub mod expression;
 pub mod limit;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
@@ -3,18 +3,157 @@ extern crate sqlparser;
 use crate::engine::Query;
 use crate::engine::*;
 use crate::ingest::raw_val::RawVal;
+use crate::mem_store::{
+    ColumnSchema, ColumnType, MaterializedView, SchemaEnforcement, TableSchema,
+};
+use crate::syntax::command::Command;
 use crate::syntax::expression::Expr;
 use crate::syntax::expression::*;
 use crate::syntax::limit::*;
 use crate::QueryError;
+use ordered_float::OrderedFloat;
 use sqlparser::ast::{Expr as ASTNode, *};
 use sqlparser::dialect::GenericDialect;
 use sqlparser::parser::{Parser, ParserError};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // Convert sqlparser-rs `ASTNode` to LocustDB's `Query`
 pub fn parse_query(query: &str) -> Result<Query, QueryError> {
+    match parse_statement(query)? {
+        Statement::Query(query) => convert_query(query),
+        _ => Err(QueryError::ParseError(
+            "Only SELECT queries are supported.".to_string(),
+        )),
+    }
+}
+
+// Convert sqlparser-rs `Statement` to LocustDB's `Command`
+pub fn parse_command(command: &str) -> Result<Command, QueryError> {
+    match parse_statement(command)? {
+        Statement::Query(query) => Ok(Command::Query(convert_query(query)?)),
+        Statement::CreateTable {
+            name,
+            columns,
+            if_not_exists,
+            query: None,
+            like: None,
+            with_options,
+            ..
+        } => {
+            let columns = columns
+                .iter()
+                .map(get_column_schema)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Command::CreateTable {
+                name: strip_quotes(&name.to_string()),
+                schema: TableSchema::new(columns)?,
+                schema_enforcement: get_schema_enforcement(&with_options)?,
+                if_not_exists,
+            })
+        }
+        Statement::Drop {
+            object_type: ObjectType::Table,
+            if_exists,
+            names,
+            ..
+        } => {
+            if names.len() != 1 {
+                return Err(QueryError::NotImplemented(
+                    "Dropping multiple tables in one statement".to_string(),
+                ));
+            }
+            Ok(Command::DropTable {
+                name: strip_quotes(&names[0].to_string()),
+                if_exists,
+            })
+        }
+        Statement::CreateView {
+            materialized,
+            name,
+            columns,
+            query,
+            ..
+        } => {
+            if !materialized {
+                return Err(QueryError::NotImplemented(
+                    "Views that are not materialized".to_string(),
+                ));
+            }
+            if !columns.is_empty() {
+                return Err(QueryError::NotImplemented(
+                    "Column list in CREATE MATERIALIZED VIEW".to_string(),
+                ));
+            }
+            Ok(Command::CreateMaterializedView(MaterializedView::new(
+                &strip_quotes(&name.to_string()),
+                &query.to_string(),
+            )?))
+        }
+        Statement::Drop {
+            object_type: ObjectType::View,
+            if_exists,
+            names,
+            ..
+        } => {
+            if names.len() != 1 {
+                return Err(QueryError::NotImplemented(
+                    "Dropping multiple views in one statement".to_string(),
+                ));
+            }
+            Ok(Command::DropMaterializedView {
+                name: strip_quotes(&names[0].to_string()),
+                if_exists,
+            })
+        }
+        Statement::AlterTable {
+            name,
+            operation: AlterTableOperation::RenameTable { table_name },
+        } => Ok(Command::RenameTable {
+            old: strip_quotes(&name.to_string()),
+            new: strip_quotes(&table_name.to_string()),
+        }),
+        Statement::Update {
+            table,
+            assignments,
+            from: None,
+            selection,
+            returning: None,
+        } => {
+            if !table.joins.is_empty() {
+                return Err(QueryError::NotImplemented("Joins in UPDATE".to_string()));
+            }
+            let assignments = assignments
+                .iter()
+                .map(|assignment| {
+                    let column = assignment
+                        .id
+                        .iter()
+                        .map(|ident| ident.value.as_str())
+                        .collect::<Vec<_>>()
+                        .join(".");
+                    Ok((column, *convert_to_native_expr(&assignment.value)?))
+                })
+                .collect::<Result<Vec<_>, QueryError>>()?;
+            let filter = match selection {
+                Some(ref s) => *convert_to_native_expr(s)?,
+                None => Expr::Const(RawVal::Int(1)),
+            };
+            Ok(Command::Update {
+                table: get_table_name(Some(table.relation))?,
+                assignments,
+                filter,
+            })
+        }
+        _ => Err(QueryError::ParseError(
+            "Only SELECT queries, UPDATE, CREATE/DROP/ALTER TABLE and CREATE/DROP MATERIALIZED VIEW statements are supported."
+                .to_string(),
+        )),
+    }
+}
+
+fn parse_statement(statement: &str) -> Result<Statement, QueryError> {
     let dialect = GenericDialect {};
-    let mut ast = Parser::parse_sql(&dialect, query).map_err(|e| match e {
+    let mut ast = Parser::parse_sql(&dialect, statement).map_err(|e| match e {
         ParserError::ParserError(e_str) => QueryError::ParseError(e_str),
         _ => fatal!("{:?}", e),
     })?;
@@ -24,16 +163,11 @@ pub fn parse_query(query: &str) -> Result<Query, QueryError> {
             ast.len()
         )));
     }
+    ast.pop()
+        .ok_or_else(|| QueryError::ParseError("Expected a query statement".to_string()))
+}
 
-    let query = match ast.pop().unwrap() {
-        Statement::Query(query) => query,
-        _ => {
-            return Err(QueryError::ParseError(
-                "Only SELECT queries are supported.".to_string(),
-            ))
-        }
-    };
-
+fn convert_query(query: Box<sqlparser::ast::Query>) -> Result<Query, QueryError> {
     let (projection, relation, selection, order_by, limit, offset) = get_query_components(query)?;
     let projection = get_projection(projection)?;
     let table = get_table_name(relation)?;
@@ -56,6 +190,75 @@ pub fn parse_query(query: &str) -> Result<Query, QueryError> {
     })
 }
 
+fn get_column_schema(column: &ColumnDef) -> Result<ColumnSchema, QueryError> {
+    let data_type = column.data_type.to_string().to_uppercase();
+    // Strip length or precision, e.g. `VARCHAR(255)`
+    let column_type = match data_type.split('(').next().unwrap_or("").trim() {
+        "TINYINT" | "SMALLINT" | "INT" | "INTEGER" | "BIGINT" | "TIMESTAMP" => ColumnType::Integer,
+        "FLOAT" | "REAL" | "DOUBLE" | "DOUBLE PRECISION" => ColumnType::Float,
+        "CHAR" | "VARCHAR" | "TEXT" | "STRING" => ColumnType::String,
+        _ => {
+            return Err(QueryError::NotImplemented(format!(
+                "Column type {} of column {}",
+                column.data_type, column.name
+            )))
+        }
+    };
+    let mut schema = ColumnSchema::new(&strip_quotes(&column.name.to_string()), column_type);
+    for option in &column.options {
+        match option.option {
+            ColumnOption::NotNull => schema = schema.not_null(),
+            ColumnOption::Null => {}
+            _ => {
+                return Err(QueryError::NotImplemented(format!(
+                    "Column option {} of column {}",
+                    option.option, column.name
+                )))
+            }
+        }
+    }
+    Ok(schema)
+}
+
+/// Reads the `schema_enforcement` and `dead_letter_table` options of `CREATE TABLE ... WITH (...)`.
+fn get_schema_enforcement(options: &[SqlOption]) -> Result<SchemaEnforcement, QueryError> {
+    let mut mode = None;
+    let mut dead_letter_table = None;
+    for option in options {
+        let value = match &option.value {
+            Value::SingleQuotedString(value) => value.clone(),
+            value => {
+                return Err(QueryError::ParseError(format!(
+                    "Expected string value for table option {}, got {}",
+                    option.name, value
+                )))
+            }
+        };
+        match option.name.value.to_lowercase().as_str() {
+            "schema_enforcement" => mode = Some(value.to_lowercase()),
+            "dead_letter_table" => dead_letter_table = Some(value),
+            _ => {
+                return Err(QueryError::NotImplemented(format!(
+                    "Table option {}",
+                    option.name
+                )))
+            }
+        }
+    }
+    match (mode.as_deref(), dead_letter_table) {
+        (None | Some("permissive"), None) => Ok(SchemaEnforcement::Permissive),
+        (Some("reject"), None) => Ok(SchemaEnforcement::Reject),
+        (None | Some("dead_letter"), Some(table)) => Ok(SchemaEnforcement::DeadLetter(table)),
+        (Some("dead_letter"), None) => Err(QueryError::ParseError(
+            "schema_enforcement 'dead_letter' requires dead_letter_table".to_string(),
+        )),
+        (Some(mode), _) => Err(QueryError::ParseError(format!(
+            "Invalid schema_enforcement '{}', expected 'permissive', 'reject' or 'dead_letter'",
+            mode
+        ))),
+    }
+}
+
 // TODO: use struct
 #[allow(clippy::type_complexity)]
 fn get_query_components(
@@ -101,7 +304,9 @@ fn get_query_components(
                     "Selecting from multiple tables.".to_string(),
                 ))
             } else if !from.is_empty() && !from[0].joins.is_empty() {
-                Err(QueryError::NotImplemented("JOIN".to_string()))
+                Err(QueryError::NotImplemented(
+                    "JOIN (Hint: ASOF joins are available through `LocustDB::asof_join`)".to_string(),
+                ))
             } else {
                 Ok((
                     projection,
@@ -235,10 +440,12 @@ fn convert_to_native_expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
             expr: ref expression,
         } => Expr::Func1(map_unary_operator(op)?, convert_to_native_expr(expression)?),
         ASTNode::Value(ref literal) => Expr::Const(get_raw_val(literal)?),
+        ASTNode::Interval(interval) => interval_to_native_expr(interval)?,
         ASTNode::Identifier(ref identifier) => {
             Expr::ColName(strip_quotes(identifier.value.as_ref()))
         }
         ASTNode::Nested(inner) => *convert_to_native_expr(inner)?,
+        ASTNode::Function(f) if f.over.is_some() => Expr::Window(Box::new(get_window_expr(f)?)),
         ASTNode::Function(f) => match format!("{}", f.name).to_uppercase().as_ref() {
             "TO_YEAR" => {
                 if f.args.len() != 1 {
@@ -248,6 +455,17 @@ fn convert_to_native_expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
                 }
                 Expr::Func1(Func1Type::ToYear, convert_to_native_expr(function_arg_to_expr(&f.args[0])?)?)
             }
+            "NOW" => {
+                if !f.args.is_empty() {
+                    return Err(QueryError::ParseError(
+                        "Expected no arguments in NOW function".to_string(),
+                    ));
+                }
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| fatal!("{}", e))?;
+                Expr::Const(RawVal::Int(now.as_secs() as i64))
+            }
             "REGEX" => {
                 if f.args.len() != 2 {
                     return Err(QueryError::ParseError(
@@ -318,6 +536,36 @@ fn convert_to_native_expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
                 }
                 Expr::Aggregate(Aggregator::MinI64, func_arg_to_native_expr(&f.args[0])?)
             }
+            "MEDIAN" => {
+                if f.args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in MEDIAN function".to_string(),
+                    ));
+                }
+                Expr::Quantile(OrderedFloat(0.5), func_arg_to_native_expr(&f.args[0])?)
+            }
+            "QUANTILE" => {
+                if f.args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in QUANTILE function".to_string(),
+                    ));
+                }
+                let q = match function_arg_to_expr(&f.args[1])? {
+                    ASTNode::Value(Value::Number(q, _)) => q.parse::<f64>().ok(),
+                    _ => None,
+                };
+                match q {
+                    Some(q) if (0.0..=1.0).contains(&q) => {
+                        Expr::Quantile(OrderedFloat(q), func_arg_to_native_expr(&f.args[0])?)
+                    }
+                    _ => {
+                        return Err(QueryError::ParseError(format!(
+                            "Expected constant between 0 and 1 as second argument to QUANTILE function, got {}",
+                            f.args[1]
+                        )))
+                    }
+                }
+            }
             _ => return Err(QueryError::NotImplemented(format!("Function {:?}", f.name))),
         },
         ASTNode::IsNull(ref node) => Expr::Func1(Func1Type::IsNull, convert_to_native_expr(node)?),
@@ -338,6 +586,92 @@ fn convert_to_native_expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
     }))
 }
 
+fn get_window_expr(f: &Function) -> Result<WindowExpr, QueryError> {
+    let aggregator = match format!("{}", f.name).to_uppercase().as_ref() {
+        "SUM" => WindowAggregator::Sum,
+        "COUNT" => WindowAggregator::Count,
+        "AVG" => WindowAggregator::Avg,
+        "MIN" => WindowAggregator::Min,
+        "MAX" => WindowAggregator::Max,
+        _ => {
+            return Err(QueryError::NotImplemented(format!(
+                "Window function {}",
+                f.name
+            )))
+        }
+    };
+    if f.args.len() != 1 {
+        return Err(QueryError::ParseError(format!(
+            "Expected one argument in {} function",
+            f.name
+        )));
+    }
+    let spec = match &f.over {
+        Some(WindowType::WindowSpec(spec)) => spec,
+        Some(WindowType::NamedWindow(name)) => {
+            return Err(QueryError::NotImplemented(format!("Named window {}", name)))
+        }
+        None => return Err(fatal!("Expected window specification for {}", f.name)),
+    };
+    let partition_by = spec
+        .partition_by
+        .iter()
+        .map(|expr| convert_to_native_expr(expr).map(|expr| *expr))
+        .collect::<Result<Vec<_>, _>>()?;
+    let order_by = get_order_by(Some(spec.order_by.clone()))?;
+    let frame = match &spec.window_frame {
+        // Without explicit frame the window extends to the current row if the window is ordered, and covers the entire partition otherwise.
+        None if order_by.is_empty() => WindowFrame {
+            start: None,
+            end: None,
+        },
+        None => WindowFrame {
+            start: None,
+            end: Some(0),
+        },
+        Some(frame) => {
+            if !matches!(frame.units, WindowFrameUnits::Rows) {
+                return Err(QueryError::NotImplemented(format!(
+                    "{} window frames (only ROWS is supported)",
+                    frame.units
+                )));
+            }
+            WindowFrame {
+                start: get_window_frame_bound(&frame.start_bound)?,
+                end: match &frame.end_bound {
+                    Some(bound) => get_window_frame_bound(bound)?,
+                    None => Some(0),
+                },
+            }
+        }
+    };
+    Ok(WindowExpr {
+        aggregator,
+        expr: *func_arg_to_native_expr(&f.args[0])?,
+        partition_by,
+        order_by,
+        frame,
+    })
+}
+
+fn get_window_frame_bound(bound: &WindowFrameBound) -> Result<Option<i64>, QueryError> {
+    let offset = |expr: &ASTNode| match expr {
+        ASTNode::Value(Value::Number(rows, _)) => rows.parse::<i64>().map_err(|_| {
+            QueryError::ParseError(format!("Invalid number of rows in window frame: {}", rows))
+        }),
+        _ => Err(QueryError::NotImplemented(format!(
+            "Invalid expression in window frame: Expected constant integer, got {}",
+            expr
+        ))),
+    };
+    match bound {
+        WindowFrameBound::CurrentRow => Ok(Some(0)),
+        WindowFrameBound::Preceding(None) | WindowFrameBound::Following(None) => Ok(None),
+        WindowFrameBound::Preceding(Some(rows)) => Ok(Some(-offset(rows)?)),
+        WindowFrameBound::Following(Some(rows)) => Ok(Some(offset(rows)?)),
+    }
+}
+
 fn func_arg_to_native_expr(node: &FunctionArg) -> Result<Box<Expr>, QueryError> {
     convert_to_native_expr(function_arg_to_expr(node)?)
 }
@@ -383,6 +717,76 @@ fn map_binary_operator(o: &BinaryOperator) -> Result<Func2Type, QueryError> {
     })
 }
 
+// The parser greedily includes any arithmetic following the interval literal in the interval value, e.g.
+// `interval '1 day' - interval '1 hour'` is parsed as an interval with value `'1 day' - interval '1 hour'`.
+fn interval_to_native_expr(interval: &Interval) -> Result<Expr, QueryError> {
+    match interval.value.as_ref() {
+        ASTNode::BinaryOp { left, op, right } if interval.leading_field.is_none() => {
+            let lhs = interval_to_native_expr(&Interval {
+                value: left.clone(),
+                ..interval.clone()
+            })?;
+            Ok(Expr::Func2(
+                map_binary_operator(op)?,
+                Box::new(lhs),
+                convert_to_native_expr(right)?,
+            ))
+        }
+        _ => Ok(Expr::Const(RawVal::Int(get_interval_seconds(interval)?))),
+    }
+}
+
+// Converts interval literals such as `interval '1 hour'` or `interval '7' day` into seconds, the unit of timestamps.
+fn get_interval_seconds(interval: &Interval) -> Result<i64, QueryError> {
+    if interval.last_field.is_some() {
+        return Err(QueryError::NotImplemented(format!("Interval {}", interval)));
+    }
+    let value = match interval.value.as_ref() {
+        ASTNode::Value(Value::SingleQuotedString(value)) => value,
+        ASTNode::Value(Value::Number(value, _)) => value,
+        _ => {
+            return Err(QueryError::NotImplemented(format!(
+                "Invalid expression in interval: Expected constant string, got {}",
+                interval.value
+            )))
+        }
+    };
+    let invalid = || QueryError::ParseError(format!("Invalid interval: {}", interval));
+    let mut tokens = value.split_whitespace();
+    let mut seconds = 0i64;
+    let mut empty = true;
+    while let Some(amount) = tokens.next() {
+        let amount = amount.parse::<i64>().map_err(|_| invalid())?;
+        let unit = match (interval.leading_field, tokens.next()) {
+            (Some(field), None) => field.to_string(),
+            (None, Some(unit)) => unit.to_string(),
+            _ => return Err(invalid()),
+        };
+        let unit_seconds = match unit.to_lowercase().trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            _ => {
+                return Err(QueryError::NotImplemented(format!(
+                    "Interval unit {} (supported units are second, minute, hour, day and week)",
+                    unit
+                )))
+            }
+        };
+        seconds = amount
+            .checked_mul(unit_seconds)
+            .and_then(|s| s.checked_add(seconds))
+            .ok_or(QueryError::Overflow)?;
+        empty = false;
+    }
+    if empty {
+        return Err(invalid());
+    }
+    Ok(seconds)
+}
+
 // Fn to map sqlparser-rs `Value` to LocustDB's `RawVal`.
 fn get_raw_val(constant: &Value) -> Result<RawVal, QueryError> {
     match constant {
@@ -417,4 +821,11 @@ mod tests {
             format!("{:?}", parse_query("select to_year(ts) from default limit 100")),
             "Ok(Query { select: [ColumnInfo { expr: Func1(ToYear, ColName(\"ts\")), name: \"to_year(ts)\" }], table: \"default\", filter: Const(Int(1)), order_by: [], limit: LimitClause { limit: 100, offset: 0 } })");
     }
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(
+            format!("{:?}", parse_query("select ts + interval '1 hour' from default where ts > 86400 - interval '1 day 30 minutes'")),
+            "Ok(Query { select: [ColumnInfo { expr: Func2(Add, ColName(\"ts\"), Const(Int(3600))), name: \"ts + INTERVAL '1 hour'\" }], table: \"default\", filter: Func2(GT, ColName(\"ts\"), Func2(Subtract, Const(Int(86400)), Const(Int(88200)))), order_by: [], limit: LimitClause { limit: 18446744073709551615, offset: 0 } })");
+    }
 }
@@ -85,7 +85,7 @@ async fn test_ingestion() {
 
         if i % 7 == 0 {
             let start_time = Instant::now();
-            db.force_flush();
+            db.force_flush().unwrap();
             log::info!("Forced flush in {:?}", start_time.elapsed());
         }
     }
@@ -163,7 +163,180 @@ fn create_locustdb(db_path: PathBuf) -> (Arc<locustdb::LocustDB>, ServerHandle)
     };
     let db = Arc::new(locustdb::LocustDB::new(&options));
     let _locustdb = db.clone();
-    let (handle, _) =
-        locustdb::server::run(_locustdb, false, vec![], "localhost:8888".to_string()).unwrap();
+    let (handle, _) = locustdb::server::run(
+        _locustdb,
+        false,
+        vec![],
+        "localhost:8888".to_string(),
+        None,
+        None,
+    )
+    .unwrap();
     (db, handle)
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_query_pagination() {
+    let db = Arc::new(LocustDB::new(&locustdb::Options {
+        max_partition_length: 16,
+        ..locustdb::Options::default()
+    }));
+    let rows = (0..100)
+        .map(|i| format!(r#"{{"id": {}}}"#, i) + "\n")
+        .collect::<String>();
+    db.ingest_ndjson("events", rows.as_bytes()).unwrap();
+    db.force_flush().unwrap();
+    let (handle, _) = locustdb::server::run(
+        db.clone(),
+        false,
+        vec![],
+        "localhost:8889".to_string(),
+        None,
+        None,
+    )
+    .unwrap();
+
+    let client = reqwest::Client::new();
+    for query in [
+        "SELECT id FROM events;",
+        "SELECT id FROM events ORDER BY id;",
+    ] {
+        let mut ids = vec![];
+        let mut cursor = serde_json::Value::Null;
+        loop {
+            let page: serde_json::Value = client
+                .post("http://localhost:8889/query")
+                .json(&serde_json::json!({ "query": query, "max_rows": 30, "cursor": cursor }))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            let rows = page["rows"].as_array().unwrap();
+            assert!(rows.len() <= 30);
+            ids.extend(rows.iter().map(|row| row[0].as_i64().unwrap()));
+            cursor = page["cursor"].clone();
+            if cursor.is_null() {
+                break;
+            }
+        }
+        ids.sort_unstable();
+        assert_eq!(ids, (0..100).collect::<Vec<_>>(), "{}", query);
+    }
+
+    let status = client
+        .post("http://localhost:8889/query")
+        .json(&serde_json::json!({ "query": "SELECT id FROM events;", "cursor": "unknown" }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    // Admin endpoints are disabled without an admin token
+    let status = client
+        .post("http://localhost:8889/admin/kill/0")
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    handle.stop(true).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_response_compression() {
+    let db = Arc::new(LocustDB::new(&locustdb::Options::default()));
+    let rows = (0..1000)
+        .map(|i| format!(r#"{{"id": {}}}"#, i) + "\n")
+        .collect::<String>();
+    db.ingest_ndjson("events", rows.as_bytes()).unwrap();
+    let (handle, _) = locustdb::server::run(
+        db.clone(),
+        false,
+        vec![],
+        "localhost:8890".to_string(),
+        None,
+        None,
+    )
+    .unwrap();
+
+    let client = reqwest::Client::new();
+    for (accept_encoding, content_encoding) in [
+        ("gzip", Some("gzip")),
+        ("zstd", Some("zstd")),
+        ("identity", None),
+    ] {
+        let response = client
+            .post("http://localhost:8890/query")
+            .header(reqwest::header::ACCEPT_ENCODING, accept_encoding)
+            .json(&serde_json::json!({ "query": "SELECT id FROM events;" }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(
+            response
+                .headers()
+                .get(reqwest::header::CONTENT_ENCODING)
+                .map(|encoding| encoding.to_str().unwrap()),
+            content_encoding
+        );
+    }
+    handle.stop(true).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_ingestion_backpressure() {
+    let db_path = tempdir().unwrap();
+    let db = Arc::new(LocustDB::new(&locustdb::Options {
+        db_path: Some(db_path.path().into()),
+        max_wal_size_bytes: 1,
+        wal_backpressure: locustdb::WalBackpressure::Reject(Duration::from_millis(1500)),
+        ..locustdb::Options::default()
+    }));
+    // Partitions can't be written, so the WAL is never flushed and stays above max_wal_size_bytes
+    let tables_path = db_path.path().join("tables");
+    let _ = std::fs::remove_dir_all(&tables_path);
+    std::fs::write(&tables_path, b"").unwrap();
+    db.ingest_ndjson("events", r#"{"id": 1}"#.as_bytes())
+        .unwrap();
+    let (handle, _) = locustdb::server::run(
+        db.clone(),
+        false,
+        vec![],
+        "localhost:8891".to_string(),
+        None,
+        None,
+    )
+    .unwrap();
+
+    let client = reqwest::Client::new();
+    for (endpoint, body) in [
+        ("insert_ndjson/events", r#"{"id": 2}"#),
+        ("insert/events", r#"{"id": 2}"#),
+        ("insert/events?format=csv", "id\n2\n"),
+    ] {
+        let response = client
+            .post(format!("http://localhost:8891/{}", endpoint))
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            "{}",
+            endpoint
+        );
+        assert_eq!(
+            response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .unwrap(),
+            "2"
+        );
+    }
+    handle.stop(true).await;
+}
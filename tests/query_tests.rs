@@ -48,6 +48,11 @@ fn test_query_ec(query: &str, expected_rows: &[Vec<Value>]) {
             max_partition_length: 3,
             ..Options::default()
         },
+        Options {
+            batch_size: 8,
+            operator_fusion: false,
+            ..Options::default()
+        },
     ];
 
     for mut opts in optss {
@@ -70,7 +75,7 @@ fn test_query_ec(query: &str, expected_rows: &[Vec<Value>]) {
         };
 
         let result1 = block_on(locustdb.run_query(query, false, true, show.clone())).unwrap();
-        locustdb.force_flush();
+        locustdb.force_flush().unwrap();
         let result2 = block_on(locustdb.run_query(query, false, true, show)).unwrap();
 
         assert_eq!(
@@ -1153,7 +1158,7 @@ fn test_long_nullable() {
     let result = block_on(locustdb.run_query(query, true, true, vec![])).unwrap();
     assert_eq!(result.unwrap().rows.unwrap(), expected_rows);
 
-    locustdb.force_flush();
+    locustdb.force_flush().unwrap();
     let query = "SELECT nullable_int FROM test WHERE nullable_int IS NOT NULL;";
     let result = block_on(locustdb.run_query(query, true, true, vec![])).unwrap();
     assert_eq!(result.unwrap().rows.unwrap().len(), 26227);
@@ -1271,6 +1276,155 @@ fn test_group_by_float() {
     );
 }
 
+#[test]
+fn test_group_by_string_and_float() {
+    test_query_ec(
+        "SELECT enum, float, count(0) FROM default ORDER BY float ASC LIMIT 4;",
+        &[
+            vec![Str("aa"), Float(-124.0), Int(1)],
+            vec![Str("cc"), Float(-1.0), Int(1)],
+            vec![Str("cc"), Float(0.0), Int(1)],
+            vec![Str("aa"), Float(1e-6), Int(2)],
+        ],
+    );
+}
+
+#[test]
+fn test_median() {
+    test_query_ec(
+        "SELECT enum, median(non_dense_ints) FROM default;",
+        &[
+            vec![Str("aa"), Int(1)],
+            vec![Str("bb"), Int(3)],
+            vec![Str("cc"), Int(2)],
+        ],
+    );
+}
+
+#[test]
+fn test_quantile() {
+    test_query_ec(
+        "SELECT quantile(id, 0.5), quantile(id, 0), quantile(id, 1) FROM default;",
+        &[vec![Float(4.5), Int(0), Int(9)]],
+    );
+}
+
+#[test]
+fn test_quantile_multiple_partitions() {
+    test_query(
+        "SELECT quantile(ts, 0.25), median(ts) FROM default;",
+        &[vec![Float(1463472766.25), Float(1471371315.5)]],
+    );
+    test_query(
+        "SELECT tld, median(ts) FROM default WHERE tld <> '' LIMIT 3;",
+        &[
+            vec![Str("biz"), Float(1466515640.0)],
+            vec![Str("com"), Int(1465751136)],
+            vec![Str("edu"), Int(1476766422)],
+        ],
+    );
+}
+
+#[test]
+fn test_moving_average() {
+    test_query_ec(
+        "SELECT id, avg(id) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) FROM default LIMIT 3;",
+        &[
+            vec![Int(0), Float(0.0)],
+            vec![Int(1), Float(0.5)],
+            vec![Int(2), Float(1.5)],
+        ],
+    );
+}
+
+#[test]
+fn test_window_partition_by() {
+    test_query_ec(
+        "SELECT enum, id, sum(id) OVER (PARTITION BY enum ORDER BY id ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) FROM default LIMIT 4;",
+        &[
+            vec![Str("aa"), Int(0), Int(0)],
+            vec![Str("aa"), Int(1), Int(1)],
+            vec![Str("aa"), Int(2), Int(3)],
+            vec![Str("aa"), Int(5), Int(8)],
+        ],
+    );
+}
+
+#[test]
+fn test_window_frame_bounds() {
+    test_query_ec(
+        "SELECT id, count(id) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND 9223372036854775807 FOLLOWING) FROM default LIMIT 3;",
+        &[
+            vec![Int(0), Int(10)],
+            vec![Int(1), Int(9)],
+            vec![Int(2), Int(8)],
+        ],
+    );
+    test_query_ec(
+        "SELECT id, count(country) OVER (ORDER BY id ROWS BETWEEN 9223372036854775807 PRECEDING AND CURRENT ROW), sum(country) OVER (ORDER BY id ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) FROM default LIMIT 2;",
+        &[vec![Int(0), Int(1), Null], vec![Int(1), Int(2), Null]],
+    );
+}
+
+#[test]
+fn test_asof_join() {
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(
+        locustdb.load_csv(
+            LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+        ),
+    );
+    let join = AsofJoin::new(
+        "SELECT id, enum FROM default WHERE enum = 'bb' ORDER BY id;",
+        "SELECT id AS ts, enum FROM default WHERE enum <> 'bb';",
+        "id",
+        "ts",
+    )
+    .with_tolerance(2);
+    let result = block_on(locustdb.asof_join(&join)).unwrap().unwrap();
+    assert_eq!(result.colnames, vec!["id", "enum", "ts", "right.enum"]);
+    assert_eq!(
+        result.rows.unwrap(),
+        vec![
+            vec![Int(3), Str("bb"), Int(2), Str("aa")],
+            vec![Int(4), Str("bb"), Int(2), Str("aa")],
+            vec![Int(9), Str("bb"), Int(8), Str("cc")],
+        ]
+    );
+
+    let join = join.by("enum", "enum");
+    let result = block_on(locustdb.asof_join(&join)).unwrap().unwrap();
+    assert_eq!(
+        result.rows.unwrap(),
+        vec![
+            vec![Int(3), Str("bb"), Null, Null],
+            vec![Int(4), Str("bb"), Null, Null],
+            vec![Int(9), Str("bb"), Null, Null],
+        ]
+    );
+
+    let result = block_on(locustdb.run_query(
+        "SELECT * FROM default AS l JOIN default AS r ON l.id >= r.id;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert!(matches!(result, Err(QueryError::NotImplemented(_))));
+}
+
+#[test]
+fn test_interval_arithmetic() {
+    test_query_ec(
+        "SELECT id FROM default WHERE id * 60 >= interval '7 minutes' - interval '60 seconds' ORDER BY id;",
+        &[vec![Int(6)], vec![Int(7)], vec![Int(8)], vec![Int(9)]],
+    );
+    test_query_ec(
+        "SELECT count(0) FROM default WHERE id < now() - interval '7 days';",
+        &[vec![Int(10)]],
+    );
+}
+
 #[test]
 fn test_or_nullcheck_and_filter1() {
     test_query_ec(
@@ -1394,6 +1548,48 @@ fn test_restore_from_disk() {
     }
 }
 
+#[cfg(feature = "enable_zstd")]
+#[test]
+fn test_restore_zstd_partitions() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        partition_compression: PartitionCompression::Zstd(9),
+        ..Default::default()
+    };
+    let query = "SELECT * FROM default;";
+    let old_db_contents = {
+        let locustdb = LocustDB::new(&opts);
+        block_on(
+            locustdb.load_csv(
+                nyc_taxi_data::ingest_reduced_file("test_data/nyc-taxi.csv.gz", "default")
+                    .with_partition_size(999),
+            ),
+        )
+        .unwrap();
+        block_on(locustdb.run_query(query, true, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    // Compressed partitions remain readable after compression is disabled
+    let opts = Options {
+        partition_compression: PartitionCompression::None,
+        ..opts
+    };
+    let locustdb = LocustDB::new(&opts);
+    let restored_db_contents = block_on(locustdb.run_query(query, true, true, vec![]))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap();
+    assert_eq!(old_db_contents, restored_db_contents);
+}
+
 #[test]
 fn test_colnames() {
     test_query_colnames(
@@ -1445,4 +1641,3249 @@ fn test_top_n_of_null() {
         &[
         ],
     );
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_order_by_fewer_columns_than_selected() {
+    test_query(
+        "SELECT first_name, ts, last_name, guid FROM default ORDER BY first_name, ts LIMIT 5;",
+        &[
+            vec![Str("Adam"), Int(1460843728), Str("Crawford"), Str("a45a55f9-61f6-4ab0-ad49-25d87a5b8437")],
+            vec![Str("Adam"), Int(1473168790), Str("Cox"), Str("fe4ffbb0-7a3b-4d78-8f07-77d74ed5ea51")],
+            vec![Str("Amanda"), Int(1457964515), Str("Ferguson"), Str("c6c98f80-461c-4835-bd59-a8ba8faa6402")],
+            vec![Str("Amy"), Int(1457993744), Str("Adams"), Str("de105473-b3aa-45fd-8b77-3d775662d577")],
+            vec![Str("Amy"), Int(1471604704), Str("Black"), Str("a59ecaef-9682-465b-b2a2-0bc2caac38ba")],
+        ],
+    );
+}
+
+#[test]
+fn test_create_table() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let schema = TableSchema::new(vec![
+        ColumnSchema::new("ts", ColumnType::Integer).not_null(),
+        ColumnSchema::new("host", ColumnType::String),
+        ColumnSchema::new("value", ColumnType::Float),
+    ])
+    .unwrap();
+    {
+        let locustdb = LocustDB::new(&opts);
+        let query = "CREATE TABLE metrics (ts BIGINT NOT NULL, host VARCHAR(64), value DOUBLE);";
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(locustdb.table_schema("metrics"), Some(schema.clone()));
+
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert!(matches!(result, Err(QueryError::SchemaError(_))));
+        let query = "CREATE TABLE IF NOT EXISTS metrics (ts BIGINT);";
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(locustdb.table_schema("metrics"), Some(schema.clone()));
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(locustdb.table_schema("metrics"), Some(schema));
+}
+
+#[test]
+fn test_drop_table() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        let _ = block_on(
+            locustdb.load_csv(
+                LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+            ),
+        );
+        let query = "CREATE TABLE metrics (ts BIGINT NOT NULL);";
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap();
+        locustdb.force_flush().unwrap();
+
+        // Partition files that were already deleted don't prevent dropping the table
+        let table_dir = tmp_dir.path().join("tables").join("default");
+        let partition_file = std::fs::read_dir(&table_dir).unwrap().next().unwrap().unwrap();
+        std::fs::remove_file(partition_file.path()).unwrap();
+        locustdb.drop_table("default").unwrap();
+        let result = block_on(locustdb.run_query("SELECT * FROM default;", false, true, vec![]));
+        assert!(result.unwrap().is_err());
+        assert!(matches!(
+            locustdb.drop_table("default"),
+            Err(QueryError::SchemaError(_))
+        ));
+
+        let result = block_on(locustdb.run_query("DROP TABLE metrics;", false, true, vec![]));
+        assert!(result.unwrap().is_ok());
+        let result = block_on(locustdb.run_query("DROP TABLE IF EXISTS metrics;", false, true, vec![]));
+        assert!(result.unwrap().is_ok());
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(locustdb.table_schema("metrics"), None);
+    let result = block_on(locustdb.run_query("SELECT * FROM default;", false, true, vec![]));
+    assert!(result.unwrap().is_err());
+}
+
+#[test]
+fn test_rename_table() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let query = "SELECT enum, count(0) FROM renamed;";
+    let expected = vec![
+        vec![Str("aa"), Int(5)],
+        vec![Str("bb"), Int(3)],
+        vec![Str("cc"), Int(2)],
+    ];
+    {
+        let locustdb = LocustDB::new(&opts);
+        let _ = block_on(
+            locustdb.load_csv(
+                LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+            ),
+        );
+        let result = block_on(locustdb.run_query(
+            "ALTER TABLE default RENAME TO renamed;",
+            false,
+            true,
+            vec![],
+        ));
+        assert!(result.unwrap().is_ok());
+        let result = block_on(locustdb.run_query("SELECT * FROM default;", false, true, vec![]));
+        assert!(result.unwrap().is_err());
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), expected);
+        assert!(matches!(
+            locustdb.rename_table("default", "renamed"),
+            Err(QueryError::SchemaError(_))
+        ));
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), expected);
+}
+
+#[test]
+fn test_update() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let query = "SELECT enum, count(0) FROM default;";
+    let expected = vec![
+        vec![Str("aa"), Int(5)],
+        vec![Str("bb"), Int(3)],
+        vec![Str("redacted"), Int(2)],
+    ];
+    {
+        let locustdb = LocustDB::new(&opts);
+        let _ = block_on(
+            locustdb.load_csv(
+                LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+            ),
+        );
+        let result = block_on(locustdb.run_query(
+            "UPDATE default SET enum = 'redacted' WHERE id = 6 OR id = 8;",
+            false,
+            true,
+            vec![],
+        ));
+        assert_eq!(result.unwrap().unwrap().rows.unwrap(), vec![vec![Int(2)]]);
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), expected);
+        // Filters support all expressions of the query engine, and values are computed from the original rows
+        let result = block_on(locustdb.run_query(
+            "UPDATE default SET id = id + 100, enum = 'aa' WHERE enum LIKE 'redact%';",
+            false,
+            true,
+            vec![],
+        ));
+        assert_eq!(result.unwrap().unwrap().rows.unwrap(), vec![vec![Int(2)]]);
+        let result = block_on(locustdb.run_query(
+            "SELECT id, enum FROM default WHERE id > 100 ORDER BY id;",
+            false,
+            true,
+            vec![],
+        ));
+        assert_eq!(
+            result.unwrap().unwrap().rows.unwrap(),
+            vec![vec![Int(106), Str("aa")], vec![Int(108), Str("aa")]]
+        );
+        let result = block_on(locustdb.run_query(
+            "UPDATE default SET id = id - 100, enum = 'redacted' WHERE id > 100;",
+            false,
+            true,
+            vec![],
+        ));
+        assert_eq!(result.unwrap().unwrap().rows.unwrap(), vec![vec![Int(2)]]);
+        let result = block_on(locustdb.run_query(
+            "UPDATE default SET enum = count(0);",
+            false,
+            true,
+            vec![],
+        ));
+        assert!(result.unwrap().is_err());
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), expected);
+}
+
+#[test]
+fn test_materialized_view() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let query = "SELECT enum, sum(n) FROM enum_counts;";
+    let expected = vec![
+        vec![Str("aa"), Int(10)],
+        vec![Str("bb"), Int(6)],
+        vec![Str("cc"), Int(4)],
+    ];
+    {
+        let locustdb = LocustDB::new(&opts);
+        let load = || {
+            block_on(
+                locustdb.load_csv(
+                    LoadOptions::new("test_data/edge_cases.csv", "default")
+                        .allow_nulls_all_columns(),
+                ),
+            )
+        };
+        let _ = load();
+        let result = block_on(locustdb.run_query(
+            "CREATE MATERIALIZED VIEW enum_counts AS SELECT enum, count(0) AS n FROM default;",
+            false,
+            true,
+            vec![],
+        ));
+        assert!(result.unwrap().is_ok());
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert_eq!(
+            result.unwrap().rows.unwrap(),
+            vec![
+                vec![Str("aa"), Int(5)],
+                vec![Str("bb"), Int(3)],
+                vec![Str("cc"), Int(2)],
+            ]
+        );
+        let _ = load();
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), expected);
+        assert!(matches!(
+            locustdb.create_materialized_view("averages", "SELECT enum, avg(id) FROM default"),
+            Err(QueryError::NotImplemented(_))
+        ));
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), expected);
+}
+
+#[test]
+fn test_scheduled_query() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(
+        locustdb.load_csv(
+            LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+        ),
+    );
+    locustdb
+        .create_scheduled_query(
+            "enum_rollup",
+            "SELECT enum, count(0) AS n FROM default",
+            "enum_counts",
+            "id",
+            1,
+        )
+        .unwrap();
+    assert!(matches!(
+        locustdb.create_scheduled_query("enum_rollup", "SELECT id FROM default", "ids", "id", 1),
+        Err(QueryError::SchemaError(_))
+    ));
+
+    // Scheduled queries run on a background thread
+    let mut rows = None;
+    for _ in 0..100 {
+        let result = block_on(locustdb.run_query(
+            "SELECT enum, n FROM enum_counts;",
+            false,
+            true,
+            vec![],
+        ));
+        if let Ok(Ok(output)) = result {
+            rows = output.rows;
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert_eq!(
+        rows.unwrap(),
+        vec![
+            vec![Str("aa"), Int(5)],
+            vec![Str("bb"), Int(3)],
+            vec![Str("cc"), Int(2)],
+        ]
+    );
+    assert!(locustdb.scheduled_queries()[0].watermark > 0);
+    locustdb.drop_scheduled_query("enum_rollup").unwrap();
+    assert!(locustdb.scheduled_queries().is_empty());
+}
+
+#[cfg(feature = "parquet_export")]
+#[test]
+fn test_export_parquet() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(
+        locustdb.load_csv(
+            LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+        ),
+    );
+    let path = tmp_dir.path().join("export.parquet");
+    block_on(locustdb.export_parquet("SELECT id, enum, float, nullable_int FROM default", &path))
+        .unwrap();
+    let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let metadata = reader.metadata().file_metadata();
+    assert_eq!(metadata.num_rows(), 10);
+    let columns = metadata
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|c| c.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(columns, vec!["id", "enum", "float", "nullable_int"]);
+
+    let path = tmp_dir.path().join("table.parquet");
+    block_on(locustdb.export_table_parquet("default", &path)).unwrap();
+    let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 10);
+}
+
+#[cfg(feature = "arrow_ingest")]
+#[test]
+fn test_arrow_ipc_ingestion() {
+    use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+    use arrow_ipc::writer::{FileWriter, StreamWriter};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let batch = RecordBatch::try_from_iter(vec![
+        ("id", Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef),
+        (
+            "name",
+            Arc::new(StringArray::from(vec![Some("a"), None, Some("c")])) as ArrayRef,
+        ),
+        (
+            "value",
+            Arc::new(Float64Array::from(vec![0.5, 1.5, 2.5])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+
+    let path = tmp_dir.path().join("batch.arrow");
+    let mut writer = FileWriter::try_new(std::fs::File::create(&path).unwrap(), &batch.schema())
+        .unwrap();
+    writer.write(&batch).unwrap();
+    writer.finish().unwrap();
+    let mut stream = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new(&mut stream, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+    }
+
+    let locustdb = LocustDB::new(&Options::default());
+    locustdb.load_arrow_ipc("arrow", &path).unwrap();
+    locustdb
+        .ingest_arrow_ipc_stream("arrow", &stream[..])
+        .unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT id, name, value FROM arrow LIMIT 3;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![
+            vec![Int(1), Str("a"), Float(0.5)],
+            vec![Int(2), Null, Float(1.5)],
+            vec![Int(3), Str("c"), Float(2.5)],
+        ]
+    );
+    let result = block_on(locustdb.run_query("SELECT count(0) FROM arrow;", false, true, vec![]))
+        .unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(6)]]);
+}
+
+#[cfg(feature = "arrow_ingest")]
+#[test]
+fn test_ingest_arrow() {
+    use locustdb::arrow_array::{ArrayRef, Int32Array, RecordBatch, StringArray, UInt64Array};
+    use std::sync::Arc;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "small",
+            Arc::new(Int32Array::from(vec![Some(-1), None, Some(7)])) as ArrayRef,
+        ),
+        (
+            "large",
+            Arc::new(UInt64Array::from(vec![1u64 << 40, 2, 3])) as ArrayRef,
+        ),
+        (
+            "name",
+            Arc::new(StringArray::from(vec!["x", "y", "z"])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+    locustdb.ingest_arrow("embedded", batch).unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT small, large, name FROM embedded;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![
+            vec![Int(-1), Int(1 << 40), Str("x")],
+            vec![Null, Int(2), Str("y")],
+            vec![Int(7), Int(3), Str("z")],
+        ]
+    );
+
+    let overflow = RecordBatch::try_from_iter(vec![(
+        "large",
+        Arc::new(UInt64Array::from(vec![u64::MAX])) as ArrayRef,
+    )])
+    .unwrap();
+    assert!(matches!(
+        locustdb.ingest_arrow("embedded", overflow),
+        Err(QueryError::Overflow)
+    ));
+}
+
+#[test]
+fn test_ndjson_ingestion() {
+    use std::io::Write;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("events.ndjson");
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(
+        file,
+        r#"{{"user": {{"id": 1, "name": "a"}}, "latency": 0.5, "ok": true}}"#
+    )
+    .unwrap();
+    writeln!(file).unwrap();
+    writeln!(
+        file,
+        r#"{{"user": {{"id": 2}}, "latency": 3, "tags": ["x", "y"]}}"#
+    )
+    .unwrap();
+    drop(file);
+
+    let locustdb = LocustDB::new(&Options::default());
+    assert_eq!(locustdb.load_ndjson("events", &path).unwrap(), 2);
+    let result = block_on(locustdb.run_query(
+        r#"SELECT "user.id", "user.name", latency, ok, tags FROM events;"#,
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![
+            vec![Int(1), Str("a"), Float(0.5), Int(1), Null],
+            vec![Int(2), Null, Int(3), Null, Str(r#"["x","y"]"#)],
+        ]
+    );
+    assert!(matches!(
+        locustdb.ingest_ndjson("events", &b"[1, 2]\n"[..]),
+        Err(QueryError::ParseError(_))
+    ));
+
+    // An invalid line prevents ingestion of all rows
+    let rows = "{\"user\": {\"id\": 3}}\n{\"user\": \n";
+    assert!(matches!(
+        locustdb.ingest_ndjson_atomic("events", rows.as_bytes()),
+        Err(QueryError::ParseError(_))
+    ));
+    let result = block_on(locustdb.run_query("SELECT count(0) FROM events;", false, true, vec![]));
+    assert_eq!(result.unwrap().unwrap().rows.unwrap(), vec![vec![Int(2)]]);
+    assert_eq!(
+        locustdb
+            .ingest_ndjson_atomic("events", "{\"user\": {\"id\": 3}}\n".as_bytes())
+            .unwrap(),
+        1
+    );
+}
+
+#[test]
+fn test_csv_ingest_options() {
+    use std::io::Write;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("data.csv");
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(file, "1;'a;b';NA").unwrap();
+    writeln!(file, "2;NA;3.5").unwrap();
+    drop(file);
+
+    let locustdb = LocustDB::memory_only();
+    block_on(
+        locustdb.load_csv(
+            LoadOptions::new(&path, "data")
+                .with_headers(false)
+                .with_delimiter(b';')
+                .with_quote(b'\'')
+                .with_null_tokens(&["NA"])
+                .with_column_types(&[(0, ColumnType::String)])
+                .allow_nulls_all_columns(),
+        ),
+    )
+    .unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT column_0, column_1, column_2 FROM data ORDER BY column_0;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![
+            vec![Str("1"), Str("a;b"), Null],
+            vec![Str("2"), Null, Float(3.5)],
+        ]
+    );
+}
+
+#[test]
+fn test_csv_type_inference() {
+    use std::io::Write;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("data.csv");
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(file, "id,code").unwrap();
+    writeln!(file, "1,10").unwrap();
+    writeln!(file, "2,20").unwrap();
+    writeln!(file, "3,x7").unwrap();
+    drop(file);
+
+    let locustdb = LocustDB::memory_only();
+    block_on(
+        locustdb.load_csv(
+            LoadOptions::new(&path, "inferred")
+                .with_partition_size(2)
+                .with_type_inference(),
+        ),
+    )
+    .unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT code FROM inferred ORDER BY id;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![vec![Str("10")], vec![Str("20")], vec![Str("x7")]]
+    );
+
+    assert!(block_on(
+        locustdb.load_csv(LoadOptions::new(&path, "sampled").with_sampled_type_inference(1))
+    )
+    .is_err());
+    block_on(
+        locustdb.load_csv(
+            LoadOptions::new(&path, "overridden")
+                .with_sampled_type_inference(1)
+                .with_column_type(1, ColumnType::String),
+        ),
+    )
+    .unwrap();
+
+    // Floats in a column inferred as integer are only accepted if they are integral
+    let path = tmp_dir.path().join("floats.csv");
+    std::fs::write(&path, "id,value\n1,1\n2,2.0\n").unwrap();
+    block_on(locustdb.load_csv(LoadOptions::new(&path, "integral").with_sampled_type_inference(1)))
+        .unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT value FROM integral ORDER BY id;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![vec![Int(1)], vec![Int(2)]]
+    );
+    std::fs::write(&path, "id,value\n1,1\n2,2.5\n").unwrap();
+    assert!(block_on(
+        locustdb.load_csv(LoadOptions::new(&path, "truncated").with_sampled_type_inference(1))
+    )
+    .is_err());
+}
+
+#[test]
+fn test_compressed_input_detection() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("data.csv");
+    let mut encoder = GzEncoder::new(
+        std::fs::File::create(&path).unwrap(),
+        flate2::Compression::default(),
+    );
+    writeln!(encoder, "id,name").unwrap();
+    writeln!(encoder, "1,a").unwrap();
+    writeln!(encoder, "2,b").unwrap();
+    encoder.finish().unwrap();
+
+    let locustdb = LocustDB::memory_only();
+    block_on(locustdb.load_csv(LoadOptions::new(&path, "data"))).unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT id, name FROM data ORDER BY id;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![vec![Int(1), Str("a")], vec![Int(2), Str("b")]]
+    );
+}
+
+#[cfg(feature = "compressed_input")]
+#[test]
+fn test_zstd_input() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("events.ndjson.zst");
+    let compressed = zstd::encode_all(&b"{\"x\": 1}\n{\"x\": 2}\n"[..], 3).unwrap();
+    std::fs::write(&path, compressed).unwrap();
+
+    let locustdb = LocustDB::memory_only();
+    assert_eq!(locustdb.load_ndjson("events", &path).unwrap(), 2);
+    let result =
+        block_on(locustdb.run_query("SELECT SUM(x) FROM events;", false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(3)]]);
+}
+
+#[cfg(feature = "sqlite_import")]
+#[test]
+fn test_sqlite_import() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("app.sqlite");
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE users (id INTEGER, score REAL, name TEXT);
+         INSERT INTO users VALUES (1, 0.5, 'a'), (2, NULL, 'b');
+         CREATE TABLE logins (user_id INTEGER);
+         INSERT INTO logins VALUES (1), (1), (2);",
+    )
+    .unwrap();
+    drop(conn);
+
+    let locustdb = LocustDB::memory_only();
+    assert_eq!(
+        locustdb.import_sqlite(&path, &[]).unwrap(),
+        vec![("logins".to_string(), 3), ("users".to_string(), 2)]
+    );
+    let result = block_on(locustdb.run_query(
+        "SELECT id, score, name FROM users ORDER BY id;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![
+            vec![Int(1), Float(0.5), Str("a")],
+            vec![Int(2), Null, Str("b")]
+        ]
+    );
+    assert!(locustdb.import_sqlite(&path, &["missing"]).is_err());
+}
+
+#[cfg(feature = "protobuf")]
+#[test]
+fn test_protobuf_ingestion() {
+    use prost::Message;
+    use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+    use prost_reflect::prost_types::{
+        DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+        FileDescriptorProto, FileDescriptorSet,
+    };
+    use prost_reflect::{DescriptorPool, DynamicMessage, Value as ProtoValue};
+    let _ = env_logger::try_init();
+
+    let field =
+        |name: &str, number: i32, field_type: Type, type_name: Option<&str>| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(field_type as i32),
+            type_name: type_name.map(str::to_string),
+            ..Default::default()
+        };
+    let enum_value = |name: &str, number: i32| EnumValueDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(number),
+        ..Default::default()
+    };
+    let file = FileDescriptorProto {
+        name: Some("events.proto".to_string()),
+        package: Some("app".to_string()),
+        syntax: Some("proto3".to_string()),
+        message_type: vec![
+            DescriptorProto {
+                name: Some("Location".to_string()),
+                field: vec![field("city", 1, Type::String, None)],
+                ..Default::default()
+            },
+            DescriptorProto {
+                name: Some("Event".to_string()),
+                field: vec![
+                    field("id", 1, Type::Int64, None),
+                    field("latency", 2, Type::Double, None),
+                    field("action", 3, Type::Enum, Some(".app.Action")),
+                    field("location", 4, Type::Message, Some(".app.Location")),
+                ],
+                ..Default::default()
+            },
+        ],
+        enum_type: vec![EnumDescriptorProto {
+            name: Some("Action".to_string()),
+            value: vec![enum_value("CLICK", 0), enum_value("VIEW", 1)],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let descriptors = FileDescriptorSet { file: vec![file] }.encode_to_vec();
+
+    let pool = DescriptorPool::decode(&descriptors[..]).unwrap();
+    let mut location = DynamicMessage::new(pool.get_message_by_name("app.Location").unwrap());
+    location.set_field_by_name("city", ProtoValue::String("Berlin".to_string()));
+    let mut first = DynamicMessage::new(pool.get_message_by_name("app.Event").unwrap());
+    first.set_field_by_name("id", ProtoValue::I64(1));
+    first.set_field_by_name("latency", ProtoValue::F64(0.5));
+    first.set_field_by_name("action", ProtoValue::EnumNumber(1));
+    first.set_field_by_name("location", ProtoValue::Message(location));
+    let mut second = DynamicMessage::new(pool.get_message_by_name("app.Event").unwrap());
+    second.set_field_by_name("id", ProtoValue::I64(2));
+    let mut body = Vec::new();
+    first.encode_length_delimited(&mut body).unwrap();
+    second.encode_length_delimited(&mut body).unwrap();
+
+    let locustdb = LocustDB::memory_only();
+    assert!(locustdb
+        .ingest_protobuf("events", "app.Event", &body)
+        .is_err());
+    let messages = locustdb
+        .register_protobuf_descriptors(&descriptors)
+        .unwrap();
+    assert!(messages.contains(&"app.Event".to_string()));
+    assert_eq!(
+        locustdb
+            .ingest_protobuf("events", "app.Event", &body)
+            .unwrap(),
+        2
+    );
+    let result = block_on(locustdb.run_query(
+        r#"SELECT id, latency, action, "location.city" FROM events ORDER BY id;"#,
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![
+            vec![Int(1), Float(0.5), Str("VIEW"), Str("Berlin")],
+            vec![Int(2), Float(0.0), Str("CLICK"), Null],
+        ]
+    );
+    assert!(locustdb
+        .ingest_protobuf("events", "app.Event", &body[..body.len() - 1])
+        .is_err());
+    let result =
+        block_on(locustdb.run_query("SELECT COUNT(0) FROM events;", false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(2)]]);
+}
+
+#[test]
+fn test_ingest_csv_reader() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let rows = locustdb
+        .ingest_csv(
+            &LoadOptions::new("", "stream"),
+            &b"id,name\n1,a\n2,\"b,c\"\n"[..],
+        )
+        .unwrap();
+    assert_eq!(rows, 2);
+    let result = block_on(locustdb.run_query(
+        "SELECT id, name FROM stream ORDER BY id;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![vec![Int(1), Str("a")], vec![Int(2), Str("b,c")]]
+    );
+    assert!(locustdb
+        .ingest_csv(&LoadOptions::new("", "stream"), &b"id,name\n1\n"[..])
+        .is_err());
+}
+
+#[test]
+fn test_schema_enforcement() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let rows = concat!(
+        r#"{"id": 1, "name": "a"}"#,
+        "\n",
+        r#"{"id": "x"}"#,
+        "\n",
+        r#"{"name": "b"}"#,
+        "\n",
+        r#"{"id": 2, "extra": 3}"#,
+        "\n",
+    );
+    {
+        let locustdb = LocustDB::new(&opts);
+        let query = "CREATE TABLE checked (id INTEGER NOT NULL, name VARCHAR) \
+                     WITH (schema_enforcement = 'dead_letter', dead_letter_table = 'checked_errors');";
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap();
+        locustdb.ingest_ndjson("checked", rows.as_bytes()).unwrap();
+        let result =
+            block_on(locustdb.run_query("SELECT id, name FROM checked;", false, true, vec![]))
+                .unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(1), Str("a")]]);
+        let result = block_on(locustdb.run_query(
+            "SELECT COUNT(0) FROM checked_errors;",
+            false,
+            true,
+            vec![],
+        ))
+        .unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(3)]]);
+
+        locustdb
+            .set_schema_enforcement("checked", SchemaEnforcement::Reject)
+            .unwrap();
+        locustdb.ingest_ndjson("checked", rows.as_bytes()).unwrap();
+        let result = block_on(locustdb.run_query(
+            "SELECT COUNT(0) FROM checked_errors;",
+            false,
+            true,
+            vec![],
+        ))
+        .unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(3)]]);
+        assert!(locustdb
+            .set_schema_enforcement("checked_errors", SchemaEnforcement::Reject)
+            .is_err());
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(
+        locustdb.schema_enforcement("checked"),
+        Some(SchemaEnforcement::Reject)
+    );
+    let result =
+        block_on(locustdb.run_query("SELECT COUNT(0) FROM checked;", false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), vec![vec![Int(2)]]);
+}
+
+#[test]
+fn test_schema_enforcement_float_rows() {
+    use locustdb::logging_client::{ColumnBuffer, ColumnData, EventBuffer, TableBuffer};
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let query = "CREATE TABLE metrics (step INTEGER NOT NULL, loss DOUBLE) \
+                 WITH (schema_enforcement = 'reject');";
+    block_on(locustdb.run_query(query, false, true, vec![]))
+        .unwrap()
+        .unwrap();
+    let mut events = EventBuffer::default();
+    events.tables.insert(
+        "metrics".to_string(),
+        TableBuffer {
+            len: 3,
+            columns: [
+                (
+                    "step".to_string(),
+                    ColumnBuffer {
+                        data: ColumnData::Dense(vec![1.0, 2.0, 2.5]),
+                    },
+                ),
+                (
+                    "loss".to_string(),
+                    ColumnBuffer {
+                        data: ColumnData::Dense(vec![0.5, 0.25, 0.125]),
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        },
+    );
+    block_on(locustdb.ingest_efficient(events)).unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT step, loss FROM metrics ORDER BY step;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap();
+    assert_eq!(
+        result.unwrap().rows.unwrap(),
+        vec![vec![Int(1), Float(0.5)], vec![Int(2), Float(0.25)]]
+    );
+}
+
+#[test]
+fn test_backfill_ingestion() {
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let query = "SELECT ts, value FROM events ORDER BY ts;";
+    let expected = vec![
+        vec![Int(1), Str("b")],
+        vec![Int(5), Str("a")],
+        vec![Int(12), Str("c")],
+        vec![Int(100), Str("live")],
+    ];
+    {
+        let locustdb = LocustDB::new(&opts);
+        locustdb
+            .ingest_ndjson("events", r#"{"ts": 100, "value": "live"}"#.as_bytes())
+            .unwrap();
+        let mut columns = HashMap::new();
+        columns.insert("ts".to_string(), vec![Int(5), Int(1), Int(12)]);
+        columns.insert("value".to_string(), vec![Str("a"), Str("b"), Str("c")]);
+        assert_eq!(
+            locustdb
+                .ingest_backfill("events", "ts", 10, columns)
+                .unwrap(),
+            3
+        );
+        let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+        assert_eq!(result.unwrap().rows.unwrap(), expected);
+        let stats = block_on(locustdb.table_stats()).unwrap();
+        let events = stats.iter().find(|table| table.name == "events").unwrap();
+        assert_eq!(events.batches, 3);
+
+        let mut columns = HashMap::new();
+        columns.insert("ts".to_string(), vec![Str("yesterday")]);
+        assert!(locustdb
+            .ingest_backfill("events", "ts", 10, columns)
+            .is_err());
+        let mut columns = HashMap::new();
+        columns.insert("ts".to_string(), vec![Int(1)]);
+        assert!(locustdb
+            .ingest_backfill("events", "ts", 0, columns)
+            .is_err());
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    let result = block_on(locustdb.run_query(query, false, true, vec![])).unwrap();
+    assert_eq!(result.unwrap().rows.unwrap(), expected);
+}
+
+#[test]
+fn test_deduplication() {
+    use std::time::Duration;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let rows = concat!(
+        r#"{"id": 1, "value": "a"}"#,
+        "\n",
+        r#"{"id": 2, "value": "b"}"#,
+        "\n",
+        r#"{"id": 1, "value": "c"}"#,
+        "\n",
+        r#"{"value": "d"}"#,
+        "\n",
+        r#"{"value": "e"}"#,
+        "\n",
+    );
+    let count = |table: &str| {
+        let query = format!("SELECT COUNT(0) FROM {};", table);
+        block_on(locustdb.run_query(&query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    locustdb
+        .set_deduplication("window", Some(Deduplication::new("id")))
+        .unwrap();
+    locustdb.ingest_ndjson("window", rows.as_bytes()).unwrap();
+    assert_eq!(count("window"), vec![vec![Int(4)]]);
+    // Keys are forgotten once rows have been flushed from the WAL
+    locustdb.ingest_ndjson("window", rows.as_bytes()).unwrap();
+    assert_eq!(count("window"), vec![vec![Int(8)]]);
+
+    locustdb
+        .set_deduplication(
+            "lookback",
+            Some(Deduplication::new("id").with_lookback(Duration::from_secs(3600))),
+        )
+        .unwrap();
+    locustdb.ingest_ndjson("lookback", rows.as_bytes()).unwrap();
+    locustdb.ingest_ndjson("lookback", rows.as_bytes()).unwrap();
+    assert_eq!(count("lookback"), vec![vec![Int(6)]]);
+
+    locustdb.set_deduplication("lookback", None).unwrap();
+    locustdb.ingest_ndjson("lookback", rows.as_bytes()).unwrap();
+    assert_eq!(count("lookback"), vec![vec![Int(11)]]);
+}
+
+#[test]
+fn test_deduplication_retry() {
+    use std::time::{Duration, Instant};
+    let _ = env_logger::try_init();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let locustdb = LocustDB::new(&Options {
+        db_path: Some(tmp_dir.path().into()),
+        max_wal_size_bytes: 1,
+        wal_backpressure: WalBackpressure::Reject(Duration::from_secs(1)),
+        ..Options::default()
+    });
+    locustdb
+        .set_deduplication(
+            "events",
+            Some(Deduplication::new("id").with_lookback(Duration::from_secs(3600))),
+        )
+        .unwrap();
+    // Partitions can't be written while `tables` is a file, so the WAL stays above max_wal_size_bytes
+    let tables_path = tmp_dir.path().join("tables");
+    let _ = std::fs::remove_dir_all(&tables_path);
+    std::fs::write(&tables_path, b"").unwrap();
+    locustdb
+        .ingest_ndjson("events", r#"{"id": 1}"#.as_bytes())
+        .unwrap();
+    let rows = concat!(r#"{"id": 2}"#, "\n", r#"{"id": 3}"#, "\n");
+    assert!(matches!(
+        locustdb.ingest_ndjson("events", rows.as_bytes()),
+        Err(QueryError::Throttled(_))
+    ));
+
+    // Rows of the throttled request are not dropped as duplicates once the WAL has been flushed
+    std::fs::remove_file(&tables_path).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+        match locustdb.ingest_ndjson("events", rows.as_bytes()) {
+            Ok(_) => break,
+            Err(QueryError::Throttled(retry_after)) if Instant::now() < deadline => {
+                std::thread::sleep(retry_after)
+            }
+            Err(err) => panic!("{}", err),
+        }
+    }
+    let ids =
+        block_on(locustdb.run_query("SELECT id FROM events ORDER BY id;", false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap();
+    assert_eq!(ids, vec![vec![Int(1)], vec![Int(2)], vec![Int(3)]]);
+}
+
+#[test]
+fn test_column_codecs() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let mut rows = String::new();
+    for i in 0..1000 {
+        // High cardinality strings are FSST compressed, slowly changing floats are XOR encoded
+        rows += &format!(
+            r#"{{"url": "https://example.com/users/{}/profile", "value": {:?}"#,
+            i,
+            20.0 + i as f64 * 0.25
+        );
+        if i % 2 == 0 {
+            rows += &format!(r#", "referrer": "https://example.com/search?q=user{}""#, i);
+        }
+        rows += "}\n";
+    }
+    locustdb.ingest_ndjson("codecs", rows.as_bytes()).unwrap();
+    let query = |query: &str| {
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    assert_eq!(
+        query(
+            "SELECT value, referrer FROM codecs WHERE url = 'https://example.com/users/4/profile';"
+        ),
+        vec![vec![Float(21.0), Str("https://example.com/search?q=user4")]]
+    );
+    assert_eq!(
+        query(
+            "SELECT value, referrer FROM codecs WHERE url = 'https://example.com/users/5/profile';"
+        ),
+        vec![vec![Float(21.25), Null]]
+    );
+    assert_eq!(
+        query("SELECT SUM(value), COUNT(referrer) FROM codecs;"),
+        vec![vec![Float(144875.0), Int(500)]]
+    );
+}
+
+#[test]
+fn test_shared_dictionary() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let ingest = |locustdb: &LocustDB, rows: &[(&str, &str)]| {
+        let rows = rows
+            .iter()
+            .map(|(country, device)| {
+                format!(r#"{{"country": "{}", "device": "{}"}}"#, country, device) + "\n"
+            })
+            .collect::<String>();
+        locustdb.ingest_ndjson("dict", rows.as_bytes()).unwrap();
+        locustdb.force_flush().unwrap();
+    };
+    let query = |locustdb: &LocustDB, query: &str| {
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+    let counts = |locustdb: &LocustDB| {
+        query(
+            locustdb,
+            "SELECT country, COUNT(0) FROM dict ORDER BY country;",
+        )
+    };
+
+    {
+        let locustdb = LocustDB::new(&opts);
+        // Written before the dictionary is enabled, so results for this partition are merged on strings
+        ingest(&locustdb, &[("us", "mobile"), ("ca", "desktop")]);
+        locustdb
+            .set_shared_dictionary("dict", "country", true)
+            .unwrap();
+        // Codes are assigned in order of first occurrence, which differs from the order of the strings
+        ingest(
+            &locustdb,
+            &[("us", "mobile"), ("de", "desktop"), ("us", "desktop")],
+        );
+        ingest(&locustdb, &[("fr", "mobile"), ("de", "mobile")]);
+        ingest(&locustdb, &[("us", "tablet"), ("fr", "mobile")]);
+        assert_eq!(
+            counts(&locustdb),
+            vec![
+                vec![Str("ca"), Int(1)],
+                vec![Str("de"), Int(2)],
+                vec![Str("fr"), Int(2)],
+                vec![Str("us"), Int(4)],
+            ]
+        );
+        assert_eq!(
+            query(
+                &locustdb,
+                "SELECT country, device, COUNT(0) FROM dict WHERE country = 'fr' OR country = 'de' ORDER BY country, device;",
+            ),
+            vec![
+                vec![Str("de"), Str("desktop"), Int(1)],
+                vec![Str("de"), Str("mobile"), Int(1)],
+                vec![Str("fr"), Str("mobile"), Int(2)],
+            ]
+        );
+    }
+
+    // Dictionaries are restored from disk and new partitions reuse the existing codes
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(locustdb.shared_dictionary_columns("dict"), vec!["country"]);
+    ingest(&locustdb, &[("de", "mobile"), ("jp", "mobile")]);
+    assert_eq!(
+        counts(&locustdb),
+        vec![
+            vec![Str("ca"), Int(1)],
+            vec![Str("de"), Int(3)],
+            vec![Str("fr"), Int(2)],
+            vec![Str("jp"), Int(1)],
+            vec![Str("us"), Int(4)],
+        ]
+    );
+}
+
+#[test]
+fn test_scrub() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let ingest = |locustdb: &LocustDB, values: &[i64]| {
+        let rows = values
+            .iter()
+            .map(|value| format!(r#"{{"value": {}}}"#, value) + "\n")
+            .collect::<String>();
+        locustdb.ingest_ndjson("scrub", rows.as_bytes()).unwrap();
+        locustdb.force_flush().unwrap();
+    };
+    let count = |locustdb: &LocustDB| {
+        block_on(locustdb.run_query("SELECT COUNT(0) FROM scrub;", false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    {
+        let locustdb = LocustDB::new(&opts);
+        ingest(&locustdb, &[1, 2, 3]);
+        let report = locustdb.scrub(true);
+        assert!(report.files_scanned > 0);
+        assert_eq!(report.files_without_checksum, 0);
+        assert!(report.corrupted.is_empty());
+
+        let table_dir = tmp_dir.path().join("tables").join("scrub");
+        let file = std::fs::read_dir(&table_dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let mut data = std::fs::read(&file).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        std::fs::write(&file, data).unwrap();
+
+        let report = locustdb.scrub(false);
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0].path, file);
+        assert!(report.quarantined.is_empty());
+
+        let report = locustdb.scrub(true);
+        assert_eq!(report.quarantined.len(), 1);
+        assert!(!file.exists());
+        assert!(tmp_dir
+            .path()
+            .join("quarantine")
+            .join("scrub")
+            .join(file.file_name().unwrap())
+            .exists());
+
+        ingest(&locustdb, &[4, 5]);
+        assert_eq!(count(&locustdb), vec![vec![Int(2)]]);
+    }
+
+    // Quarantined partitions are not restored
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(count(&locustdb), vec![vec![Int(2)]]);
+    assert!(locustdb.scrub(true).corrupted.is_empty());
+}
+
+#[test]
+fn test_create_backup() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let ingest = |locustdb: &LocustDB, values: &[i64]| {
+        let rows = values
+            .iter()
+            .map(|value| format!(r#"{{"value": {}}}"#, value) + "\n")
+            .collect::<String>();
+        locustdb.ingest_ndjson("backup", rows.as_bytes()).unwrap();
+    };
+    let query = "SELECT value FROM backup ORDER BY value;";
+
+    let locustdb = LocustDB::new(&opts);
+    ingest(&locustdb, &[1, 2]);
+    locustdb.force_flush().unwrap();
+    // Buffered rows are included in the backup
+    ingest(&locustdb, &[3]);
+    locustdb.create_backup(backup_dir.path()).unwrap();
+    assert!(locustdb.create_backup(backup_dir.path()).is_err());
+    ingest(&locustdb, &[4]);
+    locustdb.force_flush().unwrap();
+
+    let restored = LocustDB::new(&Options {
+        db_path: Some(backup_dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    let rows = block_on(restored.run_query(query, false, true, vec![]))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap();
+    assert_eq!(rows, vec![vec![Int(1)], vec![Int(2)], vec![Int(3)]]);
+}
+
+#[test]
+fn test_point_in_time_recovery() {
+    use locustdb::logging_client::{ColumnBuffer, ColumnData, EventBuffer, TableBuffer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        archive_wal: true,
+        ..Default::default()
+    };
+    let ingest = |locustdb: &LocustDB, value: f64| {
+        let mut events = EventBuffer::default();
+        events.tables.insert(
+            "pitr".to_string(),
+            TableBuffer {
+                len: 1,
+                columns: [(
+                    "value".to_string(),
+                    ColumnBuffer {
+                        data: ColumnData::Dense(vec![value]),
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            },
+        );
+        block_on(locustdb.ingest_efficient(events)).unwrap();
+    };
+    let values = |locustdb: &LocustDB| {
+        block_on(locustdb.run_query(
+            "SELECT value FROM pitr ORDER BY value;",
+            false,
+            true,
+            vec![],
+        ))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap()
+    };
+
+    let target = {
+        let locustdb = LocustDB::new(&opts);
+        ingest(&locustdb, 1.0);
+        locustdb.force_flush().unwrap();
+        locustdb.create_backup(backup_dir.path()).unwrap();
+        ingest(&locustdb, 2.0);
+        std::thread::sleep(Duration::from_millis(10));
+        let target = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        std::thread::sleep(Duration::from_millis(10));
+        ingest(&locustdb, 3.0);
+        locustdb.force_flush().unwrap();
+        target
+    };
+    assert_eq!(
+        std::fs::read_dir(tmp_dir.path().join("wal_archive"))
+            .unwrap()
+            .count(),
+        3
+    );
+
+    let restore_opts = Options {
+        db_path: Some(backup_dir.path().to_path_buf()),
+        recovery_target: Some(RecoveryTarget {
+            wal_archive: tmp_dir.path().join("wal_archive"),
+            timestamp_ms: target,
+        }),
+        ..Default::default()
+    };
+    {
+        let restored = LocustDB::new(&restore_opts);
+        assert_eq!(values(&restored), vec![vec![Float(1.0)], vec![Float(2.0)]]);
+    }
+    // Replayed segments are kept after restarting without a recovery target
+    let restored = LocustDB::new(&Options {
+        recovery_target: None,
+        ..restore_opts
+    });
+    assert_eq!(values(&restored), vec![vec![Float(1.0)], vec![Float(2.0)]]);
+}
+
+#[test]
+fn test_restore_backup() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let restore_dir = TempDir::new().unwrap();
+    let locustdb = LocustDB::new(&Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    let rows = (0..100)
+        .map(|i| format!(r#"{{"value": {}}}"#, i) + "\n")
+        .collect::<String>();
+    locustdb.ingest_ndjson("restore", rows.as_bytes()).unwrap();
+    locustdb.create_backup(backup_dir.path()).unwrap();
+
+    let restored = LocustDB::restore_backup(backup_dir.path(), restore_dir.path()).unwrap();
+    let result = block_on(restored.run_query(
+        "SELECT COUNT(0), SUM(value) FROM restore;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap()
+    .rows
+    .unwrap();
+    assert_eq!(result, vec![vec![Int(100), Int(4950)]]);
+    // The destination already contains a database
+    assert!(LocustDB::restore_backup(backup_dir.path(), restore_dir.path()).is_err());
+
+    let table_dir = backup_dir.path().join("tables").join("restore");
+    let file = std::fs::read_dir(&table_dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let mut data = std::fs::read(&file).unwrap();
+    data[0] ^= 0xff;
+    std::fs::write(&file, data).unwrap();
+    let corrupted_dir = TempDir::new().unwrap();
+    assert!(LocustDB::restore_backup(backup_dir.path(), corrupted_dir.path()).is_err());
+    assert!(!corrupted_dir.path().join("meta").exists());
+}
+
+#[test]
+fn test_data_paths() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let data_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        data_paths: vec![data_dir.path().to_path_buf()],
+        // Prevents compaction from merging the partitions
+        partition_combine_factor: 1000,
+        ..Default::default()
+    };
+    let query = "SELECT COUNT(0), SUM(value) FROM striped;";
+    let file_count = |dir: &std::path::Path| {
+        std::fs::read_dir(dir.join("tables").join("striped")).map_or(0, |files| files.count())
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        for i in 0..4 {
+            let rows = (0..10)
+                .map(|j| format!(r#"{{"value": {}}}"#, i * 10 + j) + "\n")
+                .collect::<String>();
+            locustdb.ingest_ndjson("striped", rows.as_bytes()).unwrap();
+            locustdb.force_flush().unwrap();
+        }
+        assert!(file_count(tmp_dir.path()) > 0);
+        assert!(file_count(data_dir.path()) > 0);
+        assert_eq!(file_count(tmp_dir.path()) + file_count(data_dir.path()), 4);
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    let result = block_on(locustdb.run_query(query, false, true, vec![]))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap();
+    assert_eq!(result, vec![vec![Int(40), Int(780)]]);
+    locustdb.rename_table("striped", "renamed").unwrap();
+    assert!(locustdb.scrub(false).corrupted.is_empty());
+}
+
+#[test]
+fn test_operator_fusion() {
+    let _ = env_logger::try_init();
+    let queries = [
+        "SELECT id, nullable_int + 1, nullable_int2 * 2 FROM default ORDER BY id;",
+        "SELECT id, negative - nullable_int, nullable_int2 + id FROM default WHERE nullable_int IS NOT NULL ORDER BY id;",
+        "SELECT id, country, string_packed FROM default WHERE country <> 'Germany' AND string_packed LIKE '%x%' ORDER BY id;",
+        "SELECT country, COUNT(0), SUM(nullable_int) FROM default WHERE nullable_int2 IS NULL OR id > 3;",
+        "SELECT id, (nullable_int - nullable_int2 / (id + 1)) + (nullable_int - 2 * nullable_int2) % (id + 1) FROM default ORDER BY id;",
+        "SELECT enum, MAX(u8_offset_encoded + negative) FROM default WHERE country IS NOT NULL;",
+    ];
+    let run = |operator_fusion: bool| {
+        let opts = Options {
+            operator_fusion,
+            batch_size: 8,
+            max_partition_length: 3,
+            ..Options::default()
+        };
+        let locustdb = LocustDB::new(&opts);
+        let _ = block_on(
+            locustdb.load_csv(
+                LoadOptions::new("test_data/edge_cases.csv", "default")
+                    .with_partition_size(opts.max_partition_length)
+                    .allow_nulls_all_columns(),
+            ),
+        );
+        queries
+            .iter()
+            .map(|query| {
+                block_on(locustdb.run_query(query, false, true, vec![]))
+                    .unwrap()
+                    .unwrap()
+                    .rows
+                    .unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+    let fused = run(true);
+    let unfused = run(false);
+    for ((query, fused), unfused) in queries.iter().zip(fused).zip(unfused) {
+        assert!(!fused.is_empty(), "{} returned no rows", query);
+        assert_eq!(fused, unfused, "{}", query);
+    }
+}
+
+#[test]
+fn test_mmap_columns() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let query = "SELECT * FROM default;";
+    let old_db_contents = {
+        let locustdb = LocustDB::new(&opts);
+        block_on(
+            locustdb.load_csv(
+                nyc_taxi_data::ingest_reduced_file("test_data/nyc-taxi.csv.gz", "default")
+                    .with_partition_size(999),
+            ),
+        )
+        .unwrap();
+        block_on(locustdb.run_query(query, true, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    let opts = Options {
+        mmap_columns: true,
+        ..opts
+    };
+    let locustdb = LocustDB::new(&opts);
+    let mapped_db_contents = block_on(locustdb.run_query(query, true, true, vec![]))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap();
+    assert_eq!(old_db_contents, mapped_db_contents);
+}
+
+#[test]
+fn test_partition_pruning() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        // Prevents compaction from merging the partitions
+        partition_combine_factor: 1000,
+        ..Default::default()
+    };
+    let run = |locustdb: &LocustDB, query: &str| {
+        let output = block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap();
+        (output.rows.unwrap(), output.stats.rows_scanned)
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        for i in 0..4 {
+            let rows = (0..10)
+                .map(|j| format!(r#"{{"ts": {}, "value": {}}}"#, i * 10 + j, j) + "\n")
+                .collect::<String>();
+            locustdb.ingest_ndjson("events", rows.as_bytes()).unwrap();
+            locustdb.force_flush().unwrap();
+        }
+        let query = "SELECT COUNT(0) FROM events WHERE ts >= 35;";
+        assert_eq!(run(&locustdb, query), (vec![vec![Int(5)]], 10));
+        let query = "SELECT COUNT(0) FROM events WHERE ts < 5 OR 32 = ts;";
+        assert_eq!(run(&locustdb, query), (vec![vec![Int(6)]], 20));
+        let query = "SELECT COUNT(0) FROM events WHERE value < 5;";
+        assert_eq!(run(&locustdb, query), (vec![vec![Int(20)]], 40));
+    }
+
+    // Ranges of partitions loaded from disk are read from the partition metadata
+    let locustdb = LocustDB::new(&opts);
+    let query = "SELECT ts FROM events WHERE ts > 12 AND ts <= 14 ORDER BY ts;";
+    assert_eq!(
+        run(&locustdb, query),
+        (vec![vec![Int(13)], vec![Int(14)]], 10)
+    );
+    let query = "SELECT COUNT(0) FROM events WHERE ts > 100;";
+    let (_, rows_scanned) = run(&locustdb, query);
+    assert_eq!(rows_scanned, 10);
+}
+
+#[test]
+fn test_bloom_filters() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        // Prevents compaction from merging the partitions
+        partition_combine_factor: 1000,
+        ..Default::default()
+    };
+    let run = |locustdb: &LocustDB, query: &str| {
+        let output = block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap();
+        (output.rows.unwrap(), output.stats.rows_scanned)
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        locustdb.set_bloom_filter("events", "id", true).unwrap();
+        assert_eq!(
+            locustdb.bloom_filter_columns("events"),
+            vec!["id".to_string()]
+        );
+        for i in 0..4 {
+            let rows = (0..100)
+                .map(|j| {
+                    format!(r#"{{"id": "user-{}", "name": "name-{}"}}"#, i * 100 + j, j) + "\n"
+                })
+                .collect::<String>();
+            locustdb.ingest_ndjson("events", rows.as_bytes()).unwrap();
+            locustdb.force_flush().unwrap();
+        }
+        let query = "SELECT COUNT(0) FROM events WHERE id = 'user-123';";
+        let (rows, rows_scanned) = run(&locustdb, query);
+        assert_eq!(rows, vec![vec![Int(1)]]);
+        // Allows for a false positive
+        assert!(rows_scanned <= 200, "rows_scanned = {}", rows_scanned);
+        // Columns without bloom filters scan all partitions
+        let query = "SELECT COUNT(0) FROM events WHERE name = 'name-5';";
+        assert_eq!(run(&locustdb, query), (vec![vec![Int(4)]], 400));
+    }
+
+    // Bloom filters of partitions loaded from disk are read from the partition metadata
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(
+        locustdb.bloom_filter_columns("events"),
+        vec!["id".to_string()]
+    );
+    let query = "SELECT id FROM events WHERE 'user-321' = id;";
+    let (rows, rows_scanned) = run(&locustdb, query);
+    assert_eq!(rows, vec![vec![Str("user-321")]]);
+    assert!(rows_scanned <= 200, "rows_scanned = {}", rows_scanned);
+}
+
+#[test]
+fn test_sort_key() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        locustdb.set_sort_key("events", Some("ts")).unwrap();
+        // Ingests rows in descending order of ts, the fifth flush compacts all partitions
+        for i in 0..5 {
+            let rows = (0..10)
+                .map(|j| 50 - (i * 10 + j))
+                .map(|ts| format!(r#"{{"ts": {}, "value": {}}}"#, ts, ts * 2) + "\n")
+                .collect::<String>();
+            locustdb.ingest_ndjson("events", rows.as_bytes()).unwrap();
+            locustdb.force_flush().unwrap();
+        }
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(locustdb.sort_key("events"), Some("ts".to_string()));
+    let query = "SELECT ts, value FROM events LIMIT 3;";
+    assert_eq!(
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap(),
+        vec![
+            vec![Int(1), Int(2)],
+            vec![Int(2), Int(4)],
+            vec![Int(3), Int(6)],
+        ]
+    );
+    locustdb.set_sort_key("events", None).unwrap();
+    assert_eq!(locustdb.sort_key("events"), None);
+}
+
+#[test]
+fn test_collect_garbage() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let count = |locustdb: &LocustDB| {
+        block_on(locustdb.run_query("SELECT COUNT(0) FROM gc;", false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        locustdb
+            .ingest_ndjson("gc", "{\"value\": 1}\n{\"value\": 2}\n".as_bytes())
+            .unwrap();
+        locustdb.force_flush().unwrap();
+
+        // Files left behind by a crash during a flush, a dropped table and an incomplete WAL segment
+        let tables = tmp_dir.path().join("tables");
+        let orphans = [
+            tables.join("gc").join("99999_x.part"),
+            tables.join("dropped").join("00000_x.part"),
+            tmp_dir.path().join("wal").join("7..INCOMPLETE"),
+        ];
+        for (i, orphan) in orphans.iter().enumerate() {
+            std::fs::create_dir_all(orphan.parent().unwrap()).unwrap();
+            std::fs::write(orphan, vec![0u8; 10 * (i + 1)]).unwrap();
+        }
+
+        let report = locustdb.collect_garbage(true).unwrap();
+        assert_eq!(report.bytes_reclaimed, 60);
+        let mut files = report.files.clone();
+        files.sort();
+        let mut expected = orphans.to_vec();
+        expected.sort();
+        assert_eq!(files, expected);
+        assert!(orphans.iter().all(|orphan| orphan.exists()));
+
+        let report = locustdb.collect_garbage(false).unwrap();
+        assert_eq!(report.files.len(), 3);
+        assert_eq!(report.bytes_reclaimed, 60);
+        assert!(orphans.iter().all(|orphan| !orphan.exists()));
+        assert_eq!(count(&locustdb), vec![vec![Int(2)]]);
+        assert!(locustdb.collect_garbage(false).unwrap().files.is_empty());
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(count(&locustdb), vec![vec![Int(2)]]);
+}
+
+#[test]
+fn test_migrate() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let locustdb = LocustDB::new(&opts);
+    locustdb
+        .ingest_ndjson("migrate", "{\"value\": 1}\n".as_bytes())
+        .unwrap();
+    locustdb.force_flush().unwrap();
+    let report = locustdb.migrate().unwrap();
+    assert_eq!(report.from_version, locustdb::FORMAT_VERSION);
+    assert_eq!(report.to_version, locustdb::FORMAT_VERSION);
+    assert!(report.applied.is_empty());
+
+    let locustdb = LocustDB::new(&Options::default());
+    assert!(locustdb.migrate().is_err());
+}
+
+#[test]
+fn test_wal_sync() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    assert_eq!("always".parse(), Ok(WalSync::Always));
+    assert_eq!("never".parse(), Ok(WalSync::Never));
+    assert_eq!("16".parse(), Ok(WalSync::Batched(16)));
+    assert!("sometimes".parse::<WalSync>().is_err());
+
+    for wal_sync in [WalSync::Always, WalSync::Batched(2), WalSync::Never] {
+        let tmp_dir = TempDir::new().unwrap();
+        let opts = Options {
+            db_path: Some(tmp_dir.path().to_path_buf()),
+            wal_sync,
+            ..Default::default()
+        };
+        {
+            let locustdb = LocustDB::new(&opts);
+            for i in 0..3 {
+                let row = format!(r#"{{"value": {}}}"#, i) + "\n";
+                locustdb.ingest_ndjson("wal_sync", row.as_bytes()).unwrap();
+            }
+        }
+        let locustdb = LocustDB::new(&opts);
+        let query = "SELECT COUNT(0) FROM wal_sync;";
+        let rows = block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap();
+        assert_eq!(rows, vec![vec![Int(3)]], "wal_sync = {:?}", wal_sync);
+    }
+}
+
+#[test]
+fn test_wal_flush_interval() {
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        wal_flush_interval: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let locustdb = LocustDB::new(&opts);
+    locustdb
+        .ingest_ndjson("interval", "{\"value\": 1}\n".as_bytes())
+        .unwrap();
+    // The WAL is far below `max_wal_size_bytes`, so only the interval triggers the flush
+    let table_dir = tmp_dir.path().join("tables").join("interval");
+    let start = Instant::now();
+    while !table_dir.exists() || std::fs::read_dir(&table_dir).unwrap().next().is_none() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "WAL was not flushed"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_shutdown() {
+    use locustdb::logging_client::{ColumnBuffer, ColumnData, EventBuffer, TableBuffer};
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let events = |value: f64| {
+        let mut events = EventBuffer::default();
+        events.tables.insert(
+            "shutdown".to_string(),
+            TableBuffer {
+                len: 1,
+                columns: [(
+                    "value".to_string(),
+                    ColumnBuffer {
+                        data: ColumnData::Dense(vec![value]),
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            },
+        );
+        events
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        for i in 0..3 {
+            block_on(locustdb.ingest_efficient(events(i as f64))).unwrap();
+        }
+        locustdb.shutdown();
+        assert!(block_on(locustdb.ingest_efficient(events(3.0))).is_err());
+    }
+    // All rows were flushed to partitions, so there is no WAL left to replay
+    let wal_segments =
+        std::fs::read_dir(tmp_dir.path().join("wal")).map_or(0, |entries| entries.count());
+    assert_eq!(wal_segments, 0);
+
+    let locustdb = LocustDB::new(&opts);
+    let query = "SELECT COUNT(0) FROM shutdown;";
+    let rows = block_on(locustdb.run_query(query, false, true, vec![]))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap();
+    assert_eq!(rows, vec![vec![Int(3)]]);
+}
+
+#[test]
+fn test_query_io() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        max_query_disk_reads: Some(2),
+        ..Default::default()
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        for i in 0..10 {
+            let row = format!(r#"{{"value": {}}}"#, i) + "\n";
+            locustdb.ingest_ndjson("query_io", row.as_bytes()).unwrap();
+        }
+    }
+    // Columns of a reopened database are read from disk
+    let locustdb = LocustDB::new(&opts);
+    let query = "SELECT SUM(value) FROM query_io;";
+    for io in [
+        QueryIo::default(),
+        QueryIo {
+            max_disk_reads: Some(1),
+            prefetch_partitions: Some(0),
+        },
+        QueryIo {
+            max_disk_reads: Some(1),
+            prefetch_partitions: Some(8),
+        },
+    ] {
+        let rows = block_on(locustdb.run_query_with_io(query, false, true, vec![], io))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap();
+        assert_eq!(rows, vec![vec![Int(45)]], "{:?}", io);
+    }
+    let io = QueryIo {
+        max_disk_reads: Some(0),
+        prefetch_partitions: None,
+    };
+    assert!(
+        block_on(locustdb.run_query_with_io(query, false, true, vec![], io))
+            .unwrap()
+            .is_err()
+    );
+}
+
+#[test]
+fn test_query_memory_limit() {
+    let _ = env_logger::try_init();
+    let rows = (0..1000)
+        .map(|i| format!(r#"{{"value": {}}}"#, i * 1000))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let run = |opts: &Options, query: &str| {
+        let locustdb = LocustDB::new(opts);
+        // Every value is ingested twice so that groups of different batches have to be merged
+        for _ in 0..2 {
+            locustdb
+                .ingest_ndjson("memory_limit", rows.as_bytes())
+                .unwrap();
+        }
+        block_on(locustdb.run_query(query, false, true, vec![])).unwrap()
+    };
+
+    let query =
+        "SELECT value, COUNT(0), SUM(value), MAX(value) FROM memory_limit LIMIT 900 OFFSET 50;";
+    let expected = run(&Options::default(), query).unwrap().rows.unwrap();
+    assert_eq!(expected.len(), 900);
+    assert_eq!(
+        expected[0],
+        vec![Int(50000), Int(2), Int(100000), Int(50000)]
+    );
+    for limit in [64 * 1024 * 1024, 1024] {
+        let opts = Options {
+            query_memory_limit: Some(limit),
+            ..Default::default()
+        };
+        assert_eq!(run(&opts, query).unwrap().rows.unwrap(), expected);
+    }
+
+    // Sorting after aggregating is not supported when grouping state is written to disk
+    let opts = Options {
+        query_memory_limit: Some(1024),
+        ..Default::default()
+    };
+    let query = "SELECT value, COUNT(0) FROM memory_limit ORDER BY COUNT(0) DESC;";
+    match run(&opts, query) {
+        Err(QueryError::MemoryLimitExceeded(_)) => {}
+        result => panic!(
+            "expected memory limit to be exceeded: {:?}",
+            result.map(|r| r.rows)
+        ),
+    }
+}
+
+#[test]
+fn test_export_csv_sorted() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let locustdb = LocustDB::new(&Options {
+        sort_run_rows: 3,
+        ..Default::default()
+    });
+    for i in 0..4 {
+        let rows = (0..5)
+            .map(|j| {
+                format!(
+                    r#"{{"id": {}, "name": "row{}"}}"#,
+                    (i * 7 + j * 3) % 20,
+                    i * 5 + j
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        locustdb.ingest_ndjson("export", rows.as_bytes()).unwrap();
+    }
+    let export = |query: &str| {
+        let path = tmp_dir.path().join("export.csv");
+        let rows_written = block_on(locustdb.export_csv(query, &path)).unwrap();
+        let lines = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len() as u64, rows_written + 1);
+        lines
+    };
+    let expected = |query: &str| {
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+            .into_iter()
+            .map(|row| match (&row[0], &row[1]) {
+                (Int(id), Value::Str(name)) => format!("{},{}", id, name),
+                _ => panic!("unexpected row {:?}", row),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for query in [
+        "SELECT id, name FROM export ORDER BY id DESC, name;",
+        "SELECT id, name FROM export ORDER BY id, name LIMIT 7 OFFSET 2;",
+        "SELECT id, name FROM export WHERE id < 10;",
+    ] {
+        let lines = export(query);
+        assert_eq!(lines[0], "id,name");
+        assert_eq!(lines[1..], expected(query)[..], "{}", query);
+    }
+    assert!(
+        block_on(locustdb.export_csv("DROP TABLE export;", &tmp_dir.path().join("x.csv"))).is_err()
+    );
+}
+
+#[test]
+fn test_dynamic_worker_scaling() {
+    use std::time::Duration;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options {
+        threads: 1,
+        max_threads: Some(4),
+        worker_idle_timeout: Duration::from_millis(20),
+        ..Default::default()
+    });
+    for i in 0..20 {
+        let rows = (0..100)
+            .map(|j| format!(r#"{{"value": {}}}"#, i * 100 + j))
+            .collect::<Vec<_>>()
+            .join("\n");
+        locustdb.ingest_ndjson("scaling", rows.as_bytes()).unwrap();
+    }
+    let query = "SELECT SUM(value) FROM scaling;";
+    for _ in 0..3 {
+        let results = block_on(futures::future::join_all(
+            (0..8).map(|_| locustdb.run_query(query, false, true, vec![])),
+        ));
+        for result in results {
+            assert_eq!(
+                result.unwrap().unwrap().rows.unwrap(),
+                vec![vec![Int(1999000)]]
+            );
+        }
+        // Additional workers retire while idle
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_compaction_policy() {
+    use locustdb::CompactionPolicy;
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        // Prevents the default policy from merging the partitions
+        partition_combine_factor: 1000,
+        ..Default::default()
+    };
+    let policy = CompactionPolicy::TimeWindow {
+        column: "ts".to_string(),
+        window: 20,
+    };
+    let batches = |locustdb: &LocustDB| {
+        let stats = block_on(locustdb.table_stats()).unwrap();
+        stats
+            .iter()
+            .find(|table| table.name == "events")
+            .unwrap()
+            .batches
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        assert!(locustdb
+            .set_compaction_policy(
+                "events",
+                Some(CompactionPolicy::TimeWindow {
+                    column: "ts".to_string(),
+                    window: 0,
+                }),
+            )
+            .is_err());
+        locustdb
+            .set_compaction_policy("events", Some(policy.clone()))
+            .unwrap();
+        // Every two flushes fill one window, whose partitions are then combined
+        for i in 0..4 {
+            let rows = (0..10)
+                .map(|j| format!(r#"{{"ts": {}}}"#, i * 10 + j) + "\n")
+                .collect::<String>();
+            locustdb.ingest_ndjson("events", rows.as_bytes()).unwrap();
+            locustdb.force_flush().unwrap();
+        }
+        assert_eq!(batches(&locustdb), 2);
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(locustdb.compaction_policy("events"), Some(policy));
+    let query = "SELECT COUNT(0), SUM(ts) FROM events;";
+    assert_eq!(
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap(),
+        vec![vec![Int(40), Int(780)]]
+    );
+    locustdb.set_compaction_policy("events", None).unwrap();
+    assert_eq!(locustdb.compaction_policy("events"), None);
+}
+
+#[test]
+fn test_max_rss_backpressure() {
+    use std::time::{Duration, Instant};
+    let _ = env_logger::try_init();
+    if !std::path::Path::new("/proc/self/status").exists() {
+        return;
+    }
+    let locustdb = LocustDB::new(&Options {
+        // Any process exceeds this limit
+        max_rss_bytes: Some(1),
+        ..Default::default()
+    });
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let result = loop {
+        let result = locustdb.ingest_ndjson("events", r#"{"value": 1}"#.as_bytes());
+        if result.is_err() || Instant::now() > deadline {
+            break result;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    assert!(
+        matches!(result, Err(QueryError::Overloaded(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn test_table_memory_limit() {
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let resident_bytes = |locustdb: &LocustDB, table: &str| {
+        let stats = block_on(locustdb.table_stats()).unwrap();
+        stats
+            .iter()
+            .find(|stats| stats.name == table)
+            .unwrap()
+            .batches_bytes
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        for table in ["noisy", "quiet"] {
+            let rows = (0..100)
+                .map(|i| format!(r#"{{"value": {}}}"#, i) + "\n")
+                .collect::<String>();
+            locustdb.ingest_ndjson(table, rows.as_bytes()).unwrap();
+        }
+        locustdb.force_flush().unwrap();
+        locustdb.set_memory_limit("noisy", Some(0)).unwrap();
+        let start = Instant::now();
+        while resident_bytes(&locustdb, "noisy") > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        assert!(resident_bytes(&locustdb, "quiet") > 0);
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(locustdb.memory_limit("noisy"), Some(0));
+    assert_eq!(locustdb.memory_limit("quiet"), None);
+    let query = "SELECT COUNT(0), SUM(value) FROM noisy;";
+    assert_eq!(
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap(),
+        vec![vec![Int(100), Int(4950)]]
+    );
+}
+
+#[test]
+fn test_query_snapshots() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        // Prevents compaction from merging the partitions
+        partition_combine_factor: 1000,
+        ..Default::default()
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        for i in 0..3 {
+            let rows = (0..10)
+                .map(|j| format!(r#"{{"value": {}}}"#, i * 10 + j) + "\n")
+                .collect::<String>();
+            locustdb.ingest_ndjson("events", rows.as_bytes()).unwrap();
+            locustdb.force_flush().unwrap();
+        }
+    }
+
+    let locustdb = LocustDB::new(&opts);
+    let query = "SELECT SUM(value) FROM events;";
+    let output = block_on(locustdb.run_query(query, false, true, vec![]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(output.rows.unwrap(), vec![vec![Int(435)]]);
+    assert_eq!(output.stats.partitions_scanned, 3);
+    assert_eq!(output.stats.files_opened, 3);
+
+    let snapshots = locustdb.perf_counter().query_snapshots();
+    let snapshot = snapshots
+        .iter()
+        .find(|snapshot| snapshot.query == query)
+        .unwrap();
+    assert!(snapshot.completed);
+    assert_eq!(snapshot.stats.partitions_scanned, 3);
+    assert_eq!(snapshot.stats.rows_scanned, 30);
+    assert_eq!(snapshot.stats.disk_read_bytes, output.stats.disk_read_bytes);
+    assert!(snapshot.stats.load_ns > 0);
+}
+
+#[test]
+fn test_kill_query() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options {
+        threads: 1,
+        ..Default::default()
+    });
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 64,
+        partition_size: 1 << 14,
+        columns: vec![("id".to_string(), locustdb::colgen::random_hex_string(16))],
+    }));
+    assert!(!locustdb.kill_query(u64::MAX));
+
+    let query = "SELECT id, COUNT(0) FROM test ORDER BY id LIMIT 1;";
+    std::thread::scope(|scope| {
+        let result = scope.spawn(|| block_on(locustdb.run_query(query, false, true, vec![])));
+        let id = loop {
+            let snapshots = locustdb.perf_counter().query_snapshots();
+            if let Some(snapshot) = snapshots.iter().find(|snapshot| snapshot.query == query) {
+                break snapshot.id;
+            }
+            std::thread::yield_now();
+        };
+        // The query may complete before it is killed
+        let killed = locustdb.kill_query(id);
+        let result = result.join().unwrap().unwrap();
+        if killed {
+            assert!(matches!(result, Err(QueryError::Killed)));
+        } else {
+            assert!(result.is_ok());
+        }
+        assert!(!locustdb.kill_query(id));
+    });
+}
+
+#[test]
+fn test_query_progress() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 4,
+        partition_size: 1000,
+        columns: vec![("id".to_string(), locustdb::colgen::int_uniform(0, 100))],
+    }));
+    assert!(locustdb.query_progress(u64::MAX).is_none());
+
+    let query = "SELECT COUNT(0) FROM test WHERE id >= 0;";
+    let output = block_on(locustdb.run_query(query, false, true, vec![]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(output.rows.unwrap(), vec![vec![Int(4000)]]);
+    assert!(output.stats.bytes_scanned > 0);
+
+    let snapshot = locustdb
+        .perf_counter()
+        .query_snapshots()
+        .into_iter()
+        .find(|snapshot| snapshot.query == query)
+        .unwrap();
+    let progress = locustdb.query_progress(snapshot.id).unwrap();
+    assert!(progress.completed);
+    assert_eq!(progress.partitions_total, 4);
+    assert_eq!(progress.partitions_completed, 4);
+    assert_eq!(progress.bytes_scanned, output.stats.bytes_scanned);
+}
+
+#[test]
+fn test_query_cursor() {
+    use futures::StreamExt;
+
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 8,
+        partition_size: 100,
+        columns: vec![("id".to_string(), locustdb::colgen::int_uniform(0, 100))],
+    }));
+
+    let cursor = block_on(locustdb.query_cursor("SELECT id FROM test WHERE id >= 0;")).unwrap();
+    assert_eq!(cursor.colnames(), &["id".to_string()]);
+    let rows = block_on(cursor.collect::<Vec<_>>())
+        .into_iter()
+        .flat_map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 800);
+    assert!(rows
+        .iter()
+        .all(|row| matches!(row.as_slice(), [Int(id)] if (0..100).contains(id))));
+
+    let cursor =
+        block_on(locustdb.query_cursor("SELECT id FROM test LIMIT 150 OFFSET 20;")).unwrap();
+    let batches = block_on(cursor.collect::<Vec<_>>());
+    let rows = batches.into_iter().flat_map(Result::unwrap).count();
+    assert_eq!(rows, 150);
+
+    assert!(matches!(
+        block_on(locustdb.query_cursor("SELECT id FROM test ORDER BY id;")),
+        Err(QueryError::NotImplemented(_))
+    ));
+}
+
+#[test]
+fn test_top_n_multiple_columns() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 8,
+        partition_size: 1000,
+        columns: vec![
+            ("a".to_string(), locustdb::colgen::int_uniform(0, 10)),
+            ("b".to_string(), locustdb::colgen::int_uniform(0, 1000)),
+        ],
+    }));
+
+    let all = block_on(locustdb.run_query(
+        "SELECT a, b FROM test ORDER BY a DESC, b LIMIT 10000;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    let top = block_on(locustdb.run_query(
+        "SELECT a, b FROM test ORDER BY a DESC, b LIMIT 5 OFFSET 2;",
+        true,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(top.rows.unwrap(), all.rows.unwrap()[2..7].to_vec());
+    // Each partition only passes on the first limit + offset rows
+    assert!(top.query_plans.keys().any(|plan| plan.contains("[..7]")));
+}
+
+#[test]
+fn test_parallel_combine() {
+    let _ = env_logger::try_init();
+    let run = |threads| {
+        let locustdb = LocustDB::new(&Options {
+            threads,
+            ..Options::default()
+        });
+        let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+            name: "test".to_string(),
+            partitions: 64,
+            partition_size: 1000,
+            columns: vec![("id".to_string(), locustdb::colgen::int_uniform(0, 10_000))],
+        }));
+        block_on(locustdb.run_query(
+            "SELECT id, COUNT(0) FROM test ORDER BY id LIMIT 100000;",
+            false,
+            true,
+            vec![],
+        ))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap()
+    };
+    // Partial results of all workers are combined into the same result as with a single worker
+    assert_eq!(run(8), run(1));
+}
+
+#[test]
+fn test_group_by_packed_key_merge() {
+    use std::collections::BTreeMap;
+
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 16,
+        partition_size: 1000,
+        columns: vec![
+            ("a".to_string(), locustdb::colgen::int_uniform(-5, 5)),
+            ("b".to_string(), locustdb::colgen::int_uniform(0, 300)),
+            ("c".to_string(), locustdb::colgen::int_uniform(1000, 1003)),
+        ],
+    }));
+
+    let rows = block_on(locustdb.run_query(
+        "SELECT a, b, c FROM test LIMIT 100000;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap()
+    .rows
+    .unwrap();
+    let mut expected = BTreeMap::<Vec<Value>, i64>::new();
+    for row in rows {
+        *expected.entry(row).or_default() += 1;
+    }
+    let expected = expected
+        .into_iter()
+        .map(|(mut row, count)| {
+            row.push(Int(count));
+            row
+        })
+        .collect::<Vec<_>>();
+
+    let result = block_on(locustdb.run_query(
+        "SELECT a, b, c, COUNT(0) FROM test ORDER BY a, b, c LIMIT 100000;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(result.rows.unwrap(), expected);
+}
+
+#[test]
+fn test_aggregate_cache() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options {
+        aggregate_cache_entries: 64,
+        ..Options::default()
+    });
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 8,
+        partition_size: 1000,
+        columns: vec![
+            ("a".to_string(), locustdb::colgen::int_uniform(0, 10)),
+            (
+                "s".to_string(),
+                locustdb::colgen::string_weighted(
+                    vec!["x".to_string(), "y".to_string(), "z".to_string()],
+                    vec![1.0, 2.0, 3.0],
+                ),
+            ),
+        ],
+    }));
+    let query = "SELECT s, a, COUNT(0), SUM(a) FROM test WHERE a > 2 ORDER BY s, a LIMIT 100;";
+    let run = || {
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+    };
+
+    let first = run();
+    assert_eq!(first.stats.partitions_scanned, 8);
+    // All partitions are unchanged, so their results are reused
+    let second = run();
+    assert_eq!(second.stats.partitions_scanned, 0);
+    assert_eq!(second.rows, first.rows);
+
+    // Queries with a different filter are not answered from the cache
+    let other = block_on(locustdb.run_query(
+        "SELECT s, a, COUNT(0), SUM(a) FROM test WHERE a > 3 ORDER BY s, a LIMIT 100;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(other.stats.partitions_scanned, 8);
+    assert_ne!(other.rows, first.rows);
+
+    // Results are dropped when the table is renamed, even if it is renamed back
+    locustdb.rename_table("test", "renamed").unwrap();
+    locustdb.rename_table("renamed", "test").unwrap();
+    let renamed = run();
+    assert_eq!(renamed.stats.partitions_scanned, 8);
+    assert_eq!(renamed.rows, first.rows);
+}
+
+#[test]
+fn test_late_materialization() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 8,
+        partition_size: 1000,
+        columns: vec![
+            ("id".to_string(), locustdb::colgen::incrementing_int()),
+            ("a".to_string(), locustdb::colgen::int_uniform(0, 1000)),
+            ("s".to_string(), locustdb::colgen::random_hex_string(8)),
+        ],
+    }));
+
+    let mut all = block_on(locustdb.run_query(
+        "SELECT id, a, s, a * 2 FROM test LIMIT 100000;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap()
+    .rows
+    .unwrap();
+    all.sort_by(|x, y| y[0].cmp(&x[0]));
+    let top = block_on(locustdb.run_query(
+        "SELECT s, a * 2, id, a FROM test ORDER BY id DESC LIMIT 10 OFFSET 3;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    let expected = all[3..13]
+        .iter()
+        .map(|row| vec![row[2].clone(), row[3].clone(), row[0].clone(), row[1].clone()])
+        .collect::<Vec<_>>();
+    assert_eq!(top.rows.unwrap(), expected);
+    assert_eq!(top.columns.len(), 4);
+}
+
+#[test]
+fn test_late_materialization_unpruned_partitions() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 32,
+        partition_size: 100,
+        columns: vec![
+            ("id".to_string(), locustdb::colgen::incrementing_int()),
+            ("s".to_string(), locustdb::colgen::random_hex_string(8)),
+        ],
+    }));
+
+    let mut all =
+        block_on(locustdb.run_query("SELECT s, id FROM test LIMIT 100000;", false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap();
+    all.sort();
+    // No partition is pruned, so partitions are scanned in the order in which the table returns them
+    let top = block_on(locustdb.run_query(
+        "SELECT s, id FROM test ORDER BY s LIMIT 500 OFFSET 100;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(top.rows.unwrap(), all[100..600].to_vec());
+}
+
+#[test]
+fn test_like_dictionary_encoded() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 4,
+        partition_size: 1000,
+        columns: vec![(
+            "s".to_string(),
+            locustdb::colgen::string_weighted(
+                vec!["apple".to_string(), "banana".to_string(), "avocado".to_string()],
+                vec![1.0, 2.0, 3.0],
+            ),
+        )],
+    }));
+    let counts = block_on(locustdb.run_query(
+        "SELECT s, COUNT(0) FROM test ORDER BY s;",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap()
+    .rows
+    .unwrap();
+    let count = |s: &str| {
+        counts
+            .iter()
+            .find(|row| row[0] == Str(s))
+            .map_or(0, |row| match row[1] {
+                Int(count) => count,
+                _ => panic!("{:?}", row),
+            })
+    };
+
+    let like = block_on(locustdb.run_query(
+        "SELECT COUNT(0) FROM test WHERE s LIKE 'a%';",
+        true,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(like.rows.unwrap(), vec![vec![Int(count("apple") + count("avocado"))]]);
+    // The pattern is matched against each dictionary entry instead of each row
+    assert!(like.query_plans.keys().any(|plan| plan.contains("=~")));
+
+    let regex = block_on(locustdb.run_query(
+        "SELECT COUNT(0) FROM test WHERE regex(s, 'an');",
+        false,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(regex.rows.unwrap(), vec![vec![Int(count("banana"))]]);
+}
+
+#[test]
+fn test_comparison_encoded() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 4,
+        partition_size: 1000,
+        columns: vec![
+            (
+                "s".to_string(),
+                locustdb::colgen::string_weighted(
+                    vec![
+                        "apple".to_string(),
+                        "avocado".to_string(),
+                        "banana".to_string(),
+                        "cherry".to_string(),
+                    ],
+                    vec![1.0, 2.0, 3.0, 4.0],
+                ),
+            ),
+            (
+                "i".to_string(),
+                locustdb::colgen::int_weighted(vec![1000, 1001, 1005], vec![1.0, 2.0, 3.0]),
+            ),
+            (
+                "n".to_string(),
+                locustdb::colgen::nullable_ints(vec![Some(1), Some(3), None], vec![1.0, 2.0, 3.0]),
+            ),
+        ],
+    }));
+    let count = |condition: &str| {
+        let query = format!("SELECT COUNT(0) FROM test WHERE {};", condition);
+        let result = block_on(locustdb.run_query(&query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap();
+        match result.first().map(|row| &row[0]) {
+            Some(&Int(count)) => count,
+            None => 0,
+            _ => panic!("{:?}", result),
+        }
+    };
+    let s = |value: &str| count(&format!("s = '{}'", value));
+    let i = |value: i64| count(&format!("i = {}", value));
+
+    assert_eq!(s("apple") + s("avocado") + s("banana") + s("cherry"), 4000);
+    assert_eq!(s("b"), 0);
+    assert_eq!(count("s < 'banana'"), s("apple") + s("avocado"));
+    assert_eq!(count("s <= 'b'"), s("apple") + s("avocado"));
+    assert_eq!(count("s <= 'banana'"), 4000 - s("cherry"));
+    assert_eq!(count("s > 'avocado'"), s("banana") + s("cherry"));
+    assert_eq!(count("s >= 'b'"), s("banana") + s("cherry"));
+    assert_eq!(count("s >= 'avocado'"), 4000 - s("apple"));
+    assert_eq!(count("'b' < s"), s("banana") + s("cherry"));
+    assert_eq!(count("'banana' >= s"), 4000 - s("cherry"));
+    assert_eq!(count("s < 'a'"), 0);
+    assert_eq!(count("s > 'z'"), 0);
+    assert_eq!(count("s <> 'b'"), 4000);
+
+    assert_eq!(i(1000) + i(1001) + i(1005), 4000);
+    assert_eq!(count("i < 1001"), i(1000));
+    assert_eq!(count("i >= 1001"), i(1001) + i(1005));
+    assert_eq!(count("1004 < i"), i(1005));
+    assert_eq!(count("i > -9223372036854775807"), 4000);
+    assert_eq!(count("i <> 1001"), i(1000) + i(1005));
+
+    let n1 = count("n = 1");
+    let n3 = count("n = 3");
+    assert_eq!(count("n > 2"), n3);
+    assert_eq!(count("n <= 3"), n1 + n3);
+}
+
+#[test]
+fn test_constant_folding() {
+    test_query_ec(
+        "SELECT id FROM default WHERE 1 + 1 = 2 AND id < 3 ORDER BY id;",
+        &[vec![Int(0)], vec![Int(1)], vec![Int(2)]],
+    );
+    test_query_ec(
+        "SELECT id FROM default WHERE 'b' < 'a' OR id = 3;",
+        &[vec![Int(3)]],
+    );
+    test_query_ec("SELECT id FROM default WHERE id < 3 AND NOT (1 = 1);", &[]);
+    test_query_ec("SELECT id FROM default WHERE 2 * 3 <> 6;", &[]);
+    test_query_ec(
+        "SELECT id + 0, 1 * negative, u8_offset_encoded - 0 FROM default WHERE id < 2 ORDER BY id;",
+        &[
+            vec![Int(0), Int(-199), Int(256)],
+            vec![Int(1), Int(39), Int(258)],
+        ],
+    );
+}
+
+#[test]
+fn test_deferred_filters() {
+    test_query_ec(
+        "SELECT id FROM default WHERE string_packed LIKE 'a%' AND id > 1 ORDER BY id;",
+        &[vec![Int(2)], vec![Int(4)], vec![Int(6)]],
+    );
+    test_query_ec(
+        "SELECT id FROM default WHERE country LIKE '%a%' AND enum = 'aa' AND id < 8 ORDER BY id;",
+        &[vec![Int(0)], vec![Int(2)]],
+    );
+    test_query_ec(
+        "SELECT id FROM default WHERE string_packed LIKE 'a%' AND string_packed NOT LIKE '%z%' ORDER BY id;",
+        &[vec![Int(1)], vec![Int(6)]],
+    );
+    test_query_ec(
+        "SELECT COUNT(0) FROM default WHERE string_packed LIKE '%a%' AND id <> 3;",
+        &[vec![Int(4)]],
+    );
+}
+
+#[test]
+fn test_sort_grouping() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 4,
+        partition_size: 1000,
+        columns: vec![(
+            "s".to_string(),
+            locustdb::colgen::random_string(12, 12),
+        )],
+    }));
+    let result = block_on(locustdb.run_query(
+        "SELECT s, COUNT(0) FROM test LIMIT 10000;",
+        true,
+        true,
+        vec![],
+    ))
+    .unwrap()
+    .unwrap();
+    // Column statistics show that almost all strings are distinct
+    assert!(result.query_plans.keys().any(|plan| plan.contains("sort_grouping")));
+    let rows = result.rows.unwrap();
+    let total = rows
+        .iter()
+        .map(|row| match row[1] {
+            Int(count) => count,
+            _ => panic!("{:?}", row),
+        })
+        .sum::<i64>();
+    assert_eq!(total, 4000);
+    // Groups from different partitions have been merged
+    let mut keys = rows.iter().map(|row| format!("{:?}", row[0])).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), rows.len());
+}
+
+#[test]
+fn test_column_statistics() {
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let check = |locustdb: &LocustDB| {
+        let statistics = locustdb.column_statistics("default");
+        let enum_stats = &statistics["enum"];
+        assert_eq!(enum_stats.rows, 10);
+        assert_eq!(enum_stats.null_count, 0);
+        assert_eq!(enum_stats.distinct_values, Some(3));
+        assert_eq!(statistics["country"].null_count, 4);
+        assert_eq!(statistics["id"].range, Some((0, 9)));
+    };
+    {
+        let locustdb = LocustDB::new(&opts);
+        let _ = block_on(
+            locustdb.load_csv(
+                LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+            ),
+        );
+        check(&locustdb);
+    }
+
+    // Statistics are restored from the partition metadata
+    let locustdb = LocustDB::new(&opts);
+    check(&locustdb);
+    test_query_ec(
+        "SELECT id FROM default WHERE country IS NULL ORDER BY id;",
+        &[vec![Int(3)], vec![Int(5)], vec![Int(7)], vec![Int(8)]],
+    );
+}
+
+#[test]
+fn test_run_length_encoding() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    let sticky = vec![
+        vec![0.99, 0.005, 0.005],
+        vec![0.005, 0.99, 0.005],
+        vec![0.005, 0.005, 0.99],
+    ];
+    let statuses = vec!["ok".to_string(), "error".to_string(), "pending".to_string()];
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 2,
+        partition_size: 4096,
+        columns: vec![
+            (
+                "status".to_string(),
+                locustdb::colgen::string_markov_chain(statuses, sticky.clone()),
+            ),
+            (
+                "level".to_string(),
+                locustdb::colgen::int_markov_chain(vec![1, 2, 3], sticky),
+            ),
+        ],
+    }));
+    let query = |query: &str| {
+        block_on(locustdb.run_query(query, true, true, vec![]))
+            .unwrap()
+            .unwrap()
+    };
+    let count = |rows: &[Vec<Value>], matches: &dyn Fn(&[Value]) -> bool| {
+        rows.iter()
+            .filter(|row| matches(row))
+            .map(|row| match row[2] {
+                Int(count) => count,
+                _ => panic!("{:?}", row),
+            })
+            .sum::<i64>()
+    };
+    // Grouping by the columns decodes all runs
+    let groups = query("SELECT status, level, COUNT(0) FROM test;").rows.unwrap();
+    assert_eq!(count(&groups, &|_| true), 8192);
+
+    // Filters are evaluated once per run
+    let result = query("SELECT COUNT(0) FROM test WHERE status = 'error';");
+    assert!(result.query_plans.keys().any(|plan| plan.contains("run_length_decode")));
+    assert_eq!(
+        result.rows.unwrap(),
+        vec![vec![Int(count(&groups, &|row| row[0] == Str("error")))]]
+    );
+    let result = query("SELECT COUNT(0) FROM test WHERE level * 2 > 3;");
+    assert_eq!(
+        result.rows.unwrap(),
+        vec![vec![Int(count(&groups, &|row| row[1] != Int(1)))]]
+    );
+
+    // Grouping keys computed from a single column are also evaluated once per run
+    let rows = query("SELECT level + 10, COUNT(0) FROM test;").rows.unwrap();
+    for row in rows {
+        let level = match row[0] {
+            Int(level) => level - 10,
+            _ => panic!("{:?}", row),
+        };
+        assert_eq!(row[1], Int(count(&groups, &|group| group[1] == Int(level))));
+    }
+}
+
+#[test]
+fn test_morsels() {
+    let _ = env_logger::try_init();
+    let run = |morsel_size: usize, query: &str| {
+        let locustdb = LocustDB::new(&Options {
+            morsel_size,
+            ..Options::default()
+        });
+        let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+            name: "test".to_string(),
+            partitions: 1,
+            partition_size: 10_000,
+            columns: vec![
+                ("id".to_string(), locustdb::colgen::incrementing_int()),
+                ("value".to_string(), locustdb::colgen::int_uniform(-50, 50)),
+                (
+                    "maybe".to_string(),
+                    locustdb::colgen::nullable_ints(
+                        vec![None, Some(1), Some(2)],
+                        vec![0.5, 0.25, 0.25],
+                    ),
+                ),
+            ],
+        }));
+        let output = block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap();
+        (output.rows.unwrap(), output.stats.rows_scanned)
+    };
+    for query in [
+        "SELECT COUNT(0), SUM(value), MAX(id) FROM test;",
+        "SELECT value % 7, COUNT(0), SUM(id) FROM test ORDER BY value % 7;",
+        "SELECT maybe, COUNT(0) FROM test WHERE id > 3000 ORDER BY maybe;",
+        "SELECT id, value FROM test WHERE value > 40 ORDER BY id DESC LIMIT 20;",
+        "SELECT id FROM test WHERE id % 1000 = 999 ORDER BY id;",
+    ] {
+        // Splitting the partition into morsels of 1024 rows yields the same results as scanning it whole
+        let (expected, rows_scanned) = run(0, query);
+        assert_eq!(rows_scanned, 10_000);
+        assert_eq!(run(1024, query), (expected, 10_000), "{}", query);
+    }
+}
+
+#[test]
+fn test_sorted_grouping() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    locustdb.set_sort_key("events", Some("ts")).unwrap();
+    // Ingests rows in descending order of ts, the fifth flush compacts all partitions into one sorted partition
+    for i in 0..5 {
+        let rows = (0..1000)
+            .map(|j| (4 - i) * 1_000_000 + (999 - j) * 100)
+            .flat_map(|ts| vec![ts, ts])
+            .map(|ts| format!(r#"{{"ts": {}, "value": {}}}"#, ts, ts % 7) + "\n")
+            .collect::<String>();
+        locustdb.ingest_ndjson("events", rows.as_bytes()).unwrap();
+        locustdb.force_flush().unwrap();
+    }
+    let query = |query: &str| {
+        block_on(locustdb.run_query(query, true, true, vec![]))
+            .unwrap()
+            .unwrap()
+    };
+    let result = query("SELECT ts, COUNT(0), SUM(value) FROM events LIMIT 10000;");
+    assert!(result.query_plans.keys().any(|plan| plan.contains("sorted_grouping")));
+    let rows = result.rows.unwrap();
+    assert_eq!(rows.len(), 5000);
+    for row in &rows {
+        match row[..] {
+            [Int(ts), Int(count), Int(sum)] => {
+                assert_eq!(count, 2);
+                assert_eq!(sum, 2 * (ts % 7));
+            }
+            _ => panic!("{:?}", row),
+        }
+    }
+
+    let rows = query("SELECT ts, COUNT(0) FROM events WHERE value = 3 ORDER BY ts LIMIT 2;")
+        .rows
+        .unwrap();
+    assert_eq!(rows, vec![vec![Int(500), Int(2)], vec![Int(1200), Int(2)]]);
+}
+
+#[test]
+fn test_health() {
+    use tempfile::TempDir;
+    let tmp_dir = TempDir::new().unwrap();
+    let locustdb = LocustDB::new(&Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    let health = locustdb.health();
+    assert!(health.is_ready(), "{:?}", health);
+    assert_eq!(health.wal_error, None);
+    // The probe written to check that the WAL is writable is removed again
+    assert!(!tmp_dir.path().join("healthcheck.tmp").exists());
+
+    locustdb.shutdown();
+    let health = locustdb.health();
+    assert!(!health.is_healthy(), "{:?}", health);
+}
+
+#[test]
+fn test_system_tables() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options::default());
+    block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+    ))
+    .unwrap();
+    let query = |query: &str| {
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    assert_eq!(
+        query("SELECT SUM(rows) FROM _meta_partitions WHERE table_name = 'default';"),
+        vec![vec![Int(10)]]
+    );
+    assert_eq!(
+        query("SELECT COUNT(0) FROM _meta_columns WHERE table_name = 'default';"),
+        vec![vec![Int(14)]]
+    );
+    assert_eq!(
+        query("SELECT min, max FROM _meta_columns WHERE table_name = 'default' AND column_name = 'id';"),
+        vec![vec![Int(0), Int(9)]]
+    );
+    let queries = query("SELECT query, status FROM _meta_queries;");
+    assert!(queries.contains(&vec![
+        Str("SELECT COUNT(0) FROM _meta_columns WHERE table_name = 'default';"),
+        Str("completed"),
+    ]));
+
+    let result = block_on(locustdb.run_query("DROP TABLE _meta_partitions;", false, true, vec![]));
+    assert!(matches!(result.unwrap(), Err(QueryError::SchemaError(_))));
+    let result = block_on(locustdb.run_query(
+        "CREATE TABLE _meta_queries (id BIGINT);",
+        false,
+        true,
+        vec![],
+    ));
+    assert!(matches!(result.unwrap(), Err(QueryError::SchemaError(_))));
+}
+
+#[test]
+fn test_query_log() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&Options {
+        query_log: true,
+        ..Default::default()
+    });
+    block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/edge_cases.csv", "default").allow_nulls_all_columns(),
+    ))
+    .unwrap();
+    let query = |query: &str| {
+        block_on(locustdb.run_query(query, false, true, vec![]))
+            .unwrap()
+            .unwrap()
+            .rows
+            .unwrap()
+    };
+
+    query("SELECT COUNT(0) FROM default;");
+    let result = block_on(locustdb.run_query_as("alice", "SELECT id FROM missing;", true));
+    assert!(result.unwrap().is_err());
+
+    assert_eq!(
+        query("SELECT client_user FROM _query_log WHERE error IS NOT NULL;"),
+        vec![vec![Str("alice")]]
+    );
+    assert_eq!(
+        query(
+            "SELECT rows, client_user FROM _query_log WHERE query = 'SELECT COUNT(0) FROM default;';"
+        ),
+        vec![vec![Int(1), Null]]
+    );
+
+    let unsampled = LocustDB::new(&Options {
+        query_log: true,
+        query_log_sample_rate: 0.0,
+        ..Default::default()
+    });
+    let result = block_on(unsampled.run_query("SELECT 1 FROM _query_log;", false, true, vec![]));
+    // The query log table is only created once a query has been recorded
+    assert!(result.unwrap().is_err());
+}
+
+#[test]
+fn test_ingest_mixed_event_columns() {
+    use locustdb::logging_client::{ColumnBuffer, ColumnData, EventBuffer, TableBuffer};
+    use tempfile::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new().unwrap();
+    let opts = Options {
+        db_path: Some(tmp_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let columns = vec![
+        ("value", ColumnData::Dense(vec![0.5, 1.5, 2.5])),
+        (
+            "service",
+            ColumnData::Mixed(vec![(0, Str("api")), (2, Str("worker"))]),
+        ),
+        (
+            "status",
+            ColumnData::Mixed(vec![(1, Int(500)), (2, Int(200))]),
+        ),
+    ];
+    let mut events = EventBuffer::default();
+    events.tables.insert(
+        "events".to_string(),
+        TableBuffer {
+            len: 3,
+            columns: columns
+                .into_iter()
+                .map(|(name, data)| (name.to_string(), ColumnBuffer { data }))
+                .collect(),
+        },
+    );
+    let expected = vec![
+        vec![Float(0.5), Str("api"), Null],
+        vec![Float(1.5), Null, Int(500)],
+        vec![Float(2.5), Str("worker"), Int(200)],
+    ];
+    let query = |locustdb: &LocustDB| {
+        block_on(locustdb.run_query(
+            "SELECT value, service, status FROM events ORDER BY value;",
+            false,
+            true,
+            vec![],
+        ))
+        .unwrap()
+        .unwrap()
+        .rows
+        .unwrap()
+    };
+
+    {
+        let locustdb = LocustDB::new(&opts);
+        block_on(locustdb.ingest_efficient(events)).unwrap();
+        assert_eq!(query(&locustdb), expected);
+    }
+    // Restored from the WAL
+    let locustdb = LocustDB::new(&opts);
+    assert_eq!(query(&locustdb), expected);
+}